@@ -0,0 +1,48 @@
+use serde::{Deserialize, Serialize};
+
+use crate::{collection::CollectionUuid, comment::CommentUuid, http_endpoint, media::MediaUuid};
+
+// structs
+
+// an entry in the recent activity feed, along with the unix time that it happened
+//
+// access follows SearchMedia, so media that the user can't find there (and comments on
+// that media) are left out
+#[derive(Clone, Debug, Deserialize, Serialize, PartialEq)]
+pub struct Activity {
+    pub time: u64,
+    pub event: ActivityEvent,
+}
+
+#[derive(Clone, Debug, Deserialize, Serialize, PartialEq)]
+pub enum ActivityEvent {
+    MediaAdded {
+        media_uuid: MediaUuid,
+    },
+    CollectionCreated {
+        collection_uuid: CollectionUuid,
+    },
+    CommentAdded {
+        comment_uuid: CommentUuid,
+        media_uuid: MediaUuid,
+    },
+}
+
+// messages
+
+// get the most recent additions across media, collections, and comments, newest first
+//
+// the limit is capped at RECENT_ACTIVITY_MAX
+http_endpoint!(GetRecentActivity, idempotent);
+
+pub const RECENT_ACTIVITY_MAX: u64 = 100;
+
+#[derive(Clone, Debug, Deserialize, Serialize)]
+pub struct GetRecentActivityReq {
+    pub limit: u64,
+}
+
+#[derive(Clone, Debug, Deserialize, Serialize)]
+pub struct GetRecentActivityResp {
+    pub activity: Vec<Activity>,
+}
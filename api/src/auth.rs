@@ -2,14 +2,28 @@ use std::collections::HashSet;
 
 use serde::{Deserialize, Serialize};
 
-use crate::http_endpoint;
+use crate::{http_endpoint, uuid_newtype};
 
 // structs and types
 
+uuid_newtype!(ApiKey);
+
+// api keys let scripts authenticate as a user without going through the browser login
+//
+// only a hash of the key is stored, so the key itself is only ever seen in the response
+// to CreateApiKey
+#[derive(Clone, Debug, Deserialize, Serialize)]
+pub struct ApiKey {
+    pub key_uuid: ApiKeyUuid,
+    pub uid: String,
+    pub name: String,
+    pub created: u64,
+}
+
 // messages
 
 // look up users in a group
-http_endpoint!(GetUsersInGroup);
+http_endpoint!(GetUsersInGroup, idempotent);
 
 #[derive(Clone, Debug, Deserialize, Serialize)]
 pub struct GetUsersInGroupReq {
@@ -20,3 +34,39 @@ pub struct GetUsersInGroupReq {
 pub struct GetUsersInGroupResp {
     pub uids: HashSet<String>,
 }
+
+// create an api key for the current user
+http_endpoint!(CreateApiKey);
+
+#[derive(Clone, Debug, Deserialize, Serialize)]
+pub struct CreateApiKeyReq {
+    pub name: String,
+}
+
+#[derive(Clone, Debug, Deserialize, Serialize)]
+pub struct CreateApiKeyResp {
+    pub key_uuid: ApiKeyUuid,
+    pub key: String,
+}
+
+// list the current user's api keys
+http_endpoint!(ListApiKeys, idempotent);
+
+#[derive(Clone, Debug, Deserialize, Serialize)]
+pub struct ListApiKeysReq {}
+
+#[derive(Clone, Debug, Deserialize, Serialize)]
+pub struct ListApiKeysResp {
+    pub keys: Vec<ApiKey>,
+}
+
+// revoke one of the current user's api keys
+http_endpoint!(RevokeApiKey);
+
+#[derive(Clone, Debug, Deserialize, Serialize)]
+pub struct RevokeApiKeyReq {
+    pub key_uuid: ApiKeyUuid,
+}
+
+#[derive(Clone, Debug, Deserialize, Serialize)]
+pub struct RevokeApiKeyResp {}
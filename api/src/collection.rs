@@ -1,6 +1,7 @@
 use std::collections::HashSet;
 
 use serde::{Deserialize, Serialize};
+use utoipa::ToSchema;
 
 use crate::{http_endpoint, uuid_newtype, media::MediaUuid, search::SearchFilter};
 
@@ -8,17 +9,24 @@ use crate::{http_endpoint, uuid_newtype, media::MediaUuid, search::SearchFilter}
 
 uuid_newtype!(Collection);
 
-#[derive(Clone, Debug, Deserialize, Serialize)]
+#[derive(Clone, Debug, Deserialize, Serialize, ToSchema)]
 pub struct Collection {
     pub uid: String,
     pub gid: String,
     pub name: String,
     pub note: String,
     pub tags: HashSet<String>,
+    // when fetched, this is the chosen cover or else the most recent media in the collection
     pub cover: Option<MediaUuid>,
+    // the enclosing collection, if any
+    //
+    // nesting does not affect access, so a child may be visible to groups that cannot see its
+    // parent (and vice versa)
+    #[serde(default)]
+    pub parent_uuid: Option<CollectionUuid>,
 }
 
-#[derive(Clone, Debug, Deserialize, Serialize)]
+#[derive(Clone, Debug, Deserialize, Serialize, ToSchema)]
 pub struct CollectionUpdate {
     pub name: Option<String>,
     pub note: Option<String>,
@@ -30,12 +38,12 @@ pub struct CollectionUpdate {
 // create a new collection
 http_endpoint!(AddCollection);
 
-#[derive(Clone, Debug, Deserialize, Serialize)]
+#[derive(Clone, Debug, Deserialize, Serialize, ToSchema)]
 pub struct AddCollectionReq {
     pub collection: Collection,
 }
 
-#[derive(Clone, Debug, Deserialize, Serialize)]
+#[derive(Clone, Debug, Deserialize, Serialize, ToSchema)]
 pub struct AddCollectionResp {
     pub collection_uuid: CollectionUuid,
 }
@@ -44,14 +52,14 @@ pub struct AddCollectionResp {
 //
 // note that we fetch the media with
 // a blank filter in another call
-http_endpoint!(GetCollection);
+http_endpoint!(GetCollection, idempotent);
 
-#[derive(Clone, Debug, Deserialize, Serialize)]
+#[derive(Clone, Debug, Deserialize, Serialize, ToSchema)]
 pub struct GetCollectionReq {
     pub collection_uuid: CollectionUuid,
 }
 
-#[derive(Clone, Debug, Deserialize, Serialize)]
+#[derive(Clone, Debug, Deserialize, Serialize, ToSchema)]
 pub struct GetCollectionResp {
     pub collection: Collection,
 }
@@ -59,75 +67,141 @@ pub struct GetCollectionResp {
 // delete an collection
 http_endpoint!(DeleteCollection);
 
-#[derive(Clone, Debug, Deserialize, Serialize)]
+#[derive(Clone, Debug, Deserialize, Serialize, ToSchema)]
 pub struct DeleteCollectionReq {
     pub collection_uuid: CollectionUuid,
 }
 
-#[derive(Clone, Debug, Deserialize, Serialize)]
+#[derive(Clone, Debug, Deserialize, Serialize, ToSchema)]
 pub struct DeleteCollectionResp {}
 
 // change collection properties
 http_endpoint!(UpdateCollection);
 
-#[derive(Clone, Debug, Deserialize, Serialize)]
+#[derive(Clone, Debug, Deserialize, Serialize, ToSchema)]
 pub struct UpdateCollectionReq {
     pub collection_uuid: CollectionUuid,
     pub update: CollectionUpdate,
 }
 
-#[derive(Clone, Debug, Deserialize, Serialize)]
+#[derive(Clone, Debug, Deserialize, Serialize, ToSchema)]
 pub struct UpdateCollectionResp {}
 
+// choose the media shown on the collection card
+//
+// the media must already be in the collection, and None goes back to the default
+http_endpoint!(SetCollectionCover);
+
+#[derive(Clone, Debug, Deserialize, Serialize, ToSchema)]
+pub struct SetCollectionCoverReq {
+    pub collection_uuid: CollectionUuid,
+    pub media_uuid: Option<MediaUuid>,
+}
+
+#[derive(Clone, Debug, Deserialize, Serialize, ToSchema)]
+pub struct SetCollectionCoverResp {}
+
+// move a collection under another one
+//
+// None makes it a top-level collection again, and a collection cannot be moved under
+// itself or any of its descendants
+http_endpoint!(SetCollectionParent);
+
+#[derive(Clone, Debug, Deserialize, Serialize, ToSchema)]
+pub struct SetCollectionParentReq {
+    pub collection_uuid: CollectionUuid,
+    pub parent_uuid: Option<CollectionUuid>,
+}
+
+#[derive(Clone, Debug, Deserialize, Serialize, ToSchema)]
+pub struct SetCollectionParentResp {}
+
+// list the collections directly inside of a collection
+//
+// only the children that the user can access are returned
+http_endpoint!(GetCollectionChildren, idempotent);
+
+#[derive(Clone, Debug, Deserialize, Serialize, ToSchema)]
+pub struct GetCollectionChildrenReq {
+    pub collection_uuid: CollectionUuid,
+}
+
+#[derive(Clone, Debug, Deserialize, Serialize, ToSchema)]
+pub struct GetCollectionChildrenResp {
+    pub collections: Vec<CollectionUuid>,
+}
+
 // add media to an collection
 http_endpoint!(AddMediaToCollection);
 
-#[derive(Clone, Debug, Deserialize, Serialize)]
+#[derive(Clone, Debug, Deserialize, Serialize, ToSchema)]
 pub struct AddMediaToCollectionReq {
     pub collection_uuid: CollectionUuid,
     pub media_uuid: MediaUuid,
 }
 
-#[derive(Clone, Debug, Deserialize, Serialize)]
+#[derive(Clone, Debug, Deserialize, Serialize, ToSchema)]
 pub struct AddMediaToCollectionResp {}
 
 // remove media from an collection
 http_endpoint!(RmMediaFromCollection);
 
-#[derive(Clone, Debug, Deserialize, Serialize)]
+#[derive(Clone, Debug, Deserialize, Serialize, ToSchema)]
 pub struct RmMediaFromCollectionReq {
     pub collection_uuid: CollectionUuid,
     pub media_uuid: MediaUuid,
 }
 
-#[derive(Clone, Debug, Deserialize, Serialize)]
+#[derive(Clone, Debug, Deserialize, Serialize, ToSchema)]
 pub struct RmMediaFromCollectionResp {}
 
+// arrange the media in a collection
+//
+// the listed media go first, in order, and anything left out keeps its place relative to
+// the rest behind them.  the order is what SearchMediaInCollection returns when ordered.
+http_endpoint!(ReorderCollectionMedia);
+
+#[derive(Clone, Debug, Deserialize, Serialize, ToSchema)]
+pub struct ReorderCollectionMediaReq {
+    pub collection_uuid: CollectionUuid,
+    pub media_uuids: Vec<MediaUuid>,
+}
+
+#[derive(Clone, Debug, Deserialize, Serialize, ToSchema)]
+pub struct ReorderCollectionMediaResp {}
+
 // search collections
 //
 // defaults to ""
-http_endpoint!(SearchCollections);
+http_endpoint!(SearchCollections, idempotent);
 
-#[derive(Clone, Debug, Deserialize, Serialize)]
+#[derive(Clone, Debug, Deserialize, Serialize, ToSchema)]
 pub struct SearchCollectionsReq {
     pub filter: SearchFilter,
 }
 
-#[derive(Clone, Debug, Deserialize, Serialize)]
+#[derive(Clone, Debug, Deserialize, Serialize, ToSchema)]
 pub struct SearchCollectionsResp {
     pub collections: Vec<CollectionUuid>,
 }
 
 // search media inside a particular collection
-http_endpoint!(SearchMediaInCollection);
+//
+// if recursive, this also includes the media in any descendants that the user can access
+http_endpoint!(SearchMediaInCollection, idempotent);
 
-#[derive(Clone, Debug, Deserialize, Serialize)]
+#[derive(Clone, Debug, Deserialize, Serialize, ToSchema)]
 pub struct SearchMediaInCollectionReq {
     pub collection_uuid: CollectionUuid,
     pub filter: SearchFilter,
+    #[serde(default)]
+    pub recursive: bool,
+    // in the order set by ReorderCollectionMedia, rather than whatever the database picks
+    #[serde(default)]
+    pub ordered: bool,
 }
 
-#[derive(Clone, Debug, Deserialize, Serialize)]
+#[derive(Clone, Debug, Deserialize, Serialize, ToSchema)]
 pub struct SearchMediaInCollectionResp {
     pub media: Vec<MediaUuid>,
 }
@@ -12,6 +12,9 @@ pub struct Comment {
     pub uid: String,
     pub date: u64,
     pub text: String,
+    // unix time of the last change to the text, if it has ever been edited
+    #[serde(default)]
+    pub edited: Option<u64>,
 }
 
 // messages
@@ -34,7 +37,7 @@ pub struct AddCommentResp {
 }
 
 // fetch comments for media
-http_endpoint!(GetComment);
+http_endpoint!(GetComment, idempotent);
 
 #[derive(Clone, Debug, Deserialize, Serialize)]
 pub struct GetCommentReq {
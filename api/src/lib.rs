@@ -4,13 +4,17 @@ use std::{
     sync::Arc,
 };
 
+pub mod activity;
 pub mod auth;
 pub mod collection;
 pub mod comment;
 pub mod library;
+pub mod markdown;
 pub mod media;
 pub mod search;
+pub mod share;
 pub mod sort;
+pub mod stats;
 pub mod task;
 
 // entanglment api
@@ -31,6 +35,18 @@ pub const LINK_PATH: &str = "originals";
 pub const THUMBNAIL_PATH: &str = "thumbnails";
 pub const SLICE_PATH: &str = "slices";
 
+// unlike the others, this is not a directory -- the server builds collection archives
+// as they are downloaded
+pub const ARCHIVE_PATH: &str = "collection";
+
+// share links live outside of both the media and api routes, since they are served without
+// a user
+pub const SHARE_PATH: &str = "share";
+
+// the atom feed of recently added media, which also sits outside of the other routes so that
+// feed readers see a fixed url
+pub const FEED_PATH: &str = "feed.xml";
+
 // http url root
 //
 // until we figure out how to have dioxus dynamically fetch the revese proxy settings
@@ -51,7 +67,7 @@ pub trait UuidSource {}
 macro_rules! uuid_newtype {
     ($name:tt) => {
         pastey::paste!{
-            #[derive(Clone, Copy, Debug, Deserialize, Eq, postgres_types::FromSql, Hash, PartialEq, PartialOrd, Ord, Serialize, postgres_types::ToSql)]
+            #[derive(Clone, Copy, Debug, Deserialize, Eq, postgres_types::FromSql, Hash, PartialEq, PartialOrd, Ord, Serialize, postgres_types::ToSql, utoipa::ToSchema)]
             #[postgres(transparent)]
             pub struct [<$name Uuid>](uuid::Uuid);
 
@@ -127,21 +143,105 @@ pub fn unfold_set(str: &str) -> HashSet<String> {
     set
 }
 
+// a tag containing the separator would be split apart the next time it is unfolded, and a
+// blank one can't be searched for or shown.  fold_set() also refuses the former, but only
+// as an internal error once the update reaches the database, so the webapp and the http
+// handlers check first to give a clear message.
+pub fn validate_tags(tags: &HashSet<String>) -> anyhow::Result<()> {
+    for tag in tags.iter() {
+        if tag.trim().is_empty() {
+            return Err(anyhow::Error::msg("tags cannot be blank"));
+        }
+
+        if tag.contains(FOLDING_SEPARATOR) {
+            return Err(anyhow::Error::msg(format!(
+                "tag '{tag}' cannot contain '{FOLDING_SEPARATOR}'"
+            )));
+        }
+    }
+
+    Ok(())
+}
+
 // weberror
 //
 // anyhow::Error does not implement serde::de::StdError, which prevents it from being used
 // in Dioxus's ErrorBoundary handle_error logic.  thus, we create this mostly-transparent
 // wrapper and connect it to both anyhow and the gloo_net errors returned by the api calls.
+//
+// errors from the server also keep the http status, so that the webapp can tell an auth
+// failure apart from everything else.  errors raised in the webapp itself have none.
+//
+// transient is only set when a request never got an answer, i.e. it failed to send or timed
+// out, since those are the errors that another try might fix.
 #[derive(Clone, Debug)]
-pub struct WebError(Arc<anyhow::Error>);
+pub struct WebError {
+    error: Arc<anyhow::Error>,
+    status: Option<u16>,
+    transient: bool,
+}
 
 impl WebError {
     pub fn new() -> Self {
-        WebError(Arc::new(anyhow::Error::msg("")))
+        Self::msg(String::new())
     }
 
     pub fn msg(msg: String) -> Self {
-        WebError(Arc::new(anyhow::Error::msg(msg)))
+        WebError {
+            error: Arc::new(anyhow::Error::msg(msg)),
+            status: None,
+            transient: false,
+        }
+    }
+
+    // most of the auth failures come back with an empty body, so the status stands in for
+    // the message
+    pub fn with_status(status: u16, status_text: &str, msg: String) -> Self {
+        let msg = if msg.is_empty() {
+            format!("{status} {status_text}")
+        } else {
+            msg
+        };
+
+        WebError {
+            error: Arc::new(anyhow::Error::msg(msg)),
+            status: Some(status),
+            transient: false,
+        }
+    }
+
+    pub fn status(&self) -> Option<u16> {
+        self.status
+    }
+
+    pub fn is_unauthorized(&self) -> bool {
+        self.status == Some(401)
+    }
+
+    pub fn is_not_found(&self) -> bool {
+        self.status == Some(404)
+    }
+
+    fn timeout(name: &str) -> Self {
+        WebError {
+            transient: true,
+            ..Self::msg(format!("{name} timed out after {HTTP_TIMEOUT_MS}ms"))
+        }
+    }
+
+    // the request could not be sent, or the connection failed before there was a response
+    fn transport(err: gloo_net::Error) -> Self {
+        WebError {
+            transient: true,
+            ..err.into()
+        }
+    }
+
+    // worth another try: requests that never got an answer, and the ones that the proxy
+    // answers when the server is briefly unavailable.  a response that fails to decode
+    // would only fail the same way again.
+    fn is_transient(&self) -> bool {
+        self.transient || matches!(self.status, Some(502..=504))
     }
 }
 
@@ -153,7 +253,7 @@ impl Default for WebError {
 
 impl Display for WebError {
     fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
-        self.0.fmt(f)
+        self.error.fmt(f)
     }
 }
 
@@ -161,13 +261,21 @@ impl serde::de::StdError for WebError {}
 
 impl From<gloo_net::Error> for WebError {
     fn from(value: gloo_net::Error) -> Self {
-        WebError(Arc::new(value.into()))
+        WebError {
+            error: Arc::new(value.into()),
+            status: None,
+            transient: false,
+        }
     }
 }
 
 impl From<anyhow::Error> for WebError {
     fn from(value: anyhow::Error) -> Self {
-        WebError(Arc::new(value))
+        WebError {
+            error: Arc::new(value),
+            status: None,
+            transient: false,
+        }
     }
 }
 
@@ -176,31 +284,167 @@ impl From<anyhow::Error> for WebError {
 // these functions control how the webapp communicates with the server, either by
 // creating the future directly or by providing a String that is interpreted by the
 // browser (img or a tags)
+//
+// every call gives up after HTTP_TIMEOUT_MS.  the endpoints marked idempotent, which only
+// read, are also retried on network errors and gateway failures, since those are usually
+// the reverse proxy or a flaky connection.  everything else is sent once, because a
+// mutation that timed out may well have gone through.
+pub const HTTP_TIMEOUT_MS: u32 = 60_000;
+pub const HTTP_RETRIES: u32 = 2;
+pub const HTTP_RETRY_BACKOFF_MS: u32 = 500;
+
 #[macro_export]
 macro_rules! http_endpoint {
     ($name:ident) => {
+        $crate::http_endpoint!($name, false);
+    };
+    ($name:ident, idempotent) => {
+        $crate::http_endpoint!($name, true);
+    };
+    ($name:ident, $idempotent:literal) => {
         pastey::paste!{
             pub async fn [<$name:snake>](req: &[<$name:camel Req>]) -> Result<[<$name:camel Resp>], $crate::WebError> {
-                use $crate::HTTP_URL_ROOT;
-                let resp = gloo_net::http::Request::post(format!("/{}/api/{}", HTTP_URL_ROOT, stringify!([<$name:camel>])).as_str())
-                    .json(&req.clone())?
-                    .send()
-                    .await?;
-
-                if resp.ok() {
-                    Ok(resp.json().await?)
-                } else {
-                    Err(anyhow::Error::msg(resp.text().await?).into())
-                }
+                $crate::http_post(stringify!([<$name:camel>]), req, $idempotent).await
             }
         }
     };
 }
 
+pub async fn http_post<Req, Resp>(name: &str, req: &Req, idempotent: bool) -> Result<Resp, WebError>
+where
+    Req: serde::Serialize,
+    Resp: serde::de::DeserializeOwned,
+{
+    let mut backoff = HTTP_RETRY_BACKOFF_MS;
+    let mut retries = if idempotent { HTTP_RETRIES } else { 0 };
+
+    loop {
+        match http_attempt(name, req).await {
+            Err(err) if retries > 0 && err.is_transient() => {
+                gloo_timers::future::TimeoutFuture::new(backoff).await;
+
+                backoff *= 2;
+                retries -= 1;
+            }
+            result => return result,
+        }
+    }
+}
+
+async fn http_attempt<Req, Resp>(name: &str, req: &Req) -> Result<Resp, WebError>
+where
+    Req: serde::Serialize,
+    Resp: serde::de::DeserializeOwned,
+{
+    let request = async {
+        let resp = gloo_net::http::Request::post(&format!("/{HTTP_URL_ROOT}/api/{name}"))
+            .json(req)?
+            .send()
+            .await
+            .map_err(WebError::transport)?;
+
+        if resp.ok() {
+            Ok(resp.json().await?)
+        } else {
+            Err(WebError::with_status(
+                resp.status(),
+                &resp.status_text(),
+                resp.text().await?,
+            ))
+        }
+    };
+
+    let timeout = gloo_timers::future::TimeoutFuture::new(HTTP_TIMEOUT_MS);
+
+    futures_util::pin_mut!(request);
+
+    match futures_util::future::select(request, timeout).await {
+        futures_util::future::Either::Left((result, _)) => result,
+        futures_util::future::Either::Right(_) => Err(WebError::timeout(name)),
+    }
+}
+
 pub fn full_link(media_uuid: media::MediaUuid) -> String {
     format!("/{HTTP_URL_ROOT}/media/{LINK_PATH}/{media_uuid}")
 }
 
-pub fn thumbnail_link(media_uuid: media::MediaUuid) -> String {
-    format!("/{HTTP_URL_ROOT}/media/{THUMBNAIL_PATH}/{media_uuid}")
+pub fn archive_link(collection_uuid: collection::CollectionUuid) -> String {
+    format!("/{HTTP_URL_ROOT}/media/{ARCHIVE_PATH}/{collection_uuid}.zip")
+}
+
+pub fn share_link(token: &str) -> String {
+    format!("/{HTTP_URL_ROOT}/{SHARE_PATH}/{token}")
+}
+
+pub fn thumbnail_link(media_uuid: media::MediaUuid, size: media::ThumbnailSize) -> String {
+    format!(
+        "/{HTTP_URL_ROOT}/media/{THUMBNAIL_PATH}/{media_uuid}?size={}",
+        size.query()
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn transport_failures_and_timeouts_are_transient() {
+        let err = WebError::transport(gloo_net::Error::GlooError(String::from("failed to fetch")));
+
+        assert!(err.is_transient());
+        assert!(WebError::timeout("SearchMedia").is_transient());
+    }
+
+    #[test]
+    fn gateway_failures_are_transient() {
+        for status in [502, 503, 504] {
+            assert!(WebError::with_status(status, "", String::new()).is_transient());
+        }
+    }
+
+    #[test]
+    fn other_errors_are_not_transient() {
+        for status in [400, 401, 404, 429, 500] {
+            assert!(!WebError::with_status(status, "", String::new()).is_transient());
+        }
+
+        let err = serde_json::from_str::<u64>("not json").unwrap_err();
+        assert!(!WebError::from(gloo_net::Error::SerdeError(err)).is_transient());
+
+        assert!(!WebError::from(anyhow::Error::msg("bad input")).is_transient());
+        assert!(!WebError::msg(String::from("bad input")).is_transient());
+    }
+
+    fn tags(tags: &[&str]) -> HashSet<String> {
+        tags.iter().map(|tag| tag.to_string()).collect()
+    }
+
+    #[test]
+    fn accepts_ordinary_tags() {
+        assert!(validate_tags(&tags(&[])).is_ok());
+        assert!(validate_tags(&tags(&["beach", "2021 trip", "DUPLICATE:abc"])).is_ok());
+    }
+
+    #[test]
+    fn rejects_blank_tags() {
+        assert!(validate_tags(&tags(&["beach", ""])).is_err());
+        assert!(validate_tags(&tags(&["  "])).is_err());
+        assert!(validate_tags(&tags(&["\t\n"])).is_err());
+    }
+
+    #[test]
+    fn rejects_tags_with_the_folding_separator() {
+        let err = validate_tags(&tags(&["a|b"])).unwrap_err();
+
+        assert!(err.to_string().contains("a|b"));
+    }
+
+    #[test]
+    fn validated_tags_survive_folding() {
+        let valid = tags(&["beach", "2021 trip", "sunset"]);
+
+        validate_tags(&valid).unwrap();
+
+        assert_eq!(unfold_set(&fold_set(valid.clone()).unwrap()), valid);
+    }
 }
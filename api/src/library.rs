@@ -1,14 +1,20 @@
 use serde::{Deserialize, Serialize};
+use utoipa::ToSchema;
 
 use crate::{http_endpoint, media::MediaUuid, search::SearchFilter, uuid_newtype};
 
 // structs
 uuid_newtype!(Library);
 
-#[derive(Clone, Debug, Deserialize, Serialize)]
+#[derive(Clone, Debug, Deserialize, Serialize, ToSchema, PartialEq)]
 pub struct Library {
     // the path to the library, relative to the media_srcdir
     pub path: String,
+    // for display, both empty until set with UpdateLibrary
+    #[serde(default)]
+    pub name: String,
+    #[serde(default)]
+    pub note: String,
     // effective user for running scripts
     pub uid: String,
     // owner gid used to check privileges
@@ -17,12 +23,25 @@ pub struct Library {
     pub count: i64,
 }
 
-#[derive(Clone, Debug, Deserialize, Serialize)]
+// a library along with the number of media in it, i.e. what SearchMediaInLibrary finds
+// with no filter and either hidden state.  unlike the count, which is every file that the
+// last scan saw, this leaves out unsupported files and the trash.
+#[derive(Clone, Debug, Deserialize, Serialize, ToSchema, PartialEq)]
+pub struct LibrarySummary {
+    pub library_uuid: LibraryUuid,
+    pub library: Library,
+    pub media: u64,
+}
+
+#[derive(Clone, Debug, Deserialize, Serialize, ToSchema)]
 pub struct LibraryUpdate {
+    pub name: Option<String>,
+    pub note: Option<String>,
+    // only ever set by the scanner
     pub count: Option<i64>,
 }
 
-#[derive(Clone, Debug, Deserialize, Serialize)]
+#[derive(Clone, Debug, Deserialize, Serialize, ToSchema)]
 pub struct LibraryScanJob {
     pub start_time: i64,
     pub file_count: i64,
@@ -33,42 +52,81 @@ pub struct LibraryScanJob {
 // messages
 
 // get the details for a particular library
-http_endpoint!(GetLibrary);
+http_endpoint!(GetLibrary, idempotent);
 
-#[derive(Clone, Debug, Deserialize, Serialize)]
+#[derive(Clone, Debug, Deserialize, Serialize, ToSchema)]
 pub struct GetLibraryReq {
     pub library_uuid: LibraryUuid,
 }
 
-#[derive(Clone, Debug, Deserialize, Serialize)]
+#[derive(Clone, Debug, Deserialize, Serialize, ToSchema)]
 pub struct GetLibraryResp {
     pub library: Library,
 }
 
-// find libraries
-http_endpoint!(SearchLibraries);
+// change the name or note of a library, which only its owners may do
+http_endpoint!(UpdateLibrary);
 
-#[derive(Clone, Debug, Deserialize, Serialize)]
+#[derive(Clone, Debug, Deserialize, Serialize, ToSchema)]
+pub struct UpdateLibraryReq {
+    pub library_uuid: LibraryUuid,
+    pub update: LibraryUpdate,
+}
+
+#[derive(Clone, Debug, Deserialize, Serialize, ToSchema)]
+pub struct UpdateLibraryResp {}
+
+// find libraries whose path, name, or note contains the filter
+http_endpoint!(SearchLibraries, idempotent);
+
+#[derive(Clone, Debug, Deserialize, Serialize, ToSchema)]
 pub struct SearchLibrariesReq {
     pub filter: String,
 }
 
-#[derive(Clone, Debug, Deserialize, Serialize)]
+#[derive(Clone, Debug, Deserialize, Serialize, ToSchema)]
 pub struct SearchLibrariesResp {
     pub libraries: Vec<LibraryUuid>,
 }
 
+// as above, but with the details of each library, so that a listing needs no GetLibrary
+// calls
+http_endpoint!(SearchLibrariesDetailed, idempotent);
+
+#[derive(Clone, Debug, Deserialize, Serialize, ToSchema)]
+pub struct SearchLibrariesDetailedReq {
+    pub filter: String,
+}
+
+#[derive(Clone, Debug, Deserialize, Serialize, ToSchema)]
+pub struct SearchLibrariesDetailedResp {
+    pub libraries: Vec<LibrarySummary>,
+}
+
 // find media inside of a library
-http_endpoint!(SearchMediaInLibrary);
+http_endpoint!(SearchMediaInLibrary, idempotent);
 
-#[derive(Clone, Debug, Deserialize, Serialize)]
+#[derive(Clone, Debug, Deserialize, Serialize, ToSchema)]
 pub struct SearchMediaInLibraryReq {
     pub library_uuid: LibraryUuid,
     pub hidden: Option<bool>,
     pub filter: SearchFilter,
 }
 
-#[derive(Clone, Debug, Deserialize, Serialize)]
+#[derive(Clone, Debug, Deserialize, Serialize, ToSchema)]
 pub struct SearchMediaInLibraryResp {
     pub media: Vec<MediaUuid>,
 }
+
+// count the hidden media in a library, which only its owners may do
+http_endpoint!(CountHiddenInLibrary, idempotent);
+
+#[derive(Clone, Debug, Deserialize, Serialize, ToSchema)]
+pub struct CountHiddenInLibraryReq {
+    pub library_uuid: LibraryUuid,
+}
+
+#[derive(Clone, Debug, Deserialize, Serialize, ToSchema)]
+pub struct CountHiddenInLibraryResp {
+    pub count: u64,
+}
@@ -0,0 +1,65 @@
+use ammonia::Builder;
+use pulldown_cmark::{Options, Parser, html};
+
+// markdown rendering for comments and notes
+//
+// the result is inserted into the page as raw html, so it must always go through ammonia.
+// pulldown-cmark copies inline html straight into its output and accepts links with any
+// url scheme (i.e. javascript:), so it is not safe on its own.  ammonia keeps the
+// formatting tags and drops everything else, including scripts, event handlers, and
+// links outside of its scheme allowlist.
+//
+// this lives here rather than in the server so that the webapp can render as it displays,
+// without storing html that would have to be re-sanitized if the rules ever change
+pub fn render_markdown(text: &str) -> String {
+    let parser = Parser::new_ext(text, Options::ENABLE_STRIKETHROUGH | Options::ENABLE_TABLES);
+
+    let mut unsafe_html = String::new();
+    html::push_html(&mut unsafe_html, parser);
+
+    Builder::default()
+        // a remote image would let the author see who reads the comment
+        .rm_tags(&["img"])
+        .clean(&unsafe_html)
+        .to_string()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn strips_scripts_and_handlers() {
+        let html = render_markdown(
+            "<script>alert(1)</script>\n\n<span onerror=\"alert(2)\">hi</span> \
+             <img src=\"x\" onerror=\"alert(3)\">",
+        );
+
+        assert!(!html.contains("<script"));
+        assert!(!html.contains("alert"));
+        assert!(!html.contains("onerror"));
+        assert!(!html.contains("<img"));
+        assert!(html.contains("hi"));
+    }
+
+    #[test]
+    fn strips_javascript_links() {
+        let html = render_markdown("[x](javascript:alert(1))");
+
+        assert!(!html.contains("javascript"));
+        assert!(html.contains('x'));
+    }
+
+    #[test]
+    fn keeps_formatting() {
+        let html = render_markdown(
+            "**bold** and ~~gone~~\n\n- one\n- two\n\n[link](https://example.com/a)",
+        );
+
+        assert!(html.contains("<strong>bold</strong>"));
+        assert!(html.contains("<del>gone</del>"));
+        assert!(html.contains("<ul>\n<li>one</li>\n<li>two</li>\n</ul>"));
+        assert!(html.contains("<a href=\"https://example.com/a\""));
+        assert!(html.contains(">link</a>"));
+    }
+}
@@ -2,16 +2,17 @@ use std::collections::HashSet;
 
 use serde::{Deserialize, Serialize};
 use postgres_types::{ToSql, FromSql};
+use utoipa::ToSchema;
 
 use crate::{
     collection::CollectionUuid, comment::CommentUuid, http_endpoint, library::LibraryUuid,
-    search::SearchFilter, uuid_newtype,
+    search::SearchFilter, sort::SortOrder, uuid_newtype, validate_tags,
 };
 
 // structs
 uuid_newtype!(Media);
 
-#[derive(Clone, Debug, Deserialize, Serialize, PartialEq)]
+#[derive(Clone, Debug, Deserialize, Serialize, PartialEq, ToSchema)]
 pub struct Media {
     pub library_uuid: LibraryUuid,
     pub path: String,
@@ -24,9 +25,23 @@ pub struct Media {
     pub note: String,
     pub tags: HashSet<String>,
     pub metadata: MediaMetadata,
+    // decimal degrees, taken from the exif gps tags when the media has them
+    pub latitude: Option<f64>,
+    pub longitude: Option<f64>,
+    // pixel dimensions, taken from the image header or the first video stream
+    pub width: Option<u32>,
+    pub height: Option<u32>,
+    // the remaining exif properties, which are only found on images.  capture_time is in
+    // the same format as date, but is only set when it came from the exif data.
+    pub camera: Option<String>,
+    pub orientation: Option<u32>,
+    pub capture_time: Option<String>,
+    // length of video and audio, and the codec of the first video stream
+    pub duration_secs: Option<f64>,
+    pub codec: Option<String>,
 }
 
-#[derive(Clone, Debug, Deserialize, FromSql, PartialEq, Serialize, strum::Display, strum::EnumString, ToSql)]
+#[derive(Clone, Debug, Deserialize, Eq, FromSql, Hash, PartialEq, Serialize, strum::Display, strum::EnumString, ToSql, ToSchema)]
 #[postgres(name = "media_type")]
 pub enum MediaMetadata {
     Image,
@@ -35,69 +50,366 @@ pub enum MediaMetadata {
     Audio,
 }
 
-#[derive(Clone, Debug, Deserialize, Serialize)]
+// thumbnail sizes
+//
+// grid thumbnails are created when media is scanned, while the larger sizes are created
+// by the http server the first time that they are requested
+#[derive(Clone, Copy, Debug, Default, Deserialize, Eq, Hash, PartialEq, Serialize, ToSchema)]
+#[serde(rename_all = "lowercase")]
+pub enum ThumbnailSize {
+    #[default]
+    Grid,
+    Preview,
+    Full,
+}
+
+impl ThumbnailSize {
+    // bound on the longest side of the thumbnail, in pixels
+    pub fn bound(&self) -> u32 {
+        match self {
+            Self::Grid => 400,
+            Self::Preview => 1200,
+            Self::Full => 2400,
+        }
+    }
+
+    // suffix for the thumbnail filename, where grid thumbnails keep the unsuffixed
+    // name from before there were multiple sizes
+    pub fn suffix(&self) -> &'static str {
+        match self {
+            Self::Grid => "",
+            Self::Preview => ".preview",
+            Self::Full => ".full",
+        }
+    }
+
+    pub fn query(&self) -> &'static str {
+        match self {
+            Self::Grid => "grid",
+            Self::Preview => "preview",
+            Self::Full => "full",
+        }
+    }
+}
+
+#[derive(Clone, Debug, Default, Deserialize, Serialize, ToSchema)]
 pub struct MediaUpdate {
     pub hidden: Option<bool>,
     pub date: Option<String>,
     pub note: Option<String>,
+    // replaces the whole tag set
     pub tags: Option<HashSet<String>>,
+    // applied to the stored tags (after any replacement) by the database, so that
+    // concurrent edits to different tags don't clobber each other
+    #[serde(default)]
+    pub tags_add: HashSet<String>,
+    #[serde(default)]
+    pub tags_remove: HashSet<String>,
+}
+
+impl MediaUpdate {
+    pub fn edits_tags(&self) -> bool {
+        !(self.tags_add.is_empty() && self.tags_remove.is_empty())
+    }
+
+    // removing a tag that can't exist is harmless, so only the new tags are checked
+    pub fn validate_tags(&self) -> anyhow::Result<()> {
+        if let Some(tags) = &self.tags {
+            validate_tags(tags)?;
+        }
+
+        validate_tags(&self.tags_add)
+    }
+}
+
+// where a group's access to a media comes from
+#[derive(Clone, Debug, Deserialize, Serialize, PartialEq, ToSchema)]
+pub enum AccessSource {
+    Library { library_uuid: LibraryUuid },
+    // only while the media is not hidden
+    Collection { collection_uuid: CollectionUuid },
+}
+
+#[derive(Clone, Debug, Deserialize, Serialize, PartialEq, ToSchema)]
+pub struct MediaAccess {
+    pub gid: String,
+    pub source: AccessSource,
 }
 
 // messages
 
 // fetch the media information for a particular file
-http_endpoint!(GetMedia);
+http_endpoint!(GetMedia, idempotent);
 
-#[derive(Clone, Debug, Deserialize, Serialize)]
+#[derive(Clone, Debug, Deserialize, Serialize, ToSchema)]
 pub struct GetMediaReq {
     pub media_uuid: MediaUuid,
 }
 
-#[derive(Clone, Debug, Deserialize, Serialize)]
+#[derive(Clone, Debug, Deserialize, Serialize, ToSchema)]
 pub struct GetMediaResp {
     pub media: Media,
     pub collections: Vec<CollectionUuid>,
     pub comments: Vec<CommentUuid>,
 }
 
+// add a new file to a library
+//
+// unlike the other endpoints, this is a multipart/form-data post with a library_uuid
+// field followed by a file field, so there is no request struct or endpoint function
+pub const UPLOAD_MEDIA_LIBRARY_FIELD: &str = "library_uuid";
+pub const UPLOAD_MEDIA_FILE_FIELD: &str = "file";
+
+// the extensions that a scan picks up, and thus the only files worth uploading.  this has
+// to be kept in sync with get_mtype() in the server's task/scan_utils.rs.
+pub const UPLOAD_MEDIA_EXTENSIONS: [&str; 21] = [
+    "jpg", "png", "tiff", "heic", "heif", "arw", "cr2", "dng", "nef", "orf", "raf", "rw2", "avi",
+    "mov", "mp4", "mp3", "flac", "ogg", "opus", "m4a", "wav",
+];
+
+// hidden files are refused by the server as well
+pub fn is_upload_filename(filename: &str) -> bool {
+    !filename.starts_with('.')
+        && filename
+            .rsplit_once('.')
+            .is_some_and(|(_, ext)| UPLOAD_MEDIA_EXTENSIONS.contains(&ext.to_lowercase().as_str()))
+}
+
+#[derive(Clone, Debug, Deserialize, Serialize, ToSchema)]
+pub struct UploadMediaResp {
+    pub media_uuid: MediaUuid,
+}
+
 // update the metadata
 http_endpoint!(UpdateMedia);
 
-#[derive(Clone, Debug, Deserialize, Serialize)]
+#[derive(Clone, Debug, Deserialize, Serialize, ToSchema)]
 pub struct UpdateMediaReq {
     pub media_uuid: MediaUuid,
     pub update: MediaUpdate,
 }
 
-#[derive(Clone, Debug, Deserialize, Serialize)]
+#[derive(Clone, Debug, Deserialize, Serialize, ToSchema)]
 pub struct UpdateMediaResp {}
 
+// apply the same update to many media at once
+//
+// the whole batch is rejected unless the user owns every media, and failed lists any
+// that could not be updated (i.e. because they no longer exist)
+http_endpoint!(BatchUpdateMedia);
+
+#[derive(Clone, Debug, Deserialize, Serialize, ToSchema)]
+pub struct BatchUpdateMediaReq {
+    pub media_uuids: Vec<MediaUuid>,
+    pub update: MediaUpdate,
+}
+
+#[derive(Clone, Debug, Deserialize, Serialize, ToSchema)]
+pub struct BatchUpdateMediaResp {
+    pub updated: Vec<MediaUuid>,
+    pub failed: Vec<MediaUuid>,
+}
+
+// reassign media to another library, moving the file into the same relative path under
+// the new library's directory
+//
+// the user has to own both the current library and the new one
+http_endpoint!(MoveMedia);
+
+#[derive(Clone, Debug, Deserialize, Serialize, ToSchema)]
+pub struct MoveMediaReq {
+    pub media_uuid: MediaUuid,
+    pub library_uuid: LibraryUuid,
+}
+
+#[derive(Clone, Debug, Deserialize, Serialize, ToSchema)]
+pub struct MoveMediaResp {}
+
 // search media
 //
 // note that we can implement a more complicated
 // filter struct later
-http_endpoint!(SearchMedia);
+//
+// limit and offset page through the results, and total is the
+// number of matches ignoring both
+http_endpoint!(SearchMedia, idempotent);
 
-#[derive(Clone, Debug, Default, Deserialize, Serialize)]
+#[derive(Clone, Debug, Default, Deserialize, Serialize, ToSchema)]
 pub struct SearchMediaReq {
     pub filter: SearchFilter,
+    pub sort: SortOrder,
+    pub limit: Option<u64>,
+    pub offset: Option<u64>,
 }
 
-#[derive(Clone, Debug, Default, PartialEq, Deserialize, Serialize)]
+#[derive(Clone, Debug, Default, PartialEq, Deserialize, Serialize, ToSchema)]
 pub struct SearchMediaResp {
     pub media: Vec<MediaUuid>,
+    pub total: u64,
 }
 
 // find similar media
-http_endpoint!(SimilarMedia);
+http_endpoint!(SimilarMedia, idempotent);
 
-#[derive(Clone, Debug, Deserialize, Serialize)]
+#[derive(Clone, Debug, Deserialize, Serialize, ToSchema)]
 pub struct SimilarMediaReq {
     pub media_uuid: MediaUuid,
     pub distance: i64,
 }
 
-#[derive(Clone, Debug, Default, PartialEq, Deserialize, Serialize)]
+// pairs of media and their phash distance from the requested one, closest first
+#[derive(Clone, Debug, Default, PartialEq, Deserialize, Serialize, ToSchema)]
 pub struct SimilarMediaResp {
+    pub media: Vec<(MediaUuid, i64)>,
+}
+
+// find media similar to an image that isn't in any library
+//
+// like UploadMedia, this is a multipart/form-data post, here with a distance field followed
+// by a file field.  the image is only kept long enough to hash it, and the response is a
+// SimilarMediaResp.
+pub const FIND_SIMILAR_DISTANCE_FIELD: &str = "distance";
+pub const FIND_SIMILAR_FILE_FIELD: &str = "file";
+
+// a random sample of the media that match the filter, for highlight reels and the like
+//
+// the count is capped at RANDOM_MEDIA_MAX, and fewer are returned if fewer match
+http_endpoint!(GetRandomMedia, idempotent);
+
+pub const RANDOM_MEDIA_MAX: u64 = 100;
+
+#[derive(Clone, Debug, Default, Deserialize, Serialize, ToSchema)]
+pub struct GetRandomMediaReq {
+    pub count: u64,
+    pub filter: SearchFilter,
+}
+
+#[derive(Clone, Debug, Default, PartialEq, Deserialize, Serialize, ToSchema)]
+pub struct GetRandomMediaResp {
+    pub media: Vec<MediaUuid>,
+}
+
+// move media to the trash, which hides it from every search other than SearchTrash
+http_endpoint!(DeleteMedia);
+
+#[derive(Clone, Debug, Deserialize, Serialize, ToSchema)]
+pub struct DeleteMediaReq {
+    pub media_uuid: MediaUuid,
+}
+
+#[derive(Clone, Debug, Deserialize, Serialize, ToSchema)]
+pub struct DeleteMediaResp {}
+
+// take media back out of the trash
+http_endpoint!(RestoreMedia);
+
+#[derive(Clone, Debug, Deserialize, Serialize, ToSchema)]
+pub struct RestoreMediaReq {
+    pub media_uuid: MediaUuid,
+}
+
+#[derive(Clone, Debug, Deserialize, Serialize, ToSchema)]
+pub struct RestoreMediaResp {}
+
+// permanently remove the records for media in the trash
+//
+// this is restricted to admins, since it cannot be undone
+http_endpoint!(PurgeMedia);
+
+#[derive(Clone, Debug, Deserialize, Serialize, ToSchema)]
+pub struct PurgeMediaReq {
+    pub media_uuid: MediaUuid,
+}
+
+#[derive(Clone, Debug, Deserialize, Serialize, ToSchema)]
+pub struct PurgeMediaResp {}
+
+// list the groups that can see a media and why, for owners trying to work out how
+// something is shared
+//
+// a group shows up once for each library or collection that grants it access
+http_endpoint!(GetMediaAccess, idempotent);
+
+#[derive(Clone, Debug, Deserialize, Serialize, ToSchema)]
+pub struct GetMediaAccessReq {
+    pub media_uuid: MediaUuid,
+}
+
+#[derive(Clone, Debug, Default, PartialEq, Deserialize, Serialize, ToSchema)]
+pub struct GetMediaAccessResp {
+    pub access: Vec<MediaAccess>,
+}
+
+// search the trash of every library that the user owns
+http_endpoint!(SearchTrash, idempotent);
+
+#[derive(Clone, Debug, Default, Deserialize, Serialize, ToSchema)]
+pub struct SearchTrashReq {
+    pub filter: SearchFilter,
+}
+
+#[derive(Clone, Debug, Default, PartialEq, Deserialize, Serialize, ToSchema)]
+pub struct SearchTrashResp {
     pub media: Vec<MediaUuid>,
 }
+
+// rename a tag on all media and collections owned by the user's groups, merging it
+// into the new tag wherever that is already present
+//
+// this is restricted to admins, since it touches everything at once
+http_endpoint!(RenameTag);
+
+#[derive(Clone, Debug, Deserialize, Serialize, ToSchema)]
+pub struct RenameTagReq {
+    pub from: String,
+    pub to: String,
+}
+
+#[derive(Clone, Debug, Deserialize, Serialize, ToSchema)]
+pub struct RenameTagResp {
+    pub count: u64,
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn tags(tags: &[&str]) -> HashSet<String> {
+        tags.iter().map(|tag| tag.to_string()).collect()
+    }
+
+    #[test]
+    fn update_checks_replaced_and_added_tags() {
+        assert!(MediaUpdate::default().validate_tags().is_ok());
+
+        let update = MediaUpdate {
+            tags: Some(tags(&["beach"])),
+            tags_add: tags(&["sunset"]),
+            ..Default::default()
+        };
+        assert!(update.validate_tags().is_ok());
+
+        let update = MediaUpdate {
+            tags: Some(tags(&["a|b"])),
+            ..Default::default()
+        };
+        assert!(update.validate_tags().is_err());
+
+        let update = MediaUpdate {
+            tags_add: tags(&[" "]),
+            ..Default::default()
+        };
+        assert!(update.validate_tags().is_err());
+    }
+
+    // removing a tag that could never have been stored is harmless
+    #[test]
+    fn update_ignores_removed_tags() {
+        let update = MediaUpdate {
+            tags_remove: tags(&["", "a|b"]),
+            ..Default::default()
+        };
+
+        assert!(update.validate_tags().is_ok());
+    }
+}
@@ -6,13 +6,14 @@ use std::{
 use itertools::Itertools;
 use regex::escape;
 use serde::{Deserialize, Serialize};
+use utoipa::ToSchema;
 
 use crate::{
     collection::{CollectionUuid, SearchMediaInCollectionReq},
     comment::CommentUuid,
     http_endpoint,
     library::SearchMediaInLibraryReq,
-    media::{Media, MediaUuid, SearchMediaReq},
+    media::{Media, MediaMetadata, MediaUuid, SearchMediaReq},
     sort::SortMethod,
 };
 
@@ -23,12 +24,37 @@ use crate::{
 //
 // TODO -- to use the Substring filters more optimally, we need a better splitting
 // algorithm than whitespace so as to keep quoted phrases together
-#[derive(Clone, Debug, Deserialize, Serialize)]
+#[derive(Clone, Debug, Deserialize, Serialize, ToSchema)]
 pub enum SearchFilter {
-    SubstringAny { filter: HashSet<String> },
-    SubstringAll { filter: HashSet<String> },
-    Fulltext { filter: String },
-    Keyword { filter: HashSet<String> },
+    SubstringAny {
+        filter: HashSet<String>,
+    },
+    SubstringAll {
+        filter: HashSet<String>,
+    },
+    Fulltext {
+        filter: String,
+    },
+    Keyword {
+        filter: HashSet<String>,
+    },
+    DateRange {
+        start: Option<i64>,
+        end: Option<i64>,
+    },
+    MediaType(HashSet<MediaMetadata>),
+    NearLocation {
+        lat: f64,
+        lon: f64,
+        radius_km: f64,
+    },
+    // the schema refers back to SearchFilter instead of inlining it forever
+    #[schema(no_recursion)]
+    All(Vec<SearchFilter>),
+    #[schema(no_recursion)]
+    Any(Vec<SearchFilter>),
+    #[schema(no_recursion)]
+    Not(Box<SearchFilter>),
 }
 
 impl Default for SearchFilter {
@@ -54,86 +80,302 @@ impl Display for SearchFilter {
             Self::Keyword { filter } => {
                 write!(f, "Keyword{filter:?}")
             }
+            Self::DateRange { start, end } => {
+                write!(f, "DateRange{{{start:?}, {end:?}}}")
+            }
+            Self::MediaType(types) => {
+                write!(f, "MediaType{{{}}}", types.iter().join(", "))
+            }
+            Self::NearLocation {
+                lat,
+                lon,
+                radius_km,
+            } => {
+                write!(f, "NearLocation{{{lat}, {lon}, {radius_km}km}}")
+            }
+            Self::All(filters) => {
+                write!(f, "All[{}]", filters.iter().join(", "))
+            }
+            Self::Any(filters) => {
+                write!(f, "Any[{}]", filters.iter().join(", "))
+            }
+            Self::Not(filter) => {
+                write!(f, "Not{{{filter}}}")
+            }
         }
     }
 }
 
+// approximate the circle around a point with a latitude/longitude bounding box
+//
+// this is deliberately crude, since it only needs to answer "roughly near here" without any
+// spatial extensions in the database.  the box is widened in longitude by the latitude, and
+// is split in two where it crosses the antimeridian.  the value closure formats each bound
+// for the particular backend.
+fn location_predicate(
+    lat: f64,
+    lon: f64,
+    radius_km: f64,
+    mut value: impl FnMut(f64) -> String,
+) -> Option<String> {
+    const KM_PER_DEGREE: f64 = 111.32;
+
+    if !(lat.is_finite() && lon.is_finite() && radius_km.is_finite()) || radius_km < 0.0 {
+        return Some(String::from("FALSE"));
+    }
+
+    let dlat = radius_km / KM_PER_DEGREE;
+    let lat_min = (lat - dlat).max(-90.0);
+    let lat_max = (lat + dlat).min(90.0);
+
+    let mut pred = format!(
+        "media.latitude BETWEEN {} AND {}",
+        value(lat_min),
+        value(lat_max)
+    );
+
+    // near the poles (or for huge radii) every longitude is in range
+    let cos = lat.to_radians().cos();
+
+    if cos < 1e-6 || radius_km / (KM_PER_DEGREE * cos) >= 180.0 {
+        return Some(format!("({pred} AND media.longitude IS NOT NULL)"));
+    }
+
+    let dlon = radius_km / (KM_PER_DEGREE * cos);
+    let (lon_min, lon_max) = (lon - dlon, lon + dlon);
+
+    let ranges = if lon_min < -180.0 {
+        vec![(lon_min + 360.0, 180.0), (-180.0, lon_max)]
+    } else if lon_max > 180.0 {
+        vec![(lon_min, 180.0), (-180.0, lon_max - 360.0)]
+    } else {
+        vec![(lon_min, lon_max)]
+    };
+
+    let ranges = ranges
+        .into_iter()
+        .map(|(a, b)| format!("media.longitude BETWEEN {} AND {}", value(a), value(b)))
+        .join(" OR ");
+
+    pred.push_str(&format!(" AND ({ranges})"));
+
+    Some(format!("({pred})"))
+}
+
+// substring matching in mariadb
+//
+// RLIKE compares code points, so accents always matter to it, while LIKE follows whatever
+// collation the columns were created with.  instead, both sides are lowercased and compared
+// under an explicit case- and accent-insensitive collation, so that "cafe" finds "Café"
+// regardless of the database defaults.
+//
+// the pattern is the value to bind for the parameter, with the LIKE wildcards escaped.
+pub const MARIADB_SEARCH_COLLATION: &str = "utf8mb4_unicode_ci";
+
+pub fn mariadb_contains(col: &str, param: &str) -> String {
+    format!("LOWER({col}) LIKE LOWER({param}) COLLATE {MARIADB_SEARCH_COLLATION} ESCAPE '!'")
+}
+
+pub fn mariadb_contains_pattern(value: &str) -> String {
+    let value = value
+        .replace('!', "!!")
+        .replace('%', "!%")
+        .replace('_', "!_");
+
+    format!("%{value}%")
+}
+
+// whole-word matching in mariadb
+//
+// REGEXP_REPLACE squeezes every run of non-word characters in the haystack down to a single
+// space and pads both ends, so a word always sits between two spaces.  the pattern gets the
+// same treatment and is bound as "% word %", so "cat" finds "the cat" but not "catalog",
+// while mariadb_contains() still handles the case and accents.  (*UCP) makes [:alnum:]
+// include accented letters, which would otherwise split "Café" into "caf".
+pub fn mariadb_words(col: &str) -> String {
+    format!("CONCAT(' ', REGEXP_REPLACE({col}, '(*UCP)[^[:alnum:]_]+', ' '), ' ')")
+}
+
+pub fn mariadb_words_pattern(value: &str) -> String {
+    let words = value
+        .split(|c: char| !(c.is_alphanumeric() || c == '_'))
+        .filter(|word| !word.is_empty())
+        .join(" ");
+
+    // a string with no words at all has nothing to match on, like an empty filter
+    if words.is_empty() {
+        return String::from("%");
+    }
+
+    let words = words.replace('!', "!!").replace('_', "!_");
+
+    format!("% {words} %")
+}
+
 impl SearchFilter {
     // mariadb formatting for mysql_async queries
     //
-    // returns (sql, filter) where 'sql' is a fragment of an sql query
-    // and filter is the named parameter
-    pub fn format_mariadb(&self, cols: &str) -> (String, String) {
+    // returns (sql, params) where 'sql' is a fragment of an sql query
+    // and params are the named parameters it references
+    pub fn format_mariadb(&self, cols: &str) -> (String, Vec<(String, String)>) {
+        let mut params = Vec::new();
+
+        match self.mariadb_predicate(cols, &mut params) {
+            Some(sql) => (format!(" AND {sql}"), params),
+            None => (String::new(), params),
+        }
+    }
+
+    // every literal gets its own named parameter (:filter0, :filter1, ...) so that the
+    // combinators can nest arbitrarily without ever putting user input in the query
+    //
+    // None means that the filter matches everything and can be dropped entirely
+    fn mariadb_predicate(&self, cols: &str, params: &mut Vec<(String, String)>) -> Option<String> {
+        let mut bind = |value: String| {
+            let name = format!("filter{}", params.len());
+            let sql = format!(":{name}");
+            params.push((name, value));
+            sql
+        };
+
+        let haystack = format!("CONCAT_WS(\"|\", {cols})");
+
         match self {
-            // match any of the strings using the normal regex logical OR |
-            // (?i) enables case-insensitive matching
+            // match any of the strings, see mariadb_contains() for the normalization
             Self::SubstringAny { filter } => {
                 if filter.is_empty() {
-                    return (String::new(), String::new());
+                    return None;
                 }
 
-                // note that, while this is superficially the same as fold_set(), the pipe here is a
-                // regex operator and thus specific to the query
-                let regex = filter
+                let preds = filter
                     .iter()
-                    .map(|s| escape(s))
-                    .fold(String::from("(?i)"), |a, b| a + &b + "|")
-                    .trim_matches('|')
-                    .to_string();
+                    .map(|s| mariadb_contains(&haystack, &bind(mariadb_contains_pattern(s))))
+                    .join(" OR ");
 
-                (
-                    format!(" AND CONCAT_WS(\"|\", {cols}) RLIKE :filter"),
-                    regex,
-                )
+                Some(format!("({preds})"))
             }
 
-            // match all of the strings using an expensive lookahead assertion (?=) and word boundary \b
+            // match all of the strings as whole words, see mariadb_words()
+            //
+            // this used to be a regex lookahead with word boundaries, but RLIKE can't ignore
+            // accents, so each string is a separate LIKE against the padded words instead
             Self::SubstringAll { filter } => {
                 if filter.is_empty() {
-                    return (String::new(), String::new());
+                    return None;
                 }
 
-                let regex = filter
+                let words = mariadb_words(&haystack);
+
+                let preds = filter
                     .iter()
-                    .map(|s| escape(s))
-                    .fold(String::from("(?i)"), |a, b| {
-                        a + &format!("(?=.*?\\b{b}\\b)")
-                    });
+                    .map(|s| mariadb_contains(&words, &bind(mariadb_words_pattern(s))))
+                    .join(" AND ");
 
-                (
-                    format!(" AND CONCAT_WS(\"|\", {cols}) RLIKE :filter"),
-                    regex,
-                )
+                Some(format!("({preds})"))
             }
 
             // use mariadb's fulltext index/search mechanism with several sorts of operators built-in,
             // so we don't need to parse anything
             Self::Fulltext { filter } => {
                 if filter.is_empty() {
-                    return (String::new(), String::new());
+                    return None;
                 }
 
-                (
-                    format!(" AND MATCH({cols}) AGAINST(:filter IN BOOLEAN MODE)"),
-                    filter.clone(),
-                )
+                Some(format!(
+                    "MATCH({cols}) AGAINST({} IN BOOLEAN MODE)",
+                    bind(filter.clone())
+                ))
             }
 
             // use the fulltext search as keywords, which expects a comma-separated list
             Self::Keyword { filter } => {
                 if filter.is_empty() {
-                    return (String::new(), String::new());
+                    return None;
                 }
                 let keywords = filter
                     .iter()
                     .fold(String::from(""), |a, b| a + b + ",")
                     .trim_matches(',')
                     .to_string();
-                (
-                    format!(" AND MATCH({cols}) AGAINST(:filter IN NATURAL LANGUAGE MODE)"),
-                    keywords,
-                )
+
+                Some(format!(
+                    "MATCH({cols}) AGAINST({} IN NATURAL LANGUAGE MODE)",
+                    bind(keywords)
+                ))
+            }
+
+            // restrict media.date to a window of unix timestamps, with either side left open
+            //
+            // media.date is free text taken from the exif/ffprobe metadata, so the conversion is
+            // done in the database and anything that doesn't parse (including "") drops out.  the
+            // cols are ignored since this only makes sense for media searches.
+            Self::DateRange { start, end } => {
+                let date = "UNIX_TIMESTAMP(REPLACE(LEFT(media.date, 19), 'T', ' '))";
+
+                match (start, end) {
+                    (None, None) => None,
+                    (Some(start), None) => Some(format!("{date} >= {}", bind(start.to_string()))),
+                    (None, Some(end)) => Some(format!("{date} <= {}", bind(end.to_string()))),
+                    (Some(start), Some(end)) => Some(format!(
+                        "{date} BETWEEN {} AND {}",
+                        bind(start.to_string()),
+                        bind(end.to_string())
+                    )),
+                }
+            }
+
+            // the strings are the strum::Display names, which are the same ones used by
+            // add_media() and get_media() for the media_type column
+            Self::MediaType(types) => {
+                if types.is_empty() {
+                    return None;
+                }
+
+                let types = types.iter().map(|t| bind(t.to_string())).join(", ");
+
+                Some(format!("media.media_type IN ({types})"))
+            }
+
+            // media without a location has NULL coordinates and never matches
+            Self::NearLocation {
+                lat,
+                lon,
+                radius_km,
+            } => location_predicate(*lat, *lon, *radius_km, |v| bind(v.to_string())),
+
+            // the combinators follow the same convention as the flat filters, so that an empty
+            // filter (anywhere in the tree) matches everything
+            Self::All(filters) => {
+                let preds = filters
+                    .iter()
+                    .filter_map(|f| f.mariadb_predicate(cols, params))
+                    .collect::<Vec<String>>();
+
+                if preds.is_empty() {
+                    return None;
+                }
+
+                Some(format!("({})", preds.join(" AND ")))
+            }
+
+            Self::Any(filters) => {
+                let preds = filters
+                    .iter()
+                    .map(|f| f.mariadb_predicate(cols, params))
+                    .collect::<Option<Vec<String>>>()?;
+
+                if preds.is_empty() {
+                    return None;
+                }
+
+                Some(format!("({})", preds.join(" OR ")))
             }
+
+            // negating a filter that matches everything must match nothing
+            Self::Not(filter) => match filter.mariadb_predicate(cols, params) {
+                Some(pred) => Some(format!("(NOT {pred})")),
+                None => Some(String::from("FALSE")),
+            },
         }
     }
 
@@ -141,45 +383,281 @@ impl SearchFilter {
     //
     // https://www.postgresql.org/docs/current/textsearch-controls.html
     pub fn format_postgres(&self, ts_col: &str) -> String {
+        match self.postgres_predicate(ts_col) {
+            Some(sql) => format!(" AND {sql}"),
+            None => String::new(),
+        }
+    }
+
+    fn postgres_predicate(&self, ts_col: &str) -> Option<String> {
         match self {
             Self::SubstringAny { filter } => {
                 if filter.is_empty() {
-                    return String::new();
+                    return None;
                 }
 
                 let ts_query = filter.iter().map(|s| s.to_owned()).join(" | ");
 
-                format!(" AND {ts_col} @@ to_tsquery('english', '{ts_query}')")
+                Some(format!("{ts_col} @@ to_tsquery('english', '{ts_query}')"))
             }
 
             Self::SubstringAll { filter } => {
                 if filter.is_empty() {
-                    return String::new();
+                    return None;
                 }
 
                 let ts_query = filter.iter().map(|s| s.to_owned()).join(" & ");
 
-                format!(" AND {ts_col} @@ to_tsquery('english', '{ts_query}')")
+                Some(format!("{ts_col} @@ to_tsquery('english', '{ts_query}')"))
             }
 
             Self::Fulltext { filter } => {
                 if filter.is_empty() {
-                    return String::new();
+                    return None;
                 }
 
-                format!(" AND {ts_col} @@ websearch_to_tsquery('english', '{filter}')")
+                Some(format!(
+                    "{ts_col} @@ websearch_to_tsquery('english', '{filter}')"
+                ))
             }
 
             // use the fulltext search as keywords
             Self::Keyword { filter } => {
                 if filter.is_empty() {
-                    return String::new();
+                    return None;
                 }
 
                 let ts_query = filter.iter().map(|s| s.to_owned()).join(" | ");
 
-                format!(" AND {ts_col} @@ to_tsquery('english', '{ts_query}')")
+                Some(format!("{ts_col} @@ to_tsquery('english', '{ts_query}')"))
+            }
+
+            // see the mariadb version for details.  since the bounds are integers, it is safe
+            // to insert them directly, and the regex guard keeps the cast from erroring out on
+            // dates that can't be parsed
+            Self::DateRange { start, end } => {
+                let date = "(CASE WHEN media.date ~ '^\\d{4}-\\d{2}-\\d{2}' \
+                    THEN EXTRACT(EPOCH FROM REPLACE(LEFT(media.date, 19), 'T', ' ')::timestamp) END)";
+
+                match (start, end) {
+                    (None, None) => None,
+                    (Some(start), None) => Some(format!("{date} >= {start}")),
+                    (None, Some(end)) => Some(format!("{date} <= {end}")),
+                    (Some(start), Some(end)) => Some(format!("{date} BETWEEN {start} AND {end}")),
+                }
+            }
+
+            // the variant names are fixed, so they are safe to insert directly
+            Self::MediaType(types) => {
+                if types.is_empty() {
+                    return None;
+                }
+
+                let types = types.iter().map(|t| format!("'{t}'")).join(", ");
+
+                Some(format!("media.media_type IN ({types})"))
             }
+
+            // the bounds are floats, so they are safe to insert directly
+            Self::NearLocation {
+                lat,
+                lon,
+                radius_km,
+            } => location_predicate(*lat, *lon, *radius_km, |v| v.to_string()),
+
+            Self::All(filters) => {
+                let preds = filters
+                    .iter()
+                    .filter_map(|f| f.postgres_predicate(ts_col))
+                    .collect::<Vec<String>>();
+
+                if preds.is_empty() {
+                    return None;
+                }
+
+                Some(format!("({})", preds.join(" AND ")))
+            }
+
+            Self::Any(filters) => {
+                let preds = filters
+                    .iter()
+                    .map(|f| f.postgres_predicate(ts_col))
+                    .collect::<Option<Vec<String>>>()?;
+
+                if preds.is_empty() {
+                    return None;
+                }
+
+                Some(format!("({})", preds.join(" OR ")))
+            }
+
+            Self::Not(filter) => match filter.postgres_predicate(ts_col) {
+                Some(pred) => Some(format!("(NOT {pred})")),
+                None => Some(String::from("FALSE")),
+            },
+        }
+    }
+
+    // sqlite formatting for rusqlite queries
+    //
+    // the return value and parameter naming match format_mariadb(), although rusqlite expects the
+    // leading colon to be part of the parameter name.  sqlite has no fulltext index on these tables
+    // and its REGEXP operator is backed by the regex crate, which has no lookahead assertions, so
+    // several of the filters are approximated with simpler operators.
+    pub fn format_sqlite(&self, cols: &str) -> (String, Vec<(String, String)>) {
+        let mut params = Vec::new();
+
+        match self.sqlite_predicate(cols, &mut params) {
+            Some(sql) => (format!(" AND {sql}"), params),
+            None => (String::new(), params),
+        }
+    }
+
+    fn sqlite_predicate(&self, cols: &str, params: &mut Vec<(String, String)>) -> Option<String> {
+        let mut bind = |value: String| {
+            let name = format!("filter{}", params.len());
+            let sql = format!(":{name}");
+            params.push((name, value));
+            sql
+        };
+
+        match self {
+            Self::SubstringAny { filter } => {
+                if filter.is_empty() {
+                    return None;
+                }
+
+                let regex = filter
+                    .iter()
+                    .map(|s| escape(s))
+                    .fold(String::from("(?i)"), |a, b| a + &b + "|")
+                    .trim_matches('|')
+                    .to_string();
+
+                Some(format!("concat_ws('|', {cols}) REGEXP {}", bind(regex)))
+            }
+
+            // one word-boundary match per string in place of the lookahead
+            Self::SubstringAll { filter } => {
+                if filter.is_empty() {
+                    return None;
+                }
+
+                let preds = filter
+                    .iter()
+                    .map(|s| {
+                        format!(
+                            "concat_ws('|', {cols}) REGEXP {}",
+                            bind(format!("(?i)\\b{}\\b", escape(s)))
+                        )
+                    })
+                    .join(" AND ");
+
+                Some(format!("({preds})"))
+            }
+
+            // LIKE is case-insensitive for ascii, which is the best we can do without fts5
+            Self::Fulltext { filter } => {
+                if filter.is_empty() {
+                    return None;
+                }
+
+                Some(format!(
+                    "concat_ws('|', {cols}) LIKE '%' || {} || '%'",
+                    bind(filter.clone())
+                ))
+            }
+
+            Self::Keyword { filter } => {
+                if filter.is_empty() {
+                    return None;
+                }
+
+                let preds = filter
+                    .iter()
+                    .map(|s| {
+                        format!(
+                            "concat_ws('|', {cols}) LIKE '%' || {} || '%'",
+                            bind(s.clone())
+                        )
+                    })
+                    .join(" OR ");
+
+                Some(format!("({preds})"))
+            }
+
+            // see the mariadb version for details.  strftime() returns NULL for anything that it
+            // can't parse, so those rows drop out just like they do there.
+            Self::DateRange { start, end } => {
+                let date =
+                    "CAST(strftime('%s', REPLACE(SUBSTR(media.date, 1, 19), 'T', ' ')) AS INTEGER)";
+
+                match (start, end) {
+                    (None, None) => None,
+                    (Some(start), None) => Some(format!(
+                        "{date} >= CAST({} AS INTEGER)",
+                        bind(start.to_string())
+                    )),
+                    (None, Some(end)) => Some(format!(
+                        "{date} <= CAST({} AS INTEGER)",
+                        bind(end.to_string())
+                    )),
+                    (Some(start), Some(end)) => Some(format!(
+                        "{date} BETWEEN CAST({} AS INTEGER) AND CAST({} AS INTEGER)",
+                        bind(start.to_string()),
+                        bind(end.to_string())
+                    )),
+                }
+            }
+
+            Self::MediaType(types) => {
+                if types.is_empty() {
+                    return None;
+                }
+
+                let types = types.iter().map(|t| bind(t.to_string())).join(", ");
+
+                Some(format!("media.media_type IN ({types})"))
+            }
+
+            Self::NearLocation {
+                lat,
+                lon,
+                radius_km,
+            } => location_predicate(*lat, *lon, *radius_km, |v| {
+                format!("CAST({} AS REAL)", bind(v.to_string()))
+            }),
+
+            Self::All(filters) => {
+                let preds = filters
+                    .iter()
+                    .filter_map(|f| f.sqlite_predicate(cols, params))
+                    .collect::<Vec<String>>();
+
+                if preds.is_empty() {
+                    return None;
+                }
+
+                Some(format!("({})", preds.join(" AND ")))
+            }
+
+            Self::Any(filters) => {
+                let preds = filters
+                    .iter()
+                    .map(|f| f.sqlite_predicate(cols, params))
+                    .collect::<Option<Vec<String>>>()?;
+
+                if preds.is_empty() {
+                    return None;
+                }
+
+                Some(format!("({})", preds.join(" OR ")))
+            }
+
+            Self::Not(filter) => match filter.sqlite_predicate(cols, params) {
+                Some(pred) => Some(format!("(NOT {pred})")),
+                None => Some(String::from("FALSE")),
+            },
         }
     }
 }
@@ -193,14 +671,14 @@ impl SearchFilter {
 // unfortunately, the current implementation isn't particularly performant... and this will
 // generally be one of the most important functions in the system.  thus, we will need to
 // think very carefully about the architecture and optimizations involved.
-#[derive(Clone, Debug, Deserialize, Serialize)]
+#[derive(Clone, Debug, Deserialize, Serialize, ToSchema)]
 pub enum SearchRequest {
     Media(SearchMediaReq),
     Collection(SearchMediaInCollectionReq),
     Library(SearchMediaInLibraryReq),
 }
 
-#[derive(Clone, Debug, Deserialize, Serialize)]
+#[derive(Clone, Debug, Deserialize, Serialize, ToSchema)]
 pub struct SearchResponse {
     pub media_uuid: MediaUuid,
     pub media: Media,
@@ -208,15 +686,339 @@ pub struct SearchResponse {
     pub comments: Vec<CommentUuid>,
 }
 
-http_endpoint!(BatchSearchAndSort);
+http_endpoint!(BatchSearchAndSort, idempotent);
 
-#[derive(Clone, Debug, Deserialize, Serialize)]
+#[derive(Clone, Debug, Deserialize, Serialize, ToSchema)]
 pub struct BatchSearchAndSortReq {
     pub req: SearchRequest,
     pub sort: SortMethod,
 }
 
-#[derive(Clone, Debug, Deserialize, Serialize)]
+#[derive(Clone, Debug, Deserialize, Serialize, ToSchema)]
 pub struct BatchSearchAndSortResp {
     pub media: Vec<SearchResponse>,
+    // the number of matches ignoring limit and offset, so that a client paging through
+    // SearchMedia knows when it has reached the end
+    pub total: u64,
+}
+
+// StreamSearchAndSort takes a BatchSearchAndSortReq, but answers with newline-delimited
+// json, one SearchResponse per line, written as the server fetches them.  the total is
+// known before the first line, so it is sent in this header instead.
+//
+// there is no http_endpoint!() for it, since gloo_net can only hand back the whole body.
+pub const SEARCH_TOTAL_HEADER: &str = "x-entanglement-total";
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // single-element sets keep the generated sql in a predictable order
+    fn any(s: &str) -> SearchFilter {
+        SearchFilter::SubstringAny {
+            filter: HashSet::from([s.to_owned()]),
+        }
+    }
+
+    fn params(names: &[(&str, &str)]) -> Vec<(String, String)> {
+        names
+            .iter()
+            .map(|(k, v)| (k.to_string(), v.to_string()))
+            .collect()
+    }
+
+    #[test]
+    fn empty_filters_match_everything() {
+        for filter in [
+            SearchFilter::default(),
+            SearchFilter::All(vec![]),
+            SearchFilter::Any(vec![]),
+            SearchFilter::All(vec![SearchFilter::default(), SearchFilter::Any(vec![])]),
+            SearchFilter::DateRange {
+                start: None,
+                end: None,
+            },
+        ] {
+            assert_eq!(filter.format_mariadb("c"), (String::new(), vec![]));
+            assert_eq!(filter.format_postgres("ts"), String::new());
+            assert_eq!(filter.format_sqlite("c"), (String::new(), vec![]));
+        }
+    }
+
+    #[test]
+    fn not_of_match_all_matches_nothing() {
+        let filter = SearchFilter::Not(Box::default());
+
+        assert_eq!(filter.format_mariadb("c").0, " AND FALSE");
+        assert_eq!(filter.format_postgres("ts"), " AND FALSE");
+        assert_eq!(filter.format_sqlite("c").0, " AND FALSE");
+    }
+
+    #[test]
+    fn any_with_match_all_member_is_dropped() {
+        let filter = SearchFilter::Any(vec![any("cat"), SearchFilter::default()]);
+
+        assert_eq!(filter.format_mariadb("c").0, "");
+        assert_eq!(filter.format_postgres("ts"), "");
+        assert_eq!(filter.format_sqlite("c").0, "");
+    }
+
+    #[test]
+    fn all_skips_empty_members() {
+        let filter = SearchFilter::All(vec![any("cat"), SearchFilter::default()]);
+
+        assert_eq!(
+            filter.format_mariadb("c"),
+            (
+                String::from(
+                    " AND ((LOWER(CONCAT_WS(\"|\", c)) LIKE LOWER(:filter0) \
+                     COLLATE utf8mb4_unicode_ci ESCAPE '!'))"
+                ),
+                params(&[("filter0", "%cat%")])
+            )
+        );
+    }
+
+    #[test]
+    fn nested_combinators_mariadb() {
+        let filter = SearchFilter::All(vec![
+            SearchFilter::Any(vec![any("cat"), any("dog")]),
+            SearchFilter::Not(Box::new(SearchFilter::Fulltext {
+                filter: String::from("bird"),
+            })),
+        ]);
+
+        let (sql, binds) = filter.format_mariadb("c");
+
+        let contains = |p: &str| {
+            format!(
+                "LOWER(CONCAT_WS(\"|\", c)) LIKE LOWER(:{p}) COLLATE utf8mb4_unicode_ci ESCAPE '!'"
+            )
+        };
+
+        assert_eq!(
+            sql,
+            format!(
+                " AND ((({}) OR ({})) AND (NOT MATCH(c) AGAINST(:filter2 IN BOOLEAN MODE)))",
+                contains("filter0"),
+                contains("filter1")
+            )
+        );
+        assert_eq!(
+            binds,
+            params(&[
+                ("filter0", "%cat%"),
+                ("filter1", "%dog%"),
+                ("filter2", "bird")
+            ])
+        );
+    }
+
+    #[test]
+    fn mariadb_escapes_like_wildcards() {
+        assert_eq!(mariadb_contains_pattern("50%_off!"), "%50!%!_off!!%");
+    }
+
+    // the lowercasing happens on both sides in the query, so the bound values keep their case
+    // and accents and rely on the collation to fold them
+    #[test]
+    fn mariadb_any_folds_case_and_accents() {
+        assert_eq!(
+            any("CaFé 100%_!").format_mariadb("c"),
+            (
+                String::from(
+                    " AND (LOWER(CONCAT_WS(\"|\", c)) LIKE LOWER(:filter0) \
+                     COLLATE utf8mb4_unicode_ci ESCAPE '!')"
+                ),
+                params(&[("filter0", "%CaFé 100!%!_!!%")])
+            )
+        );
+    }
+
+    #[test]
+    fn mariadb_all_matches_whole_words() {
+        let filter = SearchFilter::SubstringAll {
+            filter: HashSet::from([String::from("Cafe")]),
+        };
+
+        // " cafe " is not in " catalog cafeteria ", but it is in " le café "
+        assert_eq!(
+            filter.format_mariadb("c"),
+            (
+                String::from(
+                    " AND (LOWER(CONCAT(' ', REGEXP_REPLACE(CONCAT_WS(\"|\", c), \
+                     '(*UCP)[^[:alnum:]_]+', ' '), ' ')) LIKE LOWER(:filter0) \
+                     COLLATE utf8mb4_unicode_ci ESCAPE '!')"
+                ),
+                params(&[("filter0", "% Cafe %")])
+            )
+        );
+    }
+
+    #[test]
+    fn mariadb_words_pattern_escapes_like_wildcards() {
+        // % and ! separate words, while _ is part of one
+        assert_eq!(mariadb_words_pattern("Café"), "% Café %");
+        assert_eq!(mariadb_words_pattern("  new   york "), "% new york %");
+        assert_eq!(mariadb_words_pattern("50%_off!"), "% 50 !_off %");
+        assert_eq!(mariadb_words_pattern("snake_case"), "% snake!_case %");
+        assert_eq!(mariadb_words_pattern("!%!"), "%");
+    }
+
+    #[test]
+    fn nested_combinators_postgres() {
+        let filter = SearchFilter::Any(vec![
+            any("cat"),
+            SearchFilter::Not(Box::new(SearchFilter::MediaType(HashSet::from([
+                MediaMetadata::Video,
+            ])))),
+        ]);
+
+        assert_eq!(
+            filter.format_postgres("ts"),
+            " AND (ts @@ to_tsquery('english', 'cat') OR (NOT media.media_type IN ('Video')))"
+        );
+    }
+
+    #[test]
+    fn nested_combinators_sqlite() {
+        let filter = SearchFilter::All(vec![
+            any("c.t"),
+            SearchFilter::DateRange {
+                start: Some(10),
+                end: None,
+            },
+        ]);
+
+        let (sql, binds) = filter.format_sqlite("c");
+
+        assert_eq!(
+            sql,
+            " AND (concat_ws('|', c) REGEXP :filter0 AND \
+             CAST(strftime('%s', REPLACE(SUBSTR(media.date, 1, 19), 'T', ' ')) AS INTEGER) \
+             >= CAST(:filter1 AS INTEGER))"
+        );
+        assert_eq!(
+            binds,
+            params(&[("filter0", "(?i)c\\.t"), ("filter1", "10")])
+        );
+    }
+
+    fn near(lat: f64, lon: f64, radius_km: f64) -> Option<String> {
+        location_predicate(lat, lon, radius_km, |v| v.to_string())
+    }
+
+    #[test]
+    fn location_box() {
+        // one degree of latitude is 111.32km, as is one of longitude at the equator
+        assert_eq!(
+            near(0.0, 0.0, 111.32).unwrap(),
+            "(media.latitude BETWEEN -1 AND 1 AND (media.longitude BETWEEN -1 AND 1))"
+        );
+
+        // the longitude range widens away from the equator, i.e. doubles at 60 degrees
+        assert_eq!(
+            location_predicate(60.0, 10.0, 111.32, |v| format!("{v:.3}")).unwrap(),
+            "(media.latitude BETWEEN 59.000 AND 61.000 AND \
+             (media.longitude BETWEEN 8.000 AND 12.000))"
+        );
+    }
+
+    #[test]
+    fn location_invalid_input_matches_nothing() {
+        assert_eq!(near(f64::NAN, 0.0, 1.0).as_deref(), Some("FALSE"));
+        assert_eq!(near(0.0, f64::INFINITY, 1.0).as_deref(), Some("FALSE"));
+        assert_eq!(near(0.0, 0.0, f64::NAN).as_deref(), Some("FALSE"));
+        assert_eq!(near(0.0, 0.0, -1.0).as_deref(), Some("FALSE"));
+    }
+
+    #[test]
+    fn location_near_pole_spans_all_longitudes() {
+        assert_eq!(
+            near(90.0, 45.0, 111.32).unwrap(),
+            "(media.latitude BETWEEN 89 AND 90 AND media.longitude IS NOT NULL)"
+        );
+
+        // and so does a radius wider than half the globe
+        assert_eq!(
+            near(0.0, 0.0, 111.32 * 180.0).unwrap(),
+            "(media.latitude BETWEEN -90 AND 90 AND media.longitude IS NOT NULL)"
+        );
+    }
+
+    #[test]
+    fn location_splits_at_antimeridian() {
+        assert_eq!(
+            near(0.0, 179.5, 111.32).unwrap(),
+            "(media.latitude BETWEEN -1 AND 1 AND \
+             (media.longitude BETWEEN 178.5 AND 180 OR media.longitude BETWEEN -180 AND -179.5))"
+        );
+        assert_eq!(
+            near(0.0, -179.5, 111.32).unwrap(),
+            "(media.latitude BETWEEN -1 AND 1 AND \
+             (media.longitude BETWEEN 179.5 AND 180 OR media.longitude BETWEEN -180 AND -178.5))"
+        );
+    }
+
+    #[test]
+    fn location_binds_every_bound() {
+        let filter = SearchFilter::NearLocation {
+            lat: 0.0,
+            lon: 179.5,
+            radius_km: 111.32,
+        };
+
+        let (sql, binds) = filter.format_mariadb("c");
+
+        assert_eq!(
+            sql,
+            " AND (media.latitude BETWEEN :filter0 AND :filter1 AND \
+             (media.longitude BETWEEN :filter2 AND :filter3 OR media.longitude BETWEEN :filter4 AND :filter5))"
+        );
+        assert_eq!(
+            binds.into_iter().map(|(_, v)| v).collect::<Vec<String>>(),
+            ["-1", "1", "178.5", "180", "-180", "-179.5"]
+        );
+    }
+
+    #[test]
+    fn display_nests() {
+        let filter = SearchFilter::Not(Box::new(SearchFilter::All(vec![
+            any("cat"),
+            SearchFilter::Fulltext {
+                filter: String::from("dog"),
+            },
+        ])));
+
+        assert_eq!(
+            filter.to_string(),
+            "Not{All[SubstringAny{\"cat\"}, FullText{dog}]}"
+        );
+    }
+
+    #[test]
+    fn serde_round_trip() {
+        let filter = SearchFilter::All(vec![
+            SearchFilter::Any(vec![
+                any("cat"),
+                SearchFilter::MediaType(HashSet::from([MediaMetadata::Image])),
+            ]),
+            SearchFilter::Not(Box::new(SearchFilter::NearLocation {
+                lat: 51.5,
+                lon: -0.1,
+                radius_km: 10.0,
+            })),
+            SearchFilter::DateRange {
+                start: Some(0),
+                end: None,
+            },
+        ]);
+
+        let json = serde_json::to_string(&filter).unwrap();
+        let back: SearchFilter = serde_json::from_str(&json).unwrap();
+
+        assert_eq!(back.to_string(), filter.to_string());
+        assert_eq!(serde_json::to_string(&back).unwrap(), json);
+        assert_eq!(back.format_mariadb("c"), filter.format_mariadb("c"));
+    }
 }
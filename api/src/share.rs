@@ -0,0 +1,69 @@
+use serde::{Deserialize, Serialize};
+
+use crate::{collection::CollectionUuid, http_endpoint, media::MediaUuid, uuid_newtype};
+
+// structs and types
+
+uuid_newtype!(ShareLink);
+
+#[derive(Clone, Copy, Debug, Deserialize, Eq, PartialEq, Serialize)]
+pub enum ShareTarget {
+    Media(MediaUuid),
+    Collection(CollectionUuid),
+}
+
+// share links let anyone with the token view a single media or download a collection,
+// without an account, until the link expires or is revoked
+//
+// like api keys, only a hash of the token is stored, so the token itself is only ever
+// seen in the response to CreateShareLink
+#[derive(Clone, Debug, Deserialize, Serialize)]
+pub struct ShareLink {
+    pub share_uuid: ShareLinkUuid,
+    pub uid: String,
+    pub target: ShareTarget,
+    pub created: u64,
+    // seconds since the unix epoch
+    pub expires_at: u64,
+}
+
+// messages
+
+// create a share link for media or a collection owned by one of the user's groups
+//
+// the link itself is share_link(&token)
+http_endpoint!(CreateShareLink);
+
+#[derive(Clone, Debug, Deserialize, Serialize)]
+pub struct CreateShareLinkReq {
+    pub target: ShareTarget,
+    pub expires_at: u64,
+}
+
+#[derive(Clone, Debug, Deserialize, Serialize)]
+pub struct CreateShareLinkResp {
+    pub share_uuid: ShareLinkUuid,
+    pub token: String,
+}
+
+// list the current user's share links, including expired ones
+http_endpoint!(ListShareLinks, idempotent);
+
+#[derive(Clone, Debug, Deserialize, Serialize)]
+pub struct ListShareLinksReq {}
+
+#[derive(Clone, Debug, Deserialize, Serialize)]
+pub struct ListShareLinksResp {
+    pub links: Vec<ShareLink>,
+}
+
+// revoke one of the current user's share links
+http_endpoint!(RevokeShareLink);
+
+#[derive(Clone, Debug, Deserialize, Serialize)]
+pub struct RevokeShareLinkReq {
+    pub share_uuid: ShareLinkUuid,
+}
+
+#[derive(Clone, Debug, Deserialize, Serialize)]
+pub struct RevokeShareLinkResp {}
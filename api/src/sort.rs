@@ -1,7 +1,36 @@
 use serde::{Deserialize, Serialize};
+use utoipa::ToSchema;
 
-#[derive(Clone, Debug, Deserialize, Serialize)]
+#[derive(Clone, Debug, Deserialize, Serialize, ToSchema)]
 pub enum SortMethod {
     Date,
     Path,
 }
+
+// ordering applied by the database to the media search results
+//
+// the media_uuid tiebreaker keeps the order stable, which matters once
+// the results are paged with limit/offset
+#[derive(Clone, Copy, Debug, Default, Deserialize, PartialEq, Serialize, ToSchema)]
+pub enum SortOrder {
+    DateAsc,
+    #[default]
+    DateDesc,
+    PathAsc,
+    MtimeDesc,
+    // newest first by when the media was added to entanglement, since media uuids are v7
+    AddedDesc,
+}
+
+impl SortOrder {
+    // the same ORDER BY clause works for both mariadb and postgres
+    pub fn format_sql(&self) -> &'static str {
+        match self {
+            Self::DateAsc => " ORDER BY media.date ASC, media.media_uuid ASC",
+            Self::DateDesc => " ORDER BY media.date DESC, media.media_uuid DESC",
+            Self::PathAsc => " ORDER BY media.path ASC, media.media_uuid ASC",
+            Self::MtimeDesc => " ORDER BY media.mtime DESC, media.media_uuid DESC",
+            Self::AddedDesc => " ORDER BY media.media_uuid DESC",
+        }
+    }
+}
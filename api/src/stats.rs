@@ -0,0 +1,78 @@
+use serde::{Deserialize, Serialize};
+
+use crate::http_endpoint;
+
+// structs
+
+// totals over everything that a user can see
+//
+// media are counted under the same rules as SearchMedia, so hidden and trashed media are
+// left out, as is media that can only be seen through a hidden collection entry
+#[derive(Clone, Debug, Default, Deserialize, Serialize, PartialEq)]
+pub struct Stats {
+    pub media: u64,
+    pub images: u64,
+    pub videos: u64,
+    pub audio: u64,
+    pub bytes: u64,
+    pub collections: u64,
+    pub libraries: u64,
+}
+
+// the width of the buckets in a date histogram
+#[derive(Clone, Copy, Debug, Deserialize, Serialize, PartialEq, Eq, Hash)]
+pub enum DateGranularity {
+    Day,
+    Month,
+    Year,
+}
+
+impl DateGranularity {
+    // the buckets are prefixes of the sortable media dates, e.g. "2024-05-17", "2024-05",
+    // or "2024", so the database can group by a substring
+    pub fn prefix_len(&self) -> usize {
+        match self {
+            Self::Day => 10,
+            Self::Month => 7,
+            Self::Year => 4,
+        }
+    }
+}
+
+// the number of media whose date falls in one day, month, or year
+//
+// start and end are the (inclusive) unix timestamps of the bucket, so that a client can
+// search its media with SearchFilter::DateRange
+#[derive(Clone, Debug, Deserialize, Serialize, PartialEq)]
+pub struct DateBucket {
+    pub date: String,
+    pub start: i64,
+    pub end: i64,
+    pub count: u64,
+}
+
+// messages
+
+// get totals for the home page
+http_endpoint!(GetStats, idempotent);
+
+#[derive(Clone, Debug, Deserialize, Serialize)]
+pub struct GetStatsReq {}
+
+#[derive(Clone, Debug, Deserialize, Serialize)]
+pub struct GetStatsResp {
+    pub stats: Stats,
+}
+
+// count media by date for the timeline, oldest first
+http_endpoint!(GetDateHistogram, idempotent);
+
+#[derive(Clone, Debug, Deserialize, Serialize)]
+pub struct GetDateHistogramReq {
+    pub granularity: DateGranularity,
+}
+
+#[derive(Clone, Debug, Deserialize, Serialize)]
+pub struct GetDateHistogramResp {
+    pub buckets: Vec<DateBucket>,
+}
@@ -11,19 +11,34 @@ pub enum TaskLibrary {
     System,
 }
 
-#[derive(Clone, Debug, Deserialize, Serialize, PartialEq)]
+// the variant names are also what the database stores, so renaming one orphans its history
+#[derive(Clone, Debug, Deserialize, Serialize, PartialEq, strum::EnumString)]
 pub enum TaskType {
     ScanLibrary,
+    // as above, but rehash files whose size and mtime match their records
+    ScanLibraryFull,
     CleanLibrary,
     RunScripts,
+    // remove objects in the srv directory that don't belong to any media
     CacheScrub,
+    // as above, but only count them
+    CacheScrubDryRun,
+    // report media that share a content hash
+    FindDuplicates,
+    // as above, but also merge each group into one record
+    MergeDuplicates,
+    // fill in missing or implausible dates from exif or the filename
+    ParseDates,
+    // rehash the originals and report any that changed or vanished
+    VerifyHashes,
+    // as above, but also tag the missing media
+    VerifyHashesTagMissing,
     //VerifyMime,
     //AsyncTranscode,
     //RecalculateHashes,
-    //GuessDate
 }
 
-#[derive(Clone, Debug, Deserialize, Serialize, PartialEq)]
+#[derive(Clone, Debug, Deserialize, Serialize, PartialEq, strum::EnumString)]
 pub enum TaskStatus {
     Unknown,
     Running,
@@ -46,6 +61,43 @@ pub struct Task {
     pub warnings: Option<i64>,
     pub start: u64,
     pub end: Option<u64>,
+    // only reported by tasks that know how much work they have
+    #[serde(default)]
+    pub progress: Option<TaskProgress>,
+    // short description of what the task found or changed
+    #[serde(default)]
+    pub summary: Option<String>,
+}
+
+// periodic progress report from a running task
+//
+// updates are throttled by the task, so updated (in unix time) may lag a little
+#[derive(Clone, Copy, Debug, Deserialize, Serialize, PartialEq)]
+pub struct TaskProgress {
+    pub processed: u64,
+    pub total: u64,
+    pub updated: u64,
+}
+
+impl TaskProgress {
+    pub fn percent(&self) -> Option<f64> {
+        if self.total == 0 {
+            return None;
+        }
+
+        Some((self.processed as f64 / self.total as f64 * 100.0).min(100.0))
+    }
+
+    // rough number of seconds remaining, assuming the rate so far holds
+    pub fn eta(&self, start: u64) -> Option<u64> {
+        if self.processed == 0 {
+            return None;
+        }
+
+        let remaining = self.total.saturating_sub(self.processed);
+
+        Some(self.updated.saturating_sub(start) * remaining / self.processed)
+    }
 }
 
 // messages
@@ -74,7 +126,7 @@ pub struct StopTaskReq {
 pub struct StopTaskResp {}
 
 // show tasks
-http_endpoint!(ShowTasks);
+http_endpoint!(ShowTasks, idempotent);
 
 #[derive(Clone, Debug, Deserialize, Serialize)]
 pub struct ShowTasksReq {
@@ -0,0 +1,38 @@
+use hex::encode;
+use sha2::{Digest, Sha256};
+
+// api keys
+//
+// keys are random tokens handed out once by the http service, and only their hashes are stored.
+// since the keys have far more entropy than any password, a plain sha256 is enough to keep a
+// leaked database from turning into usable keys.
+pub const API_KEY_PREFIX: &str = "es_";
+
+pub fn hash_api_key(key: &str) -> String {
+    encode(Sha256::digest(key.as_bytes()))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn hash_is_sha256_hex() {
+        assert_eq!(
+            hash_api_key("abc"),
+            "ba7816bf8f01cfea414140de5dae2223b00361a396177a9cb410ff61f20015ad"
+        );
+        assert_eq!(
+            hash_api_key("es_0123456789abcdef"),
+            "767fd4bd49500afc22160d171a58b34046ba9dd180ce13e988fce9e6fa8c45fd"
+        );
+    }
+
+    #[test]
+    fn hash_covers_whole_key() {
+        // the stored hash is looked up by equality, so the prefix and every other byte matter
+        assert_ne!(hash_api_key("es_abc"), hash_api_key("abc"));
+        assert_ne!(hash_api_key("es_abc"), hash_api_key("es_abd"));
+        assert_ne!(hash_api_key("es_abc"), hash_api_key("ES_abc"));
+    }
+}
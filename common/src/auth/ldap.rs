@@ -8,7 +8,7 @@ use std::{
 
 use anyhow::Result;
 use async_trait::async_trait;
-use ldap3::{LdapConnAsync, LdapConnSettings, Scope, SearchEntry};
+use ldap3::{Ldap, LdapConnAsync, LdapConnSettings, LdapError, Scope, SearchEntry, ldap_escape};
 use rustls::{ClientConfig, RootCertStore};
 use rustls_native_certs::load_native_certs;
 use rustls_pki_types::{CertificateDer, PrivateKeyDer, pem::PemObject};
@@ -16,16 +16,24 @@ use serde::{Deserialize, Serialize};
 use tracing::{debug, error, info, instrument, warn};
 use url::Url;
 
-use crate::{auth::AuthzProvider, config::ESConfig};
+use crate::{
+    auth::{AuthnProvider, AuthzProvider, RetryConfig, retry},
+    config::ESConfig,
+};
 
 #[derive(Clone, Debug, Deserialize, PartialEq, Serialize)]
 pub struct LdapConfig {
     // url in normal ldap form ldaps://host:port
     pub url: Url,
     // attribute for uids, i.e. uid
-    // pub uid_attr: String,
+    //
+    // the user fields are only needed for authentication
+    pub uid_attr: Option<String>,
     // ldap search base for users
-    // pub user_base: String,
+    pub user_base: Option<String>,
+    // filter used to find users in base, i.e. (objectClass=posixAccount),
+    // which will be combined with the uid attr
+    pub user_filter: Option<String>,
     // attribute for group names, i.e. cn
     pub gid_attr: String,
     // ldap search base for groups
@@ -51,64 +59,78 @@ pub enum LdapClientAuth {
     GssApi { fqdn: String },
 }
 
-pub struct LdapAuthz {
-    config: LdapConfig,
-    settings: LdapConnSettings,
-}
+// result code for a bind with the wrong password (or a nonexistent dn)
+const LDAP_INVALID_CREDENTIALS: u32 = 49;
 
-#[async_trait]
-impl AuthzProvider for LdapAuthz {
-    #[instrument(skip_all)]
-    fn new(config: Arc<ESConfig>) -> Result<Self> {
-        info!("configuring ldap3 authz settings");
-        let config = config.ldap.clone().ok_or_else(|| anyhow::Error::msg("ldap config not found"))?;
+// timeout for each individual ldap operation, on top of the connection timeout
+const LDAP_OP_TIMEOUT: Duration = Duration::from_secs(10);
 
-        if config.url.scheme() != "ldaps" {
-            return Err(anyhow::Error::msg("ldap connector requires TLS"))
-        }
+// the tls and timeout settings are the same for authn and authz
+fn ldap_settings(config: &LdapConfig) -> Result<LdapConnSettings> {
+    if config.url.scheme() != "ldaps" {
+        return Err(anyhow::Error::msg("ldap connector requires TLS"));
+    }
+
+    let mut root_store = RootCertStore::empty();
+
+    for cert in load_native_certs().certs {
+        root_store.add(cert)?;
+    }
 
-        let mut root_store = RootCertStore::empty();
+    let tls_config = ClientConfig::builder().with_root_certificates(root_store);
 
-        for cert in load_native_certs().certs {
-            root_store.add(cert)?;
+    let tls_config = match config.clone().auth {
+        LdapClientAuth::X509 { key, cert } => {
+            let key: PrivateKeyDer = PemObject::from_pem_file(key)?;
+
+            let cert: Vec<CertificateDer> =
+                CertificateDer::pem_file_iter(cert)?.collect::<Result<Vec<_>, _>>()?;
+
+            tls_config.with_client_auth_cert(cert, key)?
         }
+        LdapClientAuth::GssApi { .. } => tls_config.with_no_client_auth(),
+    };
 
-        let tls_config = ClientConfig::builder().with_root_certificates(root_store);
+    Ok(LdapConnSettings::new()
+        .set_config(Arc::new(tls_config))
+        .set_conn_timeout(Duration::from_secs(10)))
+}
 
-        let tls_config = match config.clone().auth {
-            LdapClientAuth::X509 { key, cert } => {
-                let key: PrivateKeyDer = PemObject::from_pem_file(key)?;
+// connect to the server without binding
+async fn ldap_connect(config: &LdapConfig, settings: &LdapConnSettings) -> Result<Ldap> {
+    let (conn, ldap) = LdapConnAsync::with_settings(settings.clone(), config.url.as_str()).await?;
 
-                let cert: Vec<CertificateDer> =
-                    CertificateDer::pem_file_iter(cert)?.collect::<Result<Vec<_>, _>>()?;
+    ldap3::drive!(conn);
 
-                tls_config.with_client_auth_cert(cert, key)?
-            }
-            LdapClientAuth::GssApi { .. } => tls_config.with_no_client_auth(),
-        };
+    Ok(ldap)
+}
 
-        let settings = LdapConnSettings::new()
-            .set_config(Arc::new(tls_config))
-            .set_conn_timeout(Duration::from_secs(10));
+// connect and bind as the server itself, for searches
+async fn ldap_service_bind(config: &LdapConfig, settings: &LdapConnSettings) -> Result<Ldap> {
+    let mut ldap = ldap_connect(config, settings).await?;
 
-        Ok(LdapAuthz { config, settings })
-    }
+    ldap.with_timeout(LDAP_OP_TIMEOUT);
 
-    #[instrument(skip(self))]
-    async fn groups_for_user(&self, uid: String) -> Result<HashSet<String>> {
-        debug!("searching ldap for groups");
+    match &config.auth {
+        LdapClientAuth::X509 { .. } => ldap.sasl_external_bind().await?,
+        LdapClientAuth::GssApi { fqdn } => ldap.sasl_gssapi_bind(fqdn).await?,
+    };
 
-        let mut groups = HashSet::<String>::new();
+    Ok(ldap)
+}
 
-        let (conn, mut ldap) =
-            LdapConnAsync::with_settings(self.settings.clone(), self.config.url.as_str()).await?;
+pub struct LdapAuthz {
+    config: LdapConfig,
+    settings: LdapConnSettings,
+    retry: RetryConfig,
+}
 
-        ldap3::drive!(conn);
+// the queries are split out from the trait methods so that each one can be retried as a unit
+impl LdapAuthz {
+    async fn query_groups(&self, uid: &str) -> Result<HashSet<String>> {
+        let mut groups = HashSet::<String>::new();
 
-        match &self.config.auth {
-            LdapClientAuth::X509 { .. } => ldap.sasl_external_bind().await?,
-            LdapClientAuth::GssApi { fqdn } => ldap.sasl_gssapi_bind(fqdn).await?,
-        };
+        let mut ldap = ldap_service_bind(&self.config, &self.settings).await?;
 
         // query the ldap server for all group entries whose memeber attribute contains the uid in question
         let (res_entries, _res) = ldap
@@ -155,21 +177,10 @@ impl AuthzProvider for LdapAuthz {
         Ok(groups)
     }
 
-    #[instrument(skip(self))]
-    async fn users_in_group(&self, gid: String) -> Result<HashSet<String>> {
-        debug!("searching ldap for users");
-
+    async fn query_users(&self, gid: &str) -> Result<HashSet<String>> {
         let mut users = HashSet::<String>::new();
 
-        let (conn, mut ldap) =
-            LdapConnAsync::with_settings(self.settings.clone(), self.config.url.as_str()).await?;
-
-        ldap3::drive!(conn);
-
-        match &self.config.auth {
-            LdapClientAuth::X509 { .. } => ldap.sasl_external_bind().await?,
-            LdapClientAuth::GssApi { fqdn } => ldap.sasl_gssapi_bind(fqdn).await?,
-        };
+        let mut ldap = ldap_service_bind(&self.config, &self.settings).await?;
 
         // query the ldap server for all group entries whose member attribute contains the uid in question
         let (res_entries, _res) = ldap
@@ -204,6 +215,50 @@ impl AuthzProvider for LdapAuthz {
     }
 }
 
+#[async_trait]
+impl AuthzProvider for LdapAuthz {
+    #[instrument(skip_all)]
+    fn new(config: Arc<ESConfig>) -> Result<Self> {
+        info!("configuring ldap3 authz settings");
+        let retry = config.auth_retry.clone().unwrap_or_default();
+        let config = config.ldap.clone().ok_or_else(|| anyhow::Error::msg("ldap config not found"))?;
+
+        let settings = ldap_settings(&config)?;
+
+        Ok(LdapAuthz {
+            config,
+            settings,
+            retry,
+        })
+    }
+
+    #[instrument(skip(self))]
+    async fn groups_for_user(&self, uid: String) -> Result<HashSet<String>> {
+        debug!("searching ldap for groups");
+
+        retry(&self.retry, || self.query_groups(&uid)).await
+    }
+
+    #[instrument(skip(self))]
+    async fn users_in_group(&self, gid: String) -> Result<HashSet<String>> {
+        debug!("searching ldap for users");
+
+        retry(&self.retry, || self.query_users(&gid)).await
+    }
+
+    // no retries, since a health check should report what it sees
+    #[instrument(skip(self))]
+    async fn ping(&self) -> Result<()> {
+        debug!("binding to ldap for health check");
+
+        let mut ldap = ldap_service_bind(&self.config, &self.settings).await?;
+
+        ldap.unbind().await?;
+
+        Ok(())
+    }
+}
+
 impl Debug for LdapAuthz {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
         f.debug_struct("LdapAuthz")
@@ -217,3 +272,156 @@ impl Display for LdapAuthz {
         write!(f, "ldap group authorization via {}", self.config.url)
     }
 }
+
+// ldap authentication
+//
+// the server binds as itself to find the user's dn, and then checks the password with a
+// separate simple bind as that dn.  this needs the same ldaps connection as LdapAuthz,
+// along with the user fields in the config.
+pub struct LdapAuthn {
+    config: LdapConfig,
+    settings: LdapConnSettings,
+    retry: RetryConfig,
+    uid_attr: String,
+    user_base: String,
+}
+
+impl LdapAuthn {
+    // bind as the user, where a rejected password is a normal result but anything else
+    // (timeouts, server errors, etc) should propagate
+    async fn check_password(&self, dn: &str, password: &str) -> Result<bool> {
+        let mut ldap = ldap_connect(&self.config, &self.settings).await?;
+
+        let res = ldap
+            .with_timeout(LDAP_OP_TIMEOUT)
+            .simple_bind(dn, password)
+            .await?
+            .success();
+
+        let authenticated = match res {
+            Ok(_) => true,
+            Err(LdapError::LdapResult { result }) if result.rc == LDAP_INVALID_CREDENTIALS => false,
+            Err(err) => return Err(err.into()),
+        };
+
+        // the connection is done either way, so a failed unbind isn't interesting
+        let _ = ldap.unbind().await;
+
+        Ok(authenticated)
+    }
+
+    // find the dn for a uid, if there is exactly one
+    async fn find_user(&self, uid: &str) -> Result<Option<String>> {
+        let mut ldap = ldap_service_bind(&self.config, &self.settings).await?;
+
+        let (res_entries, _res) = ldap
+            .with_timeout(LDAP_OP_TIMEOUT)
+            .search(
+                &self.user_base,
+                Scope::Subtree,
+                &format!(
+                    "(&({}={}){})",
+                    self.uid_attr,
+                    ldap_escape(uid),
+                    self.config.user_filter.clone().unwrap_or_default()
+                ),
+                vec!["1.1"],
+            )
+            .await?
+            .success()?;
+
+        ldap.unbind().await?;
+
+        match res_entries.len() {
+            0 => Ok(None),
+            1 => Ok(res_entries
+                .into_iter()
+                .next()
+                .map(|entry| SearchEntry::construct(entry).dn)),
+            _ => {
+                warn!({ uid = uid }, "ldap user search returned multiple entries");
+                Ok(None)
+            }
+        }
+    }
+}
+
+#[async_trait]
+impl AuthnProvider for LdapAuthn {
+    #[instrument(skip_all)]
+    fn new(config: Arc<ESConfig>) -> Result<Self> {
+        info!("configuring ldap3 authn settings");
+
+        let retry = config.auth_retry.clone().unwrap_or_default();
+
+        let config = config
+            .ldap
+            .clone()
+            .ok_or_else(|| anyhow::Error::msg("ldap config not found"))?;
+
+        let uid_attr = config
+            .uid_attr
+            .clone()
+            .ok_or_else(|| anyhow::Error::msg("ldap authn requires uid_attr"))?;
+
+        let user_base = config
+            .user_base
+            .clone()
+            .ok_or_else(|| anyhow::Error::msg("ldap authn requires user_base"))?;
+
+        let settings = ldap_settings(&config)?;
+
+        Ok(LdapAuthn {
+            config,
+            settings,
+            retry,
+            uid_attr,
+            user_base,
+        })
+    }
+
+    #[instrument(skip(self, password))]
+    async fn authenticate_user(&self, uid: String, password: String) -> Result<bool> {
+        debug!("authenticating user against ldap");
+
+        // an empty password is an unauthenticated bind, which most servers accept
+        if password.is_empty() {
+            return Ok(false);
+        }
+
+        let dn = match retry(&self.retry, || self.find_user(&uid)).await? {
+            Some(dn) => dn,
+            None => return Ok(false),
+        };
+
+        let authenticated = retry(&self.retry, || self.check_password(&dn, &password)).await?;
+
+        debug!(
+            { authenticated = authenticated },
+            "finished ldap authentication"
+        );
+
+        Ok(authenticated)
+    }
+
+    #[instrument(skip(self))]
+    async fn is_valid_user(&self, uid: String) -> Result<bool> {
+        debug!("searching ldap for user");
+
+        Ok(retry(&self.retry, || self.find_user(&uid)).await?.is_some())
+    }
+}
+
+impl Debug for LdapAuthn {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("LdapAuthn")
+            .field("config", &self.config)
+            .finish()
+    }
+}
+
+impl Display for LdapAuthn {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "ldap password authentication via {}", self.config.url)
+    }
+}
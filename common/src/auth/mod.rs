@@ -1,27 +1,35 @@
 use std::{
     collections::HashSet,
     fmt::{Debug, Display},
+    future::Future,
     sync::Arc,
+    time::Duration,
 };
 
 use anyhow::Result;
 use async_trait::async_trait;
+use serde::{Deserialize, Serialize};
+use tokio::time::{sleep, timeout};
+use tracing::warn;
 
 use crate::config::ESConfig;
 
+pub mod apikey;
 pub mod cert;
 pub mod gss;
 pub mod ldap;
+pub mod oidc;
 pub mod proxy;
 pub mod tomlfile;
 
-// probably want some sort of refresh method, as well as including exponential backoff
-// on any impls that use network resources (and timeouts!)
+// probably want some sort of refresh method
+//
+// impls that use network resources should wrap their queries in retry() below, so that
+// a transient failure doesn't immediately turn into an auth failure
 //
 // see notes in server/src/auth/svc.rs about why the is_group_member() can spam messages
 //
-// TODO -- make all of these functions correctly falliable, and ensure that they have
-// some sort of retry logic built in
+// TODO -- make all of these functions correctly falliable
 #[async_trait]
 pub trait AuthzProvider: Debug + Display + Send + Sync + 'static {
     fn new(config: Arc<ESConfig>) -> Result<Self>
@@ -31,6 +39,12 @@ pub trait AuthzProvider: Debug + Display + Send + Sync + 'static {
     async fn groups_for_user(&self, uid: String) -> Result<HashSet<String>>;
 
     async fn users_in_group(&self, gid: String) -> Result<HashSet<String>>;
+
+    // a round trip to whatever backs the provider, for health checks.  providers without
+    // an external dependency have nothing to check.
+    async fn ping(&self) -> Result<()> {
+        Ok(())
+    }
 }
 
 #[async_trait]
@@ -43,3 +57,126 @@ pub trait AuthnProvider: Debug + Display + Send + Sync + 'static {
 
     async fn is_valid_user(&self, uid: String) -> Result<bool>;
 }
+
+// retry settings for the network-backed providers
+//
+// each attempt gets its own timeout, and the delay between attempts doubles up to a cap
+#[derive(Clone, Debug, Default, Deserialize, Serialize)]
+pub struct RetryConfig {
+    // total attempts, including the first, defaults to 3
+    pub max_attempts: Option<u32>,
+    // seconds before an attempt is abandoned, defaults to 15
+    pub attempt_timeout: Option<u64>,
+    // milliseconds to wait before the first retry, defaults to 250
+    pub initial_backoff: Option<u64>,
+}
+
+const RETRY_MAX_ATTEMPTS: u32 = 3;
+const RETRY_ATTEMPT_TIMEOUT: u64 = 15;
+const RETRY_INITIAL_BACKOFF: u64 = 250;
+const RETRY_MAX_BACKOFF: Duration = Duration::from_secs(10);
+
+// run op until it succeeds, returning the last error once the attempts run out
+pub async fn retry<T, F, Fut>(config: &RetryConfig, mut op: F) -> Result<T>
+where
+    F: FnMut() -> Fut,
+    Fut: Future<Output = Result<T>>,
+{
+    let max_attempts = config.max_attempts.unwrap_or(RETRY_MAX_ATTEMPTS).max(1);
+    let attempt_timeout =
+        Duration::from_secs(config.attempt_timeout.unwrap_or(RETRY_ATTEMPT_TIMEOUT));
+    let mut backoff =
+        Duration::from_millis(config.initial_backoff.unwrap_or(RETRY_INITIAL_BACKOFF));
+
+    let mut attempt = 1;
+
+    loop {
+        let err = match timeout(attempt_timeout, op()).await {
+            Ok(Ok(val)) => return Ok(val),
+            Ok(Err(err)) => err,
+            Err(_) => anyhow::Error::msg(format!("attempt timed out after {attempt_timeout:?}")),
+        };
+
+        if attempt >= max_attempts {
+            return Err(err);
+        }
+
+        warn!(
+            { attempt = attempt, max_attempts = max_attempts },
+            "retrying after error: {err}"
+        );
+
+        sleep(backoff).await;
+
+        backoff = (backoff * 2).min(RETRY_MAX_BACKOFF);
+        attempt += 1;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::atomic::{AtomicU32, Ordering};
+
+    use super::*;
+
+    // no waiting between attempts, so that the tests only take as long as the attempts
+    fn config(max_attempts: u32, attempt_timeout: u64) -> RetryConfig {
+        RetryConfig {
+            max_attempts: Some(max_attempts),
+            attempt_timeout: Some(attempt_timeout),
+            initial_backoff: Some(0),
+        }
+    }
+
+    #[tokio::test]
+    async fn retries_until_success() {
+        let calls = AtomicU32::new(0);
+
+        let result = retry(&config(5, 15), || async {
+            match calls.fetch_add(1, Ordering::SeqCst) + 1 {
+                n if n < 3 => Err(anyhow::Error::msg(format!("attempt {n} failed"))),
+                n => Ok(n),
+            }
+        })
+        .await;
+
+        assert_eq!(result.unwrap(), 3);
+        assert_eq!(calls.load(Ordering::SeqCst), 3);
+    }
+
+    #[tokio::test]
+    async fn gives_up_at_max_attempts() {
+        let calls = AtomicU32::new(0);
+
+        let result: Result<()> = retry(&config(3, 15), || async {
+            let n = calls.fetch_add(1, Ordering::SeqCst) + 1;
+            Err(anyhow::Error::msg(format!("attempt {n} failed")))
+        })
+        .await;
+
+        // the error is the one from the last attempt
+        assert_eq!(result.unwrap_err().to_string(), "attempt 3 failed");
+        assert_eq!(calls.load(Ordering::SeqCst), 3);
+    }
+
+    // with a zero timeout, an attempt that is ready on its first poll still succeeds, while
+    // one that is not is abandoned right away
+    #[tokio::test]
+    async fn abandons_hung_attempts() {
+        let calls = AtomicU32::new(0);
+
+        let result = retry(&config(3, 0), || async {
+            match calls.fetch_add(1, Ordering::SeqCst) + 1 {
+                1 => std::future::pending().await,
+                n => Ok(n),
+            }
+        })
+        .await;
+
+        assert_eq!(result.unwrap(), 2);
+
+        let result: Result<()> = retry(&config(2, 0), std::future::pending).await;
+
+        assert!(result.unwrap_err().to_string().contains("timed out"));
+    }
+}
@@ -6,11 +6,20 @@ use toml::from_str;
 use tracing::{Level, debug, instrument};
 
 use crate::{
-    auth::{gss::GssConfig, ldap::LdapConfig, proxy::ProxyHeaderConfig, tomlfile::TomlFileConfig},
+    auth::{
+        RetryConfig, gss::GssConfig, ldap::LdapConfig, oidc::OidcConfig, proxy::ProxyHeaderConfig,
+        tomlfile::TomlFileConfig,
+    },
     db::{mariadb::MariaDbConfig, postgres::PostgresConfig},
-    server::{FsConfig, HttpConfig, TaskConfig},
+    server::{FsConfig, HttpConfig, TaskConfig, WebhookConfig},
 };
 
+#[cfg(feature = "sqlite")]
+use crate::db::sqlite::SqliteConfig;
+
+#[cfg(feature = "s3")]
+use crate::storage::s3::S3Config;
+
 // entanglement configuration
 //
 // this struct contains all of the myriad configuration options used by the server and cli tools
@@ -19,17 +28,38 @@ pub struct ESConfig {
     pub authn_backend: AuthnBackend,
     pub authz_backend: AuthzBackend,
     pub db_backend: DbBackend,
+    // where thumbnails and the other streamed files are kept, which defaults to media_srvdir
+    pub storage_backend: Option<StorageBackend>,
+
+    // seconds before cached group memberships and media access are looked up again,
+    // so that changes made directly in the authz backend are eventually noticed
+    pub auth_cache_ttl: Option<u64>,
+
+    // retry policy for the network-backed auth providers (i.e. ldap)
+    pub auth_retry: Option<RetryConfig>,
+
+    // members of this group may perform irreversible operations such as purging media
+    // from the trash, and pass every access and ownership check.  if unset, nobody can.
+    pub admin_group: Option<String>,
 
     // core services
     pub fs: FsConfig,
     pub http: HttpConfig,
     pub task: TaskConfig,
 
+    // optional notifications for external automation
+    pub webhook: Option<WebhookConfig>,
+
     // backends
     pub gss: Option<GssConfig>,
     pub ldap: Option<LdapConfig>,
     pub mariadb: Option<MariaDbConfig>,
+    pub oidc: Option<OidcConfig>,
     pub postgres: Option<PostgresConfig>,
+    #[cfg(feature = "s3")]
+    pub s3: Option<S3Config>,
+    #[cfg(feature = "sqlite")]
+    pub sqlite: Option<SqliteConfig>,
     pub tomlfile: Option<TomlFileConfig>,
     pub proxyheader: Option<ProxyHeaderConfig>,
 }
@@ -44,6 +74,10 @@ pub enum AuthnBackend {
     X509Cert,
     // a toml file with usernames and passwords
     TomlFile,
+    // openid connect login against an external issuer
+    Oidc,
+    // simple bind against the ldap directory
+    Ldap,
 }
 
 #[derive(Clone, Debug, Deserialize, PartialEq, Serialize)]
@@ -60,6 +94,19 @@ pub enum AuthzBackend {
 pub enum DbBackend {
     MariaDB,
     Postgres,
+    #[cfg(feature = "sqlite")]
+    Sqlite,
+}
+
+#[derive(Clone, Debug, Default, Deserialize, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum StorageBackend {
+    // the local media_srvdir
+    #[default]
+    Fs,
+    // an s3-compatible object store
+    #[cfg(feature = "s3")]
+    S3,
 }
 
 // in order to extract the config table from a larger document, we need to specify it
@@ -1,30 +1,42 @@
 use std::{
     collections::HashSet,
     sync::Arc,
-    time::{SystemTime, UNIX_EPOCH},
+    time::{Duration, SystemTime, UNIX_EPOCH},
 };
 
 use anyhow::Result;
 use async_trait::async_trait;
-use mysql_async::{FromRowError, Pool, Row, from_row_opt, prelude::*};
+use mysql_async::{
+    Conn, FromRowError, Opts, OptsBuilder, Params, Pool, PoolConstraints, Row, Transaction, TxOpts,
+    Value, from_row_opt, prelude::*,
+};
 use serde::{Deserialize, Serialize};
-use tokio::sync::RwLock;
-use tracing::{debug, error, info, instrument};
+use tokio::{sync::RwLock, time::timeout};
+use tracing::{debug, error, info, instrument, warn};
 use url::Url;
 use uuid::Uuid;
 
 use crate::{
     config::ESConfig,
-    db::{DbBackend, MediaByCHash, MediaByPath},
+    db::{
+        ActivityRow, DbBackend, MediaByCHash, MediaByPath, ShareLinkRow, TaskRow, access_source,
+        date_bucket, hamming_distance, renamed_tags, share_target_columns, updated_tags,
+    },
 };
 use api::{
     UuidSource,
+    activity::Activity,
+    auth::{ApiKey, ApiKeyUuid},
     collection::{Collection, CollectionUpdate, CollectionUuid},
     comment::{Comment, CommentUuid},
     fold_set,
-    library::{Library, LibraryUpdate, LibraryUuid},
-    media::{Media, MediaMetadata, MediaUpdate, MediaUuid},
-    search::SearchFilter,
+    library::{Library, LibrarySummary, LibraryUpdate, LibraryUuid},
+    media::{Media, MediaAccess, MediaMetadata, MediaUpdate, MediaUuid},
+    search::{SearchFilter, mariadb_contains, mariadb_contains_pattern},
+    share::{ShareLink, ShareLinkUuid, ShareTarget},
+    sort::SortOrder,
+    stats::{DateBucket, DateGranularity, Stats},
+    task::{Task, TaskLibrary},
     unfold_set,
 };
 
@@ -36,36 +48,194 @@ use api::{
 //
 // worse, because of the overlapping indices, we have to manually RwLock the
 // tables to prevent massive collision pileup when adding media to the system.
+// see the concurrency notes below for what the locks do (and don't) cover.
 //
 // on the flip side, the queries are relatively straightforward to reason about
 // and have an easy realization in terms of the api::search::SearchFilter tools.
 
 // missing calls to-do
 //
-// delete_media
 // delete_library
 
-// sanity check to-do
+// consistency
+//
+// anything that issues more than one write does so inside a single transaction from
+// transaction(), so that a failure partway through rolls back the earlier statements.
+// dropping the transaction without calling commit() is enough to roll it back, which
+// means that the usual ? error handling does the right thing.
+
+// concurrency
+//
+// the write locks serialize the writers against each other.  they protect three things:
+//  * ADD_MEDIA is a check-then-insert on (library_uuid, path), which two concurrent scans
+//    would otherwise race (or deadlock on the gap locks)
+//  * the read-modify-write of the folded tags in update_media(), batch_update_media(), and
+//    rename_tag(), which would otherwise lose concurrent edits
+//  * the multi-table deletes in purge_media() and delete_collection(), which take the
+//    locks of every table that they touch, and so can't interleave with an insert of a
+//    comment or collection entry that refers to the rows being removed
 //
-// calls that should be transactions for consistency:
-//  * add_media
-//  * delete_collection
+// a writer takes the locks in the order that they are declared in TableLocks, and only the
+// locks for the tables that it writes.
 //
-// update_* can fail partway through
+// readers take the read locks of every table that they query, in the same order.  this
+// keeps the reads that issue more than one statement, like the total and the page in
+// search_media(), in agreement with each other.  it does mean that a search waits behind
+// the media write lock for the whole of a scan batch.
+//
+// none of this has been measured under load.  taking fewer write locks should let scans
+// and edits to unrelated tables overlap, but there is no concurrent writer/reader test
+// against a live server to show how much it helps (or that it doesn't deadlock).
 #[derive(Clone, Debug, Deserialize, Serialize)]
 pub struct MariaDbConfig {
     pub url: Url,
+    // compute phash distances in the server if BIG_HAM() is not installed
+    pub phash_fallback: Option<bool>,
+    // bounds on the number of pooled connections, which default to any pool_min and
+    // pool_max in the url and otherwise to the mysql_async defaults (10 and 100)
+    pub pool_min: Option<usize>,
+    pub pool_max: Option<usize>,
+    // seconds that a query waits for a free connection before failing, defaults to 30
+    pub acquire_timeout: Option<u64>,
+    // prepared statements kept per connection, which defaults to the larger of any
+    // stmt_cache_size in the url and 256
+    pub stmt_cache_size: Option<usize>,
+}
+
+const ACQUIRE_TIMEOUT: u64 = 30;
+
+// every query with parameters is prepared, and mysql_async keeps the statements in a per
+// connection cache keyed by the query text.  its default of 32 is smaller than the number
+// of distinct queries in this file, so the hot ones were evicted (and re-prepared) by
+// whatever else had run on that connection.  the dynamic searches only add an entry for
+// each shape of filter, since the filter values, limits, and offsets are all bound.
+const STMT_CACHE_SIZE: usize = 256;
+
+// similar_media() needs the BIG_HAM() user-defined function from libbig_ham
+const BIG_HAM_DDL: &str = "CREATE FUNCTION BIG_HAM RETURNS INTEGER SONAME 'libbig_ham.so'";
+
+// ER_SP_DOES_NOT_EXIST, which mariadb returns for "FUNCTION ... does not exist"
+const ER_SP_DOES_NOT_EXIST: u16 = 1305;
+
+fn is_missing_function(err: &mysql_async::Error) -> bool {
+    matches!(err, mysql_async::Error::Server(err) if err.code == ER_SP_DOES_NOT_EXIST)
+}
+
+// a gid matches only if it is a whole entry in the folded set.  a bare INSTR() would let
+// "admin" match a user in "administrators", so both sides are wrapped in separators first.
+const GID_CHECK: &str = "INSTR(CONCAT('|', :gid, '|'), CONCAT('|', gid, '|')) > 0";
+
+// merge the named parameters generated by SearchFilter::format_mariadb() (or any other list
+// of generated names) into the rest
+fn with_filter<V: Into<Value>>(params: Params, filter: Vec<(String, V)>) -> Params {
+    match params {
+        Params::Named(mut map) => {
+            map.extend(
+                filter
+                    .into_iter()
+                    .map(|(name, value)| (name.into_bytes(), value.into())),
+            );
+            Params::Named(map)
+        }
+        params => params,
+    }
+}
+
+// inserts a single media row, returning nothing if the path is already in the library
+const ADD_MEDIA: &str = r"
+    INSERT INTO media (media_uuid, library_uuid, path, size, chash, phash, mtime, hidden, date, note, tags, media_type, latitude, longitude, width, height, camera, orientation, capture_time, duration_secs, codec)
+    SELECT
+        UUID_v7(),
+        :library_uuid,
+        :path,
+        :size,
+        :chash,
+        :phash,
+        :mtime,
+        :hidden,
+        :date,
+        :note,
+        :tags,
+        :media_type,
+        :latitude,
+        :longitude,
+        :width,
+        :height,
+        :camera,
+        :orientation,
+        :capture_time,
+        :duration_secs,
+        :codec
+    FROM
+        DUAL
+    WHERE NOT EXISTS(
+        SELECT 1
+        FROM media
+        WHERE
+            library_uuid = :library_uuid
+            AND path = :path
+    )
+    RETURNING media_uuid";
+
+fn media_params(media: Media) -> Result<Params> {
+    Ok(params! {
+        "library_uuid" => media.library_uuid.value(),
+        "path" => media.path,
+        "size" => media.size,
+        "chash" => media.chash,
+        "phash" => media.phash,
+        "mtime" => media.mtime,
+        "hidden" => media.hidden,
+        "date" => media.date,
+        "note" => media.note,
+        "tags" => fold_set(media.tags)?,
+        "media_type" => match media.metadata {
+            MediaMetadata::Image => "Image",
+            MediaMetadata::Video => "Video",
+            MediaMetadata::VideoSlice => "VideoSlice",
+            MediaMetadata::Audio => "Audio"
+        },
+        "latitude" => media.latitude,
+        "longitude" => media.longitude,
+        "width" => media.width,
+        "height" => media.height,
+        "camera" => media.camera,
+        "orientation" => media.orientation,
+        "capture_time" => media.capture_time,
+        "duration_secs" => media.duration_secs,
+        "codec" => media.codec,
+    })
+}
+
+// expects the columns in the order that ShareLinkRow lists them
+fn share_link_row(row: Row) -> Result<ShareLinkRow> {
+    let (share_uuid, uid, media_uuid, collection_uuid, created, expires_at) =
+        from_row_opt::<(Uuid, String, Option<Uuid>, Option<Uuid>, u64, u64)>(row)?;
+
+    Ok(ShareLinkRow {
+        share_uuid,
+        uid,
+        media_uuid,
+        collection_uuid,
+        created,
+        expires_at,
+    })
 }
 
 pub struct MariaDBBackend {
     pool: Pool,
+    acquire_timeout: Duration,
     locks: TableLocks,
+    big_ham: bool,
 }
 
 #[derive(Default)]
 struct TableLocks {
     media: RwLock<()>,
     comment: RwLock<()>,
+    api_key: RwLock<()>,
+    share_link: RwLock<()>,
+    task: RwLock<()>,
     library: RwLock<()>,
     contents: RwLock<()>,
     collection: RwLock<()>,
@@ -73,6 +243,123 @@ struct TableLocks {
 
 impl UuidSource for MariaDBBackend {}
 
+impl MariaDBBackend {
+    // every query gets its connection from here, so that an exhausted pool shows up as an
+    // error instead of unexplained latency
+    async fn conn(&self) -> Result<Conn> {
+        timeout(self.acquire_timeout, self.pool.get_conn())
+            .await
+            .map_err(|_| self.exhausted())?
+            .map_err(anyhow::Error::from)
+    }
+
+    // the table locks still need to be held by the caller, since they are what keep the
+    // overlapping indices from piling up
+    async fn transaction(&self) -> Result<Transaction<'static>> {
+        timeout(
+            self.acquire_timeout,
+            self.pool.start_transaction(TxOpts::default()),
+        )
+        .await
+        .map_err(|_| self.exhausted())?
+        .map_err(anyhow::Error::from)
+    }
+
+    fn exhausted(&self) -> anyhow::Error {
+        error!("timed out waiting for a mariadb connection");
+
+        anyhow::Error::msg(format!(
+            "no mariadb connection became available within {}s; the pool may be exhausted (see pool_max)",
+            self.acquire_timeout.as_secs()
+        ))
+    }
+
+    // similar_phash() without BIG_HAM(), which fetches every visible phash and compares them
+    // in the server.  this is much slower on large libraries, so it is only used when the
+    // function is unavailable and phash_fallback is set
+    //
+    // the caller is responsible for holding the table locks
+    async fn similar_phash_fallback(
+        &self,
+        gid: HashSet<String>,
+        target: String,
+        distance: i64,
+    ) -> Result<Vec<(MediaUuid, i64)>> {
+        debug!("computing phash distances in the server");
+
+        if target.is_empty() {
+            return Ok(Vec::new());
+        }
+
+        let query = format!(
+            r"
+            SELECT
+                media.media_uuid, media.phash
+            FROM
+                (
+                    SELECT
+                        media_uuid
+                    FROM
+                        (
+                            SELECT
+                                collection_uuid
+                            FROM
+                                collections
+                            WHERE
+                                {GID_CHECK}
+                        ) AS t1
+                        INNER JOIN collection_contents ON t1.collection_uuid = collection_contents.collection_uuid
+                    UNION
+                    SELECT
+                        media_uuid
+                    FROM
+                        (
+                            SELECT
+                                library_uuid
+                            FROM
+                                libraries
+                            WHERE
+                                {GID_CHECK}
+                        ) AS t2
+                        INNER JOIN media ON t2.library_uuid = media.library_uuid
+                ) AS t3
+                INNER JOIN media ON t3.media_uuid = media.media_uuid
+            WHERE
+                media.hidden = FALSE
+                AND media.deleted_at IS NULL
+                AND media.phash != ''"
+        );
+
+        let result = query
+            .with(params! {
+                "gid" => fold_set(gid)?,
+            })
+            .run(self.conn().await?)
+            .await?
+            .collect::<Row>()
+            .await?;
+
+        let mut data = Vec::new();
+
+        for row in result {
+            let (uuid, phash) = from_row_opt::<(Uuid, String)>(row)?;
+
+            match hamming_distance(&target, &phash) {
+                Some(d) if d < distance => data.push((MediaUuid::from_value(self, uuid), d)),
+                Some(_) => (),
+                None => error!({ media_uuid = %uuid }, "invalid phash"),
+            }
+        }
+
+        // to match the ORDER BY in similar_media()
+        data.sort_by_key(|(media_uuid, d)| (*d, *media_uuid));
+
+        debug!({ count = data.len() }, "found similar media");
+
+        Ok(data)
+    }
+}
+
 #[async_trait]
 impl DbBackend for MariaDBBackend {
     async fn new(config: Arc<ESConfig>) -> Result<Self> {
@@ -87,16 +374,92 @@ impl DbBackend for MariaDBBackend {
             return Err(anyhow::Error::msg("invalid mariadb url"))
         }
 
+        let opts = Opts::from_url(config.url.as_str())?;
+        let url_constraints = opts.pool_opts().constraints();
+
+        let constraints = PoolConstraints::new(
+            config.pool_min.unwrap_or(url_constraints.min()),
+            config.pool_max.unwrap_or(url_constraints.max()),
+        )
+        .ok_or_else(|| {
+            anyhow::Error::msg("invalid mariadb pool bounds: pool_min exceeds pool_max")
+        })?;
+
+        let pool_opts = opts.pool_opts().clone().with_constraints(constraints);
+
+        let stmt_cache_size = config
+            .stmt_cache_size
+            .unwrap_or(STMT_CACHE_SIZE.max(opts.stmt_cache_size()));
+
+        let pool = Pool::new(
+            OptsBuilder::from_opts(opts)
+                .pool_opts(pool_opts)
+                .stmt_cache_size(stmt_cache_size),
+        );
+
+        let acquire_timeout =
+            Duration::from_secs(config.acquire_timeout.unwrap_or(ACQUIRE_TIMEOUT));
+
+        // BIG_HAM() is installed out of band, so check for it here rather than letting
+        // similar_media() fail at runtime.  anything other than the missing function (say, an
+        // unreachable server or bad credentials) is a real error and shouldn't be mistaken for it
+        let big_ham = match "SELECT BIG_HAM('00', '00')"
+            .ignore(pool.get_conn().await?)
+            .await
+        {
+            Ok(()) => true,
+            Err(err) if is_missing_function(&err) => false,
+            Err(err) => return Err(err.into()),
+        };
+
+        if !big_ham {
+            if config.phash_fallback.unwrap_or(false) {
+                warn!(
+                    "BIG_HAM() not found in mariadb, similar media search will compute distances in the server"
+                );
+            } else {
+                error!("BIG_HAM() not found in mariadb");
+                return Err(anyhow::Error::msg(format!(
+                    "BIG_HAM() is not installed in mariadb; either run \"{BIG_HAM_DDL}\" or set phash_fallback = true to compute phash distances in the server"
+                )));
+            }
+        }
+
         Ok(Self {
-            pool: Pool::new(config.url.as_str()),
+            pool,
+            acquire_timeout,
             locks: TableLocks::default(),
+            big_ham,
         })
     }
 
+    #[instrument(skip(self))]
+    async fn ping(&self) -> Result<()> {
+        "SELECT 1".ignore(self.conn().await?).await?;
+
+        Ok(())
+    }
+
     #[instrument(skip(self))]
     async fn media_access_groups(&self, media_uuid: MediaUuid) -> Result<HashSet<String>> {
         debug!("finding media access groups");
 
+        let data = self
+            .media_access(media_uuid)
+            .await?
+            .into_iter()
+            .map(|v| v.gid)
+            .collect::<HashSet<String>>();
+
+        debug!({ groups = ?data }, "found groups");
+
+        Ok(data)
+    }
+
+    #[instrument(skip(self))]
+    async fn media_access(&self, media_uuid: MediaUuid) -> Result<Vec<MediaAccess>> {
+        debug!("finding media access");
+
         let _mr = self.locks.media.read().await;
         let _lr = self.locks.library.read().await;
         let _xr = self.locks.contents.read().await;
@@ -107,16 +470,20 @@ impl DbBackend for MariaDBBackend {
         //  * the library that contains that media
         let result = r"
             SELECT
-                gid
+                collections.gid,
+                collections.collection_uuid,
+                NULL
             FROM
                 collections
             INNER JOIN collection_contents ON collections.collection_uuid = collection_contents.collection_uuid
             INNER JOIN media ON collection_contents.media_uuid = media.media_uuid
             WHERE
-                media.media_uuid = :media_uuid AND media.hidden = FALSE
-            UNION
+                media.media_uuid = :media_uuid AND media.hidden = FALSE AND media.deleted_at IS NULL
+            UNION ALL
             SELECT
-                gid
+                libraries.gid,
+                NULL,
+                libraries.library_uuid
             FROM
                 libraries
             INNER JOIN media ON libraries.library_uuid = media.library_uuid
@@ -125,17 +492,28 @@ impl DbBackend for MariaDBBackend {
             .with(params! {
                 "media_uuid" => media_uuid.value(),
             })
-            .run(self.pool.get_conn().await?)
+            .run(self.conn().await?)
             .await?
             .collect::<Row>()
             .await?;
 
         let data = result
             .into_iter()
-            .map(from_row_opt::<String>)
-            .collect::<Result<HashSet<_>, FromRowError>>()?;
+            .map(|row| {
+                let (gid, collection_uuid, library_uuid) =
+                    from_row_opt::<(String, Option<Uuid>, Option<Uuid>)>(row)?;
+
+                Ok(MediaAccess {
+                    gid,
+                    source: access_source(
+                        collection_uuid.map(|v| CollectionUuid::from_value(self, v)),
+                        library_uuid.map(|v| LibraryUuid::from_value(self, v)),
+                    )?,
+                })
+            })
+            .collect::<Result<Vec<MediaAccess>, anyhow::Error>>()?;
 
-        debug!({ groups = ?data }, "found groups");
+        debug!({ count = data.len() }, "found media access");
 
         Ok(data)
     }
@@ -146,70 +524,65 @@ impl DbBackend for MariaDBBackend {
         debug!({ media_path = media.path }, "adding media");
 
         let _mw = self.locks.media.write().await;
-        let _lw = self.locks.library.write().await;
 
-        let query = r"
-            INSERT INTO media (media_uuid, library_uuid, path, size, chash, phash, mtime, hidden, date, note, tags, media_type)
-            SELECT
-                UUID_v7(),
-                :library_uuid,
-                :path,
-                :size,
-                :chash,
-                :phash,
-                :mtime,
-                :hidden,
-                :date,
-                :note,
-                :tags,
-                :media_type
-            FROM
-                DUAL
-            WHERE NOT EXISTS(
-                SELECT 1
-                FROM media
-                WHERE
-                    library_uuid = :library_uuid
-                    AND path = :path
-            )
-            RETURNING media_uuid";
+        let media_path = media.path.clone();
 
-        let mut result = query
-            .with(params! {
-                "library_uuid" => media.library_uuid.value(),
-                "path" => media.path.clone(),
-                "size" => media.size,
-                "chash" => media.chash,
-                "phash" => media.phash,
-                "mtime" => media.mtime,
-                "hidden" => media.hidden,
-                "date" => media.date,
-                "note" => media.note,
-                "tags" => fold_set(media.tags)?,
-                "media_type" => match media.metadata {
-                    MediaMetadata::Image => "Image",
-                    MediaMetadata::Video => "Video",
-                    MediaMetadata::VideoSlice => "VideoSlice",
-                    MediaMetadata::Audio => "Audio"
-                },
-            })
-            .run(self.pool.get_conn().await?)
+        let mut result = ADD_MEDIA
+            .with(media_params(media)?)
+            .run(self.conn().await?)
             .await?
             .collect::<Row>()
             .await?;
 
         let row = result.pop().ok_or_else(|| {
-            error!({ media_path = media.path }, "failed to add media");
+            error!({ media_path = media_path }, "failed to add media");
             anyhow::Error::msg("failed to add media")
         })?;
 
         let media_uuid = from_row_opt::<Uuid>(row)?;
 
-        debug!({ media_path = media.path, %media_uuid }, "added media");
+        debug!({ media_path = media_path, %media_uuid }, "added media");
 
         Ok(MediaUuid::from_value(self, media_uuid))
     }
 
+    #[instrument(skip_all)]
+    async fn add_media_batch(&self, media: Vec<Media>) -> Result<Vec<Option<MediaUuid>>> {
+        debug!({ count = media.len() }, "adding media batch");
+
+        let _mw = self.locks.media.write().await;
+
+        let mut tx = self.transaction().await?;
+
+        // RETURNING can't say which row of a multi-row insert was skipped, so the rows go
+        // in one at a time, which is still only one commit for the whole batch
+        let mut data = Vec::with_capacity(media.len());
+
+        for media in media {
+            let row = ADD_MEDIA
+                .with(media_params(media)?)
+                .run(&mut tx)
+                .await?
+                .collect::<Row>()
+                .await?
+                .pop();
+
+            data.push(row.map(from_row_opt::<Uuid>).transpose()?);
+        }
+
+        tx.commit().await?;
+
+        debug!(
+            { added = data.iter().flatten().count() },
+            "added media batch"
+        );
+
+        Ok(data
+            .into_iter()
+            .map(|media_uuid| media_uuid.map(|v| MediaUuid::from_value(self, v)))
+            .collect())
+    }
+
     #[instrument(skip(self))]
     async fn get_media(
         &self,
@@ -221,12 +594,15 @@ impl DbBackend for MariaDBBackend {
         let _yr = self.locks.comment.read().await;
         let _xr = self.locks.contents.read().await;
 
+        // the four queries share a connection, and thus its prepared statements
+        let mut conn = self.conn().await?;
+
         let mut media_result = r"
             SELECT library_uuid, path, size, chash, phash, mtime, hidden, date, note, tags, media_type FROM media WHERE media_uuid = :media_uuid"
         .with(params! {
             "media_uuid" => media_uuid.value(),
         })
-        .run(self.pool.get_conn().await?)
+        .run(&mut conn)
         .await?
         .collect::<Row>()
         .await?;
@@ -248,12 +624,40 @@ impl DbBackend for MariaDBBackend {
             None => return Ok(None),
         };
 
+        // from_row_opt() only handles tuples up to twelve columns, so the location and the
+        // other details are fetched separately
+        let details_data = r"
+            SELECT latitude, longitude, width, height, camera, orientation, capture_time, duration_secs, codec FROM media WHERE media_uuid = :media_uuid"
+            .with(params! {
+                "media_uuid" => media_uuid.value(),
+            })
+            .run(&mut conn)
+            .await?
+            .collect::<Row>()
+            .await?
+            .pop()
+            .map(
+                from_row_opt::<(
+                    Option<f64>,
+                    Option<f64>,
+                    Option<u32>,
+                    Option<u32>,
+                    Option<String>,
+                    Option<u32>,
+                    Option<String>,
+                    Option<f64>,
+                    Option<String>,
+                )>,
+            )
+            .transpose()?
+            .unwrap_or_default();
+
         let collection_result = r"
             SELECT collection_uuid FROM collection_contents WHERE media_uuid = :media_uuid"
             .with(params! {
                 "media_uuid" => media_uuid.value(),
             })
-            .run(self.pool.get_conn().await?)
+            .run(&mut conn)
             .await?
             .collect::<Row>()
             .await?;
@@ -272,7 +676,7 @@ impl DbBackend for MariaDBBackend {
             .with(params! {
                 "media_uuid" => media_uuid.value(),
             })
-            .run(self.pool.get_conn().await?)
+            .run(&mut conn)
             .await?
             .collect::<Row>()
             .await?;
@@ -312,6 +716,15 @@ impl DbBackend for MariaDBBackend {
                         )));
                     }
                 },
+                latitude: details_data.0,
+                longitude: details_data.1,
+                width: details_data.2,
+                height: details_data.3,
+                camera: details_data.4,
+                orientation: details_data.5,
+                capture_time: details_data.6,
+                duration_secs: details_data.7,
+                codec: details_data.8,
             },
             collection_data,
             comment_data,
@@ -326,7 +739,7 @@ impl DbBackend for MariaDBBackend {
 
         let result = r"
             SELECT media_uuid FROM media"
-            .run(self.pool.get_conn().await?)
+            .run(self.conn().await?)
             .await?
             .collect::<Row>()
             .await?;
@@ -352,11 +765,11 @@ impl DbBackend for MariaDBBackend {
         let _mr = self.locks.media.read().await;
 
         let mut result = r"
-            SELECT media_uuid, chash, mtime FROM media WHERE path = :path"
+            SELECT media_uuid, chash, mtime, size FROM media WHERE path = :path"
             .with(params! {
                 "path" => path,
             })
-            .run(self.pool.get_conn().await?)
+            .run(self.conn().await?)
             .await?
             .collect::<Row>()
             .await?;
@@ -366,7 +779,7 @@ impl DbBackend for MariaDBBackend {
             None => return Ok(None),
         };
 
-        let data = from_row_opt::<(Uuid, String, u64)>(row)?;
+        let data = from_row_opt::<(Uuid, String, u64, u64)>(row)?;
 
         debug!({ media_uuid = %data.0 }, "found media");
 
@@ -374,6 +787,7 @@ impl DbBackend for MariaDBBackend {
             media_uuid: MediaUuid::from_value(self, data.0),
             hash: data.1,
             mtime: data.2,
+            size: data.3,
         }))
     }
 
@@ -393,7 +807,7 @@ impl DbBackend for MariaDBBackend {
                 "library_uuid" => library_uuid.value(),
                 "chash" => chash,
             })
-            .run(self.pool.get_conn().await?)
+            .run(self.conn().await?)
             .await?
             .collect::<Row>()
             .await?;
@@ -420,6 +834,26 @@ impl DbBackend for MariaDBBackend {
 
         let _mw = self.locks.media.write().await;
 
+        let mut tx = self.transaction().await?;
+
+        // tag edits are read and written back inside the transaction while holding the media
+        // write lock, so that no other update can land in between
+        let tags = if update.edits_tags() {
+            let folded: Option<String> = r"
+                SELECT tags FROM media WHERE media_uuid = :media_uuid FOR UPDATE"
+                .with(params! {
+                    "media_uuid" => media_uuid.value(),
+                })
+                .first(&mut tx)
+                .await?;
+
+            folded
+                .map(|folded| updated_tags(&folded, &update))
+                .transpose()?
+        } else {
+            update.tags.clone().map(fold_set).transpose()?
+        };
+
         if let Some(val) = update.hidden {
             r"
             UPDATE media SET hidden = :hidden WHERE media_uuid = :media_uuid"
@@ -427,7 +861,7 @@ impl DbBackend for MariaDBBackend {
                     "hidden" => val,
                     "media_uuid" => media_uuid.value(),
                 })
-                .run(self.pool.get_conn().await?)
+                .run(&mut tx)
                 .await?;
         }
 
@@ -438,7 +872,7 @@ impl DbBackend for MariaDBBackend {
                     "date" => val.clone(),
                     "media_uuid" => media_uuid.value(),
                 })
-                .run(self.pool.get_conn().await?)
+                .run(&mut tx)
                 .await?;
         }
 
@@ -449,26 +883,141 @@ impl DbBackend for MariaDBBackend {
                     "note" => val.clone(),
                     "media_uuid" => media_uuid.value(),
                 })
-                .run(self.pool.get_conn().await?)
+                .run(&mut tx)
                 .await?;
         }
 
-        if let Some(val) = update.tags {
+        if let Some(val) = tags {
             r"
             UPDATE media SET tags = :tags WHERE media_uuid = :media_uuid"
                 .with(params! {
-                    "tags" => fold_set(val.clone())?,
+                    "tags" => val,
                     "media_uuid" => media_uuid.value(),
                 })
-                .run(self.pool.get_conn().await?)
+                .run(&mut tx)
                 .await?;
         }
 
+        tx.commit().await?;
+
         debug!("updated media details");
 
         Ok(())
     }
 
+    #[instrument(skip(self, update))]
+    async fn batch_update_media(
+        &self,
+        media_uuids: Vec<MediaUuid>,
+        update: MediaUpdate,
+    ) -> Result<Vec<MediaUuid>> {
+        debug!(
+            { count = media_uuids.len() },
+            "batch updating media details"
+        );
+
+        if media_uuids.is_empty() {
+            return Ok(Vec::new());
+        }
+
+        let _mw = self.locks.media.write().await;
+
+        // named parameters can't be lists, so each uuid gets its own placeholder
+        let names = (0..media_uuids.len())
+            .map(|i| format!("media_uuid{i}"))
+            .collect::<Vec<String>>();
+
+        let in_sql = names
+            .iter()
+            .map(|name| format!(":{name}"))
+            .collect::<Vec<String>>()
+            .join(", ");
+
+        let uuid_params = || {
+            names
+                .iter()
+                .zip(media_uuids.iter())
+                .map(|(name, media_uuid)| (name.clone(), media_uuid.value()))
+                .collect::<Vec<(String, Uuid)>>()
+        };
+
+        let mut tx = self.transaction().await?;
+
+        // UPDATE has no RETURNING in mariadb, so the matching rows are found first while
+        // the write lock keeps them from changing underneath us
+        let query = format!(
+            r"
+            SELECT media_uuid, tags FROM media WHERE media_uuid IN ({in_sql}) FOR UPDATE"
+        );
+
+        let result = query
+            .with(with_filter(
+                Params::Named(Default::default()),
+                uuid_params(),
+            ))
+            .run(&mut tx)
+            .await?
+            .collect::<Row>()
+            .await?;
+
+        let rows = result
+            .into_iter()
+            .map(from_row_opt::<(Uuid, String)>)
+            .collect::<Result<Vec<(Uuid, String)>, FromRowError>>()?;
+
+        // tag edits depend on each row's current tags, so they are written back one at a time
+        if update.edits_tags() {
+            for (media_uuid, folded) in rows.iter() {
+                r"
+                UPDATE media SET tags = :tags WHERE media_uuid = :media_uuid"
+                    .with(params! {
+                        "tags" => updated_tags(folded, &update)?,
+                        "media_uuid" => *media_uuid,
+                    })
+                    .run(&mut tx)
+                    .await?;
+            }
+        }
+
+        let data = rows
+            .into_iter()
+            .map(|(media_uuid, _)| MediaUuid::from_value(self, media_uuid))
+            .collect::<Vec<MediaUuid>>();
+
+        let tags = if update.edits_tags() {
+            None
+        } else {
+            update.tags.map(fold_set).transpose()?
+        };
+
+        format!(
+            r"
+            UPDATE media SET
+                hidden = COALESCE(:hidden, hidden),
+                date = COALESCE(:date, date),
+                note = COALESCE(:note, note),
+                tags = COALESCE(:tags, tags)
+            WHERE media_uuid IN ({in_sql})"
+        )
+        .with(with_filter(
+            params! {
+                "hidden" => update.hidden,
+                "date" => update.date,
+                "note" => update.note,
+                "tags" => tags,
+            },
+            uuid_params(),
+        ))
+        .run(&mut tx)
+        .await?;
+
+        tx.commit().await?;
+
+        debug!({ count = data.len() }, "batch updated media details");
+
+        Ok(data)
+    }
+
     #[instrument(skip(self))]
     async fn replace_media_path(
         &self,
@@ -476,20 +1025,22 @@ impl DbBackend for MariaDBBackend {
         path: String,
         hash: String,
         mtime: u64,
+        size: u64,
     ) -> Result<()> {
         debug!("replacing media path");
 
         let _mw = self.locks.media.write().await;
 
         r"
-        UPDATE media SET path = :path, chash = :hash, mtime = :mtime WHERE media_uuid = :media_uuid"
+        UPDATE media SET path = :path, chash = :hash, mtime = :mtime, size = :size WHERE media_uuid = :media_uuid"
             .with(params! {
                 "media_uuid" => media_uuid.value(),
                 "path" => path,
                 "hash" => hash,
                 "mtime" => mtime,
+                "size" => size,
             })
-            .run(self.pool.get_conn().await?)
+            .run(self.conn().await?)
             .await?;
 
         debug!("replaced media path");
@@ -497,12 +1048,41 @@ impl DbBackend for MariaDBBackend {
         Ok(())
     }
 
+    #[instrument(skip(self))]
+    async fn move_media(
+        &self,
+        media_uuid: MediaUuid,
+        library_uuid: LibraryUuid,
+        path: Option<String>,
+    ) -> Result<()> {
+        debug!("moving media to library");
+
+        let _mw = self.locks.media.write().await;
+
+        r"
+        UPDATE media SET library_uuid = :library_uuid, path = COALESCE(:path, path) WHERE media_uuid = :media_uuid"
+            .with(params! {
+                "media_uuid" => media_uuid.value(),
+                "library_uuid" => library_uuid.value(),
+                "path" => path,
+            })
+            .run(self.conn().await?)
+            .await?;
+
+        debug!("moved media to library");
+
+        Ok(())
+    }
+
     #[instrument(skip(self))]
     async fn search_media(
         &self,
         gid: HashSet<String>,
         filter: SearchFilter,
-    ) -> Result<Vec<MediaUuid>> {
+        sort: SortOrder,
+        limit: Option<u64>,
+        offset: Option<u64>,
+    ) -> Result<(Vec<MediaUuid>, u64)> {
         debug!("searching for media");
 
         let _mr = self.locks.media.read().await;
@@ -516,7 +1096,8 @@ impl DbBackend for MariaDBBackend {
         //  * is in a library owned by a group containing the uid
         //  * if the media is not hidden, is in an collection owned
         //    by a group containing the uid
-        let mut query = r"
+        let mut query = format!(
+            r"
             SELECT
                 media.media_uuid
             FROM
@@ -530,7 +1111,7 @@ impl DbBackend for MariaDBBackend {
                             FROM
                                 collections
                             WHERE
-                                INSTR(:gid, gid) > 0
+                                {GID_CHECK}
                         ) AS t1
                         INNER JOIN collection_contents ON t1.collection_uuid = collection_contents.collection_uuid
                     UNION
@@ -543,22 +1124,55 @@ impl DbBackend for MariaDBBackend {
                             FROM
                                 libraries
                             WHERE
-                                INSTR(:gid, gid) > 0
+                                {GID_CHECK}
                         ) AS t2
                         INNER JOIN media ON t2.library_uuid = media.library_uuid
                 ) AS t3
                 INNER JOIN media ON t3.media_uuid = media.media_uuid
             WHERE
-                media.hidden = FALSE".to_owned();
+                media.hidden = FALSE
+                AND media.deleted_at IS NULL"
+        );
 
         query.push_str(&sql);
 
-        let result = query
-            .with(params! {
+        let params = with_filter(
+            params! {
                 "gid" => fold_set(gid)?,
-                "filter" => filter,
-            })
-            .run(self.pool.get_conn().await?)
+            },
+            filter,
+        );
+
+        // the total ignores the paging, so it has to be its own query
+        let total: Option<u64> = format!("SELECT COUNT(*) FROM ({query}) AS t4")
+            .with(params.clone())
+            .first(self.conn().await?)
+            .await?;
+
+        let total = total.unwrap_or(0);
+
+        // mariadb has no OFFSET without a LIMIT, so we use the documented maximum
+        // instead.  the values are bound rather than formatted so that every page of a
+        // search reuses the same prepared statement.
+        query.push_str(sort.format_sql());
+
+        let params = if limit.is_some() || offset.is_some() {
+            query.push_str(" LIMIT :limit OFFSET :offset");
+
+            with_filter(
+                params,
+                vec![
+                    (String::from("limit"), limit.unwrap_or(u64::MAX)),
+                    (String::from("offset"), offset.unwrap_or(0)),
+                ],
+            )
+        } else {
+            params
+        };
+
+        let result = query
+            .with(params)
+            .run(self.conn().await?)
             .await?
             .collect::<Row>()
             .await?;
@@ -572,9 +1186,9 @@ impl DbBackend for MariaDBBackend {
             })
             .collect::<Result<Vec<MediaUuid>, FromRowError>>()?;
 
-        debug!({ count = data.len() }, "found media");
+        debug!({ count = data.len(), total = total }, "found media");
 
-        Ok(data)
+        Ok((data, total))
     }
 
     #[instrument(skip(self))]
@@ -583,7 +1197,7 @@ impl DbBackend for MariaDBBackend {
         gid: HashSet<String>,
         media_uuid: MediaUuid,
         distance: i64,
-    ) -> Result<Vec<MediaUuid>> {
+    ) -> Result<Vec<(MediaUuid, i64)>> {
         // for a given uid and filter, find all media that match either:
         //  * is in a library owned by a group containing the uid
         //  * if the media is not hidden, is in an collection owned
@@ -595,51 +1209,693 @@ impl DbBackend for MariaDBBackend {
         let _xr = self.locks.contents.read().await;
         let _cr = self.locks.collection.read().await;
 
-        let result = r"
+        if !self.big_ham {
+            let target = r"SELECT phash FROM media WHERE media_uuid = :media_uuid"
+                .with(params! {
+                    "media_uuid" => media_uuid.value(),
+                })
+                .first::<String, _>(self.conn().await?)
+                .await?;
+
+            return match target {
+                Some(target) => self.similar_phash_fallback(gid, target, distance).await,
+                None => Ok(Vec::new()),
+            };
+        }
+
+        let query = format!(
+            r"
             SELECT
-                media.media_uuid
+                media_uuid, distance
             FROM
                 (
                     SELECT
-                        media_uuid
+                        media.media_uuid,
+                        BIG_HAM((SELECT phash FROM media WHERE media_uuid = :media_uuid), media.phash) AS distance
                     FROM
                         (
                             SELECT
-                                collection_uuid
+                                media_uuid
                             FROM
-                                collections
-                            WHERE
-                                INSTR(:gid, gid) > 0
-                        ) AS t1
-                        INNER JOIN collection_contents ON t1.collection_uuid = collection_contents.collection_uuid
-                    UNION
-                    SELECT
-                        media_uuid
-                    FROM
-                        (
+                                (
+                                    SELECT
+                                        collection_uuid
+                                    FROM
+                                        collections
+                                    WHERE
+                                        {GID_CHECK}
+                                ) AS t1
+                                INNER JOIN collection_contents ON t1.collection_uuid = collection_contents.collection_uuid
+                            UNION
                             SELECT
-                                library_uuid
+                                media_uuid
                             FROM
-                                libraries
-                            WHERE
-                                INSTR(:gid, gid) > 0
+                                (
+                                    SELECT
+                                        library_uuid
+                                    FROM
+                                        libraries
+                                    WHERE
+                                        {GID_CHECK}
+                                ) AS t2
+                                INNER JOIN media ON t2.library_uuid = media.library_uuid
+                        ) AS t3
+                        INNER JOIN media ON t3.media_uuid = media.media_uuid
+                    WHERE
+                        media.hidden = FALSE
+                        AND media.deleted_at IS NULL
+                        AND media.phash != ''
+                ) AS t4
+            WHERE
+                distance < :distance
+            ORDER BY
+                distance ASC, media_uuid ASC"
+        );
+
+        let result = query
+            .with(params! {
+                "gid" => fold_set(gid)?,
+                "media_uuid" => media_uuid.value(),
+                "distance" => distance,
+            })
+            .run(self.conn().await?)
+            .await?
+            .collect::<Row>()
+            .await?;
+
+        let data = result
+            .into_iter()
+            .map(|row| {
+                let (uuid, distance) = from_row_opt::<(Uuid, i64)>(row)?;
+
+                Ok((MediaUuid::from_value(self, uuid), distance))
+            })
+            .collect::<Result<Vec<(MediaUuid, i64)>, FromRowError>>()?;
+
+        debug!({ count = data.len() }, "found similar media");
+
+        Ok(data)
+    }
+
+    #[instrument(skip(self))]
+    async fn similar_phash(
+        &self,
+        gid: HashSet<String>,
+        phash: String,
+        distance: i64,
+    ) -> Result<Vec<(MediaUuid, i64)>> {
+        // see similar_media()
+        debug!("searching for media similar to a phash");
+
+        let _mr = self.locks.media.read().await;
+        let _lr = self.locks.library.read().await;
+        let _xr = self.locks.contents.read().await;
+        let _cr = self.locks.collection.read().await;
+
+        if !self.big_ham {
+            return self.similar_phash_fallback(gid, phash, distance).await;
+        }
+
+        let query = format!(
+            r"
+            SELECT
+                media_uuid, distance
+            FROM
+                (
+                    SELECT
+                        media.media_uuid,
+                        BIG_HAM(:phash, media.phash) AS distance
+                    FROM
+                        (
+                            SELECT
+                                media_uuid
+                            FROM
+                                (
+                                    SELECT
+                                        collection_uuid
+                                    FROM
+                                        collections
+                                    WHERE
+                                        {GID_CHECK}
+                                ) AS t1
+                                INNER JOIN collection_contents ON t1.collection_uuid = collection_contents.collection_uuid
+                            UNION
+                            SELECT
+                                media_uuid
+                            FROM
+                                (
+                                    SELECT
+                                        library_uuid
+                                    FROM
+                                        libraries
+                                    WHERE
+                                        {GID_CHECK}
+                                ) AS t2
+                                INNER JOIN media ON t2.library_uuid = media.library_uuid
+                        ) AS t3
+                        INNER JOIN media ON t3.media_uuid = media.media_uuid
+                    WHERE
+                        media.hidden = FALSE
+                        AND media.deleted_at IS NULL
+                        AND media.phash != ''
+                ) AS t4
+            WHERE
+                distance < :distance
+            ORDER BY
+                distance ASC, media_uuid ASC"
+        );
+
+        let result = query
+            .with(params! {
+                "gid" => fold_set(gid)?,
+                "phash" => phash,
+                "distance" => distance,
+            })
+            .run(self.conn().await?)
+            .await?
+            .collect::<Row>()
+            .await?;
+
+        let data = result
+            .into_iter()
+            .map(|row| {
+                let (uuid, distance) = from_row_opt::<(Uuid, i64)>(row)?;
+
+                Ok((MediaUuid::from_value(self, uuid), distance))
+            })
+            .collect::<Result<Vec<(MediaUuid, i64)>, FromRowError>>()?;
+
+        debug!({ count = data.len() }, "found similar media");
+
+        Ok(data)
+    }
+
+    #[instrument(skip(self))]
+    async fn random_media(
+        &self,
+        gid: HashSet<String>,
+        filter: SearchFilter,
+        count: u64,
+    ) -> Result<Vec<MediaUuid>> {
+        debug!("sampling media");
+
+        let _mr = self.locks.media.read().await;
+        let _lr = self.locks.library.read().await;
+        let _xr = self.locks.contents.read().await;
+        let _cr = self.locks.collection.read().await;
+
+        let (sql, filter) = filter.format_mariadb("media.path, media.date, media.note, media.tags");
+
+        // ORDER BY RAND() sorts every match before taking the first few, so this scales
+        // with the number of accessible media rather than the count.  that is still much
+        // cheaper than the search itself, and keyed sampling would skew towards media that
+        // follow gaps in the uuid space.
+        let mut query = format!(
+            r"
+            SELECT
+                media.media_uuid
+            FROM
+                (
+                    SELECT
+                        media_uuid
+                    FROM
+                        (
+                            SELECT
+                                collection_uuid
+                            FROM
+                                collections
+                            WHERE
+                                {GID_CHECK}
+                        ) AS t1
+                        INNER JOIN collection_contents ON t1.collection_uuid = collection_contents.collection_uuid
+                    UNION
+                    SELECT
+                        media_uuid
+                    FROM
+                        (
+                            SELECT
+                                library_uuid
+                            FROM
+                                libraries
+                            WHERE
+                                {GID_CHECK}
+                        ) AS t2
+                        INNER JOIN media ON t2.library_uuid = media.library_uuid
+                ) AS t3
+                INNER JOIN media ON t3.media_uuid = media.media_uuid
+            WHERE
+                media.hidden = FALSE
+                AND media.deleted_at IS NULL"
+        );
+
+        query.push_str(&sql);
+
+        query.push_str(" ORDER BY RAND() LIMIT :count");
+
+        let params = with_filter(
+            params! {
+                "gid" => fold_set(gid)?,
+                "count" => count,
+            },
+            filter,
+        );
+
+        let result = query
+            .with(params)
+            .run(self.conn().await?)
+            .await?
+            .collect::<Row>()
+            .await?;
+
+        let data = result
+            .into_iter()
+            .map(|row| {
+                let input = from_row_opt::<Uuid>(row)?;
+
+                Ok(MediaUuid::from_value(self, input))
+            })
+            .collect::<Result<Vec<MediaUuid>, FromRowError>>()?;
+
+        debug!({ count = data.len() }, "sampled media");
+
+        Ok(data)
+    }
+
+    #[instrument(skip(self))]
+    async fn stats(&self, gid: HashSet<String>) -> Result<Stats> {
+        debug!("counting media");
+
+        let _mr = self.locks.media.read().await;
+        let _lr = self.locks.library.read().await;
+        let _xr = self.locks.contents.read().await;
+        let _cr = self.locks.collection.read().await;
+
+        // the media are the same set as search_media().  SUM() is a DECIMAL (and NULL over
+        // an empty set), so it is cast back to an integer.
+        let query = format!(
+            r"
+            SELECT
+                COUNT(*),
+                CAST(COALESCE(SUM(media.media_type = 'Image'), 0) AS UNSIGNED),
+                CAST(COALESCE(SUM(media.media_type = 'Video'), 0) AS UNSIGNED),
+                CAST(COALESCE(SUM(media.media_type = 'Audio'), 0) AS UNSIGNED),
+                CAST(COALESCE(SUM(media.size), 0) AS UNSIGNED),
+                (SELECT COUNT(*) FROM collections WHERE {GID_CHECK}),
+                (SELECT COUNT(*) FROM libraries WHERE {GID_CHECK})
+            FROM
+                (
+                    SELECT
+                        media_uuid
+                    FROM
+                        (
+                            SELECT
+                                collection_uuid
+                            FROM
+                                collections
+                            WHERE
+                                {GID_CHECK}
+                        ) AS t1
+                        INNER JOIN collection_contents ON t1.collection_uuid = collection_contents.collection_uuid
+                    UNION
+                    SELECT
+                        media_uuid
+                    FROM
+                        (
+                            SELECT
+                                library_uuid
+                            FROM
+                                libraries
+                            WHERE
+                                {GID_CHECK}
                         ) AS t2
                         INNER JOIN media ON t2.library_uuid = media.library_uuid
                 ) AS t3
                 INNER JOIN media ON t3.media_uuid = media.media_uuid
             WHERE
-                media.hidden = FALSE
-                AND media.phash != ''
-                AND BIG_HAM((SELECT phash FROM media WHERE media_uuid = :media_uuid), media.phash) < :distance"
-        .with(params! {
-            "gid" => fold_set(gid)?,
-            "media_uuid" => media_uuid.value(),
-            "distance" => distance,
-        })
-        .run(self.pool.get_conn().await?)
-        .await?
-        .collect::<Row>()
-        .await?;
+                media.hidden = FALSE
+                AND media.deleted_at IS NULL"
+        );
+
+        let result: Option<(u64, u64, u64, u64, u64, u64, u64)> = query
+            .with(params! {
+                "gid" => fold_set(gid)?,
+            })
+            .first(self.conn().await?)
+            .await?;
+
+        let (media, images, videos, audio, bytes, collections, libraries) =
+            result.ok_or_else(|| anyhow::Error::msg("stats query returned no rows"))?;
+
+        debug!({ media = media }, "counted media");
+
+        Ok(Stats {
+            media,
+            images,
+            videos,
+            audio,
+            bytes,
+            collections,
+            libraries,
+        })
+    }
+
+    #[instrument(skip(self))]
+    async fn media_date_histogram(
+        &self,
+        gid: HashSet<String>,
+        granularity: DateGranularity,
+    ) -> Result<Vec<DateBucket>> {
+        debug!("counting media by date");
+
+        let _mr = self.locks.media.read().await;
+        let _lr = self.locks.library.read().await;
+        let _xr = self.locks.contents.read().await;
+        let _cr = self.locks.collection.read().await;
+
+        // the same media as search_media(), grouped by a prefix of the date.  dates are
+        // free text, so anything that doesn't start with a yyyy-mm-dd is left out.
+        let query = format!(
+            r"
+            SELECT
+                LEFT(media.date, :len) AS bucket,
+                COUNT(*)
+            FROM
+                (
+                    SELECT
+                        media_uuid
+                    FROM
+                        (
+                            SELECT
+                                collection_uuid
+                            FROM
+                                collections
+                            WHERE
+                                {GID_CHECK}
+                        ) AS t1
+                        INNER JOIN collection_contents ON t1.collection_uuid = collection_contents.collection_uuid
+                    UNION
+                    SELECT
+                        media_uuid
+                    FROM
+                        (
+                            SELECT
+                                library_uuid
+                            FROM
+                                libraries
+                            WHERE
+                                {GID_CHECK}
+                        ) AS t2
+                        INNER JOIN media ON t2.library_uuid = media.library_uuid
+                ) AS t3
+                INNER JOIN media ON t3.media_uuid = media.media_uuid
+            WHERE
+                media.hidden = FALSE
+                AND media.deleted_at IS NULL
+                AND media.date REGEXP '^[0-9]{{4}}-[0-9]{{2}}-[0-9]{{2}}'
+            GROUP BY
+                bucket
+            ORDER BY
+                bucket ASC"
+        );
+
+        let result = query
+            .with(params! {
+                "gid" => fold_set(gid)?,
+                "len" => granularity.prefix_len(),
+            })
+            .run(self.conn().await?)
+            .await?
+            .collect::<Row>()
+            .await?;
+
+        let mut data = Vec::new();
+
+        for row in result {
+            let (date, count) = from_row_opt::<(String, u64)>(row)?;
+
+            if let Some(bucket) = date_bucket(date, granularity, count) {
+                data.push(bucket);
+            }
+        }
+
+        debug!({ buckets = data.len() }, "counted media by date");
+
+        Ok(data)
+    }
+
+    #[instrument(skip(self))]
+    async fn recent_activity(&self, gid: HashSet<String>, limit: u64) -> Result<Vec<Activity>> {
+        debug!("finding recent activity");
+
+        let _mr = self.locks.media.read().await;
+        let _yr = self.locks.comment.read().await;
+        let _lr = self.locks.library.read().await;
+        let _xr = self.locks.contents.read().await;
+        let _cr = self.locks.collection.read().await;
+
+        // see ActivityRow for why the uuids double as timestamps.  this relies on the UUID
+        // column type sorting v7 values by time, which it does in any version that has
+        // UUID_v7() to begin with.
+        let query = format!(
+            r"
+            WITH accessible AS (
+                SELECT
+                    media.media_uuid
+                FROM
+                    (
+                        SELECT
+                            media_uuid
+                        FROM
+                            (
+                                SELECT
+                                    collection_uuid
+                                FROM
+                                    collections
+                                WHERE
+                                    {GID_CHECK}
+                            ) AS t1
+                            INNER JOIN collection_contents ON t1.collection_uuid = collection_contents.collection_uuid
+                        UNION
+                        SELECT
+                            media_uuid
+                        FROM
+                            (
+                                SELECT
+                                    library_uuid
+                                FROM
+                                    libraries
+                                WHERE
+                                    {GID_CHECK}
+                            ) AS t2
+                            INNER JOIN media ON t2.library_uuid = media.library_uuid
+                    ) AS t3
+                    INNER JOIN media ON t3.media_uuid = media.media_uuid
+                WHERE
+                    media.hidden = FALSE
+                    AND media.deleted_at IS NULL
+            )
+            SELECT
+                'media' AS kind,
+                media_uuid AS uuid,
+                NULL AS media_uuid
+            FROM
+                accessible
+            UNION ALL
+            SELECT
+                'collection',
+                collection_uuid,
+                NULL
+            FROM
+                collections
+            WHERE
+                {GID_CHECK}
+            UNION ALL
+            SELECT
+                'comment',
+                comments.comment_uuid,
+                comments.media_uuid
+            FROM
+                comments
+                INNER JOIN accessible ON comments.media_uuid = accessible.media_uuid
+            ORDER BY
+                uuid DESC
+            LIMIT
+                :limit"
+        );
+
+        let result = query
+            .with(params! {
+                "gid" => fold_set(gid)?,
+                "limit" => limit,
+            })
+            .run(self.conn().await?)
+            .await?
+            .collect::<Row>()
+            .await?;
+
+        let data = result
+            .into_iter()
+            .map(|row| {
+                let (kind, uuid, media_uuid) = from_row_opt::<(String, Uuid, Option<Uuid>)>(row)?;
+
+                ActivityRow {
+                    kind,
+                    uuid,
+                    media_uuid,
+                }
+                .into_activity(self)
+            })
+            .collect::<Result<Vec<Activity>>>()?;
+
+        debug!({ count = data.len() }, "found recent activity");
+
+        Ok(data)
+    }
+
+    // trash queries
+    #[instrument(skip(self))]
+    async fn soft_delete_media(&self, media_uuid: MediaUuid) -> Result<()> {
+        debug!("moving media to trash");
+
+        let _mw = self.locks.media.write().await;
+
+        r"
+        UPDATE media SET deleted_at = :deleted_at WHERE media_uuid = :media_uuid AND deleted_at IS NULL"
+            .with(params! {
+                "deleted_at" => SystemTime::now().duration_since(UNIX_EPOCH)?.as_secs(),
+                "media_uuid" => media_uuid.value(),
+            })
+            .run(self.conn().await?)
+            .await?;
+
+        debug!("moved media to trash");
+
+        Ok(())
+    }
+
+    #[instrument(skip(self))]
+    async fn restore_media(&self, media_uuid: MediaUuid) -> Result<()> {
+        debug!("restoring media from trash");
+
+        let _mw = self.locks.media.write().await;
+
+        r"
+        UPDATE media SET deleted_at = NULL WHERE media_uuid = :media_uuid"
+            .with(params! {
+                "media_uuid" => media_uuid.value(),
+            })
+            .run(self.conn().await?)
+            .await?;
+
+        debug!("restored media from trash");
+
+        Ok(())
+    }
+
+    #[instrument(skip(self))]
+    async fn purge_media(&self, media_uuid: MediaUuid) -> Result<()> {
+        debug!("purging media");
+
+        let _mw = self.locks.media.write().await;
+        let _yw = self.locks.comment.write().await;
+        let _xw = self.locks.contents.write().await;
+        let _cw = self.locks.collection.write().await;
+
+        let mut tx = self.transaction().await?;
+
+        let result = r"
+            DELETE FROM media WHERE media_uuid = :media_uuid AND deleted_at IS NOT NULL
+            RETURNING media_uuid"
+            .with(params! {
+                "media_uuid" => media_uuid.value(),
+            })
+            .run(&mut tx)
+            .await?
+            .collect::<Row>()
+            .await?;
+
+        if result.is_empty() {
+            return Err(anyhow::Error::msg("media is not in the trash"));
+        }
+
+        r"
+        DELETE FROM collection_contents WHERE media_uuid = :media_uuid"
+            .with(params! {
+                "media_uuid" => media_uuid.value(),
+            })
+            .run(&mut tx)
+            .await?;
+
+        r"
+        DELETE FROM comments WHERE media_uuid = :media_uuid"
+            .with(params! {
+                "media_uuid" => media_uuid.value(),
+            })
+            .run(&mut tx)
+            .await?;
+
+        r"
+        UPDATE collections SET cover = NULL WHERE cover = :media_uuid"
+            .with(params! {
+                "media_uuid" => media_uuid.value(),
+            })
+            .run(&mut tx)
+            .await?;
+
+        tx.commit().await?;
+
+        debug!("purged media");
+
+        Ok(())
+    }
+
+    #[instrument(skip(self))]
+    async fn search_trash(
+        &self,
+        gid: HashSet<String>,
+        filter: SearchFilter,
+    ) -> Result<Vec<MediaUuid>> {
+        debug!("searching trash");
+
+        let _mr = self.locks.media.read().await;
+        let _lr = self.locks.library.read().await;
+
+        let (sql, filter) = filter.format_mariadb("media.path, media.date, media.note, media.tags");
+
+        // for a given uid and filter, find all soft-deleted media in libraries owned by a group
+        // containing the uid.  collection access does not extend to the trash.
+        let mut query = format!(
+            r"
+            SELECT
+                media.media_uuid
+            FROM
+                (
+                    SELECT
+                        library_uuid
+                    FROM
+                        libraries
+                    WHERE
+                        {GID_CHECK}
+                ) AS t1
+                INNER JOIN media ON t1.library_uuid = media.library_uuid
+            WHERE
+                media.deleted_at IS NOT NULL"
+        );
+
+        query.push_str(&sql);
+        query.push_str(" ORDER BY media.deleted_at DESC, media.media_uuid DESC");
+
+        let result = query
+            .with(with_filter(
+                params! {
+                    "gid" => fold_set(gid)?,
+                },
+                filter,
+            ))
+            .run(self.conn().await?)
+            .await?
+            .collect::<Row>()
+            .await?;
 
         let data = result
             .into_iter()
@@ -650,17 +1906,91 @@ impl DbBackend for MariaDBBackend {
             })
             .collect::<Result<Vec<MediaUuid>, FromRowError>>()?;
 
-        debug!({ count = data.len() }, "found similar media");
+        debug!({ count = data.len() }, "found media in trash");
 
         Ok(data)
     }
 
+    // tag queries
+    #[instrument(skip(self))]
+    async fn rename_tag(&self, gid: HashSet<String>, from: String, to: String) -> Result<u64> {
+        debug!("renaming tag");
+
+        let _mw = self.locks.media.write().await;
+        let _lr = self.locks.library.read().await;
+        let _cw = self.locks.collection.write().await;
+
+        let gid = fold_set(gid)?;
+
+        let mut tx = self.transaction().await?;
+
+        let mut count = 0;
+
+        for (query, statement) in [
+            (
+                format!(
+                    r"
+                SELECT media_uuid, tags FROM media
+                WHERE
+                    library_uuid IN (SELECT library_uuid FROM libraries WHERE {GID_CHECK})
+                    AND INSTR(tags, :from) > 0
+                FOR UPDATE"
+                ),
+                "UPDATE media SET tags = :tags WHERE media_uuid = :uuid",
+            ),
+            (
+                format!(
+                    r"
+                SELECT collection_uuid, tags FROM collections
+                WHERE
+                    {GID_CHECK}
+                    AND INSTR(tags, :from) > 0
+                FOR UPDATE"
+                ),
+                "UPDATE collections SET tags = :tags WHERE collection_uuid = :uuid",
+            ),
+        ] {
+            // INSTR() only narrows down the candidates, renamed_tags() does the real match
+            let rows = query
+                .with(params! {
+                    "gid" => gid.clone(),
+                    "from" => from.clone(),
+                })
+                .run(&mut tx)
+                .await?
+                .collect::<Row>()
+                .await?
+                .into_iter()
+                .map(from_row_opt::<(Uuid, String)>)
+                .collect::<Result<Vec<(Uuid, String)>, FromRowError>>()?;
+
+            for (uuid, folded) in rows {
+                if let Some(tags) = renamed_tags(&folded, &from, &to)? {
+                    statement
+                        .with(params! {
+                            "tags" => tags,
+                            "uuid" => uuid,
+                        })
+                        .run(&mut tx)
+                        .await?;
+
+                    count += 1;
+                }
+            }
+        }
+
+        tx.commit().await?;
+
+        debug!({ count = count }, "renamed tag");
+
+        Ok(count)
+    }
+
     // comment queries
     #[instrument(skip(self, comment))]
     async fn add_comment(&self, comment: Comment) -> Result<CommentUuid> {
         debug!({ media_uuid = %comment.media_uuid }, "adding comment");
 
-        let _mw = self.locks.media.write().await;
         let _yw = self.locks.comment.write().await;
 
         let mut result = r"
@@ -673,7 +2003,7 @@ impl DbBackend for MariaDBBackend {
                 "date" => SystemTime::now().duration_since(UNIX_EPOCH)?.as_secs(),
                 "text" => comment.text,
             })
-            .run(self.pool.get_conn().await?)
+            .run(self.conn().await?)
             .await?
             .collect::<Row>()
             .await?;
@@ -697,11 +2027,11 @@ impl DbBackend for MariaDBBackend {
         let _yr = self.locks.comment.read().await;
 
         let mut result = r"
-            SELECT media_uuid, uid, date, text FROM comments WHERE comment_uuid = :comment_uuid"
+            SELECT media_uuid, uid, date, text, edited FROM comments WHERE comment_uuid = :comment_uuid"
             .with(params! {
                 "comment_uuid" => comment_uuid.value(),
             })
-            .run(self.pool.get_conn().await?)
+            .run(self.conn().await?)
             .await?
             .collect::<Row>()
             .await?;
@@ -711,7 +2041,7 @@ impl DbBackend for MariaDBBackend {
             None => return Ok(None),
         };
 
-        let data = from_row_opt::<(Uuid, String, u64, String)>(row)?;
+        let data = from_row_opt::<(Uuid, String, u64, String, Option<u64>)>(row)?;
 
         debug!("found comment details");
 
@@ -720,6 +2050,7 @@ impl DbBackend for MariaDBBackend {
             uid: data.1,
             date: data.2,
             text: data.3,
+            edited: data.4,
         }))
     }
 
@@ -731,7 +2062,7 @@ impl DbBackend for MariaDBBackend {
 
         let result = r"
             SELECT comment_uuid FROM comments"
-            .run(self.pool.get_conn().await?)
+            .run(self.conn().await?)
             .await?
             .collect::<Row>()
             .await?;
@@ -761,7 +2092,7 @@ impl DbBackend for MariaDBBackend {
             .with(params! {
                 "comment_uuid" => comment_uuid.value(),
             })
-            .run(self.pool.get_conn().await?)
+            .run(self.conn().await?)
             .await?;
 
         debug!("deleted comment");
@@ -775,14 +2106,20 @@ impl DbBackend for MariaDBBackend {
 
         let _yw = self.locks.comment.write().await;
 
+        // rewriting the same text isn't an edit, so it leaves the timestamp alone.  mariadb
+        // applies the assignments in order, so edited has to be compared to the old text.
         if let Some(val) = text {
             r"
-            UPDATE comments SET text = :text WHERE comment_uuid = :comment_uuid"
+            UPDATE comments SET
+                edited = IF(text <=> :text, edited, :now),
+                text = :text
+            WHERE comment_uuid = :comment_uuid"
                 .with(params! {
                     "text" => val.clone(),
+                    "now" => SystemTime::now().duration_since(UNIX_EPOCH)?.as_secs(),
                     "comment_uuid" => comment_uuid.value(),
                 })
-                .run(self.pool.get_conn().await?)
+                .run(self.conn().await?)
                 .await?;
         }
 
@@ -791,6 +2128,368 @@ impl DbBackend for MariaDBBackend {
         Ok(())
     }
 
+    // api key queries
+    #[instrument(skip(self, key_hash))]
+    async fn add_api_key(&self, uid: String, name: String, key_hash: String) -> Result<ApiKeyUuid> {
+        debug!("adding api key");
+
+        let _kw = self.locks.api_key.write().await;
+
+        let mut result = r"
+            INSERT INTO api_keys (key_uuid, uid, name, key_hash, created)
+            VALUES (UUID_v7(), :uid, :name, :key_hash, :created)
+            RETURNING key_uuid"
+            .with(params! {
+                "uid" => uid,
+                "name" => name,
+                "key_hash" => key_hash,
+                "created" => SystemTime::now().duration_since(UNIX_EPOCH)?.as_secs(),
+            })
+            .run(self.conn().await?)
+            .await?
+            .collect::<Row>()
+            .await?;
+
+        let row = result.pop().ok_or_else(|| {
+            error!("failed to add api key");
+            anyhow::Error::msg("failed to add api key")
+        })?;
+
+        let data = from_row_opt::<Uuid>(row)?;
+
+        debug!({ key_uuid = %data }, "added api key");
+
+        Ok(ApiKeyUuid::from_value(self, data))
+    }
+
+    #[instrument(skip_all)]
+    async fn get_api_key_uid(&self, key_hash: String) -> Result<Option<String>> {
+        debug!("looking up api key");
+
+        let _kr = self.locks.api_key.read().await;
+
+        let mut result = r"
+            SELECT uid FROM api_keys WHERE key_hash = :key_hash"
+            .with(params! {
+                "key_hash" => key_hash,
+            })
+            .run(self.conn().await?)
+            .await?
+            .collect::<Row>()
+            .await?;
+
+        let data = match result.pop() {
+            Some(row) => Some(from_row_opt::<String>(row)?),
+            None => None,
+        };
+
+        Ok(data)
+    }
+
+    #[instrument(skip(self))]
+    async fn get_api_keys(&self, uid: String) -> Result<Vec<ApiKey>> {
+        debug!("getting api keys");
+
+        let _kr = self.locks.api_key.read().await;
+
+        let result = r"
+            SELECT key_uuid, name, created FROM api_keys WHERE uid = :uid"
+            .with(params! {
+                "uid" => uid.clone(),
+            })
+            .run(self.conn().await?)
+            .await?
+            .collect::<Row>()
+            .await?;
+
+        let data = result
+            .into_iter()
+            .map(|row| {
+                let (key_uuid, name, created) = from_row_opt::<(Uuid, String, u64)>(row)?;
+
+                Ok(ApiKey {
+                    key_uuid: ApiKeyUuid::from_value(self, key_uuid),
+                    uid: uid.clone(),
+                    name,
+                    created,
+                })
+            })
+            .collect::<Result<Vec<ApiKey>, FromRowError>>()?;
+
+        debug!({ count = data.len() }, "found api keys");
+
+        Ok(data)
+    }
+
+    #[instrument(skip(self))]
+    async fn delete_api_key(&self, uid: String, key_uuid: ApiKeyUuid) -> Result<()> {
+        debug!("deleting api key");
+
+        let _kw = self.locks.api_key.write().await;
+
+        r"
+        DELETE FROM api_keys WHERE (key_uuid = :key_uuid AND uid = :uid)"
+            .with(params! {
+                "key_uuid" => key_uuid.value(),
+                "uid" => uid,
+            })
+            .run(self.conn().await?)
+            .await?;
+
+        debug!("deleted api key");
+
+        Ok(())
+    }
+
+    // share link queries
+    #[instrument(skip(self, token_hash))]
+    async fn add_share_link(
+        &self,
+        uid: String,
+        target: ShareTarget,
+        token_hash: String,
+        expires_at: u64,
+    ) -> Result<ShareLinkUuid> {
+        debug!("adding share link");
+
+        let _sw = self.locks.share_link.write().await;
+
+        let (media_uuid, collection_uuid) = share_target_columns(target);
+
+        let mut result = r"
+            INSERT INTO share_links (share_uuid, uid, token_hash, media_uuid, collection_uuid, created, expires_at)
+            VALUES (UUID_v7(), :uid, :token_hash, :media_uuid, :collection_uuid, :created, :expires_at)
+            RETURNING share_uuid"
+            .with(params! {
+                "uid" => uid,
+                "token_hash" => token_hash,
+                "media_uuid" => media_uuid,
+                "collection_uuid" => collection_uuid,
+                "created" => SystemTime::now().duration_since(UNIX_EPOCH)?.as_secs(),
+                "expires_at" => expires_at,
+            })
+            .run(self.conn().await?)
+            .await?
+            .collect::<Row>()
+            .await?;
+
+        let row = result.pop().ok_or_else(|| {
+            error!("failed to add share link");
+            anyhow::Error::msg("failed to add share link")
+        })?;
+
+        let data = from_row_opt::<Uuid>(row)?;
+
+        debug!({ share_uuid = %data }, "added share link");
+
+        Ok(ShareLinkUuid::from_value(self, data))
+    }
+
+    #[instrument(skip_all)]
+    async fn get_share_link(&self, token_hash: String) -> Result<Option<ShareLink>> {
+        debug!("looking up share link");
+
+        let _sr = self.locks.share_link.read().await;
+
+        let mut result = r"
+            SELECT share_uuid, uid, media_uuid, collection_uuid, created, expires_at
+            FROM share_links WHERE token_hash = :token_hash"
+            .with(params! {
+                "token_hash" => token_hash,
+            })
+            .run(self.conn().await?)
+            .await?
+            .collect::<Row>()
+            .await?;
+
+        match result.pop() {
+            Some(row) => Ok(Some(share_link_row(row)?.into_share_link(self)?)),
+            None => Ok(None),
+        }
+    }
+
+    #[instrument(skip(self))]
+    async fn get_share_links(&self, uid: String) -> Result<Vec<ShareLink>> {
+        debug!("getting share links");
+
+        let _sr = self.locks.share_link.read().await;
+
+        let result = r"
+            SELECT share_uuid, uid, media_uuid, collection_uuid, created, expires_at
+            FROM share_links WHERE uid = :uid"
+            .with(params! {
+                "uid" => uid,
+            })
+            .run(self.conn().await?)
+            .await?
+            .collect::<Row>()
+            .await?;
+
+        let data = result
+            .into_iter()
+            .map(|row| share_link_row(row)?.into_share_link(self))
+            .collect::<Result<Vec<ShareLink>>>()?;
+
+        debug!({ count = data.len() }, "found share links");
+
+        Ok(data)
+    }
+
+    #[instrument(skip(self))]
+    async fn delete_share_link(&self, uid: String, share_uuid: ShareLinkUuid) -> Result<()> {
+        debug!("deleting share link");
+
+        let _sw = self.locks.share_link.write().await;
+
+        r"
+        DELETE FROM share_links WHERE (share_uuid = :share_uuid AND uid = :uid)"
+            .with(params! {
+                "share_uuid" => share_uuid.value(),
+                "uid" => uid,
+            })
+            .run(self.conn().await?)
+            .await?;
+
+        debug!("deleted share link");
+
+        Ok(())
+    }
+
+    #[instrument(skip(self))]
+    async fn get_shared_media(&self, target: ShareTarget) -> Result<Vec<MediaUuid>> {
+        debug!("finding shared media");
+
+        let _mr = self.locks.media.read().await;
+        let _xr = self.locks.contents.read().await;
+
+        let (media_uuid, collection_uuid) = share_target_columns(target);
+
+        // only one of the uuids is set, and comparing against NULL never matches
+        let result = r"
+            SELECT media_uuid FROM media
+            WHERE
+                (
+                    media_uuid = :media_uuid
+                    OR media_uuid IN (
+                        SELECT media_uuid FROM collection_contents
+                        WHERE collection_uuid = :collection_uuid
+                    )
+                )
+                AND hidden = FALSE
+                AND deleted_at IS NULL"
+            .with(params! {
+                "media_uuid" => media_uuid,
+                "collection_uuid" => collection_uuid,
+            })
+            .run(self.conn().await?)
+            .await?
+            .collect::<Row>()
+            .await?;
+
+        let data = result
+            .into_iter()
+            .map(|row| {
+                let input = from_row_opt::<Uuid>(row)?;
+
+                Ok(MediaUuid::from_value(self, input))
+            })
+            .collect::<Result<Vec<MediaUuid>, FromRowError>>()?;
+
+        debug!({ count = data.len() }, "found shared media");
+
+        Ok(data)
+    }
+
+    // task queries
+    #[instrument(skip(self, task))]
+    async fn add_task(&self, library: TaskLibrary, task: Task) -> Result<()> {
+        debug!("adding task");
+
+        let task = TaskRow::new(library, task);
+
+        let _tw = self.locks.task.write().await;
+
+        r"
+        INSERT INTO tasks (library_uuid, task_type, uid, status, warnings, start_time, end_time, summary)
+        VALUES (:library_uuid, :task_type, :uid, :status, :warnings, :start_time, :end_time, :summary)"
+            .with(params! {
+                "library_uuid" => task.library_uuid.map(|v| v.value()),
+                "task_type" => task.task_type,
+                "uid" => task.uid,
+                "status" => task.status,
+                "warnings" => task.warnings,
+                "start_time" => task.start,
+                "end_time" => task.end,
+                "summary" => task.summary,
+            })
+            .run(self.conn().await?)
+            .await?;
+
+        debug!("added task");
+
+        Ok(())
+    }
+
+    #[instrument(skip(self))]
+    async fn get_tasks(&self, library: TaskLibrary, limit: u64) -> Result<Vec<Task>> {
+        debug!("getting tasks");
+
+        let library_uuid = match library {
+            TaskLibrary::User { library_uuid } => Some(library_uuid),
+            TaskLibrary::System => None,
+        };
+
+        let _tr = self.locks.task.read().await;
+
+        // the null-safe comparison matches the system tasks, which have no library
+        let result = r"
+            SELECT task_type, uid, status, warnings, start_time, end_time, summary
+            FROM tasks
+            WHERE library_uuid <=> :library_uuid
+            ORDER BY end_time DESC, task_id DESC
+            LIMIT :limit"
+            .with(params! {
+                "library_uuid" => library_uuid.map(|v| v.value()),
+                "limit" => limit,
+            })
+            .run(self.conn().await?)
+            .await?
+            .collect::<Row>()
+            .await?;
+
+        let data = result
+            .into_iter()
+            .map(|row| {
+                let (task_type, uid, status, warnings, start, end, summary) =
+                    from_row_opt::<(
+                        String,
+                        Option<String>,
+                        String,
+                        Option<i64>,
+                        u64,
+                        u64,
+                        Option<String>,
+                    )>(row)?;
+
+                TaskRow {
+                    library_uuid,
+                    task_type,
+                    uid,
+                    status,
+                    warnings,
+                    start,
+                    end,
+                    summary,
+                }
+                .into_task()
+            })
+            .collect::<Result<Vec<Task>>>()?;
+
+        debug!({ count = data.len() }, "found tasks");
+
+        Ok(data)
+    }
+
     // collection queries
     #[instrument(skip(self, collection))]
     async fn add_collection(&self, collection: Collection) -> Result<CollectionUuid> {
@@ -799,7 +2498,7 @@ impl DbBackend for MariaDBBackend {
         let _cw = self.locks.collection.write().await;
 
         let mut result = r"
-            INSERT INTO collections (collection_uuid, uid, gid, name, note, tags, cover)
+            INSERT INTO collections (collection_uuid, uid, gid, name, note, tags, cover, parent_uuid)
             SELECT
                 UUID_v7(),
                 :uid,
@@ -807,7 +2506,8 @@ impl DbBackend for MariaDBBackend {
                 :name,
                 :note,
                 :tags,
-                :cover
+                :cover,
+                :parent_uuid
             FROM
                 DUAL
             WHERE NOT EXISTS(
@@ -825,8 +2525,9 @@ impl DbBackend for MariaDBBackend {
                 "note" => collection.note,
                 "tags" => fold_set(collection.tags)?,
                 "cover" => collection.cover.map(|m| m.value()),
+                "parent_uuid" => collection.parent_uuid.map(|c| c.value()),
             })
-            .run(self.pool.get_conn().await?)
+            .run(self.conn().await?)
             .await?
             .collect::<Row>()
             .await?;
@@ -847,25 +2548,64 @@ impl DbBackend for MariaDBBackend {
     async fn get_collection(&self, collection_uuid: CollectionUuid) -> Result<Option<Collection>> {
         debug!("getting collection details");
 
+        let _mr = self.locks.media.read().await;
+        let _xr = self.locks.contents.read().await;
         let _cr = self.locks.collection.read().await;
 
-        let mut result = r"
-            SELECT uid, gid, name, note, tags, cover FROM collections WHERE collection_uuid = :collection_uuid"
-        .with(params! {
-            "collection_uuid" => collection_uuid.value(),
-        })
-        .run(self.pool.get_conn().await?)
-        .await?
-        .collect::<Row>()
-        .await?;
+        // the cover falls back to the most recent visible media when it was never set, or
+        // when the chosen one has since been hidden, deleted, or removed from the collection
+        let mut result = r"
+            SELECT
+                uid,
+                gid,
+                name,
+                note,
+                tags,
+                COALESCE(
+                    (
+                        SELECT media.media_uuid FROM collection_contents
+                        INNER JOIN media ON collection_contents.media_uuid = media.media_uuid
+                        WHERE
+                            collection_contents.collection_uuid = collections.collection_uuid
+                            AND media.media_uuid = collections.cover
+                            AND media.hidden = FALSE
+                            AND media.deleted_at IS NULL
+                    ),
+                    (
+                        SELECT media.media_uuid FROM collection_contents
+                        INNER JOIN media ON collection_contents.media_uuid = media.media_uuid
+                        WHERE
+                            collection_contents.collection_uuid = collections.collection_uuid
+                            AND media.hidden = FALSE
+                            AND media.deleted_at IS NULL
+                        ORDER BY media.date DESC, media.media_uuid DESC
+                        LIMIT 1
+                    )
+                ) AS cover,
+                parent_uuid
+            FROM collections WHERE collection_uuid = :collection_uuid"
+            .with(params! {
+                "collection_uuid" => collection_uuid.value(),
+            })
+            .run(self.conn().await?)
+            .await?
+            .collect::<Row>()
+            .await?;
 
         let row = match result.pop() {
             Some(row) => row,
             None => return Ok(None),
         };
 
-        let data =
-            from_row_opt::<(String, String, String, String, String, Option<Uuid>)>(row)?;
+        let data = from_row_opt::<(
+            String,
+            String,
+            String,
+            String,
+            String,
+            Option<Uuid>,
+            Option<Uuid>,
+        )>(row)?;
 
         debug!("found collection details");
 
@@ -878,6 +2618,7 @@ impl DbBackend for MariaDBBackend {
             note: data.3,
             tags: unfold_set(&tags),
             cover: data.5.map(|m| MediaUuid::from_value(self, m)),
+            parent_uuid: data.6.map(|c| CollectionUuid::from_value(self, c)),
         }))
     }
 
@@ -889,7 +2630,7 @@ impl DbBackend for MariaDBBackend {
 
         let result = r"
             SELECT collection_uuid FROM collections"
-            .run(self.pool.get_conn().await?)
+            .run(self.conn().await?)
             .await?
             .collect::<Row>()
             .await?;
@@ -915,12 +2656,23 @@ impl DbBackend for MariaDBBackend {
         let _xw = self.locks.contents.write().await;
         let _cw = self.locks.collection.write().await;
 
+        let mut tx = self.transaction().await?;
+
         r"
             DELETE FROM collection_contents WHERE collection_uuid = :collection_uuid"
             .with(params! {
                 "collection_uuid" => collection_uuid.value(),
             })
-            .run(self.pool.get_conn().await?)
+            .run(&mut tx)
+            .await?;
+
+        // the children move up to the top level rather than disappearing
+        r"
+            UPDATE collections SET parent_uuid = NULL WHERE parent_uuid = :collection_uuid"
+            .with(params! {
+                "collection_uuid" => collection_uuid.value(),
+            })
+            .run(&mut tx)
             .await?;
 
         debug!("deleting collection");
@@ -930,9 +2682,11 @@ impl DbBackend for MariaDBBackend {
             .with(params! {
                 "collection_uuid" => collection_uuid.value(),
             })
-            .run(self.pool.get_conn().await?)
+            .run(&mut tx)
             .await?;
 
+        tx.commit().await?;
+
         debug!("deleted collection");
 
         Ok(())
@@ -948,6 +2702,8 @@ impl DbBackend for MariaDBBackend {
 
         let _cw = self.locks.collection.write().await;
 
+        let mut tx = self.transaction().await?;
+
         if let Some(val) = update.name {
             r"
             UPDATE collections SET name = :name WHERE collection_uuid = :collection_uuid"
@@ -955,7 +2711,7 @@ impl DbBackend for MariaDBBackend {
                     "name" => val.clone(),
                     "collection_uuid" => collection_uuid.value(),
                 })
-                .run(self.pool.get_conn().await?)
+                .run(&mut tx)
                 .await?;
         }
 
@@ -966,7 +2722,7 @@ impl DbBackend for MariaDBBackend {
                     "note" => val.clone(),
                     "collection_uuid" => collection_uuid.value(),
                 })
-                .run(self.pool.get_conn().await?)
+                .run(&mut tx)
                 .await?;
         }
 
@@ -977,15 +2733,138 @@ impl DbBackend for MariaDBBackend {
                     "tags" => fold_set(val.clone())?,
                     "collection_uuid" => collection_uuid.value(),
                 })
-                .run(self.pool.get_conn().await?)
+                .run(&mut tx)
                 .await?;
         }
 
+        tx.commit().await?;
+
         debug!("updated collection");
 
         Ok(())
     }
 
+    #[instrument(skip(self))]
+    async fn set_collection_cover(
+        &self,
+        collection_uuid: CollectionUuid,
+        cover: Option<MediaUuid>,
+    ) -> Result<()> {
+        debug!("setting collection cover");
+
+        let _cw = self.locks.collection.write().await;
+
+        r"
+        UPDATE collections SET cover = :cover WHERE collection_uuid = :collection_uuid"
+            .with(params! {
+                "cover" => cover.map(|m| m.value()),
+                "collection_uuid" => collection_uuid.value(),
+            })
+            .run(self.conn().await?)
+            .await?;
+
+        debug!("set collection cover");
+
+        Ok(())
+    }
+
+    #[instrument(skip(self))]
+    async fn set_collection_parent(
+        &self,
+        collection_uuid: CollectionUuid,
+        parent_uuid: Option<CollectionUuid>,
+    ) -> Result<()> {
+        debug!("setting collection parent");
+
+        let _cw = self.locks.collection.write().await;
+
+        let mut tx = self.transaction().await?;
+
+        // walk up from the new parent, which must not pass through the collection
+        if let Some(parent_uuid) = parent_uuid {
+            let row = r"
+                WITH RECURSIVE ancestors (collection_uuid) AS (
+                    SELECT :parent_uuid
+                    UNION
+                    SELECT collections.parent_uuid FROM collections
+                    INNER JOIN ancestors ON collections.collection_uuid = ancestors.collection_uuid
+                    WHERE collections.parent_uuid IS NOT NULL
+                )
+                SELECT COUNT(*) FROM ancestors WHERE collection_uuid = :collection_uuid"
+                .with(params! {
+                    "parent_uuid" => parent_uuid.value(),
+                    "collection_uuid" => collection_uuid.value(),
+                })
+                .run(&mut tx)
+                .await?
+                .collect::<Row>()
+                .await?
+                .pop()
+                .ok_or_else(|| anyhow::Error::msg("failed to check collection ancestors"))?;
+
+            if from_row_opt::<i64>(row)? > 0 {
+                return Err(anyhow::Error::msg("collection cannot be its own ancestor"));
+            }
+        }
+
+        r"
+        UPDATE collections SET parent_uuid = :parent_uuid WHERE collection_uuid = :collection_uuid"
+            .with(params! {
+                "parent_uuid" => parent_uuid.map(|c| c.value()),
+                "collection_uuid" => collection_uuid.value(),
+            })
+            .run(&mut tx)
+            .await?;
+
+        tx.commit().await?;
+
+        debug!("set collection parent");
+
+        Ok(())
+    }
+
+    #[instrument(skip(self))]
+    async fn get_collection_children(
+        &self,
+        gid: HashSet<String>,
+        collection_uuid: CollectionUuid,
+    ) -> Result<Vec<CollectionUuid>> {
+        debug!("getting collection children");
+
+        let _cr = self.locks.collection.read().await;
+
+        let result = format!(
+            r"
+            SELECT
+                collection_uuid
+            FROM
+                collections
+            WHERE
+                {GID_CHECK} AND parent_uuid = :collection_uuid"
+        )
+        .with(params! {
+            "gid" => fold_set(gid)?,
+            "collection_uuid" => collection_uuid.value(),
+        })
+        .run(self.conn().await?)
+        .await?
+        .collect::<Row>()
+        .await?;
+
+        let data = result
+            .into_iter()
+            .map(|row| {
+                let input = from_row_opt::<Uuid>(row)?;
+
+                Ok(CollectionUuid::from_value(self, input))
+            })
+            .collect::<Result<Vec<CollectionUuid>, FromRowError>>()?;
+
+        debug!({ count = data.len() }, "found collection children");
+
+        Ok(data)
+    }
+
     #[instrument(skip(self))]
     async fn add_media_to_collection(
         &self,
@@ -996,11 +2875,16 @@ impl DbBackend for MariaDBBackend {
 
         let _cw = self.locks.contents.write().await;
 
+        // appended after whatever is already in the collection
         let mut result = r"
-            INSERT INTO collection_contents (media_uuid, collection_uuid)
+            INSERT INTO collection_contents (media_uuid, collection_uuid, position)
             SELECT
                 :media_uuid,
-                :collection_uuid
+                :collection_uuid,
+                (
+                    SELECT COALESCE(MAX(position), -1) + 1 FROM collection_contents
+                    WHERE collection_uuid = :collection_uuid
+                )
             FROM
                 DUAL
             WHERE NOT EXISTS(
@@ -1015,7 +2899,7 @@ impl DbBackend for MariaDBBackend {
                 "media_uuid" => media_uuid.value(),
                 "collection_uuid" => collection_uuid.value(),
             })
-            .run(self.pool.get_conn().await?)
+            .run(self.conn().await?)
             .await?
             .collect::<Row>()
             .await?;
@@ -1046,7 +2930,7 @@ impl DbBackend for MariaDBBackend {
             "media_uuid" => media_uuid.value(),
             "collection_uuid" => collection_uuid.value(),
         })
-        .run(self.pool.get_conn().await?)
+        .run(self.conn().await?)
         .await?;
 
         debug!("removed media from collection");
@@ -1054,6 +2938,49 @@ impl DbBackend for MariaDBBackend {
         Ok(())
     }
 
+    #[instrument(skip(self, media_uuids))]
+    async fn reorder_collection_media(
+        &self,
+        collection_uuid: CollectionUuid,
+        media_uuids: Vec<MediaUuid>,
+    ) -> Result<()> {
+        debug!({ count = media_uuids.len() }, "reordering collection media");
+
+        let _cw = self.locks.contents.write().await;
+
+        let mut tx = self.transaction().await?;
+
+        // everything moves back past the listed media, which then take the first positions
+        r"
+        UPDATE collection_contents SET position = position + :count
+        WHERE collection_uuid = :collection_uuid"
+            .with(params! {
+                "count" => media_uuids.len(),
+                "collection_uuid" => collection_uuid.value(),
+            })
+            .run(&mut tx)
+            .await?;
+
+        for (position, media_uuid) in media_uuids.into_iter().enumerate() {
+            r"
+            UPDATE collection_contents SET position = :position
+            WHERE collection_uuid = :collection_uuid AND media_uuid = :media_uuid"
+                .with(params! {
+                    "position" => position,
+                    "collection_uuid" => collection_uuid.value(),
+                    "media_uuid" => media_uuid.value(),
+                })
+                .run(&mut tx)
+                .await?;
+        }
+
+        tx.commit().await?;
+
+        debug!("reordered collection media");
+
+        Ok(())
+    }
+
     #[instrument(skip(self))]
     async fn search_collections(
         &self,
@@ -1068,23 +2995,26 @@ impl DbBackend for MariaDBBackend {
             filter.format_mariadb("collections.name, collections.note, collections.tags");
 
         // for a given uid and filter, find all collections owned by groups that contain that uid
-        let mut query = r"
+        let mut query = format!(
+            r"
             SELECT
                 collection_uuid
             FROM
                 collections
             WHERE
-                INSTR(:gid, gid) > 0"
-            .to_owned();
+                {GID_CHECK}"
+        );
 
         query.push_str(&sql);
 
         let result = query
-            .with(params! {
-                "gid" => fold_set(gid)?,
-                "filter" => filter,
-            })
-            .run(self.pool.get_conn().await?)
+            .with(with_filter(
+                params! {
+                    "gid" => fold_set(gid)?,
+                },
+                filter,
+            ))
+            .run(self.conn().await?)
             .await?
             .collect::<Row>()
             .await?;
@@ -1109,6 +3039,8 @@ impl DbBackend for MariaDBBackend {
         gid: HashSet<String>,
         collection_uuid: CollectionUuid,
         filter: SearchFilter,
+        recursive: bool,
+        ordered: bool,
     ) -> Result<Vec<MediaUuid>> {
         debug!("searching media in collection");
 
@@ -1116,17 +3048,37 @@ impl DbBackend for MariaDBBackend {
         let _xr = self.locks.contents.read().await;
         let _cr = self.locks.collection.read().await;
 
-        let (sql, filter) = filter.format_mariadb("media.path, media.date, media.note, media.tags");
+        let (mut sql, filter) =
+            filter.format_mariadb("media.path, media.date, media.note, media.tags");
+
+        // the id breaks ties between rows added before there were positions
+        if ordered {
+            sql.push_str(" ORDER BY t3.position, t3.id");
+        }
 
         // for a given uid, filter, and collection_uuid, find all non-hidden media in that collection
         // provided that the collection is owned by a group containing the uid
-        let mut query = r"
+        //
+        // if recursive, the same goes for each of its descendants.  UNION (rather than UNION ALL)
+        // stops the walk if the tree somehow has a cycle.
+        let mut query = format!(
+            r"
+            WITH RECURSIVE tree (collection_uuid) AS (
+                SELECT collection_uuid FROM collections
+                WHERE {GID_CHECK} AND collection_uuid = :collection_uuid
+                UNION
+                SELECT collections.collection_uuid FROM collections
+                INNER JOIN tree ON collections.parent_uuid = tree.collection_uuid
+                WHERE :recursive
+            )
             SELECT
                 media.media_uuid
             FROM
                 (
                     SELECT
-                        media_uuid
+                        media_uuid,
+                        MIN(collection_contents.position) AS position,
+                        MIN(collection_contents.id) AS id
                     FROM
                         (
                             SELECT
@@ -1134,23 +3086,30 @@ impl DbBackend for MariaDBBackend {
                             FROM
                                 collections
                             WHERE
-                                INSTR(:gid, gid) > 0 AND collection_uuid = :collection_uuid
+                                {GID_CHECK} AND collection_uuid IN (SELECT collection_uuid FROM tree)
                         ) AS t2
                         INNER JOIN collection_contents ON t2.collection_uuid = collection_contents.collection_uuid
+                    GROUP BY
+                        media_uuid
                 ) AS t3
                 INNER JOIN media ON t3.media_uuid = media.media_uuid
             WHERE
-                media.hidden = FALSE".to_owned();
+                media.hidden = FALSE
+                AND media.deleted_at IS NULL"
+        );
 
         query.push_str(&sql);
 
         let result = query
-            .with(params! {
-                "gid" => fold_set(gid)?,
-                "collection_uuid" => collection_uuid.value(),
-                "filter" => filter,
-            })
-            .run(self.pool.get_conn().await?)
+            .with(with_filter(
+                params! {
+                    "gid" => fold_set(gid)?,
+                    "collection_uuid" => collection_uuid.value(),
+                    "recursive" => recursive,
+                },
+                filter,
+            ))
+            .run(self.conn().await?)
             .await?
             .collect::<Row>()
             .await?;
@@ -1177,10 +3136,13 @@ impl DbBackend for MariaDBBackend {
         let _lw = self.locks.library.write().await;
 
         let mut result = r"
-            INSERT INTO libraries (library_uuid, path, gid, count)
+            INSERT INTO libraries (library_uuid, path, name, note, uid, gid, count)
             SELECT
-                UUID_v7()
+                UUID_v7(),
                 :path,
+                :name,
+                :note,
+                :uid,
                 :gid,
                 :count
             FROM
@@ -1194,10 +3156,13 @@ impl DbBackend for MariaDBBackend {
             RETURNING library_uuid"
             .with(params! {
                 "path" => library.path.clone(),
+                "name" => library.name,
+                "note" => library.note,
+                "uid" => library.uid,
                 "gid" => library.gid,
                 "count" => library.count,
             })
-            .run(self.pool.get_conn().await?)
+            .run(self.conn().await?)
             .await?
             .collect::<Row>()
             .await?;
@@ -1221,11 +3186,11 @@ impl DbBackend for MariaDBBackend {
         let _lr = self.locks.library.read().await;
 
         let mut result = r"
-            SELECT path, uid, gid, count FROM libraries WHERE library_uuid = :library_uuid"
+            SELECT path, name, note, uid, gid, count FROM libraries WHERE library_uuid = :library_uuid"
             .with(params! {
                 "library_uuid" => library_uuid.value(),
             })
-            .run(self.pool.get_conn().await?)
+            .run(self.conn().await?)
             .await?
             .collect::<Row>()
             .await?;
@@ -1235,15 +3200,17 @@ impl DbBackend for MariaDBBackend {
             None => return Ok(None),
         };
 
-        let data = from_row_opt::<(String, String, String, i64)>(row)?;
+        let data = from_row_opt::<(String, String, String, String, String, i64)>(row)?;
 
         debug!("found library details");
 
         Ok(Some(Library {
             path: data.0,
-            uid: data.1,
-            gid: data.2,
-            count: data.3,
+            name: data.1,
+            note: data.2,
+            uid: data.3,
+            gid: data.4,
+            count: data.5,
         }))
     }
 
@@ -1255,7 +3222,7 @@ impl DbBackend for MariaDBBackend {
 
         let result = r"
             SELECT library_uuid FROM libraries"
-            .run(self.pool.get_conn().await?)
+            .run(self.conn().await?)
             .await?
             .collect::<Row>()
             .await?;
@@ -1280,6 +3247,30 @@ impl DbBackend for MariaDBBackend {
 
         let _lw = self.locks.library.write().await;
 
+        let mut tx = self.transaction().await?;
+
+        if let Some(val) = update.name {
+            r"
+            UPDATE libraries SET name = :name WHERE library_uuid = :library_uuid"
+                .with(params! {
+                    "name" => val,
+                    "library_uuid" => library_uuid.value(),
+                })
+                .run(&mut tx)
+                .await?;
+        }
+
+        if let Some(val) = update.note {
+            r"
+            UPDATE libraries SET note = :note WHERE library_uuid = :library_uuid"
+                .with(params! {
+                    "note" => val,
+                    "library_uuid" => library_uuid.value(),
+                })
+                .run(&mut tx)
+                .await?;
+        }
+
         if let Some(val) = update.count {
             r"
             UPDATE libraries SET count = :count WHERE library_uuid = :library_uuid"
@@ -1287,10 +3278,12 @@ impl DbBackend for MariaDBBackend {
                     "count" => val,
                     "library_uuid" => library_uuid.value(),
                 })
-                .run(self.pool.get_conn().await?)
+                .run(&mut tx)
                 .await?;
         }
 
+        tx.commit().await?;
+
         debug!("updated library");
 
         Ok(())
@@ -1306,18 +3299,30 @@ impl DbBackend for MariaDBBackend {
 
         let _lr = self.locks.library.read().await;
 
-        let result = r"
+        // normalized the same way as the substring filters
+        let mut query = format!(
+            r"
             SELECT
                 library_uuid
             FROM
                 libraries
             WHERE
-                INSTR(:gid, gid) > 0 AND path LIKE :filter"
+                {GID_CHECK} AND "
+        );
+
+        query.push_str(&format!(
+            "({} OR {} OR {})",
+            mariadb_contains("path", ":filter"),
+            mariadb_contains("name", ":filter"),
+            mariadb_contains("note", ":filter"),
+        ));
+
+        let result = query
             .with(params! {
                 "gid" => fold_set(gid)?,
-                "filter" => format!("%{}%", filter),
+                "filter" => mariadb_contains_pattern(&filter),
             })
-            .run(self.pool.get_conn().await?)
+            .run(self.conn().await?)
             .await?
             .collect::<Row>()
             .await?;
@@ -1336,6 +3341,87 @@ impl DbBackend for MariaDBBackend {
         Ok(data)
     }
 
+    #[instrument(skip(self))]
+    async fn search_libraries_detailed(
+        &self,
+        gid: HashSet<String>,
+        filter: String,
+    ) -> Result<Vec<LibrarySummary>> {
+        debug!("searching libraries with details");
+
+        let _mr = self.locks.media.read().await;
+        let _lr = self.locks.library.read().await;
+
+        // the same filter as search_libraries(), and the media count matches
+        // search_media_in_library() with no filter or hidden state
+        let mut query = format!(
+            r"
+            SELECT
+                library_uuid,
+                path,
+                name,
+                note,
+                uid,
+                gid,
+                count,
+                (
+                    SELECT
+                        COUNT(*)
+                    FROM
+                        media
+                    WHERE
+                        media.library_uuid = libraries.library_uuid
+                        AND media.deleted_at IS NULL
+                )
+            FROM
+                libraries
+            WHERE
+                {GID_CHECK} AND "
+        );
+
+        query.push_str(&format!(
+            "({} OR {} OR {})",
+            mariadb_contains("path", ":filter"),
+            mariadb_contains("name", ":filter"),
+            mariadb_contains("note", ":filter"),
+        ));
+
+        let result = query
+            .with(params! {
+                "gid" => fold_set(gid)?,
+                "filter" => mariadb_contains_pattern(&filter),
+            })
+            .run(self.conn().await?)
+            .await?
+            .collect::<Row>()
+            .await?;
+
+        let data = result
+            .into_iter()
+            .map(|row| {
+                let data =
+                    from_row_opt::<(Uuid, String, String, String, String, String, i64, u64)>(row)?;
+
+                Ok(LibrarySummary {
+                    library_uuid: LibraryUuid::from_value(self, data.0),
+                    library: Library {
+                        path: data.1,
+                        name: data.2,
+                        note: data.3,
+                        uid: data.4,
+                        gid: data.5,
+                        count: data.6,
+                    },
+                    media: data.7,
+                })
+            })
+            .collect::<Result<Vec<LibrarySummary>, anyhow::Error>>()?;
+
+        debug!({ count = data.len() }, "found libraries");
+
+        Ok(data)
+    }
+
     #[instrument(skip(self))]
     async fn search_media_in_library(
         &self,
@@ -1361,7 +3447,8 @@ impl DbBackend for MariaDBBackend {
         // provided that the library is owned by a group containing the uid
         //
         // note that this is the only search query where media with "hidden = true" can be found
-        let mut query = r"
+        let mut query = format!(
+            r"
             SELECT
                 media.media_uuid
             FROM
@@ -1371,25 +3458,27 @@ impl DbBackend for MariaDBBackend {
                     FROM
                         libraries
                     WHERE
-                        INSTR(:gid, gid) > 0 AND library_uuid = :library_uuid
+                        {GID_CHECK} AND library_uuid = :library_uuid
                 ) AS t1
                 INNER JOIN media ON t1.library_uuid = media.library_uuid
             WHERE
-            "
-        .to_owned();
+                media.deleted_at IS NULL AND "
+        );
 
         query.push_str(&hidden_sql);
 
         query.push_str(&filter_sql);
 
         let result = query
-            .with(params! {
-                "gid" => fold_set(gid)?,
-                "library_uuid" => library_uuid.value(),
-                "hidden" => hidden,
-                "filter" => filter,
-            })
-            .run(self.pool.get_conn().await?)
+            .with(with_filter(
+                params! {
+                    "gid" => fold_set(gid)?,
+                    "library_uuid" => library_uuid.value(),
+                    "hidden" => hidden,
+                },
+                filter,
+            ))
+            .run(self.conn().await?)
             .await?
             .collect::<Row>()
             .await?;
@@ -1407,4 +3496,77 @@ impl DbBackend for MariaDBBackend {
 
         Ok(data)
     }
+
+    #[instrument(skip(self))]
+    async fn count_hidden_in_library(
+        &self,
+        gid: HashSet<String>,
+        library_uuid: LibraryUuid,
+    ) -> Result<u64> {
+        debug!("counting hidden media in library");
+
+        let _mr = self.locks.media.read().await;
+        let _lr = self.locks.library.read().await;
+
+        // the same access rules as search_media_in_library()
+        let query = format!(
+            r"
+            SELECT
+                COUNT(*)
+            FROM
+                (
+                    SELECT
+                        library_uuid
+                    FROM
+                        libraries
+                    WHERE
+                        {GID_CHECK} AND library_uuid = :library_uuid
+                ) AS t1
+                INNER JOIN media ON t1.library_uuid = media.library_uuid
+            WHERE
+                media.hidden = TRUE
+                AND media.deleted_at IS NULL"
+        );
+
+        let count: Option<u64> = query
+            .with(params! {
+                "gid" => fold_set(gid)?,
+                "library_uuid" => library_uuid.value(),
+            })
+            .first(self.conn().await?)
+            .await?;
+
+        let count = count.unwrap_or(0);
+
+        debug!({ count }, "counted hidden media in library");
+
+        Ok(count)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use mysql_async::{DriverError, ServerError};
+
+    use super::*;
+
+    fn server_error(code: u16) -> mysql_async::Error {
+        mysql_async::Error::Server(ServerError {
+            code,
+            message: String::new(),
+            state: String::from("42000"),
+        })
+    }
+
+    #[test]
+    fn only_missing_function_is_missing_function() {
+        assert!(is_missing_function(&server_error(ER_SP_DOES_NOT_EXIST)));
+
+        // access denied and unknown column
+        assert!(!is_missing_function(&server_error(1045)));
+        assert!(!is_missing_function(&server_error(1054)));
+        assert!(!is_missing_function(&mysql_async::Error::Driver(
+            DriverError::PoolDisconnected
+        )));
+    }
 }
@@ -3,14 +3,25 @@ use std::sync::Arc;
 
 use anyhow::Result;
 use async_trait::async_trait;
+use chrono::{Months, NaiveDate, NaiveTime};
+use uuid::Uuid;
 
 use crate::config::ESConfig;
 use api::{
+    UuidSource,
+    activity::{Activity, ActivityEvent},
+    auth::{ApiKey, ApiKeyUuid},
     collection::{Collection, CollectionUpdate, CollectionUuid},
     comment::{Comment, CommentUuid},
-    library::{Library, LibraryUpdate, LibraryUuid},
-    media::{Media, MediaUpdate, MediaUuid},
+    fold_set,
+    library::{Library, LibrarySummary, LibraryUpdate, LibraryUuid},
+    media::{AccessSource, Media, MediaAccess, MediaUpdate, MediaUuid},
     search::SearchFilter,
+    share::{ShareLink, ShareLinkUuid, ShareTarget},
+    sort::SortOrder,
+    stats::{DateBucket, DateGranularity, Stats},
+    task::{Task, TaskLibrary, TaskUid},
+    unfold_set,
 };
 
 pub mod mariadb;
@@ -19,6 +30,11 @@ pub use mariadb::MariaDBBackend;
 pub mod postgres;
 pub use postgres::PostgresBackend;
 
+#[cfg(feature = "sqlite")]
+pub mod sqlite;
+#[cfg(feature = "sqlite")]
+pub use sqlite::SqliteBackend;
+
 // these are the database RPC calls that any backend server must be able to process
 #[async_trait]
 pub trait DbBackend: Send + Sync + 'static {
@@ -26,12 +42,22 @@ pub trait DbBackend: Send + Sync + 'static {
     where
         Self: Sized;
 
+    // a trivial query, to check that the database is reachable
+    async fn ping(&self) -> Result<()>;
+
     // get this from checking all collections that contain the media + owning group of the library
     async fn media_access_groups(&self, media_uuid: MediaUuid) -> Result<HashSet<String>>;
 
+    // the same groups, along with the library or collection that each one comes from
+    async fn media_access(&self, media_uuid: MediaUuid) -> Result<Vec<MediaAccess>>;
+
     // media functions
     async fn add_media(&self, media: Media) -> Result<MediaUuid>;
 
+    // adds all of the media in a single transaction, skipping any whose path is already
+    // in its library.  the results line up with the input, with None for skipped media.
+    async fn add_media_batch(&self, media: Vec<Media>) -> Result<Vec<Option<MediaUuid>>>;
+
     async fn get_media(
         &self,
         media_uuid: MediaUuid,
@@ -41,6 +67,8 @@ pub trait DbBackend: Send + Sync + 'static {
 
     async fn get_media_by_path(&self, path: String) -> Result<Option<MediaByPath>>;
 
+    // the chash includes the algorithm prefix (see media::HashAlgorithm), so an exact
+    // match only ever finds media hashed the same way
     async fn get_media_by_chash(
         &self,
         library_uuid: LibraryUuid,
@@ -49,27 +77,109 @@ pub trait DbBackend: Send + Sync + 'static {
 
     async fn update_media(&self, media_uuid: MediaUuid, update: MediaUpdate) -> Result<()>;
 
+    // apply the same update to every media in one statement, returning the media that
+    // were actually found
+    async fn batch_update_media(
+        &self,
+        media_uuids: Vec<MediaUuid>,
+        update: MediaUpdate,
+    ) -> Result<Vec<MediaUuid>>;
+
     async fn replace_media_path(
         &self,
         media_uuid: MediaUuid,
         path: String,
         hash: String,
         mtime: u64,
+        size: u64,
     ) -> Result<()>;
 
+    // reassign media to another library, along with its new path if the file was moved
+    async fn move_media(
+        &self,
+        media_uuid: MediaUuid,
+        library_uuid: LibraryUuid,
+        path: Option<String>,
+    ) -> Result<()>;
+
+    // returns the requested page of results along with the total number of matches
     async fn search_media(
         &self,
         gid: HashSet<String>,
         filter: SearchFilter,
-    ) -> Result<Vec<MediaUuid>>;
+        sort: SortOrder,
+        limit: Option<u64>,
+        offset: Option<u64>,
+    ) -> Result<(Vec<MediaUuid>, u64)>;
 
+    // the media within distance of media_uuid (which includes itself), paired with their
+    // phash distance and closest first.  media without a phash never match.
     async fn similar_media(
         &self,
         gid: HashSet<String>,
         media_uuid: MediaUuid,
         distance: i64,
+    ) -> Result<Vec<(MediaUuid, i64)>>;
+
+    // as above, but for a phash that need not belong to any media
+    async fn similar_phash(
+        &self,
+        gid: HashSet<String>,
+        phash: String,
+        distance: i64,
+    ) -> Result<Vec<(MediaUuid, i64)>>;
+
+    // a uniform sample of up to count of the media that search_media() would find
+    async fn random_media(
+        &self,
+        gid: HashSet<String>,
+        filter: SearchFilter,
+        count: u64,
     ) -> Result<Vec<MediaUuid>>;
 
+    // counts over the media that search_media() would find, along with the collections and
+    // libraries owned by one of the groups
+    async fn stats(&self, gid: HashSet<String>) -> Result<Stats>;
+
+    // the number of media that search_media() would find in each day, month, or year,
+    // oldest first.  media without a parseable date are left out.
+    async fn media_date_histogram(
+        &self,
+        gid: HashSet<String>,
+        granularity: DateGranularity,
+    ) -> Result<Vec<DateBucket>>;
+
+    // the newest media, collections, and comments, under the same access rules as
+    // search_media() and search_collections()
+    async fn recent_activity(&self, gid: HashSet<String>, limit: u64) -> Result<Vec<Activity>>;
+
+    // trash functions
+    //
+    // soft-deleted media keep their records, collections, and comments, but are left out of
+    // every search (and of collection access) until they are restored
+    async fn soft_delete_media(&self, media_uuid: MediaUuid) -> Result<()>;
+
+    async fn restore_media(&self, media_uuid: MediaUuid) -> Result<()>;
+
+    // only removes media that are already in the trash, along with their comments and
+    // collection entries.  the file itself is untouched, so a later scan will add it back
+    // unless it is also removed from the library.
+    async fn purge_media(&self, media_uuid: MediaUuid) -> Result<()>;
+
+    // soft-deleted media in libraries owned by one of the groups, most recently deleted first
+    async fn search_trash(
+        &self,
+        gid: HashSet<String>,
+        filter: SearchFilter,
+    ) -> Result<Vec<MediaUuid>>;
+
+    // tag functions
+    //
+    // renames a tag on every media in a library owned by one of the groups and on every
+    // collection owned by one of them, merging into the new tag if it is already present.
+    // returns the number of media and collections that changed.
+    async fn rename_tag(&self, gid: HashSet<String>, from: String, to: String) -> Result<u64>;
+
     // comment functions
     async fn add_comment(&self, comment: Comment) -> Result<CommentUuid>;
 
@@ -81,6 +191,49 @@ pub trait DbBackend: Send + Sync + 'static {
 
     async fn update_comment(&self, comment_uuid: CommentUuid, update: Option<String>) -> Result<()>;
 
+    // api key functions
+    //
+    // keys are only ever handled as hashes here, see auth::apikey
+    async fn add_api_key(&self, uid: String, name: String, key_hash: String) -> Result<ApiKeyUuid>;
+
+    async fn get_api_key_uid(&self, key_hash: String) -> Result<Option<String>>;
+
+    async fn get_api_keys(&self, uid: String) -> Result<Vec<ApiKey>>;
+
+    // only deletes the key if it belongs to the uid
+    async fn delete_api_key(&self, uid: String, key_uuid: ApiKeyUuid) -> Result<()>;
+
+    // share link functions
+    //
+    // like api keys, links are only ever looked up by the hash of their token.  expired links
+    // are still returned, so that the caller can tell them apart from unknown ones.
+    async fn add_share_link(
+        &self,
+        uid: String,
+        target: ShareTarget,
+        token_hash: String,
+        expires_at: u64,
+    ) -> Result<ShareLinkUuid>;
+
+    async fn get_share_link(&self, token_hash: String) -> Result<Option<ShareLink>>;
+
+    async fn get_share_links(&self, uid: String) -> Result<Vec<ShareLink>>;
+
+    // only deletes the link if it belongs to the uid
+    async fn delete_share_link(&self, uid: String, share_uuid: ShareLinkUuid) -> Result<()>;
+
+    // the media visible through a share link, i.e. the media itself or the contents of the
+    // collection, leaving out anything that is hidden or soft-deleted
+    async fn get_shared_media(&self, target: ShareTarget) -> Result<Vec<MediaUuid>>;
+
+    // task functions
+    //
+    // only finished tasks are recorded here, since running tasks belong to the task service
+    async fn add_task(&self, library: TaskLibrary, task: Task) -> Result<()>;
+
+    // the most recent tasks for the library, newest first
+    async fn get_tasks(&self, library: TaskLibrary, limit: u64) -> Result<Vec<Task>>;
+
     // collection functions
     async fn add_collection(&self, collection: Collection) -> Result<CollectionUuid>;
 
@@ -96,6 +249,28 @@ pub trait DbBackend: Send + Sync + 'static {
         update: CollectionUpdate,
     ) -> Result<()>;
 
+    // None clears the cover, leaving get_collection() to fall back to the most recent media
+    async fn set_collection_cover(
+        &self,
+        collection_uuid: CollectionUuid,
+        cover: Option<MediaUuid>,
+    ) -> Result<()>;
+
+    // None makes the collection top-level, and this fails if the new parent is the
+    // collection itself or one of its descendants
+    async fn set_collection_parent(
+        &self,
+        collection_uuid: CollectionUuid,
+        parent_uuid: Option<CollectionUuid>,
+    ) -> Result<()>;
+
+    // the direct children of the collection that are owned by one of the groups
+    async fn get_collection_children(
+        &self,
+        gid: HashSet<String>,
+        collection_uuid: CollectionUuid,
+    ) -> Result<Vec<CollectionUuid>>;
+
     async fn add_media_to_collection(
         &self,
         media_uuid: MediaUuid,
@@ -108,17 +283,30 @@ pub trait DbBackend: Send + Sync + 'static {
         collection_uuid: CollectionUuid,
     ) -> Result<()>;
 
+    // the listed media move to the front of the collection in that order, and the rest keep
+    // their order behind them.  media that aren't in the collection are ignored.
+    async fn reorder_collection_media(
+        &self,
+        collection_uuid: CollectionUuid,
+        media_uuids: Vec<MediaUuid>,
+    ) -> Result<()>;
+
     async fn search_collections(
         &self,
         gid: HashSet<String>,
         filter: SearchFilter,
     ) -> Result<Vec<CollectionUuid>>;
 
+    // if recursive, this includes the media in every descendant owned by one of the groups,
+    // even if the collections in between are not.  if ordered, the media come back in the
+    // order set by reorder_collection_media(), with newer additions last.
     async fn search_media_in_collection(
         &self,
         gid: HashSet<String>,
         collection_uuid: CollectionUuid,
         filter: SearchFilter,
+        recursive: bool,
+        ordered: bool,
     ) -> Result<Vec<MediaUuid>>;
 
     // library functions
@@ -136,6 +324,13 @@ pub trait DbBackend: Send + Sync + 'static {
         filter: String,
     ) -> Result<Vec<LibraryUuid>>;
 
+    // the same libraries as search_libraries(), with their details and media counts
+    async fn search_libraries_detailed(
+        &self,
+        gid: HashSet<String>,
+        filter: String,
+    ) -> Result<Vec<LibrarySummary>>;
+
     async fn search_media_in_library(
         &self,
         gid: HashSet<String>,
@@ -143,6 +338,14 @@ pub trait DbBackend: Send + Sync + 'static {
         hidden: Option<bool>,
         filter: SearchFilter,
     ) -> Result<Vec<MediaUuid>>;
+
+    // the number of hidden media (outside of the trash) in a library owned by one of the
+    // groups, so that owners can tell whether there is anything behind the hidden toggle
+    async fn count_hidden_in_library(
+        &self,
+        gid: HashSet<String>,
+        library_uuid: LibraryUuid,
+    ) -> Result<u64>;
 }
 
 // structs needed to do media updates
@@ -151,6 +354,7 @@ pub struct MediaByPath {
     pub media_uuid: MediaUuid,
     pub hash: String,
     pub mtime: u64,
+    pub size: u64,
 }
 
 #[derive(Debug)]
@@ -159,3 +363,349 @@ pub struct MediaByCHash {
     pub path: String,
     pub mtime: u64,
 }
+
+// the tags that result from applying a MediaUpdate to the stored (folded) tag set
+//
+// backends that can't express the set operations in sql call this while holding whatever
+// lock keeps the row from changing between the read and the write
+pub(crate) fn updated_tags(folded: &str, update: &MediaUpdate) -> Result<String> {
+    let mut tags = match &update.tags {
+        Some(tags) => tags.clone(),
+        None => unfold_set(folded),
+    };
+
+    tags.extend(update.tags_add.iter().cloned());
+    tags.retain(|tag| !update.tags_remove.contains(tag));
+
+    fold_set(tags)
+}
+
+// the folded tags with one tag renamed, or None if the tag isn't present
+//
+// the set is split and refolded rather than edited as a string, so that tags which merely
+// contain the old one are left alone and the separators stay intact
+pub(crate) fn renamed_tags(folded: &str, from: &str, to: &str) -> Result<Option<String>> {
+    let mut tags = unfold_set(folded);
+
+    if !tags.remove(from) {
+        return Ok(None);
+    }
+
+    tags.insert(to.to_owned());
+
+    Ok(Some(fold_set(tags)?))
+}
+
+// a task as stored in the tasks table
+//
+// the library and uid columns are NULL for the system, and the enums are stored by name
+#[derive(Debug)]
+pub(crate) struct TaskRow {
+    pub library_uuid: Option<LibraryUuid>,
+    pub task_type: String,
+    pub uid: Option<String>,
+    pub status: String,
+    pub warnings: Option<i64>,
+    pub start: u64,
+    pub end: u64,
+    pub summary: Option<String>,
+}
+
+impl TaskRow {
+    pub(crate) fn new(library: TaskLibrary, task: Task) -> Self {
+        TaskRow {
+            library_uuid: match library {
+                TaskLibrary::User { library_uuid } => Some(library_uuid),
+                TaskLibrary::System => None,
+            },
+            task_type: task.task_type.to_string(),
+            uid: match task.uid {
+                TaskUid::User { uid } => Some(uid),
+                TaskUid::System => None,
+            },
+            status: task.status.to_string(),
+            warnings: task.warnings,
+            start: task.start,
+            // a task only reaches the database once it finishes
+            end: task.end.unwrap_or(task.start),
+            summary: task.summary,
+        }
+    }
+
+    pub(crate) fn into_task(self) -> Result<Task> {
+        Ok(Task {
+            task_type: self.task_type.parse()?,
+            uid: match self.uid {
+                Some(uid) => TaskUid::User { uid },
+                None => TaskUid::System,
+            },
+            status: self.status.parse()?,
+            warnings: self.warnings,
+            start: self.start,
+            end: Some(self.end),
+            progress: None,
+            summary: self.summary,
+        })
+    }
+}
+
+// a row from the recent activity union
+//
+// every uuid is a v7, which leads with its creation time, so the feed can be sorted and
+// dated by uuid alone instead of needing a timestamp column on each table.  the media
+// column is only set for comments.
+#[derive(Debug)]
+pub(crate) struct ActivityRow {
+    pub kind: String,
+    pub uuid: Uuid,
+    pub media_uuid: Option<Uuid>,
+}
+
+impl ActivityRow {
+    pub(crate) fn into_activity<S: UuidSource>(self, src: &S) -> Result<Activity> {
+        let (time, _) = self
+            .uuid
+            .get_timestamp()
+            .ok_or_else(|| anyhow::Error::msg("internal error: activity uuid has no timestamp"))?
+            .to_unix();
+
+        let event = match self.kind.as_str() {
+            "media" => ActivityEvent::MediaAdded {
+                media_uuid: MediaUuid::from_value(src, self.uuid),
+            },
+            "collection" => ActivityEvent::CollectionCreated {
+                collection_uuid: CollectionUuid::from_value(src, self.uuid),
+            },
+            "comment" => ActivityEvent::CommentAdded {
+                comment_uuid: CommentUuid::from_value(src, self.uuid),
+                media_uuid: MediaUuid::from_value(
+                    src,
+                    self.media_uuid.ok_or_else(|| {
+                        anyhow::Error::msg("internal error: comment activity has no media")
+                    })?,
+                ),
+            },
+            other => {
+                return Err(anyhow::Error::msg(format!(
+                    "internal error: unknown activity kind {other}"
+                )));
+            }
+        };
+
+        Ok(Activity { time, event })
+    }
+}
+
+// a row from the share_links table
+//
+// the target is stored as a pair of nullable columns, exactly one of which is set, so that
+// each can be joined against its own table
+#[derive(Debug)]
+pub(crate) struct ShareLinkRow {
+    pub share_uuid: Uuid,
+    pub uid: String,
+    pub media_uuid: Option<Uuid>,
+    pub collection_uuid: Option<Uuid>,
+    pub created: u64,
+    pub expires_at: u64,
+}
+
+impl ShareLinkRow {
+    pub(crate) fn into_share_link<S: UuidSource>(self, src: &S) -> Result<ShareLink> {
+        let target = match (self.media_uuid, self.collection_uuid) {
+            (Some(media_uuid), None) => ShareTarget::Media(MediaUuid::from_value(src, media_uuid)),
+            (None, Some(collection_uuid)) => {
+                ShareTarget::Collection(CollectionUuid::from_value(src, collection_uuid))
+            }
+            _ => {
+                return Err(anyhow::Error::msg(
+                    "internal error: share link must have exactly one target",
+                ));
+            }
+        };
+
+        Ok(ShareLink {
+            share_uuid: ShareLinkUuid::from_value(src, self.share_uuid),
+            uid: self.uid,
+            target,
+            created: self.created,
+            expires_at: self.expires_at,
+        })
+    }
+}
+
+// the (media_uuid, collection_uuid) columns for a share target
+pub(crate) fn share_target_columns(target: ShareTarget) -> (Option<Uuid>, Option<Uuid>) {
+    match target {
+        ShareTarget::Media(media_uuid) => (Some(media_uuid.value()), None),
+        ShareTarget::Collection(collection_uuid) => (None, Some(collection_uuid.value())),
+    }
+}
+
+// the DateBucket for a date prefix grouped by media_date_histogram(), or None if it isn't
+// a real day, month, or year (e.g. "2024-13")
+pub(crate) fn date_bucket(
+    date: String,
+    granularity: DateGranularity,
+    count: u64,
+) -> Option<DateBucket> {
+    let padded = match granularity {
+        DateGranularity::Day => date.clone(),
+        DateGranularity::Month => format!("{date}-01"),
+        DateGranularity::Year => format!("{date}-01-01"),
+    };
+
+    let first = NaiveDate::parse_from_str(&padded, "%Y-%m-%d").ok()?;
+
+    let next = match granularity {
+        DateGranularity::Day => first.succ_opt()?,
+        DateGranularity::Month => first.checked_add_months(Months::new(1))?,
+        DateGranularity::Year => first.checked_add_months(Months::new(12))?,
+    };
+
+    // like the sqlite and postgres date filters, this takes the media dates to be utc
+    Some(DateBucket {
+        date,
+        start: first.and_time(NaiveTime::MIN).and_utc().timestamp(),
+        end: next.and_time(NaiveTime::MIN).and_utc().timestamp() - 1,
+        count,
+    })
+}
+
+// hamming distance between two hex-encoded perceptual hashes, matching the BIG_HAM()
+// function used by the mariadb backend
+//
+// returns None if either hash contains a non-hex character
+pub fn hamming_distance(a: &str, b: &str) -> Option<i64> {
+    a.chars().zip(b.chars()).try_fold(0, |acc, (x, y)| {
+        Some(acc + (x.to_digit(16)? ^ y.to_digit(16)?).count_ones() as i64)
+    })
+}
+
+// the rows of media_access() are a gid and exactly one of the two uuids
+pub fn access_source(
+    collection_uuid: Option<CollectionUuid>,
+    library_uuid: Option<LibraryUuid>,
+) -> Result<AccessSource> {
+    match (collection_uuid, library_uuid) {
+        (Some(collection_uuid), None) => Ok(AccessSource::Collection { collection_uuid }),
+        (None, Some(library_uuid)) => Ok(AccessSource::Library { library_uuid }),
+        _ => Err(anyhow::Error::msg(
+            "internal error: media access row has no single source",
+        )),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn hamming_distance_counts_bits() {
+        assert_eq!(hamming_distance("", ""), Some(0));
+        assert_eq!(hamming_distance("00ff", "00ff"), Some(0));
+        assert_eq!(hamming_distance("0", "f"), Some(4));
+        assert_eq!(hamming_distance("0f", "f0"), Some(8));
+        assert_eq!(hamming_distance("8000", "0001"), Some(2));
+    }
+
+    #[test]
+    fn hamming_distance_ignores_case() {
+        assert_eq!(hamming_distance("ABCDEF", "abcdef"), Some(0));
+    }
+
+    #[test]
+    fn hamming_distance_rejects_non_hex() {
+        assert_eq!(hamming_distance("0g", "00"), None);
+        assert_eq!(hamming_distance("00", "0-"), None);
+    }
+
+    fn set(tags: &[&str]) -> HashSet<String> {
+        tags.iter().map(|t| t.to_string()).collect()
+    }
+
+    #[test]
+    fn updated_tags_adds_and_removes() {
+        let update = MediaUpdate {
+            tags_add: set(&["dog", "park"]),
+            tags_remove: set(&["cat", "missing"]),
+            ..Default::default()
+        };
+
+        assert_eq!(
+            unfold_set(&updated_tags("cat|beach", &update).unwrap()),
+            set(&["beach", "dog", "park"])
+        );
+    }
+
+    #[test]
+    fn updated_tags_replaces_first() {
+        // the edits apply on top of the replacement, and removals win over additions
+        let update = MediaUpdate {
+            tags: Some(set(&["a", "b"])),
+            tags_add: set(&["c", "d"]),
+            tags_remove: set(&["b", "d"]),
+            ..Default::default()
+        };
+
+        assert_eq!(
+            unfold_set(&updated_tags("x|y", &update).unwrap()),
+            set(&["a", "c"])
+        );
+    }
+
+    #[test]
+    fn updated_tags_without_edits_keeps_tags() {
+        assert_eq!(
+            unfold_set(&updated_tags("x|y", &MediaUpdate::default()).unwrap()),
+            set(&["x", "y"])
+        );
+        assert_eq!(updated_tags("", &MediaUpdate::default()).unwrap(), "");
+    }
+
+    #[test]
+    fn updated_tags_rejects_separator() {
+        let update = MediaUpdate {
+            tags_add: set(&["a|b"]),
+            ..Default::default()
+        };
+
+        assert!(updated_tags("x", &update).is_err());
+    }
+
+    #[test]
+    fn renamed_tags_renames() {
+        assert_eq!(
+            unfold_set(&renamed_tags("cat|beach", "cat", "dog").unwrap().unwrap()),
+            set(&["dog", "beach"])
+        );
+    }
+
+    #[test]
+    fn renamed_tags_merges() {
+        assert_eq!(
+            unfold_set(&renamed_tags("cat|dog", "cat", "dog").unwrap().unwrap()),
+            set(&["dog"])
+        );
+    }
+
+    #[test]
+    fn renamed_tags_matches_whole_tags() {
+        // neither a longer tag nor a partial match counts as the old tag
+        assert_eq!(renamed_tags("catalog|bobcat", "cat", "dog").unwrap(), None);
+        assert_eq!(renamed_tags("", "cat", "dog").unwrap(), None);
+
+        assert_eq!(
+            unfold_set(&renamed_tags("catalog|cat", "cat", "dog").unwrap().unwrap()),
+            set(&["catalog", "dog"])
+        );
+    }
+
+    #[test]
+    fn renamed_tags_rejects_separator() {
+        assert!(renamed_tags("cat", "cat", "dog|bird").is_err());
+
+        // a stray separator in the old name can never match a whole tag
+        assert_eq!(renamed_tags("cat|dog", "cat|dog", "bird").unwrap(), None);
+    }
+}
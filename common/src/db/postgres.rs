@@ -16,15 +16,24 @@ use url::Url;
 
 use crate::{
     config::ESConfig,
-    db::{DbBackend, MediaByCHash, MediaByPath},
+    db::{
+        ActivityRow, DbBackend, MediaByCHash, MediaByPath, ShareLinkRow, TaskRow, access_source,
+        date_bucket, share_target_columns,
+    },
 };
 use api::{
     UuidSource,
+    activity::Activity,
+    auth::{ApiKey, ApiKeyUuid},
     collection::{Collection, CollectionUpdate, CollectionUuid},
     comment::{Comment, CommentUuid},
-    library::{Library, LibraryUpdate, LibraryUuid},
-    media::{Media, MediaUpdate, MediaUuid},
+    library::{Library, LibrarySummary, LibraryUpdate, LibraryUuid},
+    media::{Media, MediaAccess, MediaUpdate, MediaUuid},
     search::SearchFilter,
+    share::{ShareLink, ShareLinkUuid, ShareTarget},
+    sort::SortOrder,
+    stats::{DateBucket, DateGranularity, Stats},
+    task::{Task, TaskLibrary},
 };
 
 fn set_to_hstore(set: HashSet<String>) -> HashMap<String, Option<String>> {
@@ -35,6 +44,18 @@ fn hstore_to_set(hstore: HashMap<String, Option<String>>) -> HashSet<String> {
     hstore.into_keys().collect()
 }
 
+// expects the columns that ShareLinkRow lists, by name
+fn share_link_row(row: &tokio_postgres::Row) -> Result<ShareLinkRow> {
+    Ok(ShareLinkRow {
+        share_uuid: row.try_get("share_uuid")?,
+        uid: row.try_get("uid")?,
+        media_uuid: row.try_get("media_uuid")?,
+        collection_uuid: row.try_get("collection_uuid")?,
+        created: row.try_get::<&str, i64>("created")? as u64,
+        expires_at: row.try_get::<&str, i64>("expires_at")? as u64,
+    })
+}
+
 #[derive(Clone, Debug, Deserialize, Serialize)]
 pub struct PostgresConfig {
     pub url: Url,
@@ -79,24 +100,53 @@ impl DbBackend for PostgresBackend {
         Ok(Self { pool })
     }
 
+    #[instrument(skip(self))]
+    async fn ping(&self) -> Result<()> {
+        let conn = self.pool.get().await?;
+
+        conn.execute("SELECT 1", &[]).await?;
+
+        Ok(())
+    }
+
     #[instrument(skip(self))]
     async fn media_access_groups(&self, media_uuid: MediaUuid) -> Result<HashSet<String>> {
         debug!("finding media access groups");
 
+        let data = self
+            .media_access(media_uuid)
+            .await?
+            .into_iter()
+            .map(|v| v.gid)
+            .collect::<HashSet<String>>();
+
+        debug!({ groups = ?data }, "found groups");
+
+        Ok(data)
+    }
+
+    #[instrument(skip(self))]
+    async fn media_access(&self, media_uuid: MediaUuid) -> Result<Vec<MediaAccess>> {
+        debug!("finding media access");
+
         let conn = self.pool.get().await?;
 
-        let statement = r"-- media_access_groups
+        let statement = r"-- media_access
         SELECT
-            gid
+            collections.gid,
+            collections.collection_uuid,
+            NULL AS library_uuid
         FROM
             collections
         INNER JOIN collection_contents ON collections.collection_uuid = collection_contents.collection_uuid
         INNER JOIN media ON collection_contents.media_uuid = media.media_uuid
         WHERE
-            media.media_uuid = $1 AND media.hidden = FALSE
-        UNION
+            media.media_uuid = $1 AND media.hidden = FALSE AND media.deleted_at IS NULL
+        UNION ALL
         SELECT
-            gid
+            libraries.gid,
+            NULL,
+            libraries.library_uuid
         FROM
             libraries
         INNER JOIN media ON libraries.library_uuid = media.library_uuid
@@ -105,12 +155,21 @@ impl DbBackend for PostgresBackend {
         ";
 
         let data = conn
-            .query_scalar::<String, str>(statement, &[&media_uuid])
+            .query(statement, &[&media_uuid])
             .await?
-            .into_iter()
-            .collect();
-
-        debug!({ groups = ?data }, "found groups");
+            .iter()
+            .map(|row| {
+                Ok(MediaAccess {
+                    gid: row.try_get("gid")?,
+                    source: access_source(
+                        row.try_get("collection_uuid")?,
+                        row.try_get("library_uuid")?,
+                    )?,
+                })
+            })
+            .collect::<Result<Vec<MediaAccess>>>()?;
+
+        debug!({ count = data.len() }, "found media access");
 
         Ok(data)
     }
@@ -123,8 +182,8 @@ impl DbBackend for PostgresBackend {
         let conn = self.pool.get().await?;
 
         let statement = r"-- add_media
-            INSERT INTO media (media_uuid, library_uuid, path, size, chash, phash, mtime, hidden, date, note, tags, media_type)
-            VALUES (uuidv7(), $1, $2, $3, $4, $5, $6, $7, $8, $9, $10, $11)
+            INSERT INTO media (media_uuid, library_uuid, path, size, chash, phash, mtime, hidden, date, note, tags, media_type, latitude, longitude, width, height, camera, orientation, capture_time, duration_secs, codec)
+            VALUES (uuidv7(), $1, $2, $3, $4, $5, $6, $7, $8, $9, $10, $11, $12, $13, $14, $15, $16, $17, $18, $19, $20)
             ON CONFLICT (library_uuid, path) DO NOTHING
             RETURNING media_uuid
         ";
@@ -144,6 +203,15 @@ impl DbBackend for PostgresBackend {
                     &media.note,
                     &set_to_hstore(media.tags),
                     &media.metadata,
+                    &media.latitude,
+                    &media.longitude,
+                    &media.width.map(|v| v as i32),
+                    &media.height.map(|v| v as i32),
+                    &media.camera,
+                    &media.orientation.map(|v| v as i32),
+                    &media.capture_time,
+                    &media.duration_secs,
+                    &media.codec,
                 ],
             )
             .await?;
@@ -153,6 +221,90 @@ impl DbBackend for PostgresBackend {
         Ok(media_uuid)
     }
 
+    #[instrument(skip_all)]
+    async fn add_media_batch(&self, media: Vec<Media>) -> Result<Vec<Option<MediaUuid>>> {
+        debug!({ count = media.len() }, "adding media batch");
+
+        let conn = self.pool.get().await?;
+
+        // a single multi-row insert, so it needs no explicit transaction.  the skipped rows
+        // are simply absent from RETURNING, so the results are matched up by path.
+        let statement = r"-- add_media_batch
+            INSERT INTO media (media_uuid, library_uuid, path, size, chash, phash, mtime, hidden, date, note, tags, media_type, latitude, longitude, width, height, camera, orientation, capture_time, duration_secs, codec)
+            SELECT uuidv7(), library_uuid, path, size, chash, phash, mtime, hidden, date, note, tags, media_type, latitude, longitude, width, height, camera, orientation, capture_time, duration_secs, codec
+            FROM UNNEST(
+                $1::uuid[], $2::text[], $3::bigint[], $4::text[], $5::text[], $6::bigint[], $7::boolean[],
+                $8::text[], $9::text[], $10::hstore[], $11::media_type[], $12::float8[], $13::float8[],
+                $14::integer[], $15::integer[], $16::text[], $17::integer[], $18::text[], $19::float8[], $20::text[]
+            ) AS batch (library_uuid, path, size, chash, phash, mtime, hidden, date, note, tags, media_type, latitude, longitude, width, height, camera, orientation, capture_time, duration_secs, codec)
+            ON CONFLICT (library_uuid, path) DO NOTHING
+            RETURNING library_uuid, path, media_uuid
+        ";
+
+        let keys: Vec<(LibraryUuid, String)> = media
+            .iter()
+            .map(|media| (media.library_uuid, media.path.clone()))
+            .collect();
+
+        let rows = conn
+            .query(
+                statement,
+                &[
+                    &media.iter().map(|v| v.library_uuid).collect::<Vec<_>>(),
+                    &media.iter().map(|v| v.path.clone()).collect::<Vec<_>>(),
+                    &media.iter().map(|v| v.size as i64).collect::<Vec<_>>(),
+                    &media.iter().map(|v| v.chash.clone()).collect::<Vec<_>>(),
+                    &media.iter().map(|v| v.phash.clone()).collect::<Vec<_>>(),
+                    &media.iter().map(|v| v.mtime as i64).collect::<Vec<_>>(),
+                    &media.iter().map(|v| v.hidden).collect::<Vec<_>>(),
+                    &media.iter().map(|v| v.date.clone()).collect::<Vec<_>>(),
+                    &media.iter().map(|v| v.note.clone()).collect::<Vec<_>>(),
+                    &media
+                        .iter()
+                        .map(|v| set_to_hstore(v.tags.clone()))
+                        .collect::<Vec<_>>(),
+                    &media.iter().map(|v| v.metadata.clone()).collect::<Vec<_>>(),
+                    &media.iter().map(|v| v.latitude).collect::<Vec<_>>(),
+                    &media.iter().map(|v| v.longitude).collect::<Vec<_>>(),
+                    &media
+                        .iter()
+                        .map(|v| v.width.map(|v| v as i32))
+                        .collect::<Vec<_>>(),
+                    &media
+                        .iter()
+                        .map(|v| v.height.map(|v| v as i32))
+                        .collect::<Vec<_>>(),
+                    &media.iter().map(|v| v.camera.clone()).collect::<Vec<_>>(),
+                    &media
+                        .iter()
+                        .map(|v| v.orientation.map(|v| v as i32))
+                        .collect::<Vec<_>>(),
+                    &media
+                        .iter()
+                        .map(|v| v.capture_time.clone())
+                        .collect::<Vec<_>>(),
+                    &media.iter().map(|v| v.duration_secs).collect::<Vec<_>>(),
+                    &media.iter().map(|v| v.codec.clone()).collect::<Vec<_>>(),
+                ],
+            )
+            .await?;
+
+        let mut added: HashMap<(LibraryUuid, String), MediaUuid> = HashMap::new();
+
+        for row in rows {
+            added.insert(
+                (row.try_get("library_uuid")?, row.try_get("path")?),
+                row.try_get("media_uuid")?,
+            );
+        }
+
+        debug!({ added = added.len() }, "added media batch");
+
+        // removing each match means that a path repeated within the batch is only
+        // credited to its first appearance
+        Ok(keys.into_iter().map(|key| added.remove(&key)).collect())
+    }
+
     #[instrument(skip(self))]
     async fn get_media(
         &self,
@@ -162,7 +314,7 @@ impl DbBackend for PostgresBackend {
         let conn = self.pool.get_owned().await?;
 
         let media_statement = r#"-- get_media
-            SELECT library_uuid, path, size, chash, phash, mtime, hidden, date, note, tags, media_type FROM media WHERE media_uuid = $1
+            SELECT library_uuid, path, size, chash, phash, mtime, hidden, date, note, tags, media_type, latitude, longitude, width, height, camera, orientation, capture_time, duration_secs, codec FROM media WHERE media_uuid = $1
         "#;
 
         let media_res = conn.query(media_statement, &[&media_uuid]).await?;
@@ -184,6 +336,21 @@ impl DbBackend for PostgresBackend {
             note: media_row.try_get("note")?,
             tags: hstore_to_set(media_row.try_get("tags")?),
             metadata: media_row.try_get("media_type")?,
+            latitude: media_row.try_get("latitude")?,
+            longitude: media_row.try_get("longitude")?,
+            width: media_row
+                .try_get::<&str, Option<i32>>("width")?
+                .map(|v| v as u32),
+            height: media_row
+                .try_get::<&str, Option<i32>>("height")?
+                .map(|v| v as u32),
+            camera: media_row.try_get("camera")?,
+            orientation: media_row
+                .try_get::<&str, Option<i32>>("orientation")?
+                .map(|v| v as u32),
+            capture_time: media_row.try_get("capture_time")?,
+            duration_secs: media_row.try_get("duration_secs")?,
+            codec: media_row.try_get("codec")?,
         };
 
         let collection_statement = r#"-- get_media
@@ -229,7 +396,7 @@ impl DbBackend for PostgresBackend {
         let conn = self.pool.get().await?;
 
         let statement = r#"-- get_media_by_path
-            SELECT media_uuid, chash, mtime FROM media WHERE path = $1
+            SELECT media_uuid, chash, mtime, size FROM media WHERE path = $1
         "#;
 
         let res = conn.query(statement, &[&path]).await?;
@@ -245,6 +412,7 @@ impl DbBackend for PostgresBackend {
             media_uuid: row.try_get("media_uuid")?,
             hash: row.try_get("chash")?,
             mtime: row.try_get::<&str, i64>("mtime")? as u64,
+            size: row.try_get::<&str, i64>("size")? as u64,
         }))
     }
 
@@ -284,12 +452,13 @@ impl DbBackend for PostgresBackend {
 
         let conn = self.pool.get().await?;
 
+        // the tag edits are plain hstore operators, so they happen atomically with the rest
         let statement = r#"-- update_media
             UPDATE media SET
                 hidden = COALESCE($1, hidden),
                 date = COALESCE($2, date),
                 note = COALESCE($3, note),
-                tags = COALESCE($4, tags)
+                tags = (COALESCE($4, tags) || $6::hstore) - $7::text[]
             WHERE media_uuid = $5
         "#;
 
@@ -301,6 +470,8 @@ impl DbBackend for PostgresBackend {
                 &update.note,
                 &update.tags.map(set_to_hstore),
                 &media_uuid,
+                &set_to_hstore(update.tags_add),
+                &update.tags_remove.into_iter().collect::<Vec<String>>(),
             ],
         )
         .await?;
@@ -310,6 +481,49 @@ impl DbBackend for PostgresBackend {
         Ok(())
     }
 
+    #[instrument(skip(self, update))]
+    async fn batch_update_media(
+        &self,
+        media_uuids: Vec<MediaUuid>,
+        update: MediaUpdate,
+    ) -> Result<Vec<MediaUuid>> {
+        debug!(
+            { count = media_uuids.len() },
+            "batch updating media details"
+        );
+
+        let conn = self.pool.get().await?;
+
+        let statement = r#"-- batch_update_media
+            UPDATE media SET
+                hidden = COALESCE($1, hidden),
+                date = COALESCE($2, date),
+                note = COALESCE($3, note),
+                tags = (COALESCE($4, tags) || $6::hstore) - $7::text[]
+            WHERE media_uuid = ANY($5)
+            RETURNING media_uuid
+        "#;
+
+        let media = conn
+            .query_scalar(
+                statement,
+                &[
+                    &update.hidden,
+                    &update.date,
+                    &update.note,
+                    &update.tags.map(set_to_hstore),
+                    &media_uuids,
+                    &set_to_hstore(update.tags_add),
+                    &update.tags_remove.into_iter().collect::<Vec<String>>(),
+                ],
+            )
+            .await?;
+
+        debug!({ count = media.len() }, "batch updated media details");
+
+        Ok(media)
+    }
+
     #[instrument(skip(self))]
     async fn replace_media_path(
         &self,
@@ -317,29 +531,59 @@ impl DbBackend for PostgresBackend {
         path: String,
         hash: String,
         mtime: u64,
+        size: u64,
     ) -> Result<()> {
         debug!("replacing media path");
 
         let conn = self.pool.get().await?;
 
         let statement = r#"-- replace_media_path
-            UPDATE media SET path = $1, chash = $2, mtime = $3 WHERE media_uuid = $4
+            UPDATE media SET path = $1, chash = $2, mtime = $3, size = $4 WHERE media_uuid = $5
         "#;
 
-        conn.query_one(statement, &[&path, &hash, &(mtime as i64), &media_uuid])
-            .await?;
+        conn.query_one(
+            statement,
+            &[&path, &hash, &(mtime as i64), &(size as i64), &media_uuid],
+        )
+        .await?;
 
         debug!("replaced media path");
 
         Ok(())
     }
 
+    #[instrument(skip(self))]
+    async fn move_media(
+        &self,
+        media_uuid: MediaUuid,
+        library_uuid: LibraryUuid,
+        path: Option<String>,
+    ) -> Result<()> {
+        debug!("moving media to library");
+
+        let conn = self.pool.get().await?;
+
+        let statement = r#"-- move_media
+            UPDATE media SET library_uuid = $1, path = COALESCE($2, path) WHERE media_uuid = $3
+        "#;
+
+        conn.execute(statement, &[&library_uuid, &path, &media_uuid])
+            .await?;
+
+        debug!("moved media to library");
+
+        Ok(())
+    }
+
     #[instrument(skip(self, filter))]
     async fn search_media(
         &self,
         gid: HashSet<String>,
         filter: SearchFilter,
-    ) -> Result<Vec<MediaUuid>> {
+        sort: SortOrder,
+        limit: Option<u64>,
+        offset: Option<u64>,
+    ) -> Result<(Vec<MediaUuid>, u64)> {
         debug!("searching for media");
 
         let conn = self.pool.get().await?;
@@ -384,17 +628,36 @@ impl DbBackend for PostgresBackend {
                 ) AS t3
                 INNER JOIN media ON t3.media_uuid = media.media_uuid
             WHERE
-                media.hidden = FALSE"#.to_owned();
+                media.hidden = FALSE
+                AND media.deleted_at IS NULL"#.to_owned();
 
         statement.push_str(&ts_search_sql);
 
+        let gid = gid.into_iter().collect::<Vec<String>>();
+
+        // the total ignores the paging, so it has to be its own query
+        let total: i64 = conn
+            .query_one_scalar(
+                &format!("SELECT COUNT(*) FROM ({statement}) AS t4"),
+                &[&gid],
+            )
+            .await?;
+
+        statement.push_str(sort.format_sql());
+
+        // a NULL limit or offset is the same as leaving it off
+        statement.push_str(" LIMIT $2 OFFSET $3");
+
         let media = conn
-            .query_scalar(&statement, &[&gid.into_iter().collect::<Vec<String>>()])
+            .query_scalar(
+                &statement,
+                &[&gid, &limit.map(|v| v as i64), &offset.map(|v| v as i64)],
+            )
             .await?;
 
-        debug!({ count = media.len() }, "found media");
+        debug!({ count = media.len(), total = total }, "found media");
 
-        Ok(media)
+        Ok((media, total as u64))
     }
 
     #[instrument(skip(self))]
@@ -403,12 +666,172 @@ impl DbBackend for PostgresBackend {
         gid: HashSet<String>,
         media_uuid: MediaUuid,
         distance: i64,
-    ) -> Result<Vec<MediaUuid>> {
+    ) -> Result<Vec<(MediaUuid, i64)>> {
         debug!("searching for similar media");
 
         let conn = self.pool.get().await?;
 
+        // the phash is a 256-bit blockhash in hex, so the distance is the popcount of the xor
         let statement = r#"-- similar_media
+            SELECT
+                media_uuid, distance
+            FROM
+                (
+                    SELECT
+                        media.media_uuid,
+                        bit_count(('x' || (SELECT phash FROM media WHERE media_uuid = $2))::bit(256) # ('x' || media.phash)::bit(256)) AS distance
+                    FROM
+                        (
+                            SELECT
+                                media_uuid
+                            FROM
+                                (
+                                    SELECT
+                                        collection_uuid
+                                    FROM
+                                        collections
+                                    WHERE
+                                        gid = ANY($1)
+                                ) AS t1
+                                INNER JOIN collection_contents ON t1.collection_uuid = collection_contents.collection_uuid
+                            UNION
+                            SELECT
+                                media_uuid
+                            FROM
+                                (
+                                    SELECT
+                                        library_uuid
+                                    FROM
+                                        libraries
+                                    WHERE
+                                        gid = ANY($1)
+                                ) AS t2
+                                INNER JOIN media ON t2.library_uuid = media.library_uuid
+                        ) AS t3
+                        INNER JOIN media ON t3.media_uuid = media.media_uuid
+                    WHERE
+                        media.hidden = FALSE
+                        AND media.deleted_at IS NULL
+                        AND media.phash != ''
+                ) AS t4
+            WHERE
+                distance < $3
+            ORDER BY
+                distance ASC, media_uuid ASC
+        "#;
+
+        let media = conn
+            .query(
+                statement,
+                &[
+                    &gid.into_iter().collect::<Vec<String>>(),
+                    &media_uuid,
+                    &distance,
+                ],
+            )
+            .await?
+            .into_iter()
+            .map(|row| Ok((row.try_get("media_uuid")?, row.try_get("distance")?)))
+            .collect::<Result<Vec<(MediaUuid, i64)>>>()?;
+
+        debug!({ count = media.len() }, "found similar media");
+
+        Ok(media)
+    }
+
+    #[instrument(skip(self))]
+    async fn similar_phash(
+        &self,
+        gid: HashSet<String>,
+        phash: String,
+        distance: i64,
+    ) -> Result<Vec<(MediaUuid, i64)>> {
+        debug!("searching for media similar to a phash");
+
+        let conn = self.pool.get().await?;
+
+        // see similar_media()
+        let statement = r#"-- similar_phash
+            SELECT
+                media_uuid, distance
+            FROM
+                (
+                    SELECT
+                        media.media_uuid,
+                        bit_count(('x' || $2)::bit(256) # ('x' || media.phash)::bit(256)) AS distance
+                    FROM
+                        (
+                            SELECT
+                                media_uuid
+                            FROM
+                                (
+                                    SELECT
+                                        collection_uuid
+                                    FROM
+                                        collections
+                                    WHERE
+                                        gid = ANY($1)
+                                ) AS t1
+                                INNER JOIN collection_contents ON t1.collection_uuid = collection_contents.collection_uuid
+                            UNION
+                            SELECT
+                                media_uuid
+                            FROM
+                                (
+                                    SELECT
+                                        library_uuid
+                                    FROM
+                                        libraries
+                                    WHERE
+                                        gid = ANY($1)
+                                ) AS t2
+                                INNER JOIN media ON t2.library_uuid = media.library_uuid
+                        ) AS t3
+                        INNER JOIN media ON t3.media_uuid = media.media_uuid
+                    WHERE
+                        media.hidden = FALSE
+                        AND media.deleted_at IS NULL
+                        AND media.phash != ''
+                ) AS t4
+            WHERE
+                distance < $3
+            ORDER BY
+                distance ASC, media_uuid ASC
+        "#;
+
+        let media = conn
+            .query(
+                statement,
+                &[&gid.into_iter().collect::<Vec<String>>(), &phash, &distance],
+            )
+            .await?
+            .into_iter()
+            .map(|row| Ok((row.try_get("media_uuid")?, row.try_get("distance")?)))
+            .collect::<Result<Vec<(MediaUuid, i64)>>>()?;
+
+        debug!({ count = media.len() }, "found similar media");
+
+        Ok(media)
+    }
+
+    #[instrument(skip(self))]
+    async fn random_media(
+        &self,
+        gid: HashSet<String>,
+        filter: SearchFilter,
+        count: u64,
+    ) -> Result<Vec<MediaUuid>> {
+        debug!("sampling media");
+
+        let conn = self.pool.get().await?;
+
+        let ts_search_sql = filter.format_postgres("media.ts_vec");
+
+        // ORDER BY RANDOM() sorts every match before taking the first few, so this scales
+        // with the number of accessible media rather than the count.  TABLESAMPLE would be
+        // cheaper, but it samples the media table before the access checks, and so can come
+        // up short (or empty) for users who can only see a small part of it.
+        let mut statement = r#"-- random_media
             SELECT
                 media.media_uuid
             FROM
@@ -442,135 +865,846 @@ impl DbBackend for PostgresBackend {
                 INNER JOIN media ON t3.media_uuid = media.media_uuid
             WHERE
                 media.hidden = FALSE
-                AND bit_count(('x' || (SELECT phash FROM media WHERE media_uuid = $2))::bit & ('x' || media.phash)::bit) < $3
-        "#;
+                AND media.deleted_at IS NULL"#.to_owned();
+
+        statement.push_str(&ts_search_sql);
+        statement.push_str(" ORDER BY RANDOM() LIMIT $2");
+
+        let gid = gid.into_iter().collect::<Vec<String>>();
 
         let media = conn
-            .query_scalar(
-                statement,
-                &[
-                    &gid.into_iter().collect::<Vec<String>>(),
-                    &media_uuid,
-                    &distance,
-                ],
-            )
+            .query_scalar(&statement, &[&gid, &(count as i64)])
             .await?;
 
-        debug!({ count = media.len() }, "found similar media");
+        debug!({ count = media.len() }, "sampled media");
 
         Ok(media)
     }
 
-    // comment functions
-    #[instrument(skip(self, comment))]
-    async fn add_comment(&self, comment: Comment) -> Result<CommentUuid> {
-        debug!({ media_uuid = %comment.media_uuid }, "adding comment");
+    #[instrument(skip(self))]
+    async fn stats(&self, gid: HashSet<String>) -> Result<Stats> {
+        debug!("counting media");
+
+        let conn = self.pool.get().await?;
+
+        // the media are the same set as search_media(), and SUM() is NULL over an empty set
+        let statement = r#"-- stats
+            SELECT
+                COUNT(*) AS media,
+                COUNT(*) FILTER (WHERE media.media_type = 'Image') AS images,
+                COUNT(*) FILTER (WHERE media.media_type = 'Video') AS videos,
+                COUNT(*) FILTER (WHERE media.media_type = 'Audio') AS audio,
+                COALESCE(SUM(media.size), 0)::bigint AS bytes,
+                (SELECT COUNT(*) FROM collections WHERE gid = ANY($1)) AS collections,
+                (SELECT COUNT(*) FROM libraries WHERE gid = ANY($1)) AS libraries
+            FROM
+                (
+                    SELECT
+                        media_uuid
+                    FROM
+                        (
+                            SELECT
+                                collection_uuid
+                            FROM
+                                collections
+                            WHERE
+                                gid = ANY($1)
+                        ) AS t1
+                        INNER JOIN collection_contents ON t1.collection_uuid = collection_contents.collection_uuid
+                    UNION
+                    SELECT
+                        media_uuid
+                    FROM
+                        (
+                            SELECT
+                                library_uuid
+                            FROM
+                                libraries
+                            WHERE
+                                gid = ANY($1)
+                        ) AS t2
+                        INNER JOIN media ON t2.library_uuid = media.library_uuid
+                ) AS t3
+                INNER JOIN media ON t3.media_uuid = media.media_uuid
+            WHERE
+                media.hidden = FALSE
+                AND media.deleted_at IS NULL
+        "#;
+
+        let row = conn
+            .query_one(statement, &[&gid.into_iter().collect::<Vec<String>>()])
+            .await?;
+
+        let count = |name: &str| -> Result<u64> { Ok(row.try_get::<&str, i64>(name)? as u64) };
+
+        let stats = Stats {
+            media: count("media")?,
+            images: count("images")?,
+            videos: count("videos")?,
+            audio: count("audio")?,
+            bytes: count("bytes")?,
+            collections: count("collections")?,
+            libraries: count("libraries")?,
+        };
+
+        debug!({ media = stats.media }, "counted media");
+
+        Ok(stats)
+    }
+
+    #[instrument(skip(self))]
+    async fn media_date_histogram(
+        &self,
+        gid: HashSet<String>,
+        granularity: DateGranularity,
+    ) -> Result<Vec<DateBucket>> {
+        debug!("counting media by date");
+
+        let conn = self.pool.get().await?;
+
+        // the same media as search_media(), grouped by a prefix of the date.  dates are
+        // free text, so anything that doesn't start with a yyyy-mm-dd is left out.
+        let statement = r#"-- media_date_histogram
+            SELECT
+                LEFT(media.date, $2) AS bucket,
+                COUNT(*) AS count
+            FROM
+                (
+                    SELECT
+                        media_uuid
+                    FROM
+                        (
+                            SELECT
+                                collection_uuid
+                            FROM
+                                collections
+                            WHERE
+                                gid = ANY($1)
+                        ) AS t1
+                        INNER JOIN collection_contents ON t1.collection_uuid = collection_contents.collection_uuid
+                    UNION
+                    SELECT
+                        media_uuid
+                    FROM
+                        (
+                            SELECT
+                                library_uuid
+                            FROM
+                                libraries
+                            WHERE
+                                gid = ANY($1)
+                        ) AS t2
+                        INNER JOIN media ON t2.library_uuid = media.library_uuid
+                ) AS t3
+                INNER JOIN media ON t3.media_uuid = media.media_uuid
+            WHERE
+                media.hidden = FALSE
+                AND media.deleted_at IS NULL
+                AND media.date ~ '^\d{4}-\d{2}-\d{2}'
+            GROUP BY
+                bucket
+            ORDER BY
+                bucket ASC
+        "#;
+
+        let rows = conn
+            .query(
+                statement,
+                &[
+                    &gid.into_iter().collect::<Vec<String>>(),
+                    &(granularity.prefix_len() as i32),
+                ],
+            )
+            .await?;
+
+        let mut data = Vec::new();
+
+        for row in rows {
+            let date = row.try_get::<&str, String>("bucket")?;
+            let count = row.try_get::<&str, i64>("count")? as u64;
+
+            if let Some(bucket) = date_bucket(date, granularity, count) {
+                data.push(bucket);
+            }
+        }
+
+        debug!({ buckets = data.len() }, "counted media by date");
+
+        Ok(data)
+    }
+
+    #[instrument(skip(self))]
+    async fn recent_activity(&self, gid: HashSet<String>, limit: u64) -> Result<Vec<Activity>> {
+        debug!("finding recent activity");
+
+        let conn = self.pool.get().await?;
+
+        // see ActivityRow for why the uuids double as timestamps
+        let statement = r#"-- recent_activity
+            WITH accessible AS (
+                SELECT
+                    media.media_uuid
+                FROM
+                    (
+                        SELECT
+                            media_uuid
+                        FROM
+                            (
+                                SELECT
+                                    collection_uuid
+                                FROM
+                                    collections
+                                WHERE
+                                    gid = ANY($1)
+                            ) AS t1
+                            INNER JOIN collection_contents ON t1.collection_uuid = collection_contents.collection_uuid
+                        UNION
+                        SELECT
+                            media_uuid
+                        FROM
+                            (
+                                SELECT
+                                    library_uuid
+                                FROM
+                                    libraries
+                                WHERE
+                                    gid = ANY($1)
+                            ) AS t2
+                            INNER JOIN media ON t2.library_uuid = media.library_uuid
+                    ) AS t3
+                    INNER JOIN media ON t3.media_uuid = media.media_uuid
+                WHERE
+                    media.hidden = FALSE
+                    AND media.deleted_at IS NULL
+            )
+            SELECT
+                'media' AS kind,
+                media_uuid AS uuid,
+                NULL::uuid AS media_uuid
+            FROM
+                accessible
+            UNION ALL
+            SELECT
+                'collection',
+                collection_uuid,
+                NULL
+            FROM
+                collections
+            WHERE
+                gid = ANY($1)
+            UNION ALL
+            SELECT
+                'comment',
+                comments.comment_uuid,
+                comments.media_uuid
+            FROM
+                comments
+                INNER JOIN accessible ON comments.media_uuid = accessible.media_uuid
+            ORDER BY
+                uuid DESC
+            LIMIT
+                $2
+        "#;
+
+        let gid = gid.into_iter().collect::<Vec<String>>();
+
+        let res = conn.query(statement, &[&gid, &(limit as i64)]).await?;
+
+        let data = res
+            .iter()
+            .map(|row| {
+                ActivityRow {
+                    kind: row.try_get("kind")?,
+                    uuid: row.try_get("uuid")?,
+                    media_uuid: row.try_get("media_uuid")?,
+                }
+                .into_activity(self)
+            })
+            .collect::<Result<Vec<Activity>>>()?;
+
+        debug!({ count = data.len() }, "found recent activity");
+
+        Ok(data)
+    }
+
+    // trash functions
+    #[instrument(skip(self))]
+    async fn soft_delete_media(&self, media_uuid: MediaUuid) -> Result<()> {
+        debug!("moving media to trash");
+
+        let conn = self.pool.get().await?;
+
+        let statement = r#"-- soft_delete_media
+            UPDATE media SET deleted_at = $2 WHERE media_uuid = $1 AND deleted_at IS NULL
+        "#;
+
+        conn.query(
+            statement,
+            &[
+                &media_uuid,
+                &(SystemTime::now().duration_since(UNIX_EPOCH)?.as_secs() as i64),
+            ],
+        )
+        .await?;
+
+        debug!("moved media to trash");
+
+        Ok(())
+    }
+
+    #[instrument(skip(self))]
+    async fn restore_media(&self, media_uuid: MediaUuid) -> Result<()> {
+        debug!("restoring media from trash");
+
+        let conn = self.pool.get().await?;
+
+        let statement = r#"-- restore_media
+            UPDATE media SET deleted_at = NULL WHERE media_uuid = $1
+        "#;
+
+        conn.query(statement, &[&media_uuid]).await?;
+
+        debug!("restored media from trash");
+
+        Ok(())
+    }
+
+    #[instrument(skip(self))]
+    async fn purge_media(&self, media_uuid: MediaUuid) -> Result<()> {
+        debug!("purging media");
+
+        let conn = self.pool.get().await?;
+
+        // a single statement, so that the references only go if the media does
+        let statement = r#"-- purge_media
+            WITH purged AS (
+                DELETE FROM media WHERE media_uuid = $1 AND deleted_at IS NOT NULL
+                RETURNING media_uuid
+            ),
+            contents AS (
+                DELETE FROM collection_contents WHERE media_uuid IN (SELECT media_uuid FROM purged)
+            ),
+            comments AS (
+                DELETE FROM comments WHERE media_uuid IN (SELECT media_uuid FROM purged)
+            ),
+            covers AS (
+                UPDATE collections SET cover = NULL WHERE cover IN (SELECT media_uuid FROM purged)
+            )
+            SELECT COUNT(*) FROM purged
+        "#;
+
+        let count: i64 = conn.query_one_scalar(statement, &[&media_uuid]).await?;
+
+        if count == 0 {
+            return Err(anyhow::Error::msg("media is not in the trash"));
+        }
+
+        debug!("purged media");
+
+        Ok(())
+    }
+
+    #[instrument(skip(self, filter))]
+    async fn search_trash(
+        &self,
+        gid: HashSet<String>,
+        filter: SearchFilter,
+    ) -> Result<Vec<MediaUuid>> {
+        debug!("searching trash");
+
+        let conn = self.pool.get().await?;
+
+        let ts_search_sql = filter.format_postgres("media.ts_vec");
+
+        // for a given uid and filter, find all soft-deleted media in libraries owned by a group
+        // containing the uid.  collection access does not extend to the trash.
+        let mut statement = r#"-- search_trash
+            SELECT
+                media.media_uuid
+            FROM
+                (
+                    SELECT
+                        library_uuid
+                    FROM
+                        libraries
+                    WHERE
+                        gid = ANY($1)
+                ) AS t1
+                INNER JOIN media ON t1.library_uuid = media.library_uuid
+            WHERE
+                media.deleted_at IS NOT NULL"#.to_owned();
+
+        statement.push_str(&ts_search_sql);
+        statement.push_str(" ORDER BY media.deleted_at DESC, media.media_uuid DESC");
+
+        let media = conn
+            .query_scalar(&statement, &[&gid.into_iter().collect::<Vec<String>>()])
+            .await?;
+
+        debug!({ count = media.len() }, "found media in trash");
+
+        Ok(media)
+    }
+
+    // tag functions
+    #[instrument(skip(self))]
+    async fn rename_tag(&self, gid: HashSet<String>, from: String, to: String) -> Result<u64> {
+        debug!("renaming tag");
+
+        let conn = self.pool.get().await?;
+
+        // tags are hstore keys, so the rename is a delete and an insert that dedups by itself
+        let statement = r#"-- rename_tag
+            WITH renamed_media AS (
+                UPDATE media SET tags = (tags - $2::text) || hstore($3::text, NULL)
+                WHERE
+                    tags ? $2
+                    AND library_uuid IN (SELECT library_uuid FROM libraries WHERE gid = ANY($1))
+                RETURNING media_uuid
+            ),
+            renamed_collections AS (
+                UPDATE collections SET tags = (tags - $2::text) || hstore($3::text, NULL)
+                WHERE
+                    tags ? $2
+                    AND gid = ANY($1)
+                RETURNING collection_uuid
+            )
+            SELECT (SELECT COUNT(*) FROM renamed_media) + (SELECT COUNT(*) FROM renamed_collections)
+        "#;
+
+        let count: i64 = conn
+            .query_one_scalar(
+                statement,
+                &[&gid.into_iter().collect::<Vec<String>>(), &from, &to],
+            )
+            .await?;
+
+        debug!({ count = count }, "renamed tag");
+
+        Ok(count as u64)
+    }
+
+    // comment functions
+    #[instrument(skip(self, comment))]
+    async fn add_comment(&self, comment: Comment) -> Result<CommentUuid> {
+        debug!({ media_uuid = %comment.media_uuid }, "adding comment");
+
+        let conn = self.pool.get().await?;
+
+        let statement = r#"-- add_comment
+            INSERT INTO comments (comment_uuid, media_uuid, uid, date, text)
+            VALUES (uuidv7(), $1, $2, $3, $4)
+            RETURNING comment_uuid
+        "#;
+
+        let comment_uuid: CommentUuid = conn
+            .query_one_scalar(
+                statement,
+                &[
+                    &comment.media_uuid,
+                    &comment.uid,
+                    &(SystemTime::now().duration_since(UNIX_EPOCH)?.as_secs() as i64),
+                    &comment.text,
+                ],
+            )
+            .await?;
+
+        debug!({ media_uuid = %comment.media_uuid, %comment_uuid }, "added comment");
+
+        Ok(comment_uuid)
+    }
+
+    #[instrument(skip(self))]
+    async fn get_comment(&self, comment_uuid: CommentUuid) -> Result<Option<Comment>> {
+        debug!("finding comment");
+
+        let conn = self.pool.get().await?;
+
+        let statement = r#"-- get_comment
+            SELECT media_uuid, uid, date, text, edited FROM comments WHERE comment_uuid = $1
+        "#;
+
+        let res = conn.query(statement, &[&comment_uuid]).await?;
+
+        let row = match res.first() {
+            Some(ok) => ok,
+            None => return Ok(None),
+        };
+
+        Ok(Some(Comment {
+            media_uuid: row.try_get("media_uuid")?,
+            uid: row.try_get("uid")?,
+            date: row.try_get::<&str, i64>("date")? as u64,
+            text: row.try_get("text")?,
+            edited: row
+                .try_get::<&str, Option<i64>>("edited")?
+                .map(|v| v as u64),
+        }))
+    }
+
+    #[instrument(skip(self))]
+    async fn get_comment_uuids(&self) -> Result<Vec<CommentUuid>> {
+        debug!("finding all comment uuids");
+
+        let conn = self.pool.get().await?;
+
+        let statement = r#"-- get_comment_uuids
+            SELECT comment_uuid from comments
+        "#;
+
+        let comment_uuids = conn.query_scalar(statement, &[]).await?;
+
+        debug!({ count = comment_uuids.len() }, "found comments");
+
+        Ok(comment_uuids)
+    }
+
+    #[instrument(skip(self))]
+    async fn delete_comment(&self, comment_uuid: CommentUuid) -> Result<()> {
+        debug!("deleting comment");
+
+        let conn = self.pool.get().await?;
+
+        let statement = r#"-- delete_comment
+            DELETE FROM comments WHERE comment_uuid = $1
+        "#;
+
+        conn.query(statement, &[&comment_uuid]).await?;
+
+        Ok(())
+    }
+
+    #[instrument(skip(self, update))]
+    async fn update_comment(
+        &self,
+        comment_uuid: CommentUuid,
+        update: Option<String>,
+    ) -> Result<()> {
+        debug!("updating comment");
+
+        let conn = self.pool.get().await?;
+
+        // rewriting the same text isn't an edit, so it leaves the timestamp alone
+        let statement = r#"-- update_comment
+            UPDATE comments SET
+                text = COALESCE($1, text),
+                edited = CASE WHEN $1 IS NOT NULL AND $1 IS DISTINCT FROM text THEN $3 ELSE edited END
+            WHERE comment_uuid = $2
+        "#;
+
+        conn.query(
+            statement,
+            &[
+                &update,
+                &comment_uuid,
+                &(SystemTime::now().duration_since(UNIX_EPOCH)?.as_secs() as i64),
+            ],
+        )
+        .await?;
+
+        debug!("updated comment");
+
+        Ok(())
+    }
+
+    // api key functions
+    #[instrument(skip(self, key_hash))]
+    async fn add_api_key(&self, uid: String, name: String, key_hash: String) -> Result<ApiKeyUuid> {
+        debug!("adding api key");
+
+        let conn = self.pool.get().await?;
+
+        let statement = r#"-- add_api_key
+            INSERT INTO api_keys (key_uuid, uid, name, key_hash, created)
+            VALUES (uuidv7(), $1, $2, $3, $4)
+            RETURNING key_uuid
+        "#;
+
+        let key_uuid: ApiKeyUuid = conn
+            .query_one_scalar(
+                statement,
+                &[
+                    &uid,
+                    &name,
+                    &key_hash,
+                    &(SystemTime::now().duration_since(UNIX_EPOCH)?.as_secs() as i64),
+                ],
+            )
+            .await?;
+
+        debug!({ %key_uuid }, "added api key");
+
+        Ok(key_uuid)
+    }
+
+    #[instrument(skip_all)]
+    async fn get_api_key_uid(&self, key_hash: String) -> Result<Option<String>> {
+        debug!("looking up api key");
+
+        let conn = self.pool.get().await?;
+
+        let statement = r#"-- get_api_key_uid
+            SELECT uid FROM api_keys WHERE key_hash = $1
+        "#;
+
+        let res = conn.query(statement, &[&key_hash]).await?;
+
+        match res.first() {
+            Some(row) => Ok(Some(row.try_get("uid")?)),
+            None => Ok(None),
+        }
+    }
+
+    #[instrument(skip(self))]
+    async fn get_api_keys(&self, uid: String) -> Result<Vec<ApiKey>> {
+        debug!("finding api keys");
+
+        let conn = self.pool.get().await?;
+
+        let statement = r#"-- get_api_keys
+            SELECT key_uuid, name, created FROM api_keys WHERE uid = $1
+        "#;
+
+        let res = conn.query(statement, &[&uid]).await?;
+
+        let keys = res
+            .iter()
+            .map(|row| {
+                Ok(ApiKey {
+                    key_uuid: row.try_get("key_uuid")?,
+                    uid: uid.clone(),
+                    name: row.try_get("name")?,
+                    created: row.try_get::<&str, i64>("created")? as u64,
+                })
+            })
+            .collect::<Result<Vec<ApiKey>>>()?;
+
+        debug!({ count = keys.len() }, "found api keys");
+
+        Ok(keys)
+    }
+
+    #[instrument(skip(self))]
+    async fn delete_api_key(&self, uid: String, key_uuid: ApiKeyUuid) -> Result<()> {
+        debug!("deleting api key");
+
+        let conn = self.pool.get().await?;
+
+        let statement = r#"-- delete_api_key
+            DELETE FROM api_keys WHERE key_uuid = $1 AND uid = $2
+        "#;
+
+        conn.query(statement, &[&key_uuid, &uid]).await?;
+
+        Ok(())
+    }
+
+    // share link functions
+    #[instrument(skip(self, token_hash))]
+    async fn add_share_link(
+        &self,
+        uid: String,
+        target: ShareTarget,
+        token_hash: String,
+        expires_at: u64,
+    ) -> Result<ShareLinkUuid> {
+        debug!("adding share link");
 
         let conn = self.pool.get().await?;
 
-        let statement = r#"-- add_comment
-            INSERT INTO comments (comment_uuid, media_uuid, uid, date, text)
-            VALUES (uuidv7(), $1, $2, $3, $4)
-            RETURNING comment_uuid
+        let (media_uuid, collection_uuid) = share_target_columns(target);
+
+        let statement = r#"-- add_share_link
+            INSERT INTO share_links (share_uuid, uid, token_hash, media_uuid, collection_uuid, created, expires_at)
+            VALUES (uuidv7(), $1, $2, $3, $4, $5, $6)
+            RETURNING share_uuid
         "#;
 
-        let comment_uuid: CommentUuid = conn
+        let share_uuid: ShareLinkUuid = conn
             .query_one_scalar(
                 statement,
                 &[
-                    &comment.media_uuid,
-                    &comment.uid,
+                    &uid,
+                    &token_hash,
+                    &media_uuid,
+                    &collection_uuid,
                     &(SystemTime::now().duration_since(UNIX_EPOCH)?.as_secs() as i64),
-                    &comment.text,
+                    &(expires_at as i64),
                 ],
             )
             .await?;
 
-        debug!({ media_uuid = %comment.media_uuid, %comment_uuid }, "added comment");
+        debug!({ %share_uuid }, "added share link");
 
-        Ok(comment_uuid)
+        Ok(share_uuid)
     }
 
-    #[instrument(skip(self))]
-    async fn get_comment(&self, comment_uuid: CommentUuid) -> Result<Option<Comment>> {
-        debug!("finding comment");
+    #[instrument(skip_all)]
+    async fn get_share_link(&self, token_hash: String) -> Result<Option<ShareLink>> {
+        debug!("looking up share link");
 
         let conn = self.pool.get().await?;
 
-        let statement = r#"-- get_comment
-            SELECT media_uuid, uid, date, text FROM comments WHERE comment_uuid = $1
+        let statement = r#"-- get_share_link
+            SELECT share_uuid, uid, media_uuid, collection_uuid, created, expires_at
+            FROM share_links WHERE token_hash = $1
         "#;
 
-        let res = conn.query(statement, &[&comment_uuid]).await?;
-
-        let row = match res.first() {
-            Some(ok) => ok,
-            None => return Ok(None),
-        };
+        let res = conn.query(statement, &[&token_hash]).await?;
 
-        Ok(Some(Comment {
-            media_uuid: row.try_get("media_uuid")?,
-            uid: row.try_get("uid")?,
-            date: row.try_get::<&str, i64>("date")? as u64,
-            text: row.try_get("text")?,
-        }))
+        match res.first() {
+            Some(row) => Ok(Some(share_link_row(row)?.into_share_link(self)?)),
+            None => Ok(None),
+        }
     }
 
     #[instrument(skip(self))]
-    async fn get_comment_uuids(&self) -> Result<Vec<CommentUuid>> {
-        debug!("finding all comment uuids");
+    async fn get_share_links(&self, uid: String) -> Result<Vec<ShareLink>> {
+        debug!("finding share links");
 
         let conn = self.pool.get().await?;
 
-        let statement = r#"-- get_comment_uuids
-            SELECT comment_uuid from comments
+        let statement = r#"-- get_share_links
+            SELECT share_uuid, uid, media_uuid, collection_uuid, created, expires_at
+            FROM share_links WHERE uid = $1
         "#;
 
-        let comment_uuids = conn.query_scalar(statement, &[]).await?;
+        let res = conn.query(statement, &[&uid]).await?;
 
-        debug!({ count = comment_uuids.len() }, "found comments");
+        let links = res
+            .iter()
+            .map(|row| share_link_row(row)?.into_share_link(self))
+            .collect::<Result<Vec<ShareLink>>>()?;
 
-        Ok(comment_uuids)
+        debug!({ count = links.len() }, "found share links");
+
+        Ok(links)
     }
 
     #[instrument(skip(self))]
-    async fn delete_comment(&self, comment_uuid: CommentUuid) -> Result<()> {
-        debug!("deleting comment");
+    async fn delete_share_link(&self, uid: String, share_uuid: ShareLinkUuid) -> Result<()> {
+        debug!("deleting share link");
 
         let conn = self.pool.get().await?;
 
-        let statement = r#"-- delete_comment
-            DELETE FROM comments WHERE comment_uuid = $1
+        let statement = r#"-- delete_share_link
+            DELETE FROM share_links WHERE share_uuid = $1 AND uid = $2
         "#;
 
-        conn.query(statement, &[&comment_uuid]).await?;
+        conn.query(statement, &[&share_uuid, &uid]).await?;
 
         Ok(())
     }
 
-    #[instrument(skip(self, update))]
-    async fn update_comment(
-        &self,
-        comment_uuid: CommentUuid,
-        update: Option<String>,
-    ) -> Result<()> {
-        debug!("updating comment");
+    #[instrument(skip(self))]
+    async fn get_shared_media(&self, target: ShareTarget) -> Result<Vec<MediaUuid>> {
+        debug!("finding shared media");
 
         let conn = self.pool.get().await?;
 
-        let statement = r#"-- update_comment
-            UPDATE comments SET
-                text = COALESCE($1, text)
-            WHERE comment_uuid = $2
+        let (media_uuid, collection_uuid) = share_target_columns(target);
+
+        // only one of the uuids is set, and comparing against NULL never matches
+        let statement = r#"-- get_shared_media
+            SELECT media_uuid FROM media
+            WHERE
+                (
+                    media_uuid = $1
+                    OR media_uuid IN (
+                        SELECT media_uuid FROM collection_contents
+                        WHERE collection_uuid = $2
+                    )
+                )
+                AND hidden = FALSE
+                AND deleted_at IS NULL
         "#;
 
-        conn.query(statement, &[&update, &comment_uuid]).await?;
+        let media = conn
+            .query_scalar(statement, &[&media_uuid, &collection_uuid])
+            .await?;
+
+        debug!({ count = media.len() }, "found shared media");
 
-        debug!("updated comment");
+        Ok(media)
+    }
+
+    // task functions
+    #[instrument(skip(self, task))]
+    async fn add_task(&self, library: TaskLibrary, task: Task) -> Result<()> {
+        debug!("adding task");
+
+        let task = TaskRow::new(library, task);
+
+        let conn = self.pool.get().await?;
+
+        let statement = r#"-- add_task
+            INSERT INTO tasks (library_uuid, task_type, uid, status, warnings, start_time, end_time, summary)
+            VALUES ($1, $2, $3, $4, $5, $6, $7, $8)
+        "#;
+
+        conn.query(
+            statement,
+            &[
+                &task.library_uuid,
+                &task.task_type,
+                &task.uid,
+                &task.status,
+                &task.warnings,
+                &(task.start as i64),
+                &(task.end as i64),
+                &task.summary,
+            ],
+        )
+        .await?;
 
         Ok(())
     }
 
+    #[instrument(skip(self))]
+    async fn get_tasks(&self, library: TaskLibrary, limit: u64) -> Result<Vec<Task>> {
+        debug!("finding tasks");
+
+        let library_uuid = match library {
+            TaskLibrary::User { library_uuid } => Some(library_uuid),
+            TaskLibrary::System => None,
+        };
+
+        let conn = self.pool.get().await?;
+
+        let statement = r#"-- get_tasks
+            SELECT task_type, uid, status, warnings, start_time, end_time, summary
+            FROM tasks
+            WHERE library_uuid IS NOT DISTINCT FROM $1
+            ORDER BY end_time DESC, task_id DESC
+            LIMIT $2
+        "#;
+
+        let res = conn
+            .query(statement, &[&library_uuid, &(limit as i64)])
+            .await?;
+
+        let tasks = res
+            .iter()
+            .map(|row| {
+                TaskRow {
+                    library_uuid,
+                    task_type: row.try_get("task_type")?,
+                    uid: row.try_get("uid")?,
+                    status: row.try_get("status")?,
+                    warnings: row.try_get("warnings")?,
+                    start: row.try_get::<&str, i64>("start_time")? as u64,
+                    end: row.try_get::<&str, i64>("end_time")? as u64,
+                    summary: row.try_get("summary")?,
+                }
+                .into_task()
+            })
+            .collect::<Result<Vec<Task>>>()?;
+
+        debug!({ count = tasks.len() }, "found tasks");
+
+        Ok(tasks)
+    }
+
     // collection functions
     #[instrument(skip(self, collection))]
     async fn add_collection(&self, collection: Collection) -> Result<CollectionUuid> {
@@ -579,8 +1713,8 @@ impl DbBackend for PostgresBackend {
         let conn = self.pool.get().await?;
 
         let statement = r"-- add_collection
-            INSERT INTO collections (collection_uuid, uid, gid, name, note, tags, cover)
-            VALUES (uuidv7(), $1, $2, $3, $4, $5, $6)
+            INSERT INTO collections (collection_uuid, uid, gid, name, note, tags, cover, parent_uuid)
+            VALUES (uuidv7(), $1, $2, $3, $4, $5, $6, $7)
             ON CONFLICT (uid, name) DO NOTHING
             RETURNING collection_uuid
         ";
@@ -595,6 +1729,7 @@ impl DbBackend for PostgresBackend {
                     &collection.note,
                     &set_to_hstore(collection.tags),
                     &collection.cover,
+                    &collection.parent_uuid,
                 ],
             )
             .await?;
@@ -610,8 +1745,38 @@ impl DbBackend for PostgresBackend {
 
         let conn = self.pool.get().await?;
 
+        // the cover falls back to the most recent visible media when it was never set, or
+        // when the chosen one has since been hidden, deleted, or removed from the collection
         let statement = r#"-- get_collection
-            SELECT uid, gid, name, note, tags, cover FROM collections WHERE collection_uuid = $1
+            SELECT
+                uid,
+                gid,
+                name,
+                note,
+                tags,
+                COALESCE(
+                    (
+                        SELECT media.media_uuid FROM collection_contents
+                        INNER JOIN media ON collection_contents.media_uuid = media.media_uuid
+                        WHERE
+                            collection_contents.collection_uuid = collections.collection_uuid
+                            AND media.media_uuid = collections.cover
+                            AND media.hidden = FALSE
+                            AND media.deleted_at IS NULL
+                    ),
+                    (
+                        SELECT media.media_uuid FROM collection_contents
+                        INNER JOIN media ON collection_contents.media_uuid = media.media_uuid
+                        WHERE
+                            collection_contents.collection_uuid = collections.collection_uuid
+                            AND media.hidden = FALSE
+                            AND media.deleted_at IS NULL
+                        ORDER BY media.date DESC, media.media_uuid DESC
+                        LIMIT 1
+                    )
+                ) AS cover,
+                parent_uuid
+            FROM collections WHERE collection_uuid = $1
         "#;
 
         let res = conn.query(statement, &[&collection_uuid]).await?;
@@ -630,6 +1795,7 @@ impl DbBackend for PostgresBackend {
             note: row.try_get("note")?,
             tags: hstore_to_set(row.try_get("tags")?),
             cover: row.try_get("cover")?,
+            parent_uuid: row.try_get("parent_uuid")?,
         }))
     }
 
@@ -656,7 +1822,11 @@ impl DbBackend for PostgresBackend {
 
         let conn = self.pool.get().await?;
 
+        // the children move up to the top level rather than disappearing
         let statement = r#"-- delete_collection
+            WITH orphans AS (
+                UPDATE collections SET parent_uuid = NULL WHERE parent_uuid = $1
+            )
             DELETE FROM collections WHERE collection_uuid = $1
         "#;
 
@@ -699,6 +1869,98 @@ impl DbBackend for PostgresBackend {
         Ok(())
     }
 
+    #[instrument(skip(self))]
+    async fn set_collection_cover(
+        &self,
+        collection_uuid: CollectionUuid,
+        cover: Option<MediaUuid>,
+    ) -> Result<()> {
+        debug!("setting collection cover");
+
+        let conn = self.pool.get().await?;
+
+        let statement = r#"-- set_collection_cover
+            UPDATE collections SET cover = $1 WHERE collection_uuid = $2
+        "#;
+
+        conn.query(statement, &[&cover, &collection_uuid]).await?;
+
+        debug!("set collection cover");
+
+        Ok(())
+    }
+
+    #[instrument(skip(self))]
+    async fn set_collection_parent(
+        &self,
+        collection_uuid: CollectionUuid,
+        parent_uuid: Option<CollectionUuid>,
+    ) -> Result<()> {
+        debug!("setting collection parent");
+
+        let conn = self.pool.get().await?;
+
+        // walk up from the new parent, which must not pass through the collection.  this is
+        // a single statement so that the check can't race another move.
+        let statement = r#"-- set_collection_parent
+            WITH RECURSIVE ancestors (collection_uuid) AS (
+                SELECT $1::uuid WHERE $1 IS NOT NULL
+                UNION
+                SELECT collections.parent_uuid FROM collections
+                INNER JOIN ancestors ON collections.collection_uuid = ancestors.collection_uuid
+                WHERE collections.parent_uuid IS NOT NULL
+            )
+            UPDATE collections SET parent_uuid = $1
+            WHERE
+                collection_uuid = $2
+                AND NOT EXISTS (SELECT 1 FROM ancestors WHERE collection_uuid = $2)
+            RETURNING collection_uuid
+        "#;
+
+        let rows = conn
+            .query(statement, &[&parent_uuid, &collection_uuid])
+            .await?;
+
+        if rows.is_empty() {
+            return Err(anyhow::Error::msg("collection cannot be its own ancestor"));
+        }
+
+        debug!("set collection parent");
+
+        Ok(())
+    }
+
+    #[instrument(skip(self))]
+    async fn get_collection_children(
+        &self,
+        gid: HashSet<String>,
+        collection_uuid: CollectionUuid,
+    ) -> Result<Vec<CollectionUuid>> {
+        debug!("finding collection children");
+
+        let conn = self.pool.get().await?;
+
+        let statement = r#"-- get_collection_children
+            SELECT
+                collection_uuid
+            FROM
+                collections
+            WHERE
+                gid = ANY($1) AND parent_uuid = $2
+        "#;
+
+        let collections = conn
+            .query_scalar(
+                statement,
+                &[&gid.into_iter().collect::<Vec<String>>(), &collection_uuid],
+            )
+            .await?;
+
+        debug!({ count = collections.len() }, "found collection children");
+
+        Ok(collections)
+    }
+
     #[instrument(skip(self))]
     async fn add_media_to_collection(
         &self,
@@ -710,8 +1972,9 @@ impl DbBackend for PostgresBackend {
         let conn = self.pool.get().await?;
 
         let statement = r#"-- add_media_to_collection
-            INSERT INTO collection_contents (media_uuid, collection_uuid)
-            VALUES ($1, $2)
+            INSERT INTO collection_contents (media_uuid, collection_uuid, position)
+            SELECT $1, $2, COALESCE(MAX(position), -1) + 1
+            FROM collection_contents WHERE collection_uuid = $2
             ON CONFLICT (media_uuid, collection_uuid) DO NOTHING
             RETURNING id
 
@@ -748,6 +2011,42 @@ impl DbBackend for PostgresBackend {
         Ok(())
     }
 
+    #[instrument(skip(self, media_uuids))]
+    async fn reorder_collection_media(
+        &self,
+        collection_uuid: CollectionUuid,
+        media_uuids: Vec<MediaUuid>,
+    ) -> Result<()> {
+        debug!({ count = media_uuids.len() }, "reordering collection media");
+
+        let conn = self.pool.get().await?;
+
+        // the listed media take the first positions, by their place in the array, and
+        // everything else moves back past them
+        let statement = r#"-- reorder_collection_media
+            UPDATE collection_contents
+            SET position = COALESCE(
+                (
+                    SELECT MIN(t.ord) - 1
+                    FROM UNNEST($2::uuid[]) WITH ORDINALITY AS t (media_uuid, ord)
+                    WHERE t.media_uuid = collection_contents.media_uuid
+                ),
+                position + $3
+            )
+            WHERE collection_uuid = $1
+        "#;
+
+        conn.execute(
+            statement,
+            &[&collection_uuid, &media_uuids, &(media_uuids.len() as i64)],
+        )
+        .await?;
+
+        debug!("reordered collection media");
+
+        Ok(())
+    }
+
     #[instrument(skip(self, filter))]
     async fn search_collections(
         &self,
@@ -791,20 +2090,38 @@ impl DbBackend for PostgresBackend {
         gid: HashSet<String>,
         collection_uuid: CollectionUuid,
         filter: SearchFilter,
+        recursive: bool,
+        ordered: bool,
     ) -> Result<Vec<MediaUuid>> {
         debug!("searching for media in collection");
 
         let conn = self.pool.get().await?;
 
-        let ts_search_sql = filter.format_postgres("media.ts_vec");
+        let mut ts_search_sql = filter.format_postgres("media.ts_vec");
 
+        if ordered {
+            ts_search_sql.push_str(" ORDER BY t3.position, t3.id");
+        }
+
+        // if recursive, this also walks down through the descendants.  UNION (rather than
+        // UNION ALL) stops the walk if the tree somehow has a cycle.
         let mut statement = r#"-- search_media_in_collection
+            WITH RECURSIVE tree (collection_uuid) AS (
+                SELECT collection_uuid FROM collections
+                WHERE gid = ANY($1) AND collection_uuid = $2
+                UNION
+                SELECT collections.collection_uuid FROM collections
+                INNER JOIN tree ON collections.parent_uuid = tree.collection_uuid
+                WHERE $3
+            )
             SELECT
                 media.media_uuid
             FROM
                 (
                     SELECT
-                        media_uuid
+                        media_uuid,
+                        MIN(collection_contents.position) AS position,
+                        MIN(collection_contents.id) AS id
                     FROM
                         (
                             SELECT
@@ -812,13 +2129,16 @@ impl DbBackend for PostgresBackend {
                             FROM
                                 collections
                             WHERE
-                                gid = ANY($1) AND collection_uuid = $2
+                                gid = ANY($1) AND collection_uuid IN (SELECT collection_uuid FROM tree)
                         ) AS t2
                         INNER JOIN collection_contents ON t2.collection_uuid = collection_contents.collection_uuid
+                    GROUP BY
+                        media_uuid
                 ) AS t3
                 INNER JOIN media ON t3.media_uuid = media.media_uuid
             WHERE
                 media.hidden = FALSE
+                AND media.deleted_at IS NULL
         "#.to_owned();
 
         statement.push_str(&ts_search_sql);
@@ -826,7 +2146,11 @@ impl DbBackend for PostgresBackend {
         let media = conn
             .query_scalar(
                 &statement,
-                &[&gid.into_iter().collect::<Vec<String>>(), &collection_uuid],
+                &[
+                    &gid.into_iter().collect::<Vec<String>>(),
+                    &collection_uuid,
+                    &recursive,
+                ],
             )
             .await?;
 
@@ -843,14 +2167,24 @@ impl DbBackend for PostgresBackend {
         let conn = self.pool.get().await?;
 
         let statement = r"-- add_library
-            INSERT INTO libraries (library_uuid, path, uid, gid, count)
-            VALUES (uuidv7(), $1, $2, $3, $4)
+            INSERT INTO libraries (library_uuid, path, name, note, uid, gid, count)
+            VALUES (uuidv7(), $1, $2, $3, $4, $5, $6)
             ON CONFLICT (path) DO NOTHING
             RETURNING library_uuid
         ";
 
         let library_uuid: LibraryUuid = conn
-            .query_one_scalar(statement, &[&library.path, &library.uid, &library.gid, &library.count])
+            .query_one_scalar(
+                statement,
+                &[
+                    &library.path,
+                    &library.name,
+                    &library.note,
+                    &library.uid,
+                    &library.gid,
+                    &library.count,
+                ],
+            )
             .await?;
 
         debug!({ library_path = library.path , %library_uuid }, "added library");
@@ -865,7 +2199,7 @@ impl DbBackend for PostgresBackend {
         let conn = self.pool.get().await?;
 
         let statement = r#"-- get_library
-            SELECT path, uid, gid, count FROM libraries WHERE library_uuid = $1
+            SELECT path, name, note, uid, gid, count FROM libraries WHERE library_uuid = $1
         "#;
 
         let res = conn.query(statement, &[&library_uuid]).await?;
@@ -879,6 +2213,8 @@ impl DbBackend for PostgresBackend {
 
         Ok(Some(Library {
             path: row.try_get("path")?,
+            name: row.try_get("name")?,
+            note: row.try_get("note")?,
             uid: row.try_get("uid")?,
             gid: row.try_get("gid")?,
             count: row.try_get("count")?,
@@ -910,12 +2246,17 @@ impl DbBackend for PostgresBackend {
 
         let statement = r#"-- update_library
             UPDATE libraries SET
-                count = COALESCE($1, count)
-            WHERE library_uuid = $2
+                name = COALESCE($1, name),
+                note = COALESCE($2, note),
+                count = COALESCE($3, count)
+            WHERE library_uuid = $4
         "#;
 
-        conn.query(statement, &[&update.count, &library_uuid])
-            .await?;
+        conn.query(
+            statement,
+            &[&update.name, &update.note, &update.count, &library_uuid],
+        )
+        .await?;
 
         debug!("updated library");
 
@@ -937,7 +2278,7 @@ impl DbBackend for PostgresBackend {
             FROM
                 libraries
             WHERE
-                gid = ANY($1) AND path LIKE $2
+                gid = ANY($1) AND (path LIKE $2 OR name LIKE $2 OR note LIKE $2)
         "#;
 
         let libraries = conn
@@ -955,6 +2296,74 @@ impl DbBackend for PostgresBackend {
         Ok(libraries)
     }
 
+    #[instrument(skip(self))]
+    async fn search_libraries_detailed(
+        &self,
+        gid: HashSet<String>,
+        filter: String,
+    ) -> Result<Vec<LibrarySummary>> {
+        debug!("searching for libraries with details");
+
+        let conn = self.pool.get().await?;
+
+        // the media count matches search_media_in_library() with no filter or hidden state
+        let statement = r#"-- search_libraries_detailed
+            SELECT
+                library_uuid,
+                path,
+                name,
+                note,
+                uid,
+                gid,
+                count,
+                (
+                    SELECT
+                        COUNT(*)
+                    FROM
+                        media
+                    WHERE
+                        media.library_uuid = libraries.library_uuid
+                        AND media.deleted_at IS NULL
+                ) AS media
+            FROM
+                libraries
+            WHERE
+                gid = ANY($1) AND (path LIKE $2 OR name LIKE $2 OR note LIKE $2)
+        "#;
+
+        let rows = conn
+            .query(
+                statement,
+                &[
+                    &gid.into_iter().collect::<Vec<String>>(),
+                    &format!("%{}%", filter),
+                ],
+            )
+            .await?;
+
+        let libraries = rows
+            .iter()
+            .map(|row| {
+                Ok(LibrarySummary {
+                    library_uuid: row.try_get("library_uuid")?,
+                    library: Library {
+                        path: row.try_get("path")?,
+                        name: row.try_get("name")?,
+                        note: row.try_get("note")?,
+                        uid: row.try_get("uid")?,
+                        gid: row.try_get("gid")?,
+                        count: row.try_get("count")?,
+                    },
+                    media: row.try_get::<_, i64>("media")? as u64,
+                })
+            })
+            .collect::<Result<Vec<LibrarySummary>>>()?;
+
+        debug!({ count = libraries.len() }, "found libraries");
+
+        Ok(libraries)
+    }
+
     #[instrument(skip(self))]
     async fn search_media_in_library(
         &self,
@@ -983,7 +2392,8 @@ impl DbBackend for PostgresBackend {
                 ) AS t1
                 INNER JOIN media ON t1.library_uuid = media.library_uuid
             WHERE
-                media.hidden = COALESCE($3, media.hidden)"#.to_owned();
+                media.hidden = COALESCE($3, media.hidden)
+                AND media.deleted_at IS NULL"#.to_owned();
 
         statement.push_str(&ts_search_sql);
 
@@ -998,4 +2408,44 @@ impl DbBackend for PostgresBackend {
 
         Ok(media)
     }
+
+    #[instrument(skip(self))]
+    async fn count_hidden_in_library(
+        &self,
+        gid: HashSet<String>,
+        library_uuid: LibraryUuid,
+    ) -> Result<u64> {
+        debug!("counting hidden media in library");
+
+        let conn = self.pool.get().await?;
+
+        // the same access rules as search_media_in_library()
+        let statement = r#"-- count_hidden_in_library
+            SELECT
+                COUNT(*)
+            FROM
+                (
+                    SELECT
+                        library_uuid
+                    FROM
+                        libraries
+                    WHERE
+                        gid = ANY($1) AND library_uuid = $2
+                ) AS t1
+                INNER JOIN media ON t1.library_uuid = media.library_uuid
+            WHERE
+                media.hidden = TRUE
+                AND media.deleted_at IS NULL"#;
+
+        let count: i64 = conn
+            .query_one_scalar(
+                statement,
+                &[&gid.into_iter().collect::<Vec<String>>(), &library_uuid],
+            )
+            .await?;
+
+        debug!({ count }, "counted hidden media in library");
+
+        Ok(count.try_into()?)
+    }
 }
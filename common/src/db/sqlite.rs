@@ -0,0 +1,3969 @@
+use std::{
+    collections::HashSet,
+    path::{Path, PathBuf},
+    sync::{Arc, Mutex},
+    time::{SystemTime, UNIX_EPOCH},
+};
+
+use anyhow::Result;
+use async_trait::async_trait;
+use regex::Regex;
+use rusqlite::{
+    Connection, OptionalExtension, ToSql,
+    functions::{Context, FunctionFlags},
+};
+use serde::{Deserialize, Serialize};
+use tokio::task::spawn_blocking;
+use tracing::{debug, error, info, instrument};
+use uuid::Uuid;
+
+use crate::{
+    config::ESConfig,
+    db::{
+        ActivityRow, DbBackend, MediaByCHash, MediaByPath, ShareLinkRow, TaskRow, access_source,
+        date_bucket, hamming_distance, renamed_tags, share_target_columns, updated_tags,
+    },
+};
+use api::{
+    UuidSource,
+    activity::Activity,
+    auth::{ApiKey, ApiKeyUuid},
+    collection::{Collection, CollectionUpdate, CollectionUuid},
+    comment::{Comment, CommentUuid},
+    fold_set,
+    library::{Library, LibrarySummary, LibraryUpdate, LibraryUuid},
+    media::{Media, MediaAccess, MediaMetadata, MediaUpdate, MediaUuid},
+    search::SearchFilter,
+    share::{ShareLink, ShareLinkUuid, ShareTarget},
+    sort::SortOrder,
+    stats::{DateBucket, DateGranularity, Stats},
+    task::{Task, TaskLibrary},
+    unfold_set,
+};
+
+// sqlite backend
+//
+// this is intended for small, single-user deployments where running a separate database
+// server is more trouble than it is worth.  the layout mirrors the mariadb backend, including
+// the set folding for tags, with a few differences:
+//
+//  * uuids are generated by the application rather than the database
+//  * the group check matches whole entries in the folded gid set, not arbitrary substrings
+//  * BIG_HAM and REGEXP are provided as scalar functions registered on the connection
+//
+// rusqlite is synchronous, so every call runs on the blocking pool while holding the only
+// connection.  this serializes all database access, which also means that we don't need the
+// manual table locks used by the mariadb backend.
+#[derive(Clone, Debug, Deserialize, Serialize)]
+pub struct SqliteConfig {
+    pub path: PathBuf,
+}
+
+const SCHEMA: &str = r"
+    CREATE TABLE IF NOT EXISTS libraries (
+        library_uuid BLOB PRIMARY KEY,
+        path TEXT NOT NULL UNIQUE,
+        name TEXT NOT NULL DEFAULT '',
+        note TEXT NOT NULL DEFAULT '',
+        uid TEXT NOT NULL,
+        gid TEXT NOT NULL,
+        count INTEGER NOT NULL
+    );
+
+    CREATE TABLE IF NOT EXISTS media (
+        media_uuid BLOB PRIMARY KEY,
+        library_uuid BLOB NOT NULL,
+        path TEXT NOT NULL,
+        size INTEGER NOT NULL,
+        chash TEXT NOT NULL,
+        phash TEXT NOT NULL,
+        mtime INTEGER NOT NULL,
+        hidden INTEGER NOT NULL,
+        date TEXT NOT NULL,
+        note TEXT NOT NULL,
+        tags TEXT NOT NULL,
+        media_type TEXT NOT NULL,
+        latitude REAL,
+        longitude REAL,
+        width INTEGER,
+        height INTEGER,
+        camera TEXT,
+        orientation INTEGER,
+        capture_time TEXT,
+        duration_secs REAL,
+        codec TEXT,
+        deleted_at INTEGER,
+        UNIQUE (library_uuid, path)
+    );
+
+    CREATE INDEX IF NOT EXISTS media_chash ON media (library_uuid, chash);
+
+    CREATE TABLE IF NOT EXISTS comments (
+        comment_uuid BLOB PRIMARY KEY,
+        media_uuid BLOB NOT NULL,
+        uid TEXT NOT NULL,
+        date INTEGER NOT NULL,
+        text TEXT NOT NULL,
+        edited INTEGER
+    );
+
+    CREATE TABLE IF NOT EXISTS api_keys (
+        key_uuid BLOB PRIMARY KEY,
+        uid TEXT NOT NULL,
+        name TEXT NOT NULL,
+        key_hash TEXT NOT NULL UNIQUE,
+        created INTEGER NOT NULL
+    );
+
+    CREATE TABLE IF NOT EXISTS share_links (
+        share_uuid BLOB PRIMARY KEY,
+        uid TEXT NOT NULL,
+        token_hash TEXT NOT NULL UNIQUE,
+        media_uuid BLOB,
+        collection_uuid BLOB,
+        created INTEGER NOT NULL,
+        expires_at INTEGER NOT NULL
+    );
+
+    CREATE TABLE IF NOT EXISTS tasks (
+        task_id INTEGER PRIMARY KEY AUTOINCREMENT,
+        library_uuid BLOB,
+        task_type TEXT NOT NULL,
+        uid TEXT,
+        status TEXT NOT NULL,
+        warnings INTEGER,
+        start_time INTEGER NOT NULL,
+        end_time INTEGER NOT NULL,
+        summary TEXT
+    );
+
+    CREATE INDEX IF NOT EXISTS tasks_library ON tasks (library_uuid, end_time);
+
+    CREATE TABLE IF NOT EXISTS collections (
+        collection_uuid BLOB PRIMARY KEY,
+        uid TEXT NOT NULL,
+        gid TEXT NOT NULL,
+        name TEXT NOT NULL,
+        note TEXT NOT NULL,
+        tags TEXT NOT NULL,
+        cover BLOB,
+        parent_uuid BLOB,
+        UNIQUE (uid, name)
+    );
+
+    CREATE INDEX IF NOT EXISTS collections_parent ON collections (parent_uuid);
+
+    CREATE TABLE IF NOT EXISTS collection_contents (
+        id INTEGER PRIMARY KEY AUTOINCREMENT,
+        media_uuid BLOB NOT NULL,
+        collection_uuid BLOB NOT NULL,
+        position INTEGER NOT NULL DEFAULT 0,
+        UNIQUE (media_uuid, collection_uuid)
+    );
+";
+
+// a gid matches if it is one of the entries in the folded set, which avoids the substring
+// false positives that INSTR() has
+const GID_CHECK: &str = "instr('|' || :gid || '|', '|' || gid || '|') > 0";
+
+type BoxError = Box<dyn std::error::Error + Send + Sync + 'static>;
+
+// BIG_HAM from mariadb, see hamming_distance()
+fn big_ham(ctx: &Context) -> rusqlite::Result<i64> {
+    let a = ctx.get::<String>(0)?;
+    let b = ctx.get::<String>(1)?;
+
+    hamming_distance(&a, &b)
+        .ok_or_else(|| rusqlite::Error::UserFunctionError("invalid phash".into()))
+}
+
+// backs the REGEXP operator, caching the compiled pattern for the duration of the statement
+fn regexp(ctx: &Context) -> rusqlite::Result<bool> {
+    let regex: Arc<Regex> = ctx.get_or_create_aux(0, |vr| -> Result<_, BoxError> {
+        Ok(Regex::new(vr.as_str()?)?)
+    })?;
+
+    let text = ctx
+        .get_raw(1)
+        .as_str()
+        .map_err(|err| rusqlite::Error::UserFunctionError(err.into()))?;
+
+    Ok(regex.is_match(text))
+}
+
+fn parse_metadata(media_type: &str) -> Result<MediaMetadata> {
+    media_type.parse::<MediaMetadata>().map_err(|_| {
+        error!("invalid media record");
+        anyhow::Error::msg(format!("invalid media_type {media_type}"))
+    })
+}
+
+fn stored_tags(conn: &Connection, media_uuid: &Uuid) -> Result<Option<String>> {
+    Ok(conn
+        .query_row(
+            "SELECT tags FROM media WHERE media_uuid = :media_uuid",
+            &[(":media_uuid", media_uuid)],
+            |row| row.get::<_, String>(0),
+        )
+        .optional()?)
+}
+
+// inserts a single media row, returning 0 if the path is already in the library
+fn insert_media(conn: &Connection, media_uuid: &Uuid, media: Media) -> Result<usize> {
+    let tags = fold_set(media.tags)?;
+
+    Ok(conn
+        .prepare_cached(
+            r"
+            INSERT OR IGNORE INTO media (media_uuid, library_uuid, path, size, chash, phash, mtime, hidden, date, note, tags, media_type, latitude, longitude, width, height, camera, orientation, capture_time, duration_secs, codec)
+            VALUES (:media_uuid, :library_uuid, :path, :size, :chash, :phash, :mtime, :hidden, :date, :note, :tags, :media_type, :latitude, :longitude, :width, :height, :camera, :orientation, :capture_time, :duration_secs, :codec)",
+        )?
+        .execute(&[
+            (":media_uuid", media_uuid as &dyn ToSql),
+            (":library_uuid", &media.library_uuid.value()),
+            (":path", &media.path),
+            (":size", &media.size),
+            (":chash", &media.chash),
+            (":phash", &media.phash),
+            (":mtime", &media.mtime),
+            (":hidden", &media.hidden),
+            (":date", &media.date),
+            (":note", &media.note),
+            (":tags", &tags),
+            (":media_type", &media.metadata.to_string()),
+            (":latitude", &media.latitude),
+            (":longitude", &media.longitude),
+            (":width", &media.width),
+            (":height", &media.height),
+            (":camera", &media.camera),
+            (":orientation", &media.orientation),
+            (":capture_time", &media.capture_time),
+            (":duration_secs", &media.duration_secs),
+            (":codec", &media.codec),
+        ])?)
+}
+
+// rusqlite expects the leading colon to be part of the parameter name
+fn filter_params(filter: &[(String, String)]) -> Vec<(String, &dyn ToSql)> {
+    filter
+        .iter()
+        .map(|(name, value)| (format!(":{name}"), value as &dyn ToSql))
+        .collect()
+}
+
+// expects the columns in the order that ShareLinkRow lists them
+fn share_link_row(row: &rusqlite::Row) -> rusqlite::Result<ShareLinkRow> {
+    Ok(ShareLinkRow {
+        share_uuid: row.get(0)?,
+        uid: row.get(1)?,
+        media_uuid: row.get(2)?,
+        collection_uuid: row.get(3)?,
+        created: row.get(4)?,
+        expires_at: row.get(5)?,
+    })
+}
+
+pub struct SqliteBackend {
+    conn: Arc<Mutex<Connection>>,
+}
+
+impl UuidSource for SqliteBackend {}
+
+impl SqliteBackend {
+    async fn call<T, F>(&self, f: F) -> Result<T>
+    where
+        T: Send + 'static,
+        F: FnOnce(&mut Connection) -> Result<T> + Send + 'static,
+    {
+        let conn = self.conn.clone();
+
+        spawn_blocking(move || {
+            let mut conn = conn
+                .lock()
+                .map_err(|_| anyhow::Error::msg("sqlite connection mutex poisoned"))?;
+
+            f(&mut conn)
+        })
+        .await?
+    }
+
+    // open (or create) the database at path, which may also be ":memory:"
+    pub async fn open(path: &Path) -> Result<Self> {
+        let path = path.to_path_buf();
+
+        let conn = spawn_blocking(move || {
+            let conn = Connection::open(&path)?;
+
+            let flags = FunctionFlags::SQLITE_UTF8 | FunctionFlags::SQLITE_DETERMINISTIC;
+
+            conn.create_scalar_function("big_ham", 2, flags, big_ham)?;
+            conn.create_scalar_function("regexp", 2, flags, regexp)?;
+
+            conn.execute_batch(SCHEMA)?;
+
+            Result::<Connection>::Ok(conn)
+        })
+        .await??;
+
+        Ok(Self {
+            conn: Arc::new(Mutex::new(conn)),
+        })
+    }
+
+    // the uuid newtypes are only constructable from a UuidSource, which the closures passed
+    // to call() can't borrow, so the conversions happen on the way back out
+    fn media_uuids(&self, data: Vec<Uuid>) -> Vec<MediaUuid> {
+        data.into_iter()
+            .map(|uuid| MediaUuid::from_value(self, uuid))
+            .collect()
+    }
+}
+
+#[async_trait]
+impl DbBackend for SqliteBackend {
+    async fn new(config: Arc<ESConfig>) -> Result<Self> {
+        info!("opening SQLite database");
+
+        let config = config
+            .sqlite
+            .clone()
+            .ok_or_else(|| anyhow::Error::msg("sqlite config not present"))?;
+
+        Self::open(&config.path).await
+    }
+
+    #[instrument(skip(self))]
+    async fn ping(&self) -> Result<()> {
+        self.call(|conn| {
+            conn.query_row("SELECT 1", [], |_| Ok(()))?;
+
+            Ok(())
+        })
+        .await
+    }
+
+    #[instrument(skip(self))]
+    async fn media_access_groups(&self, media_uuid: MediaUuid) -> Result<HashSet<String>> {
+        debug!("finding media access groups");
+
+        let data = self
+            .media_access(media_uuid)
+            .await?
+            .into_iter()
+            .map(|v| v.gid)
+            .collect::<HashSet<String>>();
+
+        debug!({ groups = ?data }, "found groups");
+
+        Ok(data)
+    }
+
+    #[instrument(skip(self))]
+    async fn media_access(&self, media_uuid: MediaUuid) -> Result<Vec<MediaAccess>> {
+        debug!("finding media access");
+
+        let media_uuid = media_uuid.value();
+
+        // for a given media_uuid, find all gids that match either:
+        //  * if the media is not hidden, any collection that contains the media
+        //  * the library that contains that media
+        //
+        // along with whichever of the two it was
+        let data = self
+            .call(move |conn| {
+                let mut stmt = conn.prepare_cached(
+                    r"
+                    SELECT
+                        collections.gid,
+                        collections.collection_uuid,
+                        NULL
+                    FROM
+                        collections
+                    INNER JOIN collection_contents ON collections.collection_uuid = collection_contents.collection_uuid
+                    INNER JOIN media ON collection_contents.media_uuid = media.media_uuid
+                    WHERE
+                        media.media_uuid = :media_uuid AND media.hidden = FALSE AND media.deleted_at IS NULL
+                    UNION ALL
+                    SELECT
+                        libraries.gid,
+                        NULL,
+                        libraries.library_uuid
+                    FROM
+                        libraries
+                    INNER JOIN media ON libraries.library_uuid = media.library_uuid
+                    WHERE
+                        media.media_uuid = :media_uuid",
+                )?;
+
+                let data = stmt
+                    .query_map(&[(":media_uuid", &media_uuid)], |row| {
+                        Ok((
+                            row.get::<_, String>(0)?,
+                            row.get::<_, Option<Uuid>>(1)?,
+                            row.get::<_, Option<Uuid>>(2)?,
+                        ))
+                    })?
+                    .collect::<Result<Vec<_>, rusqlite::Error>>()?;
+
+                Ok(data)
+            })
+            .await?;
+
+        let data = data
+            .into_iter()
+            .map(|(gid, collection_uuid, library_uuid)| {
+                Ok(MediaAccess {
+                    gid,
+                    source: access_source(
+                        collection_uuid.map(|v| CollectionUuid::from_value(self, v)),
+                        library_uuid.map(|v| LibraryUuid::from_value(self, v)),
+                    )?,
+                })
+            })
+            .collect::<Result<Vec<MediaAccess>>>()?;
+
+        debug!({ count = data.len() }, "found media access");
+
+        Ok(data)
+    }
+
+    // media queries
+    #[instrument(skip(self, media))]
+    async fn add_media(&self, media: Media) -> Result<MediaUuid> {
+        debug!({ media_path = media.path }, "adding media");
+
+        let media_uuid = Uuid::now_v7();
+        let media_path = media.path.clone();
+
+        let count = self
+            .call(move |conn| insert_media(conn, &media_uuid, media))
+            .await?;
+
+        if count == 0 {
+            error!({ media_path = media_path }, "failed to add media");
+            return Err(anyhow::Error::msg("failed to add media"));
+        }
+
+        debug!({ media_path = media_path, %media_uuid }, "added media");
+
+        Ok(MediaUuid::from_value(self, media_uuid))
+    }
+
+    #[instrument(skip_all)]
+    async fn add_media_batch(&self, media: Vec<Media>) -> Result<Vec<Option<MediaUuid>>> {
+        debug!({ count = media.len() }, "adding media batch");
+
+        let data = self
+            .call(move |conn| {
+                let tx = conn.transaction()?;
+
+                let mut data = Vec::with_capacity(media.len());
+
+                for media in media {
+                    let media_uuid = Uuid::now_v7();
+
+                    match insert_media(&tx, &media_uuid, media)? {
+                        0 => data.push(None),
+                        _ => data.push(Some(media_uuid)),
+                    }
+                }
+
+                tx.commit()?;
+
+                Ok(data)
+            })
+            .await?;
+
+        debug!(
+            { added = data.iter().flatten().count() },
+            "added media batch"
+        );
+
+        Ok(data
+            .into_iter()
+            .map(|media_uuid| media_uuid.map(|v| MediaUuid::from_value(self, v)))
+            .collect())
+    }
+
+    #[instrument(skip(self))]
+    async fn get_media(
+        &self,
+        media_uuid: MediaUuid,
+    ) -> Result<Option<(Media, Vec<CollectionUuid>, Vec<CommentUuid>)>> {
+        debug!("getting media details");
+
+        let uuid = media_uuid.value();
+
+        let result = self
+            .call(move |conn| {
+                let media_data = conn
+                    .prepare_cached(
+                        r"
+                        SELECT library_uuid, path, size, chash, phash, mtime, hidden, date, note, tags, media_type, latitude, longitude, width, height, camera, orientation, capture_time, duration_secs, codec FROM media WHERE media_uuid = :media_uuid",
+                    )?
+                    .query_row(&[(":media_uuid", &uuid)], |row| {
+                        Ok((
+                            row.get::<_, Uuid>(0)?,
+                            row.get::<_, String>(1)?,
+                            row.get::<_, u64>(2)?,
+                            row.get::<_, String>(3)?,
+                            row.get::<_, String>(4)?,
+                            row.get::<_, u64>(5)?,
+                            row.get::<_, bool>(6)?,
+                            row.get::<_, String>(7)?,
+                            row.get::<_, String>(8)?,
+                            row.get::<_, String>(9)?,
+                            row.get::<_, String>(10)?,
+                            row.get::<_, Option<f64>>(11)?,
+                            row.get::<_, Option<f64>>(12)?,
+                            row.get::<_, Option<u32>>(13)?,
+                            row.get::<_, Option<u32>>(14)?,
+                            row.get::<_, Option<String>>(15)?,
+                            row.get::<_, Option<u32>>(16)?,
+                            row.get::<_, Option<String>>(17)?,
+                            row.get::<_, Option<f64>>(18)?,
+                            row.get::<_, Option<String>>(19)?,
+                        ))
+                    })
+                    .optional()?;
+
+                let media_data = match media_data {
+                    Some(v) => v,
+                    None => return Ok(None),
+                };
+
+                let collection_data = conn
+                    .prepare_cached(
+                        r"
+                        SELECT collection_uuid FROM collection_contents WHERE media_uuid = :media_uuid",
+                    )?
+                    .query_map(&[(":media_uuid", &uuid)], |row| row.get::<_, Uuid>(0))?
+                    .collect::<Result<Vec<Uuid>, rusqlite::Error>>()?;
+
+                let comment_data = conn
+                    .prepare_cached(
+                        r"
+                        SELECT comment_uuid FROM comments WHERE media_uuid = :media_uuid",
+                    )?
+                    .query_map(&[(":media_uuid", &uuid)], |row| row.get::<_, Uuid>(0))?
+                    .collect::<Result<Vec<Uuid>, rusqlite::Error>>()?;
+
+                Ok(Some((media_data, collection_data, comment_data)))
+            })
+            .await?;
+
+        let (media_data, collection_data, comment_data) = match result {
+            Some(v) => v,
+            None => return Ok(None),
+        };
+
+        debug!("found media details");
+
+        Ok(Some((
+            Media {
+                library_uuid: LibraryUuid::from_value(self, media_data.0),
+                path: media_data.1,
+                size: media_data.2,
+                chash: media_data.3,
+                phash: media_data.4,
+                mtime: media_data.5,
+                hidden: media_data.6,
+                date: media_data.7,
+                note: media_data.8,
+                tags: unfold_set(&media_data.9),
+                metadata: parse_metadata(&media_data.10)?,
+                latitude: media_data.11,
+                longitude: media_data.12,
+                width: media_data.13,
+                height: media_data.14,
+                camera: media_data.15,
+                orientation: media_data.16,
+                capture_time: media_data.17,
+                duration_secs: media_data.18,
+                codec: media_data.19,
+            },
+            collection_data
+                .into_iter()
+                .map(|uuid| CollectionUuid::from_value(self, uuid))
+                .collect(),
+            comment_data
+                .into_iter()
+                .map(|uuid| CommentUuid::from_value(self, uuid))
+                .collect(),
+        )))
+    }
+
+    #[instrument(skip(self))]
+    async fn get_media_uuids(&self) -> Result<Vec<MediaUuid>> {
+        debug!("getting all media uuids");
+
+        let data = self
+            .call(|conn| {
+                let data = conn
+                    .prepare_cached("SELECT media_uuid FROM media")?
+                    .query_map([], |row| row.get::<_, Uuid>(0))?
+                    .collect::<Result<Vec<Uuid>, rusqlite::Error>>()?;
+
+                Ok(data)
+            })
+            .await?;
+
+        let data = self.media_uuids(data);
+
+        debug!({ count = data.len() }, "found media");
+
+        Ok(data)
+    }
+
+    #[instrument(skip(self))]
+    async fn get_media_by_path(&self, path: String) -> Result<Option<MediaByPath>> {
+        debug!("searching for media by path");
+
+        let data = self
+            .call(move |conn| {
+                let data = conn
+                    .prepare_cached(
+                        "SELECT media_uuid, chash, mtime, size FROM media WHERE path = :path",
+                    )?
+                    .query_row(&[(":path", &path)], |row| {
+                        Ok((
+                            row.get::<_, Uuid>(0)?,
+                            row.get::<_, String>(1)?,
+                            row.get::<_, u64>(2)?,
+                            row.get::<_, u64>(3)?,
+                        ))
+                    })
+                    .optional()?;
+
+                Ok(data)
+            })
+            .await?;
+
+        let data = match data {
+            Some(v) => v,
+            None => return Ok(None),
+        };
+
+        debug!({ media_uuid = %data.0 }, "found media");
+
+        Ok(Some(MediaByPath {
+            media_uuid: MediaUuid::from_value(self, data.0),
+            hash: data.1,
+            mtime: data.2,
+            size: data.3,
+        }))
+    }
+
+    #[instrument(skip(self))]
+    async fn get_media_by_chash(
+        &self,
+        library_uuid: LibraryUuid,
+        chash: String,
+    ) -> Result<Option<MediaByCHash>> {
+        debug!("searching for media by content hash");
+
+        let library_uuid = library_uuid.value();
+
+        let data = self
+            .call(move |conn| {
+                let data = conn
+                    .prepare_cached(
+                        r"
+                        SELECT media_uuid, path, mtime FROM media WHERE library_uuid = :library_uuid AND chash = :chash",
+                    )?
+                    .query_row(
+                        &[
+                            (":library_uuid", &library_uuid as &dyn ToSql),
+                            (":chash", &chash),
+                        ],
+                        |row| {
+                            Ok((
+                                row.get::<_, Uuid>(0)?,
+                                row.get::<_, String>(1)?,
+                                row.get::<_, u64>(2)?,
+                            ))
+                        },
+                    )
+                    .optional()?;
+
+                Ok(data)
+            })
+            .await?;
+
+        let data = match data {
+            Some(v) => v,
+            None => return Ok(None),
+        };
+
+        debug!({ media_uuid = %data.0 }, "found media");
+
+        Ok(Some(MediaByCHash {
+            media_uuid: MediaUuid::from_value(self, data.0),
+            path: data.1,
+            mtime: data.2,
+        }))
+    }
+
+    #[instrument(skip(self, update))]
+    async fn update_media(&self, media_uuid: MediaUuid, update: MediaUpdate) -> Result<()> {
+        debug!("updating media details");
+
+        let media_uuid = media_uuid.value();
+
+        self.call(move |conn| {
+            // only one call holds the connection at a time, so nothing can land between
+            // reading the stored tags and writing them back
+            let tags = if update.edits_tags() {
+                stored_tags(conn, &media_uuid)?
+                    .map(|folded| updated_tags(&folded, &update))
+                    .transpose()?
+            } else {
+                update.tags.clone().map(fold_set).transpose()?
+            };
+
+            conn.execute(
+                r"
+                UPDATE media SET
+                    hidden = COALESCE(:hidden, hidden),
+                    date = COALESCE(:date, date),
+                    note = COALESCE(:note, note),
+                    tags = COALESCE(:tags, tags)
+                WHERE media_uuid = :media_uuid",
+                &[
+                    (":hidden", &update.hidden as &dyn ToSql),
+                    (":date", &update.date),
+                    (":note", &update.note),
+                    (":tags", &tags),
+                    (":media_uuid", &media_uuid),
+                ],
+            )?;
+
+            Ok(())
+        })
+        .await?;
+
+        debug!("updated media details");
+
+        Ok(())
+    }
+
+    #[instrument(skip(self, update))]
+    async fn batch_update_media(
+        &self,
+        media_uuids: Vec<MediaUuid>,
+        update: MediaUpdate,
+    ) -> Result<Vec<MediaUuid>> {
+        debug!(
+            { count = media_uuids.len() },
+            "batch updating media details"
+        );
+
+        if media_uuids.is_empty() {
+            return Ok(Vec::new());
+        }
+
+        let tags = if update.edits_tags() {
+            None
+        } else {
+            update.tags.clone().map(fold_set).transpose()?
+        };
+
+        // sqlite has no array parameters, so each uuid gets its own placeholder
+        let uuids = media_uuids
+            .iter()
+            .enumerate()
+            .map(|(i, media_uuid)| (format!(":media_uuid{i}"), media_uuid.value()))
+            .collect::<Vec<(String, Uuid)>>();
+
+        let query = format!(
+            r"
+            UPDATE media SET
+                hidden = COALESCE(:hidden, hidden),
+                date = COALESCE(:date, date),
+                note = COALESCE(:note, note),
+                tags = COALESCE(:tags, tags)
+            WHERE media_uuid IN ({})
+            RETURNING media_uuid",
+            uuids
+                .iter()
+                .map(|(name, _)| name.as_str())
+                .collect::<Vec<&str>>()
+                .join(", ")
+        );
+
+        let data = self
+            .call(move |conn| {
+                let tx = conn.transaction()?;
+
+                let mut params: Vec<(&str, &dyn ToSql)> = vec![
+                    (":hidden", &update.hidden),
+                    (":date", &update.date),
+                    (":note", &update.note),
+                    (":tags", &tags),
+                ];
+                params.extend(
+                    uuids
+                        .iter()
+                        .map(|(name, value)| (name.as_str(), value as &dyn ToSql)),
+                );
+
+                let data = tx
+                    .prepare(&query)?
+                    .query_map(&*params, |row| row.get::<_, Uuid>(0))?
+                    .collect::<Result<Vec<Uuid>, rusqlite::Error>>()?;
+
+                // tag edits depend on each row's current tags, so they are applied one at a time
+                if update.edits_tags() {
+                    for media_uuid in data.iter() {
+                        if let Some(folded) = stored_tags(&tx, media_uuid)? {
+                            tx.execute(
+                                "UPDATE media SET tags = :tags WHERE media_uuid = :media_uuid",
+                                &[
+                                    (":tags", &updated_tags(&folded, &update)? as &dyn ToSql),
+                                    (":media_uuid", media_uuid),
+                                ],
+                            )?;
+                        }
+                    }
+                }
+
+                tx.commit()?;
+
+                Ok(data)
+            })
+            .await?;
+
+        let data = self.media_uuids(data);
+
+        debug!({ count = data.len() }, "batch updated media details");
+
+        Ok(data)
+    }
+
+    #[instrument(skip(self))]
+    async fn replace_media_path(
+        &self,
+        media_uuid: MediaUuid,
+        path: String,
+        hash: String,
+        mtime: u64,
+        size: u64,
+    ) -> Result<()> {
+        debug!("replacing media path");
+
+        let media_uuid = media_uuid.value();
+
+        self.call(move |conn| {
+            conn.execute(
+                r"
+                UPDATE media SET path = :path, chash = :hash, mtime = :mtime, size = :size WHERE media_uuid = :media_uuid",
+                &[
+                    (":media_uuid", &media_uuid as &dyn ToSql),
+                    (":path", &path),
+                    (":hash", &hash),
+                    (":mtime", &mtime),
+                    (":size", &size),
+                ],
+            )?;
+
+            Ok(())
+        })
+        .await?;
+
+        debug!("replaced media path");
+
+        Ok(())
+    }
+
+    #[instrument(skip(self))]
+    async fn move_media(
+        &self,
+        media_uuid: MediaUuid,
+        library_uuid: LibraryUuid,
+        path: Option<String>,
+    ) -> Result<()> {
+        debug!("moving media to library");
+
+        let media_uuid = media_uuid.value();
+        let library_uuid = library_uuid.value();
+
+        self.call(move |conn| {
+            conn.execute(
+                r"
+                UPDATE media SET library_uuid = :library_uuid, path = COALESCE(:path, path) WHERE media_uuid = :media_uuid",
+                &[
+                    (":media_uuid", &media_uuid as &dyn ToSql),
+                    (":library_uuid", &library_uuid),
+                    (":path", &path),
+                ],
+            )?;
+
+            Ok(())
+        })
+        .await?;
+
+        debug!("moved media to library");
+
+        Ok(())
+    }
+
+    #[instrument(skip(self))]
+    async fn search_media(
+        &self,
+        gid: HashSet<String>,
+        filter: SearchFilter,
+        sort: SortOrder,
+        limit: Option<u64>,
+        offset: Option<u64>,
+    ) -> Result<(Vec<MediaUuid>, u64)> {
+        debug!("searching for media");
+
+        let gid = fold_set(gid)?;
+        let (sql, filter) = filter.format_sqlite("media.path, media.date, media.note, media.tags");
+
+        // for a given uid and filter, find all media that match either:
+        //  * is in a library owned by a group containing the uid
+        //  * if the media is not hidden, is in an collection owned
+        //    by a group containing the uid
+        let mut query = format!(
+            r"
+            SELECT
+                media.media_uuid
+            FROM
+                (
+                    SELECT
+                        media_uuid
+                    FROM
+                        (
+                            SELECT
+                                collection_uuid
+                            FROM
+                                collections
+                            WHERE
+                                {GID_CHECK}
+                        ) AS t1
+                        INNER JOIN collection_contents ON t1.collection_uuid = collection_contents.collection_uuid
+                    UNION
+                    SELECT
+                        media_uuid
+                    FROM
+                        (
+                            SELECT
+                                library_uuid
+                            FROM
+                                libraries
+                            WHERE
+                                {GID_CHECK}
+                        ) AS t2
+                        INNER JOIN media ON t2.library_uuid = media.library_uuid
+                ) AS t3
+                INNER JOIN media ON t3.media_uuid = media.media_uuid
+            WHERE
+                media.hidden = FALSE
+                AND media.deleted_at IS NULL"
+        );
+
+        query.push_str(&sql);
+
+        let (data, total) = self
+            .call(move |conn| {
+                let filter = filter_params(&filter);
+
+                let mut params: Vec<(&str, &dyn ToSql)> = vec![(":gid", &gid)];
+                params.extend(filter.iter().map(|(name, value)| (name.as_str(), *value)));
+
+                // the total ignores the paging, so it has to be its own query
+                let total = conn
+                    .prepare(&format!("SELECT COUNT(*) FROM ({query}) AS t4"))?
+                    .query_row(&*params, |row| row.get::<_, u64>(0))?;
+
+                // a negative limit means no limit in sqlite
+                let limit = limit.map(|v| v as i64).unwrap_or(-1);
+                let offset = offset.unwrap_or(0) as i64;
+
+                let query = format!("{query}{} LIMIT :limit OFFSET :offset", sort.format_sql());
+
+                params.push((":limit", &limit));
+                params.push((":offset", &offset));
+
+                let data = conn
+                    .prepare(&query)?
+                    .query_map(&*params, |row| row.get::<_, Uuid>(0))?
+                    .collect::<Result<Vec<Uuid>, rusqlite::Error>>()?;
+
+                Ok((data, total))
+            })
+            .await?;
+
+        let data = self.media_uuids(data);
+
+        debug!({ count = data.len(), total = total }, "found media");
+
+        Ok((data, total))
+    }
+
+    #[instrument(skip(self))]
+    async fn similar_media(
+        &self,
+        gid: HashSet<String>,
+        media_uuid: MediaUuid,
+        distance: i64,
+    ) -> Result<Vec<(MediaUuid, i64)>> {
+        // for a given uid and filter, find all media that match either:
+        //  * is in a library owned by a group containing the uid
+        //  * if the media is not hidden, is in an collection owned
+        //    by a group containing the uid
+        debug!("searching for similar media");
+
+        let gid = fold_set(gid)?;
+        let media_uuid = media_uuid.value();
+
+        let query = format!(
+            r"
+            SELECT
+                media_uuid, distance
+            FROM
+                (
+                    SELECT
+                        media.media_uuid,
+                        big_ham((SELECT phash FROM media WHERE media_uuid = :media_uuid), media.phash) AS distance
+                    FROM
+                        (
+                            SELECT
+                                media_uuid
+                            FROM
+                                (
+                                    SELECT
+                                        collection_uuid
+                                    FROM
+                                        collections
+                                    WHERE
+                                        {GID_CHECK}
+                                ) AS t1
+                                INNER JOIN collection_contents ON t1.collection_uuid = collection_contents.collection_uuid
+                            UNION
+                            SELECT
+                                media_uuid
+                            FROM
+                                (
+                                    SELECT
+                                        library_uuid
+                                    FROM
+                                        libraries
+                                    WHERE
+                                        {GID_CHECK}
+                                ) AS t2
+                                INNER JOIN media ON t2.library_uuid = media.library_uuid
+                        ) AS t3
+                        INNER JOIN media ON t3.media_uuid = media.media_uuid
+                    WHERE
+                        media.hidden = FALSE
+                        AND media.deleted_at IS NULL
+                        AND media.phash != ''
+                ) AS t4
+            WHERE
+                distance < :distance
+            ORDER BY
+                distance ASC, media_uuid ASC"
+        );
+
+        let data = self
+            .call(move |conn| {
+                let data = conn
+                    .prepare_cached(&query)?
+                    .query_map(
+                        &[
+                            (":gid", &gid as &dyn ToSql),
+                            (":media_uuid", &media_uuid),
+                            (":distance", &distance),
+                        ],
+                        |row| Ok((row.get::<_, Uuid>(0)?, row.get::<_, i64>(1)?)),
+                    )?
+                    .collect::<Result<Vec<(Uuid, i64)>, rusqlite::Error>>()?;
+
+                Ok(data)
+            })
+            .await?;
+
+        let data = data
+            .into_iter()
+            .map(|(uuid, distance)| (MediaUuid::from_value(self, uuid), distance))
+            .collect::<Vec<_>>();
+
+        debug!({ count = data.len() }, "found similar media");
+
+        Ok(data)
+    }
+
+    #[instrument(skip(self))]
+    async fn similar_phash(
+        &self,
+        gid: HashSet<String>,
+        phash: String,
+        distance: i64,
+    ) -> Result<Vec<(MediaUuid, i64)>> {
+        // see similar_media()
+        debug!("searching for media similar to a phash");
+
+        let gid = fold_set(gid)?;
+
+        let query = format!(
+            r"
+            SELECT
+                media_uuid, distance
+            FROM
+                (
+                    SELECT
+                        media.media_uuid,
+                        big_ham(:phash, media.phash) AS distance
+                    FROM
+                        (
+                            SELECT
+                                media_uuid
+                            FROM
+                                (
+                                    SELECT
+                                        collection_uuid
+                                    FROM
+                                        collections
+                                    WHERE
+                                        {GID_CHECK}
+                                ) AS t1
+                                INNER JOIN collection_contents ON t1.collection_uuid = collection_contents.collection_uuid
+                            UNION
+                            SELECT
+                                media_uuid
+                            FROM
+                                (
+                                    SELECT
+                                        library_uuid
+                                    FROM
+                                        libraries
+                                    WHERE
+                                        {GID_CHECK}
+                                ) AS t2
+                                INNER JOIN media ON t2.library_uuid = media.library_uuid
+                        ) AS t3
+                        INNER JOIN media ON t3.media_uuid = media.media_uuid
+                    WHERE
+                        media.hidden = FALSE
+                        AND media.deleted_at IS NULL
+                        AND media.phash != ''
+                ) AS t4
+            WHERE
+                distance < :distance
+            ORDER BY
+                distance ASC, media_uuid ASC"
+        );
+
+        let data = self
+            .call(move |conn| {
+                let data = conn
+                    .prepare_cached(&query)?
+                    .query_map(
+                        &[
+                            (":gid", &gid as &dyn ToSql),
+                            (":phash", &phash),
+                            (":distance", &distance),
+                        ],
+                        |row| Ok((row.get::<_, Uuid>(0)?, row.get::<_, i64>(1)?)),
+                    )?
+                    .collect::<Result<Vec<(Uuid, i64)>, rusqlite::Error>>()?;
+
+                Ok(data)
+            })
+            .await?;
+
+        let data = data
+            .into_iter()
+            .map(|(uuid, distance)| (MediaUuid::from_value(self, uuid), distance))
+            .collect::<Vec<_>>();
+
+        debug!({ count = data.len() }, "found similar media");
+
+        Ok(data)
+    }
+
+    #[instrument(skip(self))]
+    async fn random_media(
+        &self,
+        gid: HashSet<String>,
+        filter: SearchFilter,
+        count: u64,
+    ) -> Result<Vec<MediaUuid>> {
+        debug!("sampling media");
+
+        let gid = fold_set(gid)?;
+        let (sql, filter) = filter.format_sqlite("media.path, media.date, media.note, media.tags");
+
+        // ORDER BY RANDOM() has to number every match before it can pick any of them, which
+        // is fine at the sizes that sqlite deployments see
+        let mut query = format!(
+            r"
+            SELECT
+                media.media_uuid
+            FROM
+                (
+                    SELECT
+                        media_uuid
+                    FROM
+                        (
+                            SELECT
+                                collection_uuid
+                            FROM
+                                collections
+                            WHERE
+                                {GID_CHECK}
+                        ) AS t1
+                        INNER JOIN collection_contents ON t1.collection_uuid = collection_contents.collection_uuid
+                    UNION
+                    SELECT
+                        media_uuid
+                    FROM
+                        (
+                            SELECT
+                                library_uuid
+                            FROM
+                                libraries
+                            WHERE
+                                {GID_CHECK}
+                        ) AS t2
+                        INNER JOIN media ON t2.library_uuid = media.library_uuid
+                ) AS t3
+                INNER JOIN media ON t3.media_uuid = media.media_uuid
+            WHERE
+                media.hidden = FALSE
+                AND media.deleted_at IS NULL"
+        );
+
+        query.push_str(&sql);
+        query.push_str(" ORDER BY RANDOM() LIMIT :count");
+
+        let data = self
+            .call(move |conn| {
+                let filter = filter_params(&filter);
+                let count = count as i64;
+
+                let mut params: Vec<(&str, &dyn ToSql)> = vec![(":gid", &gid), (":count", &count)];
+                params.extend(filter.iter().map(|(name, value)| (name.as_str(), *value)));
+
+                let data = conn
+                    .prepare(&query)?
+                    .query_map(&*params, |row| row.get::<_, Uuid>(0))?
+                    .collect::<Result<Vec<Uuid>, rusqlite::Error>>()?;
+
+                Ok(data)
+            })
+            .await?;
+
+        let data = self.media_uuids(data);
+
+        debug!({ count = data.len() }, "sampled media");
+
+        Ok(data)
+    }
+
+    #[instrument(skip(self))]
+    async fn stats(&self, gid: HashSet<String>) -> Result<Stats> {
+        debug!("counting media");
+
+        let gid = fold_set(gid)?;
+
+        // the media are the same set as search_media(), and the aggregates over an empty
+        // set are NULL rather than 0
+        let query = format!(
+            r"
+            SELECT
+                COUNT(*),
+                COALESCE(SUM(media.media_type = 'Image'), 0),
+                COALESCE(SUM(media.media_type = 'Video'), 0),
+                COALESCE(SUM(media.media_type = 'Audio'), 0),
+                COALESCE(SUM(media.size), 0),
+                (SELECT COUNT(*) FROM collections WHERE {GID_CHECK}),
+                (SELECT COUNT(*) FROM libraries WHERE {GID_CHECK})
+            FROM
+                (
+                    SELECT
+                        media_uuid
+                    FROM
+                        (
+                            SELECT
+                                collection_uuid
+                            FROM
+                                collections
+                            WHERE
+                                {GID_CHECK}
+                        ) AS t1
+                        INNER JOIN collection_contents ON t1.collection_uuid = collection_contents.collection_uuid
+                    UNION
+                    SELECT
+                        media_uuid
+                    FROM
+                        (
+                            SELECT
+                                library_uuid
+                            FROM
+                                libraries
+                            WHERE
+                                {GID_CHECK}
+                        ) AS t2
+                        INNER JOIN media ON t2.library_uuid = media.library_uuid
+                ) AS t3
+                INNER JOIN media ON t3.media_uuid = media.media_uuid
+            WHERE
+                media.hidden = FALSE
+                AND media.deleted_at IS NULL"
+        );
+
+        let stats = self
+            .call(move |conn| {
+                let stats = conn
+                    .prepare_cached(&query)?
+                    .query_row(&[(":gid", &gid)], |row| {
+                        Ok(Stats {
+                            media: row.get(0)?,
+                            images: row.get(1)?,
+                            videos: row.get(2)?,
+                            audio: row.get(3)?,
+                            bytes: row.get(4)?,
+                            collections: row.get(5)?,
+                            libraries: row.get(6)?,
+                        })
+                    })?;
+
+                Ok(stats)
+            })
+            .await?;
+
+        debug!({ media = stats.media }, "counted media");
+
+        Ok(stats)
+    }
+
+    #[instrument(skip(self))]
+    async fn media_date_histogram(
+        &self,
+        gid: HashSet<String>,
+        granularity: DateGranularity,
+    ) -> Result<Vec<DateBucket>> {
+        debug!("counting media by date");
+
+        let gid = fold_set(gid)?;
+        let len = granularity.prefix_len();
+
+        // the same media as search_media(), grouped by a prefix of the date.  dates are
+        // free text, so anything that doesn't start with a yyyy-mm-dd is left out.
+        let query = format!(
+            r"
+            SELECT
+                SUBSTR(media.date, 1, {len}) AS bucket,
+                COUNT(*)
+            FROM
+                (
+                    SELECT
+                        media_uuid
+                    FROM
+                        (
+                            SELECT
+                                collection_uuid
+                            FROM
+                                collections
+                            WHERE
+                                {GID_CHECK}
+                        ) AS t1
+                        INNER JOIN collection_contents ON t1.collection_uuid = collection_contents.collection_uuid
+                    UNION
+                    SELECT
+                        media_uuid
+                    FROM
+                        (
+                            SELECT
+                                library_uuid
+                            FROM
+                                libraries
+                            WHERE
+                                {GID_CHECK}
+                        ) AS t2
+                        INNER JOIN media ON t2.library_uuid = media.library_uuid
+                ) AS t3
+                INNER JOIN media ON t3.media_uuid = media.media_uuid
+            WHERE
+                media.hidden = FALSE
+                AND media.deleted_at IS NULL
+                AND media.date GLOB '[0-9][0-9][0-9][0-9]-[0-9][0-9]-[0-9][0-9]*'
+            GROUP BY
+                bucket
+            ORDER BY
+                bucket ASC"
+        );
+
+        let result = self
+            .call(move |conn| {
+                let data = conn
+                    .prepare_cached(&query)?
+                    .query_map(&[(":gid", &gid)], |row| {
+                        Ok((row.get::<_, String>(0)?, row.get::<_, u64>(1)?))
+                    })?
+                    .collect::<Result<Vec<(String, u64)>, rusqlite::Error>>()?;
+
+                Ok(data)
+            })
+            .await?;
+
+        let data = result
+            .into_iter()
+            .filter_map(|(date, count)| date_bucket(date, granularity, count))
+            .collect::<Vec<DateBucket>>();
+
+        debug!({ buckets = data.len() }, "counted media by date");
+
+        Ok(data)
+    }
+
+    #[instrument(skip(self))]
+    async fn recent_activity(&self, gid: HashSet<String>, limit: u64) -> Result<Vec<Activity>> {
+        debug!("finding recent activity");
+
+        let gid = fold_set(gid)?;
+
+        // see ActivityRow for why the uuids double as timestamps
+        let query = format!(
+            r"
+            WITH accessible AS (
+                SELECT
+                    media.media_uuid
+                FROM
+                    (
+                        SELECT
+                            media_uuid
+                        FROM
+                            (
+                                SELECT
+                                    collection_uuid
+                                FROM
+                                    collections
+                                WHERE
+                                    {GID_CHECK}
+                            ) AS t1
+                            INNER JOIN collection_contents ON t1.collection_uuid = collection_contents.collection_uuid
+                        UNION
+                        SELECT
+                            media_uuid
+                        FROM
+                            (
+                                SELECT
+                                    library_uuid
+                                FROM
+                                    libraries
+                                WHERE
+                                    {GID_CHECK}
+                            ) AS t2
+                            INNER JOIN media ON t2.library_uuid = media.library_uuid
+                    ) AS t3
+                    INNER JOIN media ON t3.media_uuid = media.media_uuid
+                WHERE
+                    media.hidden = FALSE
+                    AND media.deleted_at IS NULL
+            )
+            SELECT
+                'media' AS kind,
+                media_uuid AS uuid,
+                NULL AS media_uuid
+            FROM
+                accessible
+            UNION ALL
+            SELECT
+                'collection',
+                collection_uuid,
+                NULL
+            FROM
+                collections
+            WHERE
+                {GID_CHECK}
+            UNION ALL
+            SELECT
+                'comment',
+                comments.comment_uuid,
+                comments.media_uuid
+            FROM
+                comments
+                INNER JOIN accessible ON comments.media_uuid = accessible.media_uuid
+            ORDER BY
+                uuid DESC
+            LIMIT
+                :limit"
+        );
+
+        let data = self
+            .call(move |conn| {
+                let data = conn
+                    .prepare_cached(&query)?
+                    .query_map(&[(":gid", &gid as &dyn ToSql), (":limit", &limit)], |row| {
+                        Ok(ActivityRow {
+                            kind: row.get(0)?,
+                            uuid: row.get(1)?,
+                            media_uuid: row.get(2)?,
+                        })
+                    })?
+                    .collect::<Result<Vec<ActivityRow>, rusqlite::Error>>()?;
+
+                Ok(data)
+            })
+            .await?;
+
+        let data = data
+            .into_iter()
+            .map(|row| row.into_activity(self))
+            .collect::<Result<Vec<Activity>>>()?;
+
+        debug!({ count = data.len() }, "found recent activity");
+
+        Ok(data)
+    }
+
+    // trash queries
+    #[instrument(skip(self))]
+    async fn soft_delete_media(&self, media_uuid: MediaUuid) -> Result<()> {
+        debug!("moving media to trash");
+
+        let media_uuid = media_uuid.value();
+        let deleted_at = SystemTime::now().duration_since(UNIX_EPOCH)?.as_secs();
+
+        self.call(move |conn| {
+            conn.execute(
+                "UPDATE media SET deleted_at = :deleted_at WHERE media_uuid = :media_uuid AND deleted_at IS NULL",
+                &[
+                    (":deleted_at", &deleted_at as &dyn ToSql),
+                    (":media_uuid", &media_uuid),
+                ],
+            )?;
+
+            Ok(())
+        })
+        .await?;
+
+        debug!("moved media to trash");
+
+        Ok(())
+    }
+
+    #[instrument(skip(self))]
+    async fn restore_media(&self, media_uuid: MediaUuid) -> Result<()> {
+        debug!("restoring media from trash");
+
+        let media_uuid = media_uuid.value();
+
+        self.call(move |conn| {
+            conn.execute(
+                "UPDATE media SET deleted_at = NULL WHERE media_uuid = :media_uuid",
+                &[(":media_uuid", &media_uuid)],
+            )?;
+
+            Ok(())
+        })
+        .await?;
+
+        debug!("restored media from trash");
+
+        Ok(())
+    }
+
+    #[instrument(skip(self))]
+    async fn purge_media(&self, media_uuid: MediaUuid) -> Result<()> {
+        debug!("purging media");
+
+        let media_uuid = media_uuid.value();
+
+        let count = self
+            .call(move |conn| {
+                let tx = conn.transaction()?;
+
+                let count = tx.execute(
+                    "DELETE FROM media WHERE media_uuid = :media_uuid AND deleted_at IS NOT NULL",
+                    &[(":media_uuid", &media_uuid)],
+                )?;
+
+                // anything else that refers to the media only goes if the media did
+                if count > 0 {
+                    tx.execute(
+                        "DELETE FROM collection_contents WHERE media_uuid = :media_uuid",
+                        &[(":media_uuid", &media_uuid)],
+                    )?;
+
+                    tx.execute(
+                        "DELETE FROM comments WHERE media_uuid = :media_uuid",
+                        &[(":media_uuid", &media_uuid)],
+                    )?;
+
+                    tx.execute(
+                        "UPDATE collections SET cover = NULL WHERE cover = :media_uuid",
+                        &[(":media_uuid", &media_uuid)],
+                    )?;
+                }
+
+                tx.commit()?;
+
+                Ok(count)
+            })
+            .await?;
+
+        if count == 0 {
+            return Err(anyhow::Error::msg("media is not in the trash"));
+        }
+
+        debug!("purged media");
+
+        Ok(())
+    }
+
+    #[instrument(skip(self))]
+    async fn search_trash(
+        &self,
+        gid: HashSet<String>,
+        filter: SearchFilter,
+    ) -> Result<Vec<MediaUuid>> {
+        debug!("searching trash");
+
+        let gid = fold_set(gid)?;
+        let (filter_sql, filter) =
+            filter.format_sqlite("media.path, media.date, media.note, media.tags");
+
+        // for a given uid and filter, find all soft-deleted media in libraries owned by a group
+        // containing the uid.  collection access does not extend to the trash.
+        let mut query = format!(
+            r"
+            SELECT
+                media.media_uuid
+            FROM
+                (
+                    SELECT
+                        library_uuid
+                    FROM
+                        libraries
+                    WHERE
+                        {GID_CHECK}
+                ) AS t1
+                INNER JOIN media ON t1.library_uuid = media.library_uuid
+            WHERE
+                media.deleted_at IS NOT NULL"
+        );
+
+        query.push_str(&filter_sql);
+        query.push_str(" ORDER BY media.deleted_at DESC, media.media_uuid DESC");
+
+        let data = self
+            .call(move |conn| {
+                let filter = filter_params(&filter);
+
+                let mut params: Vec<(&str, &dyn ToSql)> = vec![(":gid", &gid)];
+                params.extend(filter.iter().map(|(name, value)| (name.as_str(), *value)));
+
+                let data = conn
+                    .prepare(&query)?
+                    .query_map(&*params, |row| row.get::<_, Uuid>(0))?
+                    .collect::<Result<Vec<Uuid>, rusqlite::Error>>()?;
+
+                Ok(data)
+            })
+            .await?;
+
+        let data = self.media_uuids(data);
+
+        debug!({ count = data.len() }, "found media in trash");
+
+        Ok(data)
+    }
+
+    // tag queries
+    #[instrument(skip(self))]
+    async fn rename_tag(&self, gid: HashSet<String>, from: String, to: String) -> Result<u64> {
+        debug!("renaming tag");
+
+        let gid = fold_set(gid)?;
+
+        let media_query = format!(
+            r"
+            SELECT media_uuid, tags FROM media
+            WHERE
+                library_uuid IN (SELECT library_uuid FROM libraries WHERE {GID_CHECK})
+                AND instr(tags, :from) > 0"
+        );
+
+        let collection_query = format!(
+            r"
+            SELECT collection_uuid, tags FROM collections
+            WHERE
+                {GID_CHECK}
+                AND instr(tags, :from) > 0"
+        );
+
+        let count = self
+            .call(move |conn| {
+                let tx = conn.transaction()?;
+
+                let mut count = 0;
+
+                for (query, statement) in [
+                    (
+                        media_query,
+                        "UPDATE media SET tags = :tags WHERE media_uuid = :uuid",
+                    ),
+                    (
+                        collection_query,
+                        "UPDATE collections SET tags = :tags WHERE collection_uuid = :uuid",
+                    ),
+                ] {
+                    // instr() only narrows down the candidates, renamed_tags() does the real match
+                    let rows = tx
+                        .prepare(&query)?
+                        .query_map(&[(":gid", &gid), (":from", &from)], |row| {
+                            Ok((row.get::<_, Uuid>(0)?, row.get::<_, String>(1)?))
+                        })?
+                        .collect::<Result<Vec<(Uuid, String)>, rusqlite::Error>>()?;
+
+                    for (uuid, folded) in rows {
+                        if let Some(tags) = renamed_tags(&folded, &from, &to)? {
+                            tx.execute(
+                                statement,
+                                &[(":tags", &tags as &dyn ToSql), (":uuid", &uuid)],
+                            )?;
+
+                            count += 1;
+                        }
+                    }
+                }
+
+                tx.commit()?;
+
+                Ok(count)
+            })
+            .await?;
+
+        debug!({ count = count }, "renamed tag");
+
+        Ok(count)
+    }
+
+    // comment queries
+    #[instrument(skip(self, comment))]
+    async fn add_comment(&self, comment: Comment) -> Result<CommentUuid> {
+        debug!({ media_uuid = %comment.media_uuid }, "adding comment");
+
+        let comment_uuid = Uuid::now_v7();
+        let media_uuid = comment.media_uuid;
+        let date = SystemTime::now().duration_since(UNIX_EPOCH)?.as_secs();
+
+        self.call(move |conn| {
+            conn.execute(
+                r"
+                INSERT INTO comments (comment_uuid, media_uuid, uid, date, text)
+                VALUES (:comment_uuid, :media_uuid, :uid, :date, :text)",
+                &[
+                    (":comment_uuid", &comment_uuid as &dyn ToSql),
+                    (":media_uuid", &comment.media_uuid.value()),
+                    (":uid", &comment.uid),
+                    (":date", &date),
+                    (":text", &comment.text),
+                ],
+            )?;
+
+            Ok(())
+        })
+        .await?;
+
+        debug!({media_uuid = %media_uuid, comment_uuid = %comment_uuid}, "added comment");
+
+        Ok(CommentUuid::from_value(self, comment_uuid))
+    }
+
+    #[instrument(skip(self))]
+    async fn get_comment(&self, comment_uuid: CommentUuid) -> Result<Option<Comment>> {
+        debug!("getting comment details");
+
+        let comment_uuid = comment_uuid.value();
+
+        let data = self
+            .call(move |conn| {
+                let data = conn
+                    .prepare_cached(
+                        r"
+                        SELECT media_uuid, uid, date, text, edited FROM comments WHERE comment_uuid = :comment_uuid",
+                    )?
+                    .query_row(&[(":comment_uuid", &comment_uuid)], |row| {
+                        Ok((
+                            row.get::<_, Uuid>(0)?,
+                            row.get::<_, String>(1)?,
+                            row.get::<_, u64>(2)?,
+                            row.get::<_, String>(3)?,
+                            row.get::<_, Option<u64>>(4)?,
+                        ))
+                    })
+                    .optional()?;
+
+                Ok(data)
+            })
+            .await?;
+
+        let data = match data {
+            Some(v) => v,
+            None => return Ok(None),
+        };
+
+        debug!("found comment details");
+
+        Ok(Some(Comment {
+            media_uuid: MediaUuid::from_value(self, data.0),
+            uid: data.1,
+            date: data.2,
+            text: data.3,
+            edited: data.4,
+        }))
+    }
+
+    #[instrument(skip(self))]
+    async fn get_comment_uuids(&self) -> Result<Vec<CommentUuid>> {
+        debug!("getting all comment uuids");
+
+        let data = self
+            .call(|conn| {
+                let data = conn
+                    .prepare_cached("SELECT comment_uuid FROM comments")?
+                    .query_map([], |row| row.get::<_, Uuid>(0))?
+                    .collect::<Result<Vec<Uuid>, rusqlite::Error>>()?;
+
+                Ok(data)
+            })
+            .await?;
+
+        let data = data
+            .into_iter()
+            .map(|uuid| CommentUuid::from_value(self, uuid))
+            .collect::<Vec<CommentUuid>>();
+
+        debug!({ count = data.len() }, "found comments");
+
+        Ok(data)
+    }
+
+    #[instrument(skip(self))]
+    async fn delete_comment(&self, comment_uuid: CommentUuid) -> Result<()> {
+        debug!("deleting comment");
+
+        let comment_uuid = comment_uuid.value();
+
+        self.call(move |conn| {
+            conn.execute(
+                "DELETE FROM comments WHERE comment_uuid = :comment_uuid",
+                &[(":comment_uuid", &comment_uuid)],
+            )?;
+
+            Ok(())
+        })
+        .await?;
+
+        debug!("deleted comment");
+
+        Ok(())
+    }
+
+    #[instrument(skip(self, text))]
+    async fn update_comment(&self, comment_uuid: CommentUuid, text: Option<String>) -> Result<()> {
+        debug!("updating comment");
+
+        let comment_uuid = comment_uuid.value();
+        let now = SystemTime::now().duration_since(UNIX_EPOCH)?.as_secs();
+
+        // rewriting the same text isn't an edit, so it leaves the timestamp alone
+        self.call(move |conn| {
+            conn.execute(
+                r"
+                UPDATE comments SET
+                    edited = CASE WHEN :text IS NOT NULL AND :text != text THEN :now ELSE edited END,
+                    text = COALESCE(:text, text)
+                WHERE comment_uuid = :comment_uuid",
+                &[
+                    (":text", &text as &dyn ToSql),
+                    (":now", &now),
+                    (":comment_uuid", &comment_uuid),
+                ],
+            )?;
+
+            Ok(())
+        })
+        .await?;
+
+        debug!("updated comment");
+
+        Ok(())
+    }
+
+    // api key queries
+    #[instrument(skip(self, key_hash))]
+    async fn add_api_key(&self, uid: String, name: String, key_hash: String) -> Result<ApiKeyUuid> {
+        debug!("adding api key");
+
+        let key_uuid = Uuid::now_v7();
+        let created = SystemTime::now().duration_since(UNIX_EPOCH)?.as_secs();
+
+        self.call(move |conn| {
+            conn.execute(
+                r"
+                INSERT INTO api_keys (key_uuid, uid, name, key_hash, created)
+                VALUES (:key_uuid, :uid, :name, :key_hash, :created)",
+                &[
+                    (":key_uuid", &key_uuid as &dyn ToSql),
+                    (":uid", &uid),
+                    (":name", &name),
+                    (":key_hash", &key_hash),
+                    (":created", &created),
+                ],
+            )?;
+
+            Ok(())
+        })
+        .await?;
+
+        debug!({ key_uuid = %key_uuid }, "added api key");
+
+        Ok(ApiKeyUuid::from_value(self, key_uuid))
+    }
+
+    #[instrument(skip_all)]
+    async fn get_api_key_uid(&self, key_hash: String) -> Result<Option<String>> {
+        debug!("looking up api key");
+
+        self.call(move |conn| {
+            let data = conn
+                .prepare_cached("SELECT uid FROM api_keys WHERE key_hash = :key_hash")?
+                .query_row(&[(":key_hash", &key_hash)], |row| row.get::<_, String>(0))
+                .optional()?;
+
+            Ok(data)
+        })
+        .await
+    }
+
+    #[instrument(skip(self))]
+    async fn get_api_keys(&self, uid: String) -> Result<Vec<ApiKey>> {
+        debug!("getting api keys");
+
+        let query_uid = uid.clone();
+
+        let data = self
+            .call(move |conn| {
+                let data = conn
+                    .prepare_cached(
+                        "SELECT key_uuid, name, created FROM api_keys WHERE uid = :uid",
+                    )?
+                    .query_map(&[(":uid", &query_uid)], |row| {
+                        Ok((
+                            row.get::<_, Uuid>(0)?,
+                            row.get::<_, String>(1)?,
+                            row.get::<_, u64>(2)?,
+                        ))
+                    })?
+                    .collect::<Result<Vec<(Uuid, String, u64)>, rusqlite::Error>>()?;
+
+                Ok(data)
+            })
+            .await?;
+
+        let data = data
+            .into_iter()
+            .map(|(key_uuid, name, created)| ApiKey {
+                key_uuid: ApiKeyUuid::from_value(self, key_uuid),
+                uid: uid.clone(),
+                name,
+                created,
+            })
+            .collect::<Vec<ApiKey>>();
+
+        debug!({ count = data.len() }, "found api keys");
+
+        Ok(data)
+    }
+
+    #[instrument(skip(self))]
+    async fn delete_api_key(&self, uid: String, key_uuid: ApiKeyUuid) -> Result<()> {
+        debug!("deleting api key");
+
+        let key_uuid = key_uuid.value();
+
+        self.call(move |conn| {
+            conn.execute(
+                "DELETE FROM api_keys WHERE key_uuid = :key_uuid AND uid = :uid",
+                &[(":key_uuid", &key_uuid as &dyn ToSql), (":uid", &uid)],
+            )?;
+
+            Ok(())
+        })
+        .await?;
+
+        debug!("deleted api key");
+
+        Ok(())
+    }
+
+    // share link queries
+    #[instrument(skip(self, token_hash))]
+    async fn add_share_link(
+        &self,
+        uid: String,
+        target: ShareTarget,
+        token_hash: String,
+        expires_at: u64,
+    ) -> Result<ShareLinkUuid> {
+        debug!("adding share link");
+
+        let share_uuid = Uuid::now_v7();
+        let created = SystemTime::now().duration_since(UNIX_EPOCH)?.as_secs();
+        let (media_uuid, collection_uuid) = share_target_columns(target);
+
+        self.call(move |conn| {
+            conn.execute(
+                r"
+                INSERT INTO share_links (share_uuid, uid, token_hash, media_uuid, collection_uuid, created, expires_at)
+                VALUES (:share_uuid, :uid, :token_hash, :media_uuid, :collection_uuid, :created, :expires_at)",
+                &[
+                    (":share_uuid", &share_uuid as &dyn ToSql),
+                    (":uid", &uid),
+                    (":token_hash", &token_hash),
+                    (":media_uuid", &media_uuid),
+                    (":collection_uuid", &collection_uuid),
+                    (":created", &created),
+                    (":expires_at", &expires_at),
+                ],
+            )?;
+
+            Ok(())
+        })
+        .await?;
+
+        debug!({ share_uuid = %share_uuid }, "added share link");
+
+        Ok(ShareLinkUuid::from_value(self, share_uuid))
+    }
+
+    #[instrument(skip_all)]
+    async fn get_share_link(&self, token_hash: String) -> Result<Option<ShareLink>> {
+        debug!("looking up share link");
+
+        let data = self
+            .call(move |conn| {
+                let data = conn
+                    .prepare_cached(
+                        r"
+                        SELECT share_uuid, uid, media_uuid, collection_uuid, created, expires_at
+                        FROM share_links WHERE token_hash = :token_hash",
+                    )?
+                    .query_row(&[(":token_hash", &token_hash)], share_link_row)
+                    .optional()?;
+
+                Ok(data)
+            })
+            .await?;
+
+        data.map(|row| row.into_share_link(self)).transpose()
+    }
+
+    #[instrument(skip(self))]
+    async fn get_share_links(&self, uid: String) -> Result<Vec<ShareLink>> {
+        debug!("getting share links");
+
+        let data = self
+            .call(move |conn| {
+                let data = conn
+                    .prepare_cached(
+                        r"
+                        SELECT share_uuid, uid, media_uuid, collection_uuid, created, expires_at
+                        FROM share_links WHERE uid = :uid",
+                    )?
+                    .query_map(&[(":uid", &uid)], share_link_row)?
+                    .collect::<Result<Vec<ShareLinkRow>, rusqlite::Error>>()?;
+
+                Ok(data)
+            })
+            .await?;
+
+        let data = data
+            .into_iter()
+            .map(|row| row.into_share_link(self))
+            .collect::<Result<Vec<ShareLink>>>()?;
+
+        debug!({ count = data.len() }, "found share links");
+
+        Ok(data)
+    }
+
+    #[instrument(skip(self))]
+    async fn delete_share_link(&self, uid: String, share_uuid: ShareLinkUuid) -> Result<()> {
+        debug!("deleting share link");
+
+        let share_uuid = share_uuid.value();
+
+        self.call(move |conn| {
+            conn.execute(
+                "DELETE FROM share_links WHERE share_uuid = :share_uuid AND uid = :uid",
+                &[(":share_uuid", &share_uuid as &dyn ToSql), (":uid", &uid)],
+            )?;
+
+            Ok(())
+        })
+        .await?;
+
+        debug!("deleted share link");
+
+        Ok(())
+    }
+
+    #[instrument(skip(self))]
+    async fn get_shared_media(&self, target: ShareTarget) -> Result<Vec<MediaUuid>> {
+        debug!("finding shared media");
+
+        let (media_uuid, collection_uuid) = share_target_columns(target);
+
+        // only one of the uuids is set, and comparing against NULL never matches
+        let data = self
+            .call(move |conn| {
+                let data = conn
+                    .prepare_cached(
+                        r"
+                        SELECT media_uuid FROM media
+                        WHERE
+                            (
+                                media_uuid = :media_uuid
+                                OR media_uuid IN (
+                                    SELECT media_uuid FROM collection_contents
+                                    WHERE collection_uuid = :collection_uuid
+                                )
+                            )
+                            AND hidden = FALSE
+                            AND deleted_at IS NULL",
+                    )?
+                    .query_map(
+                        &[
+                            (":media_uuid", &media_uuid as &dyn ToSql),
+                            (":collection_uuid", &collection_uuid),
+                        ],
+                        |row| row.get::<_, Uuid>(0),
+                    )?
+                    .collect::<Result<Vec<Uuid>, rusqlite::Error>>()?;
+
+                Ok(data)
+            })
+            .await?;
+
+        let data = self.media_uuids(data);
+
+        debug!({ count = data.len() }, "found shared media");
+
+        Ok(data)
+    }
+
+    // task queries
+    #[instrument(skip(self, task))]
+    async fn add_task(&self, library: TaskLibrary, task: Task) -> Result<()> {
+        debug!("adding task");
+
+        let task = TaskRow::new(library, task);
+        let library_uuid = task.library_uuid.map(|v| v.value());
+
+        self.call(move |conn| {
+            conn.execute(
+                r"
+                INSERT INTO tasks (library_uuid, task_type, uid, status, warnings, start_time, end_time, summary)
+                VALUES (:library_uuid, :task_type, :uid, :status, :warnings, :start_time, :end_time, :summary)",
+                &[
+                    (":library_uuid", &library_uuid as &dyn ToSql),
+                    (":task_type", &task.task_type),
+                    (":uid", &task.uid),
+                    (":status", &task.status),
+                    (":warnings", &task.warnings),
+                    (":start_time", &task.start),
+                    (":end_time", &task.end),
+                    (":summary", &task.summary),
+                ],
+            )?;
+
+            Ok(())
+        })
+        .await?;
+
+        debug!("added task");
+
+        Ok(())
+    }
+
+    #[instrument(skip(self))]
+    async fn get_tasks(&self, library: TaskLibrary, limit: u64) -> Result<Vec<Task>> {
+        debug!("getting tasks");
+
+        let library_uuid = match library {
+            TaskLibrary::User { library_uuid } => Some(library_uuid),
+            TaskLibrary::System => None,
+        };
+
+        let query_uuid = library_uuid.map(|v| v.value());
+
+        // IS also matches NULL, which is how the system tasks are stored
+        let data = self
+            .call(move |conn| {
+                let data = conn
+                    .prepare_cached(
+                        r"
+                        SELECT task_type, uid, status, warnings, start_time, end_time, summary
+                        FROM tasks
+                        WHERE library_uuid IS :library_uuid
+                        ORDER BY end_time DESC, task_id DESC
+                        LIMIT :limit",
+                    )?
+                    .query_map(
+                        &[
+                            (":library_uuid", &query_uuid as &dyn ToSql),
+                            (":limit", &limit),
+                        ],
+                        |row| {
+                            Ok(TaskRow {
+                                library_uuid,
+                                task_type: row.get(0)?,
+                                uid: row.get(1)?,
+                                status: row.get(2)?,
+                                warnings: row.get(3)?,
+                                start: row.get(4)?,
+                                end: row.get(5)?,
+                                summary: row.get(6)?,
+                            })
+                        },
+                    )?
+                    .collect::<Result<Vec<TaskRow>, rusqlite::Error>>()?;
+
+                Ok(data)
+            })
+            .await?;
+
+        let data = data
+            .into_iter()
+            .map(TaskRow::into_task)
+            .collect::<Result<Vec<Task>>>()?;
+
+        debug!({ count = data.len() }, "found tasks");
+
+        Ok(data)
+    }
+
+    // collection queries
+    #[instrument(skip(self, collection))]
+    async fn add_collection(&self, collection: Collection) -> Result<CollectionUuid> {
+        debug!({ collection_name = collection.name }, "adding collection");
+
+        let collection_uuid = Uuid::now_v7();
+        let collection_name = collection.name.clone();
+        let tags = fold_set(collection.tags)?;
+
+        let count = self
+            .call(move |conn| {
+                let count = conn.execute(
+                    r"
+                    INSERT OR IGNORE INTO collections (collection_uuid, uid, gid, name, note, tags, cover, parent_uuid)
+                    VALUES (:collection_uuid, :uid, :gid, :name, :note, :tags, :cover, :parent_uuid)",
+                    &[
+                        (":collection_uuid", &collection_uuid as &dyn ToSql),
+                        (":uid", &collection.uid),
+                        (":gid", &collection.gid),
+                        (":name", &collection.name),
+                        (":note", &collection.note),
+                        (":tags", &tags),
+                        (":cover", &collection.cover.map(|m| m.value())),
+                        (":parent_uuid", &collection.parent_uuid.map(|c| c.value())),
+                    ],
+                )?;
+
+                Ok(count)
+            })
+            .await?;
+
+        if count == 0 {
+            error!("failed to add collection");
+            return Err(anyhow::Error::msg("failed to add collection"));
+        }
+
+        debug!({ collection_name = collection_name, collection_uuid = %collection_uuid }, "added collection");
+
+        Ok(CollectionUuid::from_value(self, collection_uuid))
+    }
+
+    #[instrument(skip(self))]
+    async fn get_collection(&self, collection_uuid: CollectionUuid) -> Result<Option<Collection>> {
+        debug!("getting collection details");
+
+        let collection_uuid = collection_uuid.value();
+
+        let data = self
+            .call(move |conn| {
+                let data = conn
+                    .prepare_cached(
+                        r"
+                        SELECT
+                            uid,
+                            gid,
+                            name,
+                            note,
+                            tags,
+                            COALESCE(
+                                (
+                                    SELECT media.media_uuid FROM collection_contents
+                                    INNER JOIN media ON collection_contents.media_uuid = media.media_uuid
+                                    WHERE
+                                        collection_contents.collection_uuid = collections.collection_uuid
+                                        AND media.media_uuid = collections.cover
+                                        AND media.hidden = FALSE
+                                        AND media.deleted_at IS NULL
+                                ),
+                                (
+                                    SELECT media.media_uuid FROM collection_contents
+                                    INNER JOIN media ON collection_contents.media_uuid = media.media_uuid
+                                    WHERE
+                                        collection_contents.collection_uuid = collections.collection_uuid
+                                        AND media.hidden = FALSE
+                                        AND media.deleted_at IS NULL
+                                    ORDER BY media.date DESC, media.media_uuid DESC
+                                    LIMIT 1
+                                )
+                            ) AS cover,
+                            parent_uuid
+                        FROM collections WHERE collection_uuid = :collection_uuid",
+                    )?
+                    .query_row(&[(":collection_uuid", &collection_uuid)], |row| {
+                        Ok((
+                            row.get::<_, String>(0)?,
+                            row.get::<_, String>(1)?,
+                            row.get::<_, String>(2)?,
+                            row.get::<_, String>(3)?,
+                            row.get::<_, String>(4)?,
+                            row.get::<_, Option<Uuid>>(5)?,
+                            row.get::<_, Option<Uuid>>(6)?,
+                        ))
+                    })
+                    .optional()?;
+
+                Ok(data)
+            })
+            .await?;
+
+        let data = match data {
+            Some(v) => v,
+            None => return Ok(None),
+        };
+
+        debug!("found collection details");
+
+        Ok(Some(Collection {
+            uid: data.0,
+            gid: data.1,
+            name: data.2,
+            note: data.3,
+            tags: unfold_set(&data.4),
+            cover: data.5.map(|m| MediaUuid::from_value(self, m)),
+            parent_uuid: data.6.map(|c| CollectionUuid::from_value(self, c)),
+        }))
+    }
+
+    #[instrument(skip(self))]
+    async fn get_collection_uuids(&self) -> Result<Vec<CollectionUuid>> {
+        debug!("getting all collection uuids");
+
+        let data = self
+            .call(|conn| {
+                let data = conn
+                    .prepare_cached("SELECT collection_uuid FROM collections")?
+                    .query_map([], |row| row.get::<_, Uuid>(0))?
+                    .collect::<Result<Vec<Uuid>, rusqlite::Error>>()?;
+
+                Ok(data)
+            })
+            .await?;
+
+        let data = data
+            .into_iter()
+            .map(|uuid| CollectionUuid::from_value(self, uuid))
+            .collect::<Vec<CollectionUuid>>();
+
+        debug!({ count = data.len() }, "found collections");
+
+        Ok(data)
+    }
+
+    #[instrument(skip(self))]
+    async fn delete_collection(&self, collection_uuid: CollectionUuid) -> Result<()> {
+        debug!("deleting collection");
+
+        let collection_uuid = collection_uuid.value();
+
+        // unlike the mariadb backend, we can do this in one transaction
+        self.call(move |conn| {
+            let tx = conn.transaction()?;
+
+            tx.execute(
+                "DELETE FROM collection_contents WHERE collection_uuid = :collection_uuid",
+                &[(":collection_uuid", &collection_uuid)],
+            )?;
+
+            // the children move up to the top level rather than disappearing
+            tx.execute(
+                "UPDATE collections SET parent_uuid = NULL WHERE parent_uuid = :collection_uuid",
+                &[(":collection_uuid", &collection_uuid)],
+            )?;
+
+            tx.execute(
+                "DELETE FROM collections WHERE collection_uuid = :collection_uuid",
+                &[(":collection_uuid", &collection_uuid)],
+            )?;
+
+            tx.commit()?;
+
+            Ok(())
+        })
+        .await?;
+
+        debug!("deleted collection");
+
+        Ok(())
+    }
+
+    #[instrument(skip(self, update))]
+    async fn update_collection(
+        &self,
+        collection_uuid: CollectionUuid,
+        update: CollectionUpdate,
+    ) -> Result<()> {
+        debug!("updating collection");
+
+        let collection_uuid = collection_uuid.value();
+        let tags = update.tags.map(fold_set).transpose()?;
+
+        self.call(move |conn| {
+            conn.execute(
+                r"
+                UPDATE collections SET
+                    name = COALESCE(:name, name),
+                    note = COALESCE(:note, note),
+                    tags = COALESCE(:tags, tags)
+                WHERE collection_uuid = :collection_uuid",
+                &[
+                    (":name", &update.name as &dyn ToSql),
+                    (":note", &update.note),
+                    (":tags", &tags),
+                    (":collection_uuid", &collection_uuid),
+                ],
+            )?;
+
+            Ok(())
+        })
+        .await?;
+
+        debug!("updated collection");
+
+        Ok(())
+    }
+
+    #[instrument(skip(self))]
+    async fn set_collection_cover(
+        &self,
+        collection_uuid: CollectionUuid,
+        cover: Option<MediaUuid>,
+    ) -> Result<()> {
+        debug!("setting collection cover");
+
+        let collection_uuid = collection_uuid.value();
+        let cover = cover.map(|m| m.value());
+
+        self.call(move |conn| {
+            conn.execute(
+                r"
+                UPDATE collections SET cover = :cover WHERE collection_uuid = :collection_uuid",
+                &[
+                    (":cover", &cover as &dyn ToSql),
+                    (":collection_uuid", &collection_uuid),
+                ],
+            )?;
+
+            Ok(())
+        })
+        .await?;
+
+        debug!("set collection cover");
+
+        Ok(())
+    }
+
+    #[instrument(skip(self))]
+    async fn set_collection_parent(
+        &self,
+        collection_uuid: CollectionUuid,
+        parent_uuid: Option<CollectionUuid>,
+    ) -> Result<()> {
+        debug!("setting collection parent");
+
+        let collection_uuid = collection_uuid.value();
+        let parent_uuid = parent_uuid.map(|c| c.value());
+
+        self.call(move |conn| {
+            let tx = conn.transaction()?;
+
+            // walk up from the new parent, which must not pass through the collection
+            if let Some(parent_uuid) = parent_uuid {
+                let cycle = tx.query_row(
+                    r"
+                    WITH RECURSIVE ancestors (collection_uuid) AS (
+                        SELECT :parent_uuid
+                        UNION
+                        SELECT collections.parent_uuid FROM collections
+                        INNER JOIN ancestors ON collections.collection_uuid = ancestors.collection_uuid
+                        WHERE collections.parent_uuid IS NOT NULL
+                    )
+                    SELECT EXISTS (SELECT 1 FROM ancestors WHERE collection_uuid = :collection_uuid)",
+                    &[
+                        (":parent_uuid", &parent_uuid),
+                        (":collection_uuid", &collection_uuid),
+                    ],
+                    |row| row.get::<_, bool>(0),
+                )?;
+
+                if cycle {
+                    return Err(anyhow::Error::msg("collection cannot be its own ancestor"));
+                }
+            }
+
+            tx.execute(
+                r"
+                UPDATE collections SET parent_uuid = :parent_uuid WHERE collection_uuid = :collection_uuid",
+                &[
+                    (":parent_uuid", &parent_uuid as &dyn ToSql),
+                    (":collection_uuid", &collection_uuid),
+                ],
+            )?;
+
+            tx.commit()?;
+
+            Ok(())
+        })
+        .await?;
+
+        debug!("set collection parent");
+
+        Ok(())
+    }
+
+    #[instrument(skip(self))]
+    async fn get_collection_children(
+        &self,
+        gid: HashSet<String>,
+        collection_uuid: CollectionUuid,
+    ) -> Result<Vec<CollectionUuid>> {
+        debug!("getting collection children");
+
+        let gid = fold_set(gid)?;
+        let collection_uuid = collection_uuid.value();
+
+        let query = format!(
+            r"
+            SELECT
+                collection_uuid
+            FROM
+                collections
+            WHERE
+                {GID_CHECK} AND parent_uuid = :collection_uuid"
+        );
+
+        let data = self
+            .call(move |conn| {
+                let data = conn
+                    .prepare_cached(&query)?
+                    .query_map(
+                        &[
+                            (":gid", &gid as &dyn ToSql),
+                            (":collection_uuid", &collection_uuid),
+                        ],
+                        |row| row.get::<_, Uuid>(0),
+                    )?
+                    .collect::<Result<Vec<Uuid>, rusqlite::Error>>()?;
+
+                Ok(data)
+            })
+            .await?;
+
+        let data = data
+            .into_iter()
+            .map(|uuid| CollectionUuid::from_value(self, uuid))
+            .collect::<Vec<CollectionUuid>>();
+
+        debug!({ count = data.len() }, "found collection children");
+
+        Ok(data)
+    }
+
+    #[instrument(skip(self))]
+    async fn add_media_to_collection(
+        &self,
+        media_uuid: MediaUuid,
+        collection_uuid: CollectionUuid,
+    ) -> Result<()> {
+        debug!("adding media to collection");
+
+        let media_uuid = media_uuid.value();
+        let collection_uuid = collection_uuid.value();
+
+        let count = self
+            .call(move |conn| {
+                // new additions go to the end of the collection
+                let count = conn.execute(
+                    r"
+                    INSERT OR IGNORE INTO collection_contents (media_uuid, collection_uuid, position)
+                    VALUES (
+                        :media_uuid,
+                        :collection_uuid,
+                        (
+                            SELECT COALESCE(MAX(position), -1) + 1 FROM collection_contents
+                            WHERE collection_uuid = :collection_uuid
+                        )
+                    )",
+                    &[
+                        (":media_uuid", &media_uuid),
+                        (":collection_uuid", &collection_uuid),
+                    ],
+                )?;
+
+                Ok(count)
+            })
+            .await?;
+
+        if count == 0 {
+            error!("failed to add media to collection");
+            return Err(anyhow::Error::msg("failed to add media to collection"));
+        }
+
+        debug!("added media to collection");
+
+        Ok(())
+    }
+
+    #[instrument(skip(self))]
+    async fn rm_media_from_collection(
+        &self,
+        media_uuid: MediaUuid,
+        collection_uuid: CollectionUuid,
+    ) -> Result<()> {
+        debug!("removing media from collection");
+
+        let media_uuid = media_uuid.value();
+        let collection_uuid = collection_uuid.value();
+
+        self.call(move |conn| {
+            conn.execute(
+                r"
+                DELETE FROM collection_contents WHERE media_uuid = :media_uuid AND collection_uuid = :collection_uuid",
+                &[
+                    (":media_uuid", &media_uuid),
+                    (":collection_uuid", &collection_uuid),
+                ],
+            )?;
+
+            Ok(())
+        })
+        .await?;
+
+        debug!("removed media from collection");
+
+        Ok(())
+    }
+
+    #[instrument(skip(self, media_uuids))]
+    async fn reorder_collection_media(
+        &self,
+        collection_uuid: CollectionUuid,
+        media_uuids: Vec<MediaUuid>,
+    ) -> Result<()> {
+        debug!({ count = media_uuids.len() }, "reordering collection media");
+
+        let collection_uuid = collection_uuid.value();
+        let media_uuids = media_uuids
+            .into_iter()
+            .map(|v| v.value())
+            .collect::<Vec<Uuid>>();
+
+        self.call(move |conn| {
+            let tx = conn.transaction()?;
+
+            // everything moves back past the listed media, which then take the first positions
+            tx.execute(
+                r"
+                UPDATE collection_contents SET position = position + :count
+                WHERE collection_uuid = :collection_uuid",
+                &[
+                    (":count", &(media_uuids.len() as i64) as &dyn ToSql),
+                    (":collection_uuid", &collection_uuid),
+                ],
+            )?;
+
+            {
+                let mut statement = tx.prepare_cached(
+                    r"
+                    UPDATE collection_contents SET position = :position
+                    WHERE collection_uuid = :collection_uuid AND media_uuid = :media_uuid",
+                )?;
+
+                for (position, media_uuid) in media_uuids.iter().enumerate() {
+                    statement.execute(&[
+                        (":position", &(position as i64) as &dyn ToSql),
+                        (":collection_uuid", &collection_uuid),
+                        (":media_uuid", media_uuid),
+                    ])?;
+                }
+            }
+
+            tx.commit()?;
+
+            Ok(())
+        })
+        .await?;
+
+        debug!("reordered collection media");
+
+        Ok(())
+    }
+
+    #[instrument(skip(self))]
+    async fn search_collections(
+        &self,
+        gid: HashSet<String>,
+        filter: SearchFilter,
+    ) -> Result<Vec<CollectionUuid>> {
+        debug!("searching for collections");
+
+        let gid = fold_set(gid)?;
+        let (sql, filter) =
+            filter.format_sqlite("collections.name, collections.note, collections.tags");
+
+        // for a given uid and filter, find all collections owned by groups that contain that uid
+        let mut query = format!(
+            r"
+            SELECT
+                collection_uuid
+            FROM
+                collections
+            WHERE
+                {GID_CHECK}"
+        );
+
+        query.push_str(&sql);
+
+        let data = self
+            .call(move |conn| {
+                let filter = filter_params(&filter);
+
+                let mut params: Vec<(&str, &dyn ToSql)> = vec![(":gid", &gid)];
+                params.extend(filter.iter().map(|(name, value)| (name.as_str(), *value)));
+
+                let data = conn
+                    .prepare(&query)?
+                    .query_map(&*params, |row| row.get::<_, Uuid>(0))?
+                    .collect::<Result<Vec<Uuid>, rusqlite::Error>>()?;
+
+                Ok(data)
+            })
+            .await?;
+
+        let data = data
+            .into_iter()
+            .map(|uuid| CollectionUuid::from_value(self, uuid))
+            .collect::<Vec<CollectionUuid>>();
+
+        debug!({ count = data.len() }, "found collections");
+
+        Ok(data)
+    }
+
+    #[instrument(skip(self))]
+    async fn search_media_in_collection(
+        &self,
+        gid: HashSet<String>,
+        collection_uuid: CollectionUuid,
+        filter: SearchFilter,
+        recursive: bool,
+        ordered: bool,
+    ) -> Result<Vec<MediaUuid>> {
+        debug!("searching media in collection");
+
+        let gid = fold_set(gid)?;
+        let collection_uuid = collection_uuid.value();
+        let (mut sql, filter) =
+            filter.format_sqlite("media.path, media.date, media.note, media.tags");
+
+        // positions can tie (for rows from before there were positions), and then the
+        // insertion order decides
+        if ordered {
+            sql.push_str(" ORDER BY t3.position, t3.id");
+        }
+
+        // for a given uid, filter, and collection_uuid, find all non-hidden media in that collection
+        // provided that the collection is owned by a group containing the uid
+        //
+        // if recursive, the same goes for each of its descendants.  UNION (rather than UNION ALL)
+        // stops the walk if the tree somehow has a cycle.
+        let mut query = format!(
+            r"
+            WITH RECURSIVE tree (collection_uuid) AS (
+                SELECT collection_uuid FROM collections
+                WHERE {GID_CHECK} AND collection_uuid = :collection_uuid
+                UNION
+                SELECT collections.collection_uuid FROM collections
+                INNER JOIN tree ON collections.parent_uuid = tree.collection_uuid
+                WHERE :recursive
+            )
+            SELECT
+                media.media_uuid
+            FROM
+                (
+                    SELECT
+                        media_uuid,
+                        MIN(collection_contents.position) AS position,
+                        MIN(collection_contents.id) AS id
+                    FROM
+                        (
+                            SELECT
+                                collection_uuid
+                            FROM
+                                collections
+                            WHERE
+                                {GID_CHECK} AND collection_uuid IN (SELECT collection_uuid FROM tree)
+                        ) AS t2
+                        INNER JOIN collection_contents ON t2.collection_uuid = collection_contents.collection_uuid
+                    GROUP BY
+                        media_uuid
+                ) AS t3
+                INNER JOIN media ON t3.media_uuid = media.media_uuid
+            WHERE
+                media.hidden = FALSE
+                AND media.deleted_at IS NULL"
+        );
+
+        query.push_str(&sql);
+
+        let data = self
+            .call(move |conn| {
+                let filter = filter_params(&filter);
+
+                let mut params: Vec<(&str, &dyn ToSql)> = vec![
+                    (":gid", &gid),
+                    (":collection_uuid", &collection_uuid),
+                    (":recursive", &recursive),
+                ];
+                params.extend(filter.iter().map(|(name, value)| (name.as_str(), *value)));
+
+                let data = conn
+                    .prepare(&query)?
+                    .query_map(&*params, |row| row.get::<_, Uuid>(0))?
+                    .collect::<Result<Vec<Uuid>, rusqlite::Error>>()?;
+
+                Ok(data)
+            })
+            .await?;
+
+        let data = self.media_uuids(data);
+
+        debug!({ count = data.len() }, "found media in collection");
+
+        Ok(data)
+    }
+
+    // library queries
+    #[instrument(skip(self, library))]
+    async fn add_library(&self, library: Library) -> Result<LibraryUuid> {
+        debug!({ library_path = library.path }, "adding library");
+
+        let library_uuid = Uuid::now_v7();
+        let library_path = library.path.clone();
+
+        let count = self
+            .call(move |conn| {
+                let count = conn.execute(
+                    r"
+                    INSERT OR IGNORE INTO libraries (library_uuid, path, name, note, uid, gid, count)
+                    VALUES (:library_uuid, :path, :name, :note, :uid, :gid, :count)",
+                    &[
+                        (":library_uuid", &library_uuid as &dyn ToSql),
+                        (":path", &library.path),
+                        (":name", &library.name),
+                        (":note", &library.note),
+                        (":uid", &library.uid),
+                        (":gid", &library.gid),
+                        (":count", &library.count),
+                    ],
+                )?;
+
+                Ok(count)
+            })
+            .await?;
+
+        if count == 0 {
+            error!("failed to add library");
+            return Err(anyhow::Error::msg("failed to add library"));
+        }
+
+        debug!({ library_path = library_path, library_uuid = %library_uuid }, "added library");
+
+        Ok(LibraryUuid::from_value(self, library_uuid))
+    }
+
+    #[instrument(skip(self))]
+    async fn get_library(&self, library_uuid: LibraryUuid) -> Result<Option<Library>> {
+        debug!("getting library details");
+
+        let library_uuid = library_uuid.value();
+
+        let data = self
+            .call(move |conn| {
+                let data = conn
+                    .prepare_cached(
+                        r"
+                        SELECT path, name, note, uid, gid, count FROM libraries WHERE library_uuid = :library_uuid",
+                    )?
+                    .query_row(&[(":library_uuid", &library_uuid)], |row| {
+                        Ok(Library {
+                            path: row.get(0)?,
+                            name: row.get(1)?,
+                            note: row.get(2)?,
+                            uid: row.get(3)?,
+                            gid: row.get(4)?,
+                            count: row.get(5)?,
+                        })
+                    })
+                    .optional()?;
+
+                Ok(data)
+            })
+            .await?;
+
+        debug!("found library details");
+
+        Ok(data)
+    }
+
+    #[instrument(skip(self))]
+    async fn get_library_uuids(&self) -> Result<Vec<LibraryUuid>> {
+        debug!("getting all library uuids");
+
+        let data = self
+            .call(|conn| {
+                let data = conn
+                    .prepare_cached("SELECT library_uuid FROM libraries")?
+                    .query_map([], |row| row.get::<_, Uuid>(0))?
+                    .collect::<Result<Vec<Uuid>, rusqlite::Error>>()?;
+
+                Ok(data)
+            })
+            .await?;
+
+        let data = data
+            .into_iter()
+            .map(|uuid| LibraryUuid::from_value(self, uuid))
+            .collect::<Vec<LibraryUuid>>();
+
+        debug!({ count = data.len() }, "found libraries");
+
+        Ok(data)
+    }
+
+    #[instrument(skip(self))]
+    async fn update_library(&self, library_uuid: LibraryUuid, update: LibraryUpdate) -> Result<()> {
+        debug!("updating library");
+
+        let library_uuid = library_uuid.value();
+
+        self.call(move |conn| {
+            conn.execute(
+                r"
+                UPDATE libraries SET
+                    name = COALESCE(:name, name),
+                    note = COALESCE(:note, note),
+                    count = COALESCE(:count, count)
+                WHERE library_uuid = :library_uuid",
+                &[
+                    (":name", &update.name as &dyn ToSql),
+                    (":note", &update.note),
+                    (":count", &update.count),
+                    (":library_uuid", &library_uuid),
+                ],
+            )?;
+
+            Ok(())
+        })
+        .await?;
+
+        debug!("updated library");
+
+        Ok(())
+    }
+
+    #[instrument(skip(self))]
+    async fn search_libraries(
+        &self,
+        gid: HashSet<String>,
+        filter: String,
+    ) -> Result<Vec<LibraryUuid>> {
+        debug!("searching libraries");
+
+        let gid = fold_set(gid)?;
+        let filter = format!("%{filter}%");
+
+        let query = format!(
+            r"
+            SELECT
+                library_uuid
+            FROM
+                libraries
+            WHERE
+                {GID_CHECK}
+                AND (path LIKE :filter OR name LIKE :filter OR note LIKE :filter)"
+        );
+
+        let data = self
+            .call(move |conn| {
+                let data = conn
+                    .prepare_cached(&query)?
+                    .query_map(&[(":gid", &gid), (":filter", &filter)], |row| {
+                        row.get::<_, Uuid>(0)
+                    })?
+                    .collect::<Result<Vec<Uuid>, rusqlite::Error>>()?;
+
+                Ok(data)
+            })
+            .await?;
+
+        let data = data
+            .into_iter()
+            .map(|uuid| LibraryUuid::from_value(self, uuid))
+            .collect::<Vec<LibraryUuid>>();
+
+        debug!({ count = data.len() }, "found libraries");
+
+        Ok(data)
+    }
+
+    #[instrument(skip(self))]
+    async fn search_libraries_detailed(
+        &self,
+        gid: HashSet<String>,
+        filter: String,
+    ) -> Result<Vec<LibrarySummary>> {
+        debug!("searching libraries with details");
+
+        let gid = fold_set(gid)?;
+        let filter = format!("%{filter}%");
+
+        // the media count matches search_media_in_library() with no filter or hidden state
+        let query = format!(
+            r"
+            SELECT
+                library_uuid,
+                path,
+                name,
+                note,
+                uid,
+                gid,
+                count,
+                (
+                    SELECT
+                        COUNT(*)
+                    FROM
+                        media
+                    WHERE
+                        media.library_uuid = libraries.library_uuid
+                        AND media.deleted_at IS NULL
+                )
+            FROM
+                libraries
+            WHERE
+                {GID_CHECK}
+                AND (path LIKE :filter OR name LIKE :filter OR note LIKE :filter)"
+        );
+
+        let data = self
+            .call(move |conn| {
+                let data = conn
+                    .prepare_cached(&query)?
+                    .query_map(&[(":gid", &gid), (":filter", &filter)], |row| {
+                        Ok((
+                            row.get::<_, Uuid>(0)?,
+                            Library {
+                                path: row.get(1)?,
+                                name: row.get(2)?,
+                                note: row.get(3)?,
+                                uid: row.get(4)?,
+                                gid: row.get(5)?,
+                                count: row.get(6)?,
+                            },
+                            row.get::<_, u64>(7)?,
+                        ))
+                    })?
+                    .collect::<Result<Vec<_>, rusqlite::Error>>()?;
+
+                Ok(data)
+            })
+            .await?;
+
+        let data = data
+            .into_iter()
+            .map(|(uuid, library, media)| LibrarySummary {
+                library_uuid: LibraryUuid::from_value(self, uuid),
+                library,
+                media,
+            })
+            .collect::<Vec<LibrarySummary>>();
+
+        debug!({ count = data.len() }, "found libraries");
+
+        Ok(data)
+    }
+
+    #[instrument(skip(self))]
+    async fn search_media_in_library(
+        &self,
+        gid: HashSet<String>,
+        library_uuid: LibraryUuid,
+        hidden: Option<bool>,
+        filter: SearchFilter,
+    ) -> Result<Vec<MediaUuid>> {
+        debug!("searching media in library");
+
+        let gid = fold_set(gid)?;
+        let library_uuid = library_uuid.value();
+        let (filter_sql, filter) =
+            filter.format_sqlite("media.path, media.date, media.note, media.tags");
+
+        // for a given uid, filter, hidden state and library_uuid, find all media in that collection
+        // provided that the library is owned by a group containing the uid
+        //
+        // note that this is the only search query where media with "hidden = true" can be found
+        let mut query = format!(
+            r"
+            SELECT
+                media.media_uuid
+            FROM
+                (
+                    SELECT
+                        library_uuid
+                    FROM
+                        libraries
+                    WHERE
+                        {GID_CHECK} AND library_uuid = :library_uuid
+                ) AS t1
+                INNER JOIN media ON t1.library_uuid = media.library_uuid
+            WHERE
+                media.hidden = COALESCE(:hidden, media.hidden)
+                AND media.deleted_at IS NULL"
+        );
+
+        query.push_str(&filter_sql);
+
+        let data = self
+            .call(move |conn| {
+                let filter = filter_params(&filter);
+
+                let mut params: Vec<(&str, &dyn ToSql)> = vec![
+                    (":gid", &gid),
+                    (":library_uuid", &library_uuid),
+                    (":hidden", &hidden),
+                ];
+                params.extend(filter.iter().map(|(name, value)| (name.as_str(), *value)));
+
+                let data = conn
+                    .prepare(&query)?
+                    .query_map(&*params, |row| row.get::<_, Uuid>(0))?
+                    .collect::<Result<Vec<Uuid>, rusqlite::Error>>()?;
+
+                Ok(data)
+            })
+            .await?;
+
+        let data = self.media_uuids(data);
+
+        debug!({ count = data.len() }, "found media in library");
+
+        Ok(data)
+    }
+
+    #[instrument(skip(self))]
+    async fn count_hidden_in_library(
+        &self,
+        gid: HashSet<String>,
+        library_uuid: LibraryUuid,
+    ) -> Result<u64> {
+        debug!("counting hidden media in library");
+
+        let gid = fold_set(gid)?;
+        let library_uuid = library_uuid.value();
+
+        // the same access rules as search_media_in_library()
+        let query = format!(
+            r"
+            SELECT
+                COUNT(*)
+            FROM
+                (
+                    SELECT
+                        library_uuid
+                    FROM
+                        libraries
+                    WHERE
+                        {GID_CHECK} AND library_uuid = :library_uuid
+                ) AS t1
+                INNER JOIN media ON t1.library_uuid = media.library_uuid
+            WHERE
+                media.hidden = TRUE
+                AND media.deleted_at IS NULL"
+        );
+
+        let count = self
+            .call(move |conn| {
+                let count = conn.prepare(&query)?.query_row(
+                    &[
+                        (":gid", &gid as &dyn ToSql),
+                        (":library_uuid", &library_uuid),
+                    ],
+                    |row| row.get::<_, u64>(0),
+                )?;
+
+                Ok(count)
+            })
+            .await?;
+
+        debug!({ count }, "counted hidden media in library");
+
+        Ok(count)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use api::activity::ActivityEvent;
+
+    async fn backend() -> SqliteBackend {
+        SqliteBackend::open(Path::new(":memory:")).await.unwrap()
+    }
+
+    fn media(db: &SqliteBackend, path: &str) -> Media {
+        Media {
+            library_uuid: LibraryUuid::from_value(db, Uuid::now_v7()),
+            path: path.to_owned(),
+            size: 1024,
+            chash: String::from("chash"),
+            phash: String::from("phash"),
+            mtime: 1_700_000_000,
+            hidden: false,
+            date: String::from("2021-07-04 12:34:56"),
+            note: String::new(),
+            tags: HashSet::new(),
+            metadata: MediaMetadata::Image,
+            latitude: None,
+            longitude: None,
+            width: None,
+            height: None,
+            camera: None,
+            orientation: None,
+            capture_time: None,
+            duration_secs: None,
+            codec: None,
+        }
+    }
+
+    fn collection(name: &str) -> Collection {
+        Collection {
+            uid: String::from("owner"),
+            gid: String::from("group"),
+            name: name.to_owned(),
+            note: String::new(),
+            tags: HashSet::new(),
+            cover: None,
+            parent_uuid: None,
+        }
+    }
+
+    fn library(path: &str, gid: &str) -> Library {
+        Library {
+            path: path.to_owned(),
+            name: String::new(),
+            note: String::new(),
+            uid: String::from("owner"),
+            gid: gid.to_owned(),
+            count: 0,
+        }
+    }
+
+    fn comment(media_uuid: MediaUuid, text: &str) -> Comment {
+        Comment {
+            media_uuid,
+            uid: String::from("owner"),
+            date: 1_700_000_000,
+            text: text.to_owned(),
+            edited: None,
+        }
+    }
+
+    #[tokio::test]
+    async fn media_details_round_trip() {
+        let db = backend().await;
+
+        let media = Media {
+            latitude: Some(51.5),
+            longitude: Some(-0.125),
+            width: Some(6000),
+            height: Some(4000),
+            camera: Some(String::from("Canon EOS R6")),
+            orientation: Some(6),
+            capture_time: Some(String::from("2021-07-04 12:34:56")),
+            ..media(&db, "a.jpg")
+        };
+
+        let media_uuid = db.add_media(media.clone()).await.unwrap();
+
+        let (found, _, _) = db.get_media(media_uuid).await.unwrap().unwrap();
+
+        assert_eq!(found, media);
+    }
+
+    #[tokio::test]
+    async fn video_details_round_trip() {
+        let db = backend().await;
+
+        let media = Media {
+            metadata: MediaMetadata::Video,
+            width: Some(1920),
+            height: Some(1080),
+            duration_secs: Some(12.5),
+            codec: Some(String::from("h264")),
+            ..media(&db, "a.mp4")
+        };
+
+        let media_uuid = db.add_media(media.clone()).await.unwrap();
+
+        let (found, _, _) = db.get_media(media_uuid).await.unwrap().unwrap();
+
+        assert_eq!(found, media);
+    }
+
+    #[tokio::test]
+    async fn replace_media_path_updates_size() {
+        let db = backend().await;
+
+        let media_uuid = db.add_media(media(&db, "a.jpg")).await.unwrap();
+
+        db.replace_media_path(
+            media_uuid,
+            String::from("b.jpg"),
+            String::from("new chash"),
+            1_700_000_100,
+            2048,
+        )
+        .await
+        .unwrap();
+
+        let gone = db.get_media_by_path(String::from("a.jpg")).await.unwrap();
+        assert!(gone.is_none());
+
+        let found = db
+            .get_media_by_path(String::from("b.jpg"))
+            .await
+            .unwrap()
+            .unwrap();
+
+        assert_eq!(found.media_uuid, media_uuid);
+        assert_eq!(found.hash, "new chash");
+        assert_eq!(found.mtime, 1_700_000_100);
+        assert_eq!(found.size, 2048);
+    }
+
+    // GID_CHECK matches whole entries of the folded set, never a substring of one
+    #[tokio::test]
+    async fn gids_match_whole_groups() {
+        let db = backend().await;
+
+        let mut media_uuids = Vec::new();
+
+        for gid in ["administrators", "admin"] {
+            let library_uuid = db
+                .add_library(library(&format!("/{gid}"), gid))
+                .await
+                .unwrap();
+
+            let media = Media {
+                library_uuid,
+                ..media(&db, &format!("/{gid}/a.jpg"))
+            };
+
+            media_uuids.push(db.add_media(media).await.unwrap());
+        }
+
+        let search = |gid: &str| {
+            db.search_media(
+                HashSet::from([gid.to_owned()]),
+                SearchFilter::default(),
+                SortOrder::PathAsc,
+                None,
+                None,
+            )
+        };
+
+        assert_eq!(search("admin").await.unwrap().0, vec![media_uuids[1]]);
+        assert_eq!(
+            search("administrators").await.unwrap().0,
+            vec![media_uuids[0]]
+        );
+        assert!(search("admins").await.unwrap().0.is_empty());
+    }
+
+    // a trigger makes purge_media() fail on the comments, after the media row and its
+    // collection_contents rows are already gone inside the transaction
+    #[tokio::test]
+    async fn failed_purge_leaves_nothing_behind() {
+        let db = backend().await;
+
+        let media_uuid = db.add_media(media(&db, "a.jpg")).await.unwrap();
+        let collection_uuid = db.add_collection(collection("trip")).await.unwrap();
+
+        db.add_media_to_collection(media_uuid, collection_uuid)
+            .await
+            .unwrap();
+
+        let comment_uuid = db.add_comment(comment(media_uuid, "nice")).await.unwrap();
+
+        db.soft_delete_media(media_uuid).await.unwrap();
+
+        db.call(|conn| {
+            Ok(conn.execute_batch(
+                "CREATE TRIGGER fail_purge BEFORE DELETE ON comments
+                 BEGIN SELECT RAISE(ABORT, 'injected failure'); END",
+            )?)
+        })
+        .await
+        .unwrap();
+
+        assert!(db.purge_media(media_uuid).await.is_err());
+
+        let (_, collections, comments) = db.get_media(media_uuid).await.unwrap().unwrap();
+
+        assert_eq!(collections, vec![collection_uuid]);
+        assert_eq!(comments, vec![comment_uuid]);
+
+        // and the same purge goes through once the failure is gone
+        db.call(|conn| Ok(conn.execute_batch("DROP TRIGGER fail_purge")?))
+            .await
+            .unwrap();
+
+        db.purge_media(media_uuid).await.unwrap();
+
+        assert!(db.get_media(media_uuid).await.unwrap().is_none());
+    }
+
+    #[tokio::test]
+    async fn only_new_text_marks_a_comment_edited() {
+        let db = backend().await;
+
+        let media_uuid = db.add_media(media(&db, "a.jpg")).await.unwrap();
+        let comment_uuid = db.add_comment(comment(media_uuid, "nice")).await.unwrap();
+
+        let posted = db.get_comment(comment_uuid).await.unwrap().unwrap().date;
+
+        let edited = || async { db.get_comment(comment_uuid).await.unwrap().unwrap().edited };
+
+        for text in [None, Some(String::from("nice"))] {
+            db.update_comment(comment_uuid, text).await.unwrap();
+            assert_eq!(edited().await, None);
+        }
+
+        db.update_comment(comment_uuid, Some(String::from("very nice")))
+            .await
+            .unwrap();
+
+        let first_edit = edited().await;
+        assert!(first_edit.is_some());
+
+        let found = db.get_comment(comment_uuid).await.unwrap().unwrap();
+        assert_eq!(found.text, "very nice");
+        assert_eq!(found.date, posted);
+
+        // and a later no-op leaves the first edit's time alone
+        db.update_comment(comment_uuid, Some(String::from("very nice")))
+            .await
+            .unwrap();
+
+        assert_eq!(edited().await, first_edit);
+    }
+
+    fn group() -> HashSet<String> {
+        HashSet::from([String::from("group")])
+    }
+
+    fn others() -> HashSet<String> {
+        HashSet::from([String::from("others")])
+    }
+
+    async fn add(
+        db: &SqliteBackend,
+        library_uuid: LibraryUuid,
+        path: &str,
+        size: u64,
+        metadata: MediaMetadata,
+        hidden: bool,
+    ) -> MediaUuid {
+        db.add_media(Media {
+            library_uuid,
+            size,
+            metadata,
+            hidden,
+            ..media(db, path)
+        })
+        .await
+        .unwrap()
+    }
+
+    struct Fixture {
+        mine: LibraryUuid,
+        theirs: LibraryUuid,
+        image: MediaUuid,
+        video: MediaUuid,
+        hidden: MediaUuid,
+        trashed: MediaUuid,
+        shared: MediaUuid,
+        unshared: MediaUuid,
+        collection: CollectionUuid,
+    }
+
+    // a library for each of "group" and "others", with a collection in "group" that shares
+    // one media from the library of "others".  the sizes are powers of two, so that every
+    // sum of them is distinct.
+    async fn fixture(db: &SqliteBackend) -> Fixture {
+        let mine = db.add_library(library("/mine", "group")).await.unwrap();
+        let theirs = db.add_library(library("/theirs", "others")).await.unwrap();
+
+        let image = add(db, mine, "image", 1, MediaMetadata::Image, false).await;
+        let video = add(db, mine, "video", 2, MediaMetadata::Video, false).await;
+        let hidden = add(db, mine, "hidden", 4, MediaMetadata::Image, true).await;
+
+        let trashed = add(db, mine, "trashed", 8, MediaMetadata::Image, false).await;
+        db.soft_delete_media(trashed).await.unwrap();
+
+        let shared = add(db, theirs, "shared", 16, MediaMetadata::Image, false).await;
+        let unshared = add(db, theirs, "unshared", 32, MediaMetadata::Image, false).await;
+
+        let collection = db.add_collection(collection("shared")).await.unwrap();
+        db.add_media_to_collection(shared, collection)
+            .await
+            .unwrap();
+
+        Fixture {
+            mine,
+            theirs,
+            image,
+            video,
+            hidden,
+            trashed,
+            shared,
+            unshared,
+            collection,
+        }
+    }
+
+    #[tokio::test]
+    async fn stats_skip_hidden_and_trashed_media() {
+        let db = backend().await;
+        fixture(&db).await;
+
+        // hidden and trashed media are left out, but shared media are not
+        assert_eq!(
+            db.stats(group()).await.unwrap(),
+            Stats {
+                media: 3,
+                images: 2,
+                videos: 1,
+                audio: 0,
+                bytes: 1 + 2 + 16,
+                collections: 1,
+                libraries: 1,
+            }
+        );
+
+        assert_eq!(
+            db.stats(others()).await.unwrap(),
+            Stats {
+                media: 2,
+                images: 2,
+                videos: 0,
+                audio: 0,
+                bytes: 16 + 32,
+                collections: 0,
+                libraries: 1,
+            }
+        );
+
+        assert_eq!(
+            db.stats(HashSet::from([String::from("nobody")]))
+                .await
+                .unwrap(),
+            Stats::default()
+        );
+    }
+
+    #[tokio::test]
+    async fn random_media_stays_within_access() {
+        let db = backend().await;
+        let f = fixture(&db).await;
+
+        let sample = async |gid, count| -> HashSet<MediaUuid> {
+            let media = db
+                .random_media(gid, SearchFilter::default(), count)
+                .await
+                .unwrap();
+
+            let sampled = HashSet::from_iter(media.iter().copied());
+            assert_eq!(sampled.len(), media.len(), "duplicate in {media:?}");
+            sampled
+        };
+
+        let visible = HashSet::from([f.image, f.video, f.shared]);
+
+        // asking for more than there are returns each of them once
+        assert_eq!(sample(group(), 10).await, visible);
+        assert_eq!(
+            sample(others(), 10).await,
+            HashSet::from([f.shared, f.unshared])
+        );
+
+        // and smaller samples never reach outside of them, whichever ones get picked
+        for _ in 0..20 {
+            let sampled = sample(group(), 2).await;
+
+            assert_eq!(sampled.len(), 2);
+            assert!(sampled.is_subset(&visible), "{sampled:?}");
+            assert!(!sampled.contains(&f.hidden));
+            assert!(!sampled.contains(&f.trashed));
+        }
+    }
+
+    #[tokio::test]
+    async fn activity_is_newest_first_and_respects_access() {
+        let db = backend().await;
+        let f = fixture(&db).await;
+
+        let events = async |gid, limit| -> Vec<ActivityEvent> {
+            db.recent_activity(gid, limit)
+                .await
+                .unwrap()
+                .into_iter()
+                .map(|activity| activity.event)
+                .collect()
+        };
+
+        let media_added = |media_uuid| ActivityEvent::MediaAdded { media_uuid };
+
+        assert_eq!(
+            events(group(), 10).await,
+            vec![
+                ActivityEvent::CollectionCreated {
+                    collection_uuid: f.collection
+                },
+                media_added(f.shared),
+                media_added(f.video),
+                media_added(f.image),
+            ]
+        );
+
+        // a new comment goes to the top, but only for those who can see its media
+        let comment_uuid = db.add_comment(comment(f.image, "nice")).await.unwrap();
+        db.add_comment(comment(f.unshared, "hidden from group"))
+            .await
+            .unwrap();
+
+        assert_eq!(
+            events(group(), 1).await,
+            vec![ActivityEvent::CommentAdded {
+                comment_uuid,
+                media_uuid: f.image
+            }]
+        );
+
+        let theirs = events(others(), 10).await;
+
+        assert_eq!(theirs.len(), 3, "{theirs:?}");
+        assert!(matches!(
+            theirs[0],
+            ActivityEvent::CommentAdded { media_uuid, .. } if media_uuid == f.unshared
+        ));
+        assert_eq!(
+            theirs[1..],
+            [media_added(f.unshared), media_added(f.shared)]
+        );
+    }
+
+    #[tokio::test]
+    async fn hidden_count_matches_hidden_search() {
+        let db = backend().await;
+        let f = fixture(&db).await;
+
+        let hidden_search = async |library_uuid| -> HashSet<MediaUuid> {
+            db.search_media_in_library(group(), library_uuid, Some(true), SearchFilter::default())
+                .await
+                .unwrap()
+                .into_iter()
+                .collect()
+        };
+
+        // the visible media and the trashed one don't count
+        assert_eq!(
+            db.count_hidden_in_library(group(), f.mine).await.unwrap(),
+            1
+        );
+        assert_eq!(hidden_search(f.mine).await, HashSet::from([f.hidden]));
+
+        db.update_media(
+            f.image,
+            MediaUpdate {
+                hidden: Some(true),
+                ..Default::default()
+            },
+        )
+        .await
+        .unwrap();
+
+        assert_eq!(
+            db.count_hidden_in_library(group(), f.mine).await.unwrap(),
+            2
+        );
+        assert_eq!(
+            hidden_search(f.mine).await,
+            HashSet::from([f.hidden, f.image])
+        );
+
+        // and it only counts libraries that the group owns, not those it shares from
+        db.update_media(
+            f.shared,
+            MediaUpdate {
+                hidden: Some(true),
+                ..Default::default()
+            },
+        )
+        .await
+        .unwrap();
+
+        assert_eq!(
+            db.count_hidden_in_library(group(), f.theirs).await.unwrap(),
+            0
+        );
+        assert_eq!(
+            db.count_hidden_in_library(others(), f.theirs)
+                .await
+                .unwrap(),
+            1
+        );
+        assert_eq!(
+            db.count_hidden_in_library(others(), f.mine).await.unwrap(),
+            0
+        );
+    }
+
+    #[tokio::test]
+    async fn histogram_buckets_by_day_and_month() {
+        let db = backend().await;
+        let library_uuid = db.add_library(library("/dates", "group")).await.unwrap();
+
+        for (path, date, hidden) in [
+            ("a", "2024-01-15 10:00:00", false),
+            ("b", "2024-01-15 23:59:59", false),
+            ("c", "2024-01-31 08:00:00", false),
+            ("d", "2024-03-01", false),
+            ("e", "2024-12-25 12:00:00", false),
+            ("f", "2024-03-01 09:00:00", true),
+            ("g", "sometime in 2024", false),
+        ] {
+            db.add_media(Media {
+                library_uuid,
+                date: date.to_owned(),
+                hidden,
+                ..media(&db, path)
+            })
+            .await
+            .unwrap();
+        }
+
+        let histogram = async |granularity| -> Vec<DateBucket> {
+            db.media_date_histogram(group(), granularity).await.unwrap()
+        };
+
+        let counts = |buckets: &[DateBucket]| -> Vec<(String, u64)> {
+            buckets
+                .iter()
+                .map(|bucket| (bucket.date.clone(), bucket.count))
+                .collect()
+        };
+
+        // hidden media and dates that aren't yyyy-mm-dd are left out
+        let days = histogram(DateGranularity::Day).await;
+
+        assert_eq!(
+            counts(&days),
+            vec![
+                (String::from("2024-01-15"), 2),
+                (String::from("2024-01-31"), 1),
+                (String::from("2024-03-01"), 1),
+                (String::from("2024-12-25"), 1),
+            ]
+        );
+        assert_eq!((days[2].start, days[2].end), (1709251200, 1709337599));
+
+        let months = histogram(DateGranularity::Month).await;
+
+        assert_eq!(
+            counts(&months),
+            vec![
+                (String::from("2024-01"), 3),
+                (String::from("2024-03"), 1),
+                (String::from("2024-12"), 1),
+            ]
+        );
+        assert_eq!((months[0].start, months[0].end), (1704067200, 1706745599));
+        assert_eq!((months[1].start, months[1].end), (1709251200, 1711929599));
+    }
+
+    #[tokio::test]
+    async fn library_search_matches_the_note() {
+        let db = backend().await;
+
+        let beach = db
+            .add_library(Library {
+                note: String::from("photos from the summer holiday"),
+                ..library("/beach", "group")
+            })
+            .await
+            .unwrap();
+        db.add_library(library("/mountains", "group"))
+            .await
+            .unwrap();
+
+        // neither the path nor the name mention it
+        assert_eq!(
+            db.search_libraries(group(), String::from("Holiday"))
+                .await
+                .unwrap(),
+            vec![beach]
+        );
+        assert!(
+            db.search_libraries(others(), String::from("holiday"))
+                .await
+                .unwrap()
+                .is_empty()
+        );
+    }
+
+    #[tokio::test]
+    async fn detailed_library_counts_match_library_search() {
+        let db = backend().await;
+        let f = fixture(&db).await;
+
+        for (gid, expected) in [(group(), (f.mine, 3)), (others(), (f.theirs, 2))] {
+            let summaries = db
+                .search_libraries_detailed(gid.clone(), String::new())
+                .await
+                .unwrap();
+
+            assert_eq!(
+                summaries
+                    .iter()
+                    .map(|summary| (summary.library_uuid, summary.media))
+                    .collect::<Vec<_>>(),
+                vec![expected]
+            );
+
+            // hidden media count, trashed ones don't
+            for summary in summaries {
+                let media = db
+                    .search_media_in_library(
+                        gid.clone(),
+                        summary.library_uuid,
+                        None,
+                        SearchFilter::default(),
+                    )
+                    .await
+                    .unwrap();
+
+                assert_eq!(summary.media, media.len() as u64);
+            }
+        }
+    }
+
+    #[tokio::test]
+    async fn collection_order_survives_appends_and_removals() {
+        let db = backend().await;
+        let library_uuid = db.add_library(library("/story", "group")).await.unwrap();
+        let collection_uuid = db.add_collection(collection("story")).await.unwrap();
+
+        let mut media_uuids = Vec::new();
+
+        for path in ["a", "b", "c", "d"] {
+            let media_uuid = add(&db, library_uuid, path, 1, MediaMetadata::Image, false).await;
+            media_uuids.push(media_uuid);
+        }
+
+        let [a, b, c, d] = media_uuids[..] else {
+            unreachable!()
+        };
+
+        let ordered = async || -> Vec<MediaUuid> {
+            db.search_media_in_collection(
+                group(),
+                collection_uuid,
+                SearchFilter::default(),
+                false,
+                true,
+            )
+            .await
+            .unwrap()
+        };
+
+        // new additions go to the end
+        for media_uuid in [a, b, c] {
+            db.add_media_to_collection(media_uuid, collection_uuid)
+                .await
+                .unwrap();
+        }
+
+        assert_eq!(ordered().await, vec![a, b, c]);
+
+        db.reorder_collection_media(collection_uuid, vec![c, a])
+            .await
+            .unwrap();
+
+        assert_eq!(ordered().await, vec![c, a, b]);
+
+        // removing one leaves a hole in the positions, which neither the order nor later
+        // appends and reorders should notice
+        db.rm_media_from_collection(a, collection_uuid)
+            .await
+            .unwrap();
+
+        assert_eq!(ordered().await, vec![c, b]);
+
+        db.add_media_to_collection(d, collection_uuid)
+            .await
+            .unwrap();
+
+        assert_eq!(ordered().await, vec![c, b, d]);
+
+        // and the removed media is ignored when it's listed
+        db.reorder_collection_media(collection_uuid, vec![d, a, b])
+            .await
+            .unwrap();
+
+        assert_eq!(ordered().await, vec![d, b, c]);
+    }
+}
@@ -3,13 +3,15 @@ use std::{
     fmt::Debug,
     future::Future,
     hash::Hash,
-    sync::Arc,
-    time::{SystemTime, UNIX_EPOCH},
+    sync::{Arc, LazyLock},
+    time::{Duration, Instant, SystemTime, UNIX_EPOCH},
 };
 
 use anyhow::Result;
 use async_cell::sync::AsyncCell;
 use dashmap::{DashMap, mapref::entry::Entry};
+use metrics::counter;
+use regex::Regex;
 use tracing::{error, instrument};
 
 pub mod auth;
@@ -17,6 +19,8 @@ pub mod config;
 pub mod db;
 pub mod media;
 pub mod server;
+pub mod storage;
+pub mod webhook;
 
 // entanglement common library
 //
@@ -38,9 +42,28 @@ pub fn unix_time() -> u64 {
 //
 // we sanitize the inputs both to avoid awkward issues in the frontend as well as ensure that
 // folding/unfolding sets of strings (see api/lib.rs) has no strange behavior
+//
+// the auth service applies these to whatever the providers hand back, and the http handlers
+// apply them to any uid or gid in a request
 pub const USER_REGEX: &str = r"^[a-zA-Z0-9_.-]{1,64}$";
 pub const GROUP_REGEX: &str = r"^[a-zA-Z0-9_.-]{1,64}$";
 
+static USER: LazyLock<Regex> =
+    LazyLock::new(|| Regex::new(USER_REGEX).expect("invalid user regex"));
+static GROUP: LazyLock<Regex> =
+    LazyLock::new(|| Regex::new(GROUP_REGEX).expect("invalid group regex"));
+
+pub fn is_valid_uid(uid: &str) -> bool {
+    USER.is_match(uid)
+}
+
+pub fn is_valid_gid(gid: &str) -> bool {
+    GROUP.is_match(gid)
+}
+
+// each AwaitCache entry is the cell that callers wait on, and when it was created
+type CacheEntry<V> = (Arc<AsyncCell<Option<V>>>, Instant);
+
 // awaitable cache
 //
 // this is loosely inspired by the WaitCache crate, except that we want to have requests await
@@ -50,23 +73,35 @@ pub const GROUP_REGEX: &str = r"^[a-zA-Z0-9_.-]{1,64}$";
 // since by construction we write infrequently, it suffices to check that the things we are
 // cloning (small HashSets) are not too big/costly
 //
+// entries can optionally expire after a fixed ttl, measured from when the cell was created.  an
+// expired cell is swapped out under the entry lock, so concurrent callers still coalesce on the
+// replacement.
+//
 // note that there have historically been some issues with DashMap and holding references across
 // await boundaries, but they have largely been cleared up
+//
+// the name labels the hit and miss counters, so it should be unique across the server
 #[derive(Debug)]
 pub struct AwaitCache<K: Clone + Debug + Eq + Hash, V: Clone + Debug> {
-    items: DashMap<K, Arc<AsyncCell<Option<V>>>>,
+    name: &'static str,
+    items: DashMap<K, CacheEntry<V>>,
+    ttl: Option<Duration>,
 }
 
-impl<K: Clone + Debug + Eq + Hash, V: Clone + Debug> Default for AwaitCache<K, V> {
-    fn default() -> Self {
-        Self::new()
+impl<K: Clone + Debug + Eq + Hash, V: Clone + Debug> AwaitCache<K, V> {
+    pub fn new(name: &'static str) -> Self {
+        AwaitCache {
+            name,
+            items: DashMap::new(),
+            ttl: None,
+        }
     }
-}
 
-impl<K: Clone + Debug + Eq + Hash, V: Clone + Debug> AwaitCache<K, V> {
-    pub fn new() -> Self {
+    pub fn with_ttl(name: &'static str, ttl: Duration) -> Self {
         AwaitCache {
+            name,
             items: DashMap::new(),
+            ttl: Some(ttl),
         }
     }
 
@@ -76,15 +111,35 @@ impl<K: Clone + Debug + Eq + Hash, V: Clone + Debug> AwaitCache<K, V> {
         // holding the lock, we can't use the native get() -- it would need to return Mutex<Option<V>>
         // instead of the transpose
         let (cell, set) = match self.items.entry(key.clone()) {
-            Entry::Occupied(entry) => (entry.get().clone(), false),
+            Entry::Occupied(mut entry) => {
+                let (cell, created) = entry.get().clone();
+
+                match self.ttl {
+                    Some(ttl) if created.elapsed() >= ttl => {
+                        let cell = Arc::new(AsyncCell::new());
+
+                        entry.insert((cell.clone(), Instant::now()));
+                        (cell, true)
+                    }
+                    _ => (cell, false),
+                }
+            }
             Entry::Vacant(entry) => {
                 let cell = Arc::new(AsyncCell::new());
 
-                entry.insert(cell.clone());
+                entry.insert((cell.clone(), Instant::now()));
                 (cell, true)
             }
         };
 
+        // a caller that waits on another's initialization still counts as a hit
+        counter!(
+            "entanglement_cache_lookups_total",
+            "cache" => self.name,
+            "result" => if set { "miss" } else { "hit" }
+        )
+        .increment(1);
+
         // attempt to initialize the cell
         //
         // if this fails, we need to set the value to None (signalling to any listeners that this
@@ -122,3 +177,54 @@ impl<K: Clone + Debug + Eq + Hash, V: Clone + Debug> AwaitCache<K, V> {
         self.items.remove(key);
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use std::sync::atomic::{AtomicUsize, Ordering};
+
+    use super::*;
+
+    // counts the calls, so that the tests can tell a cache hit from a fresh initialization
+    async fn init(calls: &AtomicUsize) -> Result<usize> {
+        Ok(calls.fetch_add(1, Ordering::SeqCst) + 1)
+    }
+
+    #[tokio::test]
+    async fn caches_without_ttl() {
+        let cache = AwaitCache::new("test");
+        let calls = AtomicUsize::new(0);
+
+        assert_eq!(cache.perhaps("key", init(&calls)).await.unwrap(), 1);
+        assert_eq!(cache.perhaps("key", init(&calls)).await.unwrap(), 1);
+        assert_eq!(cache.perhaps("other", init(&calls)).await.unwrap(), 2);
+    }
+
+    #[tokio::test]
+    async fn entries_expire_after_ttl() {
+        let cache = AwaitCache::with_ttl("test", Duration::from_millis(50));
+        let calls = AtomicUsize::new(0);
+
+        assert_eq!(cache.perhaps("key", init(&calls)).await.unwrap(), 1);
+        assert_eq!(cache.perhaps("key", init(&calls)).await.unwrap(), 1);
+
+        tokio::time::sleep(Duration::from_millis(60)).await;
+
+        // the expired entry is replaced, and the replacement is cached in turn
+        assert_eq!(cache.perhaps("key", init(&calls)).await.unwrap(), 2);
+        assert_eq!(cache.perhaps("key", init(&calls)).await.unwrap(), 2);
+    }
+
+    #[tokio::test]
+    async fn failures_are_not_cached() {
+        let cache = AwaitCache::new("test");
+        let calls = AtomicUsize::new(0);
+
+        assert!(
+            cache
+                .perhaps("key", async { Err(anyhow::Error::msg("failed")) })
+                .await
+                .is_err()
+        );
+        assert_eq!(cache.perhaps("key", init(&calls)).await.unwrap(), 1);
+    }
+}
@@ -0,0 +1,113 @@
+use std::path::PathBuf;
+
+use anyhow::Result;
+use image::{DynamicImage, ImageFormat, Rgb, RgbImage};
+use lofty::{
+    file::{AudioFile, TaggedFileExt},
+    picture::PictureType,
+    tag::Accessor,
+};
+use tokio::task::spawn_blocking;
+use tracing::{debug, instrument};
+
+use crate::media::{AudioDetails, MediaData, MediaDetails, mtime_date};
+use api::media::{MediaMetadata, ThumbnailSize};
+
+// audio calculations
+//
+// lofty handles the id3, flac, and vorbis tag formats behind one interface.  like the image
+// crate, it is synchronous, so everything here runs under spawn_blocking()
+//
+// audio has no perceptual hash, so it never shows up in similar media searches
+
+#[instrument]
+pub async fn process_audio(path: &PathBuf) -> Result<MediaData> {
+    debug!("processing audio");
+
+    let path = path.clone();
+
+    let (details, date) = spawn_blocking(move || {
+        let tagged = lofty::read_from_path(&path)?;
+
+        let duration = tagged.properties().duration();
+
+        let tag = tagged.primary_tag().or_else(|| tagged.first_tag());
+
+        let details = AudioDetails {
+            artist: tag.and_then(|t| t.artist()).map(|v| v.to_string()),
+            album: tag.and_then(|t| t.album()).map(|v| v.to_string()),
+            title: tag.and_then(|t| t.title()).map(|v| v.to_string()),
+            duration_secs: (!duration.is_zero()).then(|| duration.as_secs_f64()),
+        };
+
+        // the tags rarely have anything more specific than a year, so use the mtime
+        let date = mtime_date(&path)?;
+
+        Result::<(AudioDetails, String)>::Ok((details, date))
+    })
+    .await??;
+
+    debug!({ details = ?details }, "finished processing audio");
+
+    Ok(MediaData {
+        hash: String::new(),
+        date,
+        metadata: MediaMetadata::Audio,
+        details: Some(MediaDetails::Audio(details)),
+    })
+}
+
+// use the embedded cover art if there is any, preferring the front cover
+fn read_cover_art(path: &PathBuf) -> Result<Option<DynamicImage>> {
+    let tagged = lofty::read_from_path(path)?;
+
+    let pictures = tagged
+        .tags()
+        .iter()
+        .flat_map(|t| t.pictures())
+        .collect::<Vec<_>>();
+
+    let picture = pictures
+        .iter()
+        .find(|p| p.pic_type() == PictureType::CoverFront)
+        .or_else(|| pictures.first());
+
+    match picture {
+        Some(p) => Ok(Some(image::load_from_memory(p.data())?)),
+        None => Ok(None),
+    }
+}
+
+#[instrument]
+pub async fn create_audio_thumbnail(
+    original_path: &PathBuf,
+    thumbnail_path: &PathBuf,
+    size: ThumbnailSize,
+) -> Result<()> {
+    debug!("creating audio thumbnail");
+
+    let original_path = original_path.clone();
+    let thumbnail_path = thumbnail_path.clone();
+
+    spawn_blocking(move || {
+        let thumbnail = match read_cover_art(&original_path)? {
+            Some(image) => image.thumbnail(size.bound(), size.bound()),
+            // without any art, fall back to a plain placeholder so that the grid still
+            // has something to show
+            None => {
+                debug!("no cover art found, using placeholder");
+
+                let bound = size.bound().min(400);
+
+                DynamicImage::ImageRgb8(RgbImage::from_pixel(bound, bound, Rgb([64, 64, 72])))
+            }
+        };
+
+        thumbnail.save_with_format(thumbnail_path, ImageFormat::Png)?;
+
+        debug!("finished creating audio thumbnail");
+
+        Ok(())
+    })
+    .await?
+}
@@ -0,0 +1,59 @@
+use std::{path::Path, sync::LazyLock};
+
+use chrono::{Datelike, NaiveDate, NaiveDateTime, Utc};
+use regex::Regex;
+
+// the format used for the date column, matching the exif DateTimeOriginal display format
+//
+// every field is zero-padded and ordered from largest to smallest, so the column sorts
+// chronologically as plain text.  the ORDER BY date queries depend on this, so both the
+// scan and the dateparse task only ever write dates in this format.
+pub const MEDIA_DATE_FORMAT: &str = "%Y-%m-%d %H:%M:%S";
+
+// nothing digital (or scanned and dated by the scanner) should predate this
+const EARLIEST_YEAR: i32 = 1900;
+
+// dates embedded in the names that cameras, phones, and screenshot tools give their files,
+// i.e. IMG_20210704_123456.jpg, PXL_20210704_123456789.mp4, or 2021-07-04 12.34.56.png
+//
+// the date must not be part of a longer run of digits, but the time may be followed by
+// fractional seconds
+static FILENAME_DATE: LazyLock<Regex> = LazyLock::new(|| {
+    Regex::new(
+        r"(?:^|\D)((?:19|20)\d{2})[-_.]?(0[1-9]|1[0-2])[-_.]?(0[1-9]|[12]\d|3[01])(?:[-_ T.]?([01]\d|2[0-3])[-_.:]?([0-5]\d)[-_.:]?([0-5]\d)|\D|$)",
+    )
+    .expect("invalid filename date regex")
+});
+
+// whether a date column value is something that we can sort on sensibly
+//
+// empty dates (i.e. videos without a creation_time), garbage, and dates outside of the
+// range that a photo could have been taken in are all implausible
+pub fn plausible_date(date: &str) -> bool {
+    match NaiveDateTime::parse_from_str(date.trim(), MEDIA_DATE_FORMAT) {
+        Ok(date) => date.year() >= EARLIEST_YEAR && date <= Utc::now().naive_utc(),
+        Err(_) => false,
+    }
+}
+
+// the date embedded in a filename, if there is a plausible one
+//
+// names with only a date are taken to be at midnight
+pub fn filename_date(path: &Path) -> Option<String> {
+    let name = path.file_stem()?.to_string_lossy();
+
+    FILENAME_DATE.captures_iter(&name).find_map(|captures| {
+        let number = |i: usize| captures.get(i).and_then(|m| m.as_str().parse::<u32>().ok());
+
+        let date = NaiveDate::from_ymd_opt(number(1)? as i32, number(2)?, number(3)?)?;
+
+        let date = match (number(4), number(5), number(6)) {
+            (Some(h), Some(m), Some(s)) => date.and_hms_opt(h, m, s)?,
+            _ => date.and_hms_opt(0, 0, 0)?,
+        };
+
+        let date = date.format(MEDIA_DATE_FORMAT).to_string();
+
+        plausible_date(&date).then_some(date)
+    })
+}
@@ -0,0 +1,51 @@
+use std::path::Path;
+
+use anyhow::Result;
+use image::{DynamicImage, RgbImage};
+use libheif_rs::{ColorSpace, HeifContext, LibHeif, RgbChroma};
+
+// heif decoding
+//
+// the image crate can't decode heic/heif, so these go through libheif instead.  libheif
+// applies the rotation and mirroring transforms stored in the container while decoding,
+// so the result is already in display orientation.
+
+fn path_str(path: &Path) -> Result<&str> {
+    path.to_str()
+        .ok_or_else(|| anyhow::Error::msg("heif path is not valid utf-8"))
+}
+
+pub fn heif_dimensions(path: &Path) -> Result<(u32, u32)> {
+    let context = HeifContext::read_from_file(path_str(path)?)?;
+    let handle = context.primary_image_handle()?;
+
+    Ok((handle.width(), handle.height()))
+}
+
+pub fn decode_heif(path: &Path) -> Result<DynamicImage> {
+    let lib = LibHeif::new();
+
+    let context = HeifContext::read_from_file(path_str(path)?)?;
+    let handle = context.primary_image_handle()?;
+
+    let image = lib.decode(&handle, ColorSpace::Rgb(RgbChroma::Rgb), None)?;
+
+    let plane = image
+        .planes()
+        .interleaved
+        .ok_or_else(|| anyhow::Error::msg("heif image has no interleaved plane"))?;
+
+    // the rows may be padded out to the stride, so copy them one at a time
+    let row = plane.width as usize * 3;
+
+    let mut buffer = Vec::with_capacity(row * plane.height as usize);
+
+    for line in plane.data.chunks(plane.stride).take(plane.height as usize) {
+        buffer.extend_from_slice(&line[..row]);
+    }
+
+    let image = RgbImage::from_raw(plane.width, plane.height, buffer)
+        .ok_or_else(|| anyhow::Error::msg("heif image buffer has the wrong size"))?;
+
+    Ok(DynamicImage::ImageRgb8(image))
+}
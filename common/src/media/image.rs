@@ -2,18 +2,73 @@ use std::path::{Path, PathBuf};
 
 use anyhow::Result;
 use blockhash::blockhash256;
-use image::{DynamicImage, ImageDecoder, ImageFormat, ImageReader};
+use chrono::NaiveDate;
+use image::{DynamicImage, ImageDecoder, ImageFormat, ImageReader, metadata::Orientation};
 use tokio::task::spawn_blocking;
 use tracing::{debug, instrument};
 
-use crate::media::MediaData;
-use api::media::MediaMetadata;
+use crate::media::{
+    ImageDetails, MediaData, MediaDetails,
+    date::{MEDIA_DATE_FORMAT, plausible_date},
+    mtime_date,
+};
+use api::media::{MediaMetadata, ThumbnailSize};
 
 // image calculations
 //
 // unfortunately, the image crate is largely built from synchronous std::io tech, which
 // means spawn_blocking() wrappers on all of it to avoid jamming the runtime
 
+// heif brands, i.e. the four bytes after "ftyp" in the first box of the file
+const HEIF_BRANDS: [&[u8; 4]; 8] = [
+    b"heic", b"heix", b"hevc", b"hevx", b"heim", b"heis", b"mif1", b"msf1",
+];
+
+// check the magic bytes for a heic/heif container
+//
+// these can't be handled by the image crate, and the extension is not reliable since
+// some phones and editors write .jpg files with heif contents
+pub fn is_heif(path: &Path) -> Result<bool> {
+    let mut header = [0; 12];
+
+    let mut file = std::fs::File::open(path)?;
+
+    if std::io::Read::read_exact(&mut file, &mut header).is_err() {
+        return Ok(false);
+    }
+
+    Ok(&header[4..8] == b"ftyp" && HEIF_BRANDS.iter().any(|b| &header[8..12] == *b))
+}
+
+#[cfg(feature = "heif")]
+fn open_heif(path: &Path) -> Result<DynamicImage> {
+    crate::media::heif::decode_heif(path)
+}
+
+#[cfg(not(feature = "heif"))]
+fn open_heif(_path: &Path) -> Result<DynamicImage> {
+    Err(anyhow::Error::msg("heif support is not enabled"))
+}
+
+#[cfg(feature = "heif")]
+fn heif_dimensions(path: &Path) -> Result<(u32, u32)> {
+    crate::media::heif::heif_dimensions(path)
+}
+
+#[cfg(not(feature = "heif"))]
+fn heif_dimensions(_path: &Path) -> Result<(u32, u32)> {
+    Err(anyhow::Error::msg("heif support is not enabled"))
+}
+
+// open an image with whichever decoder can handle it
+pub fn open_image(path: &Path) -> Result<DynamicImage> {
+    if is_heif(path)? {
+        return open_heif(path);
+    }
+
+    Ok(image::open(path)?)
+}
+
 #[instrument]
 pub async fn hash_image(path: &Path) -> Result<String> {
     debug!("calculating hash");
@@ -21,7 +76,7 @@ pub async fn hash_image(path: &Path) -> Result<String> {
     let path = path.to_path_buf();
 
     let hash = spawn_blocking(move || {
-        let image = image::open(path)?;
+        let image = open_image(&path)?;
 
         Result::<String>::Ok(blockhash256(&image).to_string())
     })
@@ -30,6 +85,172 @@ pub async fn hash_image(path: &Path) -> Result<String> {
     Ok(hash)
 }
 
+// pull the first string out of an exif ascii field, dropping the padding that some
+// cameras leave at the end
+fn exif_string(exif: &exif::Exif, tag: exif::Tag) -> Option<String> {
+    match exif.get_field(tag, exif::In::PRIMARY)?.value {
+        exif::Value::Ascii(ref values) => values
+            .first()
+            .map(|v| {
+                String::from_utf8_lossy(v)
+                    .trim_matches(char::from(0))
+                    .trim()
+                    .to_owned()
+            })
+            .filter(|v| !v.is_empty()),
+        _ => None,
+    }
+}
+
+fn exif_uint(exif: &exif::Exif, tag: exif::Tag) -> Option<u32> {
+    exif.get_field(tag, exif::In::PRIMARY)?.value.get_uint(0)
+}
+
+// the capture time in the date column format
+//
+// the exif display value can't be used directly, since it passes blank and malformed
+// dates through as text (i.e. "unknown"), and those would then sort as if they were real.
+// the time is left as the camera's local time, since most cameras don't record an offset.
+fn exif_capture_time(exif: &exif::Exif) -> Option<String> {
+    let field = exif.get_field(exif::Tag::DateTimeOriginal, exif::In::PRIMARY)?;
+
+    let dt = match field.value {
+        exif::Value::Ascii(ref values) => exif::DateTime::from_ascii(values.first()?).ok()?,
+        _ => return None,
+    };
+
+    // from_ascii doesn't check the ranges
+    let date = NaiveDate::from_ymd_opt(dt.year.into(), dt.month.into(), dt.day.into())?
+        .and_hms_opt(dt.hour.into(), dt.minute.into(), dt.second.into())?
+        .format(MEDIA_DATE_FORMAT)
+        .to_string();
+
+    plausible_date(&date).then_some(date)
+}
+
+// convert one gps coordinate from degrees/minutes/seconds to signed decimal degrees
+//
+// the reference tag says which hemisphere the (unsigned) rationals are in
+fn exif_coordinate(
+    exif: &exif::Exif,
+    tag: exif::Tag,
+    ref_tag: exif::Tag,
+    negative: &str,
+    limit: f64,
+) -> Option<f64> {
+    let dms = match exif.get_field(tag, exif::In::PRIMARY)?.value {
+        exif::Value::Rational(ref values) if values.len() == 3 => {
+            values.iter().map(|v| v.to_f64()).collect::<Vec<f64>>()
+        }
+        _ => return None,
+    };
+
+    let degrees = dms[0] + dms[1] / 60.0 + dms[2] / 3600.0;
+
+    let degrees = match exif_string(exif, ref_tag) {
+        Some(r) if r.eq_ignore_ascii_case(negative) => -degrees,
+        _ => degrees,
+    };
+
+    // some cameras write zeroed (or garbage) rationals when there is no fix
+    (degrees.is_finite() && degrees.abs() <= limit).then_some(degrees)
+}
+
+// read the exif properties that we care about
+//
+// the pixel dimensions in the exif data are frequently stale after editing, so the
+// width and height come from the image header instead
+pub fn read_image_details(path: &Path) -> Result<ImageDetails> {
+    let (width, height) = match is_heif(path)? {
+        true => heif_dimensions(path)?,
+        false => image::image_dimensions(path)?,
+    };
+
+    exif_details(path, width, height)
+}
+
+// the exif half of read_image_details(), for callers that find the dimensions elsewhere
+pub(crate) fn exif_details(path: &Path, width: u32, height: u32) -> Result<ImageDetails> {
+    let file = std::fs::File::open(path)?;
+
+    let mut bufreader = std::io::BufReader::new(file);
+
+    let exif = match exif::Reader::new().read_from_container(&mut bufreader).ok() {
+        Some(v) => v,
+        None => {
+            return Ok(ImageDetails {
+                width: Some(width),
+                height: Some(height),
+                ..Default::default()
+            });
+        }
+    };
+
+    let capture_time = exif_capture_time(&exif);
+
+    let camera = match (
+        exif_string(&exif, exif::Tag::Make),
+        exif_string(&exif, exif::Tag::Model),
+    ) {
+        // most manufacturers repeat the make in the model, i.e. "Canon" and "Canon EOS R6"
+        (Some(make), Some(model)) if model.starts_with(&make) => Some(model),
+        (Some(make), Some(model)) => Some(format!("{make} {model}")),
+        (make, model) => make.or(model),
+    };
+
+    // only keep the location if both halves are present
+    let (latitude, longitude) = match (
+        exif_coordinate(
+            &exif,
+            exif::Tag::GPSLatitude,
+            exif::Tag::GPSLatitudeRef,
+            "S",
+            90.0,
+        ),
+        exif_coordinate(
+            &exif,
+            exif::Tag::GPSLongitude,
+            exif::Tag::GPSLongitudeRef,
+            "W",
+            180.0,
+        ),
+    ) {
+        (Some(lat), Some(lon)) => (Some(lat), Some(lon)),
+        _ => (None, None),
+    };
+
+    Ok(ImageDetails {
+        width: Some(width),
+        height: Some(height),
+        capture_time,
+        camera,
+        orientation: exif_uint(&exif, exif::Tag::Orientation),
+        latitude,
+        longitude,
+    })
+}
+
+// just the exif capture time, which works on any container that has exif data (including
+// raw files that the image crate can't open)
+pub fn read_capture_time(path: &Path) -> Result<Option<String>> {
+    let file = std::fs::File::open(path)?;
+
+    let mut bufreader = std::io::BufReader::new(file);
+
+    Ok(exif::Reader::new()
+        .read_from_container(&mut bufreader)
+        .ok()
+        .and_then(|exif| exif_capture_time(&exif)))
+}
+
+// the capture time if there is a plausible one, otherwise the file mtime
+pub(crate) fn media_date(path: &Path, details: &ImageDetails) -> Result<String> {
+    match details.capture_time.clone() {
+        Some(v) => Ok(v),
+        None => mtime_date(path),
+    }
+}
+
 #[instrument]
 pub async fn process_image(path: &Path) -> Result<MediaData> {
     debug!("processing image");
@@ -38,42 +259,50 @@ pub async fn process_image(path: &Path) -> Result<MediaData> {
 
     // exif processing
     //
-    // we attempt to read the exif metadata for the image to extract the date
-    // following the exif docs, open the file synchronously and read from the container
-    let (path, datetime_original) = spawn_blocking(move || {
-        let file = std::fs::File::open(&path)?;
-
-        let mut bufreader = std::io::BufReader::new(file);
-
-        let exifreader = exif::Reader::new();
+    // following the exif docs, open the file synchronously and read from the container.
+    // if there is no capture time, fall back to the file mtime so that the image still
+    // sorts somewhere reasonable
+    let (path, details, date) = spawn_blocking(move || {
+        let details = read_image_details(&path)?;
+        let date = media_date(&path, &details)?;
 
-        let datetime_original = match exifreader.read_from_container(&mut bufreader).ok() {
-            None => String::from(""),
-            Some(exif) => exif
-                .get_field(exif::Tag::DateTimeOriginal, exif::In::PRIMARY)
-                .map(|dto| format!("{}", dto.display_value()))
-                .unwrap_or_default(),
-        };
-
-        Result::<(PathBuf, String)>::Ok((path, datetime_original))
+        Result::<(PathBuf, ImageDetails, String)>::Ok((path, details, date))
     })
     .await??;
 
+    debug!({ details = ?details }, "read image details");
+
     let hash = hash_image(&path).await?;
 
     debug!("finshed processing image");
 
     Ok(MediaData {
         hash,
-        date: datetime_original,
+        date,
         metadata: MediaMetadata::Image,
+        details: Some(MediaDetails::Image(details)),
     })
 }
 
+// the exif orientation tag for an image, or no transform if it is missing or invalid
+pub(crate) fn read_orientation(path: &Path) -> Orientation {
+    let exif = std::fs::File::open(path).ok().and_then(|file| {
+        exif::Reader::new()
+            .read_from_container(&mut std::io::BufReader::new(file))
+            .ok()
+    });
+
+    exif.and_then(|exif| exif_uint(&exif, exif::Tag::Orientation))
+        .and_then(|v| u8::try_from(v).ok())
+        .and_then(Orientation::from_exif)
+        .unwrap_or(Orientation::NoTransforms)
+}
+
 #[instrument]
 pub async fn create_image_thumbnail(
     original_path: &PathBuf,
     thumbnail_path: &PathBuf,
+    size: ThumbnailSize,
 ) -> Result<()> {
     debug!("creating thumbnail");
 
@@ -81,19 +310,37 @@ pub async fn create_image_thumbnail(
     let thumbnail_path = thumbnail_path.clone();
 
     spawn_blocking(move || {
+        // heif images are already in display orientation once decoded
+        if is_heif(&original_path)? {
+            let thumbnail = open_heif(&original_path)?.thumbnail(size.bound(), size.bound());
+
+            thumbnail.save_with_format(thumbnail_path, ImageFormat::Png)?;
+
+            debug!("finished creating thumbnail");
+
+            return Ok(());
+        }
+
         let mut decoder = ImageReader::open(original_path.clone())?.into_decoder()?;
 
         // ensure that the thumbnail matches the orientation of the original image
-        let orientation = decoder.orientation()?;
+        //
+        // not every decoder reports the exif orientation, so fall back to reading the
+        // tag ourselves before giving up and using the raw pixel orientation
+        let orientation = match decoder.orientation()? {
+            Orientation::NoTransforms => read_orientation(&original_path),
+            orientation => orientation,
+        };
 
         debug!({orientation = ?orientation}, "orientation for image");
 
-        let image = DynamicImage::from_decoder(decoder)?;
+        let mut image = DynamicImage::from_decoder(decoder)?;
 
-        // create the thumbnail with bounds, not exact sizing
-        let mut thumbnail = image.thumbnail(400, 400);
+        // rotate before scaling so that the thumbnail bounds apply to the displayed shape
+        image.apply_orientation(orientation);
 
-        thumbnail.apply_orientation(orientation);
+        // create the thumbnail with bounds, not exact sizing
+        let thumbnail = image.thumbnail(size.bound(), size.bound());
 
         thumbnail.save_with_format(thumbnail_path, ImageFormat::Png)?;
 
@@ -103,3 +350,54 @@ pub async fn create_image_thumbnail(
     })
     .await?
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // an 8x4 jpeg carrying make, model, orientation, DateTimeOriginal, and gps tags
+    // (51°30'0" N, 0°7'30" W)
+    const EXIF_JPEG: &str = concat!(env!("CARGO_MANIFEST_DIR"), "/testdata/exif.jpg");
+
+    #[test]
+    fn reads_exif_details() {
+        let details = read_image_details(Path::new(EXIF_JPEG)).unwrap();
+
+        assert_eq!(details.width, Some(8));
+        assert_eq!(details.height, Some(4));
+        assert_eq!(details.capture_time.as_deref(), Some("2021-07-04 12:34:56"));
+        assert_eq!(details.camera.as_deref(), Some("Canon EOS R6"));
+        assert_eq!(details.orientation, Some(6));
+
+        let latitude = details.latitude.unwrap();
+        let longitude = details.longitude.unwrap();
+
+        assert!((latitude - 51.5).abs() < 1e-9);
+        assert!((longitude + 0.125).abs() < 1e-9);
+    }
+
+    #[test]
+    fn reads_capture_time_alone() {
+        assert_eq!(
+            read_capture_time(Path::new(EXIF_JPEG)).unwrap().as_deref(),
+            Some("2021-07-04 12:34:56")
+        );
+    }
+
+    #[test]
+    fn missing_exif_keeps_dimensions() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("plain.png");
+
+        image::RgbImage::new(3, 2).save(&path).unwrap();
+
+        assert_eq!(
+            read_image_details(&path).unwrap(),
+            ImageDetails {
+                width: Some(3),
+                height: Some(2),
+                ..Default::default()
+            }
+        );
+    }
+}
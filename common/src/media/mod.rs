@@ -1,18 +1,29 @@
-use std::path::{Path, PathBuf};
+use std::{
+    fs::File,
+    io::{BufReader, Read},
+    path::{Path, PathBuf},
+};
 
 use anyhow::Result;
+use chrono::{DateTime, Utc};
 use hex::encode;
+use serde::{Deserialize, Serialize};
 use sha2::{Digest, Sha512};
-use tokio::{
-    fs::File,
-    io::{AsyncReadExt, BufReader},
-};
+use tokio::task::spawn_blocking;
 
-use api::media::MediaMetadata;
+use api::media::{MediaMetadata, ThumbnailSize};
+use audio::create_audio_thumbnail;
+use date::MEDIA_DATE_FORMAT;
 use image::create_image_thumbnail;
+use raw::{RawStrategy, create_raw_thumbnail, is_raw};
 use video::create_video_thumbnail;
 
+pub mod audio;
+pub mod date;
+#[cfg(feature = "heif")]
+pub mod heif;
 pub mod image;
+pub mod raw;
 pub mod video;
 
 const HASH_BUFFER: usize = 65536;
@@ -23,22 +34,147 @@ pub struct MediaData {
     pub hash: String,
     pub date: String,
     pub metadata: MediaMetadata,
+    pub details: Option<MediaDetails>,
 }
 
-pub async fn content_hash(path: impl AsRef<Path>) -> Result<String> {
-    let file = File::open(&path).await?;
+// extra properties found while processing media
+//
+// MediaMetadata is also the media_type column in the database, so these are carried
+// alongside it rather than inside the enum variants
+#[derive(Clone, Debug, PartialEq)]
+pub enum MediaDetails {
+    Image(ImageDetails),
+    Video(VideoDetails),
+    Audio(AudioDetails),
+}
 
-    let mut hasher = Sha512::new();
-    let mut buffer = [0; HASH_BUFFER];
+// image properties read from the exif data
+//
+// any field may be missing, since plenty of images (screenshots, scans, edited exports)
+// have partial or no exif data
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct ImageDetails {
+    pub width: Option<u32>,
+    pub height: Option<u32>,
+    pub capture_time: Option<String>,
+    pub camera: Option<String>,
+    pub orientation: Option<u32>,
+    pub latitude: Option<f64>,
+    pub longitude: Option<f64>,
+}
 
-    // TODO -- perf tuning
-    let mut reader = BufReader::with_capacity(HASH_BUFFER, file);
+// video properties read from the first video stream by ffprobe
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct VideoDetails {
+    pub duration_secs: Option<f64>,
+    pub width: Option<u32>,
+    pub height: Option<u32>,
+    pub codec: Option<String>,
+}
 
-    while reader.read(&mut buffer).await? > 0 {
-        hasher.update(buffer);
+// audio properties read from the id3/flac/vorbis tags
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct AudioDetails {
+    pub artist: Option<String>,
+    pub album: Option<String>,
+    pub title: Option<String>,
+    pub duration_secs: Option<f64>,
+}
+
+// file mtime in the same format as the exif dates, for media without a better date
+pub fn mtime_date(path: &Path) -> Result<String> {
+    Ok(DateTime::<Utc>::from(std::fs::metadata(path)?.modified()?)
+        .format(MEDIA_DATE_FORMAT)
+        .to_string())
+}
+
+// content hash algorithms
+//
+// the chash column is compared as an opaque string, so hashes from anything other than
+// the original sha512 carry the algorithm name as a prefix.  this keeps existing records
+// valid while ensuring that hashes from different algorithms can never match each other.
+#[derive(Clone, Debug, Default, Deserialize, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum HashAlgorithm {
+    #[default]
+    Sha512,
+    // considerably faster on large video files
+    Blake3,
+}
+
+const BLAKE3_PREFIX: &str = "blake3:";
+
+impl HashAlgorithm {
+    // the algorithm that produced a stored hash, so that it can be checked again
+    pub fn of_chash(chash: &str) -> Self {
+        if chash.starts_with(BLAKE3_PREFIX) {
+            HashAlgorithm::Blake3
+        } else {
+            HashAlgorithm::Sha512
+        }
     }
+}
+
+enum Hasher {
+    Sha512(Box<Sha512>),
+    Blake3(Box<blake3::Hasher>),
+}
+
+impl Hasher {
+    fn new(algorithm: &HashAlgorithm) -> Self {
+        match algorithm {
+            HashAlgorithm::Sha512 => Hasher::Sha512(Box::new(Sha512::new())),
+            HashAlgorithm::Blake3 => Hasher::Blake3(Box::new(blake3::Hasher::new())),
+        }
+    }
+
+    fn update(&mut self, data: &[u8]) {
+        match self {
+            Hasher::Sha512(h) => h.update(data),
+            Hasher::Blake3(h) => {
+                h.update(data);
+            }
+        }
+    }
+
+    fn finalize(self) -> String {
+        match self {
+            Hasher::Sha512(h) => encode((*h).finalize()),
+            Hasher::Blake3(h) => format!("{BLAKE3_PREFIX}{}", h.finalize().to_hex()),
+        }
+    }
+}
+
+// hashing a large video keeps a thread busy for a while, so the whole loop runs under
+// spawn_blocking() instead of tying up a runtime worker between reads
+pub async fn content_hash(path: impl AsRef<Path>, algorithm: &HashAlgorithm) -> Result<String> {
+    let path = path.as_ref().to_path_buf();
+    let algorithm = algorithm.clone();
 
-    Ok(encode(hasher.finalize()))
+    spawn_blocking(move || {
+        let file = File::open(&path)?;
+
+        let mut hasher = Hasher::new(&algorithm);
+        let mut buffer = [0; HASH_BUFFER];
+
+        // TODO -- perf tuning
+        let mut reader = BufReader::with_capacity(HASH_BUFFER, file);
+
+        // read() may return fewer bytes than the buffer holds (most obviously at the end
+        // of the file), so only the filled portion can go into the hasher
+        loop {
+            let n = reader.read(&mut buffer)?;
+
+            if n == 0 {
+                break;
+            }
+
+            hasher.update(&buffer[..n]);
+        }
+
+        Ok(hasher.finalize())
+    })
+    .await?
 }
 
 pub async fn create_thumbnail(
@@ -46,14 +182,111 @@ pub async fn create_thumbnail(
     thumbnail_path: &PathBuf,
     scratch_dir: &Path,
     metadata: &MediaMetadata,
+    raw_strategy: &RawStrategy,
+    size: ThumbnailSize,
 ) -> Result<()> {
     match metadata {
-        MediaMetadata::Image => Box::pin(create_image_thumbnail(path, thumbnail_path)).await?,
+        MediaMetadata::Image if is_raw(path) => {
+            Box::pin(create_raw_thumbnail(
+                path,
+                thumbnail_path,
+                raw_strategy,
+                size,
+            ))
+            .await?
+        }
+        MediaMetadata::Image => {
+            Box::pin(create_image_thumbnail(path, thumbnail_path, size)).await?
+        }
         MediaMetadata::Video => {
-            Box::pin(create_video_thumbnail(path, thumbnail_path, scratch_dir)).await?
+            Box::pin(create_video_thumbnail(
+                path,
+                thumbnail_path,
+                scratch_dir,
+                size,
+            ))
+            .await?
+        }
+        MediaMetadata::Audio => {
+            Box::pin(create_audio_thumbnail(path, thumbnail_path, size)).await?
         }
         _ => return Err(anyhow::Error::msg("no thumbnail method found")),
     };
 
     Ok(())
 }
+
+#[cfg(test)]
+mod tests {
+    use std::io::Write;
+
+    use super::*;
+
+    fn write_temp(data: &[u8]) -> tempfile::NamedTempFile {
+        let mut file = tempfile::NamedTempFile::new().unwrap();
+        file.write_all(data).unwrap();
+        file.flush().unwrap();
+        file
+    }
+
+    #[tokio::test]
+    async fn sha512_known_value() {
+        let file = write_temp(b"abc");
+
+        assert_eq!(
+            content_hash(file.path(), &HashAlgorithm::Sha512)
+                .await
+                .unwrap(),
+            "ddaf35a193617abacc417349ae20413112e6fa4e89a97ea20a9eeee64b55d39a\
+             2192992a274fc1a836ba3c23a3feebbd454d4423643ce80e2a9ac94fa54ca49f"
+        );
+    }
+
+    #[tokio::test]
+    async fn blake3_known_value() {
+        let file = write_temp(b"abc");
+
+        assert_eq!(
+            content_hash(file.path(), &HashAlgorithm::Blake3)
+                .await
+                .unwrap(),
+            "blake3:6437b3ac38465133ffb63b75273a8db548c558465d79db03fd359c6cd5bd9d85"
+        );
+    }
+
+    // the tail of a file that isn't a multiple of the buffer size must only be hashed once
+    #[tokio::test]
+    async fn partial_reads_match_one_shot() {
+        let data = (0..3 * HASH_BUFFER + 17)
+            .map(|i| (i % 251) as u8)
+            .collect::<Vec<u8>>();
+        let file = write_temp(&data);
+
+        assert_eq!(
+            content_hash(file.path(), &HashAlgorithm::Sha512)
+                .await
+                .unwrap(),
+            encode(Sha512::digest(&data))
+        );
+        assert_eq!(
+            content_hash(file.path(), &HashAlgorithm::Blake3)
+                .await
+                .unwrap(),
+            format!("{BLAKE3_PREFIX}{}", blake3::hash(&data).to_hex())
+        );
+    }
+
+    #[tokio::test]
+    async fn of_chash_recovers_algorithm() {
+        let file = write_temp(b"");
+
+        for algorithm in [HashAlgorithm::Sha512, HashAlgorithm::Blake3] {
+            let chash = content_hash(file.path(), &algorithm).await.unwrap();
+
+            assert_eq!(
+                std::mem::discriminant(&HashAlgorithm::of_chash(&chash)),
+                std::mem::discriminant(&algorithm)
+            );
+        }
+    }
+}
@@ -0,0 +1,174 @@
+use std::path::{Path, PathBuf};
+
+use anyhow::Result;
+use blockhash::blockhash256;
+use image::{DynamicImage, GenericImageView, ImageFormat};
+use serde::{Deserialize, Serialize};
+use tokio::{process::Command, task::spawn_blocking};
+use tracing::{debug, instrument};
+
+use crate::media::{
+    ImageDetails, MediaData, MediaDetails,
+    image::{exif_details, media_date, read_orientation},
+};
+use api::media::{MediaMetadata, ThumbnailSize};
+
+// camera raw calculations
+//
+// decoding raw sensor data is well beyond what we want to do here, so instead we pull out
+// the preview jpeg that nearly every camera embeds in the file and treat that as the image
+// for hashing and thumbnails.  the original file is still what gets linked and served.
+
+pub const RAW_EXTENSIONS: [&str; 7] = ["arw", "cr2", "dng", "nef", "orf", "raf", "rw2"];
+
+#[derive(Clone, Debug, Default, Deserialize, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum RawStrategy {
+    // read the preview location from the tiff structure of the file, which covers the
+    // common tiff-based formats (cr2, nef, arw, dng) without any external tools
+    #[default]
+    Embedded,
+    // shell out to `dcraw -e`, which knows about many more formats
+    Dcraw,
+}
+
+pub fn is_raw(path: &Path) -> bool {
+    path.extension()
+        .and_then(|f| f.to_str())
+        .map(|s| RAW_EXTENSIONS.contains(&s.to_lowercase().as_str()))
+        .unwrap_or(false)
+}
+
+// find the largest jpeg referenced by the tiff ifds
+//
+// the thumbnail ifd is usually tiny, but some cameras (i.e. canon) put a full-size preview
+// in the strips of ifd0 instead
+fn find_embedded_preview(data: &[u8]) -> Result<Vec<u8>> {
+    let exif = exif::Reader::new().read_raw(data.to_vec())?;
+
+    let get = |tag, ifd| {
+        exif.get_field(tag, ifd)
+            .and_then(|f| f.value.get_uint(0))
+            .map(|v| v as usize)
+    };
+
+    let mut candidates = Vec::new();
+
+    for ifd in [exif::In::PRIMARY, exif::In::THUMBNAIL] {
+        if let (Some(offset), Some(length)) = (
+            get(exif::Tag::JPEGInterchangeFormat, ifd),
+            get(exif::Tag::JPEGInterchangeFormatLength, ifd),
+        ) {
+            candidates.push((offset, length));
+        }
+
+        // old-style jpeg compression stored as a single strip
+        if get(exif::Tag::Compression, ifd) == Some(6)
+            && let (Some(offset), Some(length)) = (
+                get(exif::Tag::StripOffsets, ifd),
+                get(exif::Tag::StripByteCounts, ifd),
+            )
+        {
+            candidates.push((offset, length));
+        }
+    }
+
+    candidates
+        .into_iter()
+        .filter_map(|(offset, length)| data.get(offset..offset.checked_add(length)?))
+        .filter(|v| v.starts_with(&[0xff, 0xd8]))
+        .max_by_key(|v| v.len())
+        .map(|v| v.to_vec())
+        .ok_or_else(|| anyhow::Error::msg("no embedded preview found"))
+}
+
+#[instrument]
+async fn extract_preview(path: &Path, strategy: &RawStrategy) -> Result<Vec<u8>> {
+    debug!("extracting raw preview");
+
+    match strategy {
+        RawStrategy::Embedded => {
+            let data = tokio::fs::read(path).await?;
+
+            spawn_blocking(move || find_embedded_preview(&data)).await?
+        }
+        RawStrategy::Dcraw => {
+            let handle = Command::new("dcraw")
+                .args(["-e", "-c"])
+                .arg(path)
+                .kill_on_drop(true)
+                .output()
+                .await?;
+
+            if !handle.status.success() || handle.stdout.is_empty() {
+                return Err(anyhow::Error::msg("dcraw failed to extract a preview"));
+            }
+
+            Ok(handle.stdout)
+        }
+    }
+}
+
+#[instrument(skip(strategy))]
+pub async fn process_raw(path: &PathBuf, strategy: &RawStrategy) -> Result<MediaData> {
+    debug!("processing raw image");
+
+    let preview = extract_preview(path, strategy).await?;
+
+    let path = path.clone();
+
+    // the dimensions are those of the preview, which is what the gallery will show
+    let (hash, details, date) = spawn_blocking(move || {
+        let image = image::load_from_memory(&preview)?;
+
+        let hash = blockhash256(&image).to_string();
+
+        let (width, height) = image.dimensions();
+
+        let details = exif_details(&path, width, height)?;
+        let date = media_date(&path, &details)?;
+
+        Result::<(String, ImageDetails, String)>::Ok((hash, details, date))
+    })
+    .await??;
+
+    debug!("finished processing raw image");
+
+    Ok(MediaData {
+        hash,
+        date,
+        metadata: MediaMetadata::Image,
+        details: Some(MediaDetails::Image(details)),
+    })
+}
+
+#[instrument(skip(strategy))]
+pub async fn create_raw_thumbnail(
+    original_path: &PathBuf,
+    thumbnail_path: &PathBuf,
+    strategy: &RawStrategy,
+    size: ThumbnailSize,
+) -> Result<()> {
+    debug!("creating raw thumbnail");
+
+    let preview = extract_preview(original_path, strategy).await?;
+
+    let original_path = original_path.clone();
+    let thumbnail_path = thumbnail_path.clone();
+
+    spawn_blocking(move || {
+        let mut image: DynamicImage = image::load_from_memory(&preview)?;
+
+        // the orientation tag belongs to the raw file, not the preview
+        image.apply_orientation(read_orientation(&original_path));
+
+        let thumbnail = image.thumbnail(size.bound(), size.bound());
+
+        thumbnail.save_with_format(thumbnail_path, ImageFormat::Png)?;
+
+        debug!("finished creating raw thumbnail");
+
+        Ok(())
+    })
+    .await?
+}
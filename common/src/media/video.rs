@@ -1,14 +1,15 @@
 use std::path::{Path, PathBuf};
 
 use anyhow::Result;
+use serde::Deserialize;
 use tokio::process::Command;
-use tracing::{debug, instrument};
+use tracing::{debug, instrument, warn};
 
 use crate::media::{
-    MediaData,
+    MediaData, MediaDetails, VideoDetails,
     image::{create_image_thumbnail, hash_image},
 };
-use api::media::MediaMetadata;
+use api::media::{MediaMetadata, ThumbnailSize};
 
 // video calculations
 
@@ -24,10 +25,22 @@ pub async fn process_video(path: &PathBuf, scratchdir: &Path) -> Result<MediaDat
 
     let hash = hash_image(&img_path).await?;
 
+    // the details are nice to have, so a failed probe still leaves us with a bare video
+    let details = match probe_video(path).await {
+        Ok(v) => Some(MediaDetails::Video(v)),
+        Err(err) => {
+            warn!("failed to probe video details: {err}");
+            None
+        }
+    };
+
+    debug!({ details = ?details }, "finished processing video");
+
     Ok(MediaData {
         hash,
         date,
         metadata: MediaMetadata::Video,
+        details,
     })
 }
 
@@ -36,6 +49,7 @@ pub async fn create_video_thumbnail(
     original_path: &PathBuf,
     thumbnail_path: &PathBuf,
     scratchdir: &Path,
+    size: ThumbnailSize,
 ) -> Result<()> {
     debug!("started creating video thumbnail");
 
@@ -44,7 +58,7 @@ pub async fn create_video_thumbnail(
     img_path.push("thumb.png");
 
     create_video_ffmpeg_image(original_path, &img_path).await?;
-    create_image_thumbnail(&img_path, thumbnail_path).await?;
+    create_image_thumbnail(&img_path, thumbnail_path, size).await?;
 
     Ok(())
 }
@@ -96,3 +110,118 @@ async fn parse_video_metadata_dump(original_path: &PathBuf) -> Result<String> {
         return Ok(out.to_string());
     }
 }
+
+// subset of the ffprobe json output
+#[derive(Debug, Deserialize)]
+struct ProbeOutput {
+    #[serde(default)]
+    streams: Vec<ProbeStream>,
+    format: Option<ProbeFormat>,
+}
+
+#[derive(Debug, Deserialize)]
+struct ProbeStream {
+    codec_name: Option<String>,
+    width: Option<u32>,
+    height: Option<u32>,
+}
+
+#[derive(Debug, Deserialize)]
+struct ProbeFormat {
+    // ffprobe reports this as a string
+    duration: Option<String>,
+}
+
+#[instrument]
+pub async fn probe_video(original_path: &PathBuf) -> Result<VideoDetails> {
+    let handle = Command::new("ffprobe")
+        .args(["-v", "quiet"])
+        .args(["-select_streams", "v:0"])
+        .args([
+            "-show_entries",
+            "stream=codec_name,width,height:format=duration",
+        ])
+        .args(["-output_format", "json"])
+        .arg(original_path)
+        .kill_on_drop(true)
+        .output()
+        .await?;
+
+    if !handle.status.success() {
+        return Err(anyhow::Error::msg("ffprobe failed to process the media"));
+    }
+
+    parse_probe_output(&handle.stdout)
+}
+
+fn parse_probe_output(stdout: &[u8]) -> Result<VideoDetails> {
+    let out: ProbeOutput = serde_json::from_slice(stdout)?;
+
+    let stream = out
+        .streams
+        .into_iter()
+        .next()
+        .ok_or_else(|| anyhow::Error::msg("no video stream found"))?;
+
+    Ok(VideoDetails {
+        duration_secs: out
+            .format
+            .and_then(|f| f.duration)
+            .and_then(|d| d.parse::<f64>().ok()),
+        width: stream.width,
+        height: stream.height,
+        codec: stream.codec_name,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // one second of 8x4 mjpeg at 10 fps
+    const CLIP: &str = concat!(env!("CARGO_MANIFEST_DIR"), "/testdata/clip.avi");
+
+    #[test]
+    fn parses_probe_output() {
+        let out = br#"{
+            "programs": [],
+            "streams": [{ "codec_name": "h264", "width": 1920, "height": 1080 }],
+            "format": { "duration": "12.345000" }
+        }"#;
+
+        assert_eq!(
+            parse_probe_output(out).unwrap(),
+            VideoDetails {
+                duration_secs: Some(12.345),
+                width: Some(1920),
+                height: Some(1080),
+                codec: Some(String::from("h264")),
+            }
+        );
+    }
+
+    #[test]
+    fn probe_output_without_video_stream() {
+        assert!(parse_probe_output(br#"{ "streams": [], "format": {} }"#).is_err());
+    }
+
+    #[tokio::test]
+    async fn probes_short_clip() {
+        // like the rest of the video processing, this needs ffprobe on the path
+        if std::process::Command::new("ffprobe")
+            .arg("-version")
+            .output()
+            .is_err()
+        {
+            eprintln!("skipping, ffprobe is not installed");
+            return;
+        }
+
+        let details = probe_video(&PathBuf::from(CLIP)).await.unwrap();
+
+        assert_eq!(details.width, Some(8));
+        assert_eq!(details.height, Some(4));
+        assert_eq!(details.codec.as_deref(), Some("mjpeg"));
+        assert!((details.duration_secs.unwrap() - 1.0).abs() < 0.05);
+    }
+}
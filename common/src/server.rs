@@ -1,6 +1,13 @@
-use std::path::PathBuf;
+use std::{collections::HashMap, path::PathBuf};
 
 use serde::{Deserialize, Serialize};
+use url::Url;
+
+use crate::{
+    auth::RetryConfig,
+    media::{HashAlgorithm, raw::RawStrategy},
+};
+use api::library::LibraryUuid;
 
 // entanglement server configuration subtables
 //
@@ -39,13 +46,83 @@ pub struct HttpConfig {
     // concatenated, pem-encoded ca certs to use when verifying
     // a client tls connection
     pub client_ca_cert: Option<PathBuf>,
+
+    // largest media upload accepted, in bytes, defaults to 4 GiB
+    pub upload_limit: Option<usize>,
+
+    // prometheus metrics, which are disabled unless this table is present
+    pub metrics: Option<MetricsConfig>,
+
+    // per-client request limits, which are disabled unless this table is present
+    pub rate_limit: Option<RateLimitConfig>,
+
+    // number of media in the atom feed, defaults to 50
+    pub feed_size: Option<u64>,
+}
+
+// where the prometheus metrics are served
+//
+// by default, they are at /entanglement/metrics on the main socket, behind the usual
+// authentication and limited to the admin group.  if a socket is set, they are instead
+// served there over plain http without any authentication, which is only appropriate
+// for an address that just the scraper can reach.
+#[derive(Clone, Debug, Deserialize, Serialize)]
+pub struct MetricsConfig {
+    pub socket: Option<String>,
+}
+
+// token buckets for the http routes
+//
+// each client gets its own bucket for each route, where the client is the authenticated user
+// or, for the routes outside of the auth middleware, the source address.  routes are named by
+// their path below the url root as written in the router, e.g. "api/SearchMedia" or
+// "media/{dir}/{media_uuid}", and any route not listed uses the default.
+#[derive(Clone, Debug, Deserialize, Serialize)]
+pub struct RateLimitConfig {
+    pub default: RateLimit,
+    #[serde(default)]
+    pub routes: HashMap<String, RateLimit>,
+}
+
+// a bucket holds up to burst requests and refills at rate requests per second.  both have to be
+// positive, since otherwise an empty bucket never refills (or there is never a token to take)
+#[derive(Clone, Copy, Debug, Deserialize, Serialize)]
+#[serde(try_from = "RateLimitFields")]
+pub struct RateLimit {
+    pub rate: f64,
+    pub burst: u64,
+}
+
+#[derive(Deserialize)]
+struct RateLimitFields {
+    rate: f64,
+    burst: u64,
+}
+
+impl TryFrom<RateLimitFields> for RateLimit {
+    type Error = String;
+
+    fn try_from(fields: RateLimitFields) -> Result<Self, Self::Error> {
+        if fields.rate.is_nan() || fields.rate <= 0.0 {
+            return Err(String::from("rate limit rate must be positive"));
+        }
+
+        if fields.burst == 0 {
+            return Err(String::from("rate limit burst must be positive"));
+        }
+
+        Ok(RateLimit {
+            rate: fields.rate,
+            burst: fields.burst,
+        })
+    }
 }
 
 #[derive(Clone, Debug, Deserialize, Serialize)]
 pub struct TaskConfig {
-    // maximum number of tokio tasks use for running scan jobs,
-    // which should be less than the number of OS threads since
-    // some of the crates have blocking io calls
+    // maximum number of files that a task processes at once.  the
+    // blocking io and cpu-heavy work runs on tokio's blocking pool,
+    // so this bounds the load on the disks rather than the runtime
     pub scan_threads: usize,
 
     // temporary folder used by scanner for things like creating
@@ -54,4 +131,77 @@ pub struct TaskConfig {
 
     // time to wait on individual scan jobs
     pub scan_timeout: u64,
+
+    // how to find the preview image in camera raw files,
+    // defaults to reading the embedded jpeg directly
+    pub raw_strategy: Option<RawStrategy>,
+
+    // content hash used to identify files across scans,
+    // defaults to sha512 to match existing records
+    //
+    // changing it needs a ScanLibraryFull of every library,
+    // which rehashes each file at its recorded path.  until
+    // then, moved files and duplicate uploads are only found
+    // among records that were hashed with the new algorithm.
+    pub hash_algorithm: Option<HashAlgorithm>,
+
+    // libraries to scan automatically, see below
+    pub scan_schedule: Option<Vec<ScanSchedule>>,
+}
+
+// a recurring library scan
+//
+// the cron expression includes seconds, i.e. "0 30 3 * * *" scans every day at
+// 03:30 utc.  a run is skipped if the library already has a task running.
+#[derive(Clone, Debug, Deserialize, Serialize)]
+pub struct ScanSchedule {
+    pub library_uuid: LibraryUuid,
+    pub cron: String,
+}
+
+// external endpoints notified about task completions and new media, see webhook.rs
+#[derive(Clone, Debug, Deserialize, Serialize)]
+pub struct WebhookConfig {
+    pub urls: Vec<Url>,
+
+    // attempts and timeouts for each delivery, which default
+    // to the same values as the auth providers
+    pub retry: Option<RetryConfig>,
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn rate_limit(doc: &str) -> Result<RateLimit, toml::de::Error> {
+        toml::from_str(doc)
+    }
+
+    #[test]
+    fn accepts_positive_rate_limits() {
+        let limit = rate_limit("rate = 0.5\nburst = 10").unwrap();
+
+        assert_eq!(limit.rate, 0.5);
+        assert_eq!(limit.burst, 10);
+    }
+
+    #[test]
+    fn rejects_rate_limits_that_never_allow_a_request() {
+        assert!(rate_limit("rate = 0.0\nburst = 10").is_err());
+        assert!(rate_limit("rate = -1.0\nburst = 10").is_err());
+        assert!(rate_limit("rate = nan\nburst = 10").is_err());
+        assert!(rate_limit("rate = 1.0\nburst = 0").is_err());
+    }
+
+    #[test]
+    fn rejects_invalid_route_limits() {
+        let config: Result<RateLimitConfig, _> = toml::from_str(
+            r#"
+            default = { rate = 1.0, burst = 10 }
+            routes = { "api/SearchMedia" = { rate = 0.0, burst = 10 } }
+            "#,
+        );
+
+        assert!(config.is_err());
+    }
 }
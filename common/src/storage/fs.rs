@@ -0,0 +1,165 @@
+use std::{
+    io::{ErrorKind, SeekFrom},
+    path::{Path, PathBuf},
+    sync::Arc,
+};
+
+use anyhow::Result;
+use async_trait::async_trait;
+use mime_guess::MimeGuess;
+use tokio::{
+    fs::{
+        File, canonicalize, copy, metadata, read_dir, remove_dir_all, remove_file, rename, symlink,
+        symlink_metadata,
+    },
+    io::{AsyncReadExt, AsyncSeekExt},
+};
+use tracing::{debug, instrument};
+
+use crate::{
+    config::ESConfig,
+    storage::{ObjectInfo, ObjectReader, StorageBackend, thumbnail_name},
+};
+use api::{
+    LINK_PATH, THUMBNAIL_PATH,
+    media::{MediaUuid, ThumbnailSize},
+};
+
+// local filesystem storage
+//
+// the srv directory is used directly, with the originals linked in from the libraries so
+// that the http server can function like an object store without reorganizing anything.
+// the subdirectories are created by the startup checks.
+#[derive(Debug)]
+pub struct FsBackend {
+    config: Arc<ESConfig>,
+}
+
+pub fn media_link_path(config: Arc<ESConfig>, media_uuid: MediaUuid) -> PathBuf {
+    config
+        .fs
+        .media_srvdir
+        .join(LINK_PATH)
+        .join(media_uuid.to_string())
+}
+
+pub fn media_thumbnail_path(
+    config: Arc<ESConfig>,
+    media_uuid: MediaUuid,
+    size: ThumbnailSize,
+) -> PathBuf {
+    config
+        .fs
+        .media_srvdir
+        .join(THUMBNAIL_PATH)
+        .join(thumbnail_name(media_uuid, size))
+}
+
+impl FsBackend {
+    pub fn new(config: Arc<ESConfig>) -> Self {
+        FsBackend { config }
+    }
+
+    fn path(&self, dir: &str, name: &str) -> PathBuf {
+        self.config.fs.media_srvdir.join(dir).join(name)
+    }
+}
+
+#[async_trait]
+impl StorageBackend for FsBackend {
+    #[instrument(skip(self))]
+    async fn put_original(&self, media_uuid: MediaUuid, path: &Path) -> Result<()> {
+        let link_path = media_link_path(self.config.clone(), media_uuid);
+
+        let _ = remove_file(&link_path).await;
+
+        symlink(path, &link_path).await?;
+
+        Ok(())
+    }
+
+    // a rename keeps anyone from being served a partial file, but the scratch directories
+    // may be on a different filesystem
+    #[instrument(skip(self))]
+    async fn put_file(&self, dir: &str, name: &str, path: &Path) -> Result<()> {
+        let target = self.path(dir, name);
+
+        if rename(path, &target).await.is_err() {
+            let mut partial = target.clone().into_os_string();
+            partial.push(".tmp");
+
+            copy(path, &partial).await?;
+            rename(&partial, &target).await?;
+            remove_file(path).await?;
+        }
+
+        Ok(())
+    }
+
+    // the metadata follows the links, so it describes the original
+    async fn info(&self, dir: &str, name: &str) -> Result<Option<ObjectInfo>> {
+        let path = self.path(dir, name);
+
+        let file_metadata = match metadata(&path).await {
+            Ok(v) => v,
+            Err(err) if err.kind() == ErrorKind::NotFound => return Ok(None),
+            Err(err) => return Err(err.into()),
+        };
+
+        let content_type = match dir {
+            LINK_PATH => MimeGuess::from_path(canonicalize(&path).await?)
+                .first()
+                .map(|mime| mime.essence_str().to_owned()),
+            _ => None,
+        };
+
+        Ok(Some(ObjectInfo {
+            length: file_metadata.len(),
+            modified: file_metadata.modified().ok(),
+            content_type,
+        }))
+    }
+
+    async fn get(&self, dir: &str, name: &str, range: Option<(u64, u64)>) -> Result<ObjectReader> {
+        let mut file = File::open(self.path(dir, name)).await?;
+
+        Ok(match range {
+            Some((start, end)) => {
+                file.seek(SeekFrom::Start(start)).await?;
+                Box::new(file.take(end - start))
+            }
+            None => Box::new(file),
+        })
+    }
+
+    #[instrument(skip(self))]
+    async fn delete(&self, dir: &str, name: &str) -> Result<()> {
+        let path = self.path(dir, name);
+
+        // anything else in the srv directory is junk, including directories
+        let result = match symlink_metadata(&path).await {
+            Ok(v) if v.is_dir() => remove_dir_all(&path).await,
+            Ok(_) => remove_file(&path).await,
+            Err(err) => Err(err),
+        };
+
+        match result {
+            Ok(()) => debug!("removed {path:?}"),
+            Err(err) if err.kind() == ErrorKind::NotFound => {}
+            Err(err) => return Err(err.into()),
+        }
+
+        Ok(())
+    }
+
+    async fn list(&self, dir: &str) -> Result<Vec<String>> {
+        let mut entries = read_dir(self.config.fs.media_srvdir.join(dir)).await?;
+        let mut out = Vec::new();
+
+        while let Some(entry) = entries.next_entry().await? {
+            out.push(entry.file_name().to_string_lossy().into_owned());
+        }
+
+        Ok(out)
+    }
+}
@@ -0,0 +1,70 @@
+use std::{path::Path, sync::Arc, time::SystemTime};
+
+use anyhow::Result;
+use async_trait::async_trait;
+use tokio::io::AsyncRead;
+
+use crate::config::{ESConfig, StorageBackend as StorageBackendType};
+use api::media::{MediaUuid, ThumbnailSize};
+
+pub mod fs;
+pub use fs::FsBackend;
+
+#[cfg(feature = "s3")]
+pub mod s3;
+#[cfg(feature = "s3")]
+pub use s3::S3Backend;
+
+// media storage
+//
+// everything that the http service streams to clients lives in the srv directory: links to
+// the originals, thumbnails, and video slices.  these are the operations that any place
+// holding them must support, so that they can live in an object store instead of on a
+// local filesystem.
+//
+// objects are addressed the same way as the stream routes, i.e. by the subdirectory of the
+// srv directory (LINK_PATH, THUMBNAIL_PATH, SLICE_PATH) and the file name.  the libraries
+// themselves are always read from media_srcdir, since scanning needs a real filesystem.
+#[async_trait]
+pub trait StorageBackend: std::fmt::Debug + Send + Sync + 'static {
+    // make an original available under LINK_PATH
+    async fn put_original(&self, media_uuid: MediaUuid, path: &Path) -> Result<()>;
+
+    // store a file written by the server (i.e. a thumbnail), which is consumed in the process
+    async fn put_file(&self, dir: &str, name: &str, path: &Path) -> Result<()>;
+
+    // None if the object does not exist
+    async fn info(&self, dir: &str, name: &str) -> Result<Option<ObjectInfo>>;
+
+    // the bytes from start up to (but not including) end, or else the whole object
+    async fn get(&self, dir: &str, name: &str, range: Option<(u64, u64)>) -> Result<ObjectReader>;
+
+    // succeeds if the object is already gone
+    async fn delete(&self, dir: &str, name: &str) -> Result<()>;
+
+    // the name of every object in the directory
+    async fn list(&self, dir: &str) -> Result<Vec<String>>;
+}
+
+#[derive(Clone, Debug)]
+pub struct ObjectInfo {
+    pub length: u64,
+    pub modified: Option<SystemTime>,
+    // only known for the originals, from the file extension
+    pub content_type: Option<String>,
+}
+
+pub type ObjectReader = Box<dyn AsyncRead + Send + Unpin>;
+
+pub fn thumbnail_name(media_uuid: MediaUuid, size: ThumbnailSize) -> String {
+    format!("{media_uuid}{}", size.suffix())
+}
+
+// the storage backend selected in the config, which defaults to the local filesystem
+pub fn create_storage(config: Arc<ESConfig>) -> Result<Arc<dyn StorageBackend>> {
+    Ok(match config.storage_backend.clone().unwrap_or_default() {
+        StorageBackendType::Fs => Arc::new(FsBackend::new(config)),
+        #[cfg(feature = "s3")]
+        StorageBackendType::S3 => Arc::new(S3Backend::new(config)?),
+    })
+}
@@ -0,0 +1,191 @@
+use std::{path::Path, sync::Arc, time::SystemTime};
+
+use anyhow::Result;
+use async_trait::async_trait;
+use chrono::DateTime;
+use mime_guess::MimeGuess;
+use s3::{Bucket, Region, creds::Credentials, error::S3Error};
+use serde::{Deserialize, Serialize};
+use tokio::{
+    fs::{File, remove_file},
+    io::duplex,
+    task::spawn,
+};
+use tracing::{error, instrument};
+
+use crate::{
+    config::ESConfig,
+    storage::{ObjectInfo, ObjectReader, StorageBackend},
+};
+use api::{LINK_PATH, media::MediaUuid};
+
+// s3-compatible object storage
+//
+// the objects are kept under the same dir/name keys as the srv directory, so switching an
+// existing server over means copying the thumbnails across (or letting the clean task
+// recreate them).  unlike the filesystem backend, the originals are uploaded rather than
+// linked, so the bucket needs room for a full copy of every library.
+#[derive(Clone, Debug, Deserialize, Serialize)]
+pub struct S3Config {
+    pub bucket: String,
+    pub region: String,
+    // for minio and other s3-compatible stores, e.g. "http://minio:9000"
+    pub endpoint: Option<String>,
+    // prepended to every key, so that the bucket can be shared
+    pub prefix: Option<String>,
+    // if unset, these are found the same way as the aws cli does (AWS_ACCESS_KEY_ID and
+    // AWS_SECRET_ACCESS_KEY, the credentials file, or the instance metadata)
+    pub access_key: Option<String>,
+    pub secret_key: Option<String>,
+    // most s3-compatible stores need path-style urls instead of bucket subdomains
+    pub path_style: Option<bool>,
+}
+
+// size of the pipe between the download and the http body
+const STREAM_BUF_SIZE: usize = 1024 * 1024;
+
+#[derive(Debug)]
+pub struct S3Backend {
+    bucket: Box<Bucket>,
+    prefix: String,
+}
+
+impl S3Backend {
+    pub fn new(config: Arc<ESConfig>) -> Result<Self> {
+        let s3_config = config.s3.clone().ok_or_else(|| {
+            anyhow::Error::msg("s3 storage backend selected without an s3 config")
+        })?;
+
+        let region = match s3_config.endpoint {
+            Some(endpoint) => Region::Custom {
+                region: s3_config.region,
+                endpoint,
+            },
+            None => s3_config.region.parse()?,
+        };
+
+        let credentials = Credentials::new(
+            s3_config.access_key.as_deref(),
+            s3_config.secret_key.as_deref(),
+            None,
+            None,
+            None,
+        )?;
+
+        let mut bucket = Bucket::new(&s3_config.bucket, region, credentials)?;
+
+        if s3_config.path_style.unwrap_or(false) {
+            bucket = bucket.with_path_style();
+        }
+
+        let prefix = match s3_config.prefix.as_deref().map(|v| v.trim_matches('/')) {
+            None | Some("") => String::new(),
+            Some(prefix) => format!("{prefix}/"),
+        };
+
+        Ok(S3Backend { bucket, prefix })
+    }
+
+    fn key(&self, dir: &str, name: &str) -> String {
+        format!("{}{dir}/{name}", self.prefix)
+    }
+}
+
+#[async_trait]
+impl StorageBackend for S3Backend {
+    // the content type is stored with the object, since the key has no extension
+    #[instrument(skip(self))]
+    async fn put_original(&self, media_uuid: MediaUuid, path: &Path) -> Result<()> {
+        let mut file = File::open(path).await?;
+
+        let content_type = MimeGuess::from_path(path).first_or_octet_stream();
+
+        self.bucket
+            .put_object_stream_with_content_type(
+                &mut file,
+                self.key(LINK_PATH, &media_uuid.to_string()),
+                content_type.essence_str(),
+            )
+            .await?;
+
+        Ok(())
+    }
+
+    #[instrument(skip(self))]
+    async fn put_file(&self, dir: &str, name: &str, path: &Path) -> Result<()> {
+        let mut file = File::open(path).await?;
+
+        self.bucket
+            .put_object_stream(&mut file, self.key(dir, name))
+            .await?;
+
+        remove_file(path).await?;
+
+        Ok(())
+    }
+
+    async fn info(&self, dir: &str, name: &str) -> Result<Option<ObjectInfo>> {
+        let head = match self.bucket.head_object(self.key(dir, name)).await {
+            Ok((head, _)) => head,
+            Err(S3Error::HttpFailWithBody(404, _)) => return Ok(None),
+            Err(err) => return Err(err.into()),
+        };
+
+        Ok(Some(ObjectInfo {
+            length: head.content_length.unwrap_or_default().try_into()?,
+            modified: head
+                .last_modified
+                .and_then(|v| DateTime::parse_from_rfc2822(&v).ok())
+                .map(SystemTime::from),
+            content_type: head.content_type,
+        }))
+    }
+
+    // the object is downloaded into one end of a pipe while the caller reads the other, so
+    // that large videos are never held in memory.  a failure partway through can only be
+    // logged, and leaves the reader with a truncated object.
+    async fn get(&self, dir: &str, name: &str, range: Option<(u64, u64)>) -> Result<ObjectReader> {
+        let key = self.key(dir, name);
+        let bucket = self.bucket.clone();
+
+        let (mut writer, reader) = duplex(STREAM_BUF_SIZE);
+
+        spawn(async move {
+            let result = match range {
+                // the s3 range is inclusive
+                Some((start, end)) => {
+                    bucket
+                        .get_object_range_to_writer(&key, start, Some(end - 1), &mut writer)
+                        .await
+                }
+                None => bucket.get_object_to_writer(&key, &mut writer).await,
+            };
+
+            if let Err(err) = result {
+                error!({ key }, "failed to download object: {err}");
+            }
+        });
+
+        Ok(Box::new(reader))
+    }
+
+    #[instrument(skip(self))]
+    async fn delete(&self, dir: &str, name: &str) -> Result<()> {
+        self.bucket.delete_object(self.key(dir, name)).await?;
+
+        Ok(())
+    }
+
+    async fn list(&self, dir: &str) -> Result<Vec<String>> {
+        let prefix = self.key(dir, "");
+
+        Ok(self
+            .bucket
+            .list(prefix.clone(), None)
+            .await?
+            .into_iter()
+            .flat_map(|result| result.contents)
+            .filter_map(|object| object.key.strip_prefix(&prefix).map(|v| v.to_owned()))
+            .collect())
+    }
+}
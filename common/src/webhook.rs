@@ -0,0 +1,144 @@
+use std::sync::Arc;
+
+use anyhow::Result;
+use serde::Serialize;
+use tokio::{
+    sync::mpsc::{Receiver, Sender, channel, error::TrySendError},
+    task::spawn,
+};
+use tracing::{Instrument, Level, debug, instrument, span, warn};
+use url::Url;
+
+use crate::{
+    auth::{RetryConfig, retry},
+    config::ESConfig,
+    unix_time,
+};
+use api::{
+    library::LibraryUuid,
+    media::MediaUuid,
+    task::{TaskStatus, TaskType},
+};
+
+// webhook notifications
+//
+// events are POSTed as json to every configured url.  delivery is best-effort: events
+// are queued and sent by a single worker, and once the queue is full, new events are
+// dropped rather than making the caller wait.  thus, a slow or dead endpoint can only
+// ever cost us its own notifications.
+#[derive(Clone, Debug)]
+pub struct WebhookDispatcher {
+    sender: Option<Sender<WebhookPayload>>,
+}
+
+// events waiting on delivery, across all urls
+const WEBHOOK_QUEUE: usize = 1024;
+
+// the body of each POST
+#[derive(Clone, Debug, Serialize)]
+pub struct WebhookPayload {
+    // unix time that the event happened
+    pub time: u64,
+    #[serde(flatten)]
+    pub event: WebhookEvent,
+}
+
+#[derive(Clone, Debug, Serialize)]
+#[serde(tag = "event", rename_all = "snake_case")]
+pub enum WebhookEvent {
+    // the library is null for system tasks
+    TaskCompleted {
+        library_uuid: Option<LibraryUuid>,
+        task_type: TaskType,
+        status: TaskStatus,
+        warnings: Option<i64>,
+        start: u64,
+        end: u64,
+        summary: Option<String>,
+    },
+    MediaAdded {
+        library_uuid: LibraryUuid,
+        media_uuid: MediaUuid,
+        path: String,
+    },
+}
+
+impl WebhookDispatcher {
+    // must be called from within the runtime, since it spawns the delivery worker
+    pub fn new(config: Arc<ESConfig>) -> Self {
+        let Some(webhook) = config.webhook.clone().filter(|v| !v.urls.is_empty()) else {
+            return WebhookDispatcher { sender: None };
+        };
+
+        let (tx, rx) = channel(WEBHOOK_QUEUE);
+
+        spawn(
+            deliver(
+                rx,
+                reqwest::Client::new(),
+                webhook.urls,
+                webhook.retry.unwrap_or_default(),
+            )
+            .instrument(span!(Level::INFO, "webhook_worker")),
+        );
+
+        WebhookDispatcher { sender: Some(tx) }
+    }
+
+    // queue an event for delivery, returning immediately
+    pub fn dispatch(&self, event: WebhookEvent) {
+        let Some(sender) = &self.sender else {
+            return;
+        };
+
+        let payload = WebhookPayload {
+            time: unix_time(),
+            event,
+        };
+
+        match sender.try_send(payload) {
+            Ok(()) => {}
+            Err(TrySendError::Full(_)) => warn!("webhook queue full, dropping event"),
+            Err(TrySendError::Closed(_)) => warn!("webhook worker stopped, dropping event"),
+        }
+    }
+}
+
+async fn deliver(
+    mut receiver: Receiver<WebhookPayload>,
+    client: reqwest::Client,
+    urls: Vec<Url>,
+    retry_config: RetryConfig,
+) {
+    while let Some(payload) = receiver.recv().await {
+        for url in urls.iter() {
+            if let Err(err) = post(&client, url, &payload, &retry_config).await {
+                warn!({ %url }, "failed to deliver webhook: {err}");
+            }
+        }
+    }
+}
+
+#[instrument(skip(client, payload, retry_config))]
+async fn post(
+    client: &reqwest::Client,
+    url: &Url,
+    payload: &WebhookPayload,
+    retry_config: &RetryConfig,
+) -> Result<()> {
+    retry(retry_config, || async {
+        client
+            .post(url.clone())
+            .json(payload)
+            .send()
+            .await?
+            .error_for_status()?;
+
+        Ok(())
+    })
+    .await?;
+
+    debug!("delivered webhook");
+
+    Ok(())
+}
@@ -24,6 +24,8 @@ trait ESAuthService: ESInner {
 
     async fn is_group_member(&self, uid: String, gid: HashSet<String>) -> Result<bool>;
 
+    async fn is_admin(&self, uid: String) -> Result<bool>;
+
     async fn can_access_media(&self, uid: String, media_uuid: MediaUuid) -> Result<bool>;
 
     async fn owns_media(&self, uid: String, media_uuid: MediaUuid) -> Result<bool>;
@@ -32,4 +34,7 @@ trait ESAuthService: ESInner {
     async fn authenticate_user(&self, uid: String, password: String) -> Result<bool>;
 
     async fn is_valid_user(&self, uid: String) -> Result<bool>;
+
+    // health
+    async fn ping(&self) -> Result<()>;
 }
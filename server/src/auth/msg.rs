@@ -4,7 +4,7 @@ use api::media::MediaUuid;
 
 use crate::service::*;
 
-#[derive(Debug)]
+#[derive(Debug, strum::IntoStaticStr)]
 pub enum AuthMsg {
     _ClearUserCache {
         resp: EsmResp<()>,
@@ -27,6 +27,10 @@ pub enum AuthMsg {
         uid: String,
         gid: HashSet<String>,
     },
+    IsAdmin {
+        resp: EsmResp<bool>,
+        uid: String,
+    },
     CanAccessMedia {
         resp: EsmResp<bool>,
         uid: String,
@@ -46,6 +50,9 @@ pub enum AuthMsg {
         resp: EsmResp<bool>,
         uid: String,
     },
+    Ping {
+        resp: EsmResp<()>,
+    },
 }
 
 impl From<AuthMsg> for Esm {
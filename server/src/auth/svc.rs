@@ -2,35 +2,42 @@ use std::{collections::HashSet, sync::Arc, time::Duration};
 
 use async_cell::sync::AsyncCell;
 use async_trait::async_trait;
-use regex::Regex;
 use tokio::{sync::Mutex, task::spawn, time::timeout};
-use tracing::{Instrument, Level, debug, error, info, instrument, span};
+use tracing::{Instrument, Level, debug, info, instrument, span};
 
 use crate::{
     auth::{ESAuthService, msg::AuthMsg},
     db::msg::DbMsg,
-    service::{ESInner, ESMRegistry, EntanglementService, Esm, EsmReceiver, ServiceType},
+    service::{
+        ESInner, ESMRegistry, EntanglementService, Esm, EsmReceiver, ServiceType, serve_messages,
+    },
 };
 use api::media::MediaUuid;
 use common::{
-    AwaitCache, GROUP_REGEX, USER_REGEX,
+    AwaitCache,
     auth::{
         AuthnProvider, AuthzProvider,
         cert::CertAuthn,
-        ldap::LdapAuthz,
+        ldap::{LdapAuthn, LdapAuthz},
+        oidc::OidcAuthn,
         proxy::ProxyAuth,
         tomlfile::{TomlAuthnFile, TomlAuthzFile},
     },
     config::{AuthnBackend, AuthzBackend, ESConfig},
+    is_valid_gid, is_valid_uid,
 };
 
+// default lifetime of cached group memberships and media access, in seconds
+const AUTH_CACHE_TTL: u64 = 600;
+
 // auth service
 //
 // the auth service is really two services in one -- a way to query several authentication (authn)
 // and authorization (authz) providers, and a cache so we don't have to query them often
 //
-// the cache semantics are not ideal (need to be flushed manually when the group information
-// changes), but the database service will flush individual media files when their collections change
+// the cache semantics are not ideal -- group information changed in the authz backend is only
+// picked up once the entries expire (see auth_cache_ttl), but the database service will flush
+// individual media files when their collections change
 //
 // there are almost certainly several better ways of doing this, which will likely matter when we
 // switch to using ldap instead of a fixed file, but the services should roughly stay the same
@@ -73,25 +80,7 @@ impl EntanglementService for AuthService {
         //
         // example would use StreamExt's ready_chunks() method
 
-        let serve = {
-            async move {
-                let mut receiver = receiver.lock().await;
-
-                while let Some(msg) = receiver.recv().await {
-                    let state = Arc::clone(&state);
-                    spawn(async move {
-                        match state.message_handler(msg).await {
-                            Ok(()) => (),
-                            Err(err) => {
-                                error!({service = "auth", channel = "esm", error = %err})
-                            }
-                        }
-                    });
-                }
-
-                Err(anyhow::Error::msg("auth service esm channel disconnected"))
-            }
-        };
+        let serve = serve_messages("auth", receiver, state);
 
         self.handle.set(spawn(serve));
 
@@ -109,8 +98,7 @@ pub struct AuthCache {
     user_cache: Arc<AwaitCache<String, HashSet<String>>>,
     // media_uuid: set(gid)
     access_cache: Arc<AwaitCache<MediaUuid, HashSet<String>>>,
-    user_regex: Regex,
-    group_regex: Regex,
+    admin_group: Option<String>,
 }
 
 #[async_trait]
@@ -120,6 +108,8 @@ impl ESInner for AuthCache {
             AuthnBackend::ProxyHeader => Box::new(ProxyAuth::new(config.clone())?),
             AuthnBackend::TomlFile => Box::new(TomlAuthnFile::new(config.clone())?),
             AuthnBackend::X509Cert => Box::new(CertAuthn::new(config.clone())?),
+            AuthnBackend::Oidc => Box::new(OidcAuthn::new(config.clone())?),
+            AuthnBackend::Ldap => Box::new(LdapAuthn::new(config.clone())?),
         };
 
         let authz_provider: Box<dyn AuthzProvider> = match config.authz_backend {
@@ -127,14 +117,15 @@ impl ESInner for AuthCache {
             AuthzBackend::TomlFile => Box::new(TomlAuthzFile::new(config.clone())?),
         };
 
+        let ttl = Duration::from_secs(config.auth_cache_ttl.unwrap_or(AUTH_CACHE_TTL));
+
         Ok(AuthCache {
             registry: registry.clone(),
             authn_provider,
             authz_provider,
-            user_cache: Arc::new(AwaitCache::new()),
-            access_cache: Arc::new(AwaitCache::new()),
-            user_regex: Regex::new(USER_REGEX)?,
-            group_regex: Regex::new(GROUP_REGEX)?,
+            user_cache: Arc::new(AwaitCache::with_ttl("user", ttl)),
+            access_cache: Arc::new(AwaitCache::with_ttl("access", ttl)),
+            admin_group: config.admin_group.clone(),
         })
     }
 
@@ -161,6 +152,7 @@ impl ESInner for AuthCache {
                 AuthMsg::IsGroupMember { resp, uid, gid } => {
                     self.respond(resp, self.is_group_member(uid, gid)).await
                 }
+                AuthMsg::IsAdmin { resp, uid } => self.respond(resp, self.is_admin(uid)).await,
                 AuthMsg::CanAccessMedia {
                     resp,
                     uid,
@@ -185,6 +177,7 @@ impl ESInner for AuthCache {
                     self.respond(resp, self.authenticate_user(uid, password))
                         .await
                 }
+                AuthMsg::Ping { resp } => self.respond(resp, self.ping()).await,
             },
             _ => Err(anyhow::Error::msg("not implemented")),
         }
@@ -207,7 +200,7 @@ impl AuthCache {
 
         let mut groups = self.authz_provider.groups_for_user(uid.clone()).await?;
 
-        groups.retain(|s| self.group_regex.is_match(s));
+        groups.retain(|s| is_valid_gid(s));
 
         Ok(groups)
     }
@@ -303,18 +296,42 @@ impl ESAuthService for AuthCache {
     }
 
     async fn users_in_group(&self, gid: String) -> anyhow::Result<HashSet<String>> {
-        self.authz_provider.users_in_group(gid).await
+        if !is_valid_gid(&gid) {
+            return Err(anyhow::Error::msg("invalid gid"));
+        }
+
+        let mut users = self.authz_provider.users_in_group(gid).await?;
+
+        users.retain(|s| is_valid_uid(s));
+
+        Ok(users)
     }
 
     async fn is_group_member(&self, uid: String, gid: HashSet<String>) -> anyhow::Result<bool> {
         Ok(gid.intersection(&self.groups_for_user(uid).await?).count() > 0)
     }
 
+    // members of the admin group can access and own everything.  that decision is only ever made
+    // from the user cache, never stored in the access cache, so removing someone from the group
+    // takes effect on the same schedule as any other membership change.
+    //
+    // nobody is an admin if the group is unset
+    async fn is_admin(&self, uid: String) -> anyhow::Result<bool> {
+        match self.admin_group.clone() {
+            Some(gid) => self.is_group_member(uid, HashSet::from([gid])).await,
+            None => Ok(false),
+        }
+    }
+
     // CACHE LOOKUP FUNCTION
     //
     // this is the primary access method for the media AwaitCache
     #[instrument(skip_all)]
     async fn can_access_media(&self, uid: String, media_uuid: MediaUuid) -> anyhow::Result<bool> {
+        if self.is_admin(uid.clone()).await? {
+            return Ok(true);
+        }
+
         let access_cache = self.access_cache.clone();
 
         let groups = access_cache
@@ -360,6 +377,10 @@ impl ESAuthService for AuthCache {
             None => return Ok(false),
         };
 
+        if self.is_admin(uid.clone()).await? {
+            return Ok(true);
+        }
+
         let (library_tx, library_rx) = tokio::sync::oneshot::channel();
 
         db_svc_sender
@@ -392,10 +413,17 @@ impl ESAuthService for AuthCache {
     }
 
     async fn is_valid_user(&self, uid: String) -> anyhow::Result<bool> {
-        if !self.user_regex.is_match(&uid) {
+        if !is_valid_uid(&uid) {
             return Err(anyhow::Error::msg("invalid uid"));
         }
 
         self.authn_provider.is_valid_user(uid.clone()).await
     }
+
+    // only the authz provider is checked, since every request needs it, while most of the
+    // authn providers just trust the tls or proxy layers
+    #[instrument(skip(self))]
+    async fn ping(&self) -> anyhow::Result<()> {
+        self.authz_provider.ping().await
+    }
 }
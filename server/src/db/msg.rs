@@ -1,22 +1,48 @@
 use std::collections::HashSet;
 
-use api::{collection::*, comment::*, library::*, media::*, search::SearchFilter};
+use api::{
+    activity::Activity,
+    auth::*,
+    collection::*,
+    comment::*,
+    library::*,
+    media::*,
+    search::SearchFilter,
+    share::*,
+    sort::SortOrder,
+    stats::{DateBucket, DateGranularity, Stats},
+    task::{Task, TaskLibrary},
+};
 use common::db::{MediaByCHash, MediaByPath};
 
 use crate::service::*;
 
-#[derive(Debug)]
+#[derive(Debug, strum::IntoStaticStr)]
 pub enum DbMsg {
+    Ping {
+        resp: EsmResp<()>,
+    },
+
     // auth messages
     MediaAccessGroups {
         resp: EsmResp<HashSet<String>>,
         media_uuid: MediaUuid,
     },
+    GetMediaAccess {
+        resp: EsmResp<Vec<MediaAccess>>,
+        media_uuid: MediaUuid,
+    },
 
     // media messages
+    // boxed since Media is by far the largest payload, and every Esm would otherwise be
+    // sized to fit it
     AddMedia {
         resp: EsmResp<MediaUuid>,
-        media: Media,
+        media: Box<Media>,
+    },
+    AddMediaBatch {
+        resp: EsmResp<Vec<Option<MediaUuid>>>,
+        media: Vec<Media>,
     },
     GetMedia {
         #[allow(clippy::type_complexity)]
@@ -40,24 +66,92 @@ pub enum DbMsg {
         media_uuid: MediaUuid,
         update: MediaUpdate,
     },
+    BatchUpdateMedia {
+        resp: EsmResp<Vec<MediaUuid>>,
+        media_uuids: Vec<MediaUuid>,
+        update: MediaUpdate,
+    },
     ReplaceMediaPath {
         resp: EsmResp<()>,
         media_uuid: MediaUuid,
         path: String,
         hash: String,
         mtime: u64,
+        size: u64,
+    },
+    MoveMedia {
+        resp: EsmResp<()>,
+        media_uuid: MediaUuid,
+        library_uuid: LibraryUuid,
+        path: Option<String>,
     },
     SearchMedia {
-        resp: EsmResp<Vec<MediaUuid>>,
+        resp: EsmResp<(Vec<MediaUuid>, u64)>,
         gid: HashSet<String>,
         filter: SearchFilter,
+        sort: SortOrder,
+        limit: Option<u64>,
+        offset: Option<u64>,
     },
     SimilarMedia {
-        resp: EsmResp<Vec<MediaUuid>>,
+        resp: EsmResp<Vec<(MediaUuid, i64)>>,
         gid: HashSet<String>,
         media_uuid: MediaUuid,
         distance: i64,
     },
+    SimilarPhash {
+        resp: EsmResp<Vec<(MediaUuid, i64)>>,
+        gid: HashSet<String>,
+        phash: String,
+        distance: i64,
+    },
+    RandomMedia {
+        resp: EsmResp<Vec<MediaUuid>>,
+        gid: HashSet<String>,
+        filter: SearchFilter,
+        count: u64,
+    },
+    GetStats {
+        resp: EsmResp<Stats>,
+        gid: HashSet<String>,
+    },
+    GetDateHistogram {
+        resp: EsmResp<Vec<DateBucket>>,
+        gid: HashSet<String>,
+        granularity: DateGranularity,
+    },
+    RecentActivity {
+        resp: EsmResp<Vec<Activity>>,
+        gid: HashSet<String>,
+        limit: u64,
+    },
+
+    // trash messages
+    SoftDeleteMedia {
+        resp: EsmResp<()>,
+        media_uuid: MediaUuid,
+    },
+    RestoreMedia {
+        resp: EsmResp<()>,
+        media_uuid: MediaUuid,
+    },
+    PurgeMedia {
+        resp: EsmResp<()>,
+        media_uuid: MediaUuid,
+    },
+    SearchTrash {
+        resp: EsmResp<Vec<MediaUuid>>,
+        gid: HashSet<String>,
+        filter: SearchFilter,
+    },
+
+    // tag messages
+    RenameTag {
+        resp: EsmResp<u64>,
+        gid: HashSet<String>,
+        from: String,
+        to: String,
+    },
 
     // comment messages
     AddComment {
@@ -78,6 +172,65 @@ pub enum DbMsg {
         text: Option<String>,
     },
 
+    // api key messages
+    AddApiKey {
+        resp: EsmResp<ApiKeyUuid>,
+        uid: String,
+        name: String,
+        key_hash: String,
+    },
+    GetApiKeyUid {
+        resp: EsmResp<Option<String>>,
+        key_hash: String,
+    },
+    GetApiKeys {
+        resp: EsmResp<Vec<ApiKey>>,
+        uid: String,
+    },
+    DeleteApiKey {
+        resp: EsmResp<()>,
+        uid: String,
+        key_uuid: ApiKeyUuid,
+    },
+
+    // share link messages
+    AddShareLink {
+        resp: EsmResp<ShareLinkUuid>,
+        uid: String,
+        target: ShareTarget,
+        token_hash: String,
+        expires_at: u64,
+    },
+    GetShareLink {
+        resp: EsmResp<Option<ShareLink>>,
+        token_hash: String,
+    },
+    GetShareLinks {
+        resp: EsmResp<Vec<ShareLink>>,
+        uid: String,
+    },
+    DeleteShareLink {
+        resp: EsmResp<()>,
+        uid: String,
+        share_uuid: ShareLinkUuid,
+    },
+    GetSharedMedia {
+        resp: EsmResp<Vec<MediaUuid>>,
+        target: ShareTarget,
+    },
+
+    // task messages
+    AddTask {
+        resp: EsmResp<()>,
+        library: TaskLibrary,
+        task: Task,
+    },
+    GetTasks {
+        resp: EsmResp<Vec<Task>>,
+        library: TaskLibrary,
+        limit: u64,
+    },
+
     // collection messages
     AddCollection {
         resp: EsmResp<CollectionUuid>,
@@ -96,6 +249,21 @@ pub enum DbMsg {
         collection_uuid: CollectionUuid,
         update: CollectionUpdate,
     },
+    SetCollectionCover {
+        resp: EsmResp<()>,
+        collection_uuid: CollectionUuid,
+        cover: Option<MediaUuid>,
+    },
+    SetCollectionParent {
+        resp: EsmResp<()>,
+        collection_uuid: CollectionUuid,
+        parent_uuid: Option<CollectionUuid>,
+    },
+    GetCollectionChildren {
+        resp: EsmResp<Vec<CollectionUuid>>,
+        gid: HashSet<String>,
+        collection_uuid: CollectionUuid,
+    },
     AddMediaToCollection {
         resp: EsmResp<()>,
         media_uuid: MediaUuid,
@@ -106,6 +274,11 @@ pub enum DbMsg {
         media_uuid: MediaUuid,
         collection_uuid: CollectionUuid,
     },
+    ReorderCollectionMedia {
+        resp: EsmResp<()>,
+        collection_uuid: CollectionUuid,
+        media_uuids: Vec<MediaUuid>,
+    },
     SearchCollections {
         resp: EsmResp<Vec<CollectionUuid>>,
         gid: HashSet<String>,
@@ -116,6 +289,8 @@ pub enum DbMsg {
         gid: HashSet<String>,
         collection_uuid: CollectionUuid,
         filter: SearchFilter,
+        recursive: bool,
+        ordered: bool,
     },
 
     // library messages
@@ -137,6 +312,11 @@ pub enum DbMsg {
         gid: HashSet<String>,
         filter: String,
     },
+    SearchLibrariesDetailed {
+        resp: EsmResp<Vec<LibrarySummary>>,
+        gid: HashSet<String>,
+        filter: String,
+    },
     SearchMediaInLibrary {
         resp: EsmResp<Vec<MediaUuid>>,
         gid: HashSet<String>,
@@ -144,6 +324,11 @@ pub enum DbMsg {
         hidden: Option<bool>,
         filter: SearchFilter,
     },
+    CountHiddenInLibrary {
+        resp: EsmResp<u64>,
+        gid: HashSet<String>,
+        library_uuid: LibraryUuid,
+    },
 }
 
 impl From<DbMsg> for Esm {
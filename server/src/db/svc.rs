@@ -3,13 +3,20 @@ use std::{marker::PhantomData, sync::Arc};
 use async_cell::sync::AsyncCell;
 use async_trait::async_trait;
 use tokio::{sync::Mutex, task::spawn};
-use tracing::{debug, error, info, instrument};
+use tracing::{debug, info, instrument};
 
 use crate::{
     db::msg::DbMsg,
-    service::{ESInner, ESMRegistry, EntanglementService, Esm, EsmReceiver, ServiceType},
+    service::{
+        ESInner, ESMRegistry, EntanglementService, Esm, EsmReceiver, ServiceType, serve_messages,
+    },
+};
+use api::media::{Media, MediaUuid};
+use common::{
+    config::ESConfig,
+    db::DbBackend,
+    webhook::{WebhookDispatcher, WebhookEvent},
 };
-use common::{config::ESConfig, db::DbBackend};
 
 // database service
 //
@@ -49,25 +56,7 @@ impl<B: DbBackend> EntanglementService for DbService<B> {
         let receiver = Arc::clone(&self.receiver);
         let state = Arc::new(DbRunner::<B>::new(self.config.clone(), registry.clone()).await?);
 
-        let serve = {
-            async move {
-                let mut receiver = receiver.lock().await;
-
-                while let Some(msg) = receiver.recv().await {
-                    let state = Arc::clone(&state);
-                    spawn(async move {
-                        match state.message_handler(msg).await {
-                            Ok(()) => (),
-                            Err(err) => {
-                                error!({service = "db", channel = "esm", error = %err})
-                            }
-                        }
-                    });
-                }
-
-                Err(anyhow::Error::msg("db service esm channel disconnected"))
-            }
-        };
+        let serve = serve_messages("db", receiver, state);
 
         self.handle.set(spawn(serve));
 
@@ -79,6 +68,7 @@ impl<B: DbBackend> EntanglementService for DbService<B> {
 pub struct DbRunner<B: DbBackend> {
     registry: ESMRegistry,
     backend: B,
+    webhooks: WebhookDispatcher,
 }
 
 #[async_trait]
@@ -87,6 +77,7 @@ impl<B: DbBackend> ESInner for DbRunner<B> {
         Ok(DbRunner {
             registry: registry.clone(),
             backend: B::new(config.clone()).await?,
+            webhooks: WebhookDispatcher::new(config.clone()),
         })
     }
 
@@ -97,15 +88,22 @@ impl<B: DbBackend> ESInner for DbRunner<B> {
     async fn message_handler(&self, esm: Esm) -> anyhow::Result<()> {
         match esm {
             Esm::Db(message) => match message {
+                DbMsg::Ping { resp } => self.respond(resp, self.backend.ping()).await,
+
                 // auth messages
                 DbMsg::MediaAccessGroups { resp, media_uuid } => {
                     self.respond(resp, self.backend.media_access_groups(media_uuid))
                         .await
                 }
+                DbMsg::GetMediaAccess { resp, media_uuid } => {
+                    self.respond(resp, self.backend.media_access(media_uuid))
+                        .await
+                }
 
                 // media messages
-                DbMsg::AddMedia { resp, media } => {
-                    self.respond(resp, self.backend.add_media(media)).await
+                DbMsg::AddMedia { resp, media } => self.respond(resp, self.add_media(*media)).await,
+                DbMsg::AddMediaBatch { resp, media } => {
+                    self.respond(resp, self.add_media_batch(media)).await
                 }
                 DbMsg::GetMedia { resp, media_uuid } => {
                     self.respond(resp, self.backend.get_media(media_uuid)).await
@@ -133,23 +131,54 @@ impl<B: DbBackend> ESInner for DbRunner<B> {
                     self.respond(resp, self.backend.update_media(media_uuid, update))
                         .await
                 }
+                DbMsg::BatchUpdateMedia {
+                    resp,
+                    media_uuids,
+                    update,
+                } => {
+                    self.respond(resp, self.backend.batch_update_media(media_uuids, update))
+                        .await
+                }
                 DbMsg::ReplaceMediaPath {
                     resp,
                     media_uuid,
                     path,
                     hash,
                     mtime,
+                    size,
                 } => {
                     self.respond(
                         resp,
                         self.backend
-                            .replace_media_path(media_uuid, path, hash, mtime),
+                            .replace_media_path(media_uuid, path, hash, mtime, size),
                     )
                     .await
                 }
-                DbMsg::SearchMedia { resp, gid, filter } => {
-                    self.respond(resp, self.backend.search_media(gid, filter))
-                        .await
+                DbMsg::MoveMedia {
+                    resp,
+                    media_uuid,
+                    library_uuid,
+                    path,
+                } => {
+                    self.respond(
+                        resp,
+                        self.backend.move_media(media_uuid, library_uuid, path),
+                    )
+                    .await
+                }
+                DbMsg::SearchMedia {
+                    resp,
+                    gid,
+                    filter,
+                    sort,
+                    limit,
+                    offset,
+                } => {
+                    self.respond(
+                        resp,
+                        self.backend.search_media(gid, filter, sort, limit, offset),
+                    )
+                    .await
                 }
                 DbMsg::SimilarMedia {
                     resp,
@@ -160,6 +189,66 @@ impl<B: DbBackend> ESInner for DbRunner<B> {
                     self.respond(resp, self.backend.similar_media(gid, media_uuid, distance))
                         .await
                 }
+                DbMsg::SimilarPhash {
+                    resp,
+                    gid,
+                    phash,
+                    distance,
+                } => {
+                    self.respond(resp, self.backend.similar_phash(gid, phash, distance))
+                        .await
+                }
+                DbMsg::RandomMedia {
+                    resp,
+                    gid,
+                    filter,
+                    count,
+                } => {
+                    self.respond(resp, self.backend.random_media(gid, filter, count))
+                        .await
+                }
+                DbMsg::GetStats { resp, gid } => self.respond(resp, self.backend.stats(gid)).await,
+                DbMsg::GetDateHistogram {
+                    resp,
+                    gid,
+                    granularity,
+                } => {
+                    self.respond(resp, self.backend.media_date_histogram(gid, granularity))
+                        .await
+                }
+                DbMsg::RecentActivity { resp, gid, limit } => {
+                    self.respond(resp, self.backend.recent_activity(gid, limit))
+                        .await
+                }
+
+                // trash messages
+                DbMsg::SoftDeleteMedia { resp, media_uuid } => {
+                    self.respond(resp, self.backend.soft_delete_media(media_uuid))
+                        .await
+                }
+                DbMsg::RestoreMedia { resp, media_uuid } => {
+                    self.respond(resp, self.backend.restore_media(media_uuid))
+                        .await
+                }
+                DbMsg::PurgeMedia { resp, media_uuid } => {
+                    self.respond(resp, self.backend.purge_media(media_uuid))
+                        .await
+                }
+                DbMsg::SearchTrash { resp, gid, filter } => {
+                    self.respond(resp, self.backend.search_trash(gid, filter))
+                        .await
+                }
+
+                // tag messages
+                DbMsg::RenameTag {
+                    resp,
+                    gid,
+                    from,
+                    to,
+                } => {
+                    self.respond(resp, self.backend.rename_tag(gid, from, to))
+                        .await
+                }
 
                 // comment messages
                 DbMsg::AddComment { resp, comment } => {
@@ -182,6 +271,85 @@ impl<B: DbBackend> ESInner for DbRunner<B> {
                         .await
                 }
 
+                // api key messages
+                DbMsg::AddApiKey {
+                    resp,
+                    uid,
+                    name,
+                    key_hash,
+                } => {
+                    self.respond(resp, self.backend.add_api_key(uid, name, key_hash))
+                        .await
+                }
+                DbMsg::GetApiKeyUid { resp, key_hash } => {
+                    self.respond(resp, self.backend.get_api_key_uid(key_hash))
+                        .await
+                }
+                DbMsg::GetApiKeys { resp, uid } => {
+                    self.respond(resp, self.backend.get_api_keys(uid)).await
+                }
+                DbMsg::DeleteApiKey {
+                    resp,
+                    uid,
+                    key_uuid,
+                } => {
+                    self.respond(resp, self.backend.delete_api_key(uid, key_uuid))
+                        .await
+                }
+
+                // share link messages
+                DbMsg::AddShareLink {
+                    resp,
+                    uid,
+                    target,
+                    token_hash,
+                    expires_at,
+                } => {
+                    self.respond(
+                        resp,
+                        self.backend
+                            .add_share_link(uid, target, token_hash, expires_at),
+                    )
+                    .await
+                }
+                DbMsg::GetShareLink { resp, token_hash } => {
+                    self.respond(resp, self.backend.get_share_link(token_hash))
+                        .await
+                }
+                DbMsg::GetShareLinks { resp, uid } => {
+                    self.respond(resp, self.backend.get_share_links(uid)).await
+                }
+                DbMsg::DeleteShareLink {
+                    resp,
+                    uid,
+                    share_uuid,
+                } => {
+                    self.respond(resp, self.backend.delete_share_link(uid, share_uuid))
+                        .await
+                }
+                DbMsg::GetSharedMedia { resp, target } => {
+                    self.respond(resp, self.backend.get_shared_media(target))
+                        .await
+                }
+
+                // task messages
+                DbMsg::AddTask {
+                    resp,
+                    library,
+                    task,
+                } => {
+                    self.respond(resp, self.backend.add_task(library, task))
+                        .await
+                }
+                DbMsg::GetTasks {
+                    resp,
+                    library,
+                    limit,
+                } => {
+                    self.respond(resp, self.backend.get_tasks(library, limit))
+                        .await
+                }
+
                 // collection messages
                 DbMsg::AddCollection { resp, collection } => {
                     self.respond(resp, self.backend.add_collection(collection))
@@ -212,6 +380,40 @@ impl<B: DbBackend> ESInner for DbRunner<B> {
                     )
                     .await
                 }
+                DbMsg::SetCollectionCover {
+                    resp,
+                    collection_uuid,
+                    cover,
+                } => {
+                    self.respond(
+                        resp,
+                        self.backend.set_collection_cover(collection_uuid, cover),
+                    )
+                    .await
+                }
+                DbMsg::SetCollectionParent {
+                    resp,
+                    collection_uuid,
+                    parent_uuid,
+                } => {
+                    self.respond(
+                        resp,
+                        self.backend
+                            .set_collection_parent(collection_uuid, parent_uuid),
+                    )
+                    .await
+                }
+                DbMsg::GetCollectionChildren {
+                    resp,
+                    gid,
+                    collection_uuid,
+                } => {
+                    self.respond(
+                        resp,
+                        self.backend.get_collection_children(gid, collection_uuid),
+                    )
+                    .await
+                }
                 DbMsg::AddMediaToCollection {
                     resp,
                     media_uuid,
@@ -236,6 +438,18 @@ impl<B: DbBackend> ESInner for DbRunner<B> {
                     )
                     .await
                 }
+                DbMsg::ReorderCollectionMedia {
+                    resp,
+                    collection_uuid,
+                    media_uuids,
+                } => {
+                    self.respond(
+                        resp,
+                        self.backend
+                            .reorder_collection_media(collection_uuid, media_uuids),
+                    )
+                    .await
+                }
                 DbMsg::SearchCollections { resp, gid, filter } => {
                     self.respond(resp, self.backend.search_collections(gid, filter))
                         .await
@@ -245,11 +459,18 @@ impl<B: DbBackend> ESInner for DbRunner<B> {
                     gid,
                     collection_uuid,
                     filter,
+                    recursive,
+                    ordered,
                 } => {
                     self.respond(
                         resp,
-                        self.backend
-                            .search_media_in_collection(gid, collection_uuid, filter),
+                        self.backend.search_media_in_collection(
+                            gid,
+                            collection_uuid,
+                            filter,
+                            recursive,
+                            ordered,
+                        ),
                     )
                     .await
                 }
@@ -274,6 +495,10 @@ impl<B: DbBackend> ESInner for DbRunner<B> {
                     self.respond(resp, self.backend.search_libraries(gid, filter))
                         .await
                 }
+                DbMsg::SearchLibrariesDetailed { resp, gid, filter } => {
+                    self.respond(resp, self.backend.search_libraries_detailed(gid, filter))
+                        .await
+                }
                 DbMsg::SearchMediaInLibrary {
                     resp,
                     gid,
@@ -288,8 +513,58 @@ impl<B: DbBackend> ESInner for DbRunner<B> {
                     )
                     .await
                 }
+                DbMsg::CountHiddenInLibrary {
+                    resp,
+                    gid,
+                    library_uuid,
+                } => {
+                    self.respond(
+                        resp,
+                        self.backend.count_hidden_in_library(gid, library_uuid),
+                    )
+                    .await
+                }
             },
             _ => Err(anyhow::Error::msg("not implemented")),
         }
     }
 }
+
+impl<B: DbBackend> DbRunner<B> {
+    // every new media passes through here, whether from a scan or an upload
+    async fn add_media(&self, media: Media) -> anyhow::Result<MediaUuid> {
+        let library_uuid = media.library_uuid;
+        let path = media.path.clone();
+
+        let media_uuid = self.backend.add_media(media).await?;
+
+        self.webhooks.dispatch(WebhookEvent::MediaAdded {
+            library_uuid,
+            media_uuid,
+            path,
+        });
+
+        Ok(media_uuid)
+    }
+
+    async fn add_media_batch(&self, media: Vec<Media>) -> anyhow::Result<Vec<Option<MediaUuid>>> {
+        let added = media
+            .iter()
+            .map(|media| (media.library_uuid, media.path.clone()))
+            .collect::<Vec<_>>();
+
+        let media_uuids = self.backend.add_media_batch(media).await?;
+
+        for ((library_uuid, path), media_uuid) in added.into_iter().zip(media_uuids.iter()) {
+            if let Some(media_uuid) = media_uuid {
+                self.webhooks.dispatch(WebhookEvent::MediaAdded {
+                    library_uuid,
+                    media_uuid: *media_uuid,
+                    path,
+                });
+            }
+        }
+
+        Ok(media_uuids)
+    }
+}
@@ -1,7 +1,15 @@
-use std::{path::PathBuf, sync::Arc};
+use std::path::{Path, PathBuf};
 
-use api::{LINK_PATH, THUMBNAIL_PATH, media::MediaUuid};
-use common::config::ESConfig;
+use anyhow::Result;
+
+use api::{
+    THUMBNAIL_PATH,
+    media::{MediaMetadata, MediaUuid, ThumbnailSize},
+};
+use common::{
+    media::{create_thumbnail, raw::RawStrategy},
+    storage::{StorageBackend, thumbnail_name},
+};
 
 // legacy file service
 //
@@ -9,19 +17,33 @@ use common::config::ESConfig;
 // but it was moved to the task module instead.
 //
 // since it is likely that there will be more server filesystem
-// specific tasks, we leave the module in its own folder
-pub fn media_link_path(config: Arc<ESConfig>, media_uuid: MediaUuid) -> PathBuf {
-    config
-        .fs
-        .media_srvdir
-        .join(LINK_PATH)
-        .join(media_uuid.to_string())
-}
+// specific tasks, we leave the module in its own folder.  the
+// srv directory paths live with the filesystem storage backend,
+// see common/storage/fs.rs
+
+// thumbnails are created in the scratch directory and then handed to the storage backend,
+// which is what keeps anyone from being served a partial thumbnail
+pub async fn store_thumbnail(
+    storage: &dyn StorageBackend,
+    original: &PathBuf,
+    scratch_dir: &Path,
+    metadata: &MediaMetadata,
+    raw_strategy: &RawStrategy,
+    media_uuid: MediaUuid,
+    size: ThumbnailSize,
+) -> Result<()> {
+    let name = thumbnail_name(media_uuid, size);
+    let thumbnail = scratch_dir.join(&name);
+
+    create_thumbnail(
+        original,
+        &thumbnail,
+        scratch_dir,
+        metadata,
+        raw_strategy,
+        size,
+    )
+    .await?;
 
-pub fn media_thumbnail_path(config: Arc<ESConfig>, media_uuid: MediaUuid) -> PathBuf {
-    config
-        .fs
-        .media_srvdir
-        .join(THUMBNAIL_PATH)
-        .join(media_uuid.to_string())
+    storage.put_file(THUMBNAIL_PATH, &name, &thumbnail).await
 }
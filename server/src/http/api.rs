@@ -6,16 +6,27 @@ use axum::{
     http::StatusCode,
     response::{IntoResponse, Response},
 };
-use tokio::sync::Mutex;
 use tracing::instrument;
 
 use crate::{
     auth::{check::AuthCheck, msg::AuthMsg},
     db::msg::DbMsg,
-    http::{AppError, auth::CurrentUser, svc::HttpEndpoint},
+    http::{
+        AppError,
+        auth::{CurrentUser, random_token},
+        search::{search_request, search_response},
+        svc::HttpEndpoint,
+    },
     task::msg::TaskMsg,
 };
-use api::{auth::*, collection::*, comment::*, library::*, media::*, search::*, task::*};
+use api::{
+    FOLDING_SEPARATOR, activity::*, auth::*, collection::*, comment::*, library::*, media::*,
+    search::*, stats::*, task::*, validate_tags,
+};
+use common::{
+    auth::apikey::{API_KEY_PREFIX, hash_api_key},
+    is_valid_gid,
+};
 
 // http api endpoints
 //
@@ -41,6 +52,10 @@ pub(super) async fn get_users_in_group(
     Extension(_current_user): Extension<CurrentUser>,
     Json(message): Json<GetUsersInGroupReq>,
 ) -> Result<Response, AppError> {
+    if !is_valid_gid(&message.gid) {
+        return Ok((StatusCode::BAD_REQUEST, "invalid gid").into_response());
+    }
+
     let (tx, rx) = tokio::sync::oneshot::channel();
 
     state
@@ -59,7 +74,95 @@ pub(super) async fn get_users_in_group(
     Ok(Json(GetUsersInGroupResp { uids: result }).into_response())
 }
 
+// api keys always belong to the current user, so there is no further policy to check
+#[instrument(skip_all)]
+pub(super) async fn create_api_key(
+    State(state): State<Arc<HttpEndpoint>>,
+    Extension(current_user): Extension<CurrentUser>,
+    Json(message): Json<CreateApiKeyReq>,
+) -> Result<Response, AppError> {
+    let key = format!("{API_KEY_PREFIX}{}", random_token());
+
+    let (tx, rx) = tokio::sync::oneshot::channel();
+
+    state
+        .db_svc_sender
+        .send(
+            DbMsg::AddApiKey {
+                resp: tx,
+                uid: current_user.uid,
+                name: message.name,
+                key_hash: hash_api_key(&key),
+            }
+            .into(),
+        )
+        .await?;
+
+    let key_uuid = rx.await??;
+
+    Ok(Json(CreateApiKeyResp { key_uuid, key }).into_response())
+}
+
+#[instrument(skip_all)]
+pub(super) async fn list_api_keys(
+    State(state): State<Arc<HttpEndpoint>>,
+    Extension(current_user): Extension<CurrentUser>,
+    Json(_message): Json<ListApiKeysReq>,
+) -> Result<Response, AppError> {
+    let (tx, rx) = tokio::sync::oneshot::channel();
+
+    state
+        .db_svc_sender
+        .send(
+            DbMsg::GetApiKeys {
+                resp: tx,
+                uid: current_user.uid,
+            }
+            .into(),
+        )
+        .await?;
+
+    let keys = rx.await??;
+
+    Ok(Json(ListApiKeysResp { keys }).into_response())
+}
+
+#[instrument(skip_all)]
+pub(super) async fn revoke_api_key(
+    State(state): State<Arc<HttpEndpoint>>,
+    Extension(current_user): Extension<CurrentUser>,
+    Json(message): Json<RevokeApiKeyReq>,
+) -> Result<Response, AppError> {
+    let (tx, rx) = tokio::sync::oneshot::channel();
+
+    state
+        .db_svc_sender
+        .send(
+            DbMsg::DeleteApiKey {
+                resp: tx,
+                uid: current_user.uid,
+                key_uuid: message.key_uuid,
+            }
+            .into(),
+        )
+        .await?;
+
+    rx.await??;
+
+    Ok(Json(RevokeApiKeyResp {}).into_response())
+}
+
 // media handlers
+#[utoipa::path(
+    post,
+    path = "/GetMedia",
+    tag = "media",
+    request_body = GetMediaReq,
+    responses(
+        (status = 200, body = GetMediaResp),
+        (status = 401, description = "not authorized")
+    )
+)]
 #[instrument(skip_all)]
 pub(super) async fn get_media(
     State(state): State<Arc<HttpEndpoint>>,
@@ -98,6 +201,61 @@ pub(super) async fn get_media(
     .into_response())
 }
 
+// which groups can see the media, and whether that is through its library or one of its
+// collections.  this is the same lookup that the access checks use, so it is only handed
+// to the owner of the media.
+#[utoipa::path(
+    post,
+    path = "/GetMediaAccess",
+    tag = "media",
+    request_body = GetMediaAccessReq,
+    responses(
+        (status = 200, body = GetMediaAccessResp),
+        (status = 401, description = "not authorized")
+    )
+)]
+#[instrument(skip_all)]
+pub(super) async fn get_media_access(
+    State(state): State<Arc<HttpEndpoint>>,
+    Extension(current_user): Extension<CurrentUser>,
+    Json(message): Json<GetMediaAccessReq>,
+) -> Result<Response, AppError> {
+    if !state
+        .owns_media(&current_user.uid, &message.media_uuid)
+        .await?
+    {
+        return Ok(StatusCode::UNAUTHORIZED.into_response());
+    }
+
+    let (tx, rx) = tokio::sync::oneshot::channel();
+
+    state
+        .db_svc_sender
+        .send(
+            DbMsg::GetMediaAccess {
+                resp: tx,
+                media_uuid: message.media_uuid,
+            }
+            .into(),
+        )
+        .await?;
+
+    let access = rx.await??;
+
+    Ok(Json(GetMediaAccessResp { access }).into_response())
+}
+
+#[utoipa::path(
+    post,
+    path = "/UpdateMedia",
+    tag = "media",
+    request_body = UpdateMediaReq,
+    responses(
+        (status = 200, body = UpdateMediaResp),
+        (status = 400, description = "invalid tags"),
+        (status = 401, description = "not authorized")
+    )
+)]
 #[instrument(skip_all)]
 pub(super) async fn update_media(
     State(state): State<Arc<HttpEndpoint>>,
@@ -111,6 +269,12 @@ pub(super) async fn update_media(
         return Ok(StatusCode::UNAUTHORIZED.into_response());
     }
 
+    if let Err(err) = message.update.validate_tags() {
+        return Ok((StatusCode::BAD_REQUEST, err.to_string()).into_response());
+    }
+
+    let changes_hidden = message.update.hidden.is_some();
+
     let (tx, rx) = tokio::sync::oneshot::channel();
 
     state
@@ -127,9 +291,86 @@ pub(super) async fn update_media(
 
     rx.await??;
 
+    // collections only grant access to media that aren't hidden
+    if changes_hidden {
+        state
+            .clear_access_cache(Vec::from(&[message.media_uuid]))
+            .await?;
+    }
+
     Ok(Json(UpdateMediaResp {}).into_response())
 }
 
+#[utoipa::path(
+    post,
+    path = "/BatchUpdateMedia",
+    tag = "media",
+    request_body = BatchUpdateMediaReq,
+    responses(
+        (status = 200, body = BatchUpdateMediaResp),
+        (status = 400, description = "invalid tags"),
+        (status = 401, description = "not authorized")
+    )
+)]
+#[instrument(skip_all)]
+pub(super) async fn batch_update_media(
+    State(state): State<Arc<HttpEndpoint>>,
+    Extension(current_user): Extension<CurrentUser>,
+    Json(message): Json<BatchUpdateMediaReq>,
+) -> Result<Response, AppError> {
+    // a partial update would be hard to explain to the user, so one bad media rejects
+    // the whole batch
+    for media_uuid in message.media_uuids.iter() {
+        if !state.owns_media(&current_user.uid, media_uuid).await? {
+            return Ok(StatusCode::UNAUTHORIZED.into_response());
+        }
+    }
+
+    if let Err(err) = message.update.validate_tags() {
+        return Ok((StatusCode::BAD_REQUEST, err.to_string()).into_response());
+    }
+
+    let changes_hidden = message.update.hidden.is_some();
+
+    let (tx, rx) = tokio::sync::oneshot::channel();
+
+    state
+        .db_svc_sender
+        .send(
+            DbMsg::BatchUpdateMedia {
+                resp: tx,
+                media_uuids: message.media_uuids.clone(),
+                update: message.update,
+            }
+            .into(),
+        )
+        .await?;
+
+    let updated = rx.await??;
+
+    // see update_media(), except that the whole batch goes in one message
+    if changes_hidden && !updated.is_empty() {
+        state.clear_access_cache(updated.clone()).await?;
+    }
+
+    let failed = message
+        .media_uuids
+        .into_iter()
+        .filter(|media_uuid| !updated.contains(media_uuid))
+        .collect();
+
+    Ok(Json(BatchUpdateMediaResp { updated, failed }).into_response())
+}
+
+#[utoipa::path(
+    post,
+    path = "/SearchMedia",
+    tag = "media",
+    request_body = SearchMediaReq,
+    responses(
+        (status = 200, body = SearchMediaResp)
+    )
+)]
 #[instrument(skip_all)]
 pub(super) async fn search_media(
     State(state): State<Arc<HttpEndpoint>>,
@@ -149,16 +390,28 @@ pub(super) async fn search_media(
                 resp: tx,
                 gid,
                 filter: message.filter,
+                sort: message.sort,
+                limit: message.limit,
+                offset: message.offset,
             }
             .into(),
         )
         .await?;
 
-    let result = rx.await??;
+    let (media, total) = rx.await??;
 
-    Ok(Json(SearchMediaResp { media: result }).into_response())
+    Ok(Json(SearchMediaResp { media, total }).into_response())
 }
 
+#[utoipa::path(
+    post,
+    path = "/SimilarMedia",
+    tag = "media",
+    request_body = SimilarMediaReq,
+    responses(
+        (status = 200, body = SimilarMediaResp)
+    )
+)]
 #[instrument(skip_all)]
 pub(super) async fn similar_media(
     State(state): State<Arc<HttpEndpoint>>,
@@ -189,6 +442,349 @@ pub(super) async fn similar_media(
     Ok(Json(SimilarMediaResp { media: result }).into_response())
 }
 
+#[utoipa::path(
+    post,
+    path = "/GetRandomMedia",
+    tag = "media",
+    request_body = GetRandomMediaReq,
+    responses(
+        (status = 200, body = GetRandomMediaResp)
+    )
+)]
+#[instrument(skip_all)]
+pub(super) async fn get_random_media(
+    State(state): State<Arc<HttpEndpoint>>,
+    Extension(current_user): Extension<CurrentUser>,
+    Json(message): Json<GetRandomMediaReq>,
+) -> Result<Response, AppError> {
+    // auth handled as part of the db search
+
+    let gid = state.groups_for_user(&current_user.uid).await?;
+
+    let (tx, rx) = tokio::sync::oneshot::channel();
+
+    state
+        .db_svc_sender
+        .send(
+            DbMsg::RandomMedia {
+                resp: tx,
+                gid,
+                filter: message.filter,
+                count: message.count.min(RANDOM_MEDIA_MAX),
+            }
+            .into(),
+        )
+        .await?;
+
+    let result = rx.await??;
+
+    Ok(Json(GetRandomMediaResp { media: result }).into_response())
+}
+
+#[instrument(skip_all)]
+pub(super) async fn get_stats(
+    State(state): State<Arc<HttpEndpoint>>,
+    Extension(current_user): Extension<CurrentUser>,
+    Json(_message): Json<GetStatsReq>,
+) -> Result<Response, AppError> {
+    // auth handled as part of the db search
+
+    let gid = state.groups_for_user(&current_user.uid).await?;
+
+    let (tx, rx) = tokio::sync::oneshot::channel();
+
+    state
+        .db_svc_sender
+        .send(DbMsg::GetStats { resp: tx, gid }.into())
+        .await?;
+
+    let stats = rx.await??;
+
+    Ok(Json(GetStatsResp { stats }).into_response())
+}
+
+#[instrument(skip_all)]
+pub(super) async fn get_date_histogram(
+    State(state): State<Arc<HttpEndpoint>>,
+    Extension(current_user): Extension<CurrentUser>,
+    Json(message): Json<GetDateHistogramReq>,
+) -> Result<Response, AppError> {
+    // auth handled as part of the db search
+
+    let gid = state.groups_for_user(&current_user.uid).await?;
+
+    let (tx, rx) = tokio::sync::oneshot::channel();
+
+    state
+        .db_svc_sender
+        .send(
+            DbMsg::GetDateHistogram {
+                resp: tx,
+                gid,
+                granularity: message.granularity,
+            }
+            .into(),
+        )
+        .await?;
+
+    let buckets = rx.await??;
+
+    Ok(Json(GetDateHistogramResp { buckets }).into_response())
+}
+
+#[instrument(skip_all)]
+pub(super) async fn get_recent_activity(
+    State(state): State<Arc<HttpEndpoint>>,
+    Extension(current_user): Extension<CurrentUser>,
+    Json(message): Json<GetRecentActivityReq>,
+) -> Result<Response, AppError> {
+    // auth handled as part of the db search
+
+    let gid = state.groups_for_user(&current_user.uid).await?;
+
+    let (tx, rx) = tokio::sync::oneshot::channel();
+
+    state
+        .db_svc_sender
+        .send(
+            DbMsg::RecentActivity {
+                resp: tx,
+                gid,
+                limit: message.limit.min(RECENT_ACTIVITY_MAX),
+            }
+            .into(),
+        )
+        .await?;
+
+    let activity = rx.await??;
+
+    Ok(Json(GetRecentActivityResp { activity }).into_response())
+}
+
+#[utoipa::path(
+    post,
+    path = "/DeleteMedia",
+    tag = "media",
+    request_body = DeleteMediaReq,
+    responses(
+        (status = 200, body = DeleteMediaResp),
+        (status = 401, description = "not authorized")
+    )
+)]
+#[instrument(skip_all)]
+pub(super) async fn delete_media(
+    State(state): State<Arc<HttpEndpoint>>,
+    Extension(current_user): Extension<CurrentUser>,
+    Json(message): Json<DeleteMediaReq>,
+) -> Result<Response, AppError> {
+    if !state
+        .owns_media(&current_user.uid, &message.media_uuid)
+        .await?
+    {
+        return Ok(StatusCode::UNAUTHORIZED.into_response());
+    }
+
+    let (tx, rx) = tokio::sync::oneshot::channel();
+
+    state
+        .db_svc_sender
+        .send(
+            DbMsg::SoftDeleteMedia {
+                resp: tx,
+                media_uuid: message.media_uuid,
+            }
+            .into(),
+        )
+        .await?;
+
+    rx.await??;
+
+    // collection access to trashed media is revoked
+    state
+        .clear_access_cache(Vec::from(&[message.media_uuid]))
+        .await?;
+
+    Ok(Json(DeleteMediaResp {}).into_response())
+}
+
+#[utoipa::path(
+    post,
+    path = "/RestoreMedia",
+    tag = "media",
+    request_body = RestoreMediaReq,
+    responses(
+        (status = 200, body = RestoreMediaResp),
+        (status = 401, description = "not authorized")
+    )
+)]
+#[instrument(skip_all)]
+pub(super) async fn restore_media(
+    State(state): State<Arc<HttpEndpoint>>,
+    Extension(current_user): Extension<CurrentUser>,
+    Json(message): Json<RestoreMediaReq>,
+) -> Result<Response, AppError> {
+    if !state
+        .owns_media(&current_user.uid, &message.media_uuid)
+        .await?
+    {
+        return Ok(StatusCode::UNAUTHORIZED.into_response());
+    }
+
+    let (tx, rx) = tokio::sync::oneshot::channel();
+
+    state
+        .db_svc_sender
+        .send(
+            DbMsg::RestoreMedia {
+                resp: tx,
+                media_uuid: message.media_uuid,
+            }
+            .into(),
+        )
+        .await?;
+
+    rx.await??;
+
+    state
+        .clear_access_cache(Vec::from(&[message.media_uuid]))
+        .await?;
+
+    Ok(Json(RestoreMediaResp {}).into_response())
+}
+
+#[utoipa::path(
+    post,
+    path = "/PurgeMedia",
+    tag = "media",
+    request_body = PurgeMediaReq,
+    responses(
+        (status = 200, body = PurgeMediaResp),
+        (status = 401, description = "not authorized")
+    )
+)]
+#[instrument(skip_all)]
+pub(super) async fn purge_media(
+    State(state): State<Arc<HttpEndpoint>>,
+    Extension(current_user): Extension<CurrentUser>,
+    Json(message): Json<PurgeMediaReq>,
+) -> Result<Response, AppError> {
+    // irreversible or library-wide operations are limited to the admin group
+    if !state.is_admin(&current_user.uid).await? {
+        return Ok(StatusCode::UNAUTHORIZED.into_response());
+    }
+
+    let (tx, rx) = tokio::sync::oneshot::channel();
+
+    state
+        .db_svc_sender
+        .send(
+            DbMsg::PurgeMedia {
+                resp: tx,
+                media_uuid: message.media_uuid,
+            }
+            .into(),
+        )
+        .await?;
+
+    rx.await??;
+
+    state
+        .clear_access_cache(Vec::from(&[message.media_uuid]))
+        .await?;
+
+    Ok(Json(PurgeMediaResp {}).into_response())
+}
+
+#[utoipa::path(
+    post,
+    path = "/SearchTrash",
+    tag = "media",
+    request_body = SearchTrashReq,
+    responses(
+        (status = 200, body = SearchTrashResp)
+    )
+)]
+#[instrument(skip_all)]
+pub(super) async fn search_trash(
+    State(state): State<Arc<HttpEndpoint>>,
+    Extension(current_user): Extension<CurrentUser>,
+    Json(message): Json<SearchTrashReq>,
+) -> Result<Response, AppError> {
+    // auth handled as part of the db search
+
+    let gid = state.groups_for_user(&current_user.uid).await?;
+
+    let (tx, rx) = tokio::sync::oneshot::channel();
+
+    state
+        .db_svc_sender
+        .send(
+            DbMsg::SearchTrash {
+                resp: tx,
+                gid,
+                filter: message.filter,
+            }
+            .into(),
+        )
+        .await?;
+
+    let media = rx.await??;
+
+    Ok(Json(SearchTrashResp { media }).into_response())
+}
+
+#[utoipa::path(
+    post,
+    path = "/RenameTag",
+    tag = "media",
+    request_body = RenameTagReq,
+    responses(
+        (status = 200, body = RenameTagResp),
+        (status = 400, description = "invalid tag name"),
+        (status = 401, description = "not authorized")
+    )
+)]
+#[instrument(skip_all)]
+pub(super) async fn rename_tag(
+    State(state): State<Arc<HttpEndpoint>>,
+    Extension(current_user): Extension<CurrentUser>,
+    Json(message): Json<RenameTagReq>,
+) -> Result<Response, AppError> {
+    // see purge_media
+    if !state.is_admin(&current_user.uid).await? {
+        return Ok(StatusCode::UNAUTHORIZED.into_response());
+    }
+
+    let from = message.from.trim().to_owned();
+    let to = message.to.trim().to_owned();
+
+    if from.is_empty() || to.is_empty() || to.contains(FOLDING_SEPARATOR) {
+        return Ok((StatusCode::BAD_REQUEST, "invalid tag name").into_response());
+    }
+
+    // the rename is limited to what the admin's own groups own
+    let gid = state.groups_for_user(&current_user.uid).await?;
+
+    let (tx, rx) = tokio::sync::oneshot::channel();
+
+    state
+        .db_svc_sender
+        .send(
+            DbMsg::RenameTag {
+                resp: tx,
+                gid,
+                from,
+                to,
+            }
+            .into(),
+        )
+        .await?;
+
+    let count = rx.await??;
+
+    Ok(Json(RenameTagResp { count }).into_response())
+}
+
 #[instrument(skip_all)]
 pub(super) async fn add_comment(
     State(state): State<Arc<HttpEndpoint>>,
@@ -214,6 +810,7 @@ pub(super) async fn add_comment(
                     uid: current_user.uid,
                     date: message.comment.date,
                     text: message.comment.text,
+                    edited: None,
                 },
             }
             .into(),
@@ -324,12 +921,28 @@ pub(super) async fn update_comment(
     Ok(Json(UpdateCommentResp {}).into_response())
 }
 
+#[utoipa::path(
+    post,
+    path = "/AddCollection",
+    tag = "collection",
+    request_body = AddCollectionReq,
+    responses(
+        (status = 200, body = AddCollectionResp),
+        (status = 400, description = "invalid gid or tags"),
+        (status = 401, description = "not authorized")
+    )
+)]
 #[instrument(skip_all)]
 pub(super) async fn add_collection(
     State(state): State<Arc<HttpEndpoint>>,
     Extension(current_user): Extension<CurrentUser>,
     Json(message): Json<AddCollectionReq>,
 ) -> Result<Response, AppError> {
+    // checked first so that the group lookup never sees a malformed gid
+    if !is_valid_gid(&message.collection.gid) {
+        return Ok((StatusCode::BAD_REQUEST, "invalid gid").into_response());
+    }
+
     // anyone may create an collection, but they must be in the group of the collection they create
     if !state
         .is_group_member(
@@ -341,6 +954,24 @@ pub(super) async fn add_collection(
         return Err(anyhow::Error::msg("User must be a member of collection group").into());
     }
 
+    // nesting under someone else's collection would add to their tree
+    let owns_parent = match message.collection.parent_uuid {
+        Some(parent_uuid) => {
+            state
+                .owns_collection(&current_user.uid, &parent_uuid)
+                .await?
+        }
+        None => true,
+    };
+
+    if !owns_parent {
+        return Ok(StatusCode::UNAUTHORIZED.into_response());
+    }
+
+    if let Err(err) = validate_tags(&message.collection.tags) {
+        return Ok((StatusCode::BAD_REQUEST, err.to_string()).into_response());
+    }
+
     let (tx, rx) = tokio::sync::oneshot::channel();
 
     state
@@ -355,6 +986,7 @@ pub(super) async fn add_collection(
                     note: message.collection.note,
                     tags: message.collection.tags,
                     cover: message.collection.cover,
+                    parent_uuid: message.collection.parent_uuid,
                 },
             }
             .into(),
@@ -369,6 +1001,16 @@ pub(super) async fn add_collection(
     .into_response())
 }
 
+#[utoipa::path(
+    post,
+    path = "/GetCollection",
+    tag = "collection",
+    request_body = GetCollectionReq,
+    responses(
+        (status = 200, body = GetCollectionResp),
+        (status = 401, description = "not authorized")
+    )
+)]
 #[instrument(skip_all)]
 pub(super) async fn get_collection(
     State(state): State<Arc<HttpEndpoint>>,
@@ -402,6 +1044,16 @@ pub(super) async fn get_collection(
     Ok(Json(GetCollectionResp { collection: result }).into_response())
 }
 
+#[utoipa::path(
+    post,
+    path = "/DeleteCollection",
+    tag = "collection",
+    request_body = DeleteCollectionReq,
+    responses(
+        (status = 200, body = DeleteCollectionResp),
+        (status = 401, description = "not authorized")
+    )
+)]
 #[instrument(skip_all)]
 pub(super) async fn delete_collection(
     State(state): State<Arc<HttpEndpoint>>,
@@ -428,43 +1080,290 @@ pub(super) async fn delete_collection(
         )
         .await?;
 
-    rx.await??;
+    rx.await??;
+
+    Ok(Json(DeleteCollectionResp {}).into_response())
+}
+
+#[utoipa::path(
+    post,
+    path = "/UpdateCollection",
+    tag = "collection",
+    request_body = UpdateCollectionReq,
+    responses(
+        (status = 200, body = UpdateCollectionResp),
+        (status = 400, description = "invalid tags"),
+        (status = 401, description = "not authorized")
+    )
+)]
+#[instrument(skip_all)]
+pub(super) async fn update_collection(
+    State(state): State<Arc<HttpEndpoint>>,
+    Extension(current_user): Extension<CurrentUser>,
+    Json(message): Json<UpdateCollectionReq>,
+) -> Result<Response, AppError> {
+    if !state
+        .owns_collection(&current_user.uid, &message.collection_uuid)
+        .await?
+    {
+        return Ok(StatusCode::UNAUTHORIZED.into_response());
+    }
+
+    if let Some(tags) = &message.update.tags
+        && let Err(err) = validate_tags(tags)
+    {
+        return Ok((StatusCode::BAD_REQUEST, err.to_string()).into_response());
+    }
+
+    let (tx, rx) = tokio::sync::oneshot::channel();
+
+    state
+        .db_svc_sender
+        .send(
+            DbMsg::UpdateCollection {
+                resp: tx,
+                collection_uuid: message.collection_uuid,
+                update: message.update,
+            }
+            .into(),
+        )
+        .await?;
+
+    rx.await??;
+
+    Ok(Json(UpdateCollectionResp {}).into_response())
+}
+
+#[utoipa::path(
+    post,
+    path = "/SetCollectionCover",
+    tag = "collection",
+    request_body = SetCollectionCoverReq,
+    responses(
+        (status = 200, body = SetCollectionCoverResp),
+        (status = 401, description = "not authorized")
+    )
+)]
+#[instrument(skip_all)]
+pub(super) async fn set_collection_cover(
+    State(state): State<Arc<HttpEndpoint>>,
+    Extension(current_user): Extension<CurrentUser>,
+    Json(message): Json<SetCollectionCoverReq>,
+) -> Result<Response, AppError> {
+    if !state
+        .owns_collection(&current_user.uid, &message.collection_uuid)
+        .await?
+    {
+        return Ok(StatusCode::UNAUTHORIZED.into_response());
+    }
+
+    // a cover from outside the collection would leak media to its viewers
+    if let Some(media_uuid) = message.media_uuid {
+        let (tx, rx) = tokio::sync::oneshot::channel();
+
+        state
+            .db_svc_sender
+            .send(
+                DbMsg::GetMedia {
+                    resp: tx,
+                    media_uuid,
+                }
+                .into(),
+            )
+            .await?;
+
+        let (_, collections, _) = rx
+            .await??
+            .ok_or_else(|| anyhow::Error::msg("unknown media_uuid"))?;
+
+        if !collections.contains(&message.collection_uuid) {
+            return Err(anyhow::Error::msg("cover must be in the collection").into());
+        }
+    }
+
+    let (tx, rx) = tokio::sync::oneshot::channel();
+
+    state
+        .db_svc_sender
+        .send(
+            DbMsg::SetCollectionCover {
+                resp: tx,
+                collection_uuid: message.collection_uuid,
+                cover: message.media_uuid,
+            }
+            .into(),
+        )
+        .await?;
+
+    rx.await??;
+
+    Ok(Json(SetCollectionCoverResp {}).into_response())
+}
+
+#[utoipa::path(
+    post,
+    path = "/SetCollectionParent",
+    tag = "collection",
+    request_body = SetCollectionParentReq,
+    responses(
+        (status = 200, body = SetCollectionParentResp),
+        (status = 400, description = "collection cannot be its own ancestor"),
+        (status = 401, description = "not authorized")
+    )
+)]
+#[instrument(skip_all)]
+pub(super) async fn set_collection_parent(
+    State(state): State<Arc<HttpEndpoint>>,
+    Extension(current_user): Extension<CurrentUser>,
+    Json(message): Json<SetCollectionParentReq>,
+) -> Result<Response, AppError> {
+    if !state
+        .owns_collection(&current_user.uid, &message.collection_uuid)
+        .await?
+    {
+        return Ok(StatusCode::UNAUTHORIZED.into_response());
+    }
+
+    // see add_collection
+    let owns_parent = match message.parent_uuid {
+        Some(parent_uuid) => {
+            state
+                .owns_collection(&current_user.uid, &parent_uuid)
+                .await?
+        }
+        None => true,
+    };
+
+    if !owns_parent {
+        return Ok(StatusCode::UNAUTHORIZED.into_response());
+    }
+
+    // the db refuses a cycle too, but only with an error that would become a 500
+    if let Some(parent_uuid) = message.parent_uuid
+        && is_ancestor(&state, message.collection_uuid, parent_uuid).await?
+    {
+        return Ok((
+            StatusCode::BAD_REQUEST,
+            "collection cannot be its own ancestor",
+        )
+            .into_response());
+    }
+
+    let (tx, rx) = tokio::sync::oneshot::channel();
+
+    state
+        .db_svc_sender
+        .send(
+            DbMsg::SetCollectionParent {
+                resp: tx,
+                collection_uuid: message.collection_uuid,
+                parent_uuid: message.parent_uuid,
+            }
+            .into(),
+        )
+        .await?;
+
+    rx.await??;
+
+    Ok(Json(SetCollectionParentResp {}).into_response())
+}
+
+// whether ancestor is the collection itself or anywhere above it
+async fn is_ancestor(
+    state: &HttpEndpoint,
+    ancestor: CollectionUuid,
+    collection_uuid: CollectionUuid,
+) -> anyhow::Result<bool> {
+    let mut seen = HashSet::new();
+    let mut next = Some(collection_uuid);
+
+    while let Some(collection_uuid) = next {
+        if collection_uuid == ancestor {
+            return Ok(true);
+        }
+
+        // the db should never have let a cycle in, but this must not loop forever if it did
+        if !seen.insert(collection_uuid) {
+            return Ok(false);
+        }
+
+        let (tx, rx) = tokio::sync::oneshot::channel();
+
+        state
+            .db_svc_sender
+            .send(
+                DbMsg::GetCollection {
+                    resp: tx,
+                    collection_uuid,
+                }
+                .into(),
+            )
+            .await?;
+
+        next = rx.await??.and_then(|collection| collection.parent_uuid);
+    }
 
-    Ok(Json(DeleteCollectionResp {}).into_response())
+    Ok(false)
 }
 
+#[utoipa::path(
+    post,
+    path = "/GetCollectionChildren",
+    tag = "collection",
+    request_body = GetCollectionChildrenReq,
+    responses(
+        (status = 200, body = GetCollectionChildrenResp),
+        (status = 401, description = "not authorized")
+    )
+)]
 #[instrument(skip_all)]
-pub(super) async fn update_collection(
+pub(super) async fn get_collection_children(
     State(state): State<Arc<HttpEndpoint>>,
     Extension(current_user): Extension<CurrentUser>,
-    Json(message): Json<UpdateCollectionReq>,
+    Json(message): Json<GetCollectionChildrenReq>,
 ) -> Result<Response, AppError> {
     if !state
-        .owns_collection(&current_user.uid, &message.collection_uuid)
+        .can_access_collection(&current_user.uid, &message.collection_uuid)
         .await?
     {
         return Ok(StatusCode::UNAUTHORIZED.into_response());
     }
 
+    // each child is checked on its own in the db search
+    let gid = state.groups_for_user(&current_user.uid).await?;
+
     let (tx, rx) = tokio::sync::oneshot::channel();
 
     state
         .db_svc_sender
         .send(
-            DbMsg::UpdateCollection {
+            DbMsg::GetCollectionChildren {
                 resp: tx,
+                gid,
                 collection_uuid: message.collection_uuid,
-                update: message.update,
             }
             .into(),
         )
         .await?;
 
-    rx.await??;
+    let result = rx.await??;
 
-    Ok(Json(UpdateCollectionResp {}).into_response())
+    Ok(Json(GetCollectionChildrenResp {
+        collections: result,
+    })
+    .into_response())
 }
 
+#[utoipa::path(
+    post,
+    path = "/AddMediaToCollection",
+    tag = "collection",
+    request_body = AddMediaToCollectionReq,
+    responses(
+        (status = 200, body = AddMediaToCollectionResp),
+        (status = 401, description = "not authorized")
+    )
+)]
 #[instrument(skip_all)]
 pub(super) async fn add_media_to_collection(
     State(state): State<Arc<HttpEndpoint>>,
@@ -508,6 +1407,16 @@ pub(super) async fn add_media_to_collection(
     Ok(Json(AddMediaToCollectionResp {}).into_response())
 }
 
+#[utoipa::path(
+    post,
+    path = "/RmMediaFromCollection",
+    tag = "collection",
+    request_body = RmMediaFromCollectionReq,
+    responses(
+        (status = 200, body = RmMediaFromCollectionResp),
+        (status = 401, description = "not authorized")
+    )
+)]
 #[instrument(skip_all)]
 pub(super) async fn rm_media_from_collection(
     State(state): State<Arc<HttpEndpoint>>,
@@ -550,6 +1459,58 @@ pub(super) async fn rm_media_from_collection(
     Ok(Json(RmMediaFromCollectionResp {}).into_response())
 }
 
+#[utoipa::path(
+    post,
+    path = "/ReorderCollectionMedia",
+    tag = "collection",
+    request_body = ReorderCollectionMediaReq,
+    responses(
+        (status = 200, body = ReorderCollectionMediaResp),
+        (status = 401, description = "not authorized")
+    )
+)]
+#[instrument(skip_all)]
+pub(super) async fn reorder_collection_media(
+    State(state): State<Arc<HttpEndpoint>>,
+    Extension(current_user): Extension<CurrentUser>,
+    Json(message): Json<ReorderCollectionMediaReq>,
+) -> Result<Response, AppError> {
+    // the order only touches rows already in the collection, so ownership is enough
+    if !state
+        .owns_collection(&current_user.uid, &message.collection_uuid)
+        .await?
+    {
+        return Ok(StatusCode::UNAUTHORIZED.into_response());
+    }
+
+    let (tx, rx) = tokio::sync::oneshot::channel();
+
+    state
+        .db_svc_sender
+        .send(
+            DbMsg::ReorderCollectionMedia {
+                resp: tx,
+                collection_uuid: message.collection_uuid,
+                media_uuids: message.media_uuids,
+            }
+            .into(),
+        )
+        .await?;
+
+    rx.await??;
+
+    Ok(Json(ReorderCollectionMediaResp {}).into_response())
+}
+
+#[utoipa::path(
+    post,
+    path = "/SearchCollections",
+    tag = "collection",
+    request_body = SearchCollectionsReq,
+    responses(
+        (status = 200, body = SearchCollectionsResp)
+    )
+)]
 #[instrument(skip_all)]
 pub(super) async fn search_collections(
     State(state): State<Arc<HttpEndpoint>>,
@@ -582,6 +1543,15 @@ pub(super) async fn search_collections(
     .into_response())
 }
 
+#[utoipa::path(
+    post,
+    path = "/SearchMediaInCollection",
+    tag = "collection",
+    request_body = SearchMediaInCollectionReq,
+    responses(
+        (status = 200, body = SearchMediaInCollectionResp)
+    )
+)]
 #[instrument(skip_all)]
 pub(super) async fn search_media_in_collection(
     State(state): State<Arc<HttpEndpoint>>,
@@ -602,6 +1572,8 @@ pub(super) async fn search_media_in_collection(
                 gid,
                 collection_uuid: message.collection_uuid,
                 filter: message.filter,
+                recursive: message.recursive,
+                ordered: message.ordered,
             }
             .into(),
         )
@@ -612,6 +1584,16 @@ pub(super) async fn search_media_in_collection(
     Ok(Json(SearchMediaInCollectionResp { media: result }).into_response())
 }
 
+#[utoipa::path(
+    post,
+    path = "/GetLibrary",
+    tag = "library",
+    request_body = GetLibraryReq,
+    responses(
+        (status = 200, body = GetLibraryResp),
+        (status = 401, description = "not authorized")
+    )
+)]
 #[instrument(skip_all)]
 pub(super) async fn get_library(
     State(state): State<Arc<HttpEndpoint>>,
@@ -645,6 +1627,63 @@ pub(super) async fn get_library(
     Ok(Json(GetLibraryResp { library: result }).into_response())
 }
 
+#[utoipa::path(
+    post,
+    path = "/UpdateLibrary",
+    tag = "library",
+    request_body = UpdateLibraryReq,
+    responses(
+        (status = 200, body = UpdateLibraryResp),
+        (status = 401, description = "not authorized")
+    )
+)]
+#[instrument(skip_all)]
+pub(super) async fn update_library(
+    State(state): State<Arc<HttpEndpoint>>,
+    Extension(current_user): Extension<CurrentUser>,
+    Json(message): Json<UpdateLibraryReq>,
+) -> Result<Response, AppError> {
+    if !state
+        .owns_library(&current_user.uid, &message.library_uuid)
+        .await?
+    {
+        return Ok(StatusCode::UNAUTHORIZED.into_response());
+    }
+
+    // the count belongs to the scanner
+    let update = LibraryUpdate {
+        count: None,
+        ..message.update
+    };
+
+    let (tx, rx) = tokio::sync::oneshot::channel();
+
+    state
+        .db_svc_sender
+        .send(
+            DbMsg::UpdateLibrary {
+                resp: tx,
+                library_uuid: message.library_uuid,
+                update,
+            }
+            .into(),
+        )
+        .await?;
+
+    rx.await??;
+
+    Ok(Json(UpdateLibraryResp {}).into_response())
+}
+
+#[utoipa::path(
+    post,
+    path = "/SearchLibraries",
+    tag = "library",
+    request_body = SearchLibrariesReq,
+    responses(
+        (status = 200, body = SearchLibrariesResp)
+    )
+)]
 #[instrument(skip_all)]
 pub(super) async fn search_libraries(
     State(state): State<Arc<HttpEndpoint>>,
@@ -674,6 +1713,52 @@ pub(super) async fn search_libraries(
     Ok(Json(SearchLibrariesResp { libraries: result }).into_response())
 }
 
+#[utoipa::path(
+    post,
+    path = "/SearchLibrariesDetailed",
+    tag = "library",
+    request_body = SearchLibrariesDetailedReq,
+    responses(
+        (status = 200, body = SearchLibrariesDetailedResp)
+    )
+)]
+#[instrument(skip_all)]
+pub(super) async fn search_libraries_detailed(
+    State(state): State<Arc<HttpEndpoint>>,
+    Extension(current_user): Extension<CurrentUser>,
+    Json(message): Json<SearchLibrariesDetailedReq>,
+) -> Result<Response, AppError> {
+    // auth handled as part of the db search
+    let gid = state.groups_for_user(&current_user.uid).await?;
+
+    let (tx, rx) = tokio::sync::oneshot::channel();
+
+    state
+        .db_svc_sender
+        .send(
+            DbMsg::SearchLibrariesDetailed {
+                resp: tx,
+                gid,
+                filter: message.filter,
+            }
+            .into(),
+        )
+        .await?;
+
+    let result = rx.await??;
+
+    Ok(Json(SearchLibrariesDetailedResp { libraries: result }).into_response())
+}
+
+#[utoipa::path(
+    post,
+    path = "/SearchMediaInLibrary",
+    tag = "library",
+    request_body = SearchMediaInLibraryReq,
+    responses(
+        (status = 200, body = SearchMediaInLibraryResp)
+    )
+)]
 #[instrument(skip_all)]
 pub(super) async fn search_media_in_library(
     State(state): State<Arc<HttpEndpoint>>,
@@ -705,6 +1790,51 @@ pub(super) async fn search_media_in_library(
     Ok(Json(SearchMediaInLibraryResp { media: result }).into_response())
 }
 
+#[utoipa::path(
+    post,
+    path = "/CountHiddenInLibrary",
+    tag = "library",
+    request_body = CountHiddenInLibraryReq,
+    responses(
+        (status = 200, body = CountHiddenInLibraryResp),
+        (status = 401, description = "not authorized")
+    )
+)]
+#[instrument(skip_all)]
+pub(super) async fn count_hidden_in_library(
+    State(state): State<Arc<HttpEndpoint>>,
+    Extension(current_user): Extension<CurrentUser>,
+    Json(message): Json<CountHiddenInLibraryReq>,
+) -> Result<Response, AppError> {
+    // only the owners can see hidden media, so only they may know how much there is
+    if !state
+        .owns_library(&current_user.uid, &message.library_uuid)
+        .await?
+    {
+        return Ok(StatusCode::UNAUTHORIZED.into_response());
+    }
+
+    let gid = state.groups_for_user(&current_user.uid).await?;
+
+    let (tx, rx) = tokio::sync::oneshot::channel();
+
+    state
+        .db_svc_sender
+        .send(
+            DbMsg::CountHiddenInLibrary {
+                resp: tx,
+                gid,
+                library_uuid: message.library_uuid,
+            }
+            .into(),
+        )
+        .await?;
+
+    let count = rx.await??;
+
+    Ok(Json(CountHiddenInLibraryResp { count }).into_response())
+}
+
 #[instrument(skip_all)]
 pub(super) async fn start_task(
     State(state): State<Arc<HttpEndpoint>>,
@@ -811,9 +1941,19 @@ pub(super) async fn show_tasks(
 // see notes in api/search.rs
 //
 // there are probably a dozen ways to do this better, including moving logic
-// into the database calls, avoiding an expensive copy at the end, and so on
+// into the database calls, and so on
 //
-// TODO -- look into streaming responses and lazy loading in the UI
+// this is fine for the smaller searches, but large libraries should use the streaming
+// variant in search.rs instead
+#[utoipa::path(
+    post,
+    path = "/BatchSearchAndSort",
+    tag = "search",
+    request_body = BatchSearchAndSortReq,
+    responses(
+        (status = 200, body = BatchSearchAndSortResp)
+    )
+)]
 #[instrument(skip_all)]
 pub(super) async fn batch_search_and_sort(
     State(state): State<Arc<HttpEndpoint>>,
@@ -822,89 +1962,605 @@ pub(super) async fn batch_search_and_sort(
 ) -> Result<Response, AppError> {
     let gid = state.groups_for_user(&current_user.uid).await?;
 
-    let (tx, rx) = tokio::sync::oneshot::channel();
+    let (media_uuids, total) = search_request(&state, gid, message.req).await?;
 
-    match message.req {
-        SearchRequest::Media(request) => {
-            state
-                .db_svc_sender
-                .send(
-                    DbMsg::SearchMedia {
-                        resp: tx,
-                        gid,
-                        filter: request.filter,
-                    }
-                    .into(),
-                )
-                .await?;
-        }
-        SearchRequest::Collection(request) => {
-            state
-                .db_svc_sender
-                .send(
-                    DbMsg::SearchMediaInCollection {
-                        resp: tx,
-                        gid,
-                        collection_uuid: request.collection_uuid,
-                        filter: request.filter,
-                    }
-                    .into(),
-                )
-                .await?;
+    let mut media = Vec::with_capacity(media_uuids.len());
+
+    for media_uuid in media_uuids {
+        media.push(
+            search_response(&state, media_uuid)
+                .await?
+                .ok_or_else(|| anyhow::Error::msg("unknown media_uuid"))?,
+        );
+    }
+
+    Ok(Json(BatchSearchAndSortResp { media, total }).into_response())
+}
+
+#[cfg(all(test, feature = "sqlite"))]
+mod tests {
+    use serde::de::DeserializeOwned;
+
+    use super::*;
+    use crate::http::testing::{
+        ADMIN_GID, ADMIN_UID, OTHER_UID, OWNER_GID, OWNER_UID, TestEndpoint, user,
+    };
+    use api::sort::SortOrder;
+
+    async fn body<T: DeserializeOwned>(response: Response) -> T {
+        assert_eq!(response.status(), StatusCode::OK);
+
+        let bytes = axum::body::to_bytes(response.into_body(), usize::MAX)
+            .await
+            .unwrap();
+
+        serde_json::from_slice(&bytes).unwrap()
+    }
+
+    fn collection(gid: &str) -> Json<AddCollectionReq> {
+        Json(AddCollectionReq {
+            collection: Collection {
+                uid: String::from(OWNER_UID),
+                gid: gid.to_owned(),
+                name: String::from("collection"),
+                note: String::new(),
+                tags: HashSet::new(),
+                cover: None,
+                parent_uuid: None,
+            },
+        })
+    }
+
+    #[tokio::test]
+    async fn users_in_group_accepts_a_valid_gid() {
+        let endpoint = TestEndpoint::new().await;
+
+        let response = get_users_in_group(
+            State(endpoint.state.clone()),
+            user(OWNER_UID),
+            Json(GetUsersInGroupReq {
+                gid: String::from(OWNER_GID),
+            }),
+        )
+        .await
+        .unwrap();
+
+        let resp: GetUsersInGroupResp = body(response).await;
+        assert_eq!(resp.uids, HashSet::from([String::from(OWNER_UID)]));
+    }
+
+    #[tokio::test]
+    async fn users_in_group_rejects_an_invalid_gid() {
+        let endpoint = TestEndpoint::new().await;
+
+        for gid in ["some group", "group|admins", ""] {
+            let response = get_users_in_group(
+                State(endpoint.state.clone()),
+                user(OWNER_UID),
+                Json(GetUsersInGroupReq {
+                    gid: gid.to_owned(),
+                }),
+            )
+            .await
+            .unwrap();
+
+            assert_eq!(response.status(), StatusCode::BAD_REQUEST);
         }
-        SearchRequest::Library(request) => {
-            state
-                .db_svc_sender
-                .send(
-                    DbMsg::SearchMediaInLibrary {
-                        resp: tx,
-                        gid,
-                        library_uuid: request.library_uuid,
-                        hidden: request.hidden,
-                        filter: request.filter,
-                    }
-                    .into(),
-                )
-                .await?;
+    }
+
+    #[tokio::test]
+    async fn add_collection_accepts_a_valid_gid() {
+        let endpoint = TestEndpoint::new().await;
+
+        let response = add_collection(
+            State(endpoint.state.clone()),
+            user(OWNER_UID),
+            collection(OWNER_GID),
+        )
+        .await
+        .unwrap();
+
+        let _: AddCollectionResp = body(response).await;
+    }
+
+    #[tokio::test]
+    async fn add_collection_rejects_an_invalid_gid() {
+        let endpoint = TestEndpoint::new().await;
+
+        for gid in ["some group", "group|admins", ""] {
+            let response = add_collection(
+                State(endpoint.state.clone()),
+                user(OWNER_UID),
+                collection(gid),
+            )
+            .await
+            .unwrap();
+
+            assert_eq!(response.status(), StatusCode::BAD_REQUEST);
         }
-    };
+    }
 
-    let media_uuids = rx.await??;
+    async fn get_media_status(
+        endpoint: &TestEndpoint,
+        uid: &str,
+        media_uuid: MediaUuid,
+    ) -> StatusCode {
+        get_media(
+            State(endpoint.state.clone()),
+            user(uid),
+            Json(GetMediaReq { media_uuid }),
+        )
+        .await
+        .unwrap()
+        .status()
+    }
 
-    let out = Mutex::new(Vec::<SearchResponse>::new());
+    #[tokio::test]
+    async fn admins_access_media_outside_their_groups() {
+        let endpoint = TestEndpoint::new().await;
+        let media_uuid = endpoint.add_media("a.jpg", b"jpeg bytes").await;
+
+        assert_eq!(
+            get_media_status(&endpoint, ADMIN_UID, media_uuid).await,
+            StatusCode::OK
+        );
+        assert_eq!(
+            get_media_status(&endpoint, OTHER_UID, media_uuid).await,
+            StatusCode::UNAUTHORIZED
+        );
+
+        // the decision comes from the user cache, so it has to be cleared to see the change
+        endpoint.set_groups(&[(OWNER_GID, &[OWNER_UID]), (ADMIN_GID, &[])]);
+        endpoint.clear_user_cache().await;
+
+        assert_eq!(
+            get_media_status(&endpoint, ADMIN_UID, media_uuid).await,
+            StatusCode::UNAUTHORIZED
+        );
+    }
 
-    for media_uuid in media_uuids {
-        let (tx, rx) = tokio::sync::oneshot::channel();
+    async fn search(endpoint: &TestEndpoint) -> Vec<MediaUuid> {
+        let response = search_media(
+            State(endpoint.state.clone()),
+            user(OWNER_UID),
+            Json(SearchMediaReq {
+                filter: SearchFilter::default(),
+                sort: SortOrder::PathAsc,
+                limit: None,
+                offset: None,
+            }),
+        )
+        .await
+        .unwrap();
 
-        state
-            .db_svc_sender
-            .send(
-                DbMsg::GetMedia {
-                    resp: tx,
+        body::<SearchMediaResp>(response).await.media
+    }
+
+    async fn trash(endpoint: &TestEndpoint) -> Vec<MediaUuid> {
+        let response = search_trash(
+            State(endpoint.state.clone()),
+            user(OWNER_UID),
+            Json(SearchTrashReq {
+                filter: SearchFilter::default(),
+            }),
+        )
+        .await
+        .unwrap();
+
+        body::<SearchTrashResp>(response).await.media
+    }
+
+    #[tokio::test]
+    async fn trashed_media_leaves_search_until_restored() {
+        let endpoint = TestEndpoint::new().await;
+        let a = endpoint.add_media("a.jpg", b"jpeg bytes").await;
+        let b = endpoint.add_media("b.jpg", b"jpeg bytes").await;
+
+        let response = delete_media(
+            State(endpoint.state.clone()),
+            user(OWNER_UID),
+            Json(DeleteMediaReq { media_uuid: a }),
+        )
+        .await
+        .unwrap();
+        assert_eq!(response.status(), StatusCode::OK);
+
+        assert_eq!(search(&endpoint).await, vec![b]);
+        assert_eq!(trash(&endpoint).await, vec![a]);
+
+        let response = restore_media(
+            State(endpoint.state.clone()),
+            user(OWNER_UID),
+            Json(RestoreMediaReq { media_uuid: a }),
+        )
+        .await
+        .unwrap();
+        assert_eq!(response.status(), StatusCode::OK);
+
+        assert_eq!(search(&endpoint).await, vec![a, b]);
+        assert!(trash(&endpoint).await.is_empty());
+    }
+
+    #[tokio::test]
+    async fn only_admins_purge_media() {
+        let endpoint = TestEndpoint::new().await;
+        let media_uuid = endpoint.add_media("a.jpg", b"jpeg bytes").await;
+
+        delete_media(
+            State(endpoint.state.clone()),
+            user(OWNER_UID),
+            Json(DeleteMediaReq { media_uuid }),
+        )
+        .await
+        .unwrap();
+
+        let purge = |uid| {
+            purge_media(
+                State(endpoint.state.clone()),
+                user(uid),
+                Json(PurgeMediaReq { media_uuid }),
+            )
+        };
+
+        // owning the media is not enough
+        assert_eq!(
+            purge(OWNER_UID).await.unwrap().status(),
+            StatusCode::UNAUTHORIZED
+        );
+        assert_eq!(trash(&endpoint).await, vec![media_uuid]);
+
+        assert_eq!(purge(ADMIN_UID).await.unwrap().status(), StatusCode::OK);
+        assert!(trash(&endpoint).await.is_empty());
+    }
+
+    // a new collection owned by OWNER_GID, created through the handler
+    //
+    // names are unique for each uid
+    async fn add_child(
+        endpoint: &TestEndpoint,
+        name: &str,
+        parent_uuid: Option<CollectionUuid>,
+    ) -> CollectionUuid {
+        let Json(mut message) = collection(OWNER_GID);
+        message.collection.name = name.to_owned();
+        message.collection.parent_uuid = parent_uuid;
+
+        let response = add_collection(
+            State(endpoint.state.clone()),
+            user(OWNER_UID),
+            Json(message),
+        )
+        .await
+        .unwrap();
+
+        body::<AddCollectionResp>(response).await.collection_uuid
+    }
+
+    async fn set_parent(
+        endpoint: &TestEndpoint,
+        collection_uuid: CollectionUuid,
+        parent_uuid: Option<CollectionUuid>,
+    ) -> StatusCode {
+        set_collection_parent(
+            State(endpoint.state.clone()),
+            user(OWNER_UID),
+            Json(SetCollectionParentReq {
+                collection_uuid,
+                parent_uuid,
+            }),
+        )
+        .await
+        .unwrap()
+        .status()
+    }
+
+    #[tokio::test]
+    async fn lists_collection_children() {
+        let endpoint = TestEndpoint::new().await;
+
+        let parent = add_child(&endpoint, "2021", None).await;
+        let child = add_child(&endpoint, "summer", Some(parent)).await;
+        let grandchild = add_child(&endpoint, "beach", Some(child)).await;
+
+        let children = |collection_uuid| {
+            get_collection_children(
+                State(endpoint.state.clone()),
+                user(OWNER_UID),
+                Json(GetCollectionChildrenReq { collection_uuid }),
+            )
+        };
+
+        // only the direct children
+        let resp: GetCollectionChildrenResp = body(children(parent).await.unwrap()).await;
+        assert_eq!(resp.collections, vec![child]);
+
+        let resp: GetCollectionChildrenResp = body(children(grandchild).await.unwrap()).await;
+        assert!(resp.collections.is_empty());
+    }
+
+    #[tokio::test]
+    async fn searches_descendants_when_recursive() {
+        let endpoint = TestEndpoint::new().await;
+
+        let parent = add_child(&endpoint, "2021", None).await;
+        let child = add_child(&endpoint, "summer", Some(parent)).await;
+        let grandchild = add_child(&endpoint, "beach", Some(child)).await;
+
+        let mut media_uuids = Vec::new();
+
+        for (path, collection_uuid) in [("a.jpg", parent), ("b.jpg", child), ("c.jpg", grandchild)]
+        {
+            let media_uuid = endpoint.add_media(path, b"jpeg bytes").await;
+
+            endpoint
+                .db(|resp| DbMsg::AddMediaToCollection {
+                    resp,
                     media_uuid,
-                }
-                .into(),
+                    collection_uuid,
+                })
+                .await;
+
+            media_uuids.push(media_uuid);
+        }
+
+        let endpoint = &endpoint;
+        let search = |collection_uuid, recursive| async move {
+            let response = search_media_in_collection(
+                State(endpoint.state.clone()),
+                user(OWNER_UID),
+                Json(SearchMediaInCollectionReq {
+                    collection_uuid,
+                    filter: SearchFilter::default(),
+                    recursive,
+                    ordered: false,
+                }),
             )
-            .await?;
+            .await
+            .unwrap();
+
+            body::<SearchMediaInCollectionResp>(response)
+                .await
+                .media
+                .into_iter()
+                .collect::<HashSet<_>>()
+        };
+
+        assert_eq!(search(parent, false).await, HashSet::from([media_uuids[0]]));
+        assert_eq!(
+            search(parent, true).await,
+            HashSet::from_iter(media_uuids.clone())
+        );
+        assert_eq!(
+            search(child, true).await,
+            HashSet::from([media_uuids[1], media_uuids[2]])
+        );
+    }
 
-        let media_data = rx
-            .await??
-            .ok_or_else(|| anyhow::Error::msg("unknown media_uuid"))?;
+    #[tokio::test]
+    async fn rejects_collection_cycles() {
+        let endpoint = TestEndpoint::new().await;
+
+        let parent = add_child(&endpoint, "2021", None).await;
+        let child = add_child(&endpoint, "summer", Some(parent)).await;
+        let grandchild = add_child(&endpoint, "beach", Some(child)).await;
+
+        assert_eq!(
+            set_parent(&endpoint, parent, Some(parent)).await,
+            StatusCode::BAD_REQUEST
+        );
+        assert_eq!(
+            set_parent(&endpoint, parent, Some(grandchild)).await,
+            StatusCode::BAD_REQUEST
+        );
+
+        // moving within the tree is fine as long as it stays a tree
+        assert_eq!(
+            set_parent(&endpoint, grandchild, Some(parent)).await,
+            StatusCode::OK
+        );
+        assert_eq!(
+            set_parent(&endpoint, child, Some(grandchild)).await,
+            StatusCode::OK
+        );
+        assert_eq!(set_parent(&endpoint, child, None).await, StatusCode::OK);
+    }
 
-        let mut out = out.lock().await;
+    async fn note(endpoint: &TestEndpoint, media_uuid: MediaUuid) -> String {
+        endpoint
+            .db(|resp| DbMsg::GetMedia { resp, media_uuid })
+            .await
+            .unwrap()
+            .0
+            .note
+    }
 
-        out.push(SearchResponse {
-            media_uuid,
-            media: media_data.0,
-            collections: media_data.1,
-            comments: media_data.2,
-        });
+    async fn batch_note(
+        endpoint: &TestEndpoint,
+        media_uuids: Vec<MediaUuid>,
+        note: &str,
+    ) -> Response {
+        batch_update_media(
+            State(endpoint.state.clone()),
+            user(OWNER_UID),
+            Json(BatchUpdateMediaReq {
+                media_uuids,
+                update: MediaUpdate {
+                    note: Some(note.to_owned()),
+                    ..Default::default()
+                },
+            }),
+        )
+        .await
+        .unwrap()
     }
 
-    let out = out.lock().await;
+    #[tokio::test]
+    async fn batch_update_applies_to_every_media() {
+        let endpoint = TestEndpoint::new().await;
+        let a = endpoint.add_media("a.jpg", b"jpeg bytes").await;
+        let b = endpoint.add_media("b.jpg", b"jpeg bytes").await;
 
-    Ok(Json(BatchSearchAndSortResp {
-        media: out.to_vec(),
-    })
-    .into_response())
+        let resp: BatchUpdateMediaResp =
+            body(batch_note(&endpoint, vec![a, b], "beach").await).await;
+
+        assert_eq!(resp.updated, vec![a, b]);
+        assert!(resp.failed.is_empty());
+        assert_eq!(note(&endpoint, a).await, "beach");
+        assert_eq!(note(&endpoint, b).await, "beach");
+    }
+
+    #[tokio::test]
+    async fn batch_update_rejects_a_partly_owned_batch() {
+        let endpoint = TestEndpoint::new().await;
+        let owned = endpoint.add_media("a.jpg", b"jpeg bytes").await;
+
+        let library_uuid = endpoint.add_library("elsewhere", ADMIN_GID).await;
+        let other = endpoint
+            .add_media_with(
+                Media {
+                    library_uuid,
+                    ..endpoint.media("b.jpg")
+                },
+                b"jpeg bytes",
+            )
+            .await;
+
+        // in either order, and without touching the media that the user does own
+        for media_uuids in [vec![owned, other], vec![other, owned]] {
+            assert_eq!(
+                batch_note(&endpoint, media_uuids, "beach").await.status(),
+                StatusCode::UNAUTHORIZED
+            );
+        }
+
+        assert_eq!(note(&endpoint, owned).await, "");
+        assert_eq!(note(&endpoint, other).await, "");
+    }
+
+    // adds the media to a new collection owned by gid, going straight to the database since
+    // OWNER_UID need not be in the group
+    async fn share(endpoint: &TestEndpoint, media_uuid: MediaUuid, gid: &str) -> CollectionUuid {
+        let Json(message) = collection(gid);
+        let collection_uuid = endpoint
+            .db(|resp| DbMsg::AddCollection {
+                resp,
+                collection: message.collection,
+            })
+            .await;
+
+        endpoint
+            .db(|resp| DbMsg::AddMediaToCollection {
+                resp,
+                media_uuid,
+                collection_uuid,
+            })
+            .await;
+
+        collection_uuid
+    }
+
+    #[tokio::test]
+    async fn batch_hide_removes_media_from_search_and_collections() {
+        let endpoint = TestEndpoint::new().await;
+        let a = endpoint.add_media("a.jpg", b"jpeg bytes").await;
+        let b = endpoint.add_media("b.jpg", b"jpeg bytes").await;
+
+        // OTHER_UID only sees the media through a collection
+        endpoint.set_groups(&[
+            (OWNER_GID, &[OWNER_UID]),
+            (ADMIN_GID, &[ADMIN_UID]),
+            ("viewers", &[OTHER_UID]),
+        ]);
+        endpoint.clear_user_cache().await;
+
+        share(&endpoint, a, "viewers").await;
+
+        // which fills the access cache
+        assert_eq!(
+            get_media_status(&endpoint, OTHER_UID, a).await,
+            StatusCode::OK
+        );
+
+        let response = batch_update_media(
+            State(endpoint.state.clone()),
+            user(OWNER_UID),
+            Json(BatchUpdateMediaReq {
+                media_uuids: vec![a, b],
+                update: MediaUpdate {
+                    hidden: Some(true),
+                    ..Default::default()
+                },
+            }),
+        )
+        .await
+        .unwrap();
+
+        let resp: BatchUpdateMediaResp = body(response).await;
+        assert_eq!(resp.updated, vec![a, b]);
+
+        assert!(search(&endpoint).await.is_empty());
+
+        // hidden media are only shared through their library, and the stale cache entry
+        // must not say otherwise
+        assert_eq!(
+            get_media_status(&endpoint, OTHER_UID, a).await,
+            StatusCode::UNAUTHORIZED
+        );
+        assert_eq!(
+            get_media_status(&endpoint, OWNER_UID, a).await,
+            StatusCode::OK
+        );
+    }
+
+    #[tokio::test]
+    async fn media_access_lists_each_source() {
+        let endpoint = TestEndpoint::new().await;
+        let media_uuid = endpoint.add_media("a.jpg", b"jpeg bytes").await;
+        let collection_uuid = share(&endpoint, media_uuid, "viewers").await;
+
+        let access = |uid| {
+            get_media_access(
+                State(endpoint.state.clone()),
+                user(uid),
+                Json(GetMediaAccessReq { media_uuid }),
+            )
+        };
+
+        let from_library = MediaAccess {
+            gid: String::from(OWNER_GID),
+            source: AccessSource::Library {
+                library_uuid: endpoint.media("a.jpg").library_uuid,
+            },
+        };
+        let from_collection = MediaAccess {
+            gid: String::from("viewers"),
+            source: AccessSource::Collection { collection_uuid },
+        };
+
+        let resp: GetMediaAccessResp = body(access(OWNER_UID).await.unwrap()).await;
+        assert_eq!(resp.access.len(), 2, "{:?}", resp.access);
+        assert!(resp.access.contains(&from_library));
+        assert!(resp.access.contains(&from_collection));
+
+        // only owners get to see who else has access
+        assert_eq!(
+            access(OTHER_UID).await.unwrap().status(),
+            StatusCode::UNAUTHORIZED
+        );
+
+        // and hiding the media leaves only its library
+        endpoint
+            .db(|resp| DbMsg::UpdateMedia {
+                resp,
+                media_uuid,
+                update: MediaUpdate {
+                    hidden: Some(true),
+                    ..Default::default()
+                },
+            })
+            .await;
+
+        let resp: GetMediaAccessResp = body(access(OWNER_UID).await.unwrap()).await;
+        assert_eq!(resp.access, vec![from_library]);
+    }
 }
@@ -1,16 +1,34 @@
-use std::sync::Arc;
+use std::{
+    sync::Arc,
+    time::{Duration, Instant},
+};
 
+use anyhow::Result;
 use axum::{
-    extract::{Request, State},
+    extract::{Query, Request, State},
     http::{
-        header::AUTHORIZATION,
-        {HeaderName, StatusCode},
+        header::{AUTHORIZATION, COOKIE, SET_COOKIE},
+        {HeaderMap, HeaderName, StatusCode},
     },
     middleware::Next,
-    response::Response,
+    response::{AppendHeaders, IntoResponse, Redirect, Response},
 };
+use dashmap::DashMap;
+use rand::random;
+use serde::Deserialize;
+use tracing::{debug, instrument, warn};
 
-use crate::http::svc::HttpEndpoint;
+use crate::{db::msg::DbMsg, http::svc::HttpEndpoint, service::EsmSender};
+use api::HTTP_URL_ROOT;
+use common::{
+    auth::{
+        AuthnProvider,
+        apikey::{API_KEY_PREFIX, hash_api_key},
+        oidc::OidcAuthn,
+    },
+    config::ESConfig,
+    is_valid_uid,
+};
 
 // user auth information passed in from middleware to the axum extractors,
 // attached to the request via an extension with this type
@@ -26,11 +44,49 @@ pub struct ClientCn {
     pub cn: String,
 }
 
+// authentication via api key
+//
+// scripts and other non-browser clients can't easily go through the proxy or the oidc
+// login, so the middleware for those backends also accepts an "Authorization: Bearer"
+// header holding one of the user's keys.  only the hash is stored, so the lookup is
+// done on the hash as well.
+#[instrument(skip_all)]
+async fn api_key_user(db_svc_sender: &EsmSender, headers: &HeaderMap) -> Option<String> {
+    let key = headers
+        .get(AUTHORIZATION)
+        .and_then(|header| header.to_str().ok())
+        .and_then(|header| header.strip_prefix("Bearer "))
+        .map(str::trim)
+        .filter(|key| key.starts_with(API_KEY_PREFIX))?;
+
+    let (tx, rx) = tokio::sync::oneshot::channel();
+
+    db_svc_sender
+        .send(
+            DbMsg::GetApiKeyUid {
+                resp: tx,
+                key_hash: hash_api_key(key),
+            }
+            .into(),
+        )
+        .await
+        .ok()?;
+
+    match rx.await.ok()? {
+        Ok(uid) => uid,
+        Err(err) => {
+            warn!("failed to look up api key: {err}");
+            None
+        }
+    }
+}
+
 // authentication via reverse proxy
 #[derive(Clone)]
 pub struct ProxyAuthData {
     pub header_key: HeaderName,
     pub cn: String,
+    pub db_svc_sender: EsmSender,
 }
 
 pub async fn proxy_auth(
@@ -51,16 +107,23 @@ pub async fn proxy_auth(
     let header_val = req
         .headers()
         .get(state.header_key)
-        .and_then(|header| header.to_str().ok());
+        .and_then(|header| header.to_str().ok())
+        .map(|val| val.to_owned());
 
-    let header_val = match header_val {
+    // the proxy only passes the header for users that it authenticated itself
+    let uid = match header_val {
         Some(val) => val,
-        None => return Err(StatusCode::UNAUTHORIZED),
+        None => api_key_user(&state.db_svc_sender, req.headers())
+            .await
+            .ok_or(StatusCode::UNAUTHORIZED)?,
     };
 
-    let user = CurrentUser {
-        uid: header_val.to_owned(),
-    };
+    if !is_valid_uid(&uid) {
+        warn!({ uid = uid }, "rejecting invalid uid from proxy");
+        return Err(StatusCode::UNAUTHORIZED);
+    }
+
+    let user = CurrentUser { uid };
 
     // if auth succeeds, pass CurrentUser as a request extension to handlers
     req.extensions_mut().insert(user);
@@ -79,6 +142,14 @@ pub async fn cert_auth(mut req: Request, next: Next) -> Result<Response, StatusC
             .clone(),
     };
 
+    if !is_valid_uid(&user.uid) {
+        warn!(
+            { uid = user.uid },
+            "rejecting invalid uid from client certificate"
+        );
+        return Err(StatusCode::UNAUTHORIZED);
+    }
+
     // if auth succeeds, pass CurrentUser as a request extension to handlers
     req.extensions_mut().insert(user);
 
@@ -86,6 +157,199 @@ pub async fn cert_auth(mut req: Request, next: Next) -> Result<Response, StatusC
     Ok(next.run(req).await)
 }
 
+// authentication via openid connect
+//
+// the login route sends the browser to the issuer, and the callback route checks the
+// resulting id token before handing out a session cookie for the middleware to find.
+// sessions only live in memory, so restarting the server logs everyone out.
+//
+// the login state and nonce are kept in a cookie on the browser that started the login,
+// rather than on the server.  the callback only accepts a state that matches that cookie,
+// so a callback url from someone else's login can't be used to log a victim in as them.
+const OIDC_SESSION_COOKIE: &str = "entanglement_session";
+
+const OIDC_LOGIN_COOKIE: &str = "entanglement_login";
+
+// seconds that the browser has to come back from the issuer
+const OIDC_LOGIN_LIFETIME: u64 = 600;
+
+const OIDC_DEFAULT_SESSION_LIFETIME: u64 = 43200;
+
+#[derive(Debug)]
+pub struct OidcState {
+    provider: OidcAuthn,
+    db_svc_sender: EsmSender,
+    session_lifetime: Duration,
+    // session id: (uid, expiry)
+    sessions: DashMap<String, (String, Instant)>,
+}
+
+impl OidcState {
+    pub fn new(config: Arc<ESConfig>, db_svc_sender: EsmSender) -> Result<Self> {
+        let session_lifetime = config
+            .oidc
+            .as_ref()
+            .and_then(|c| c.session_lifetime)
+            .unwrap_or(OIDC_DEFAULT_SESSION_LIFETIME);
+
+        Ok(OidcState {
+            provider: OidcAuthn::new(config)?,
+            db_svc_sender,
+            session_lifetime: Duration::from_secs(session_lifetime),
+            sessions: DashMap::new(),
+        })
+    }
+}
+
+pub(super) fn random_token() -> String {
+    format!("{:032x}{:032x}", random::<u128>(), random::<u128>())
+}
+
+fn cookie(headers: &HeaderMap, name: &str) -> Option<String> {
+    headers
+        .get_all(COOKIE)
+        .iter()
+        .filter_map(|v| v.to_str().ok())
+        .flat_map(|v| v.split(';'))
+        .filter_map(|c| c.trim().split_once('='))
+        .find(|(k, _)| *k == name)
+        .map(|(_, v)| v.to_owned())
+}
+
+#[instrument(skip_all)]
+pub async fn oidc_login(State(state): State<Arc<OidcState>>) -> Result<Response, StatusCode> {
+    let login_state = random_token();
+    let nonce = random_token();
+
+    let url = state
+        .provider
+        .authorization_url(&login_state, &nonce)
+        .await
+        .map_err(|err| {
+            warn!("failed to build oidc authorization url: {err}");
+            StatusCode::BAD_GATEWAY
+        })?;
+
+    let cookie = format!(
+        "{OIDC_LOGIN_COOKIE}={login_state}.{nonce}; Path=/{HTTP_URL_ROOT}/auth; Max-Age={OIDC_LOGIN_LIFETIME}; HttpOnly; Secure; SameSite=Lax"
+    );
+
+    Ok(([(SET_COOKIE, cookie)], Redirect::to(url.as_str())).into_response())
+}
+
+#[derive(Debug, Deserialize)]
+pub struct OidcCallback {
+    code: Option<String>,
+    state: String,
+    error: Option<String>,
+}
+
+#[instrument(skip_all)]
+pub async fn oidc_callback(
+    State(state): State<Arc<OidcState>>,
+    headers: HeaderMap,
+    Query(query): Query<OidcCallback>,
+) -> Result<Response, StatusCode> {
+    // the state must be the one that this browser was handed when it started the login
+    let (login_state, nonce) = cookie(&headers, OIDC_LOGIN_COOKIE)
+        .and_then(|v| v.split_once('.').map(|(s, n)| (s.to_owned(), n.to_owned())))
+        .ok_or(StatusCode::BAD_REQUEST)?;
+
+    if login_state != query.state {
+        warn!("oidc callback state does not match the login cookie");
+        return Err(StatusCode::BAD_REQUEST);
+    }
+
+    if let Some(err) = query.error {
+        warn!("oidc issuer returned an error: {err}");
+        return Err(StatusCode::UNAUTHORIZED);
+    }
+
+    let code = query.code.ok_or(StatusCode::BAD_REQUEST)?;
+
+    let id_token = state.provider.exchange_code(&code).await.map_err(|err| {
+        warn!("failed to exchange oidc authorization code: {err}");
+        StatusCode::UNAUTHORIZED
+    })?;
+
+    let uid = state
+        .provider
+        .validate_id_token(&id_token, Some(&nonce))
+        .await
+        .map_err(|err| {
+            warn!("failed to validate id token: {err}");
+            StatusCode::UNAUTHORIZED
+        })?;
+
+    debug!({ uid = uid }, "oidc login succeeded");
+
+    state
+        .sessions
+        .retain(|_, (_, expiry)| *expiry > Instant::now());
+
+    let session = random_token();
+
+    state.sessions.insert(
+        session.clone(),
+        (uid, Instant::now() + state.session_lifetime),
+    );
+
+    let cookie = format!(
+        "{OIDC_SESSION_COOKIE}={session}; Path=/{HTTP_URL_ROOT}; Max-Age={}; HttpOnly; Secure; SameSite=Lax",
+        state.session_lifetime.as_secs()
+    );
+
+    // the login cookie has done its job
+    let login_cookie = format!(
+        "{OIDC_LOGIN_COOKIE}=; Path=/{HTTP_URL_ROOT}/auth; Max-Age=0; HttpOnly; Secure; SameSite=Lax"
+    );
+
+    Ok((
+        AppendHeaders([(SET_COOKIE, cookie), (SET_COOKIE, login_cookie)]),
+        Redirect::to(&format!("/{HTTP_URL_ROOT}/app")),
+    )
+        .into_response())
+}
+
+pub async fn oidc_auth(
+    State(state): State<Arc<OidcState>>,
+    mut req: Request,
+    next: Next,
+) -> Result<Response, StatusCode> {
+    let uid = cookie(req.headers(), OIDC_SESSION_COOKIE).and_then(|session| {
+        state
+            .sessions
+            .get(&session)
+            .filter(|v| v.1 > Instant::now())
+            .map(|v| v.0.clone())
+    });
+
+    let uid = match uid {
+        Some(uid) => Some(uid),
+        None => api_key_user(&state.db_svc_sender, req.headers()).await,
+    };
+
+    let is_app = req
+        .uri()
+        .path()
+        .starts_with(&format!("/{HTTP_URL_ROOT}/app"));
+
+    let uid = match uid {
+        Some(uid) => uid,
+        // api calls can't follow a redirect to the issuer, but the webapp itself can
+        None if is_app => {
+            return Ok(Redirect::to(&format!("/{HTTP_URL_ROOT}/auth/login")).into_response());
+        }
+        None => return Err(StatusCode::UNAUTHORIZED),
+    };
+
+    // if auth succeeds, pass CurrentUser as a request extension to handlers
+    req.extensions_mut().insert(CurrentUser { uid });
+
+    // then, continue on in the tower of middleware
+    Ok(next.run(req).await)
+}
+
 async fn _password_auth(
     State(_state): State<Arc<HttpEndpoint>>,
     mut req: Request,
@@ -124,3 +388,213 @@ async fn _password_auth(
 async fn _authorize(_auth_token: &str) -> anyhow::Result<CurrentUser> {
     Err(anyhow::Error::msg("not implemented"))
 }
+
+#[cfg(test)]
+mod tests {
+    use axum::{
+        Extension, Json, Router, body::Body, http::header::LOCATION, middleware, routing::get,
+    };
+    use serde_json::json;
+    use tower::ServiceExt;
+
+    use super::*;
+
+    // an issuer that only serves discovery, which is all that the login route needs
+    async fn oidc_state() -> Arc<OidcState> {
+        let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let issuer = format!("http://{}", listener.local_addr().unwrap());
+
+        let metadata = json!({
+            "issuer": issuer,
+            "authorization_endpoint": format!("{issuer}/authorize"),
+            "token_endpoint": format!("{issuer}/token"),
+            "jwks_uri": format!("{issuer}/jwks"),
+        });
+
+        let app = Router::new().route(
+            "/.well-known/openid-configuration",
+            get(move || async move { Json(metadata) }),
+        );
+
+        tokio::spawn(async move { axum::serve(listener, app).await.unwrap() });
+
+        let config: ESConfig = toml::from_str(&format!(
+            r#"
+            authn_backend = "oidc"
+            authz_backend = "tomlfile"
+            db_backend = "mariadb"
+
+            [fs]
+            media_srcdir = "/nonexistent/src"
+            media_srvdir = "/nonexistent/srv"
+
+            [http]
+            socket = "127.0.0.1:0"
+            doc_root = "/nonexistent/doc"
+            key = "/nonexistent/key.pem"
+            cert = "/nonexistent/cert.pem"
+
+            [task]
+            scan_threads = 1
+            scan_scratch = "/nonexistent/scratch"
+            scan_timeout = 60
+
+            [oidc]
+            issuer = "{issuer}"
+            client_id = "entanglement"
+            client_secret = "secret"
+            redirect_url = "https://entanglement.example/{HTTP_URL_ROOT}/auth/callback"
+            "#
+        ))
+        .unwrap();
+
+        let (db_svc_sender, _) = tokio::sync::mpsc::channel(1);
+
+        Arc::new(OidcState::new(Arc::new(config), db_svc_sender).unwrap())
+    }
+
+    fn callback(state: &str) -> Query<OidcCallback> {
+        Query(OidcCallback {
+            code: Some(String::from("code")),
+            state: state.to_owned(),
+            error: None,
+        })
+    }
+
+    fn with_cookie(cookie: &str) -> HeaderMap {
+        let mut headers = HeaderMap::new();
+        headers.insert(COOKIE, cookie.parse().unwrap());
+        headers
+    }
+
+    #[tokio::test]
+    async fn login_binds_the_state_to_the_browser() {
+        let response = oidc_login(State(oidc_state().await)).await.unwrap();
+
+        // both are hex, so they need no decoding
+        let location = response.headers()[LOCATION].to_str().unwrap();
+        let param = |name: &str| {
+            location
+                .split(['?', '&'])
+                .find_map(|pair| pair.strip_prefix(&format!("{name}=")))
+                .unwrap()
+                .to_owned()
+        };
+
+        let cookie = response.headers()[SET_COOKIE].to_str().unwrap();
+
+        assert!(cookie.starts_with(&format!(
+            "{OIDC_LOGIN_COOKIE}={}.{};",
+            param("state"),
+            param("nonce")
+        )));
+        assert!(cookie.contains("HttpOnly"));
+        assert!(cookie.contains("SameSite=Lax"));
+        assert!(cookie.contains(&format!("Max-Age={OIDC_LOGIN_LIFETIME}")));
+    }
+
+    #[tokio::test]
+    async fn callback_requires_the_login_cookie() {
+        let result = oidc_callback(
+            State(oidc_state().await),
+            HeaderMap::new(),
+            callback("state"),
+        )
+        .await;
+
+        assert_eq!(result.unwrap_err(), StatusCode::BAD_REQUEST);
+    }
+
+    #[tokio::test]
+    async fn callback_rejects_a_state_from_another_login() {
+        let result = oidc_callback(
+            State(oidc_state().await),
+            with_cookie(&format!("{OIDC_LOGIN_COOKIE}=mine.nonce")),
+            callback("theirs"),
+        )
+        .await;
+
+        assert_eq!(result.unwrap_err(), StatusCode::BAD_REQUEST);
+    }
+
+    // echoes the uid that the middleware settled on
+    async fn whoami(router: Router, cn: &str, header: Option<&str>) -> (StatusCode, String) {
+        let mut request = Request::get("/").body(Body::empty()).unwrap();
+
+        if let Some(val) = header {
+            request
+                .headers_mut()
+                .insert("x-remote-user", val.parse().unwrap());
+        }
+
+        let response = router
+            .layer(Extension(ClientCn { cn: cn.to_owned() }))
+            .oneshot(request)
+            .await
+            .unwrap();
+
+        let status = response.status();
+        let body = axum::body::to_bytes(response.into_body(), usize::MAX)
+            .await
+            .unwrap();
+
+        (status, String::from_utf8(body.to_vec()).unwrap())
+    }
+
+    fn echo() -> Router {
+        Router::new().route(
+            "/",
+            get(|Extension(user): Extension<CurrentUser>| async move { user.uid }),
+        )
+    }
+
+    fn proxy_router() -> Router {
+        let (db_svc_sender, _) = tokio::sync::mpsc::channel(1);
+
+        echo().route_layer(middleware::from_fn_with_state(
+            ProxyAuthData {
+                header_key: HeaderName::from_static("x-remote-user"),
+                cn: String::from("proxy"),
+                db_svc_sender,
+            },
+            proxy_auth,
+        ))
+    }
+
+    #[tokio::test]
+    async fn cert_auth_accepts_a_valid_cn() {
+        let router = echo().route_layer(middleware::from_fn(cert_auth));
+
+        assert_eq!(
+            whoami(router, "alice", None).await,
+            (StatusCode::OK, String::from("alice"))
+        );
+    }
+
+    #[tokio::test]
+    async fn cert_auth_rejects_an_invalid_cn() {
+        for cn in ["alice smith", "alice|admins", ""] {
+            let router = echo().route_layer(middleware::from_fn(cert_auth));
+
+            assert_eq!(whoami(router, cn, None).await.0, StatusCode::UNAUTHORIZED);
+        }
+    }
+
+    #[tokio::test]
+    async fn proxy_auth_accepts_a_valid_header() {
+        assert_eq!(
+            whoami(proxy_router(), "proxy", Some("alice")).await,
+            (StatusCode::OK, String::from("alice"))
+        );
+    }
+
+    #[tokio::test]
+    async fn proxy_auth_rejects_an_invalid_header() {
+        for uid in ["alice smith", "alice|admins", ""] {
+            assert_eq!(
+                whoami(proxy_router(), "proxy", Some(uid)).await.0,
+                StatusCode::UNAUTHORIZED
+            );
+        }
+    }
+}
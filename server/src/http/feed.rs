@@ -0,0 +1,165 @@
+use std::{fmt::Write, path::Path, sync::Arc};
+
+use anyhow::Result;
+use axum::{
+    extract::{Extension, State},
+    http::header::CONTENT_TYPE,
+    response::{IntoResponse, Response},
+};
+use chrono::{DateTime, SecondsFormat, Utc};
+use tracing::{debug, instrument};
+
+use crate::{
+    auth::check::AuthCheck,
+    db::msg::DbMsg,
+    http::{AppError, auth::CurrentUser, svc::HttpEndpoint},
+};
+use api::{
+    FEED_PATH, HTTP_URL_ROOT,
+    media::{MediaUuid, ThumbnailSize},
+    search::SearchFilter,
+    sort::SortOrder,
+    thumbnail_link,
+};
+
+// atom feed
+//
+// the most recently added media that the user can find with SearchMedia, so that they can
+// follow a library from a feed reader.  the feed sits behind the auth middleware like the
+// rest of the app, which means that readers need an api key for the backends that support
+// them.
+//
+// the links are relative, which readers resolve against the url of the feed itself.
+const DEFAULT_FEED_SIZE: u64 = 50;
+
+#[instrument(skip_all)]
+pub(super) async fn get_feed(
+    State(state): State<Arc<HttpEndpoint>>,
+    Extension(current_user): Extension<CurrentUser>,
+) -> Result<Response, AppError> {
+    // auth handled as part of the db search, which also leaves out hidden media
+    let gid = state.groups_for_user(&current_user.uid).await?;
+
+    let (tx, rx) = tokio::sync::oneshot::channel();
+
+    state
+        .db_svc_sender
+        .send(
+            DbMsg::SearchMedia {
+                resp: tx,
+                gid,
+                filter: SearchFilter::default(),
+                sort: SortOrder::AddedDesc,
+                limit: Some(state.config.http.feed_size.unwrap_or(DEFAULT_FEED_SIZE)),
+                offset: None,
+            }
+            .into(),
+        )
+        .await?;
+
+    let (media_uuids, _) = rx.await??;
+
+    debug!({ count = media_uuids.len() }, "building feed");
+
+    let mut entries = String::new();
+    let mut updated = None;
+
+    for media_uuid in media_uuids {
+        let (tx, rx) = tokio::sync::oneshot::channel();
+
+        state
+            .db_svc_sender
+            .send(
+                DbMsg::GetMedia {
+                    resp: tx,
+                    media_uuid,
+                }
+                .into(),
+            )
+            .await?;
+
+        // trashed since the search
+        let Some((media, _, _)) = rx.await?? else {
+            continue;
+        };
+
+        let added = added_time(media_uuid)?;
+
+        // the results are newest first
+        if updated.is_none() {
+            updated = Some(added.clone());
+        }
+
+        let title = Path::new(&media.path)
+            .file_name()
+            .map(|v| v.to_string_lossy().into_owned())
+            .unwrap_or_else(|| media_uuid.to_string());
+
+        let detail = format!("/{HTTP_URL_ROOT}/app/gallery/{media_uuid}");
+
+        let mut content = format!(
+            "<a href=\"{detail}\"><img src=\"{}\"/></a><p>{}</p>",
+            escape(&thumbnail_link(media_uuid, ThumbnailSize::Grid)),
+            escape(&media.date),
+        );
+
+        if !media.note.is_empty() {
+            write!(content, "<p>{}</p>", escape(&media.note))?;
+        }
+
+        write!(
+            entries,
+            "<entry>\
+            <id>urn:uuid:{media_uuid}</id>\
+            <title>{}</title>\
+            <updated>{added}</updated>\
+            <link rel=\"alternate\" href=\"{detail}\"/>\
+            <content type=\"html\">{}</content>\
+            </entry>",
+            escape(&title),
+            escape(&content),
+        )?;
+    }
+
+    let updated = match updated {
+        Some(v) => v,
+        None => Utc::now().to_rfc3339_opts(SecondsFormat::Secs, true),
+    };
+
+    let feed = format!(
+        "<?xml version=\"1.0\" encoding=\"utf-8\"?>\
+        <feed xmlns=\"http://www.w3.org/2005/Atom\">\
+        <id>urn:entanglement:feed:{}</id>\
+        <title>entanglement</title>\
+        <author><name>entanglement</name></author>\
+        <updated>{updated}</updated>\
+        <link rel=\"self\" href=\"/{HTTP_URL_ROOT}/{FEED_PATH}\"/>\
+        {entries}\
+        </feed>",
+        escape(&current_user.uid),
+    );
+
+    Ok(([(CONTENT_TYPE, "application/atom+xml")], feed).into_response())
+}
+
+// media uuids are v7, so they carry the time that the media was added
+fn added_time(media_uuid: MediaUuid) -> Result<String> {
+    let (secs, _) = media_uuid
+        .value()
+        .get_timestamp()
+        .ok_or_else(|| anyhow::Error::msg("internal error: media uuid has no timestamp"))?
+        .to_unix();
+
+    let time = DateTime::<Utc>::from_timestamp(secs.try_into()?, 0)
+        .ok_or_else(|| anyhow::Error::msg("internal error: media uuid timestamp out of range"))?;
+
+    Ok(time.to_rfc3339_opts(SecondsFormat::Secs, true))
+}
+
+fn escape(text: &str) -> String {
+    text.replace('&', "&amp;")
+        .replace('<', "&lt;")
+        .replace('>', "&gt;")
+        .replace('"', "&quot;")
+        .replace('\'', "&apos;")
+}
@@ -0,0 +1,110 @@
+use std::{future::Future, sync::Arc, time::Duration};
+
+use anyhow::Result;
+use axum::{
+    Json,
+    extract::State,
+    http::StatusCode,
+    response::{IntoResponse, Response},
+};
+use serde::Serialize;
+use tokio::{task::spawn_blocking, time::timeout};
+use tracing::{instrument, warn};
+
+use crate::{
+    auth::msg::AuthMsg, checks::create_temp_file, db::msg::DbMsg, http::svc::HttpEndpoint,
+};
+
+// health checks
+//
+// the healthz endpoint is for orchestration probes, and so it sits outside of the auth
+// middleware.  it only ever reports which dependency failed and why, which should not
+// reveal anything beyond what the server logs to stdout anyway.
+
+// seconds before a dependency that hasn't answered is counted as down
+const HEALTH_TIMEOUT: u64 = 5;
+
+#[derive(Debug, Serialize)]
+#[serde(tag = "status", content = "error", rename_all = "lowercase")]
+enum HealthCheck {
+    Ok,
+    Failed(String),
+}
+
+#[derive(Debug, Serialize)]
+struct HealthReport {
+    db: HealthCheck,
+    auth: HealthCheck,
+    media_srvdir: HealthCheck,
+}
+
+impl HealthReport {
+    fn healthy(&self) -> bool {
+        [&self.db, &self.auth, &self.media_srvdir]
+            .iter()
+            .all(|check| matches!(check, HealthCheck::Ok))
+    }
+}
+
+async fn check<Fut: Future<Output = Result<()>>>(name: &str, fut: Fut) -> HealthCheck {
+    let result = match timeout(Duration::from_secs(HEALTH_TIMEOUT), fut).await {
+        Ok(result) => result,
+        Err(_) => Err(anyhow::Error::msg("timed out")),
+    };
+
+    match result {
+        Ok(()) => HealthCheck::Ok,
+        Err(err) => {
+            warn!("{name} health check failed: {err}");
+            HealthCheck::Failed(err.to_string())
+        }
+    }
+}
+
+// returns 200 if every dependency is up and 503 otherwise, with the breakdown either way
+#[instrument(skip_all)]
+pub(super) async fn healthz(State(state): State<Arc<HttpEndpoint>>) -> Response {
+    let db = check("db", async {
+        let (tx, rx) = tokio::sync::oneshot::channel();
+
+        state
+            .db_svc_sender
+            .send(DbMsg::Ping { resp: tx }.into())
+            .await?;
+
+        rx.await?
+    });
+
+    let auth = check("auth", async {
+        let (tx, rx) = tokio::sync::oneshot::channel();
+
+        state
+            .auth_svc_sender
+            .send(AuthMsg::Ping { resp: tx }.into())
+            .await?;
+
+        rx.await?
+    });
+
+    let media_srvdir = check("media_srvdir", async {
+        let media_srvdir = state.config.fs.media_srvdir.clone();
+
+        spawn_blocking(move || create_temp_file(&media_srvdir)).await?
+    });
+
+    let (db, auth, media_srvdir) = tokio::join!(db, auth, media_srvdir);
+
+    let report = HealthReport {
+        db,
+        auth,
+        media_srvdir,
+    };
+
+    let status = if report.healthy() {
+        StatusCode::OK
+    } else {
+        StatusCode::SERVICE_UNAVAILABLE
+    };
+
+    (status, Json(report)).into_response()
+}
@@ -0,0 +1,99 @@
+use std::{
+    net::{SocketAddr, SocketAddrV6},
+    sync::Arc,
+    time::Instant,
+};
+
+use anyhow::Result;
+use axum::{
+    Extension, Router,
+    extract::{MatchedPath, Request, State},
+    http::{StatusCode, header::CONTENT_TYPE},
+    middleware::Next,
+    response::{IntoResponse, Response},
+    routing::get,
+};
+use metrics::{counter, histogram};
+use tokio::{net::TcpListener, task::spawn};
+use tracing::{error, info, instrument};
+
+use crate::{
+    auth::check::AuthCheck,
+    http::{AppError, auth::CurrentUser, svc::HttpEndpoint},
+    metrics::render,
+};
+use api::HTTP_URL_ROOT;
+
+// prometheus's text exposition format
+const METRICS_CONTENT_TYPE: &str = "text/plain; version=0.0.4";
+
+// request metrics middleware
+//
+// this is added as a route layer so that the matched path is available, which keeps the
+// uuids in the media routes from becoming separate label values
+pub(super) async fn track_requests(request: Request, next: Next) -> Response {
+    let route = request
+        .extensions()
+        .get::<MatchedPath>()
+        .map(|path| path.as_str().to_owned())
+        .unwrap_or_else(|| "unmatched".to_owned());
+
+    let start = Instant::now();
+
+    let response = next.run(request).await;
+
+    counter!(
+        "entanglement_http_requests_total",
+        "route" => route.clone(),
+        "status" => response.status().as_u16().to_string()
+    )
+    .increment(1);
+
+    histogram!("entanglement_http_request_duration_seconds", "route" => route)
+        .record(start.elapsed());
+
+    response
+}
+
+fn metrics_response() -> Response {
+    match render() {
+        Some(body) => ([(CONTENT_TYPE, METRICS_CONTENT_TYPE)], body).into_response(),
+        None => StatusCode::NOT_FOUND.into_response(),
+    }
+}
+
+// metrics on the main socket, which are limited to the admin group
+#[instrument(skip_all)]
+pub(super) async fn get_metrics(
+    State(state): State<Arc<HttpEndpoint>>,
+    Extension(current_user): Extension<CurrentUser>,
+) -> Result<Response, AppError> {
+    if !state.is_admin(&current_user.uid).await? {
+        return Ok(StatusCode::UNAUTHORIZED.into_response());
+    }
+
+    Ok(metrics_response())
+}
+
+// metrics on their own socket, without tls or authentication
+#[instrument]
+pub(super) async fn serve_metrics(socket: String) -> Result<()> {
+    info!("starting metrics listener");
+
+    let socket = SocketAddr::from(socket.parse::<SocketAddrV6>()?);
+
+    let listener = TcpListener::bind(socket).await?;
+
+    let router = Router::new().route(
+        &format!("/{HTTP_URL_ROOT}/metrics"),
+        get(|| async { metrics_response() }),
+    );
+
+    spawn(async move {
+        if let Err(err) = axum::serve(listener, router).await {
+            error!("metrics listener failed: {err}");
+        }
+    });
+
+    Ok(())
+}
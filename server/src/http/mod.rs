@@ -5,11 +5,25 @@ use axum::{
 
 pub mod api;
 pub mod auth;
+pub mod feed;
+pub mod health;
+pub mod metrics;
 pub mod msg;
+pub mod openapi;
+pub mod ratelimit;
+pub mod relocate;
+pub mod search;
+pub mod share;
 pub mod stream;
 pub mod svc;
+#[cfg(all(test, feature = "sqlite"))]
+mod testing;
+pub mod upload;
 
 // copied verbatim from https://github.com/tokio-rs/axum/blob/main/examples/anyhow-error-response/src/main.rs
+//
+// Debug is only there so that the handler tests can unwrap() a response
+#[derive(Debug)]
 struct AppError(anyhow::Error);
 
 impl IntoResponse for AppError {
@@ -1,4 +1,4 @@
-#[derive(Debug)]
+#[derive(Debug, strum::IntoStaticStr)]
 pub enum HttpMsg {
     _Status,
     // possible method to stop messages from piling up
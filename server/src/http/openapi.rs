@@ -0,0 +1,66 @@
+use axum::{
+    Json,
+    response::{IntoResponse, Response},
+};
+use tracing::instrument;
+use utoipa::OpenApi;
+
+use crate::http::{api, relocate, search};
+
+// openapi description
+//
+// the api endpoints are all POSTs of a json ...Req struct that answer with the matching
+// ...Resp struct (see the http_endpoint macro in api/lib.rs), which is easy enough to
+// follow from the webapp but opaque to anyone else.  the schemas are derived from the
+// api crate structs and the paths are annotated on the handlers themselves, so adding an
+// endpoint here only requires listing it below.
+//
+// so far, this covers the media, collection, library, and search endpoints.
+//
+// the server url must match the api nesting in svc.rs
+#[derive(OpenApi)]
+#[openapi(
+    info(title = "entanglement"),
+    servers((url = "/entanglement/api")),
+    paths(
+        api::get_media,
+        api::get_media_access,
+        api::update_media,
+        api::batch_update_media,
+        relocate::move_media,
+        api::search_media,
+        api::similar_media,
+        api::get_random_media,
+        api::delete_media,
+        api::restore_media,
+        api::purge_media,
+        api::search_trash,
+        api::rename_tag,
+        api::add_collection,
+        api::get_collection,
+        api::delete_collection,
+        api::update_collection,
+        api::set_collection_cover,
+        api::set_collection_parent,
+        api::get_collection_children,
+        api::add_media_to_collection,
+        api::rm_media_from_collection,
+        api::reorder_collection_media,
+        api::search_collections,
+        api::search_media_in_collection,
+        api::get_library,
+        api::update_library,
+        api::search_libraries,
+        api::search_libraries_detailed,
+        api::search_media_in_library,
+        api::count_hidden_in_library,
+        api::batch_search_and_sort,
+        search::stream_search_and_sort,
+    )
+)]
+pub(super) struct ApiDoc;
+
+#[instrument(skip_all)]
+pub(super) async fn get_openapi() -> Response {
+    Json(ApiDoc::openapi()).into_response()
+}
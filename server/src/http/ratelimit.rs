@@ -0,0 +1,267 @@
+use std::{
+    net::SocketAddr,
+    sync::Arc,
+    time::{Duration, Instant},
+};
+
+use axum::{
+    extract::{ConnectInfo, MatchedPath, Request, State},
+    http::{StatusCode, header::RETRY_AFTER},
+    middleware::Next,
+    response::{IntoResponse, Response},
+};
+use dashmap::DashMap;
+use metrics::counter;
+use tokio::{task::spawn, time::interval};
+use tracing::debug;
+
+use crate::http::auth::CurrentUser;
+use api::HTTP_URL_ROOT;
+use common::server::{RateLimit, RateLimitConfig};
+
+// rate limiting middleware
+//
+// each (route, client) pair has a token bucket that is created full on the first request and
+// refilled lazily whenever it is checked.  this is added as a route layer for the same reason as
+// track_requests(), and so that it runs after the auth middleware has found the user.
+//
+// clients without a user are keyed by their address, which is the reverse proxy's if there is
+// one.  those routes are all cheap, though, so a shared bucket only costs some headroom.
+
+// seconds between sweeps for buckets that have refilled, which are no different from new ones
+const PRUNE_INTERVAL: u64 = 60;
+
+// a client that keeps exactly to the rate refills one token between requests, give or take the
+// rounding of the spacing to whole nanoseconds, which would otherwise leave it just short
+const TOKEN_SLACK: f64 = 1e-6;
+
+struct Bucket {
+    tokens: f64,
+    updated: Instant,
+}
+
+pub(super) struct RateLimiter {
+    config: RateLimitConfig,
+    buckets: DashMap<(String, String), Bucket>,
+}
+
+impl RateLimiter {
+    pub(super) fn start(config: RateLimitConfig) -> Arc<Self> {
+        let limiter = Arc::new(RateLimiter {
+            config,
+            buckets: DashMap::new(),
+        });
+
+        spawn({
+            let limiter = limiter.clone();
+
+            async move {
+                let mut interval = interval(Duration::from_secs(PRUNE_INTERVAL));
+
+                loop {
+                    interval.tick().await;
+                    limiter.prune();
+                }
+            }
+        });
+
+        limiter
+    }
+
+    fn limit(&self, route: &str) -> RateLimit {
+        self.config
+            .routes
+            .get(route)
+            .copied()
+            .unwrap_or(self.config.default)
+    }
+
+    // takes a token from the bucket, or else returns the seconds until there is one
+    fn check(&self, route: String, client: String, now: Instant) -> Option<u64> {
+        let limit = self.limit(&route);
+        let burst = limit.burst as f64;
+
+        let mut bucket = self
+            .buckets
+            .entry((route, client))
+            .or_insert_with(|| Bucket {
+                tokens: burst,
+                updated: now,
+            });
+
+        let elapsed = now.duration_since(bucket.updated).as_secs_f64();
+
+        bucket.tokens = (bucket.tokens + elapsed * limit.rate).min(burst);
+        bucket.updated = now;
+
+        if bucket.tokens >= 1.0 - TOKEN_SLACK {
+            bucket.tokens = (bucket.tokens - 1.0).max(0.0);
+            return None;
+        }
+
+        Some(((1.0 - bucket.tokens) / limit.rate).ceil().max(1.0) as u64)
+    }
+
+    fn prune(&self) {
+        let now = Instant::now();
+
+        self.buckets.retain(|(route, _), bucket| {
+            let limit = self.limit(route);
+            let elapsed = now.duration_since(bucket.updated).as_secs_f64();
+
+            bucket.tokens + elapsed * limit.rate < limit.burst as f64
+        });
+    }
+}
+
+pub(super) async fn rate_limit(
+    State(limiter): State<Arc<RateLimiter>>,
+    request: Request,
+    next: Next,
+) -> Response {
+    let route = request
+        .extensions()
+        .get::<MatchedPath>()
+        .map(|path| path.as_str())
+        .unwrap_or("unmatched");
+
+    let route = route
+        .strip_prefix(&format!("/{HTTP_URL_ROOT}/"))
+        .unwrap_or(route)
+        .to_owned();
+
+    let client = match request.extensions().get::<CurrentUser>() {
+        Some(user) => format!("user:{}", user.uid),
+        None => match request.extensions().get::<ConnectInfo<SocketAddr>>() {
+            Some(ConnectInfo(addr)) => format!("addr:{}", addr.ip()),
+            None => "unknown".to_owned(),
+        },
+    };
+
+    if let Some(retry_after) = limiter.check(route.clone(), client.clone(), Instant::now()) {
+        debug!({ route, client, retry_after }, "rate limit exceeded");
+
+        counter!("entanglement_http_rate_limited_total", "route" => route).increment(1);
+
+        return (
+            StatusCode::TOO_MANY_REQUESTS,
+            [(RETRY_AFTER, retry_after.to_string())],
+            "rate limit exceeded",
+        )
+            .into_response();
+    }
+
+    next.run(request).await
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::HashMap;
+
+    use axum::{Router, body::Body, middleware, routing::get};
+    use tower::ServiceExt;
+
+    use super::*;
+
+    fn router(rate: f64, burst: u64) -> Router {
+        let limiter = RateLimiter::start(RateLimitConfig {
+            default: RateLimit { rate, burst },
+            routes: HashMap::new(),
+        });
+
+        Router::new()
+            .route(
+                &format!("/{HTTP_URL_ROOT}/api/Test"),
+                get(|| async { "ok" }),
+            )
+            .route_layer(middleware::from_fn_with_state(limiter, rate_limit))
+    }
+
+    async fn status(router: &Router) -> (StatusCode, Option<String>) {
+        let response = router
+            .clone()
+            .oneshot(
+                Request::get(format!("/{HTTP_URL_ROOT}/api/Test"))
+                    .body(Body::empty())
+                    .unwrap(),
+            )
+            .await
+            .unwrap();
+
+        let retry_after = response
+            .headers()
+            .get(RETRY_AFTER)
+            .map(|val| val.to_str().unwrap().to_owned());
+
+        (response.status(), retry_after)
+    }
+
+    #[tokio::test]
+    async fn rejects_requests_beyond_the_burst() {
+        let router = router(0.1, 3);
+
+        for _ in 0..3 {
+            assert_eq!(status(&router).await, (StatusCode::OK, None));
+        }
+
+        // an empty bucket needs ten seconds for the next token
+        assert_eq!(
+            status(&router).await,
+            (StatusCode::TOO_MANY_REQUESTS, Some(String::from("10")))
+        );
+    }
+
+    #[tokio::test]
+    async fn limits_each_route_separately() {
+        let limiter = RateLimiter::start(RateLimitConfig {
+            default: RateLimit {
+                rate: 0.1,
+                burst: 1,
+            },
+            routes: HashMap::from([(
+                String::from("api/Other"),
+                RateLimit {
+                    rate: 0.1,
+                    burst: 2,
+                },
+            )]),
+        });
+
+        let now = Instant::now();
+        let check = |route: &str| limiter.check(route.to_owned(), String::from("user:alice"), now);
+
+        assert_eq!(check("api/Test"), None);
+        assert!(check("api/Test").is_some());
+
+        assert_eq!(check("api/Other"), None);
+        assert_eq!(check("api/Other"), None);
+        assert!(check("api/Other").is_some());
+    }
+
+    #[tokio::test]
+    async fn allows_steady_traffic_at_the_rate() {
+        for rate in [1.0, 3.0, 10.0, 0.7] {
+            let limiter = RateLimiter::start(RateLimitConfig {
+                default: RateLimit { rate, burst: 1 },
+                routes: HashMap::new(),
+            });
+
+            let start = Instant::now();
+            let spacing = Duration::from_secs_f64(1.0 / rate);
+            let check =
+                |at| limiter.check(String::from("api/Test"), String::from("user:alice"), at);
+
+            // with no burst to spare, every request needs the token refilled since the last
+            for n in 0..100 {
+                assert_eq!(
+                    check(start + spacing * n),
+                    None,
+                    "request {n} at rate {rate}"
+                );
+            }
+
+            // while one that comes early does not
+            assert!(check(start + spacing * 99 + spacing / 2).is_some());
+        }
+    }
+}
@@ -0,0 +1,318 @@
+use std::{
+    path::{Path, PathBuf},
+    sync::Arc,
+};
+
+use anyhow::Result;
+use axum::{
+    Json,
+    extract::{Extension, State},
+    http::StatusCode,
+    response::{IntoResponse, Response},
+};
+use tokio::fs::{canonicalize, copy, create_dir_all, remove_file, rename, try_exists};
+use tracing::{debug, instrument, warn};
+
+use crate::{
+    auth::check::AuthCheck,
+    db::msg::DbMsg,
+    http::{AppError, auth::CurrentUser, svc::HttpEndpoint},
+};
+use api::{
+    library::LibraryUuid,
+    media::{MoveMediaReq, MoveMediaResp},
+};
+
+// moving media between libraries
+//
+// libraries are directories, so reassigning a media record also means moving its file.
+// otherwise, the old library's next scan would find the file again, and the new library's
+// scans would never see it at all.  the file keeps its path relative to the library root,
+// and the move is refused if something is already there.
+//
+// the library decides which groups can see the media, so the access cache has to be
+// cleared once the record is updated.
+#[utoipa::path(
+    post,
+    path = "/MoveMedia",
+    tag = "media",
+    request_body = MoveMediaReq,
+    responses(
+        (status = 200, body = MoveMediaResp),
+        (status = 401, description = "not authorized")
+    )
+)]
+#[instrument(skip_all)]
+pub(super) async fn move_media(
+    State(state): State<Arc<HttpEndpoint>>,
+    Extension(current_user): Extension<CurrentUser>,
+    Json(message): Json<MoveMediaReq>,
+) -> Result<Response, AppError> {
+    let (tx, rx) = tokio::sync::oneshot::channel();
+
+    state
+        .db_svc_sender
+        .send(
+            DbMsg::GetMedia {
+                resp: tx,
+                media_uuid: message.media_uuid,
+            }
+            .into(),
+        )
+        .await?;
+
+    let media = rx
+        .await??
+        .ok_or_else(|| anyhow::Error::msg("unknown media_uuid"))?
+        .0;
+
+    if !state
+        .owns_library(&current_user.uid, &media.library_uuid)
+        .await?
+        || !state
+            .owns_library(&current_user.uid, &message.library_uuid)
+            .await?
+    {
+        return Ok(StatusCode::UNAUTHORIZED.into_response());
+    }
+
+    if media.library_uuid == message.library_uuid {
+        return Ok(Json(MoveMediaResp {}).into_response());
+    }
+
+    let source = PathBuf::from(&media.path);
+
+    let relative = source
+        .strip_prefix(library_root(&state, media.library_uuid).await?)
+        .map_err(|_| anyhow::Error::msg("media is outside of its library directory"))?
+        .to_path_buf();
+
+    let destination = library_root(&state, message.library_uuid)
+        .await?
+        .join(relative);
+
+    if try_exists(&destination).await? {
+        return Ok((StatusCode::CONFLICT, "file already exists in library").into_response());
+    }
+
+    let path = destination
+        .to_str()
+        .ok_or_else(|| anyhow::Error::msg("failed to convert destination path to str"))?
+        .to_owned();
+
+    debug!({ media_uuid = %message.media_uuid, path }, "moving media file");
+
+    if let Some(parent) = destination.parent() {
+        create_dir_all(parent).await?;
+    }
+
+    move_file(&source, &destination).await?;
+
+    let result = async {
+        let (tx, rx) = tokio::sync::oneshot::channel();
+
+        state
+            .db_svc_sender
+            .send(
+                DbMsg::MoveMedia {
+                    resp: tx,
+                    media_uuid: message.media_uuid,
+                    library_uuid: message.library_uuid,
+                    path: Some(path),
+                }
+                .into(),
+            )
+            .await?;
+
+        rx.await?
+    }
+    .await;
+
+    // put the file back so that the record still points at it
+    if let Err(err) = result {
+        if let Err(err) = move_file(&destination, &source).await {
+            warn!("failed to restore media file after a failed move: {err}");
+        }
+
+        return Err(err.into());
+    }
+
+    state
+        .storage
+        .put_original(message.media_uuid, &destination)
+        .await?;
+
+    state
+        .clear_access_cache(Vec::from(&[message.media_uuid]))
+        .await?;
+
+    Ok(Json(MoveMediaResp {}).into_response())
+}
+
+// the scanner records canonicalized paths, so the library root has to match
+async fn library_root(state: &HttpEndpoint, library_uuid: LibraryUuid) -> Result<PathBuf> {
+    let (tx, rx) = tokio::sync::oneshot::channel();
+
+    state
+        .db_svc_sender
+        .send(
+            DbMsg::GetLibrary {
+                resp: tx,
+                library_uuid,
+            }
+            .into(),
+        )
+        .await?;
+
+    let library = rx
+        .await??
+        .ok_or_else(|| anyhow::Error::msg("unknown library_uuid"))?;
+
+    // see task/scan.rs
+    if PathBuf::from(&library.path).is_absolute() {
+        return Err(anyhow::Error::msg("invalid absolute library path"));
+    }
+
+    Ok(canonicalize(state.config.fs.media_srcdir.join(library.path)).await?)
+}
+
+// libraries may be on different filesystems
+async fn move_file(from: &Path, to: &Path) -> Result<()> {
+    if rename(from, to).await.is_err() {
+        copy(from, to).await?;
+        remove_file(from).await?;
+    }
+
+    Ok(())
+}
+
+#[cfg(all(test, feature = "sqlite"))]
+mod tests {
+    use super::*;
+    use crate::http::testing::{
+        ADMIN_GID, ADMIN_UID, OTHER_UID, OWNER_GID, OWNER_UID, TestEndpoint, user,
+    };
+    use api::media::MediaUuid;
+
+    const DESTINATION_GID: &str = "destination";
+
+    // a media whose file is really in the first library, and a second library for it to
+    // move to, with the groups as given
+    async fn setup(
+        owners: &[&str],
+        destination_owners: &[&str],
+    ) -> (TestEndpoint, MediaUuid, LibraryUuid) {
+        let endpoint = TestEndpoint::new().await;
+
+        endpoint.set_groups(&[
+            (OWNER_GID, owners),
+            (DESTINATION_GID, destination_owners),
+            (ADMIN_GID, &[ADMIN_UID]),
+        ]);
+
+        let destination = endpoint.add_library("elsewhere", DESTINATION_GID).await;
+
+        let source = endpoint.library_dir().canonicalize().unwrap().join("a.jpg");
+        std::fs::write(&source, b"jpeg bytes").unwrap();
+
+        let media_uuid = endpoint
+            .add_media(source.to_str().unwrap(), b"jpeg bytes")
+            .await;
+
+        (endpoint, media_uuid, destination)
+    }
+
+    async fn move_to(
+        endpoint: &TestEndpoint,
+        uid: &str,
+        media_uuid: MediaUuid,
+        library_uuid: LibraryUuid,
+    ) -> StatusCode {
+        move_media(
+            State(endpoint.state.clone()),
+            user(uid),
+            Json(MoveMediaReq {
+                media_uuid,
+                library_uuid,
+            }),
+        )
+        .await
+        .unwrap()
+        .status()
+    }
+
+    #[tokio::test]
+    async fn moves_the_file_and_its_access() {
+        let (endpoint, media_uuid, destination) =
+            setup(&[OWNER_UID], &[OWNER_UID, OTHER_UID]).await;
+
+        let state = &endpoint.state;
+
+        assert!(
+            !state
+                .can_access_media(OTHER_UID, &media_uuid)
+                .await
+                .unwrap()
+        );
+
+        assert_eq!(
+            move_to(&endpoint, OWNER_UID, media_uuid, destination).await,
+            StatusCode::OK
+        );
+
+        // the destination's group can see it now...
+        assert!(
+            state
+                .can_access_media(OTHER_UID, &media_uuid)
+                .await
+                .unwrap()
+        );
+
+        // ...and the source's group can't
+        endpoint.set_groups(&[(OWNER_GID, &[OWNER_UID])]);
+        endpoint.clear_user_cache().await;
+
+        assert!(
+            !state
+                .can_access_media(OWNER_UID, &media_uuid)
+                .await
+                .unwrap()
+        );
+
+        let (media, _, _) = endpoint
+            .db(|resp| DbMsg::GetMedia { resp, media_uuid })
+            .await
+            .unwrap();
+
+        let moved = endpoint
+            .library_dir()
+            .with_file_name("elsewhere")
+            .join("a.jpg");
+
+        assert_eq!(media.library_uuid, destination);
+        assert_eq!(PathBuf::from(media.path), moved.canonicalize().unwrap());
+        assert!(!endpoint.library_dir().join("a.jpg").exists());
+    }
+
+    #[tokio::test]
+    async fn requires_owning_the_destination() {
+        let (endpoint, media_uuid, destination) = setup(&[OWNER_UID], &[OTHER_UID]).await;
+
+        assert_eq!(
+            move_to(&endpoint, OWNER_UID, media_uuid, destination).await,
+            StatusCode::UNAUTHORIZED
+        );
+        assert!(endpoint.library_dir().join("a.jpg").exists());
+    }
+
+    #[tokio::test]
+    async fn requires_owning_the_source() {
+        let (endpoint, media_uuid, destination) = setup(&[OWNER_UID], &[OTHER_UID]).await;
+
+        assert_eq!(
+            move_to(&endpoint, OTHER_UID, media_uuid, destination).await,
+            StatusCode::UNAUTHORIZED
+        );
+        assert!(endpoint.library_dir().join("a.jpg").exists());
+    }
+}
@@ -0,0 +1,301 @@
+use std::{collections::HashSet, sync::Arc};
+
+use anyhow::Result;
+use axum::{
+    body::{Body, Bytes},
+    extract::{Extension, Json, State},
+    http::{HeaderName, header::CONTENT_TYPE},
+    response::{IntoResponse, Response},
+};
+use futures::{StreamExt, stream};
+use tracing::{debug, error, instrument};
+
+use crate::{
+    auth::check::AuthCheck,
+    db::msg::DbMsg,
+    http::{AppError, auth::CurrentUser, svc::HttpEndpoint},
+};
+use api::{
+    media::MediaUuid,
+    search::{BatchSearchAndSortReq, SEARCH_TOTAL_HEADER, SearchRequest, SearchResponse},
+};
+
+// streaming search
+//
+// BatchSearchAndSort collects every SearchResponse before serializing any of them, so a
+// large library is held in memory (twice, briefly) and the client sees nothing until the
+// last GetMedia returns.  StreamSearchAndSort takes the same request, but writes each
+// SearchResponse as a line of json as soon as it has been fetched, with the total number
+// of matches in a header.
+//
+// the status and headers are sent before the first line, so an error partway through can
+// only be logged and leaves the client with a truncated response.  media that is deleted
+// between the search and its GetMedia is left out rather than ending the stream.
+#[utoipa::path(
+    post,
+    path = "/StreamSearchAndSort",
+    tag = "search",
+    request_body = BatchSearchAndSortReq,
+    responses(
+        (status = 200, content_type = "application/x-ndjson", body = SearchResponse,
+            headers(("x-entanglement-total" = u64, description = "number of matches")))
+    )
+)]
+#[instrument(skip_all)]
+pub(super) async fn stream_search_and_sort(
+    State(state): State<Arc<HttpEndpoint>>,
+    Extension(current_user): Extension<CurrentUser>,
+    Json(message): Json<BatchSearchAndSortReq>,
+) -> Result<Response, AppError> {
+    let gid = state.groups_for_user(&current_user.uid).await?;
+
+    let (media_uuids, total) = search_request(&state, gid, message.req).await?;
+
+    debug!({ count = media_uuids.len(), total }, "streaming search results");
+
+    let lines = stream::iter(media_uuids).filter_map(move |media_uuid| {
+        let state = state.clone();
+
+        async move {
+            match search_line(&state, media_uuid).await {
+                Ok(line) => line.map(Ok),
+                Err(err) => {
+                    error!({ %media_uuid }, "search stream failed: {err}");
+                    Some(Err(err))
+                }
+            }
+        }
+    });
+
+    Ok((
+        [
+            (CONTENT_TYPE, String::from("application/x-ndjson")),
+            (
+                HeaderName::from_static(SEARCH_TOTAL_HEADER),
+                total.to_string(),
+            ),
+        ],
+        Body::from_stream(lines),
+    )
+        .into_response())
+}
+
+async fn search_line(state: &HttpEndpoint, media_uuid: MediaUuid) -> Result<Option<Bytes>> {
+    let Some(response) = search_response(state, media_uuid).await? else {
+        return Ok(None);
+    };
+
+    let mut line = serde_json::to_vec(&response)?;
+    line.push(b'\n');
+
+    Ok(Some(Bytes::from(line)))
+}
+
+// the matching media uuids, in order, along with the number of matches ignoring limit and
+// offset.  auth is handled as part of the db searches.
+pub(super) async fn search_request(
+    state: &HttpEndpoint,
+    gid: HashSet<String>,
+    req: SearchRequest,
+) -> Result<(Vec<MediaUuid>, u64)> {
+    Ok(match req {
+        SearchRequest::Media(request) => {
+            let (tx, rx) = tokio::sync::oneshot::channel();
+
+            state
+                .db_svc_sender
+                .send(
+                    DbMsg::SearchMedia {
+                        resp: tx,
+                        gid,
+                        filter: request.filter,
+                        sort: request.sort,
+                        limit: request.limit,
+                        offset: request.offset,
+                    }
+                    .into(),
+                )
+                .await?;
+
+            rx.await??
+        }
+        SearchRequest::Collection(request) => {
+            let (tx, rx) = tokio::sync::oneshot::channel();
+
+            state
+                .db_svc_sender
+                .send(
+                    DbMsg::SearchMediaInCollection {
+                        resp: tx,
+                        gid,
+                        collection_uuid: request.collection_uuid,
+                        filter: request.filter,
+                        recursive: request.recursive,
+                        ordered: request.ordered,
+                    }
+                    .into(),
+                )
+                .await?;
+
+            let media_uuids = rx.await??;
+            let total = media_uuids.len() as u64;
+
+            (media_uuids, total)
+        }
+        SearchRequest::Library(request) => {
+            let (tx, rx) = tokio::sync::oneshot::channel();
+
+            state
+                .db_svc_sender
+                .send(
+                    DbMsg::SearchMediaInLibrary {
+                        resp: tx,
+                        gid,
+                        library_uuid: request.library_uuid,
+                        hidden: request.hidden,
+                        filter: request.filter,
+                    }
+                    .into(),
+                )
+                .await?;
+
+            let media_uuids = rx.await??;
+            let total = media_uuids.len() as u64;
+
+            (media_uuids, total)
+        }
+    })
+}
+
+// None if the media has been deleted since the search
+pub(super) async fn search_response(
+    state: &HttpEndpoint,
+    media_uuid: MediaUuid,
+) -> Result<Option<SearchResponse>> {
+    let (tx, rx) = tokio::sync::oneshot::channel();
+
+    state
+        .db_svc_sender
+        .send(
+            DbMsg::GetMedia {
+                resp: tx,
+                media_uuid,
+            }
+            .into(),
+        )
+        .await?;
+
+    Ok(rx
+        .await??
+        .map(|(media, collections, comments)| SearchResponse {
+            media_uuid,
+            media,
+            collections,
+            comments,
+        }))
+}
+
+#[cfg(all(test, feature = "sqlite"))]
+mod tests {
+    use axum::http::StatusCode;
+
+    use super::*;
+    use crate::http::testing::{OWNER_UID, TestEndpoint};
+    use api::{
+        media::SearchMediaReq,
+        search::SearchFilter,
+        sort::{SortMethod, SortOrder},
+    };
+
+    // the total from the header, along with the parsed lines of the body
+    async fn stream_search(
+        endpoint: &TestEndpoint,
+        limit: Option<u64>,
+    ) -> (u64, Vec<SearchResponse>) {
+        let response = stream_search_and_sort(
+            State(endpoint.state.clone()),
+            Extension(CurrentUser {
+                uid: String::from(OWNER_UID),
+            }),
+            Json(BatchSearchAndSortReq {
+                req: SearchRequest::Media(SearchMediaReq {
+                    filter: SearchFilter::default(),
+                    sort: SortOrder::PathAsc,
+                    limit,
+                    offset: None,
+                }),
+                sort: SortMethod::Path,
+            }),
+        )
+        .await
+        .unwrap();
+
+        assert_eq!(response.status(), StatusCode::OK);
+        assert_eq!(response.headers()[CONTENT_TYPE], "application/x-ndjson");
+
+        let total = response.headers()[SEARCH_TOTAL_HEADER]
+            .to_str()
+            .unwrap()
+            .parse()
+            .unwrap();
+
+        let body = axum::body::to_bytes(response.into_body(), usize::MAX)
+            .await
+            .unwrap();
+
+        // every line is terminated, including the last
+        assert!(body.is_empty() || body.ends_with(b"\n"));
+
+        let lines = std::str::from_utf8(&body)
+            .unwrap()
+            .lines()
+            .map(|line| serde_json::from_str(line).unwrap())
+            .collect();
+
+        (total, lines)
+    }
+
+    #[tokio::test]
+    async fn streams_one_line_per_match() {
+        let endpoint = TestEndpoint::new().await;
+
+        let mut media_uuids = Vec::new();
+        for path in ["a.jpg", "b.jpg", "c.jpg"] {
+            media_uuids.push(endpoint.add_media(path, b"jpeg bytes").await);
+        }
+
+        let (total, lines) = stream_search(&endpoint, None).await;
+
+        assert_eq!(lines.len() as u64, total);
+        assert_eq!(
+            lines.iter().map(|line| line.media_uuid).collect::<Vec<_>>(),
+            media_uuids
+        );
+        assert_eq!(lines[0].media.path, "a.jpg");
+    }
+
+    #[tokio::test]
+    async fn streams_nothing_without_matches() {
+        let endpoint = TestEndpoint::new().await;
+
+        let (total, lines) = stream_search(&endpoint, None).await;
+
+        assert_eq!(total, 0);
+        assert!(lines.is_empty());
+    }
+
+    // the header counts every match, so that a client paging through knows when to stop
+    #[tokio::test]
+    async fn total_ignores_the_limit() {
+        let endpoint = TestEndpoint::new().await;
+
+        for path in ["a.jpg", "b.jpg", "c.jpg"] {
+            endpoint.add_media(path, b"jpeg bytes").await;
+        }
+
+        let (total, lines) = stream_search(&endpoint, Some(2)).await;
+
+        assert_eq!(total, 3);
+        assert_eq!(lines.len(), 2);
+    }
+}
@@ -0,0 +1,323 @@
+use std::{
+    sync::Arc,
+    time::{SystemTime, UNIX_EPOCH},
+};
+
+use axum::{
+    Json,
+    extract::{Extension, Path, State},
+    http::{HeaderMap, StatusCode},
+    response::{IntoResponse, Response},
+};
+use tracing::{debug, instrument};
+
+use crate::{
+    auth::check::AuthCheck,
+    db::msg::DbMsg,
+    http::{
+        AppError,
+        auth::{CurrentUser, random_token},
+        stream::{stream_archive, stream_file},
+        svc::HttpEndpoint,
+    },
+};
+use api::{LINK_PATH, share::*};
+use common::auth::apikey::hash_api_key;
+
+// public share links
+//
+// a share link lets anyone holding its token view a single media or download a collection
+// without logging in.  the tokens are random in the same way as api keys, and are hashed
+// the same way before they reach the database.
+//
+// the link is resolved against the current state of its target every time, so hiding or
+// trashing the media (or removing it from the collection) takes it out of the share, even
+// though the link itself stays valid until it expires or is revoked.
+
+// only the owning groups can publish their media, since anyone can follow the link
+#[instrument(skip_all)]
+pub(super) async fn create_share_link(
+    State(state): State<Arc<HttpEndpoint>>,
+    Extension(current_user): Extension<CurrentUser>,
+    Json(message): Json<CreateShareLinkReq>,
+) -> Result<Response, AppError> {
+    let owns_target = match message.target {
+        ShareTarget::Media(media_uuid) => state.owns_media(&current_user.uid, &media_uuid).await?,
+        ShareTarget::Collection(collection_uuid) => {
+            state
+                .owns_collection(&current_user.uid, &collection_uuid)
+                .await?
+        }
+    };
+
+    if !owns_target {
+        return Ok(StatusCode::UNAUTHORIZED.into_response());
+    }
+
+    if message.expires_at <= SystemTime::now().duration_since(UNIX_EPOCH)?.as_secs() {
+        return Ok((
+            StatusCode::BAD_REQUEST,
+            "share link must expire in the future",
+        )
+            .into_response());
+    }
+
+    let token = random_token();
+
+    let (tx, rx) = tokio::sync::oneshot::channel();
+
+    state
+        .db_svc_sender
+        .send(
+            DbMsg::AddShareLink {
+                resp: tx,
+                uid: current_user.uid,
+                target: message.target,
+                token_hash: hash_api_key(&token),
+                expires_at: message.expires_at,
+            }
+            .into(),
+        )
+        .await?;
+
+    let share_uuid = rx.await??;
+
+    Ok(Json(CreateShareLinkResp { share_uuid, token }).into_response())
+}
+
+#[instrument(skip_all)]
+pub(super) async fn list_share_links(
+    State(state): State<Arc<HttpEndpoint>>,
+    Extension(current_user): Extension<CurrentUser>,
+    Json(_message): Json<ListShareLinksReq>,
+) -> Result<Response, AppError> {
+    let (tx, rx) = tokio::sync::oneshot::channel();
+
+    state
+        .db_svc_sender
+        .send(
+            DbMsg::GetShareLinks {
+                resp: tx,
+                uid: current_user.uid,
+            }
+            .into(),
+        )
+        .await?;
+
+    let links = rx.await??;
+
+    Ok(Json(ListShareLinksResp { links }).into_response())
+}
+
+#[instrument(skip_all)]
+pub(super) async fn revoke_share_link(
+    State(state): State<Arc<HttpEndpoint>>,
+    Extension(current_user): Extension<CurrentUser>,
+    Json(message): Json<RevokeShareLinkReq>,
+) -> Result<Response, AppError> {
+    let (tx, rx) = tokio::sync::oneshot::channel();
+
+    state
+        .db_svc_sender
+        .send(
+            DbMsg::DeleteShareLink {
+                resp: tx,
+                uid: current_user.uid,
+                share_uuid: message.share_uuid,
+            }
+            .into(),
+        )
+        .await?;
+
+    rx.await??;
+
+    Ok(Json(RevokeShareLinkResp {}).into_response())
+}
+
+// serve the target of a share link, which sits outside of the auth middleware
+//
+// media are streamed the same way as the originals route, and collections are sent as the
+// same archive as the collection download.  unknown, revoked, and expired tokens all look
+// alike, so that the response doesn't say whether a token ever existed.
+#[instrument(skip_all)]
+pub(super) async fn stream_share(
+    headers: HeaderMap,
+    State(state): State<Arc<HttpEndpoint>>,
+    Path(token): Path<String>,
+) -> Result<Response, AppError> {
+    let (tx, rx) = tokio::sync::oneshot::channel();
+
+    state
+        .db_svc_sender
+        .send(
+            DbMsg::GetShareLink {
+                resp: tx,
+                token_hash: hash_api_key(&token),
+            }
+            .into(),
+        )
+        .await?;
+
+    let Some(link) = rx.await?? else {
+        return Ok(StatusCode::NOT_FOUND.into_response());
+    };
+
+    if link.expires_at <= SystemTime::now().duration_since(UNIX_EPOCH)?.as_secs() {
+        debug!({ share_uuid = %link.share_uuid }, "share link expired");
+        return Ok(StatusCode::NOT_FOUND.into_response());
+    }
+
+    debug!({ share_uuid = %link.share_uuid }, "serving share link");
+
+    let (tx, rx) = tokio::sync::oneshot::channel();
+
+    state
+        .db_svc_sender
+        .send(
+            DbMsg::GetSharedMedia {
+                resp: tx,
+                target: link.target,
+            }
+            .into(),
+        )
+        .await?;
+
+    let media_uuids = rx.await??;
+
+    match link.target {
+        ShareTarget::Media(media_uuid) => {
+            // hidden or in the trash
+            if !media_uuids.contains(&media_uuid) {
+                return Ok(StatusCode::NOT_FOUND.into_response());
+            }
+
+            let media_uuid_str = media_uuid.to_string();
+
+            Ok(stream_file(&state, headers, LINK_PATH, &media_uuid_str, &media_uuid_str).await?)
+        }
+        ShareTarget::Collection(collection_uuid) => {
+            let (tx, rx) = tokio::sync::oneshot::channel();
+
+            state
+                .db_svc_sender
+                .send(
+                    DbMsg::GetCollection {
+                        resp: tx,
+                        collection_uuid,
+                    }
+                    .into(),
+                )
+                .await?;
+
+            let Some(collection) = rx.await?? else {
+                return Ok(StatusCode::NOT_FOUND.into_response());
+            };
+
+            Ok(stream_archive(&state, &collection.name, media_uuids).await?)
+        }
+    }
+}
+
+#[cfg(all(test, feature = "sqlite"))]
+mod tests {
+    use super::*;
+    use crate::http::testing::{OWNER_UID, TestEndpoint};
+    use api::media::{MediaUpdate, MediaUuid};
+
+    const TOKEN: &str = "share-token";
+
+    fn now() -> u64 {
+        SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .unwrap()
+            .as_secs()
+    }
+
+    async fn share(endpoint: &TestEndpoint, media_uuid: MediaUuid, expires_at: u64) {
+        endpoint
+            .db(|resp| DbMsg::AddShareLink {
+                resp,
+                uid: String::from(OWNER_UID),
+                target: ShareTarget::Media(media_uuid),
+                token_hash: hash_api_key(TOKEN),
+                expires_at,
+            })
+            .await;
+    }
+
+    async fn get(endpoint: &TestEndpoint, token: &str) -> Response {
+        stream_share(
+            HeaderMap::new(),
+            State(endpoint.state.clone()),
+            Path(token.to_owned()),
+        )
+        .await
+        .unwrap()
+    }
+
+    #[tokio::test]
+    async fn streams_media_before_expiry() {
+        let endpoint = TestEndpoint::new().await;
+        let media_uuid = endpoint.add_media("a.jpg", b"jpeg bytes").await;
+
+        share(&endpoint, media_uuid, now() + 3600).await;
+
+        let response = get(&endpoint, TOKEN).await;
+        assert_eq!(response.status(), StatusCode::OK);
+
+        let body = axum::body::to_bytes(response.into_body(), usize::MAX)
+            .await
+            .unwrap();
+        assert_eq!(&body[..], b"jpeg bytes");
+
+        assert_eq!(
+            get(&endpoint, "some-other-token").await.status(),
+            StatusCode::NOT_FOUND
+        );
+    }
+
+    #[tokio::test]
+    async fn denies_after_expiry() {
+        let endpoint = TestEndpoint::new().await;
+        let media_uuid = endpoint.add_media("a.jpg", b"jpeg bytes").await;
+
+        share(&endpoint, media_uuid, now() - 1).await;
+
+        assert_eq!(get(&endpoint, TOKEN).await.status(), StatusCode::NOT_FOUND);
+    }
+
+    #[tokio::test]
+    async fn denies_hidden_media() {
+        let endpoint = TestEndpoint::new().await;
+        let media_uuid = endpoint.add_media("a.jpg", b"jpeg bytes").await;
+
+        share(&endpoint, media_uuid, now() + 3600).await;
+
+        endpoint
+            .db(|resp| DbMsg::UpdateMedia {
+                resp,
+                media_uuid,
+                update: MediaUpdate {
+                    hidden: Some(true),
+                    ..Default::default()
+                },
+            })
+            .await;
+
+        assert_eq!(get(&endpoint, TOKEN).await.status(), StatusCode::NOT_FOUND);
+    }
+
+    #[tokio::test]
+    async fn denies_trashed_media() {
+        let endpoint = TestEndpoint::new().await;
+        let media_uuid = endpoint.add_media("a.jpg", b"jpeg bytes").await;
+
+        share(&endpoint, media_uuid, now() + 3600).await;
+
+        endpoint
+            .db(|resp| DbMsg::SoftDeleteMedia { resp, media_uuid })
+            .await;
+
+        assert_eq!(get(&endpoint, TOKEN).await.status(), StatusCode::NOT_FOUND);
+    }
+}
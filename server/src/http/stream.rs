@@ -1,52 +1,76 @@
 use std::{
-    io::{ErrorKind, SeekFrom},
+    collections::HashSet,
+    io::ErrorKind,
+    path::{Path as FsPath, PathBuf},
     sync::Arc,
 };
 
 use anyhow::Result;
+use async_zip::{Compression, ZipEntryBuilder, tokio::write::ZipFileWriter};
 use axum::{
     body::Body,
-    extract::{Extension, Path, State},
+    extract::{Extension, Path, Query, State},
     http::StatusCode,
     response::{IntoResponse, Response},
 };
+use futures::AsyncWriteExt;
 use http::{
     HeaderMap, HeaderValue,
-    header::{ACCEPT_RANGES, CONTENT_LENGTH, CONTENT_RANGE, CONTENT_TYPE, RANGE},
+    header::{
+        ACCEPT_RANGES, CONTENT_DISPOSITION, CONTENT_LENGTH, CONTENT_RANGE, CONTENT_TYPE, RANGE,
+    },
 };
-use mime_guess::MimeGuess;
+use regex::Regex;
+use serde::Deserialize;
 use tokio::{
-    fs::{File, read_link},
-    io::AsyncSeekExt,
+    fs::{create_dir_all, remove_dir_all},
+    io::{AsyncReadExt, DuplexStream, duplex},
+};
+use tokio_util::{
+    codec::{BytesCodec, FramedRead},
+    io::ReaderStream,
 };
-use tokio_stream::StreamExt;
-use tokio_util::codec::{BytesCodec, FramedRead};
 use tracing::{debug, error, instrument, warn};
 
 use crate::{
     auth::check::AuthCheck,
+    db::msg::DbMsg,
+    fs::store_thumbnail,
     http::{AppError, auth::CurrentUser, svc::HttpEndpoint},
 };
-use api::{LINK_PATH, SLICE_PATH, THUMBNAIL_PATH, UuidSource, media::MediaUuid};
+use api::{
+    LINK_PATH, SLICE_PATH, THUMBNAIL_PATH, UuidSource,
+    collection::CollectionUuid,
+    media::{MediaUuid, ThumbnailSize},
+    search::SearchFilter,
+};
+use common::storage::thumbnail_name;
 
 // media stream/download
 //
 // this is the core media downloading function through which all media accesses happen
 //
 // as such, it has to enforce the authorization model, but it also has to include all
-// of the http streaming logic (range, mime, etc) that depends on the filesystem
+// of the http streaming logic (range, mime, etc) on top of the storage backend
 const READ_BUF_SIZE: usize = 1024 * 1024;
 
 struct StreamUuidParser;
 
 impl UuidSource for StreamUuidParser {}
 
+#[derive(Debug, Deserialize)]
+pub(super) struct StreamQuery {
+    // only used for thumbnails
+    size: Option<ThumbnailSize>,
+}
+
 #[instrument(skip_all)]
 pub(super) async fn stream_media(
     headers: HeaderMap,
     State(state): State<Arc<HttpEndpoint>>,
     Extension(current_user): Extension<CurrentUser>,
     Path((dir, media_uuid_str)): Path<(String, String)>,
+    Query(query): Query<StreamQuery>,
 ) -> Result<Response, AppError> {
     debug!({ dir, media_uuid_str }, "serving media");
 
@@ -78,36 +102,63 @@ pub(super) async fn stream_media(
         return Ok(StatusCode::BAD_REQUEST.into_response());
     }
 
-    let mut filename = state.config.fs.media_srvdir.join(&dir);
+    let name = if dir == THUMBNAIL_PATH {
+        let size = query.size.unwrap_or_default();
 
-    filename.push(&media_uuid_str);
+        let name = thumbnail_name(media_uuid, size);
 
-    // here and below we use tokio logic to handle the filesystem operations
-    // so that we don't block the server threads
-    let mut file_handle = match File::open(&filename).await {
-        Ok(f) => f,
-        Err(err) => {
-            match err.kind() {
-                ErrorKind::NotFound => debug!({ dir, media_uuid_str }, "media not found: {err}"),
+        // thumbnails other than the grid size are only created when first requested
+        if state.storage.info(THUMBNAIL_PATH, &name).await?.is_none()
+            && let Err(err) = state.generate_thumbnail(media_uuid, size).await
+        {
+            warn!({ dir, media_uuid_str }, "failed to create thumbnail: {err}");
+            return Ok((StatusCode::NOT_FOUND, err.to_string()).into_response());
+        }
 
-                ErrorKind::PermissionDenied => {
+        name
+    } else {
+        media_uuid_str.clone()
+    };
+
+    Ok(stream_file(&state, headers, &dir, &media_uuid_str, &name).await?)
+}
+
+// the http streaming logic for a single object in storage
+//
+// callers are responsible for the authorization checks, since this only ever sees the
+// object name.  the mime type always comes from the original, so media_uuid_str must be
+// a valid name under LINK_PATH.
+pub(super) async fn stream_file(
+    state: &Arc<HttpEndpoint>,
+    headers: HeaderMap,
+    dir: &str,
+    media_uuid_str: &str,
+    name: &str,
+) -> Result<Response> {
+    let info = match state.storage.info(dir, name).await {
+        Ok(Some(v)) => v,
+        Ok(None) => {
+            debug!({ dir, media_uuid_str }, "media not found");
+            return Ok(StatusCode::NOT_FOUND.into_response());
+        }
+        Err(err) => {
+            match err.downcast_ref::<std::io::Error>().map(|err| err.kind()) {
+                Some(ErrorKind::PermissionDenied) => {
                     error!({ dir, media_uuid_str }, "permission denied: {err}")
                 }
 
-                ErrorKind::StaleNetworkFileHandle => {
+                Some(ErrorKind::StaleNetworkFileHandle) => {
                     error!({ dir, media_uuid_str }, "stale NFS handle: {err}")
                 }
 
-                _ => warn!({ dir, media_uuid_str }, "io error: {err}"),
+                _ => warn!({ dir, media_uuid_str }, "storage error: {err}"),
             }
 
             return Ok((StatusCode::NOT_FOUND, err.to_string()).into_response());
         }
     };
 
-    let file_metadata = file_handle.metadata().await?;
-
-    let length = file_metadata.len();
+    let length = info.length;
 
     // range header check
     //
@@ -117,19 +168,25 @@ pub(super) async fn stream_media(
     // verification header back to the client
     //
     // without this logic, browsers will have to buffer the whole file before they can seek.
-    let (partial, (start, end)) = match headers.get(RANGE) {
+    let range = match headers.get(RANGE).and_then(|val| val.to_str().ok()) {
+        None => None,
+        Some(val) => match parse_ranges(&state.range_regex, val, length) {
+            Ok(v) => v,
+            Err(err) => {
+                // the client needs the length to ask again for something sensible
+                return Ok((
+                    StatusCode::RANGE_NOT_SATISFIABLE,
+                    [(CONTENT_RANGE, format!("bytes */{length}"))],
+                    format!("{err}"),
+                )
+                    .into_response());
+            }
+        },
+    };
+
+    let (partial, (start, end)) = match range {
+        Some(v) => (true, v),
         None => (false, (0, length)),
-        Some(val) => (
-            true,
-            match parse_ranges(&state, val.to_str()?, length) {
-                Ok(v) => v,
-                Err(err) => {
-                    return Ok(
-                        (StatusCode::RANGE_NOT_SATISFIABLE, format!("{err}")).into_response()
-                    );
-                }
-            },
-        ),
     };
 
     debug!({ dir, media_uuid_str }, "streaming {} bytes", end - start);
@@ -155,23 +212,20 @@ pub(super) async fn stream_media(
         );
     }
 
-    // follow the symlnk to (maybe) fetch the mime type of the media based on the
-    // file extention of the original
-    match MimeGuess::from_path(
-        read_link(
-            state
-                .config
-                .fs
-                .media_srvdir
-                .join(LINK_PATH)
-                .join(&media_uuid_str),
-        )
-        .await?,
-    )
-    .first()
-    {
+    // the storage backend (maybe) knows the mime type of the original, based on its
+    // file extension
+    let content_type = match dir {
+        LINK_PATH => info.content_type,
+        _ => state
+            .storage
+            .info(LINK_PATH, media_uuid_str)
+            .await?
+            .and_then(|v| v.content_type),
+    };
+
+    match content_type {
         Some(mime) => {
-            headers.insert(CONTENT_TYPE, HeaderValue::from_str(mime.essence_str())?);
+            headers.insert(CONTENT_TYPE, HeaderValue::from_str(&mime)?);
         }
         None => {
             warn!({ dir, media_uuid_str }, "failed to guess mime type")
@@ -188,34 +242,23 @@ pub(super) async fn stream_media(
 
     // http response body
     //
-    // starting with the file handle, we first use tokio's AsyncRead to create a FramedRead,
-    // which is an adapter for AsyncRead -> Stream that uses a codec to define how much of
-    // the underlying structure is returned on each call to the Stream's poll_next().
+    // starting with the reader from the storage backend, which already covers only the
+    // requested range, we use a FramedRead as an adapter for AsyncRead -> Stream that uses
+    // a codec to define how much of the underlying structure is returned on each call to
+    // the Stream's poll_next().
     //
     // in this case, we want a Byte each time, so the codec is very simple.  the Stream is
     // then fed into axum's built-in streaming body logic.
-    let body = if partial {
-        // if we want the bytes from partway into the file, we need to first move the Seek
-        // pointer to the starting byte
-        file_handle
-            .seek(SeekFrom::Current(start.try_into()?))
-            .await?;
-
-        // then, we create a new stream that only has (end - start) bytes and use that
-        //
-        // note the argument to take() is one-indexed; see below for semantics
-        Body::from_stream(
-            FramedRead::with_capacity(file_handle, BytesCodec::new(), READ_BUF_SIZE)
-                .take((end - start).try_into()?),
-        )
-    } else {
-        // for normal reads, just consume the whole file
-        Body::from_stream(FramedRead::with_capacity(
-            file_handle,
-            BytesCodec::new(),
-            READ_BUF_SIZE,
-        ))
-    };
+    let reader = state
+        .storage
+        .get(dir, name, partial.then_some((start, end)))
+        .await?;
+
+    let body = Body::from_stream(FramedRead::with_capacity(
+        reader,
+        BytesCodec::new(),
+        READ_BUF_SIZE,
+    ));
 
     // http response status code
     //
@@ -230,82 +273,361 @@ pub(super) async fn stream_media(
     Ok((code, headers, body).into_response())
 }
 
+// collection download
+//
+// the archive is written into one end of an in-memory pipe while the body streams out
+// of the other, so only READ_BUF_SIZE of it is ever held at once.  the entries are
+// stored rather than compressed, since nearly all media formats are compressed already.
+//
+// once the headers are sent there is no way to report an error, so a failure partway
+// through is logged and the client is left with a truncated archive.
+#[instrument(skip_all)]
+pub(super) async fn stream_collection(
+    State(state): State<Arc<HttpEndpoint>>,
+    Extension(current_user): Extension<CurrentUser>,
+    Path(archive): Path<String>,
+) -> Result<Response, AppError> {
+    debug!({ archive }, "serving collection archive");
+
+    let collection_uuid = match archive
+        .strip_suffix(".zip")
+        .map(|v| CollectionUuid::try_parse(&StreamUuidParser, v))
+    {
+        Some(Ok(v)) => v,
+        _ => return Ok(StatusCode::BAD_REQUEST.into_response()),
+    };
+
+    if !state
+        .can_access_collection(&current_user.uid, &collection_uuid)
+        .await?
+    {
+        return Ok(StatusCode::UNAUTHORIZED.into_response());
+    }
+
+    let (tx, rx) = tokio::sync::oneshot::channel();
+
+    state
+        .db_svc_sender
+        .send(
+            DbMsg::GetCollection {
+                resp: tx,
+                collection_uuid,
+            }
+            .into(),
+        )
+        .await?;
+
+    let collection = rx
+        .await??
+        .ok_or_else(|| anyhow::Error::msg("unknown collection_uuid"))?;
+
+    // the collection search leaves out hidden media
+    let gid = state.groups_for_user(&current_user.uid).await?;
+
+    let (tx, rx) = tokio::sync::oneshot::channel();
+
+    state
+        .db_svc_sender
+        .send(
+            DbMsg::SearchMediaInCollection {
+                resp: tx,
+                gid,
+                collection_uuid,
+                filter: SearchFilter::default(),
+                recursive: false,
+                ordered: true,
+            }
+            .into(),
+        )
+        .await?;
+
+    let media_uuids = rx.await??;
+
+    Ok(stream_archive(&state, &collection.name, media_uuids).await?)
+}
+
+// the zip streaming logic for a collection download, named after the collection
+//
+// as with stream_file(), callers are responsible for the authorization checks, which
+// includes leaving out any hidden media
+pub(super) async fn stream_archive(
+    state: &Arc<HttpEndpoint>,
+    name: &str,
+    media_uuids: Vec<MediaUuid>,
+) -> Result<Response> {
+    // quotes and backslashes would end the filename early
+    let name = name.replace(['"', '\\'], "_");
+
+    let (writer, reader) = duplex(READ_BUF_SIZE);
+
+    tokio::spawn({
+        let state = state.clone();
+        let name = name.clone();
+
+        async move {
+            if let Err(err) = state.write_archive(writer, media_uuids).await {
+                error!({ name }, "failed to write collection archive: {err}");
+            }
+        }
+    });
+
+    let mut headers = HeaderMap::new();
+
+    headers.insert(CONTENT_TYPE, HeaderValue::from_static("application/zip"));
+
+    headers.insert(
+        CONTENT_DISPOSITION,
+        HeaderValue::from_str(&format!("attachment; filename=\"{name}.zip\"")).unwrap_or_else(
+            |_| HeaderValue::from_static("attachment; filename=\"collection.zip\""),
+        ),
+    );
+
+    Ok((
+        StatusCode::OK,
+        headers,
+        Body::from_stream(ReaderStream::with_capacity(reader, READ_BUF_SIZE)),
+    )
+        .into_response())
+}
+
+impl HttpEndpoint {
+    #[instrument(skip_all)]
+    async fn write_archive(&self, writer: DuplexStream, media_uuids: Vec<MediaUuid>) -> Result<()> {
+        let mut zip = ZipFileWriter::with_tokio(writer);
+
+        let mut names = HashSet::new();
+        let mut buffer = vec![0; READ_BUF_SIZE];
+
+        for media_uuid in media_uuids {
+            let (tx, rx) = tokio::sync::oneshot::channel();
+
+            self.db_svc_sender
+                .send(
+                    DbMsg::GetMedia {
+                        resp: tx,
+                        media_uuid,
+                    }
+                    .into(),
+                )
+                .await?;
+
+            let (media, _, _) = rx
+                .await??
+                .ok_or_else(|| anyhow::Error::msg("unknown media_uuid"))?;
+
+            let name = archive_name(&mut names, &media.path, media_uuid);
+
+            debug!({ media_uuid = media_uuid.to_string(), name }, "adding media to archive");
+
+            let mut file = self
+                .storage
+                .get(LINK_PATH, &media_uuid.to_string(), None)
+                .await?;
+
+            let mut entry = zip
+                .write_entry_stream(ZipEntryBuilder::new(name.into(), Compression::Stored))
+                .await?;
+
+            loop {
+                let n = file.read(&mut buffer).await?;
+
+                if n == 0 {
+                    break;
+                }
+
+                entry.write_all(&buffer[..n]).await?;
+            }
+
+            entry.close().await?;
+        }
+
+        zip.close().await?;
+
+        Ok(())
+    }
+
+    // create a missing thumbnail
+    //
+    // the cache ensures that concurrent requests for the same thumbnail await the first
+    // one instead of each running the generation.  the entry is removed afterwards, since
+    // the file itself is the real cache and may be removed by the clean task.
+    #[instrument(skip(self))]
+    async fn generate_thumbnail(&self, media_uuid: MediaUuid, size: ThumbnailSize) -> Result<()> {
+        let key = (media_uuid, size);
+
+        let result = self
+            .thumbnail_cache
+            .perhaps(key, async {
+                debug!("creating thumbnail on demand");
+
+                let (tx, rx) = tokio::sync::oneshot::channel();
+
+                self.db_svc_sender
+                    .send(
+                        DbMsg::GetMedia {
+                            resp: tx,
+                            media_uuid,
+                        }
+                        .into(),
+                    )
+                    .await?;
+
+                let (media, _, _) = rx
+                    .await??
+                    .ok_or_else(|| anyhow::Error::msg("unknown media_uuid"))?;
+
+                // the thumbnail functions need the original's extension, which the link
+                // doesn't have
+                let original = PathBuf::from(&media.path);
+
+                let scratch_dir = self
+                    .config
+                    .task
+                    .scan_scratch
+                    .join(THUMBNAIL_PATH)
+                    .join(thumbnail_name(media_uuid, size));
+
+                create_dir_all(&scratch_dir).await?;
+
+                let result = store_thumbnail(
+                    self.storage.as_ref(),
+                    &original,
+                    &scratch_dir,
+                    &media.metadata,
+                    &self.config.task.raw_strategy.clone().unwrap_or_default(),
+                    media_uuid,
+                    size,
+                )
+                .await;
+
+                let _ = remove_dir_all(&scratch_dir).await;
+
+                result
+            })
+            .await;
+
+        self.thumbnail_cache.remove(&key);
+
+        result
+    }
+}
+
+// the file name of the original, with a numbered suffix if another entry already has it
+//
+// names are compared case-insensitively so that the archive also extracts cleanly on
+// windows and macos
+fn archive_name(names: &mut HashSet<String>, path: &str, media_uuid: MediaUuid) -> String {
+    let path = FsPath::new(path);
+
+    let stem = path
+        .file_stem()
+        .map(|v| v.to_string_lossy().into_owned())
+        .unwrap_or_else(|| media_uuid.to_string());
+
+    let extension = path
+        .extension()
+        .map(|v| format!(".{}", v.to_string_lossy()))
+        .unwrap_or_default();
+
+    let mut name = format!("{stem}{extension}");
+    let mut count = 1;
+
+    while !names.insert(name.to_lowercase()) {
+        name = format!("{stem}_{count}{extension}");
+        count += 1;
+    }
+
+    name
+}
+
+// the byte range in a range header.  the number of capture groups has to match the extract()
+// in parse_ranges(), or it will panic on every request.
+pub(super) const RANGE_PATTERN: &str = r"^\s*(\d*)-(\d*)\s*$";
+
 // http range header parser
 //
 // logic copied from https://github.com/dicej/tagger/blob/master/server/src/media.rs
 //
-// errors here should be reported by caller as StatusCode::RANGE_NOT_SATISFIABLE
+// returns None if the header should be ignored and the whole file sent, which is what
+// the spec asks for when the unit is unknown or the range set is malformed.  errors here
+// are ranges that can't be satisfied, and should be reported by caller as
+// StatusCode::RANGE_NOT_SATISFIABLE.
 //
-// if we enable multipart support, this will need to be adapted to produce a vec
-fn parse_ranges(state: &Arc<HttpEndpoint>, ranges: &str, length: u64) -> Result<(u64, u64)> {
+// requests for multiple ranges are also answered with the whole file.  browsers don't
+// send them when streaming, and the spec allows a server to ignore them rather than
+// build a multipart/byteranges response.
+fn parse_ranges(range_regex: &Regex, ranges: &str, length: u64) -> Result<Option<(u64, u64)>> {
     // there is only one supported unit, but the spec technically allows for others
-    if !ranges.starts_with("bytes=") {
-        return Err(anyhow::Error::msg("invalid range unit"));
+    let Some(ranges) = ranges.strip_prefix("bytes=") else {
+        return Ok(None);
+    };
+
+    let mut specs = ranges.split(',');
+
+    let spec = specs.next().unwrap_or_default();
+
+    if specs.next().is_some() {
+        debug!("ignoring request for multiple ranges");
+        return Ok(None);
     }
 
-    // even though we only support sending a single range back, we need to check to see if
-    // the client is expecting more.  the const generic for extract is the number of
-    // capture groups, and must match the regex (or the whole thing will panic)
-    let mut match_iter = state
-        .range_regex
-        .captures_iter(ranges)
-        .map(|c| c.extract::<2>())
-        .map(|(_, [s, e])| parse_endpoints(s, e));
-
-    let (start, end) = match match_iter.next() {
-        None => return Ok((0, length)),
-        Some(range) => {
-            let range = range?;
-
-            // the output (start, end) semantics are awkward
-            //
-            // start is used in seek(), where 0 indicates "before the first byte."
-            // it is zero-indexed.
-            //
-            // end is used to determine length, where 4 means "read the first four bytes."
-            // it is one-indexed.
-            //
-            // however, both s and e in the "s-e" pattern are zero-indexed, and thus the
-            // maximal value of e is length-1.
-            //
-            // the simplest method is to have (end - start) indicate the total length
-            // the stream to the one-indexed take() while ensuring that start() remains
-            // zero-indexed.  thus, end is about count and start is about position.
-            match range {
-                // "0-511" => get the first 512 bytes => (end - start) = 512
-                //
-                // in this pattern only, we need to read the eth byte, so the stopping point
-                // is one further than e itself (i.e. convert zero- to one-index)
-                (Some(s), Some(e)) => (s, e + 1),
-                // "512-" (for 1024b file) => get second 512 => (end - start) = 512
-                //
-                // the difference in indexes automatically picks up the missing byte from
-                // starting at the beginning of the sth byte
-                (Some(s), None) => (s, length),
-                // "-512" (for 1024b file) => get 512b leading to end => (end - start) = 512
-                //
-                // the difference in indexes is accounted for because e is going backwards
-                (None, Some(e)) => (length - e, length),
-                // if the range header is set but does not specify a by range, return the
-                // whole file to the client
-                (None, None) => (0, length),
-            }
-        }
+    // the const generic for extract is the number of capture groups, and must match the
+    // regex (or the whole thing will panic)
+    let Some((_, [s, e])) = range_regex.captures(spec).map(|c| c.extract::<2>()) else {
+        return Ok(None);
     };
 
-    // sanity checks -- note that u64 cannot be negative so we just need to assert order
-    // and that we will send something back
-    if start > length || end > length || start > end || end == 0 {
-        return Err(anyhow::Error::msg("invalid range"));
-    }
+    // only fails for numbers too large to be a real offset, which is as good as malformed
+    let Ok(range) = parse_endpoints(s, e) else {
+        return Ok(None);
+    };
 
-    // this avoids a collect() and a heap allocation of a Vec, but it's probably not a
-    // relevant cost compared to the rest of the system
-    if match_iter.next().is_some() {
-        return Err(anyhow::Error::msg("multiple ranges unsupported"));
+    // the output (start, end) semantics are awkward
+    //
+    // start is used in seek(), where 0 indicates "before the first byte."
+    // it is zero-indexed.
+    //
+    // end is used to determine length, where 4 means "read the first four bytes."
+    // it is one-indexed.
+    //
+    // however, both s and e in the "s-e" pattern are zero-indexed, and thus the
+    // maximal value of e is length-1.
+    //
+    // the simplest method is to have (end - start) indicate the total length
+    // the stream to the one-indexed take() while ensuring that start() remains
+    // zero-indexed.  thus, end is about count and start is about position.
+    let (start, end) = match range {
+        // "0-511" => get the first 512 bytes => (end - start) = 512
+        //
+        // in this pattern only, we need to read the eth byte, so the stopping point
+        // is one further than e itself (i.e. convert zero- to one-index).  an e past
+        // the end of the file just means "to the end."
+        (Some(s), Some(e)) if s <= e => (s, e.saturating_add(1).min(length)),
+        // "512-0" is not a range at all
+        (Some(_), Some(_)) => return Ok(None),
+        // "512-" (for 1024b file) => get second 512 => (end - start) = 512
+        //
+        // the difference in indexes automatically picks up the missing byte from
+        // starting at the beginning of the sth byte
+        (Some(s), None) => (s, length),
+        // "-512" (for 1024b file) => get 512b leading to end => (end - start) = 512
+        //
+        // the difference in indexes is accounted for because e is going backwards, and
+        // asking for more than the whole file just gets the whole file
+        (None, Some(e)) if e > 0 => (length.saturating_sub(e), length),
+        // "-0" asks for nothing, which can't be satisfied
+        (None, Some(_)) => return Err(anyhow::Error::msg("empty suffix range")),
+        // "-" doesn't specify a range
+        (None, None) => return Ok(None),
+    };
+
+    // a range has to start inside the file, which also rules out every range of an empty
+    // file, and end is already clamped to the length
+    if start >= length {
+        return Err(anyhow::Error::msg("range starts past the end of the file"));
     }
 
-    Ok((start, end))
+    Ok(Some((start, end)))
 }
 
 fn parse_endpoints(start: &str, end: &str) -> Result<(Option<u64>, Option<u64>)> {
@@ -320,3 +642,72 @@ fn parse_endpoints(start: &str, end: &str) -> Result<(Option<u64>, Option<u64>)>
 
     Ok((parse(start)?, parse(end)?))
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn parse(ranges: &str, length: u64) -> Result<Option<(u64, u64)>> {
+        parse_ranges(&Regex::new(RANGE_PATTERN).unwrap(), ranges, length)
+    }
+
+    #[test]
+    fn bounded_ranges() {
+        assert_eq!(parse("bytes=0-511", 1024).unwrap(), Some((0, 512)));
+        assert_eq!(parse("bytes=1023-1023", 1024).unwrap(), Some((1023, 1024)));
+        assert_eq!(parse("bytes= 10-19 ", 1024).unwrap(), Some((10, 20)));
+
+        // an end past the file is clamped to it
+        assert_eq!(parse("bytes=512-5000", 1024).unwrap(), Some((512, 1024)));
+    }
+
+    #[test]
+    fn open_ended_ranges() {
+        assert_eq!(parse("bytes=512-", 1024).unwrap(), Some((512, 1024)));
+        assert_eq!(parse("bytes=0-", 1024).unwrap(), Some((0, 1024)));
+    }
+
+    #[test]
+    fn suffix_ranges() {
+        assert_eq!(parse("bytes=-512", 1024).unwrap(), Some((512, 1024)));
+        assert_eq!(parse("bytes=-1", 1024).unwrap(), Some((1023, 1024)));
+
+        // more than the whole file is just the whole file
+        assert_eq!(parse("bytes=-5000", 1024).unwrap(), Some((0, 1024)));
+
+        // but an empty suffix can't be satisfied
+        assert!(parse("bytes=-0", 1024).is_err());
+    }
+
+    #[test]
+    fn ranges_past_the_end() {
+        assert!(parse("bytes=1024-", 1024).is_err());
+        assert!(parse("bytes=2000-3000", 1024).is_err());
+
+        // nothing at all fits in an empty file
+        assert!(parse("bytes=0-", 0).is_err());
+        assert!(parse("bytes=-10", 0).is_err());
+    }
+
+    #[test]
+    fn multiple_ranges_send_whole_file() {
+        assert_eq!(parse("bytes=0-1,5-6", 1024).unwrap(), None);
+        assert_eq!(parse("bytes=-1,0-0", 1024).unwrap(), None);
+    }
+
+    #[test]
+    fn malformed_ranges_send_whole_file() {
+        for ranges in [
+            "items=0-1",
+            "0-1",
+            "bytes=",
+            "bytes=-",
+            "bytes=abc",
+            "bytes=1-2-3",
+            "bytes=512-0",
+            "bytes=99999999999999999999999-",
+        ] {
+            assert_eq!(parse(ranges, 1024).unwrap(), None, "{ranges}");
+        }
+    }
+}
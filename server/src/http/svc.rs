@@ -1,6 +1,7 @@
 use std::{
     net::{SocketAddr, SocketAddrV6},
     path::PathBuf,
+    pin::pin,
     sync::Arc,
     time::Duration,
 };
@@ -10,15 +11,12 @@ use async_cell::sync::AsyncCell;
 use async_trait::async_trait;
 use axum::{
     Router,
-    extract::Request,
+    extract::{ConnectInfo, DefaultBodyLimit, Request},
     middleware,
     response::Redirect,
     routing::{get, post},
 };
-use futures::{
-    FutureExt, TryFutureExt,
-    stream::{FuturesUnordered, StreamExt},
-};
+use futures::TryFutureExt;
 use hyper::{
     body::Incoming, header::HeaderName, server::conn::http2::Builder, service::service_fn,
 };
@@ -36,6 +34,7 @@ use tokio::{
     time::timeout,
 };
 use tokio_rustls::TlsAcceptor;
+use tokio_util::{sync::CancellationToken, task::TaskTracker};
 use tower::Service;
 use tower_http::{
     services::{ServeDir, ServeFile},
@@ -45,13 +44,24 @@ use tracing::{debug, error, info, instrument, warn};
 use x509_certificate::X509Certificate;
 
 use crate::{
-    http::{api::*, auth::*, stream::*},
+    http::{
+        api::*, auth::*, feed::*, health::*, metrics::*, openapi::*, ratelimit::*, relocate::*,
+        search::*, share::*, stream::*, upload::*,
+    },
     service::{
         ESInner, ESMRegistry, EntanglementService, Esm, EsmReceiver, EsmSender, ServiceType,
+        serve_messages,
     },
 };
-use api::HTTP_URL_ROOT;
-use common::config::{AuthnBackend, ESConfig};
+use api::{
+    ARCHIVE_PATH, FEED_PATH, HTTP_URL_ROOT, SHARE_PATH,
+    media::{MediaUuid, ThumbnailSize},
+};
+use common::{
+    AwaitCache,
+    config::{AuthnBackend, ESConfig},
+    storage::{StorageBackend, create_storage},
+};
 
 // http service
 //
@@ -107,25 +117,7 @@ impl EntanglementService for HttpService {
         let hyper_handle = state.clone().serve_http(socket).await;
         self.hyper_handle.set(hyper_handle);
 
-        let msg_serve = {
-            async move {
-                let mut receiver = receiver.lock().await;
-
-                while let Some(msg) = receiver.recv().await {
-                    let state = Arc::clone(&state);
-                    spawn(async move {
-                        match state.message_handler(msg).await {
-                            Ok(()) => (),
-                            Err(err) => {
-                                error!({service = "http", channel = "esm", error = %err})
-                            }
-                        }
-                    });
-                }
-
-                Err(anyhow::Error::msg("http service esm channel disconnected"))
-            }
-        };
+        let msg_serve = serve_messages("http", receiver, state);
 
         self.msg_handle.set(spawn(msg_serve));
 
@@ -145,6 +137,10 @@ pub struct HttpEndpoint {
     pub(super) db_svc_sender: EsmSender,
     pub(super) task_svc_sender: EsmSender,
     pub(super) range_regex: Arc<Regex>,
+    pub(super) thumbnail_cache: Arc<AwaitCache<(MediaUuid, ThumbnailSize), ()>>,
+    pub(super) storage: Arc<dyn StorageBackend>,
+    shutdown: CancellationToken,
+    connections: TaskTracker,
 }
 
 #[async_trait]
@@ -158,9 +154,11 @@ impl ESInner for HttpEndpoint {
             auth_svc_sender: registry.get(&ServiceType::Auth).unwrap().clone(),
             db_svc_sender: registry.get(&ServiceType::Db).unwrap().clone(),
             task_svc_sender: registry.get(&ServiceType::Task).unwrap().clone(),
-            // changes in this regex have to be accompanied by changing the capture match
-            // settings in stream.rs, or it will panic on every invocation
-            range_regex: Arc::new(Regex::new(r"(\d*)-(\d*)")?),
+            range_regex: Arc::new(Regex::new(RANGE_PATTERN)?),
+            thumbnail_cache: Arc::new(AwaitCache::new("thumbnail")),
+            storage: create_storage(config.clone())?,
+            shutdown: CancellationToken::new(),
+            connections: TaskTracker::new(),
         })
     }
 
@@ -172,6 +170,21 @@ impl ESInner for HttpEndpoint {
     async fn message_handler(&self, _esm: Esm) -> Result<()> {
         Err(anyhow::Error::msg("not implemented"))
     }
+
+    // stop the listener and let the open connections finish the requests they already have
+    async fn shutdown(&self) -> Result<()> {
+        self.shutdown.cancel();
+        self.connections.close();
+
+        info!(
+            { connections = self.connections.len() },
+            "waiting for http connections to close"
+        );
+
+        self.connections.wait().await;
+
+        Ok(())
+    }
 }
 
 // http endpoint details
@@ -226,19 +239,53 @@ impl HttpEndpoint {
         ));
 
         // media -- streaming files to clients
-        let media_router = Router::new()
+        let mut media_router = Router::new()
             .route("/{dir}/{media_uuid}", get(stream_media))
+            .route(
+                &format!("/{ARCHIVE_PATH}/{{archive}}"),
+                get(stream_collection),
+            )
             .with_state(state.clone());
 
         // api -- the server's remote method calls
 
         // it would be nice to come up with a macro to automate some of this...
-        let api_router: Router<()> = Router::new()
+        let mut api_router: Router<()> = Router::new()
             .route("/GetUsersInGroup", post(get_users_in_group))
+            .route("/CreateApiKey", post(create_api_key))
+            .route("/ListApiKeys", post(list_api_keys))
+            .route("/RevokeApiKey", post(revoke_api_key))
+            .route("/CreateShareLink", post(create_share_link))
+            .route("/ListShareLinks", post(list_share_links))
+            .route("/RevokeShareLink", post(revoke_share_link))
             .route("/GetMedia", post(get_media))
+            .route("/GetMediaAccess", post(get_media_access))
+            .route(
+                "/UploadMedia",
+                post(upload_media).layer(DefaultBodyLimit::max(
+                    config.http.upload_limit.unwrap_or(DEFAULT_UPLOAD_LIMIT),
+                )),
+            )
             .route("/UpdateMedia", post(update_media))
+            .route("/BatchUpdateMedia", post(batch_update_media))
+            .route("/MoveMedia", post(move_media))
             .route("/SearchMedia", post(search_media))
             .route("/SimilarMedia", post(similar_media))
+            .route(
+                "/FindSimilarByUpload",
+                post(find_similar_by_upload).layer(DefaultBodyLimit::max(
+                    config.http.upload_limit.unwrap_or(DEFAULT_UPLOAD_LIMIT),
+                )),
+            )
+            .route("/GetRandomMedia", post(get_random_media))
+            .route("/GetStats", post(get_stats))
+            .route("/GetDateHistogram", post(get_date_histogram))
+            .route("/GetRecentActivity", post(get_recent_activity))
+            .route("/DeleteMedia", post(delete_media))
+            .route("/RestoreMedia", post(restore_media))
+            .route("/PurgeMedia", post(purge_media))
+            .route("/SearchTrash", post(search_trash))
+            .route("/RenameTag", post(rename_tag))
             .route("/AddComment", post(add_comment))
             .route("/GetComment", post(get_comment))
             .route("/DeleteComment", post(delete_comment))
@@ -247,19 +294,44 @@ impl HttpEndpoint {
             .route("/GetCollection", post(get_collection))
             .route("/DeleteCollection", post(delete_collection))
             .route("/UpdateCollection", post(update_collection))
+            .route("/SetCollectionCover", post(set_collection_cover))
+            .route("/SetCollectionParent", post(set_collection_parent))
+            .route("/GetCollectionChildren", post(get_collection_children))
             .route("/AddMediaToCollection", post(add_media_to_collection))
             .route("/RmMediaFromCollection", post(rm_media_from_collection))
+            .route("/ReorderCollectionMedia", post(reorder_collection_media))
             .route("/SearchCollections", post(search_collections))
             .route("/SearchMediaInCollection", post(search_media_in_collection))
             .route("/GetLibrary", post(get_library))
+            .route("/UpdateLibrary", post(update_library))
             .route("/SearchLibraries", post(search_libraries))
+            .route("/SearchLibrariesDetailed", post(search_libraries_detailed))
             .route("/SearchMediaInLibrary", post(search_media_in_library))
+            .route("/CountHiddenInLibrary", post(count_hidden_in_library))
             .route("/StartTask", post(start_task))
             .route("/StopTask", post(stop_task))
             .route("/ShowTasks", post(show_tasks))
             .route("/BatchSearchAndSort", post(batch_search_and_sort))
+            .route("/StreamSearchAndSort", post(stream_search_and_sort))
             .with_state(state.clone());
 
+        // rate limits -- per-client token buckets, added ahead of the metrics so that the
+        // rejected requests are still counted
+        let rate_limiter = config.http.rate_limit.clone().map(RateLimiter::start);
+
+        if let Some(limiter) = &rate_limiter {
+            media_router = media_router
+                .route_layer(middleware::from_fn_with_state(limiter.clone(), rate_limit));
+            api_router =
+                api_router.route_layer(middleware::from_fn_with_state(limiter.clone(), rate_limit));
+        }
+
+        // metrics -- request counters for the media and api routes
+        if config.http.metrics.is_some() {
+            media_router = media_router.route_layer(middleware::from_fn(track_requests));
+            api_router = api_router.route_layer(middleware::from_fn(track_requests));
+        }
+
         // combine the routes (note that this can panic if the routes overlap) and add any relevant
         // middleware from the rest of the http module.  these must match the defitions used in the
         // api crate endpoint macro, link functions, and Dioxus.toml
@@ -275,6 +347,35 @@ impl HttpEndpoint {
             .fallback(move || async move { Redirect::permanent(&format!("/{app_url_root}/app")) })
             .layer(TraceLayer::new_for_http());
 
+        // the metrics endpoint is either on its own socket or added here, ahead of the auth
+        // middleware so that it requires a user
+        if let Some(metrics_config) = config.http.metrics.clone() {
+            match metrics_config.socket {
+                Some(socket) => serve_metrics(socket)
+                    .await
+                    .expect("http server failed to start metrics listener"),
+                None => {
+                    router = router.route(
+                        &format!("/{HTTP_URL_ROOT}/metrics"),
+                        get(get_metrics).with_state(state.clone()),
+                    )
+                }
+            }
+        }
+
+        // the atom feed is also ahead of the auth middleware, since it is filtered by the user's
+        // groups like any other search
+        let mut feed_router = Router::new()
+            .route(&format!("/{HTTP_URL_ROOT}/{FEED_PATH}"), get(get_feed))
+            .with_state(state.clone());
+
+        if let Some(limiter) = &rate_limiter {
+            feed_router = feed_router
+                .route_layer(middleware::from_fn_with_state(limiter.clone(), rate_limit));
+        }
+
+        router = router.merge(feed_router);
+
         // auth middleware
         if config.authn_backend == AuthnBackend::ProxyHeader {
             let config = config
@@ -286,13 +387,59 @@ impl HttpEndpoint {
                 header_key: HeaderName::from_lowercase(config.header.to_lowercase().as_bytes())
                     .expect(""),
                 cn: config.proxy_cn,
+                db_svc_sender: state.db_svc_sender.clone(),
             };
 
             router = router.route_layer(middleware::from_fn_with_state(data, proxy_auth));
         } else if config.authn_backend == AuthnBackend::X509Cert {
             router = router.route_layer(middleware::from_fn(cert_auth));
+        } else if config.authn_backend == AuthnBackend::Oidc {
+            let data = Arc::new(
+                OidcState::new(config.clone(), state.db_svc_sender.clone())
+                    .expect("http server failed to create oidc client"),
+            );
+
+            router = router.route_layer(middleware::from_fn_with_state(data.clone(), oidc_auth));
+
+            // the login routes have to be reachable without a session, so they are added
+            // after the middleware
+            let mut auth_router = Router::new()
+                .route("/login", get(oidc_login))
+                .route("/callback", get(oidc_callback))
+                .with_state(data);
+
+            if let Some(limiter) = &rate_limiter {
+                auth_router = auth_router
+                    .route_layer(middleware::from_fn_with_state(limiter.clone(), rate_limit));
+            }
+
+            let auth_router = auth_router.layer(TraceLayer::new_for_http());
+
+            router = router.nest(&format!("/{HTTP_URL_ROOT}/auth"), auth_router);
+        }
+
+        // probes can't authenticate, so the health check is added after the middleware
+        router = router.route(
+            &format!("/{HTTP_URL_ROOT}/healthz"),
+            get(healthz).with_state(state.clone()),
+        );
+
+        // the api description is public too, since it says nothing about what the server holds
+        router = router.route(&format!("/{HTTP_URL_ROOT}/openapi.json"), get(get_openapi));
+
+        // likewise, share links are for people without an account
+        let mut share_route = get(stream_share).with_state(state.clone());
+
+        if let Some(limiter) = &rate_limiter {
+            share_route =
+                share_route.layer(middleware::from_fn_with_state(limiter.clone(), rate_limit));
         }
 
+        router = router.route(
+            &format!("/{HTTP_URL_ROOT}/{SHARE_PATH}/{{token}}"),
+            share_route,
+        );
+
         // tls setup
         //
         // basically everything in this section is failable in some way, but since any failure means that the server
@@ -360,9 +507,10 @@ impl HttpEndpoint {
         // the main http server loop
         //
         // we want to return the handle to the caller, not the future, and so we just spawn it here
-        let handle = spawn(async move {
-            let mut connection_tasks = FuturesUnordered::new();
+        let shutdown = self.shutdown.clone();
+        let connections = self.connections.clone();
 
+        let handle = spawn(async move {
             // automatic http connection cleanup
             //
             // clients (reverse proxies and broswers) are not always good about closing stale connections,
@@ -373,19 +521,15 @@ impl HttpEndpoint {
             //
             // all of the logic lives in a loop, so we have to explicitly handle errors when accepting
             // the connection, and the resulting connection future that is polled to drive http has to be
-            // spawned on the runtime.
-            let mut cleanup_interval = tokio::time::interval(Duration::from_secs(20));
-
+            // spawned on the runtime.  the tracker forgets each connection once it is done.
             loop {
                 let router = router.clone();
 
                 tokio::select! {
                     // attempt to accept a new connection
-                    accept_result = listener.accept().and_then(|(stream, _remote_addr)| tls_acceptor.accept(stream)) => {
+                    accept_result = listener.accept().and_then(|(stream, remote_addr)| tls_acceptor.accept(stream).map_ok(move |stream| (stream, remote_addr))) => {
                         match accept_result {
-                            Ok(stream) => {
-                                // TODO -- axum has ConnectInfo with a dedicated extractor
-                                //
+                            Ok((stream, remote_addr)) => {
                                 // since we are using the connection details as inputs to the auth middleware, we
                                 // take a somewhat more roundabout path towards building the service_fn than the
                                 // basic tokio/hyper/axum configuration
@@ -407,6 +551,10 @@ impl HttpEndpoint {
                                 };
 
                                 let service = service_fn(move |mut request: Request<Incoming>| {
+                                    // the same extension that axum's ConnectInfo extractor reads,
+                                    // which the rate limiter falls back on
+                                    request.extensions_mut().insert(ConnectInfo(remote_addr));
+
                                     if let Some(cn) = &conn_cn {
                                         request.extensions_mut().insert(ClientCn {cn: cn.clone()});
                                     }
@@ -418,17 +566,33 @@ impl HttpEndpoint {
 
                                 let io = TokioIo::new(stream);
 
+                                let shutdown = shutdown.clone();
+
                                 // the connection future that enforces the timeout, as well
                                 // as handles errors from the http connection
+                                //
+                                // on shutdown, the connection stops taking new streams but
+                                // finishes the ones that are already open
                                 let conn_fut = async move {
-                                    let result = timeout(
-                                        Duration::from_secs(120),
-                                        Builder::new(TokioExecutor::new())
-                                            .keep_alive_interval(Duration::from_secs(20))
-                                            .keep_alive_timeout(Duration::from_secs(20))
-                                            .timer(TokioTimer::new())
-                                            .serve_connection(io, service)
-                                    ).await;
+                                    let mut conn = pin!(Builder::new(TokioExecutor::new())
+                                        .keep_alive_interval(Duration::from_secs(20))
+                                        .keep_alive_timeout(Duration::from_secs(20))
+                                        .timer(TokioTimer::new())
+                                        .serve_connection(io, service));
+
+                                    let mut draining = false;
+
+                                    let result = timeout(Duration::from_secs(120), async {
+                                        loop {
+                                            tokio::select! {
+                                                result = conn.as_mut() => break result,
+                                                _ = shutdown.cancelled(), if !draining => {
+                                                    conn.as_mut().graceful_shutdown();
+                                                    draining = true;
+                                                }
+                                            }
+                                        }
+                                    }).await;
 
                                     match result {
                                         Ok(Ok(())) => (),
@@ -452,8 +616,8 @@ impl HttpEndpoint {
                                     }
                                 };
 
-                                // spawn the connection future and collect the joinhandle
-                                connection_tasks.push(spawn(conn_fut));
+                                // spawn the connection future on the tracker
+                                connections.spawn(conn_fut);
 
                             },
                             Err(err) => {
@@ -464,15 +628,15 @@ impl HttpEndpoint {
                         }
                     },
 
-                    // clean up finished tasks when the interval future ticks
-                    _ = cleanup_interval.tick() => {
-                        while let Some(Some(_)) = connection_tasks.next().now_or_never() {}
+                    // stop accepting connections, see HttpEndpoint::shutdown()
+                    _ = shutdown.cancelled() => {
+                        info!("http listener stopped");
+                        break;
                     },
-
-                    // TODO: add broadcast channel for shutdown signals
-                    // _ = shutdown_signal => break,
                 }
             }
+
+            Ok(())
         });
 
         debug!("started axum/hyper http listener");
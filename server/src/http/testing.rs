@@ -0,0 +1,274 @@
+use std::{
+    collections::HashSet,
+    path::{Path, PathBuf},
+    sync::Arc,
+};
+
+use axum::Extension;
+
+use tempfile::TempDir;
+
+use crate::{
+    auth::{msg::AuthMsg, svc::AuthService},
+    db::{msg::DbMsg, svc::DbService},
+    http::{auth::CurrentUser, svc::HttpEndpoint},
+    service::{ESInner, ESMRegistry, EntanglementService, EsmReceiver, EsmResp, ServiceType},
+};
+use api::{
+    LINK_PATH, SLICE_PATH, THUMBNAIL_PATH,
+    library::{Library, LibraryUuid},
+    media::{Media, MediaMetadata, MediaUuid},
+};
+use common::{config::ESConfig, db::SqliteBackend};
+
+// handler test harness
+//
+// a real HttpEndpoint along with the db and auth services, with the sqlite database, the
+// media_srvdir, and a toml file of users and groups in a temporary directory, so that the
+// handlers can be called directly.  the task service is never started, so its sender leads
+// to a receiver that is kept around but never read.
+//
+// the library belongs to OWNER_GID, whose only member is OWNER_UID.  ADMIN_UID is the only
+// member of ADMIN_GID, which is the admin_group, and OTHER_UID is in no groups at all.
+pub(super) const OWNER_UID: &str = "owner";
+pub(super) const OWNER_GID: &str = "group";
+pub(super) const ADMIN_UID: &str = "admin";
+pub(super) const ADMIN_GID: &str = "admins";
+pub(super) const OTHER_UID: &str = "other";
+
+// relative to the media_srcdir, which is created along with it
+const LIBRARY_PATH: &str = "library";
+
+pub(super) struct TestEndpoint {
+    pub state: Arc<HttpEndpoint>,
+    pub library_uuid: LibraryUuid,
+    dir: TempDir,
+    _task_receiver: EsmReceiver,
+}
+
+impl TestEndpoint {
+    pub async fn new() -> Self {
+        let dir = tempfile::tempdir().unwrap();
+        let root = dir.path().display();
+
+        let config: ESConfig = toml::from_str(&format!(
+            r#"
+            authn_backend = "tomlfile"
+            authz_backend = "tomlfile"
+            db_backend = "sqlite"
+            admin_group = "{ADMIN_GID}"
+
+            [fs]
+            media_srcdir = "{root}/src"
+            media_srvdir = "{root}/srv"
+
+            [http]
+            socket = "127.0.0.1:0"
+            doc_root = "{root}/doc"
+            key = "{root}/key.pem"
+            cert = "{root}/cert.pem"
+
+            [task]
+            scan_threads = 1
+            scan_scratch = "{root}/scratch"
+            scan_timeout = 60
+
+            [sqlite]
+            path = "{root}/entanglement.db"
+
+            [tomlfile]
+            filename = "{root}/users.toml"
+            "#
+        ))
+        .unwrap();
+
+        // main() checks for these before starting anything
+        for subdir in [LINK_PATH, THUMBNAIL_PATH, SLICE_PATH] {
+            std::fs::create_dir_all(config.fs.media_srvdir.join(subdir)).unwrap();
+        }
+
+        std::fs::create_dir_all(config.fs.media_srcdir.join(LIBRARY_PATH)).unwrap();
+
+        write_groups(
+            &config.tomlfile.as_ref().unwrap().filename,
+            &[(OWNER_GID, &[OWNER_UID]), (ADMIN_GID, &[ADMIN_UID])],
+        );
+
+        let config = Arc::new(config);
+        let registry = ESMRegistry::new();
+
+        let (task_sender, task_receiver) = tokio::sync::mpsc::channel(16);
+        registry.insert(ServiceType::Task, task_sender).unwrap();
+
+        DbService::<SqliteBackend>::create(config.clone(), &registry)
+            .start(&registry)
+            .await
+            .unwrap();
+
+        AuthService::create(config.clone(), &registry)
+            .start(&registry)
+            .await
+            .unwrap();
+
+        let state = Arc::new(HttpEndpoint::new(config, registry).await.unwrap());
+
+        let library_uuid = db(&state, |resp| DbMsg::_AddLibrary {
+            resp,
+            library: library(LIBRARY_PATH, OWNER_GID),
+        })
+        .await;
+
+        TestEndpoint {
+            state,
+            library_uuid,
+            dir,
+            _task_receiver: task_receiver,
+        }
+    }
+
+    pub async fn db<T>(&self, msg: impl FnOnce(EsmResp<T>) -> DbMsg) -> T {
+        db(&self.state, msg).await
+    }
+
+    // the toml file is read on every lookup, so only the user cache stands between a change
+    // to the groups and the handlers seeing it
+    pub fn set_groups(&self, groups: &[(&str, &[&str])]) {
+        write_groups(&self.dir.path().join("users.toml"), groups);
+    }
+
+    pub async fn clear_user_cache(&self) {
+        let (tx, rx) = tokio::sync::oneshot::channel();
+
+        self.state
+            .auth_svc_sender
+            .send(
+                AuthMsg::_ClearUserCache {
+                    resp: tx,
+                    uid: Vec::new(),
+                }
+                .into(),
+            )
+            .await
+            .unwrap();
+
+        rx.await.unwrap().unwrap()
+    }
+
+    pub fn library_dir(&self) -> PathBuf {
+        self.dir.path().join("src").join(LIBRARY_PATH)
+    }
+
+    // another library next to the first, which belongs to gid instead
+    pub async fn add_library(&self, path: &str, gid: &str) -> LibraryUuid {
+        std::fs::create_dir_all(self.dir.path().join("src").join(path)).unwrap();
+
+        self.db(|resp| DbMsg::_AddLibrary {
+            resp,
+            library: library(path, gid),
+        })
+        .await
+    }
+
+    // adds the media to the library, along with its file in the originals
+    pub async fn add_media(&self, path: &str, contents: &[u8]) -> MediaUuid {
+        let media = Media {
+            size: contents.len() as u64,
+            ..self.media(path)
+        };
+
+        self.add_media_with(media, contents).await
+    }
+
+    // an image in the first library, for tests to change whatever else they care about
+    pub fn media(&self, path: &str) -> Media {
+        Media {
+            library_uuid: self.library_uuid,
+            path: path.to_owned(),
+            size: 0,
+            chash: String::from("chash"),
+            phash: String::from("phash"),
+            mtime: 1_700_000_000,
+            hidden: false,
+            date: String::from("2021-07-04 12:34:56"),
+            note: String::new(),
+            tags: HashSet::new(),
+            metadata: MediaMetadata::Image,
+            latitude: None,
+            longitude: None,
+            width: None,
+            height: None,
+            camera: None,
+            orientation: None,
+            capture_time: None,
+            duration_secs: None,
+            codec: None,
+        }
+    }
+
+    pub async fn add_media_with(&self, media: Media, contents: &[u8]) -> MediaUuid {
+        let media_uuid = self
+            .db(|resp| DbMsg::AddMedia {
+                resp,
+                media: Box::new(media),
+            })
+            .await;
+
+        std::fs::write(
+            self.dir
+                .path()
+                .join("srv")
+                .join(LINK_PATH)
+                .join(media_uuid.to_string()),
+            contents,
+        )
+        .unwrap();
+
+        media_uuid
+    }
+}
+
+async fn db<T>(state: &HttpEndpoint, msg: impl FnOnce(EsmResp<T>) -> DbMsg) -> T {
+    let (tx, rx) = tokio::sync::oneshot::channel();
+
+    state.db_svc_sender.send(msg(tx).into()).await.unwrap();
+
+    rx.await.unwrap().unwrap()
+}
+
+fn library(path: &str, gid: &str) -> Library {
+    Library {
+        path: path.to_owned(),
+        name: String::new(),
+        note: String::new(),
+        uid: String::from(OWNER_UID),
+        gid: gid.to_owned(),
+        count: 0,
+    }
+}
+
+pub(super) fn user(uid: &str) -> Extension<CurrentUser> {
+    Extension(CurrentUser {
+        uid: uid.to_owned(),
+    })
+}
+
+// the users are always the same three, whatever their groups
+fn write_groups(filename: &Path, groups: &[(&str, &[&str])]) {
+    let mut doc = String::new();
+
+    for uid in [OWNER_UID, ADMIN_UID, OTHER_UID] {
+        doc.push_str(&format!("[users.{uid}]\nname = \"{uid}\"\n\n"));
+    }
+
+    for (gid, members) in groups {
+        let members = members
+            .iter()
+            .map(|uid| format!("\"{uid}\""))
+            .collect::<Vec<_>>()
+            .join(", ");
+
+        doc.push_str(&format!("[groups.{gid}]\nmembers = [{members}]\n\n"));
+    }
+
+    std::fs::write(filename, doc).unwrap();
+}
@@ -0,0 +1,422 @@
+use std::{
+    path::{Path, PathBuf},
+    sync::Arc,
+};
+
+use anyhow::Result;
+use axum::{
+    Json,
+    extract::{Extension, Multipart, State, multipart::Field},
+    http::StatusCode,
+    response::{IntoResponse, Response},
+};
+use tokio::{
+    fs::{File, copy, create_dir_all, remove_dir_all, remove_file, rename, try_exists},
+    io::AsyncWriteExt,
+};
+use tracing::{debug, instrument, warn};
+
+use crate::{
+    auth::check::AuthCheck,
+    db::msg::DbMsg,
+    http::{AppError, auth::CurrentUser, auth::random_token, svc::HttpEndpoint},
+    task::scan_utils::{ingest_file, is_media_path},
+};
+use api::{
+    UuidSource,
+    library::LibraryUuid,
+    media::{
+        FIND_SIMILAR_DISTANCE_FIELD, FIND_SIMILAR_FILE_FIELD, SimilarMediaResp,
+        UPLOAD_MEDIA_FILE_FIELD, UPLOAD_MEDIA_LIBRARY_FIELD, UploadMediaResp,
+    },
+};
+use common::media::{content_hash, image::hash_image};
+
+// media upload
+//
+// this is the http equivalent of copying a file into the library directory and running
+// a scan, for users that don't have access to the server's filesystem
+//
+// the file is first written to the scratch directory, where it is hashed and checked
+// against the library before being moved into place.  this keeps duplicates and partial
+// uploads out of the library, where a concurrent scan could otherwise pick them up.
+pub(super) const DEFAULT_UPLOAD_LIMIT: usize = 4 * 1024 * 1024 * 1024;
+
+const UPLOAD_SCRATCH_PATH: &str = "uploads";
+
+struct UploadUuidParser;
+
+impl UuidSource for UploadUuidParser {}
+
+#[instrument(skip_all)]
+pub(super) async fn upload_media(
+    State(state): State<Arc<HttpEndpoint>>,
+    Extension(current_user): Extension<CurrentUser>,
+    mut multipart: Multipart,
+) -> Result<Response, AppError> {
+    // the library has to come first so that nothing is written before the auth check
+    let library_uuid = match multipart.next_field().await? {
+        Some(field) if field.name() == Some(UPLOAD_MEDIA_LIBRARY_FIELD) => {
+            match LibraryUuid::try_parse(&UploadUuidParser, field.text().await?.trim()) {
+                Ok(v) => v,
+                Err(_) => return Ok(StatusCode::BAD_REQUEST.into_response()),
+            }
+        }
+        _ => {
+            return Ok((StatusCode::BAD_REQUEST, "expected library_uuid field").into_response());
+        }
+    };
+
+    if !state.owns_library(&current_user.uid, &library_uuid).await? {
+        return Ok(StatusCode::UNAUTHORIZED.into_response());
+    }
+
+    let field = match multipart.next_field().await? {
+        Some(field) if field.name() == Some(UPLOAD_MEDIA_FILE_FIELD) => field,
+        _ => return Ok((StatusCode::BAD_REQUEST, "expected file field").into_response()),
+    };
+
+    // only the last component of the client's filename is used, so it can't point
+    // anywhere outside of the library directory
+    let filename = match field
+        .file_name()
+        .and_then(|v| Path::new(v).file_name())
+        .and_then(|v| v.to_str())
+    {
+        Some(v) if !v.starts_with('.') && is_media_path(Path::new(v)) => v.to_owned(),
+        _ => {
+            return Ok((StatusCode::BAD_REQUEST, "missing or unsupported filename").into_response());
+        }
+    };
+
+    let (tx, rx) = tokio::sync::oneshot::channel();
+
+    state
+        .db_svc_sender
+        .send(
+            DbMsg::GetLibrary {
+                resp: tx,
+                library_uuid,
+            }
+            .into(),
+        )
+        .await?;
+
+    let library = rx
+        .await??
+        .ok_or_else(|| anyhow::Error::msg("unknown library_uuid"))?;
+
+    // see task/scan.rs
+    if PathBuf::from(&library.path).is_absolute() {
+        return Err(anyhow::Error::msg("invalid absolute library path").into());
+    }
+
+    let destination = state
+        .config
+        .fs
+        .media_srcdir
+        .join(library.path)
+        .join(&filename);
+
+    if try_exists(&destination).await? {
+        return Ok((StatusCode::CONFLICT, "file already exists in library").into_response());
+    }
+
+    debug!({ filename, %library_uuid }, "receiving upload");
+
+    let scratch_dir = state
+        .config
+        .task
+        .scan_scratch
+        .join(UPLOAD_SCRATCH_PATH)
+        .join(random_token());
+
+    create_dir_all(&scratch_dir).await?;
+
+    let result = store_upload(
+        &state,
+        field,
+        library_uuid,
+        &filename,
+        &destination,
+        &scratch_dir,
+    )
+    .await;
+
+    if let Err(err) = remove_dir_all(&scratch_dir).await {
+        warn!("failed to clean up upload scratch directory: {err}");
+    }
+
+    Ok(result?)
+}
+
+// write the upload to scratch, check it for duplicates, and then move it into the library
+// and register it
+#[instrument(skip_all)]
+async fn store_upload(
+    state: &HttpEndpoint,
+    mut field: Field<'_>,
+    library_uuid: LibraryUuid,
+    filename: &str,
+    destination: &Path,
+    scratch_dir: &Path,
+) -> Result<Response> {
+    let upload = scratch_dir.join(filename);
+
+    let mut file = File::create(&upload).await?;
+
+    while let Some(chunk) = field.chunk().await? {
+        file.write_all(&chunk).await?;
+    }
+
+    file.flush().await?;
+
+    let chash = content_hash(
+        &upload,
+        &state.config.task.hash_algorithm.clone().unwrap_or_default(),
+    )
+    .await?;
+
+    let (tx, rx) = tokio::sync::oneshot::channel();
+
+    state
+        .db_svc_sender
+        .send(
+            DbMsg::GetMediaUuidByCHash {
+                resp: tx,
+                library_uuid,
+                chash,
+            }
+            .into(),
+        )
+        .await?;
+
+    if let Some(media) = rx.await?? {
+        debug!({ media_uuid = %media.media_uuid }, "upload is a duplicate");
+        return Ok((StatusCode::CONFLICT, "media already exists in library").into_response());
+    }
+
+    // the scratch directory may be on another filesystem
+    if rename(&upload, destination).await.is_err() {
+        copy(&upload, destination).await?;
+    }
+
+    match ingest_file(
+        state.config.clone(),
+        state.db_svc_sender.clone(),
+        library_uuid,
+        destination.to_path_buf(),
+        scratch_dir.join("scan"),
+    )
+    .await
+    {
+        Ok(Some(media_uuid)) => Ok(Json(UploadMediaResp { media_uuid }).into_response()),
+        // another upload or a scan got there first
+        Ok(None) => {
+            let _ = remove_file(destination).await;
+            Ok((StatusCode::CONFLICT, "media already exists in library").into_response())
+        }
+        Err(err) => {
+            let _ = remove_file(destination).await;
+            Err(err)
+        }
+    }
+}
+
+// hash an uploaded image and search for media near it, as in SimilarMedia
+//
+// the probe goes through the same scratch directory as an upload and is removed as soon
+// as it is hashed.  auth is handled as part of the db search.
+#[instrument(skip_all)]
+pub(super) async fn find_similar_by_upload(
+    State(state): State<Arc<HttpEndpoint>>,
+    Extension(current_user): Extension<CurrentUser>,
+    mut multipart: Multipart,
+) -> Result<Response, AppError> {
+    let distance = match multipart.next_field().await? {
+        Some(field) if field.name() == Some(FIND_SIMILAR_DISTANCE_FIELD) => {
+            match field.text().await?.trim().parse::<i64>() {
+                Ok(v) => v,
+                Err(_) => return Ok((StatusCode::BAD_REQUEST, "invalid distance").into_response()),
+            }
+        }
+        _ => return Ok((StatusCode::BAD_REQUEST, "expected distance field").into_response()),
+    };
+
+    let mut field = match multipart.next_field().await? {
+        Some(field) if field.name() == Some(FIND_SIMILAR_FILE_FIELD) => field,
+        _ => return Ok((StatusCode::BAD_REQUEST, "expected file field").into_response()),
+    };
+
+    // the decoder is chosen by extension, so only that much of the filename is kept
+    let extension = match field
+        .file_name()
+        .and_then(|v| Path::new(v).extension())
+        .and_then(|v| v.to_str())
+    {
+        Some(v) if v.chars().all(|c| c.is_ascii_alphanumeric()) => v.to_owned(),
+        _ => {
+            return Ok((StatusCode::BAD_REQUEST, "missing or unsupported filename").into_response());
+        }
+    };
+
+    let scratch_dir = state
+        .config
+        .task
+        .scan_scratch
+        .join(UPLOAD_SCRATCH_PATH)
+        .join(random_token());
+
+    create_dir_all(&scratch_dir).await?;
+
+    let probe = scratch_dir.join(format!("probe.{extension}"));
+
+    let result = async {
+        let mut file = File::create(&probe).await?;
+
+        while let Some(chunk) = field.chunk().await? {
+            file.write_all(&chunk).await?;
+        }
+
+        file.flush().await?;
+
+        hash_image(&probe).await
+    }
+    .await;
+
+    if let Err(err) = remove_dir_all(&scratch_dir).await {
+        warn!("failed to clean up upload scratch directory: {err}");
+    }
+
+    let phash = match result {
+        Ok(v) => v,
+        Err(err) => {
+            debug!("failed to hash probe image: {err}");
+            return Ok((StatusCode::BAD_REQUEST, "unable to read image").into_response());
+        }
+    };
+
+    let gid = state.groups_for_user(&current_user.uid).await?;
+
+    let (tx, rx) = tokio::sync::oneshot::channel();
+
+    state
+        .db_svc_sender
+        .send(
+            DbMsg::SimilarPhash {
+                resp: tx,
+                gid,
+                phash,
+                distance,
+            }
+            .into(),
+        )
+        .await?;
+
+    let result = rx.await??;
+
+    Ok(Json(SimilarMediaResp { media: result }).into_response())
+}
+
+#[cfg(all(test, feature = "sqlite"))]
+mod tests {
+    use axum::{
+        body::Body,
+        extract::{FromRequest, Request},
+        http::header::CONTENT_TYPE,
+    };
+
+    use super::*;
+    use crate::http::testing::{OTHER_UID, OWNER_UID, TestEndpoint, user};
+
+    // a single green pixel, which is the smallest thing that the image processing accepts
+    const PNG: &[u8] = b"\x89PNG\r\n\x1a\n\
+        \x00\x00\x00\x0dIHDR\x00\x00\x00\x01\x00\x00\x00\x01\x08\x02\x00\x00\x00\x90\x77\x53\xde\
+        \x00\x00\x00\x0cIDAT\x78\xda\x63\xf8\xcf\xc0\x00\x00\x03\x01\x01\x00\xf7\x03\x41\x43\
+        \x00\x00\x00\x00IEND\xae\x42\x60\x82";
+
+    const BOUNDARY: &str = "entanglement-boundary";
+
+    async fn upload(endpoint: &TestEndpoint, uid: &str, filename: &str) -> Response {
+        let mut body = format!(
+            "--{BOUNDARY}\r\n\
+             Content-Disposition: form-data; name=\"{UPLOAD_MEDIA_LIBRARY_FIELD}\"\r\n\r\n\
+             {}\r\n\
+             --{BOUNDARY}\r\n\
+             Content-Disposition: form-data; name=\"{UPLOAD_MEDIA_FILE_FIELD}\"; \
+             filename=\"{filename}\"\r\n\
+             Content-Type: image/png\r\n\r\n",
+            endpoint.library_uuid
+        )
+        .into_bytes();
+
+        body.extend_from_slice(PNG);
+        body.extend_from_slice(format!("\r\n--{BOUNDARY}--\r\n").as_bytes());
+
+        let request = Request::post("/")
+            .header(
+                CONTENT_TYPE,
+                format!("multipart/form-data; boundary={BOUNDARY}"),
+            )
+            .body(Body::from(body))
+            .unwrap();
+
+        let multipart = Multipart::from_request(request, &()).await.unwrap();
+
+        upload_media(State(endpoint.state.clone()), user(uid), multipart)
+            .await
+            .unwrap()
+    }
+
+    #[tokio::test]
+    async fn uploads_into_the_library() {
+        let endpoint = TestEndpoint::new().await;
+
+        let response = upload(&endpoint, OWNER_UID, "a.png").await;
+        assert_eq!(response.status(), StatusCode::OK);
+
+        let body = axum::body::to_bytes(response.into_body(), usize::MAX)
+            .await
+            .unwrap();
+        let UploadMediaResp { media_uuid } = serde_json::from_slice(&body).unwrap();
+
+        let (media, _, _) = endpoint
+            .db(|resp| DbMsg::GetMedia { resp, media_uuid })
+            .await
+            .unwrap();
+
+        assert_eq!(media.library_uuid, endpoint.library_uuid);
+        assert_eq!(
+            std::fs::read(endpoint.library_dir().join("a.png")).unwrap(),
+            PNG
+        );
+    }
+
+    #[tokio::test]
+    async fn rejects_a_duplicate_chash() {
+        let endpoint = TestEndpoint::new().await;
+
+        assert_eq!(
+            upload(&endpoint, OWNER_UID, "a.png").await.status(),
+            StatusCode::OK
+        );
+
+        // the same contents under another name never reach the library
+        assert_eq!(
+            upload(&endpoint, OWNER_UID, "b.png").await.status(),
+            StatusCode::CONFLICT
+        );
+        assert!(!endpoint.library_dir().join("b.png").exists());
+    }
+
+    #[tokio::test]
+    async fn rejects_a_library_the_user_does_not_own() {
+        let endpoint = TestEndpoint::new().await;
+
+        assert_eq!(
+            upload(&endpoint, OTHER_UID, "a.png").await.status(),
+            StatusCode::UNAUTHORIZED
+        );
+        assert!(!endpoint.library_dir().join("a.png").exists());
+    }
+}
@@ -1,6 +1,6 @@
 use std::path::PathBuf;
 
-use clap::Parser;
+use clap::{Parser, ValueEnum};
 use tokio::signal::unix::{SignalKind, signal};
 use tracing::info;
 use tracing_subscriber::EnvFilter;
@@ -11,18 +11,44 @@ mod db;
 mod debug;
 mod fs;
 mod http;
+mod metrics;
 mod service;
 mod task;
 
 use api::{LINK_PATH, SLICE_PATH, THUMBNAIL_PATH};
-use common::{config::read_config, db::PostgresBackend};
+use common::{
+    config::{DbBackend, read_config},
+    db::{MariaDBBackend, PostgresBackend},
+};
 use service::{ESMRegistry, EntanglementService};
 
+// used when neither --log-filter nor RUST_LOG is set, since h2 is very chatty at info
+const DEFAULT_LOG_FILTER: &str = "info,h2=off";
+
+#[derive(Clone, Debug, ValueEnum)]
+enum LogFormat {
+    Text,
+    Json,
+}
+
 #[derive(Parser, Debug)]
 #[command(version, about, long_about = None)]
 struct Args {
     #[arg(short, long, default_value = "/etc/entanglement/config.toml")]
     config: String,
+
+    /// log output format, where json includes the span fields for log aggregators
+    #[arg(
+        long,
+        value_enum,
+        env = "ENTANGLEMENT_LOG_FORMAT",
+        default_value = "text"
+    )]
+    log_format: LogFormat,
+
+    /// log filter in RUST_LOG syntax, e.g. "info,h2=off,common::db=debug"
+    #[arg(long, env = "RUST_LOG", default_value = DEFAULT_LOG_FILTER)]
+    log_filter: String,
 }
 
 #[tokio::main]
@@ -34,9 +60,16 @@ async fn main() -> anyhow::Result<()> {
 
     let args = Args::parse();
 
-    tracing_subscriber::fmt()
-        .with_env_filter(EnvFilter::from_default_env())
-        .init();
+    // logging starts before the config is read, so these are flags instead
+    let filter = EnvFilter::try_new(&args.log_filter)?;
+
+    match args.log_format {
+        LogFormat::Text => tracing_subscriber::fmt().with_env_filter(filter).init(),
+        LogFormat::Json => tracing_subscriber::fmt()
+            .json()
+            .with_env_filter(filter)
+            .init(),
+    }
 
     info!("starting entanglement media management server");
 
@@ -54,6 +87,10 @@ async fn main() -> anyhow::Result<()> {
     checks::subdir_exists(&config, SLICE_PATH)
         .expect("could not create video slice path in media_srvdir");
 
+    if config.http.metrics.is_some() {
+        metrics::install()?;
+    }
+
     info!("starting core services");
 
     // TODO -- build the root cert store into config/world
@@ -61,23 +98,48 @@ async fn main() -> anyhow::Result<()> {
     let registry = ESMRegistry::new();
 
     let auth_svc = auth::svc::AuthService::create(config.clone(), &registry);
-    let db_svc = db::svc::DbService::<PostgresBackend>::create(config.clone(), &registry);
     let http_svc = http::svc::HttpService::create(config.clone(), &registry);
     let task_svc = task::svc::TaskService::create(config.clone(), &registry);
 
+    // the db service is generic over the backend, so it has to be created and started
+    // separately in each arm.  this happens before the other services start, since
+    // they may need the db sender from the registry
+    match config.db_backend {
+        DbBackend::MariaDB => {
+            db::svc::DbService::<MariaDBBackend>::create(config.clone(), &registry)
+                .start(&registry)
+                .await?
+        }
+        DbBackend::Postgres => {
+            db::svc::DbService::<PostgresBackend>::create(config.clone(), &registry)
+                .start(&registry)
+                .await?
+        }
+        #[cfg(feature = "sqlite")]
+        DbBackend::Sqlite => {
+            db::svc::DbService::<common::db::SqliteBackend>::create(config.clone(), &registry)
+                .start(&registry)
+                .await?
+        }
+    }
+
     auth_svc.start(&registry).await?;
-    db_svc.start(&registry).await?;
     http_svc.start(&registry).await?;
     task_svc.start(&registry).await?;
 
     info!("startup complete!");
 
     let mut sigint = signal(SignalKind::interrupt())?;
+    let mut sigterm = signal(SignalKind::terminate())?;
 
     tokio::select! {
-        _ = sigint.recv() => {
-            info!("caught SIGINT");
-            Ok(())
-        },
+        _ = sigint.recv() => info!("caught SIGINT"),
+        _ = sigterm.recv() => info!("caught SIGTERM"),
     }
+
+    service::shutdown(&registry).await;
+
+    info!("shutdown complete");
+
+    Ok(())
 }
@@ -0,0 +1,59 @@
+use std::{future::Future, sync::OnceLock, time::Instant};
+
+use anyhow::Result;
+use metrics::histogram;
+use metrics_exporter_prometheus::{Matcher, PrometheusBuilder, PrometheusHandle};
+
+// prometheus metrics
+//
+// the metrics crate records into a process-wide recorder, so every service can emit metrics
+// without threading a handle through the registry.  until install() is called (i.e. if the
+// metrics config is absent), the macros are no-ops.
+//
+// the exported metrics are:
+//  * entanglement_http_requests_total and entanglement_http_request_duration_seconds, by
+//    route and status (see http/metrics.rs)
+//  * entanglement_message_duration_seconds, by service and message, which for the db
+//    service is the time spent in each query
+//  * entanglement_cache_lookups_total, by cache and hit/miss (see common::AwaitCache)
+//  * entanglement_tasks_running and entanglement_tasks_completed_total, by task type
+static PROMETHEUS: OnceLock<PrometheusHandle> = OnceLock::new();
+
+// in seconds, spanning cached lookups through slow library-wide queries
+const DURATION_BUCKETS: &[f64] = &[
+    0.001, 0.0025, 0.005, 0.01, 0.025, 0.05, 0.1, 0.25, 0.5, 1.0, 2.5, 5.0, 10.0,
+];
+
+pub fn install() -> Result<()> {
+    let handle = PrometheusBuilder::new()
+        .set_buckets_for_metric(
+            Matcher::Suffix("duration_seconds".to_owned()),
+            DURATION_BUCKETS,
+        )?
+        .install_recorder()?;
+
+    PROMETHEUS
+        .set(handle)
+        .map_err(|_| anyhow::Error::msg("internal error: metrics recorder installed twice"))
+}
+
+// the current metrics in the prometheus text format, if they are enabled
+pub fn render() -> Option<String> {
+    PROMETHEUS.get().map(|handle| handle.render())
+}
+
+// time the handling of a single service message
+pub async fn timed<F: Future>(service: &'static str, message: &'static str, fut: F) -> F::Output {
+    let start = Instant::now();
+
+    let output = fut.await;
+
+    histogram!(
+        "entanglement_message_duration_seconds",
+        "service" => service,
+        "message" => message
+    )
+    .record(start.elapsed());
+
+    output
+}
@@ -1,9 +1,17 @@
-use std::{future::Future, sync::Arc};
+use std::{future::Future, sync::Arc, time::Duration};
 
 use anyhow::Result;
 use async_trait::async_trait;
 use dashmap::DashMap;
+use tokio::{
+    select,
+    sync::Mutex,
+    time::{Instant, timeout_at},
+};
+use tokio_util::{sync::CancellationToken, task::TaskTracker};
+use tracing::{debug, error, info, warn};
 
+use crate::metrics::timed;
 use common::config::ESConfig;
 
 // these are the services that make up the entanglment server backend
@@ -34,6 +42,21 @@ pub enum Esm {
     Db(crate::db::msg::DbMsg),
     _Http(crate::http::msg::HttpMsg),
     Task(crate::task::msg::TaskMsg),
+    // stop taking new messages and answer once the in-flight ones are done, see serve_messages()
+    Shutdown { resp: EsmResp<()> },
+}
+
+impl Esm {
+    // the variant name of the inner message, used to label metrics
+    pub fn name(&self) -> &'static str {
+        match self {
+            Esm::Auth(msg) => msg.into(),
+            Esm::Db(msg) => msg.into(),
+            Esm::_Http(msg) => msg.into(),
+            Esm::Task(msg) => msg.into(),
+            Esm::Shutdown { .. } => "Shutdown",
+        }
+    }
 }
 
 // service registry
@@ -105,6 +128,11 @@ pub trait ESInner: Sized + Send + Sync + 'static {
 
     async fn message_handler(&self, esm: Esm) -> Result<()>;
 
+    // called once a Shutdown message arrives, while the service can still message itself
+    async fn shutdown(&self) -> Result<()> {
+        Ok(())
+    }
+
     // rather than have the inner service trait functions (i.e., the rpc calls) respond directly,
     // we define this helper function for use in the message_handler loop
     //
@@ -126,3 +154,136 @@ pub trait ESInner: Sized + Send + Sync + 'static {
         })
     }
 }
+
+// service message loop
+//
+// each service hands its messages to message_handler() here, which runs them all
+// concurrently.  the handlers are tracked so that a Shutdown message can wait for the ones
+// in flight before it is answered.
+//
+// the receiver stays open until the service's own shutdown() is finished, since that may
+// depend on messages (i.e. cancelled tasks reporting back), and then anything still queued
+// is handled before the loop exits.
+pub async fn serve_messages<T: ESInner>(
+    service: &'static str,
+    receiver: Arc<Mutex<EsmReceiver>>,
+    state: Arc<T>,
+) -> Result<()> {
+    let mut receiver = receiver.lock().await;
+
+    let tracker = TaskTracker::new();
+    let stopped = CancellationToken::new();
+
+    let mut shutdown = None;
+    let mut closed = false;
+
+    loop {
+        let msg = select! {
+            msg = receiver.recv() => msg,
+            _ = stopped.cancelled(), if !closed => {
+                receiver.close();
+                closed = true;
+                continue;
+            }
+        };
+
+        let Some(msg) = msg else {
+            break;
+        };
+
+        let state = Arc::clone(&state);
+
+        match msg {
+            Esm::Shutdown { resp } => {
+                if shutdown.is_some() {
+                    let _ = resp.send(Err(anyhow::Error::msg("service is already shutting down")));
+                    continue;
+                }
+
+                shutdown = Some(resp);
+
+                info!({ service }, "shutting down service");
+
+                let stopped = stopped.clone();
+
+                tracker.spawn(async move {
+                    if let Err(err) = state.shutdown().await {
+                        error!(
+                            {service = service, channel = "esm", error = %err},
+                            "failed to shut down"
+                        );
+                    }
+
+                    stopped.cancel();
+                });
+            }
+            msg => {
+                tracker.spawn(async move {
+                    match timed(service, msg.name(), state.message_handler(msg)).await {
+                        Ok(()) => (),
+                        Err(err) => {
+                            error!({service = service, channel = "esm", error = %err})
+                        }
+                    }
+                });
+            }
+        }
+    }
+
+    // the registry holds a sender for every service, so only a shutdown closes the channel
+    let Some(resp) = shutdown else {
+        return Err(anyhow::Error::msg(format!(
+            "{service} service esm channel disconnected"
+        )));
+    };
+
+    tracker.close();
+    tracker.wait().await;
+
+    debug!({ service }, "service stopped");
+
+    let _ = resp.send(Ok(()));
+
+    Ok(())
+}
+
+// how long the whole shutdown may take before the server exits anyway, which should be
+// shorter than the grace period of whatever sends the SIGTERM
+const SHUTDOWN_TIMEOUT: Duration = Duration::from_secs(30);
+
+// graceful shutdown
+//
+// the services are stopped one at a time, starting with http so that no new work comes
+// in and ending with the db since everything else depends on it.  failures are only
+// logged, since the server is exiting regardless.
+pub async fn shutdown(registry: &ESMRegistry) {
+    let deadline = Instant::now() + SHUTDOWN_TIMEOUT;
+
+    for service in [
+        ServiceType::Http,
+        ServiceType::Task,
+        ServiceType::Auth,
+        ServiceType::Db,
+    ] {
+        let result = timeout_at(deadline, async {
+            let (tx, rx) = tokio::sync::oneshot::channel();
+
+            registry
+                .get(&service)?
+                .send(Esm::Shutdown { resp: tx })
+                .await?;
+
+            rx.await?
+        })
+        .await;
+
+        match result {
+            Ok(Ok(())) => debug!({ ?service }, "service shut down"),
+            Ok(Err(err)) => warn!({ ?service, error = %err }, "service failed to shut down"),
+            Err(_) => {
+                warn!({ ?service }, "shutdown timed out");
+                return;
+            }
+        }
+    }
+}
@@ -9,26 +9,36 @@ use std::{
 
 use anyhow::Result;
 use tokio::{
-    fs::{create_dir_all, metadata, remove_dir_all, remove_file, symlink, try_exists},
+    fs::{create_dir_all, metadata, remove_dir_all, try_exists},
     sync::oneshot::channel,
     task::JoinSet,
 };
-use tracing::{Level, debug, instrument, span, warn};
+use tokio_util::sync::CancellationToken;
+use tracing::{Level, debug, info, instrument, span, warn};
 
 use crate::{
     db::msg::DbMsg,
-    fs::{media_link_path, media_thumbnail_path},
+    fs::store_thumbnail,
     service::{ESMRegistry, EsmSender, ServiceType},
     task::scan_utils::add_tag_to_media,
 };
-use api::{library::LibraryUuid, media::MediaUuid, search::SearchFilter};
-use common::{config::ESConfig, media::create_thumbnail};
+use api::{
+    LINK_PATH, THUMBNAIL_PATH,
+    library::LibraryUuid,
+    media::{MediaUuid, ThumbnailSize},
+    search::SearchFilter,
+};
+use common::{
+    config::ESConfig,
+    storage::{StorageBackend, create_storage, thumbnail_name},
+};
 
 #[derive(Debug)]
 pub struct CleanContext {
     pub config: Arc<ESConfig>,
     pub db_svc_sender: EsmSender,
     pub scratch_base: PathBuf,
+    pub storage: Arc<dyn StorageBackend>,
 }
 
 impl Drop for CleanContext {
@@ -40,11 +50,13 @@ impl Drop for CleanContext {
     }
 }
 
-#[instrument(skip(config, registry))]
+// like the scan, a cancelled clean finishes the media in flight and then returns
+#[instrument(skip(config, registry, cancel))]
 pub async fn clean_library(
     config: Arc<ESConfig>,
     registry: ESMRegistry,
     library_uuid: LibraryUuid,
+    cancel: CancellationToken,
 ) -> Result<i64> {
     debug!("library clean pre-startup verification");
 
@@ -91,6 +103,7 @@ pub async fn clean_library(
             .scan_scratch
             .clone()
             .join(library_uuid.to_string()),
+        storage: create_storage(config.clone())?,
     });
 
     let warnings = Arc::new(AtomicI64::new(0));
@@ -108,6 +121,11 @@ pub async fn clean_library(
     );
 
     for media_uuid in media_in_library {
+        if cancel.is_cancelled() {
+            info!("library clean cancelled, waiting for media in progress");
+            break;
+        }
+
         while tasks.len() > clean_threads {
             tasks.join_next().await;
         }
@@ -140,7 +158,6 @@ pub async fn clean_library(
 
 #[instrument(skip(context))]
 async fn clean_media(context: Arc<CleanContext>, media_uuid: MediaUuid) -> Result<()> {
-    let config = context.config.clone();
     let db_svc_sender = context.db_svc_sender.clone();
 
     let (tx, rx) = tokio::sync::oneshot::channel();
@@ -183,71 +200,70 @@ async fn clean_media(context: Arc<CleanContext>, media_uuid: MediaUuid) -> Resul
 
     let path_metadata = metadata(&path).await?;
 
-    // symlink validation and cleanup
+    // link validation and cleanup
     //
-    // the symlink folder is effectively a cache for the media path column, and this
-    // ensures that the links into the given library are all accurate
+    // the links are effectively a cache for the media path column, and this ensures
+    // that the links into the given library are all accurate.  a link to the right
+    // file matches its length and is no older than it, which also covers a backend
+    // that copies the originals.
     //
     // there is a separate task (that can run concurrently) that removes unknown
-    // symlinks, since no library will attempt to modify them via this task
-    let link_path = media_link_path(config.clone(), media_uuid);
-
-    let mut relink = false;
+    // links, since no library will attempt to modify them via this task
+    let media_uuid_str = media_uuid.to_string();
+    let modified = path_metadata.modified()?;
 
-    // early return handles the case where we cannot verify if the link exists
-    if try_exists(&link_path).await? {
-        let link_metadata = metadata(&link_path).await?;
-
-        if !link_metadata.is_symlink()
-            || link_path.canonicalize()? != media_link_path(config.clone(), media_uuid)
-        {
-            relink = true;
+    let relink = match context.storage.info(LINK_PATH, &media_uuid_str).await? {
+        Some(info) => {
+            info.length != path_metadata.len() || info.modified.is_none_or(|v| v < modified)
         }
-    } else {
-        relink = true;
-    }
+        None => true,
+    };
 
     debug!({ relink });
 
     if relink {
-        match remove_file(&link_path).await {
-            Ok(()) => {}
-            Err(err) => match err.kind() {
-                std::io::ErrorKind::NotFound => {}
-                _ => return Err(anyhow::Error::from(err)),
-            },
-        }
-
-        symlink(&path, &link_path).await?;
+        context.storage.put_original(media_uuid, &path).await?;
     }
 
     // thumbnail validation and cleanup
     //
     // we need to replace the thumbnails if they don't exist or the media changes
-    let thumbnail_path = media_thumbnail_path(config.clone(), media_uuid);
-
-    let mut regen = false;
-
-    if try_exists(&thumbnail_path).await? {
-        let thumnail_metadata = metadata(&thumbnail_path).await?;
-
-        if path_metadata.modified()? > thumnail_metadata.modified()? {
-            regen = true;
-        }
-    } else {
-        regen = true;
-    }
+    let regen = match context
+        .storage
+        .info(
+            THUMBNAIL_PATH,
+            &thumbnail_name(media_uuid, ThumbnailSize::Grid),
+        )
+        .await?
+    {
+        Some(info) => info.modified.is_none_or(|v| modified > v),
+        None => true,
+    };
 
     if regen {
-        if try_exists(&thumbnail_path).await? {
-            remove_file(&thumbnail_path).await?
-        };
+        // the larger sizes are created on demand by the http service, so it suffices
+        // to remove the stale ones
+        for size in [ThumbnailSize::Preview, ThumbnailSize::Full] {
+            let _ = context
+                .storage
+                .delete(THUMBNAIL_PATH, &thumbnail_name(media_uuid, size))
+                .await;
+        }
 
         let scratch_dir = context.scratch_base.join(media_uuid.to_string());
 
         create_dir_all(&scratch_dir).await?;
 
-        create_thumbnail(&path, &thumbnail_path, &scratch_dir, &media.metadata).await?;
+        store_thumbnail(
+            context.storage.as_ref(),
+            &path,
+            &scratch_dir,
+            &media.metadata,
+            &context.config.task.raw_strategy.clone().unwrap_or_default(),
+            media_uuid,
+            ThumbnailSize::Grid,
+        )
+        .await?;
 
         remove_dir_all(scratch_dir).await?;
     }
@@ -0,0 +1,208 @@
+use std::{
+    collections::HashSet,
+    path::{Path, PathBuf},
+};
+
+use anyhow::Result;
+use tokio::{sync::oneshot::channel, task::spawn_blocking};
+use tokio_util::sync::CancellationToken;
+use tracing::{debug, info, instrument, warn};
+
+use crate::{
+    db::msg::DbMsg,
+    service::{ESMRegistry, EsmSender, ServiceType},
+    task::scan_utils::{ProgressReporter, report_summary},
+};
+use api::{
+    library::LibraryUuid,
+    media::{MediaMetadata, MediaUpdate, MediaUuid},
+    search::SearchFilter,
+    task::TaskLibrary,
+};
+use common::media::{
+    date::{filename_date, plausible_date},
+    image::read_capture_time,
+    mtime_date,
+};
+
+// date backfill task
+//
+// when the scan can't find a capture time, the date column falls back to the file mtime
+// (or is left empty for videos without a creation time), which is frequently just the
+// time that the file was copied into the library.  this task revisits those media and
+// looks for a better date, first in the exif data and then in the filename.  media with
+// a date that came from anywhere else are left alone.
+#[instrument(skip(registry, cancel))]
+pub async fn dateparse_library(
+    registry: ESMRegistry,
+    library_uuid: LibraryUuid,
+    cancel: CancellationToken,
+) -> Result<i64> {
+    let db_svc_sender = registry.get(&ServiceType::Db)?;
+    let task_svc_sender = registry.get(&ServiceType::Task)?;
+
+    let (tx, rx) = channel();
+
+    db_svc_sender
+        .send(
+            DbMsg::GetLibrary {
+                resp: tx,
+                library_uuid,
+            }
+            .into(),
+        )
+        .await?;
+
+    let library = rx
+        .await??
+        .ok_or_else(|| anyhow::Error::msg("library does not exist"))?;
+
+    let (tx, rx) = channel();
+
+    db_svc_sender
+        .send(
+            DbMsg::SearchMediaInLibrary {
+                resp: tx,
+                gid: HashSet::from([library.gid]),
+                library_uuid,
+                hidden: None,
+                filter: SearchFilter::default(),
+            }
+            .into(),
+        )
+        .await?;
+
+    let media_in_library = rx.await??;
+
+    debug!(
+        { count = media_in_library.len() },
+        "library dateparse beginning database walk"
+    );
+
+    let task_library = TaskLibrary::User { library_uuid };
+
+    let mut progress = ProgressReporter::new(
+        task_svc_sender.clone(),
+        task_library,
+        media_in_library.len() as u64,
+    );
+
+    let mut warnings = 0;
+    let mut corrected = 0;
+
+    for media_uuid in media_in_library {
+        if cancel.is_cancelled() {
+            info!("library dateparse cancelled");
+            break;
+        }
+
+        match dateparse_media(&db_svc_sender, media_uuid).await {
+            Ok(true) => corrected += 1,
+            Ok(false) => {}
+            Err(err) => {
+                warn!("dateparse error: {err:?}");
+                warnings += 1;
+            }
+        }
+
+        progress.advance().await;
+    }
+
+    progress.report().await;
+
+    report_summary(
+        &task_svc_sender,
+        task_library,
+        format!("{corrected} dates corrected"),
+    )
+    .await;
+
+    Ok(warnings)
+}
+
+// returns true if the date was changed
+#[instrument(skip(db_svc_sender))]
+async fn dateparse_media(db_svc_sender: &EsmSender, media_uuid: MediaUuid) -> Result<bool> {
+    let (tx, rx) = channel();
+
+    db_svc_sender
+        .send(
+            DbMsg::GetMedia {
+                resp: tx,
+                media_uuid,
+            }
+            .into(),
+        )
+        .await?;
+
+    let media = rx
+        .await??
+        .ok_or_else(|| {
+            anyhow::Error::msg("internal error: failed to get_media after searching library")
+        })?
+        .0;
+
+    let path = PathBuf::from(media.path);
+    let current = media.date;
+    let metadata = media.metadata;
+
+    let date = spawn_blocking(move || better_date(&path, &current, &metadata)).await??;
+
+    let Some(date) = date else {
+        return Ok(false);
+    };
+
+    debug!({ date }, "correcting media date");
+
+    let (tx, rx) = channel();
+
+    db_svc_sender
+        .send(
+            DbMsg::UpdateMedia {
+                resp: tx,
+                media_uuid,
+                update: MediaUpdate {
+                    hidden: None,
+                    date: Some(date),
+                    note: None,
+                    tags: None,
+                    tags_add: HashSet::new(),
+                    tags_remove: HashSet::new(),
+                },
+            }
+            .into(),
+        )
+        .await?;
+
+    rx.await??;
+
+    Ok(true)
+}
+
+// a replacement for the current date, if it needs one and one can be found
+//
+// a date that matches the mtime is treated as a guess, since that is what the scan falls
+// back to.  a missing file can't be checked, but any date found in the name still beats
+// an implausible one.
+fn better_date(path: &Path, current: &str, metadata: &MediaMetadata) -> Result<Option<String>> {
+    let guessed = match mtime_date(path) {
+        Ok(mtime) => mtime == current.trim(),
+        Err(_) => false,
+    };
+
+    if plausible_date(current) && !guessed {
+        return Ok(None);
+    }
+
+    // unreadable exif just means falling back to the filename
+    let exif_date = match metadata {
+        MediaMetadata::Image => read_capture_time(path)
+            .unwrap_or_default()
+            .filter(|date| plausible_date(date)),
+        _ => None,
+    };
+
+    Ok(exif_date
+        .or_else(|| filename_date(path))
+        .filter(|date| date != current.trim()))
+}
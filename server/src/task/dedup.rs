@@ -0,0 +1,435 @@
+use std::{
+    cmp::Reverse,
+    collections::{HashMap, HashSet},
+};
+
+use anyhow::Result;
+use tokio::sync::oneshot::channel;
+use tokio_util::sync::CancellationToken;
+use tracing::{debug, info, instrument, warn};
+
+use crate::{
+    auth::msg::AuthMsg,
+    db::msg::DbMsg,
+    service::{ESMRegistry, EsmSender, ServiceType},
+    task::scan_utils::report_summary,
+};
+use api::{
+    collection::CollectionUuid,
+    library::LibraryUuid,
+    media::{Media, MediaUpdate, MediaUuid},
+    search::SearchFilter,
+    task::TaskLibrary,
+};
+
+// media found while walking the library, along with the collections that contain it
+#[derive(Debug)]
+struct DedupEntry {
+    media_uuid: MediaUuid,
+    media: Media,
+    collections: Vec<CollectionUuid>,
+}
+
+// find media in a library that share a content hash
+//
+// the scan only skips a file if its hash is already present, so duplicates appear when the
+// same file is copied into the library faster than the scan can see it (or when records
+// predate that check).  without merge, this only reports what it finds.  with merge, each
+// group is collapsed into one survivor: the survivor joins every collection that held a
+// copy and picks up all of their tags, while the other copies are tagged and moved to the
+// trash so that the change can be undone.
+#[instrument(skip(registry, cancel))]
+pub async fn dedup_library(
+    registry: ESMRegistry,
+    library_uuid: LibraryUuid,
+    merge: bool,
+    cancel: CancellationToken,
+) -> Result<i64> {
+    let db_svc_sender = registry.get(&ServiceType::Db)?;
+    let auth_svc_sender = registry.get(&ServiceType::Auth)?;
+    let task_svc_sender = registry.get(&ServiceType::Task)?;
+
+    let (tx, rx) = channel();
+
+    db_svc_sender
+        .send(
+            DbMsg::GetLibrary {
+                resp: tx,
+                library_uuid,
+            }
+            .into(),
+        )
+        .await?;
+
+    let library = rx
+        .await??
+        .ok_or_else(|| anyhow::Error::msg("library does not exist"))?;
+
+    // trashed media are already excluded, so a merge that is run twice won't pick up
+    // the copies that it removed the first time
+    let (tx, rx) = channel();
+
+    db_svc_sender
+        .send(
+            DbMsg::SearchMediaInLibrary {
+                resp: tx,
+                gid: HashSet::from([library.gid]),
+                library_uuid,
+                hidden: None,
+                filter: SearchFilter::default(),
+            }
+            .into(),
+        )
+        .await?;
+
+    let media_in_library = rx.await??;
+
+    debug!(
+        { count = media_in_library.len() },
+        "library dedup beginning database walk"
+    );
+
+    let mut warnings = 0;
+
+    let mut entries = Vec::new();
+
+    for media_uuid in media_in_library {
+        if cancel.is_cancelled() {
+            info!("library dedup cancelled");
+            return Ok(warnings);
+        }
+
+        let (tx, rx) = channel();
+
+        db_svc_sender
+            .send(
+                DbMsg::GetMedia {
+                    resp: tx,
+                    media_uuid,
+                }
+                .into(),
+            )
+            .await?;
+
+        let Some((media, collections, _)) = rx.await?? else {
+            warn!({ %media_uuid }, "media vanished during dedup");
+            warnings += 1;
+            continue;
+        };
+
+        entries.push(DedupEntry {
+            media_uuid,
+            media,
+            collections,
+        });
+    }
+
+    let clusters = cluster_duplicates(entries);
+
+    let copies = clusters
+        .iter()
+        .map(|entries| entries.len() - 1)
+        .sum::<usize>();
+
+    let reclaimable = clusters
+        .iter()
+        .flat_map(|entries| entries.iter().skip(1))
+        .map(|entry| entry.media.size)
+        .sum::<u64>();
+
+    let mut merged = 0;
+
+    if merge {
+        for entries in clusters.iter() {
+            if cancel.is_cancelled() {
+                info!("library dedup cancelled, leaving remaining duplicates");
+                break;
+            }
+
+            match merge_cluster(&db_svc_sender, &auth_svc_sender, entries).await {
+                Ok(()) => merged += 1,
+                Err(err) => {
+                    warn!("dedup merge error: {err:?}");
+                    warnings += 1;
+                }
+            }
+        }
+    }
+
+    let mut summary = format!(
+        "{} groups of duplicates with {copies} extra copies ({} MiB reclaimable)",
+        clusters.len(),
+        reclaimable / (1024 * 1024)
+    );
+
+    if merge {
+        summary.push_str(&format!(", {merged} groups merged"));
+    }
+
+    report_summary(
+        &task_svc_sender,
+        TaskLibrary::User { library_uuid },
+        summary,
+    )
+    .await;
+
+    Ok(warnings)
+}
+
+// group the entries by content hash, keeping only the groups with more than one copy
+//
+// the survivor goes first in each group.  it is the visible copy in the most collections,
+// which is the one that users are most likely to have linked to, falling back to the uuid
+// for a stable choice.  the groups are in survivor order, so that a merge that stops
+// partway always gets through the same ones first.
+fn cluster_duplicates(entries: Vec<DedupEntry>) -> Vec<Vec<DedupEntry>> {
+    let mut by_chash: HashMap<String, Vec<DedupEntry>> = HashMap::new();
+
+    for entry in entries {
+        by_chash
+            .entry(entry.media.chash.clone())
+            .or_default()
+            .push(entry);
+    }
+
+    let mut clusters = by_chash
+        .into_values()
+        .filter(|entries| entries.len() > 1)
+        .collect::<Vec<_>>();
+
+    for entries in clusters.iter_mut() {
+        entries.sort_by_key(|entry| {
+            (
+                entry.media.hidden,
+                Reverse(entry.collections.len()),
+                entry.media_uuid,
+            )
+        });
+    }
+
+    clusters.sort_by_key(|entries| entries[0].media_uuid);
+
+    clusters
+}
+
+#[instrument(skip_all, fields(survivor = %entries[0].media_uuid))]
+async fn merge_cluster(
+    db_svc_sender: &EsmSender,
+    auth_svc_sender: &EsmSender,
+    entries: &[DedupEntry],
+) -> Result<()> {
+    let (survivor, duplicates) = entries
+        .split_first()
+        .ok_or_else(|| anyhow::Error::msg("internal error: empty duplicate group"))?;
+
+    // carry over collection membership, which also carries over access to the media
+    let mut collections = survivor.collections.iter().collect::<HashSet<_>>();
+
+    for collection_uuid in duplicates.iter().flat_map(|entry| entry.collections.iter()) {
+        if !collections.insert(collection_uuid) {
+            continue;
+        }
+
+        let (tx, rx) = channel();
+
+        db_svc_sender
+            .send(
+                DbMsg::AddMediaToCollection {
+                    resp: tx,
+                    media_uuid: survivor.media_uuid,
+                    collection_uuid: *collection_uuid,
+                }
+                .into(),
+            )
+            .await?;
+
+        rx.await??;
+    }
+
+    let tags_add = duplicates
+        .iter()
+        .flat_map(|entry| entry.media.tags.iter().cloned())
+        .filter(|tag| !survivor.media.tags.contains(tag))
+        .collect::<HashSet<_>>();
+
+    if !tags_add.is_empty() {
+        let (tx, rx) = channel();
+
+        db_svc_sender
+            .send(
+                DbMsg::UpdateMedia {
+                    resp: tx,
+                    media_uuid: survivor.media_uuid,
+                    update: MediaUpdate {
+                        hidden: None,
+                        date: None,
+                        note: None,
+                        tags: None,
+                        tags_add,
+                        tags_remove: HashSet::new(),
+                    },
+                }
+                .into(),
+            )
+            .await?;
+
+        rx.await??;
+    }
+
+    // the tag records where each copy went, so that it can be found in the trash
+    for duplicate in duplicates {
+        let (tx, rx) = channel();
+
+        db_svc_sender
+            .send(
+                DbMsg::UpdateMedia {
+                    resp: tx,
+                    media_uuid: duplicate.media_uuid,
+                    update: MediaUpdate {
+                        hidden: None,
+                        date: None,
+                        note: None,
+                        tags: None,
+                        tags_add: HashSet::from([format!("DUPLICATE:{}", survivor.media_uuid)]),
+                        tags_remove: HashSet::new(),
+                    },
+                }
+                .into(),
+            )
+            .await?;
+
+        rx.await??;
+
+        let (tx, rx) = channel();
+
+        db_svc_sender
+            .send(
+                DbMsg::SoftDeleteMedia {
+                    resp: tx,
+                    media_uuid: duplicate.media_uuid,
+                }
+                .into(),
+            )
+            .await?;
+
+        rx.await??;
+    }
+
+    let (tx, rx) = channel();
+
+    auth_svc_sender
+        .send(
+            AuthMsg::ClearAccessCache {
+                resp: tx,
+                media_uuid: entries.iter().map(|entry| entry.media_uuid).collect(),
+            }
+            .into(),
+        )
+        .await?;
+
+    rx.await??;
+
+    debug!({ duplicates = duplicates.len() }, "merged duplicate group");
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use api::{UuidSource, media::MediaMetadata};
+
+    struct TestUuids;
+
+    impl UuidSource for TestUuids {}
+
+    fn uuid(n: u32) -> String {
+        format!("00000000-0000-0000-0000-{n:012}")
+    }
+
+    fn media_uuid(n: u32) -> MediaUuid {
+        MediaUuid::try_parse(&TestUuids, &uuid(n)).unwrap()
+    }
+
+    fn entry(n: u32, chash: &str, hidden: bool, collections: u32) -> DedupEntry {
+        DedupEntry {
+            media_uuid: media_uuid(n),
+            media: Media {
+                library_uuid: LibraryUuid::try_parse(&TestUuids, &uuid(0)).unwrap(),
+                path: format!("{n}.jpg"),
+                size: 1024,
+                chash: chash.to_owned(),
+                phash: String::from("phash"),
+                mtime: 1_700_000_000,
+                hidden,
+                date: String::new(),
+                note: String::new(),
+                tags: HashSet::new(),
+                metadata: MediaMetadata::Image,
+                latitude: None,
+                longitude: None,
+                width: None,
+                height: None,
+                camera: None,
+                orientation: None,
+                capture_time: None,
+                duration_secs: None,
+                codec: None,
+            },
+            collections: (0..collections)
+                .map(|c| CollectionUuid::try_parse(&TestUuids, &uuid(c)).unwrap())
+                .collect(),
+        }
+    }
+
+    fn order(clusters: &[Vec<DedupEntry>]) -> Vec<Vec<MediaUuid>> {
+        clusters
+            .iter()
+            .map(|entries| entries.iter().map(|entry| entry.media_uuid).collect())
+            .collect()
+    }
+
+    fn uuids(ns: &[u32]) -> Vec<MediaUuid> {
+        ns.iter().map(|n| media_uuid(*n)).collect()
+    }
+
+    #[test]
+    fn groups_copies_by_chash() {
+        let clusters = cluster_duplicates(vec![
+            entry(1, "a", false, 0),
+            entry(2, "b", false, 0),
+            entry(3, "a", false, 0),
+            entry(4, "c", false, 0),
+            entry(5, "b", false, 0),
+            entry(6, "b", false, 0),
+        ]);
+
+        // "c" has no copies, so it is left out
+        assert_eq!(order(&clusters), vec![uuids(&[1, 3]), uuids(&[2, 5, 6])]);
+    }
+
+    #[test]
+    fn survivor_is_in_the_most_collections() {
+        let clusters = cluster_duplicates(vec![
+            entry(1, "a", false, 1),
+            entry(2, "a", false, 3),
+            entry(3, "a", false, 0),
+            entry(4, "a", false, 3),
+        ]);
+
+        // ties fall back to the uuid
+        assert_eq!(order(&clusters), vec![uuids(&[2, 4, 1, 3])]);
+    }
+
+    #[test]
+    fn hidden_copies_go_last() {
+        let clusters = cluster_duplicates(vec![
+            entry(1, "a", true, 5),
+            entry(2, "a", false, 0),
+            entry(3, "a", true, 0),
+            entry(4, "a", false, 1),
+        ]);
+
+        assert_eq!(order(&clusters), vec![uuids(&[4, 2, 1, 3])]);
+    }
+}
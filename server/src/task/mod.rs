@@ -2,17 +2,17 @@ use anyhow::Result;
 use async_trait::async_trait;
 
 use crate::service::ESInner;
-use api::task::{Task, TaskLibrary, TaskStatus, TaskType, TaskUid};
+use api::task::{Task, TaskLibrary, TaskProgress, TaskStatus, TaskType, TaskUid};
 
 mod clean;
+mod dateparse;
+mod dedup;
 pub mod msg;
 mod scan;
-mod scan_utils;
+pub mod scan_utils;
 mod scrub;
 pub mod svc;
-
-// pub mod dedup;
-// pub mod dateparse;
+mod verify;
 
 #[async_trait]
 pub trait ESTaskService: ESInner {
@@ -27,6 +27,10 @@ pub trait ESTaskService: ESInner {
 
     async fn show_tasks(&self, library: TaskLibrary) -> Result<Vec<Task>>;
 
+    async fn update_progress(&self, library: TaskLibrary, progress: TaskProgress) -> Result<()>;
+
+    async fn update_summary(&self, library: TaskLibrary, summary: String) -> Result<()>;
+
     async fn complete_task(
         &self,
         library: TaskLibrary,
@@ -1,7 +1,7 @@
 use crate::service::{Esm, EsmResp};
 use api::task::*;
 
-#[derive(Debug)]
+#[derive(Debug, strum::IntoStaticStr)]
 pub enum TaskMsg {
     StartTask {
         resp: EsmResp<()>,
@@ -17,6 +17,16 @@ pub enum TaskMsg {
         resp: EsmResp<Vec<Task>>,
         library: TaskLibrary,
     },
+    UpdateProgress {
+        resp: EsmResp<()>,
+        library: TaskLibrary,
+        progress: TaskProgress,
+    },
+    UpdateSummary {
+        resp: EsmResp<()>,
+        library: TaskLibrary,
+        summary: String,
+    },
     CompleteTask {
         resp: EsmResp<()>,
         library: TaskLibrary,
@@ -1,5 +1,5 @@
 use std::{
-    path::PathBuf,
+    path::{Path, PathBuf},
     sync::{
         Arc,
         atomic::{AtomicI64, Ordering},
@@ -10,17 +10,29 @@ use std::{
 use anyhow::Result;
 use dashmap::DashSet;
 
-use tokio::{fs::create_dir_all, sync::oneshot::channel, task::JoinSet, time::timeout};
+use tokio::{
+    fs::create_dir_all,
+    sync::{Semaphore, oneshot::channel},
+    task::{JoinSet, spawn_blocking},
+    time::timeout,
+};
+use tokio_util::sync::CancellationToken;
 use tracing::{Instrument, Level, debug, error, info, instrument, span, warn};
 use walkdir::WalkDir;
 
 use crate::{
     db::msg::DbMsg,
     service::{ESMRegistry, ServiceType},
-    task::scan_utils::{FileStatus, ScanContext, ScanFile, get_path_and_metadata},
+    task::scan_utils::{
+        FileStatus, MediaBatcher, ProgressReporter, ScanContext, ScanFile, get_path_and_metadata,
+        report_summary,
+    },
+};
+use api::{
+    library::{LibraryUpdate, LibraryUuid},
+    task::TaskLibrary,
 };
-use api::library::{LibraryUpdate, LibraryUuid};
-use common::config::ESConfig;
+use common::{config::ESConfig, storage::create_storage};
 
 // library scanner task
 //
@@ -29,12 +41,23 @@ use common::config::ESConfig;
 // symlinks so that transfer services can access the files.
 //
 // in its current implementation, the only critical failures (that return Err) are in the setup,
-// or with the database connection -- any per-file problems are reported back as warnings.
-#[instrument(skip(config, registry))]
+// or with the database connection -- any per-file problems (including unreadable directory
+// entries) are reported back as warnings, and the paths that failed go into the task summary.
+//
+// files whose size and mtime match their records are skipped without hashing, unless rehash
+// is set (the ScanLibraryFull task).
+//
+// if the scan is cancelled, it finishes the files already in flight and then returns.  new
+// files are added to the database in small batches as they finish processing, so everything
+// registered up to that point is kept, but the deduplication and library count wait for a
+// complete scan.
+#[instrument(skip(config, registry, cancel))]
 pub async fn scan_library(
     config: Arc<ESConfig>,
     registry: ESMRegistry,
     library_uuid: LibraryUuid,
+    rehash: bool,
+    cancel: CancellationToken,
 ) -> Result<i64> {
     debug!("library scan pre-startup verification");
 
@@ -62,16 +85,29 @@ pub async fn scan_library(
         db_svc_sender: db_svc_sender.clone(),
         file_count: AtomicI64::new(0),
         warnings: AtomicI64::new(0),
+        failed_files: DashSet::new(),
         known_files: DashSet::new(),
         scratch_base: config
             .task
             .scan_scratch
             .clone()
             .join(library_uuid.to_string()),
+        rehash,
+        storage: create_storage(config.clone())?,
+        media_batcher: Some(MediaBatcher::new(
+            db_svc_sender.clone(),
+            config.task.scan_threads,
+        )),
     });
 
     create_dir_all(&context.scratch_base).await?;
 
+    // the number of files in flight is capped so that a large scan can't swamp the media
+    // server.  the hashing and image work happen under spawn_blocking(), so the limit also
+    // bounds how much of the blocking pool a scan can hold at once, while the runtime
+    // workers stay free for the http server.
+    let semaphore = Arc::new(Semaphore::new(config.task.scan_threads.max(1)));
+
     let mut tasks: JoinSet<()> = JoinSet::new();
 
     let scan_timeout = Duration::from_secs(context.config.task.scan_timeout);
@@ -85,6 +121,28 @@ pub async fn scan_library(
 
     let library_root = config.fs.media_srcdir.clone().join(library.path);
 
+    // count the files up front so that progress can be reported as a fraction.  this only
+    // reads the directory tree, which is quick next to hashing every new file.
+    let total = spawn_blocking({
+        let library_root = library_root.clone();
+
+        move || {
+            WalkDir::new(library_root)
+                .same_file_system(true)
+                .into_iter()
+                .filter_map(|entry| entry.ok())
+                .filter(|entry| entry.path().is_file())
+                .count()
+        }
+    })
+    .await?;
+
+    let task_svc_sender = registry.get(&ServiceType::Task)?;
+    let task_library = TaskLibrary::User { library_uuid };
+
+    let mut progress =
+        ProgressReporter::new(task_svc_sender.clone(), task_library, total.try_into()?);
+
     //
     // scan phase one
     //
@@ -96,7 +154,7 @@ pub async fn scan_library(
     // whose path is known and hasn't been modified
     info!({?library_root}, "library scan phase one: filesystem walk and adding new media");
 
-    for entry in WalkDir::new(library_root)
+    for entry in WalkDir::new(&library_root)
         .same_file_system(true)
         .contents_first(true)
         .into_iter()
@@ -107,12 +165,14 @@ pub async fn scan_library(
             return Err(anyhow::Error::msg("database esm channel dropped"));
         };
 
-        // we allow this to be configurable so that we don't swamp the media server when registering
-        // a large collection of media
-        while tasks.len() > config.task.scan_threads {
-            tasks.join_next().await;
+        if cancel.is_cancelled() {
+            info!("library scan cancelled, waiting for files in progress");
+            break;
         }
 
+        // the finished tasks have nothing to report, so they only need to be reaped
+        while tasks.try_join_next().is_some() {}
+
         // process the media and register it with the database
         //
         // to allow for easy error propagation, we let register_media() return Result<()> and then
@@ -120,47 +180,62 @@ pub async fn scan_library(
         //
         // importantly, those warnings should be attached to the span associated with path, so we
         // set up the span outside instead of using #[instrument]
-        let (path, metadata) = get_path_and_metadata(entry).await?;
+        let entry_path = match &entry {
+            Ok(v) => v.path().to_path_buf(),
+            Err(err) => err.path().map(Path::to_path_buf).unwrap_or_default(),
+        };
+
+        let (path, metadata) = match get_path_and_metadata(entry).await {
+            Ok(v) => v,
+            Err(err) => {
+                context.file_failed(entry_path, err);
+                continue;
+            }
+        };
 
         if metadata.is_file() {
-            tasks.spawn({
-                let context = context.clone();
+            progress.advance().await;
+
+            // waiting for the permit here, rather than in the task, keeps the walk from
+            // racing ahead of the files in flight
+            let permit = semaphore.clone().acquire_owned().await?;
 
+            let context = context.clone();
+
+            tasks.spawn(async move {
+                let _permit = permit;
+
+                // the content hash is the expensive part of this, so it has to count
+                // against the limit along with the rest of the processing
                 let file = match ScanFile::from_path(context.clone(), path.clone(), metadata).await
                 {
                     Ok(ok) => match ok {
                         FileStatus::Register(v) => v,
                         FileStatus::Exists(file) => {
                             context.known_files.insert(file);
-                            continue;
+                            return;
                         }
                         FileStatus::Skip => {
                             context.file_count.fetch_add(1, Ordering::Relaxed);
-                            continue;
+                            return;
                         }
-                        FileStatus::Unknown => continue,
+                        FileStatus::Unknown => return,
                     },
                     Err(err) => {
-                        warn!("scan error: {err:?}");
-                        context.warnings.fetch_add(1, Ordering::Relaxed);
-                        continue
+                        context.file_failed(path, err);
+                        return;
                     }
                 };
 
-                async move {
-                    match timeout(scan_timeout, file.register())
-                        .await
-                        .map_err(|_| anyhow::Error::msg("scan exceeded timeout"))
-                    {
-                        Ok(Ok(Some(_))) => {
-                            context.file_count.fetch_add(1, Ordering::Relaxed);
-                        }
-                        Ok(Ok(None)) => {}
-                        Ok(Err(err)) | Err(err) => {
-                            warn!("scan error: {err:?}");
-                            context.warnings.fetch_add(1, Ordering::Relaxed);
-                        }
+                match timeout(scan_timeout, file.register())
+                    .await
+                    .map_err(|_| anyhow::Error::msg("scan exceeded timeout"))
+                {
+                    Ok(Ok(Some(_))) => {
+                        context.file_count.fetch_add(1, Ordering::Relaxed);
                     }
+                    Ok(Ok(None)) => {}
+                    Ok(Err(err)) | Err(err) => context.file_failed(path, err),
                 }
             });
         }
@@ -169,6 +244,19 @@ pub async fn scan_library(
     // wait for phase one to complete
     tasks.join_all().await;
 
+    progress.report().await;
+
+    if cancel.is_cancelled() {
+        report_summary(
+            &task_svc_sender,
+            task_library,
+            scan_summary(&context, &library_root),
+        )
+        .await;
+
+        return Ok(context.warnings.load(Ordering::Relaxed));
+    }
+
     //
     // scan phase two
     //
@@ -192,12 +280,18 @@ pub async fn scan_library(
             return Err(anyhow::Error::msg("database esm channel dropped"));
         };
 
-        while tasks.len() > config.task.scan_threads {
-            tasks.join_next().await;
+        if cancel.is_cancelled() {
+            info!("library scan cancelled, waiting for media in progress");
+            break;
         }
 
+        let permit = semaphore.clone().acquire_owned().await?;
+
+        while tasks.try_join_next().is_some() {}
+
         tasks.spawn(
             async move {
+                let _permit = permit;
                 let context = context.clone();
 
                 match timeout(scan_timeout, context.resolve_duplicates(media_uuid, files))
@@ -218,6 +312,17 @@ pub async fn scan_library(
     // wait for phase two to complete
     tasks.join_all().await;
 
+    report_summary(
+        &task_svc_sender,
+        task_library,
+        scan_summary(&context, &library_root),
+    )
+    .await;
+
+    if cancel.is_cancelled() {
+        return Ok(context.warnings.load(Ordering::Relaxed));
+    }
+
     let file_count = context.file_count.load(Ordering::Relaxed);
     let warnings = context.warnings.load(Ordering::Relaxed);
 
@@ -231,6 +336,8 @@ pub async fn scan_library(
                 resp: tx,
                 library_uuid,
                 update: LibraryUpdate {
+                    name: None,
+                    note: None,
                     count: Some(file_count),
                 },
             }
@@ -242,3 +349,45 @@ pub async fn scan_library(
 
     Ok(warnings)
 }
+
+// the summary only has room for so many paths, and the rest are in the logs anyway
+const MAX_SUMMARY_FAILURES: usize = 20;
+
+fn scan_summary(context: &ScanContext, library_root: &Path) -> String {
+    let mut summary = format!(
+        "{} files indexed, {} failed",
+        context.file_count.load(Ordering::Relaxed),
+        context.failed_files.len()
+    );
+
+    if context.failed_files.is_empty() {
+        return summary;
+    }
+
+    let mut failed = context
+        .failed_files
+        .iter()
+        .map(|v| {
+            let path = v.key();
+
+            path.strip_prefix(library_root)
+                .unwrap_or(path)
+                .to_string_lossy()
+                .into_owned()
+        })
+        .collect::<Vec<String>>();
+
+    failed.sort();
+
+    summary.push_str(": ");
+    summary.push_str(&failed[..failed.len().min(MAX_SUMMARY_FAILURES)].join(", "));
+
+    if failed.len() > MAX_SUMMARY_FAILURES {
+        summary.push_str(&format!(
+            " (and {} more)",
+            failed.len() - MAX_SUMMARY_FAILURES
+        ));
+    }
+
+    summary
+}
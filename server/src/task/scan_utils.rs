@@ -6,31 +6,40 @@ use std::{
         Arc,
         atomic::{AtomicI64, Ordering},
     },
-    time::UNIX_EPOCH,
+    time::{Duration, Instant, UNIX_EPOCH},
 };
 
 use anyhow::Result;
 use dashmap::{DashMap, DashSet};
-use tokio::fs::{canonicalize, create_dir_all, metadata, remove_file, symlink};
-use tracing::{Level, debug, instrument, span, warn};
+use tokio::{
+    fs::{canonicalize, create_dir_all, metadata, try_exists},
+    sync::{Mutex, oneshot},
+    task::spawn,
+    time::interval,
+};
+use tracing::{Level, debug, info, instrument, span, warn};
 use walkdir::DirEntry;
 
-use crate::{
-    db::msg::DbMsg,
-    fs::{media_link_path, media_thumbnail_path},
-    service::EsmSender,
-};
+use crate::{db::msg::DbMsg, fs::store_thumbnail, service::EsmSender, task::msg::TaskMsg};
 use api::{
     FOLDING_SEPARATOR,
     library::LibraryUuid,
-    media::{Media, MediaMetadata, MediaUpdate, MediaUuid},
+    media::{Media, MediaMetadata, MediaUpdate, MediaUuid, ThumbnailSize},
+    task::{TaskLibrary, TaskProgress},
 };
 use common::{
     config::ESConfig,
     db::{MediaByCHash, MediaByPath},
     media::{
-        MediaData, content_hash, create_thumbnail, image::process_image, video::process_video,
+        HashAlgorithm, MediaData, MediaDetails,
+        audio::process_audio,
+        content_hash,
+        image::process_image,
+        raw::{RAW_EXTENSIONS, RawStrategy, process_raw},
+        video::process_video,
     },
+    storage::{StorageBackend, create_storage},
+    unix_time,
 };
 
 // scan_utils
@@ -53,12 +62,15 @@ pub struct KnownFile {
     pub path: String,
     pub hash: String,
     pub mtime: u64,
+    pub size: u64,
 }
 
 #[derive(Clone, Debug)]
 enum MediaType {
     Image,
+    Raw,
     Video,
+    Audio,
 }
 
 pub async fn get_path_and_metadata(
@@ -75,44 +87,24 @@ pub async fn add_tag_to_media(
     media_uuid: MediaUuid,
     new_tag: String,
 ) -> Result<()> {
-    let (tx, rx) = tokio::sync::oneshot::channel();
-
-    db_svc_sender
-        .send(
-            DbMsg::GetMedia {
-                resp: tx,
-                media_uuid,
-            }
-            .into(),
-        )
-        .await?;
-
-    let (media, _, _) = rx
-        .await??
-        .ok_or_else(|| anyhow::Error::msg(format!("unknown media_uuid: {media_uuid}")))?;
-
-    let (tx, rx) = tokio::sync::oneshot::channel();
-
-    let mut tags = media.tags.clone();
-
     if new_tag.contains(FOLDING_SEPARATOR) {
         return Err(anyhow::Error::msg(
             "internal error: new tag contains folding seperator",
         ));
     }
 
-    tags.insert(new_tag);
+    let (tx, rx) = tokio::sync::oneshot::channel();
 
+    // the database adds the tag to whatever is stored, so a concurrent edit from the
+    // webapp can't be lost
     db_svc_sender
         .send(
             DbMsg::UpdateMedia {
                 resp: tx,
                 media_uuid,
                 update: MediaUpdate {
-                    hidden: None,
-                    date: None,
-                    note: None,
-                    tags: Some(tags),
+                    tags_add: HashSet::from([new_tag]),
+                    ..Default::default()
                 },
             }
             .into(),
@@ -129,7 +121,8 @@ pub async fn add_tag_to_media(
 // this list is not even close to exhaustive, and future improvements are expected.
 //
 // note that the extensions are also used by the http service to guess the mime type of
-// the media files; see http/stream.rs for details.
+// the media files; see http/stream.rs for details.  the webapp checks uploads against
+// api::media::UPLOAD_MEDIA_EXTENSIONS, so any changes here need to be made there as well.
 fn get_mtype(path: &Path) -> Result<MediaType> {
     let ext = path
         .extension()
@@ -138,12 +131,269 @@ fn get_mtype(path: &Path) -> Result<MediaType> {
         .ok_or_else(|| anyhow::Error::msg("failed to extract file extention"))?;
 
     match ext.as_str() {
-        "jpg" | "png" | "tiff" => Ok(MediaType::Image),
+        "jpg" | "png" | "tiff" | "heic" | "heif" => Ok(MediaType::Image),
+        v if RAW_EXTENSIONS.contains(&v) => Ok(MediaType::Raw),
         "avi" | "mov" | "mp4" => Ok(MediaType::Video),
+        "mp3" | "flac" | "ogg" | "opus" | "m4a" | "wav" => Ok(MediaType::Audio),
         _ => Err(anyhow::Error::msg(format!("unknown media extention {ext}"))),
     }
 }
 
+// whether a scan would pick up the file, judging by its extension
+pub fn is_media_path(path: &Path) -> bool {
+    get_mtype(path).is_ok()
+}
+
+// register a single file outside of a library scan, i.e. one that was just uploaded
+//
+// this goes through the same checks and processing as the scanner, and returns None if
+// the file turned out to be known already.  scratch_base is removed afterwards, so it
+// should not be shared.
+#[instrument(skip(config, db_svc_sender, scratch_base))]
+pub async fn ingest_file(
+    config: Arc<ESConfig>,
+    db_svc_sender: EsmSender,
+    library_uuid: LibraryUuid,
+    path: PathBuf,
+    scratch_base: PathBuf,
+) -> Result<Option<MediaUuid>> {
+    create_dir_all(&scratch_base).await?;
+
+    let context = Arc::new(ScanContext {
+        storage: create_storage(config.clone())?,
+        config,
+        library_uuid,
+        db_svc_sender,
+        file_count: AtomicI64::new(0),
+        warnings: AtomicI64::new(0),
+        failed_files: DashSet::new(),
+        known_files: DashSet::new(),
+        scratch_base,
+        rehash: false,
+        media_batcher: None,
+    });
+
+    // the scanner canonicalizes paths, so the records have to match
+    let path = canonicalize(path).await?;
+    let metadata = metadata(&path).await?;
+
+    match ScanFile::from_path(context, path, metadata).await? {
+        FileStatus::Register(file) => file.register().await,
+        FileStatus::Exists(_) | FileStatus::Skip => Ok(None),
+        FileStatus::Unknown => Err(anyhow::Error::msg("unknown media type")),
+    }
+}
+
+// throttled progress reports to the task service
+//
+// a large library has tens of thousands of files, and a message for each one would be
+// mostly noise.  instead, an update goes out every PROGRESS_FILES files or after
+// PROGRESS_INTERVAL, whichever comes first.
+const PROGRESS_FILES: u64 = 250;
+const PROGRESS_INTERVAL: Duration = Duration::from_secs(5);
+
+#[derive(Debug)]
+pub struct ProgressReporter {
+    task_svc_sender: EsmSender,
+    library: TaskLibrary,
+    processed: u64,
+    total: u64,
+    last: Instant,
+}
+
+impl ProgressReporter {
+    pub fn new(task_svc_sender: EsmSender, library: TaskLibrary, total: u64) -> Self {
+        ProgressReporter {
+            task_svc_sender,
+            library,
+            processed: 0,
+            total,
+            last: Instant::now(),
+        }
+    }
+
+    pub async fn advance(&mut self) {
+        self.processed += 1;
+
+        if self.processed.is_multiple_of(PROGRESS_FILES) || self.last.elapsed() >= PROGRESS_INTERVAL
+        {
+            self.report().await;
+        }
+    }
+
+    // progress is informational, so failing to report it shouldn't stop the task
+    pub async fn report(&mut self) {
+        self.last = Instant::now();
+
+        let progress = TaskProgress {
+            processed: self.processed,
+            // files can appear after they were counted
+            total: self.total.max(self.processed),
+            updated: unix_time(),
+        };
+
+        let (tx, rx) = tokio::sync::oneshot::channel();
+
+        let result = async {
+            self.task_svc_sender
+                .send(
+                    TaskMsg::UpdateProgress {
+                        resp: tx,
+                        library: self.library,
+                        progress,
+                    }
+                    .into(),
+                )
+                .await?;
+
+            rx.await??;
+
+            Result::<()>::Ok(())
+        }
+        .await;
+
+        if let Err(err) = result {
+            warn!("failed to report task progress: {err}");
+        }
+    }
+}
+
+// attach a summary to the running task, which is kept once it completes
+pub async fn report_summary(task_svc_sender: &EsmSender, library: TaskLibrary, summary: String) {
+    info!({ summary }, "task summary");
+
+    let (tx, rx) = tokio::sync::oneshot::channel();
+
+    let result = async {
+        task_svc_sender
+            .send(
+                TaskMsg::UpdateSummary {
+                    resp: tx,
+                    library,
+                    summary,
+                }
+                .into(),
+            )
+            .await?;
+
+        rx.await??;
+
+        Result::<()>::Ok(())
+    }
+    .await;
+
+    if let Err(err) = result {
+        warn!("failed to report task summary: {err}");
+    }
+}
+
+// batched media inserts
+//
+// each insert costs a round trip and a commit (and, for mariadb, the table locks), which
+// adds up once a scan is registering thousands of new files.  instead, new media wait
+// here until a batch fills up or MEDIA_BATCH_INTERVAL passes, and then go to the database
+// together.  each file still waits on its own uuid, since it needs it for install().
+//
+// the batch size is capped at scan_threads, since no more files than that can be waiting.
+const MEDIA_BATCH_SIZE: usize = 64;
+const MEDIA_BATCH_INTERVAL: Duration = Duration::from_millis(100);
+
+type PendingMedia = (Media, oneshot::Sender<Result<Option<MediaUuid>>>);
+
+#[derive(Debug)]
+pub struct MediaBatcher {
+    db_svc_sender: EsmSender,
+    batch_size: usize,
+    pending: Mutex<Vec<PendingMedia>>,
+}
+
+impl MediaBatcher {
+    // must be called from within the runtime, since it spawns the periodic flush
+    pub fn new(db_svc_sender: EsmSender, scan_threads: usize) -> Arc<Self> {
+        let batcher = Arc::new(MediaBatcher {
+            db_svc_sender,
+            batch_size: scan_threads.clamp(1, MEDIA_BATCH_SIZE),
+            pending: Mutex::new(Vec::new()),
+        });
+
+        // the flush task only holds a weak reference, so it stops once the scan is done
+        let weak = Arc::downgrade(&batcher);
+
+        spawn(async move {
+            let mut interval = interval(MEDIA_BATCH_INTERVAL);
+
+            loop {
+                interval.tick().await;
+
+                let Some(batcher) = weak.upgrade() else {
+                    break;
+                };
+
+                batcher.flush().await;
+            }
+        });
+
+        batcher
+    }
+
+    // queue the media for the next batch, returning None if its path was already known
+    pub async fn add(&self, media: Media) -> Result<Option<MediaUuid>> {
+        let (tx, rx) = oneshot::channel();
+
+        let full = {
+            let mut pending = self.pending.lock().await;
+            pending.push((media, tx));
+            pending.len() >= self.batch_size
+        };
+
+        if full {
+            self.flush().await;
+        }
+
+        rx.await?
+    }
+
+    #[instrument(skip_all)]
+    async fn flush(&self) {
+        let batch = std::mem::take(&mut *self.pending.lock().await);
+
+        if batch.is_empty() {
+            return;
+        }
+
+        debug!({ count = batch.len() }, "flushing media batch");
+
+        let (media, senders): (Vec<Media>, Vec<_>) = batch.into_iter().unzip();
+
+        let result = async {
+            let (tx, rx) = oneshot::channel();
+
+            self.db_svc_sender
+                .send(DbMsg::AddMediaBatch { resp: tx, media }.into())
+                .await?;
+
+            rx.await?
+        }
+        .await;
+
+        // a closed receiver means that the file already timed out, which it reports itself
+        match result {
+            Ok(media_uuids) => {
+                for (sender, media_uuid) in senders.into_iter().zip(media_uuids) {
+                    let _ = sender.send(Ok(media_uuid));
+                }
+            }
+            Err(err) => {
+                let err = err.to_string();
+
+                for sender in senders {
+                    let _ = sender.send(Err(anyhow::Error::msg(err.clone())));
+                }
+            }
+        }
+    }
+}
+
 async fn create_scratch_dir(context: Arc<ScanContext>, chash: &str) -> Result<PathBuf> {
     let scratch_dir = context.scratch_base.join(chash);
 
@@ -163,8 +413,24 @@ pub struct ScanContext {
     pub db_svc_sender: EsmSender,
     pub file_count: AtomicI64,
     pub warnings: AtomicI64,
+    // the files behind the per-file warnings, for the task summary
+    pub failed_files: DashSet<PathBuf>,
     pub known_files: DashSet<KnownFile>,
     pub scratch_base: PathBuf,
+    // hash every file, even the ones that look unchanged
+    pub rehash: bool,
+    pub storage: Arc<dyn StorageBackend>,
+    // None adds each media on its own, which is what a single upload wants
+    pub media_batcher: Option<Arc<MediaBatcher>>,
+}
+
+impl ScanContext {
+    // a per-file failure is only a warning, since the rest of the scan can carry on
+    pub fn file_failed(&self, path: PathBuf, err: anyhow::Error) {
+        warn!({ ?path }, "scan error: {err:?}");
+        self.warnings.fetch_add(1, Ordering::Relaxed);
+        self.failed_files.insert(path);
+    }
 }
 
 impl Drop for ScanContext {
@@ -177,6 +443,10 @@ impl Drop for ScanContext {
 }
 
 impl ScanContext {
+    fn hash_algorithm(&self) -> HashAlgorithm {
+        self.config.task.hash_algorithm.clone().unwrap_or_default()
+    }
+
     #[instrument(skip_all)]
     async fn get_media_by_path(&self, pathstr: &str) -> Result<Option<MediaByPath>> {
         let (tx, rx) = tokio::sync::oneshot::channel();
@@ -247,6 +517,10 @@ impl ScanContext {
     //
     // in the event of a hash and path match (corresponding to moving the original file but leaving
     // a new file in the orignal path), clone the record for the new file
+    //
+    // an unchanged original is skipped by the walk, so it never makes it into the known files.
+    // if the original path still exists, then, the hash matches are copies rather than a move,
+    // and the record stays where it is.
     #[instrument(skip_all)]
     pub async fn resolve_duplicates(
         self: &Arc<Self>,
@@ -276,6 +550,13 @@ impl ScanContext {
             ))
         })?;
 
+        if !files.iter().any(|f| f.path == media.path)
+            && try_exists(PathBuf::from(&media.path)).await?
+        {
+            debug!({%media_uuid, path = media.path}, "original file still present, ignoring copies");
+            return Ok(());
+        }
+
         let parse_files = move |files: &Vec<KnownFile>| {
             // first check if the original object exists with a matching hash
             //
@@ -356,6 +637,7 @@ impl ScanContext {
                         path: file.path,
                         hash: file.hash,
                         mtime: file.mtime,
+                        size: file.size,
                     }
                     .into(),
                 )
@@ -452,6 +734,12 @@ impl Drop for ScanFile {
     }
 }
 
+// a record matched by path is up to date if the file has the same size and hasn't been
+// modified since, in which case the scan can skip the content hash
+fn is_up_to_date(media: &MediaByPath, mtime: u64, size: u64) -> bool {
+    media.mtime >= mtime && media.size == size
+}
+
 impl ScanFile {
     #[instrument(skip_all, fields(?path))]
     pub async fn from_path(
@@ -486,12 +774,14 @@ impl ScanFile {
         //
         // the first way that a file can be linked to a record in the database is by path
         //
-        // we can compare the mtime in the record with the file to know if the record is
-        // up-to-date, which in turn allows us to skip the content hash
+        // we can compare the mtime and size in the record with the file to know if the record
+        // is up-to-date, which in turn allows us to skip the content hash.  a full scan hashes
+        // anyway, which catches changes that kept both (or a clock that went backwards), and
+        // the dedup phase updates the record if the hash differs.
         let current = context.get_media_by_path(&pathstr).await?;
 
         if let Some(media) = current {
-            if media.mtime >= mtime {
+            if !context.rehash && is_up_to_date(&media, mtime, metadata.len()) {
                 debug!("record up to date");
 
                 return Ok(FileStatus::Skip);
@@ -501,15 +791,16 @@ impl ScanFile {
                 return Ok(FileStatus::Exists(KnownFile {
                     media_uuid: media.media_uuid,
                     path: pathstr.to_string(),
-                    hash: content_hash(&path).await?,
+                    hash: content_hash(&path, &context.hash_algorithm()).await?,
                     mtime,
+                    size: metadata.len(),
                 }));
             }
         }
 
         // calculate the content hash of the file, which is the expensive step,
         // and use it to create a unique scratch directory
-        let chash = content_hash(&path).await?;
+        let chash = content_hash(&path, &context.hash_algorithm()).await?;
 
         let scratch_dir = create_scratch_dir(context.clone(), &chash).await?;
 
@@ -572,6 +863,7 @@ impl ScanFile {
                 path: self.pathstr.to_string(),
                 hash: self.hash.clone(),
                 mtime: self.mtime,
+                size: self.metadata.len(),
             });
 
             return Ok(None);
@@ -582,11 +874,13 @@ impl ScanFile {
         // some of these use spawn_blocking() due to the underlying libraries
         let media_data: MediaData = match self.mtype {
             MediaType::Image => process_image(&self.path).await?,
+            MediaType::Raw => process_raw(&self.path, &self.raw_strategy()).await?,
             MediaType::Video => process_video(&self.path, &self.scratch_dir).await?,
+            MediaType::Audio => process_audio(&self.path).await?,
         };
 
         // once we have the metadata, we assemble the Media struct and send it to the database
-        let media = Media {
+        let mut media = Media {
             library_uuid: self.context.library_uuid,
             path: self.pathstr.clone(),
             size: self.metadata.len(),
@@ -598,17 +892,63 @@ impl ScanFile {
             note: "".to_owned(),
             tags: HashSet::new(),
             metadata: media_data.metadata.clone(),
+            latitude: None,
+            longitude: None,
+            width: None,
+            height: None,
+            camera: None,
+            orientation: None,
+            capture_time: None,
+            duration_secs: None,
+            codec: None,
         };
 
-        // add the media to the database and get the uuid
-        let (tx, rx) = tokio::sync::oneshot::channel();
-
-        self.context
-            .db_svc_sender
-            .send(DbMsg::AddMedia { resp: tx, media }.into())
-            .await?;
+        // each kind of media only fills in the details that it has
+        match media_data.details {
+            Some(MediaDetails::Image(details)) => {
+                media.latitude = details.latitude;
+                media.longitude = details.longitude;
+                media.width = details.width;
+                media.height = details.height;
+                media.camera = details.camera;
+                media.orientation = details.orientation;
+                media.capture_time = details.capture_time;
+            }
+            Some(MediaDetails::Video(details)) => {
+                media.width = details.width;
+                media.height = details.height;
+                media.duration_secs = details.duration_secs;
+                media.codec = details.codec;
+            }
+            Some(MediaDetails::Audio(details)) => {
+                media.duration_secs = details.duration_secs;
+            }
+            None => {}
+        }
 
-        let media_uuid = rx.await??;
+        // add the media to the database and get the uuid
+        let media_uuid = match &self.context.media_batcher {
+            Some(batcher) => batcher
+                .add(media)
+                .await?
+                .ok_or_else(|| anyhow::Error::msg("failed to add media"))?,
+            None => {
+                let (tx, rx) = tokio::sync::oneshot::channel();
+
+                self.context
+                    .db_svc_sender
+                    .send(
+                        DbMsg::AddMedia {
+                            resp: tx,
+                            media: Box::new(media),
+                        }
+                        .into(),
+                    )
+                    .await?;
+
+                rx.await??
+            }
+        };
 
         self.install(media_uuid, media_data.metadata).await?;
 
@@ -616,38 +956,86 @@ impl ScanFile {
         Ok(Some(media_uuid))
     }
 
+    fn raw_strategy(&self) -> RawStrategy {
+        self.context
+            .config
+            .task
+            .raw_strategy
+            .clone()
+            .unwrap_or_default()
+    }
+
     // media "installation"
     //
-    // to actually access the media, we use symlinks.  this allows the http server to function like
-    // an object store without needing to reorganize the filesystem. see also http/stream.rs.
+    // to actually access the media, it is put into the storage backend, which for the filesystem
+    // means symlinks.  this allows the http server to function like an object store without needing
+    // to reorganize the filesystem. see also http/stream.rs.
     //
     // currently this part consists of two steps, but in principle any postprocessing needed to use
     // media should go here as well.  it may be that we split out this function if its internals are
     // useful for the dedup or cleaning tasks.
     #[instrument(skip(self, media_metadata))]
     async fn install(&self, media_uuid: MediaUuid, media_metadata: MediaMetadata) -> Result<()> {
-        debug!("creating symlinks and thumbnails");
-
-        // symlink
-        let symlink_path = media_link_path(self.context.config.clone(), media_uuid);
+        debug!("storing original and thumbnails");
 
-        let _ = remove_file(&symlink_path).await;
-
-        symlink(&self.path, &symlink_path).await?;
-
-        // thumbnail
-        let thumbnail_path = media_thumbnail_path(self.context.config.clone(), media_uuid);
-
-        let _ = remove_file(&thumbnail_path).await;
+        self.context
+            .storage
+            .put_original(media_uuid, &self.path)
+            .await?;
 
-        create_thumbnail(
+        store_thumbnail(
+            self.context.storage.as_ref(),
             &self.path,
-            &thumbnail_path,
             &self.scratch_dir,
             &media_metadata,
+            &self.raw_strategy(),
+            media_uuid,
+            ThumbnailSize::Grid,
         )
         .await?;
 
         Ok(())
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use api::UuidSource;
+
+    struct TestUuids;
+
+    impl UuidSource for TestUuids {}
+
+    const MTIME: u64 = 1_700_000_000;
+
+    fn record(mtime: u64, size: u64) -> MediaByPath {
+        MediaByPath {
+            media_uuid: MediaUuid::from_value(&TestUuids, Default::default()),
+            hash: String::from("chash"),
+            mtime,
+            size,
+        }
+    }
+
+    #[test]
+    fn unchanged_files_are_skipped() {
+        assert!(is_up_to_date(&record(MTIME, 1024), MTIME, 1024));
+
+        // the record was updated after the file was last written
+        assert!(is_up_to_date(&record(MTIME + 100, 1024), MTIME, 1024));
+    }
+
+    #[test]
+    fn modified_files_are_reprocessed() {
+        assert!(!is_up_to_date(&record(MTIME, 1024), MTIME + 1, 1024));
+        assert!(!is_up_to_date(&record(MTIME, 1024), MTIME + 1, 2048));
+    }
+
+    // e.g. a file rewritten by a tool that preserves the mtime
+    #[test]
+    fn resized_files_are_reprocessed() {
+        assert!(!is_up_to_date(&record(MTIME, 1024), MTIME, 2048));
+        assert!(!is_up_to_date(&record(MTIME + 100, 1024), MTIME, 512));
+    }
+}
@@ -1,34 +1,42 @@
-use std::{
-    collections::HashSet,
-    fs::Metadata,
-    path::Path,
-    sync::{
-        Arc,
-        atomic::{AtomicI64, Ordering},
-    },
-};
+use std::{collections::HashSet, sync::Arc};
 
 use anyhow::Result;
-use tokio::{
-    fs::{remove_dir_all, remove_file},
-    sync::oneshot::channel,
-};
-use tracing::{debug, error, instrument, warn};
-use walkdir::{DirEntry, WalkDir};
+use tokio::sync::oneshot::channel;
+use tokio_util::sync::CancellationToken;
+use tracing::{debug, info, instrument, warn};
 
 use crate::{
     db::msg::DbMsg,
     service::{ESMRegistry, ServiceType},
-    task::scan_utils::get_path_and_metadata,
+    task::scan_utils::report_summary,
+};
+use api::{
+    LINK_PATH, SLICE_PATH, THUMBNAIL_PATH, UuidSource,
+    media::{MediaUuid, ThumbnailSize},
+    task::TaskLibrary,
+};
+use common::{
+    config::{ESConfig, StorageBackend},
+    storage::create_storage,
 };
-use api::{LINK_PATH, THUMBNAIL_PATH, UuidSource, media::MediaUuid};
-use common::config::ESConfig;
 
-#[instrument(skip_all)]
-pub async fn cache_scrub(config: Arc<ESConfig>, registry: ESMRegistry) -> Result<i64> {
+// remove the objects in the srv directory that no longer belong to any media
+//
+// everything in there is named for a media_uuid (see the stream routes), so anything that
+// isn't is left over from purged media or a crashed scan.  only the storage backend is
+// touched, so the originals in media_srcdir are never at risk.  with dry_run, the orphans
+// are only counted.
+#[instrument(skip(config, registry, cancel))]
+pub async fn cache_scrub(
+    config: Arc<ESConfig>,
+    registry: ESMRegistry,
+    dry_run: bool,
+    cancel: CancellationToken,
+) -> Result<i64> {
     debug!("media cache clean pre-startup verification");
 
     let db_svc_sender = registry.get(&ServiceType::Db)?;
+    let task_svc_sender = registry.get(&ServiceType::Task)?;
 
     let (tx, rx) = channel();
 
@@ -38,97 +46,77 @@ pub async fn cache_scrub(config: Arc<ESConfig>, registry: ESMRegistry) -> Result
 
     let media_uuids = rx.await??.into_iter().collect::<HashSet<MediaUuid>>();
 
-    let warnings = Arc::new(AtomicI64::new(0));
-
-    // remove any links that do not reference a valid media_uuid
-    debug!("scrubbing link cache");
-
-    for entry in WalkDir::new(config.fs.media_srvdir.clone().join(LINK_PATH))
-        .same_file_system(true)
-        .max_depth(1)
-        .into_iter()
-    {
-        if let Err(err) = scrub_link(entry, &media_uuids).await {
-            warn!("link scrub error: {err}");
-            warnings.fetch_add(1, Ordering::Relaxed);
+    let mut warnings = 0;
+    let mut orphans = 0;
+    let mut reclaimed = 0;
+
+    let storage = create_storage(config.clone())?;
+
+    // the filesystem links are symlinks, so their size is really the size of the original
+    let links_use_space = !matches!(
+        config.storage_backend.clone().unwrap_or_default(),
+        StorageBackend::Fs
+    );
+
+    'scrub: for dir in [LINK_PATH, THUMBNAIL_PATH, SLICE_PATH] {
+        debug!({ dir }, "scrubbing cache");
+
+        for name in storage.list(dir).await? {
+            if cancel.is_cancelled() {
+                info!("cache scrub cancelled");
+                break 'scrub;
+            }
+
+            if valid_uuid(&name, &media_uuids) {
+                continue;
+            }
+
+            let length = match storage.info(dir, &name).await {
+                Ok(Some(info)) if dir != LINK_PATH || links_use_space => info.length,
+                _ => 0,
+            };
+
+            if dry_run {
+                debug!({ dir, name, length }, "found unknown object");
+            } else {
+                debug!({ dir, name, length }, "removing unknown object");
+
+                if let Err(err) = storage.delete(dir, &name).await {
+                    warn!({ dir, name }, "cache scrub error: {err}");
+                    warnings += 1;
+                    continue;
+                }
+            }
+
+            orphans += 1;
+            reclaimed += length;
         }
     }
 
-    // remove any thumbnails that do not reference a valid media_uuid
-    debug!("scrubbing thumbnail cache");
-
-    for entry in WalkDir::new(config.fs.media_srvdir.clone().join(THUMBNAIL_PATH))
-        .same_file_system(true)
-        .max_depth(1)
-        .into_iter()
-    {
-        if let Err(err) = scrub_thumbnail(entry, &media_uuids).await {
-            warn!("thumbnail scrub error: {err}");
-            warnings.fetch_add(1, Ordering::Relaxed);
-        }
-    }
+    let summary = format!(
+        "{orphans} orphaned objects {} ({} MiB)",
+        if dry_run { "found" } else { "removed" },
+        reclaimed / (1024 * 1024)
+    );
 
-    let warnings = warnings.load(Ordering::Relaxed);
+    report_summary(&task_svc_sender, TaskLibrary::System, summary).await;
 
     Ok(warnings)
 }
 
-#[instrument(skip_all)]
-async fn remove_path(path: &Path, metadata: &Metadata) -> Result<()> {
-    if metadata.is_file() || metadata.is_symlink() {
-        remove_file(path).await?
-    } else if metadata.is_dir() {
-        remove_dir_all(path).await?
-    } else {
-        error!("{path:?} is neither file nor directory");
-        return Err(anyhow::Error::msg(format!(
-            "internal error: {path:?} is neither file nor directory"
-        )));
-    }
-    Ok(())
-}
-
 struct PathUuidParser;
 
 impl UuidSource for PathUuidParser {}
 
-fn valid_uuid(path: &Path, media_uuids: &HashSet<MediaUuid>) -> bool {
-    match path.file_name().map(|s| MediaUuid::try_parse(&PathUuidParser, &s.to_string_lossy())) {
-        Some(Ok(v)) => media_uuids.contains(&v),
-        _ => false,
-    }
-}
-
-// cache scrubbers
-//
-// while these are very similar, we want to allow for differing logic when
-// cleaning the individual caches instead of having one big match statement
-#[instrument(skip_all)]
-async fn scrub_link(
-    entry: walkdir::Result<DirEntry>,
-    media_uuids: &HashSet<MediaUuid>,
-) -> Result<()> {
-    let (path, metadata) = get_path_and_metadata(entry).await?;
-
-    if !(metadata.is_symlink() && valid_uuid(&path, media_uuids)) {
-        debug!("removing {path:?}");
-        remove_path(&path, &metadata).await?;
-    }
-
-    Ok(())
-}
+fn valid_uuid(name: &str, media_uuids: &HashSet<MediaUuid>) -> bool {
+    // thumbnails may have a size suffix after the uuid, see ThumbnailSize::suffix()
+    let name = [ThumbnailSize::Preview, ThumbnailSize::Full]
+        .iter()
+        .find_map(|size| name.strip_suffix(size.suffix()))
+        .unwrap_or(name);
 
-#[instrument(skip_all)]
-async fn scrub_thumbnail(
-    entry: walkdir::Result<DirEntry>,
-    media_uuids: &HashSet<MediaUuid>,
-) -> Result<()> {
-    let (path, metadata) = get_path_and_metadata(entry).await?;
-
-    if !(metadata.is_file() && valid_uuid(&path, media_uuids)) {
-        debug!("removing {path:?}");
-        remove_path(&path, &metadata).await?;
+    match MediaUuid::try_parse(&PathUuidParser, name) {
+        Ok(v) => media_uuids.contains(&v),
+        Err(_) => false,
     }
-
-    Ok(())
 }
@@ -1,31 +1,44 @@
-use std::{pin::Pin, sync::Arc};
+use std::{pin::Pin, str::FromStr, sync::Arc, time::Duration};
 
 use anyhow::Result;
 use async_cell::sync::AsyncCell;
 use async_trait::async_trait;
+use chrono::Utc;
+use cron::Schedule;
 use dashmap::{DashMap, Entry};
 use futures::Future;
+use metrics::{counter, gauge};
 use ringbuffer::{AllocRingBuffer, RingBuffer};
 use tokio::{
     select,
     sync::{Mutex, RwLock},
     task::{JoinHandle, spawn},
+    time::{sleep, timeout},
 };
 use tokio_util::sync::CancellationToken;
-use tracing::{Instrument, Level, debug, error, info, instrument, span};
+use tracing::{Instrument, Level, debug, error, info, instrument, span, warn};
 
 use crate::{
     db::msg::DbMsg,
     debug::sleep_task,
     service::{
         ESInner, ESMRegistry, EntanglementService, Esm, EsmReceiver, EsmSender, ServiceType,
+        serve_messages,
     },
     task::{
-        ESTaskService, clean::clean_library, msg::TaskMsg, scan::scan_library, scrub::cache_scrub,
+        ESTaskService, clean::clean_library, dateparse::dateparse_library, dedup::dedup_library,
+        msg::TaskMsg, scan::scan_library, scrub::cache_scrub, verify::verify_library,
     },
 };
-use api::task::{Task, TaskLibrary, TaskStatus, TaskType, TaskUid};
-use common::{config::ESConfig, unix_time};
+use api::{
+    library::LibraryUuid,
+    task::{Task, TaskLibrary, TaskProgress, TaskStatus, TaskType, TaskUid},
+};
+use common::{
+    config::ESConfig,
+    unix_time,
+    webhook::{WebhookDispatcher, WebhookEvent},
+};
 
 // task service
 //
@@ -64,25 +77,24 @@ impl EntanglementService for TaskService {
         let receiver = Arc::clone(&self.receiver);
         let state = Arc::new(TaskRunner::new(self.config.clone(), registry.clone()).await?);
 
-        let serve = {
-            async move {
-                let mut receiver = receiver.lock().await;
-
-                while let Some(msg) = receiver.recv().await {
-                    let state = Arc::clone(&state);
-                    spawn(async move {
-                        match state.message_handler(msg).await {
-                            Ok(()) => (),
-                            Err(err) => {
-                                error!({service = "task", channel = "esm", error = %err})
-                            }
-                        }
-                    });
-                }
+        // the schedules are checked here so that a typo stops the server instead of
+        // silently never scanning
+        for scan_schedule in self.config.task.scan_schedule.clone().unwrap_or_default() {
+            let schedule = Schedule::from_str(&scan_schedule.cron).map_err(|err| {
+                anyhow::Error::msg(format!(
+                    "invalid scan schedule {} for library {}: {err}",
+                    scan_schedule.cron, scan_schedule.library_uuid
+                ))
+            })?;
 
-                Err(anyhow::Error::msg("task service esm channel disconnected"))
-            }
-        };
+            spawn(run_schedule(
+                state.clone(),
+                scan_schedule.library_uuid,
+                schedule,
+            ));
+        }
+
+        let serve = serve_messages("task", receiver, state);
 
         self.handle.set(spawn(serve));
 
@@ -99,6 +111,7 @@ pub struct TaskRunner {
     // until the task successfully starts without blocking the DashMap
     running_tasks: DashMap<TaskLibrary, Arc<RwLock<Option<RunningTask>>>>,
     task_history: DashMap<TaskLibrary, Arc<RwLock<AllocRingBuffer<Task>>>>,
+    webhooks: WebhookDispatcher,
 }
 
 #[derive(Debug)]
@@ -116,6 +129,7 @@ impl ESInner for TaskRunner {
             registry: registry.clone(),
             running_tasks: DashMap::new(),
             task_history: DashMap::new(),
+            webhooks: WebhookDispatcher::new(config.clone()),
         })
     }
 
@@ -141,6 +155,22 @@ impl ESInner for TaskRunner {
                 TaskMsg::ShowTasks { resp, library } => {
                     self.respond(resp, self.show_tasks(library)).await
                 }
+                TaskMsg::UpdateProgress {
+                    resp,
+                    library,
+                    progress,
+                } => {
+                    self.respond(resp, self.update_progress(library, progress))
+                        .await
+                }
+                TaskMsg::UpdateSummary {
+                    resp,
+                    library,
+                    summary,
+                } => {
+                    self.respond(resp, self.update_summary(library, summary))
+                        .await
+                }
                 TaskMsg::CompleteTask {
                     resp,
                     library,
@@ -155,6 +185,37 @@ impl ESInner for TaskRunner {
             _ => Err(anyhow::Error::msg("not implemented")),
         }
     }
+
+    // cancel everything that is running, and wait for the watchers to report back so that
+    // the stopped tasks still make it into the history
+    //
+    // the watchers abort any task that ignores the cancellation after CANCEL_GRACE, so this
+    // is bounded even without the overall shutdown timeout
+    async fn shutdown(&self) -> Result<()> {
+        let running_tasks = self
+            .running_tasks
+            .iter()
+            .map(|entry| entry.value().clone())
+            .collect::<Vec<_>>();
+
+        for rt_entry in running_tasks.iter() {
+            if let Some(running_task) = rt_entry.read().await.as_ref() {
+                info!(
+                    { start = running_task.task.start },
+                    "stopping task for shutdown"
+                );
+                running_task.cancel.cancel();
+            }
+        }
+
+        for rt_entry in running_tasks.iter() {
+            while rt_entry.read().await.is_some() {
+                sleep(Duration::from_millis(100)).await;
+            }
+        }
+
+        Ok(())
+    }
 }
 
 // task runner
@@ -238,18 +299,67 @@ impl ESTaskService for TaskRunner {
         let config = self.config.clone();
         let registry = self.registry.clone();
 
+        // tasks check the token between items, see watch_task()
+        let cancel = CancellationToken::new();
+
         let task_future: Pin<Box<dyn Future<Output = Result<i64>> + Send>> = match library {
             // user library tasks
             TaskLibrary::User { library_uuid } => match task_type {
-                TaskType::ScanLibrary => Box::pin(scan_library(config, registry, library_uuid)),
-                TaskType::CleanLibrary => Box::pin(clean_library(config, registry, library_uuid)),
+                TaskType::ScanLibrary => Box::pin(scan_library(
+                    config,
+                    registry,
+                    library_uuid,
+                    false,
+                    cancel.clone(),
+                )),
+                TaskType::ScanLibraryFull => Box::pin(scan_library(
+                    config,
+                    registry,
+                    library_uuid,
+                    true,
+                    cancel.clone(),
+                )),
+                TaskType::CleanLibrary => Box::pin(clean_library(
+                    config,
+                    registry,
+                    library_uuid,
+                    cancel.clone(),
+                )),
                 TaskType::RunScripts => Box::pin(sleep_task(library_uuid)),
+                TaskType::FindDuplicates => {
+                    Box::pin(dedup_library(registry, library_uuid, false, cancel.clone()))
+                }
+                TaskType::MergeDuplicates => {
+                    Box::pin(dedup_library(registry, library_uuid, true, cancel.clone()))
+                }
+                TaskType::ParseDates => {
+                    Box::pin(dateparse_library(registry, library_uuid, cancel.clone()))
+                }
+                TaskType::VerifyHashes => Box::pin(verify_library(
+                    config,
+                    registry,
+                    library_uuid,
+                    false,
+                    cancel.clone(),
+                )),
+                TaskType::VerifyHashesTagMissing => Box::pin(verify_library(
+                    config,
+                    registry,
+                    library_uuid,
+                    true,
+                    cancel.clone(),
+                )),
                 _ => return Err(anyhow::Error::msg("unsupported user task")),
             },
 
             // system-wide tasks
             TaskLibrary::System => match task_type {
-                TaskType::CacheScrub => Box::pin(cache_scrub(config, registry)),
+                TaskType::CacheScrub => {
+                    Box::pin(cache_scrub(config, registry, false, cancel.clone()))
+                }
+                TaskType::CacheScrubDryRun => {
+                    Box::pin(cache_scrub(config, registry, true, cancel.clone()))
+                }
                 _ => return Err(anyhow::Error::msg("unsupported system task")),
             },
         };
@@ -257,7 +367,7 @@ impl ESTaskService for TaskRunner {
         info!({ start }, "starting task");
 
         // spawn the future inside of the infalliable watcher and send the result back via ESM
-        let (_handle, cancel) = watch_task(library, task_svc_sender, task_future);
+        let _handle = watch_task(library, task_svc_sender, task_future, cancel.clone());
 
         // we still have the write lock on the currently running task, but in principle the task
         // may have already completed and sent the message to complete_task().  thus, even if we
@@ -269,8 +379,13 @@ impl ESTaskService for TaskRunner {
             warnings: None,
             start,
             end: None,
+            progress: None,
+            summary: None,
         };
 
+        gauge!("entanglement_tasks_running", "task_type" => task.task_type.to_string())
+            .increment(1.0);
+
         *running_task = Some(RunningTask {
             task,
             cancel,
@@ -321,20 +436,62 @@ impl ESTaskService for TaskRunner {
             },
         };
 
-        match self.task_history.get(&library) {
-            None => {}
-            Some(entry) => {
-                let ring = entry.read().await;
+        let ring_entry = self.history(library).await;
 
-                let mut vec = ring.to_vec();
-                vec.reverse();
-                out.append(&mut vec);
-            }
-        };
+        let ring = ring_entry.read().await;
+
+        let mut vec = ring.to_vec();
+        vec.reverse();
+        out.append(&mut vec);
 
         Ok(out)
     }
 
+    #[instrument(skip(self))]
+    async fn update_progress(&self, library: TaskLibrary, progress: TaskProgress) -> Result<()> {
+        let rt_entry = self
+            .running_tasks
+            .get(&library)
+            .ok_or_else(|| anyhow::Error::msg(format!("library {library} has not run a task")))?;
+
+        let mut running_task = rt_entry.write().await;
+
+        let running_task = running_task
+            .as_mut()
+            .ok_or_else(|| anyhow::Error::msg(format!("library {library} has no running task")))?;
+
+        // each message is handled in its own tokio task, so updates can arrive out of order
+        if running_task
+            .task
+            .progress
+            .is_some_and(|v| v.processed > progress.processed)
+        {
+            return Ok(());
+        }
+
+        running_task.task.progress = Some(progress);
+
+        Ok(())
+    }
+
+    #[instrument(skip(self))]
+    async fn update_summary(&self, library: TaskLibrary, summary: String) -> Result<()> {
+        let rt_entry = self
+            .running_tasks
+            .get(&library)
+            .ok_or_else(|| anyhow::Error::msg(format!("library {library} has not run a task")))?;
+
+        let mut running_task = rt_entry.write().await;
+
+        let running_task = running_task
+            .as_mut()
+            .ok_or_else(|| anyhow::Error::msg(format!("library {library} has no running task")))?;
+
+        running_task.task.summary = Some(summary);
+
+        Ok(())
+    }
+
     #[instrument(skip(self))]
     async fn complete_task(
         &self,
@@ -366,125 +523,278 @@ impl ESTaskService for TaskRunner {
             Result::<RunningTask>::Ok(completed_task)
         }?;
 
-        // create the library's entry in the history task map if it doesn't exist
-        //
-        // this should be the only place that entries are put into the history DashMap,
-        // all other calls should error somehow
-        let ring_entry = match self.task_history.entry(library) {
-            Entry::Occupied(entry) => entry.get().clone(),
-            Entry::Vacant(entry) => {
-                let v = Arc::new(RwLock::new(AllocRingBuffer::new(64)));
-                entry.insert(v.clone());
-                v
-            }
-        };
-
-        // grab the ring buffer lock for the entirety of the archiving action
-        let mut ring = ring_entry.write().await;
+        // the history has to be loaded before the task is written to the database, or a
+        // library's first completion after a restart would appear twice
+        let ring_entry = self.history(library).await;
 
-        ring.enqueue(Task {
+        let task = Task {
             task_type: completed_task.task.task_type,
             uid: completed_task.task.uid,
             status,
             warnings,
             start: completed_task.task.start,
             end: Some(end),
-        });
+            progress: completed_task.task.progress,
+            summary: completed_task.task.summary,
+        };
+
+        gauge!("entanglement_tasks_running", "task_type" => task.task_type.to_string())
+            .decrement(1.0);
+
+        counter!(
+            "entanglement_tasks_completed_total",
+            "task_type" => task.task_type.to_string(),
+            "status" => task.status.to_string()
+        )
+        .increment(1);
+
+        // grab the ring buffer lock for the entirety of the archiving action
+        {
+            let mut ring = ring_entry.write().await;
+
+            ring.enqueue(task.clone());
+        }
 
         info!(
             { start = completed_task.task.start },
             "task saved to history"
         );
 
+        self.webhooks.dispatch(WebhookEvent::TaskCompleted {
+            library_uuid: match library {
+                TaskLibrary::User { library_uuid } => Some(library_uuid),
+                TaskLibrary::System => None,
+            },
+            task_type: task.task_type.clone(),
+            status: task.status.clone(),
+            warnings: task.warnings,
+            start: task.start,
+            end,
+            summary: task.summary.clone(),
+        });
+
+        // the in-memory history is still accurate, so a failed write only costs the
+        // record after the next restart
+        if let Err(err) = self.persist_task(library, task).await {
+            warn!("failed to persist task history: {err}");
+        }
+
         Ok(())
     }
 }
 
+// how many finished tasks are kept (and shown) for each library
+const TASK_HISTORY: usize = 64;
+
+impl TaskRunner {
+    // the finished tasks for a library
+    //
+    // the history is loaded from the database the first time that a library is seen,
+    // so that it survives restarts.  this should be the only place that entries are put
+    // into the history DashMap.
+    async fn history(&self, library: TaskLibrary) -> Arc<RwLock<AllocRingBuffer<Task>>> {
+        if let Some(entry) = self.task_history.get(&library) {
+            return entry.clone();
+        }
+
+        let mut ring = AllocRingBuffer::new(TASK_HISTORY);
+
+        match self.load_history(library).await {
+            // the database returns the newest first, but the ring is oldest first
+            Ok(tasks) => tasks.into_iter().rev().for_each(|task| {
+                ring.enqueue(task);
+            }),
+            Err(err) => warn!("failed to load task history: {err}"),
+        }
+
+        // another call may have loaded the history while we were waiting on the database
+        match self.task_history.entry(library) {
+            Entry::Occupied(entry) => entry.get().clone(),
+            Entry::Vacant(entry) => {
+                let v = Arc::new(RwLock::new(ring));
+                entry.insert(v.clone());
+                v
+            }
+        }
+    }
+
+    #[instrument(skip(self))]
+    async fn load_history(&self, library: TaskLibrary) -> Result<Vec<Task>> {
+        let db_svc_sender = self.registry.get(&ServiceType::Db)?;
+
+        let (tx, rx) = tokio::sync::oneshot::channel();
+
+        db_svc_sender
+            .send(
+                DbMsg::GetTasks {
+                    resp: tx,
+                    library,
+                    limit: TASK_HISTORY as u64,
+                }
+                .into(),
+            )
+            .await?;
+
+        rx.await?
+    }
+
+    #[instrument(skip(self, task))]
+    async fn persist_task(&self, library: TaskLibrary, task: Task) -> Result<()> {
+        let db_svc_sender = self.registry.get(&ServiceType::Db)?;
+
+        let (tx, rx) = tokio::sync::oneshot::channel();
+
+        db_svc_sender
+            .send(
+                DbMsg::AddTask {
+                    resp: tx,
+                    library,
+                    task,
+                }
+                .into(),
+            )
+            .await?;
+
+        rx.await?
+    }
+}
+
+// how long a cancelled task has to wind down before it is aborted outright
+const CANCEL_GRACE: Duration = Duration::from_secs(60);
+
 // task watcher
 //
 // this function ensures that our falliable tasks can be cancelled, and that
 // the results are all correctly accounted for when sending the completion
 // message back to the task service.
+//
+// cancellation is cooperative -- the tasks stop taking on new items once the token
+// is cancelled, and return once the items in flight are done so that none of them
+// are left half-processed.  anything that doesn't stop within CANCEL_GRACE (or
+// doesn't check the token at all) is aborted.
 fn watch_task(
     library: TaskLibrary,
     sender: EsmSender,
     task_future: Pin<Box<dyn Future<Output = Result<i64>> + Send>>,
-) -> (JoinHandle<()>, CancellationToken) {
-    let cancel = CancellationToken::new();
+    cancel: CancellationToken,
+) -> JoinHandle<()> {
+    let task = async move {
+        debug!("starting");
 
-    // the extra block is to clone the cancel token properly
-    let handle = {
-        let cancel = cancel.clone();
+        let mut task_handle = spawn(task_future);
 
-        let task = async move {
-            debug!("starting");
+        let abort_handle = task_handle.abort_handle();
 
-            let task_handle = spawn(task_future);
+        debug!("waiting");
 
-            let abort_handle = task_handle.abort_handle();
+        let (status, warnings) = select! {
+            _ = cancel.cancelled() => {
+                info!("cancelling task");
 
-            debug!("waiting");
+                match timeout(CANCEL_GRACE, &mut task_handle).await {
+                    Ok(Ok(Ok(warnings))) => {
+                        info!("task stopped");
+                        (TaskStatus::Aborted, Some(warnings))
+                    }
+                    Ok(Ok(Err(err))) => {
+                        error!("task failed while stopping: {err}");
+                        (TaskStatus::Aborted, None)
+                    }
+                    Ok(Err(err)) => {
+                        error!("task runtime join error: {err}");
+                        (TaskStatus::Aborted, None)
+                    }
+                    Err(_) => {
+                        warn!("task did not stop in time, aborting");
+                        abort_handle.abort();
+                        (TaskStatus::Aborted, None)
+                    }
+                }
+            }
 
-            let (status, warnings) = select! {
-                _ = cancel.cancelled() => {
-                    info!("aborting task");
-                    abort_handle.abort();
-                    (TaskStatus::Aborted, None)
+            res = &mut task_handle => {
+                match res {
+                    Ok(Ok(warnings)) => {
+                        info!("task succeeded");
+                        (TaskStatus::Success, Some(warnings))},
+                    Ok(Err(err)) => {
+                        error!("task failed: {err}");
+                        (TaskStatus::Failure, None)
+                    },
+                    Err(err) => {
+                        error!("task runtime join error: {err}");
+                        (TaskStatus::Unknown, None)},
                 }
 
-                res = task_handle => {
-                    match res {
-                        Ok(Ok(warnings)) => {
-                            info!("task succeeded");
-                            (TaskStatus::Success, Some(warnings))},
-                        Ok(Err(err)) => {
-                            error!("task failed: {err}");
-                            (TaskStatus::Failure, None)
-                        },
-                        Err(err) => {
-                            error!("task runtime join error: {err}");
-                            (TaskStatus::Unknown, None)},
+            }
+
+        };
+
+        // since the watcher future should not be able to fail, we collect all of the various
+        // failure modes associated with sending the completion message and print their errors
+        let (tx, rx) = tokio::sync::oneshot::channel();
+
+        // this ultimately blocks on the write lock for the running task (via the handler), since
+        // we need it to take() the running task from the Option and put it into the ringbuffer
+        //
+        // however, the only contention for that lock is in the creation of the task
+        match async {
+            sender
+                .send(
+                    TaskMsg::CompleteTask {
+                        resp: tx,
+                        library,
+                        status,
+                        warnings,
+                        end: unix_time(),
                     }
+                    .into(),
+                )
+                .await?;
 
-                }
+            rx.await??;
 
-            };
-
-            // since the watcher future should not be able to fail, we collect all of the various
-            // failure modes associated with sending the completion message and print their errors
-            let (tx, rx) = tokio::sync::oneshot::channel();
-
-            // this ultimately blocks on the write lock for the running task (via the handler), since
-            // we need it to take() the running task from the Option and put it into the ringbuffer
-            //
-            // however, the only contention for that lock is in the creation of the task
-            match async {
-                sender
-                    .send(
-                        TaskMsg::CompleteTask {
-                            resp: tx,
-                            library,
-                            status,
-                            warnings,
-                            end: unix_time(),
-                        }
-                        .into(),
-                    )
-                    .await?;
-
-                rx.await??;
-
-                Result::<()>::Ok(())
-            }
+            Result::<()>::Ok(())
+        }
+        .await
+        {
+            Ok(_) => {}
+            Err(err) => error!("failed to send/receive completion message: {err}"),
+        }
+    }
+    .instrument(span!(Level::INFO, "watch_task"));
+
+    spawn(task)
+}
+
+// scheduled scans
+//
+// each schedule sleeps until its next tick and then starts the scan in the same way as
+// a user would, so start_task() is what keeps scheduled runs from overlapping with each
+// other or with a task that someone started by hand
+#[instrument(skip(state, schedule))]
+async fn run_schedule(state: Arc<TaskRunner>, library_uuid: LibraryUuid, schedule: Schedule) {
+    let library = TaskLibrary::User { library_uuid };
+
+    let mut next = schedule.upcoming(Utc).next();
+
+    while let Some(tick) = next {
+        // to_std() fails if the tick has already passed, which just means run it now
+        sleep((tick - Utc::now()).to_std().unwrap_or_default()).await;
+
+        info!("starting scheduled scan");
+
+        if let Err(err) = state
+            .start_task(library, TaskType::ScanLibrary, TaskUid::System)
             .await
-            {
-                Ok(_) => {}
-                Err(err) => error!("failed to send/receive completion message: {err}"),
-            }
-        }.instrument(span!(Level::INFO, "watch_task"));
+        {
+            info!("skipped scheduled scan: {err}");
+        }
 
-        spawn(task)
-    };
+        // counting from the later of the two means that ticks missed while the server
+        // was busy (or suspended) are skipped rather than run back to back
+        next = schedule.after(&tick.max(Utc::now())).next();
+    }
 
-    (handle, cancel)
+    info!("scan schedule has no more runs");
 }
@@ -0,0 +1,200 @@
+use std::{collections::HashSet, path::PathBuf, sync::Arc};
+
+use anyhow::Result;
+use tokio::{fs::try_exists, sync::oneshot::channel, task::JoinSet};
+use tokio_util::sync::CancellationToken;
+use tracing::{debug, info, instrument, warn};
+
+use crate::{
+    db::msg::DbMsg,
+    service::{ESMRegistry, EsmSender, ServiceType},
+    task::scan_utils::{ProgressReporter, add_tag_to_media, report_summary},
+};
+use api::{library::LibraryUuid, media::MediaUuid, search::SearchFilter, task::TaskLibrary};
+use common::{
+    config::ESConfig,
+    media::{HashAlgorithm, content_hash},
+};
+
+// what verifying a single media found
+#[derive(Debug)]
+enum Verified {
+    Matched,
+    // the file changed since it was hashed, either from bit rot or being replaced
+    Mismatched,
+    Missing,
+}
+
+// verify the originals in a library against their stored content hashes
+//
+// the scan only rehashes files whose mtime changed, so corruption that leaves the mtime
+// alone is never noticed otherwise.  each original is rehashed with whichever algorithm
+// produced its stored hash, and the mismatched and missing media are logged by uuid and
+// path, with the counts going into the task summary.
+//
+// nothing is changed unless tag_missing is set, in which case missing media get the same
+// TBD_DELETEME tag as the clean task uses.  mismatched media are only ever reported, since
+// a rescan is the way to pick up a file that was replaced on purpose.
+#[instrument(skip(config, registry, cancel))]
+pub async fn verify_library(
+    config: Arc<ESConfig>,
+    registry: ESMRegistry,
+    library_uuid: LibraryUuid,
+    tag_missing: bool,
+    cancel: CancellationToken,
+) -> Result<i64> {
+    let db_svc_sender = registry.get(&ServiceType::Db)?;
+    let task_svc_sender = registry.get(&ServiceType::Task)?;
+
+    let (tx, rx) = channel();
+
+    db_svc_sender
+        .send(
+            DbMsg::GetLibrary {
+                resp: tx,
+                library_uuid,
+            }
+            .into(),
+        )
+        .await?;
+
+    let library = rx
+        .await??
+        .ok_or_else(|| anyhow::Error::msg("library does not exist"))?;
+
+    let (tx, rx) = channel();
+
+    db_svc_sender
+        .send(
+            DbMsg::SearchMediaInLibrary {
+                resp: tx,
+                gid: HashSet::from([library.gid]),
+                library_uuid,
+                hidden: None,
+                filter: SearchFilter::default(),
+            }
+            .into(),
+        )
+        .await?;
+
+    let media_in_library = rx.await??;
+
+    debug!(
+        { count = media_in_library.len() },
+        "library verify beginning database walk"
+    );
+
+    let task_library = TaskLibrary::User { library_uuid };
+
+    let mut progress = ProgressReporter::new(
+        task_svc_sender.clone(),
+        task_library,
+        media_in_library.len() as u64,
+    );
+
+    let mut warnings = 0;
+    let mut matched = 0;
+    let mut mismatched = 0;
+    let mut missing = 0;
+
+    let mut tasks = JoinSet::new();
+
+    // hashing is mostly reading, so this shares the scan's limit
+    let verify_threads = config.task.scan_threads;
+
+    let mut media_iter = media_in_library.into_iter();
+
+    loop {
+        while tasks.len() < verify_threads && !cancel.is_cancelled() {
+            let Some(media_uuid) = media_iter.next() else {
+                break;
+            };
+
+            tasks.spawn({
+                let db_svc_sender = db_svc_sender.clone();
+
+                async move { verify_media(db_svc_sender, media_uuid, tag_missing).await }
+            });
+        }
+
+        let Some(result) = tasks.join_next().await else {
+            break;
+        };
+
+        match result? {
+            Ok(Verified::Matched) => matched += 1,
+            Ok(Verified::Mismatched) => mismatched += 1,
+            Ok(Verified::Missing) => missing += 1,
+            Err(err) => {
+                warn!("verify error: {err:?}");
+                warnings += 1;
+            }
+        }
+
+        progress.advance().await;
+    }
+
+    if cancel.is_cancelled() {
+        info!("library verify cancelled");
+    }
+
+    progress.report().await;
+
+    let mut summary = format!("{matched} verified, {mismatched} changed, {missing} missing");
+
+    if tag_missing && missing > 0 {
+        summary.push_str(" (tagged TBD_DELETEME)");
+    }
+
+    report_summary(&task_svc_sender, task_library, summary).await;
+
+    Ok(warnings + mismatched + missing)
+}
+
+#[instrument(skip(db_svc_sender))]
+async fn verify_media(
+    db_svc_sender: EsmSender,
+    media_uuid: MediaUuid,
+    tag_missing: bool,
+) -> Result<Verified> {
+    let (tx, rx) = channel();
+
+    db_svc_sender
+        .send(
+            DbMsg::GetMedia {
+                resp: tx,
+                media_uuid,
+            }
+            .into(),
+        )
+        .await?;
+
+    let media = rx
+        .await??
+        .ok_or_else(|| {
+            anyhow::Error::msg("internal error: failed to get_media after searching library")
+        })?
+        .0;
+
+    let path = PathBuf::from(media.path);
+
+    if !try_exists(&path).await? {
+        warn!({ %media_uuid, path = ?path }, "original is missing");
+
+        if tag_missing {
+            add_tag_to_media(db_svc_sender, media_uuid, "TBD_DELETEME".to_owned()).await?;
+        }
+
+        return Ok(Verified::Missing);
+    }
+
+    let chash = content_hash(&path, &HashAlgorithm::of_chash(&media.chash)).await?;
+
+    if chash != media.chash {
+        warn!({ %media_uuid, path = ?path }, "original does not match its content hash");
+
+        return Ok(Verified::Mismatched);
+    }
+
+    Ok(Verified::Matched)
+}
@@ -6,7 +6,8 @@ use clap::{Parser, Subcommand, ValueEnum};
 use common::{
     auth::{
         AuthnProvider, AuthzProvider,
-        ldap::LdapAuthz,
+        ldap::{LdapAuthn, LdapAuthz},
+        oidc::OidcAuthn,
         tomlfile::{TomlAuthnFile, TomlAuthzFile},
     },
     config::read_config,
@@ -15,6 +16,8 @@ use common::{
 #[derive(Clone, Debug, ValueEnum)]
 enum AuthnBackend {
     TomlFile,
+    Oidc,
+    Ldap,
 }
 
 #[derive(Clone, Debug, ValueEnum)]
@@ -129,6 +132,8 @@ async fn main() -> Result<()> {
             Commands::Authn { backend, authncmd } => {
                 let backend: Box<dyn AuthnProvider> = match backend {
                     AuthnBackend::TomlFile => Box::new(TomlAuthnFile::new(config.clone())?),
+                    AuthnBackend::Oidc => Box::new(OidcAuthn::new(config.clone())?),
+                    AuthnBackend::Ldap => Box::new(LdapAuthn::new(config.clone())?),
                 };
 
                 match authncmd {
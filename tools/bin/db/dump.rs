@@ -3,7 +3,7 @@ use std::{collections::HashMap, path::PathBuf, sync::Arc};
 use anyhow::Result;
 use api::{
     UuidSource,
-    collection::CollectionUuid,
+    collection::{Collection, CollectionUuid},
     comment::Comment,
     library::LibraryUuid,
     media::{Media, MediaUuid},
@@ -35,9 +35,9 @@ fn unmake_key(key: &[u8]) -> Result<Uuid> {
 }
 
 #[derive(Serialize, Deserialize)]
-struct MediaRecord {
-    media: Media,
-    collections: Vec<CollectionUuid>,
+pub struct MediaRecord {
+    pub media: Media,
+    pub collections: Vec<CollectionUuid>,
 }
 
 pub async fn dump(config: Arc<ESConfig>, filename: PathBuf) -> Result<()> {
@@ -226,6 +226,7 @@ pub async fn undump(config: Arc<ESConfig>, filename: PathBuf) -> Result<()> {
     // collections
     println!("creating collections");
     let mut collection_map = HashMap::new();
+    let mut parent_map = HashMap::new();
 
     let collection_iter = rdb.prefix_iterator([COLLECTION_PREFIX]);
 
@@ -236,7 +237,15 @@ pub async fn undump(config: Arc<ESConfig>, filename: PathBuf) -> Result<()> {
 
         let old_uuid = CollectionUuid::from_value(&parser, unmake_key(&key)?);
 
-        let collection_uuid = db.add_collection(serde_json::from_slice(&value)?).await?;
+        // the parent may not exist yet, so it is restored once they all do
+        let mut collection: Collection = serde_json::from_slice(&value)?;
+        let old_parent = collection.parent_uuid.take();
+
+        let collection_uuid = db.add_collection(collection).await?;
+
+        if let Some(old_parent) = old_parent {
+            parent_map.insert(collection_uuid, old_parent);
+        }
 
         if collection_map.insert(old_uuid, collection_uuid).is_some() {
             return Err(anyhow::Error::msg("duplicate collection_uuid"));
@@ -244,6 +253,21 @@ pub async fn undump(config: Arc<ESConfig>, filename: PathBuf) -> Result<()> {
     }
     println!("  complete");
 
+    // restore the collection hierarchy
+    println!("nesting collections");
+
+    for (i, (collection_uuid, old_parent)) in parent_map.iter().enumerate() {
+        print!("\r  parents: {}", i + 1);
+
+        let parent_uuid = collection_map
+            .get(old_parent)
+            .ok_or_else(|| anyhow::Error::msg("missing parent collection uuid"))?;
+
+        db.set_collection_parent(*collection_uuid, Some(*parent_uuid))
+            .await?;
+    }
+    println!("  complete");
+
     // restore media back to their original collections
     println!("putting media into collections");
 
@@ -0,0 +1,209 @@
+use std::{
+    collections::{HashMap, HashSet},
+    path::PathBuf,
+    sync::Arc,
+};
+
+use anyhow::Result;
+use api::{
+    collection::{Collection, CollectionUuid},
+    library::LibraryUuid,
+};
+use serde::{Deserialize, Serialize};
+
+use crate::dump::MediaRecord;
+use common::{
+    config::ESConfig,
+    db::{DbBackend, MariaDBBackend, PostgresBackend},
+};
+
+// library metadata export
+//
+// unlike the full dump, this covers a single library and is meant to be read (or edited)
+// by people, so it is plain json.  the media records are the same as the dump, including
+// the hashes, so the import never needs to touch the files themselves.
+//
+// collections are carried along by value, since their uuids mean nothing to another
+// database.  on import, they are matched on gid and name, and the nesting is not restored.
+#[derive(Serialize, Deserialize)]
+struct LibraryExport {
+    library_uuid: LibraryUuid,
+    media: Vec<MediaRecord>,
+    collections: HashMap<CollectionUuid, Collection>,
+}
+
+pub async fn export(config: Arc<ESConfig>, library_uuid: LibraryUuid, file: PathBuf) -> Result<()> {
+    let db: Box<dyn DbBackend> = match config.db_backend {
+        common::config::DbBackend::MariaDB => Box::new(MariaDBBackend::new(config).await?),
+        common::config::DbBackend::Postgres => Box::new(PostgresBackend::new(config).await?),
+    };
+
+    db.get_library(library_uuid)
+        .await?
+        .ok_or_else(|| anyhow::Error::msg("missing library"))?;
+
+    // media
+    println!("fetching media");
+    let mut media = Vec::new();
+
+    for media_uuid in db.get_media_uuids().await? {
+        // anything in the trash has no record to export
+        let Some((record, collections, _comments)) = db.get_media(media_uuid).await? else {
+            continue;
+        };
+
+        if record.library_uuid != library_uuid {
+            continue;
+        }
+
+        media.push(MediaRecord {
+            media: record,
+            collections,
+        });
+
+        print!("\r  media: {}", media.len());
+    }
+    println!("  complete");
+
+    // collections
+    println!("fetching collections");
+    let mut collections = HashMap::new();
+
+    for collection_uuid in media
+        .iter()
+        .flat_map(|record| record.collections.iter())
+        .collect::<HashSet<_>>()
+    {
+        let collection = db
+            .get_collection(*collection_uuid)
+            .await?
+            .ok_or_else(|| anyhow::Error::msg("missing collection"))?;
+
+        collections.insert(*collection_uuid, collection);
+
+        print!("\r  collections: {}", collections.len());
+    }
+    println!("  complete");
+
+    tokio::fs::write(
+        &file,
+        serde_json::to_vec_pretty(&LibraryExport {
+            library_uuid,
+            media,
+            collections,
+        })?,
+    )
+    .await?;
+
+    println!("library export complete");
+
+    Ok(())
+}
+
+// importing is idempotent, so a partial import can be rerun
+//
+// media are skipped if another record already has the path, or (as in the scan) the
+// same content in the same library.  either way, the existing record still gets the
+// collection memberships from the export.
+pub async fn import(config: Arc<ESConfig>, library_uuid: LibraryUuid, file: PathBuf) -> Result<()> {
+    let db: Box<dyn DbBackend> = match config.db_backend {
+        common::config::DbBackend::MariaDB => Box::new(MariaDBBackend::new(config).await?),
+        common::config::DbBackend::Postgres => Box::new(PostgresBackend::new(config).await?),
+    };
+
+    db.get_library(library_uuid)
+        .await?
+        .ok_or_else(|| anyhow::Error::msg("missing library"))?;
+
+    let export: LibraryExport = serde_json::from_slice(&tokio::fs::read(&file).await?)?;
+
+    // collections
+    println!("matching collections");
+    let mut existing = HashMap::new();
+
+    for collection_uuid in db.get_collection_uuids().await? {
+        if let Some(collection) = db.get_collection(collection_uuid).await? {
+            existing.insert((collection.gid, collection.name), collection_uuid);
+        }
+    }
+
+    let mut collection_map = HashMap::new();
+    let mut created = 0;
+
+    for (old_uuid, mut collection) in export.collections {
+        let key = (collection.gid.clone(), collection.name.clone());
+
+        let collection_uuid = match existing.get(&key) {
+            Some(v) => *v,
+            None => {
+                collection.parent_uuid = None;
+                collection.cover = None;
+
+                let collection_uuid = db.add_collection(collection).await?;
+                existing.insert(key, collection_uuid);
+
+                created += 1;
+                print!("\r  created: {created}");
+
+                collection_uuid
+            }
+        };
+
+        collection_map.insert(old_uuid, collection_uuid);
+    }
+    println!("  complete");
+
+    // media
+    println!("creating media");
+    let mut added = 0;
+    let mut skipped = 0;
+
+    for (i, mut record) in export.media.into_iter().enumerate() {
+        print!("\r  media: {}", i + 1);
+
+        record.media.library_uuid = library_uuid;
+
+        let found = match db.get_media_by_path(record.media.path.clone()).await? {
+            Some(v) => Some(v.media_uuid),
+            None => db
+                .get_media_by_chash(library_uuid, record.media.chash.clone())
+                .await?
+                .map(|v| v.media_uuid),
+        };
+
+        let (media_uuid, current) = match found {
+            Some(media_uuid) => {
+                skipped += 1;
+
+                let current = db
+                    .get_media(media_uuid)
+                    .await?
+                    .map(|(_, collections, _)| collections)
+                    .unwrap_or_default();
+
+                (media_uuid, current)
+            }
+            None => {
+                added += 1;
+
+                (db.add_media(record.media).await?, Vec::new())
+            }
+        };
+
+        for old_uuid in record.collections {
+            let collection_uuid = collection_map
+                .get(&old_uuid)
+                .ok_or_else(|| anyhow::Error::msg("missing collection uuid"))?;
+
+            if !current.contains(collection_uuid) {
+                db.add_media_to_collection(media_uuid, *collection_uuid)
+                    .await?;
+            }
+        }
+    }
+    println!("  complete");
+
+    println!("library import complete: {added} added, {skipped} already present");
+
+    Ok(())
+}
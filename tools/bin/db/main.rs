@@ -3,9 +3,11 @@ use std::path::PathBuf;
 use anyhow::Result;
 use clap::{Parser, Subcommand};
 
+use api::{UuidSource, library::LibraryUuid};
 use common::config::read_config;
 
 mod dump;
+mod json;
 
 #[derive(Parser)]
 #[command(version, about, long_about = None)]
@@ -30,8 +32,26 @@ enum Command {
         #[arg(short, long)]
         directory: PathBuf,
     },
+    /// export the media metadata of one library to a json file
+    Export {
+        #[arg(short, long)]
+        library: String,
+        #[arg(short, long)]
+        file: PathBuf,
+    },
+    /// import media metadata from a json file into a library, skipping existing media
+    Import {
+        #[arg(short, long)]
+        library: String,
+        #[arg(short, long)]
+        file: PathBuf,
+    },
 }
 
+struct CliParser;
+
+impl UuidSource for CliParser {}
+
 #[tokio::main]
 async fn main() -> Result<()> {
     let cli = Cli::parse();
@@ -41,6 +61,12 @@ async fn main() -> Result<()> {
     match cli.command {
         Command::Dump { directory } => dump::dump(config, directory).await?,
         Command::Undump { directory } => dump::undump(config, directory).await?,
+        Command::Export { library, file } => {
+            json::export(config, LibraryUuid::try_parse(&CliParser, &library)?, file).await?
+        }
+        Command::Import { library, file } => {
+            json::import(config, LibraryUuid::try_parse(&CliParser, &library)?, file).await?
+        }
     }
 
     Ok(())
@@ -0,0 +1,88 @@
+use std::path::Path;
+
+use anyhow::Result;
+use reqwest::{Certificate, Client, Identity, header::AUTHORIZATION};
+use serde::{Serialize, de::DeserializeOwned};
+
+use api::HTTP_URL_ROOT;
+
+// api client
+//
+// the server only accepts requests from either its reverse proxy or users presenting a
+// certificate from the client ca (see the conn-check in entg-http), so this does the same
+// as the latter and identifies the user with their own cert.  api keys are also accepted,
+// which is the only option for the proxy and oidc backends.
+pub struct TaskClient {
+    client: Client,
+    url: String,
+    api_key: Option<String>,
+}
+
+impl TaskClient {
+    pub async fn new(
+        url: &str,
+        cert: Option<&Path>,
+        key: Option<&Path>,
+        ca_cert: Option<&Path>,
+        api_key: Option<String>,
+    ) -> Result<Self> {
+        let mut builder = Client::builder().use_rustls_tls();
+
+        match (cert, key) {
+            (Some(cert), Some(key)) => {
+                // reqwest wants both halves in the same pem buffer
+                let mut pem = tokio::fs::read(cert).await?;
+                pem.extend(tokio::fs::read(key).await?);
+
+                builder = builder.identity(Identity::from_pem(&pem)?);
+            }
+            (None, None) => {}
+            _ => {
+                return Err(anyhow::Error::msg(
+                    "client cert and key must be specified together",
+                ));
+            }
+        }
+
+        if let Some(ca_cert) = ca_cert {
+            builder = builder
+                .add_root_certificate(Certificate::from_pem(&tokio::fs::read(ca_cert).await?)?);
+        }
+
+        Ok(TaskClient {
+            client: builder.build()?,
+            url: url.trim_end_matches('/').to_owned(),
+            api_key,
+        })
+    }
+
+    // the server equivalent of the http_endpoint macro in api/lib.rs
+    pub async fn call<Req: Serialize, Resp: DeserializeOwned>(
+        &self,
+        endpoint: &str,
+        req: &Req,
+    ) -> Result<Resp> {
+        let mut request = self
+            .client
+            .post(format!("{}/{HTTP_URL_ROOT}/api/{endpoint}", self.url))
+            .json(req);
+
+        if let Some(api_key) = &self.api_key {
+            request = request.header(AUTHORIZATION, format!("Bearer {api_key}"));
+        }
+
+        let resp = request.send().await?;
+
+        let status = resp.status();
+
+        if !status.is_success() {
+            let text = resp.text().await.unwrap_or_default();
+
+            return Err(anyhow::Error::msg(format!(
+                "{endpoint} failed with {status}: {text}"
+            )));
+        }
+
+        Ok(resp.json().await?)
+    }
+}
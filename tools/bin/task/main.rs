@@ -0,0 +1,212 @@
+use std::{
+    path::PathBuf,
+    time::{Duration, SystemTime, UNIX_EPOCH},
+};
+
+use anyhow::Result;
+use clap::{Parser, Subcommand};
+
+use api::{
+    UuidSource,
+    library::LibraryUuid,
+    task::{
+        ShowTasksReq, ShowTasksResp, StartTaskReq, StartTaskResp, Task, TaskLibrary, TaskStatus,
+        TaskType,
+    },
+};
+
+mod client;
+use client::TaskClient;
+
+#[derive(Parser)]
+#[command(version, about, long_about = None)]
+struct Cli {
+    /// server url, i.e. https://entanglement.example.com:8443
+    #[arg(short, long, env = "ENTG_URL")]
+    url: String,
+
+    /// client certificate (pem), signed by the server's client ca
+    #[arg(long, requires = "key")]
+    cert: Option<PathBuf>,
+
+    /// client certificate key (pem)
+    #[arg(long, requires = "cert")]
+    key: Option<PathBuf>,
+
+    /// ca certificate for the server, if it isn't publicly trusted
+    #[arg(long)]
+    ca_cert: Option<PathBuf>,
+
+    /// api key, instead of a client certificate
+    #[arg(long, env = "ENTG_API_KEY", hide_env_values = true)]
+    api_key: Option<String>,
+
+    #[command(subcommand)]
+    command: Command,
+}
+
+#[derive(Subcommand)]
+enum Command {
+    /// start a scan on a library
+    Scan {
+        /// library uuid
+        library: String,
+
+        /// rehash every file, even if its size and mtime are unchanged
+        #[arg(long)]
+        full: bool,
+
+        /// follow the scan until it completes
+        #[arg(short, long)]
+        watch: bool,
+
+        /// seconds between progress checks
+        #[arg(short, long, default_value_t = 2)]
+        interval: u64,
+    },
+    /// show the task history of a library
+    Show {
+        /// library uuid
+        library: String,
+    },
+}
+
+struct CliParser;
+
+impl UuidSource for CliParser {}
+
+#[tokio::main]
+async fn main() -> Result<()> {
+    let cli = Cli::parse();
+
+    let client = TaskClient::new(
+        &cli.url,
+        cli.cert.as_deref(),
+        cli.key.as_deref(),
+        cli.ca_cert.as_deref(),
+        cli.api_key,
+    )
+    .await?;
+
+    match cli.command {
+        Command::Scan {
+            library,
+            full,
+            watch,
+            interval,
+        } => {
+            let library_uuid = LibraryUuid::try_parse(&CliParser, &library)?;
+
+            let task_type = if full {
+                TaskType::ScanLibraryFull
+            } else {
+                TaskType::ScanLibrary
+            };
+
+            scan(
+                &client,
+                library_uuid,
+                task_type,
+                watch,
+                Duration::from_secs(interval),
+            )
+            .await?
+        }
+        Command::Show { library } => {
+            let library_uuid = LibraryUuid::try_parse(&CliParser, &library)?;
+
+            for task in show_tasks(&client, library_uuid).await? {
+                println!("{}", describe(&task));
+            }
+        }
+    }
+
+    Ok(())
+}
+
+async fn scan(
+    client: &TaskClient,
+    library_uuid: LibraryUuid,
+    task_type: TaskType,
+    watch: bool,
+    interval: Duration,
+) -> Result<()> {
+    // task times are in whole seconds, so anything that started in the same second as the
+    // request is taken to be ours
+    let requested = SystemTime::now().duration_since(UNIX_EPOCH)?.as_secs();
+
+    let _: StartTaskResp = client
+        .call(
+            "StartTask",
+            &StartTaskReq {
+                library_uuid,
+                task_type: task_type.clone(),
+            },
+        )
+        .await?;
+
+    println!("started scan on library {library_uuid}");
+
+    if !watch {
+        return Ok(());
+    }
+
+    loop {
+        // a library only runs one task at a time, and the running one is listed first
+        let task = show_tasks(client, library_uuid)
+            .await?
+            .into_iter()
+            .find(|task| task.task_type == task_type && task.start >= requested)
+            .ok_or_else(|| anyhow::Error::msg("scan is missing from the task history"))?;
+
+        println!("{}", describe(&task));
+
+        match task.status {
+            TaskStatus::Running => tokio::time::sleep(interval).await,
+            TaskStatus::Success => return Ok(()),
+            status => return Err(anyhow::Error::msg(format!("scan finished with {status}"))),
+        }
+    }
+}
+
+async fn show_tasks(client: &TaskClient, library_uuid: LibraryUuid) -> Result<Vec<Task>> {
+    let resp: ShowTasksResp = client
+        .call(
+            "ShowTasks",
+            &ShowTasksReq {
+                library: TaskLibrary::User { library_uuid },
+            },
+        )
+        .await?;
+
+    Ok(resp.tasks)
+}
+
+fn describe(task: &Task) -> String {
+    let mut out = format!(
+        "{} {} (started {})",
+        task.task_type, task.status, task.start
+    );
+
+    if let Some(progress) = task.progress.filter(|_| task.status == TaskStatus::Running) {
+        out.push_str(&format!(": {}/{}", progress.processed, progress.total));
+
+        if let Some(percent) = progress.percent() {
+            out.push_str(&format!(" ({percent:.1}%)"));
+        }
+
+        if let Some(eta) = progress.eta(task.start) {
+            out.push_str(&format!(", about {eta}s left"));
+        }
+    }
+
+    if let Some(warnings) = task.warnings.filter(|v| *v > 0) {
+        out.push_str(&format!(", {warnings} warnings"));
+    }
+
+    if let Some(summary) = &task.summary {
+        out.push_str(&format!(", {summary}"));
+    }
+
+    out
+}
@@ -2,7 +2,7 @@ use dioxus::prelude::*;
 use dioxus_router::prelude::*;
 
 use crate::Route;
-use api::{collection::*, thumbnail_link};
+use api::{collection::*, media::ThumbnailSize, thumbnail_link};
 
 #[derive(Clone, PartialEq, Props)]
 pub struct CollectionCardProps {
@@ -120,7 +120,7 @@ fn CollectionCardInner(props: CollectionCardProps) -> Element {
 
                     if let Some(media_uuid) = collection.cover {
                         img {
-                            src: thumbnail_link(media_uuid),
+                            src: thumbnail_link(media_uuid, ThumbnailSize::Grid),
                             alt: format!("Cover for {}", collection.name),
                             style: "width: 100%; height: 100%; object-fit: cover;",
                         }
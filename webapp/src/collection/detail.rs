@@ -14,9 +14,16 @@ use crate::{
         search::SearchBar,
         sidebar::AdvancedSidebar,
     },
+    gallery::GALLERY_RESULTS,
 };
 use api::{
-    UuidSource, collection::*, fold_set, media::MediaUuid, search::{BatchSearchAndSortReq, SearchFilter, SearchRequest, batch_search_and_sort}, sort::SortMethod
+    UuidSource, archive_link,
+    collection::*,
+    fold_set,
+    markdown::render_markdown,
+    media::MediaUuid,
+    search::{BatchSearchAndSortReq, SearchFilter, SearchRequest, batch_search_and_sort},
+    sort::SortMethod,
 };
 
 #[derive(Clone, PartialEq, Props)]
@@ -108,19 +115,34 @@ fn CollectionInner(props: CollectionInnerProps) -> Element {
             req: SearchRequest::Collection(SearchMediaInCollectionReq {
                 collection_uuid,
                 filter: SearchFilter::SubstringAny { filter },
+                recursive: false,
+                ordered: true,
             }),
             sort: SortMethod::Date,
         })
         .await
     });
 
+    // see GALLERY_RESULTS
+    use_effect(move || {
+        if let Some(Ok(resp)) = &*media_future.read() {
+            *GALLERY_RESULTS.write() = resp.media.iter().map(|m| m.media_uuid).collect();
+        }
+    });
+
     // see GalleryInner for details
     //
     // the two futures both early return the same loading skeleton, but they could differ in principle
     let collection_data = &*collection_future.read();
     let collection_data = match collection_data.clone().transpose().show(|error| {
+        let message = if error.is_unauthorized() {
+            String::from("You do not have access to this collection")
+        } else {
+            format!("There was an error fetching the collection metadata: {error}")
+        };
+
         rsx! {
-            CollectionError { message: format!("There was an error fetching the collection metadata: {error}") }
+            CollectionError { message }
         }
     })? {
         None => {
@@ -199,8 +221,9 @@ fn CollectionInner(props: CollectionInnerProps) -> Element {
                             }
 
                             if !collection.note.is_empty() {
-                                p { style: "padding: var(--space-3); background-color: var(--neutral-50); border-radius: var(--radius-md); font-style: italic; color: var(--text-secondary); max-width: 700px;",
-                                    "{collection.note}"
+                                div {
+                                    style: "padding: var(--space-3); background-color: var(--neutral-50); border-radius: var(--radius-md); font-style: italic; color: var(--text-secondary); max-width: 700px;",
+                                    dangerous_inner_html: render_markdown(&collection.note),
                                 }
                             }
                             if !collection.tags.is_empty() {
@@ -211,6 +234,12 @@ fn CollectionInner(props: CollectionInnerProps) -> Element {
                         }
 
                         div { style: "display: flex; gap: var(--space-2);",
+                            a {
+                                class: "btn btn-secondary",
+                                href: archive_link(collection_uuid()),
+                                download: "",
+                                "Download"
+                            }
                             button {
                                 class: "btn btn-secondary",
                                 onclick: move |_| {
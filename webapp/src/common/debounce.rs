@@ -0,0 +1,33 @@
+use dioxus::prelude::*;
+use gloo_timers::callback::Timeout;
+
+// the default for inputs that look something up as the user types
+pub const DEBOUNCE_MS: u32 = 300;
+
+// a copy of the source signal that only catches up once it has stopped changing for delay_ms,
+// so that a use_resource() reading it runs once per pause instead of once per keystroke.
+//
+// each change replaces the pending timer, and dropping a gloo Timeout cancels it, so only the
+// last value in a burst is ever set.  resources already drop their superseded futures when
+// they re-run, which takes care of any request that is still in flight.
+pub fn use_debounced<T>(source: Signal<T>, delay_ms: u32) -> Signal<T>
+where
+    T: Clone + PartialEq + 'static,
+{
+    let mut debounced = use_signal(|| source.peek().clone());
+    let mut pending = use_signal(|| None::<Timeout>);
+
+    use_effect(move || {
+        let value = source();
+
+        let timeout = Timeout::new(delay_ms, move || {
+            if *debounced.peek() != value {
+                debounced.set(value);
+            }
+        });
+
+        pending.set(Some(timeout));
+    });
+
+    debounced
+}
@@ -1,4 +1,5 @@
 pub mod colors;
+pub mod debounce;
 pub mod storage;
 pub mod style;
 
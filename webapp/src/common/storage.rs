@@ -47,3 +47,48 @@ where
         Err(_) => T::default(),
     }
 }
+
+// scroll state
+//
+// where a page was scrolled to, along with how many pages of results it had loaded to get
+// there, so that a reload can land back in the same place.  the key is the route, so each
+// page keeps its own.
+#[derive(Clone, Copy, Debug, Default, Deserialize, PartialEq, Serialize)]
+pub struct ScrollState {
+    pub scroll_top: i32,
+    pub pages: u64,
+}
+
+pub fn set_scroll_state(route: &str, state: ScrollState) {
+    set_local_storage(&format!("scroll_{route}"), state)
+}
+
+pub fn get_scroll_state(route: &str) -> ScrollState {
+    try_local_storage(&format!("scroll_{route}"))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // LocalStorage keeps values as json, so this is the round trip that a reload goes through
+    #[test]
+    fn scroll_state_round_trip() {
+        let state = ScrollState {
+            scroll_top: 2400,
+            pages: 3,
+        };
+
+        let stored = serde_json::to_string(&state).unwrap();
+
+        assert_eq!(serde_json::from_str::<ScrollState>(&stored).unwrap(), state);
+    }
+
+    // try_local_storage() falls back to the default for anything that doesn't parse
+    #[test]
+    fn scroll_state_rejects_malformed_values() {
+        assert!(serde_json::from_str::<ScrollState>(r#"{"scroll_top": 2400}"#).is_err());
+        assert!(serde_json::from_str::<ScrollState>(r#"{"scroll_top": 0, "pages": -1}"#).is_err());
+        assert!(serde_json::from_str::<ScrollState>("not json").is_err());
+    }
+}
@@ -236,6 +236,8 @@ pub const BASE_COMPONENTS: &str = r#"
   display: flex;
   align-items: center;
   justify-content: center;
+  /* Pinches and drags are handled by the viewer, not the browser */
+  touch-action: none;
 }
 
 .fullsize-image {
@@ -244,17 +246,15 @@ pub const BASE_COMPONENTS: &str = r#"
   max-height: 100%;
   object-fit: contain;
   cursor: grab;
+  user-select: none;
+  /* Zooming only changes the transform, so keep the image on its own layer */
+  will-change: transform;
 }
 
 .fullsize-image.panning {
   cursor: grabbing;
 }
 
-.fullsize-image.zoomed {
-  /* Override contain when zoomed */
-  object-fit: none;
-}
-
 /* Zoom controls */
 .zoom-controls {
   position: absolute;
@@ -333,6 +333,49 @@ pub const BASE_COMPONENTS: &str = r#"
   100% { background-position: 200% 0; }
 }
 
+/* Spinner for loading more results */
+.loading-spinner {
+  width: 32px;
+  height: 32px;
+  margin: var(--space-6) auto;
+  border: 3px solid var(--neutral-200);
+  border-top-color: var(--primary);
+  border-radius: 50%;
+  animation: spin 0.8s linear infinite;
+}
+
+@keyframes spin {
+  to { transform: rotate(360deg); }
+}
+
+/* Drag-and-drop upload target */
+.drop-zone {
+  margin-top: var(--space-4);
+  padding: var(--space-4);
+  border: 2px dashed var(--border);
+  border-radius: var(--radius-md);
+  transition: border-color var(--transition-fast) var(--easing-standard),
+    background-color var(--transition-fast) var(--easing-standard);
+}
+
+.drop-zone.active {
+  border-color: var(--primary);
+  background-color: var(--neutral-50);
+}
+
+.upload-list {
+  max-height: 200px;
+  overflow-y: auto;
+  font-size: 0.875rem;
+}
+
+.upload-list li {
+  display: flex;
+  justify-content: space-between;
+  gap: var(--space-4);
+  padding: var(--space-1) 0;
+}
+
 /* Collection Selection Item */
 .collection-item {
   padding: var(--space-3);
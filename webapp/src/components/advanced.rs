@@ -141,7 +141,7 @@ pub enum BulkEditMode {
     EditTags,
     AddToCollection,
     //RmFromCollection,
-    //Hide,
+    Hide,
 }
 #[derive(Clone, PartialEq, Props)]
 struct BulkEditButtonProps {
@@ -159,6 +159,7 @@ fn BulkEditButton(props: BulkEditButtonProps) -> Element {
             "Add to Collection",
             Modal::BulkAddToCollection(bulk_edit_signal()),
         ),
+        BulkEditMode::Hide => ("Hide / Unhide", Modal::BulkHide(bulk_edit_signal())),
     };
 
     rsx! {
@@ -241,6 +242,15 @@ pub fn BulkEditTab(props: BulkEditTabProps) -> Element {
             div {
                 class: "bulk-actions",
                 style: "display: flex; gap: var(--space-3); margin-top: var(--space-4);",
+                button {
+                    class: "btn btn-secondary",
+                    onclick: move |_| {
+                        if let Some(media_uuids) = media_uuids() {
+                            bulk_edit_signal.set(Some(media_uuids));
+                        }
+                    },
+                    "Select All"
+                }
                 button {
                     class: "btn btn-secondary",
                     onclick: move |_| {
@@ -270,6 +280,7 @@ pub fn BulkEditTab(props: BulkEditTabProps) -> Element {
             }
             div { class: "info-box", style: "margin-top: var(--space-4);",
                 p { "Click on media items in the gallery to select them. Click again to deselect." }
+                p { "Shift-click to select (or deselect) everything since the last click." }
                 p { "Selected items will be highlighted with a blue border." }
             }
         } else {
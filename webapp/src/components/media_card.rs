@@ -6,10 +6,24 @@ use dioxus_router::prelude::*;
 use crate::{
     Route,
     common::{colors::CollectionColor, storage::set_local_storage},
-    gallery::GALLERY_COLLECTION_KEY,
+    gallery::{GALLERY_COLLECTION_KEY, GALLERY_RESULTS},
 };
 use api::{collection::CollectionUuid, media::*, thumbnail_link};
 
+// the last media toggled in bulk edit mode, which is where a shift-click range starts
+static SELECTION_ANCHOR: GlobalSignal<Option<MediaUuid>> = Signal::global(|| None);
+
+// the media between the anchor and the target (inclusive) in the order that the grid
+// displays them, see GALLERY_RESULTS
+fn selection_range(anchor: MediaUuid, target: MediaUuid) -> Option<Vec<MediaUuid>> {
+    let results = GALLERY_RESULTS.peek();
+
+    let start = results.iter().position(|v| *v == anchor)?;
+    let end = results.iter().position(|v| *v == target)?;
+
+    Some(results[start.min(end)..=start.max(end)].to_vec())
+}
+
 // TODO -- deduplicate the error handling in the the callsites by making a MediaGrid
 // with an error boundary
 #[derive(Clone, PartialEq, Props)]
@@ -40,19 +54,31 @@ pub fn MediaCard(props: MediaCardProps) -> Element {
         .map(|s| s.contains(&media_uuid))
         .unwrap_or(false);
 
+    // shift-clicking applies the same change to everything back to the last click, so a
+    // range is deselected by shift-clicking an item that is already selected
     let mut toggle_selection = move |evt: MouseEvent| {
         evt.prevent_default();
         evt.stop_propagation();
 
+        let range = match (evt.modifiers().shift(), *SELECTION_ANCHOR.peek()) {
+            (true, Some(anchor)) => selection_range(anchor, media_uuid),
+            _ => None,
+        }
+        .unwrap_or_else(|| Vec::from([media_uuid]));
+
         bulk_edit_signal.with_mut(|set| {
             if let Some(set) = set {
                 if set.contains(&media_uuid) {
-                    set.remove(&media_uuid);
+                    for uuid in range {
+                        set.remove(&uuid);
+                    }
                 } else {
-                    set.insert(media_uuid);
+                    set.extend(range);
                 }
             }
         });
+
+        *SELECTION_ANCHOR.write() = Some(media_uuid);
     };
 
     let collection_context = props.collection_uuid.map(|uuid| uuid.to_string());
@@ -104,6 +130,12 @@ pub fn MediaCard(props: MediaCardProps) -> Element {
             if bulk_edit_signal().is_some() {
                 div {
                     style: "position: absolute; top: 0; left: 0; width: 100%; height: 100%; z-index: 10; cursor: pointer;",
+                    // otherwise the browser also selects the text between the clicks
+                    onmousedown: move |evt: MouseEvent| {
+                        if evt.modifiers().shift() {
+                            evt.prevent_default();
+                        }
+                    },
                     onclick: toggle_selection,
                     // somewhat inexplicably, this is the radio button in the corner of the tile
                     div { style: "position: absolute; top: 10px; right: 10px; width: 24px; height: 24px; border-radius: 50%; background-color: var(--surface); border: 2px solid var(--primary); display: flex; align-items: center; justify-content: center;",
@@ -129,7 +161,7 @@ pub fn MediaCard(props: MediaCardProps) -> Element {
                 },
                 div { class: "media-card-image",
                     img {
-                        src: thumbnail_link(media_uuid),
+                        src: thumbnail_link(media_uuid, ThumbnailSize::Grid),
                         alt: if media.note.is_empty() { format!("Media {}", media_uuid) } else { media.note.clone() },
                         loading: "lazy",
                     }
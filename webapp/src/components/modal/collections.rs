@@ -4,13 +4,16 @@ use dioxus::prelude::*;
 use gloo_timers::callback::Timeout;
 use tracing::error;
 
-use crate::components::{
-    modal::{MODAL_STACK, Modal, ModalInner, ModalSize, ProgressBar},
-    search::CompactSearchBar,
+use crate::{
+    common::debounce::{DEBOUNCE_MS, use_debounced},
+    components::{
+        modal::{MODAL_STACK, Modal, ModalError, ModalInner, ModalSize, ProgressBar},
+        search::CompactSearchBar,
+    },
 };
 use api::{
-    FOLDING_SEPARATOR, auth::*, collection::*, fold_set, media::MediaUuid, search::SearchFilter,
-    unfold_set,
+    FOLDING_SEPARATOR, WebError, auth::*, collection::*, fold_set, media::MediaUuid,
+    search::SearchFilter, unfold_set, validate_tags,
 };
 
 #[derive(Clone, PartialEq, Props)]
@@ -33,10 +36,12 @@ pub fn CreateCollectionModal(props: CreateCollectionModalProps) -> Element {
 
     let mut name_error = use_signal(String::new);
     let mut group_error = use_signal(String::new);
+    let mut tags_error = use_signal(String::new);
 
     let handle_submit = move |_| async move {
         name_error.set(String::new());
         group_error.set(String::new());
+        tags_error.set(String::new());
 
         // Basic validation
         let mut is_valid = true;
@@ -51,6 +56,13 @@ pub fn CreateCollectionModal(props: CreateCollectionModalProps) -> Element {
             is_valid = false;
         }
 
+        let tags = unfold_set(&collection_tags());
+
+        if let Err(err) = validate_tags(&tags) {
+            tags_error.set(err.to_string());
+            is_valid = false;
+        }
+
         if !is_valid {
             return;
         }
@@ -63,8 +75,9 @@ pub fn CreateCollectionModal(props: CreateCollectionModalProps) -> Element {
                 gid: collection_group(),
                 name: collection_name(),
                 note: collection_note(),
-                tags: unfold_set(&collection_tags()),
+                tags,
                 cover: None,
+                parent_uuid: None,
             },
         })
         .await
@@ -88,11 +101,11 @@ pub fn CreateCollectionModal(props: CreateCollectionModalProps) -> Element {
         }
     };
 
-    // Display the users of the given group
-    //
-    // TODO -- this needs debounced, or maybe a button to check explicity
+    // Display the users of the given group, once the user stops typing
+    let debounced_group = use_debounced(collection_group, DEBOUNCE_MS);
+
     let group_future = use_resource(move || async move {
-        let gid = collection_group();
+        let gid = debounced_group();
 
         if gid.trim().is_empty() {
             return HashSet::new();
@@ -257,6 +270,13 @@ pub fn CreateCollectionModal(props: CreateCollectionModalProps) -> Element {
                         oninput: move |evt| collection_tags.set(evt.value().clone()),
                         placeholder: format!("Add tags for this collection, separated by {}", FOLDING_SEPARATOR),
                     }
+                    if !tags_error().is_empty() {
+                        div {
+                            class: "form-error",
+                            style: "color: var(--error); font-size: 0.875rem; margin-top: 0.25rem;",
+                            "{tags_error}"
+                        }
+                    }
                 }
             }
         }
@@ -276,7 +296,7 @@ pub fn EditCollectionModal(props: EditCollectionModalProps) -> Element {
     // Fetch collection details to pre-fill the form
     let collection_uuid = props.collection_uuid;
 
-    let collection_future =
+    let mut collection_future =
         use_resource(
             move || async move { get_collection(&GetCollectionReq { collection_uuid }).await },
         );
@@ -418,10 +438,9 @@ pub fn EditCollectionModal(props: EditCollectionModalProps) -> Element {
                         }
                     }
                     Some(Err(err)) => rsx! {
-                        div {
-                            class: "error-state",
-                            style: "color: var(--error); padding: var(--space-4); text-align: center;",
-                            "Error loading collection: {err}"
+                        ModalError {
+                            message: format!("Error loading collection: {err}"),
+                            on_retry: move |_| collection_future.restart(),
                         }
                     },
                     None => rsx! {
@@ -449,15 +468,19 @@ pub fn DeleteCollectionModal(props: DeleteCollectionModalProps) -> Element {
 
     let collection_uuid = props.collection_uuid;
 
-    let collection_future =
+    let mut collection_future =
         use_resource(
             move || async move { get_collection(&GetCollectionReq { collection_uuid }).await },
         );
 
-    // TODO -- we may want a general ModalError here instead of just skipping the failure
-    let collection_name = match &*collection_future.read() {
-        Some(Ok(result)) => result.collection.name.clone(),
-        _ => format!("Collection #{}", collection_uuid),
+    // the uuid is still enough to go ahead with, but the failure should not be hidden
+    let (collection_name, collection_error) = match &*collection_future.read() {
+        Some(Ok(result)) => (result.collection.name.clone(), None),
+        Some(Err(err)) => (
+            format!("Collection #{}", collection_uuid),
+            Some(format!("Error loading collection: {err}")),
+        ),
+        None => (format!("Collection #{}", collection_uuid), None),
     };
 
     let mut status_signal = use_signal(String::new);
@@ -501,6 +524,13 @@ pub fn DeleteCollectionModal(props: DeleteCollectionModalProps) -> Element {
             footer,
 
             div { class: "confirmation-content",
+                if let Some(message) = collection_error {
+                    ModalError {
+                        message,
+                        on_retry: move |_| collection_future.restart(),
+                    }
+                }
+
                 p {
                     class: "confirmation-message",
                     style: "margin-bottom: var(--space-4);",
@@ -543,13 +573,6 @@ pub fn AddMediaToCollectionModal(props: AddMediaToCollectionModalProps) -> Eleme
         .await
     });
 
-    // TODO -- better error handling when collections cannot be searched
-    let collections = match &*collections_future.read() {
-        Some(Ok(response)) => Some(response.collections.clone()),
-        Some(Err(_)) => None,
-        None => None,
-    };
-
     let mut status_signal = use_signal(String::new);
 
     let media_uuid = props.media_uuid;
@@ -613,7 +636,7 @@ pub fn AddMediaToCollectionModal(props: AddMediaToCollectionModalProps) -> Eleme
                     placeholder: "Enter collection name or description...",
                 }
 
-                CollectionSelectionList { collections, selected_collection }
+                CollectionSelectionList { collections_future, selected_collection }
 
                 // Create new collection button
                 div { style: "margin-top: var(--space-4); text-align: center;",
@@ -647,15 +670,19 @@ pub fn RmFromCollectionModal(props: RmFromCollectionModalProps) -> Element {
 
     let collection_uuid = props.collection_uuid;
 
-    let collection_future =
+    let mut collection_future =
         use_resource(
             move || async move { get_collection(&GetCollectionReq { collection_uuid }).await },
         );
 
-    // TODO -- we may want a general ModalError here instead of just skipping the failure
-    let collection_name = match &*collection_future.read() {
-        Some(Ok(result)) => result.collection.name.clone(),
-        _ => format!("Collection #{}", collection_uuid),
+    // the uuid is still enough to go ahead with, but the failure should not be hidden
+    let (collection_name, collection_error) = match &*collection_future.read() {
+        Some(Ok(result)) => (result.collection.name.clone(), None),
+        Some(Err(err)) => (
+            format!("Collection #{}", collection_uuid),
+            Some(format!("Error loading collection: {err}")),
+        ),
+        None => (format!("Collection #{}", collection_uuid), None),
     };
 
     let mut status_signal = use_signal(String::new);
@@ -703,6 +730,13 @@ pub fn RmFromCollectionModal(props: RmFromCollectionModalProps) -> Element {
         ModalInner { title: "Confirm Removal", size: ModalSize::Small, footer,
 
             div { class: "confirmation-content",
+                if let Some(message) = collection_error {
+                    ModalError {
+                        message,
+                        on_retry: move |_| collection_future.restart(),
+                    }
+                }
+
                 p { class: "confirmation-message",
                     "Are you sure you want to remove this media from \"{collection_name}\"? The media will still exist in your library."
                 }
@@ -751,13 +785,6 @@ pub fn BulkAddToCollectionModal(props: BulkAddToCollectionModalProps) -> Element
         .await
     });
 
-    // TODO -- better error handling when collections cannot be searched
-    let collections = match &*collections_future.read() {
-        Some(Ok(response)) => Some(response.collections.clone()),
-        Some(Err(_)) => None,
-        None => None,
-    };
-
     let mut status_signal = use_signal(String::new);
 
     let mut processing_count = use_signal(|| 0);
@@ -775,6 +802,8 @@ pub fn BulkAddToCollectionModal(props: BulkAddToCollectionModalProps) -> Element
                     filter: SearchFilter::SubstringAny {
                         filter: HashSet::new(),
                     },
+                    recursive: false,
+                    ordered: false,
                 })
                 .await
                 {
@@ -887,7 +916,7 @@ pub fn BulkAddToCollectionModal(props: BulkAddToCollectionModalProps) -> Element
                     placeholder: "Enter collection name or description...",
                 }
 
-                CollectionSelectionList { collections, selected_collection }
+                CollectionSelectionList { collections_future, selected_collection }
 
                 // Media count summary
                 div { style: "margin-top: var(--space-4); padding: var(--space-3); background-color: var(--neutral-50); border-radius: var(--radius-md);",
@@ -916,22 +945,34 @@ pub fn BulkAddToCollectionModal(props: BulkAddToCollectionModalProps) -> Element
 
 #[derive(Clone, PartialEq, Props)]
 pub struct CollectionSelectionListProps {
-    collections: Option<Vec<CollectionUuid>>,
+    collections_future: Resource<Result<SearchCollectionsResp, WebError>>,
     selected_collection: Signal<Option<CollectionUuid>>,
 }
 
 #[component]
 fn CollectionSelectionList(props: CollectionSelectionListProps) -> Element {
-    let collections = props.collections;
+    let mut collections_future = props.collections_future;
     let mut selected_collection = props.selected_collection;
 
+    let collections = match &*collections_future.read() {
+        Some(Ok(response)) => Ok(Some(response.collections.clone())),
+        Some(Err(err)) => Err(format!("Error searching collections: {err}")),
+        None => Ok(None),
+    };
+
     rsx! {
         div {
             class: "collections-list",
             style: "margin-top: var(--space-4); max-height: 300px; overflow-y: auto; border: 1px solid var(--border); border-radius: var(--radius-md);",
 
             match collections {
-                Some(collections) => {
+                Err(message) => rsx! {
+                    ModalError {
+                        message,
+                        on_retry: move |_| collections_future.restart(),
+                    }
+                },
+                Ok(Some(collections)) => {
                     if collections.is_empty() {
                         rsx! {
                             div {
@@ -953,7 +994,7 @@ fn CollectionSelectionList(props: CollectionSelectionListProps) -> Element {
                         }
                     }
                 }
-                None => {
+                Ok(None) => {
                     rsx! {
                         // Loading state
                         for _ in 0..3 {
@@ -1045,12 +1086,12 @@ fn CollectionSelectionItem(props: CollectionSelectionItemProps) -> Element {
                 }
             }
         }
-        Some(Err(_)) => {
+        Some(Err(err)) => {
             rsx! {
                 div {
                     class: "collection-item error",
                     style: "padding: var(--space-3); border-bottom: 1px solid var(--border); color: var(--error);",
-                    "Error loading collection #{collection_uuid}"
+                    "Error loading collection #{collection_uuid}: {err}"
                 }
             }
         }
@@ -3,10 +3,10 @@ use gloo_timers::callback::Timeout;
 
 use crate::{
     common::local_time,
-    components::modal::{MODAL_STACK, ModalInner, ModalSize},
+    components::modal::{MODAL_STACK, ModalError, ModalInner, ModalSize},
 };
 
-use api::{library::LibraryUuid, task::*};
+use api::{library::*, task::*};
 
 #[derive(Clone, PartialEq, Props)]
 pub struct StartTaskModalProps {
@@ -73,6 +73,14 @@ pub fn StartTaskModal(props: StartTaskModalProps) -> Element {
                             is_selected: selected_task() == TaskType::ScanLibrary,
                             on_select: move |_| selected_task.set(TaskType::ScanLibrary),
                         }
+                        TaskOption {
+                            task_type: TaskType::ScanLibraryFull,
+                            title: "Full Scan",
+                            description: "Scan the library, rehashing files even if they look unchanged.",
+                            icon: "🔬",
+                            is_selected: selected_task() == TaskType::ScanLibraryFull,
+                            on_select: move |_| selected_task.set(TaskType::ScanLibraryFull),
+                        }
                         TaskOption {
                             task_type: TaskType::CleanLibrary,
                             title: "Clean Library",
@@ -89,6 +97,46 @@ pub fn StartTaskModal(props: StartTaskModalProps) -> Element {
                             is_selected: selected_task() == TaskType::RunScripts,
                             on_select: move |_| selected_task.set(TaskType::RunScripts),
                         }
+                        TaskOption {
+                            task_type: TaskType::FindDuplicates,
+                            title: "Find Duplicates",
+                            description: "Report media that share the same contents.",
+                            icon: "👯",
+                            is_selected: selected_task() == TaskType::FindDuplicates,
+                            on_select: move |_| selected_task.set(TaskType::FindDuplicates),
+                        }
+                        TaskOption {
+                            task_type: TaskType::MergeDuplicates,
+                            title: "Merge Duplicates",
+                            description: "Collapse media that share the same contents into a single copy.",
+                            icon: "🗜️",
+                            is_selected: selected_task() == TaskType::MergeDuplicates,
+                            on_select: move |_| selected_task.set(TaskType::MergeDuplicates),
+                        }
+                        TaskOption {
+                            task_type: TaskType::ParseDates,
+                            title: "Parse Dates",
+                            description: "Fill in missing media dates from the exif data or filename.",
+                            icon: "📅",
+                            is_selected: selected_task() == TaskType::ParseDates,
+                            on_select: move |_| selected_task.set(TaskType::ParseDates),
+                        }
+                        TaskOption {
+                            task_type: TaskType::VerifyHashes,
+                            title: "Verify Files",
+                            description: "Check that the original files still match their content hashes.",
+                            icon: "🩺",
+                            is_selected: selected_task() == TaskType::VerifyHashes,
+                            on_select: move |_| selected_task.set(TaskType::VerifyHashes),
+                        }
+                        TaskOption {
+                            task_type: TaskType::VerifyHashesTagMissing,
+                            title: "Verify Files and Tag Missing",
+                            description: "Check the original files, and tag any that no longer exist.",
+                            icon: "🏷️",
+                            is_selected: selected_task() == TaskType::VerifyHashesTagMissing,
+                            on_select: move |_| selected_task.set(TaskType::VerifyHashesTagMissing),
+                        }
                     }
                 }
                 div {
@@ -106,9 +154,20 @@ pub fn StartTaskModal(props: StartTaskModalProps) -> Element {
                                 li { "New files will be added to the database." }
                                 li { "Thumbnails will be generated for new media." }
                                 li { "Metadata will be extracted where possible." }
+                                li { "Files whose size and modification time are unchanged are skipped." }
                                 li { "Coming soon: scripts will run to update metadata and tags." }
                             }
                         },
+                        TaskType::ScanLibraryFull => rsx! {
+                            p {
+                                "This task will scan the library like Scan Library, but without trusting the file sizes and modification times."
+                            }
+                            ul { style: "margin-top: var(--space-2); margin-left: var(--space-4); list-style-type: disc;",
+                                li { "Every file is hashed, so this takes much longer on a large library." }
+                                li { "Records for files whose contents changed are updated." }
+                                li { "New files will be added to the database, as in a normal scan." }
+                            }
+                        },
                         TaskType::CleanLibrary => rsx! {
                             p {
                                 "This task will check for database entries that no longer exist in the filesystem and mark them accordingly."
@@ -128,6 +187,48 @@ pub fn StartTaskModal(props: StartTaskModalProps) -> Element {
                                 li { "Automated tagging may be performed." }
                             }
                         },
+                        TaskType::FindDuplicates => rsx! {
+                            p { "This task will look for media in the library with identical contents." }
+                            ul { style: "margin-top: var(--space-2); margin-left: var(--space-4); list-style-type: disc;",
+                                li { "Media are compared by content hash, so only exact copies are found." }
+                                li { "The number of copies and the space they use are shown once the task completes." }
+                                li { "Nothing is changed." }
+                            }
+                        },
+                        TaskType::MergeDuplicates => rsx! {
+                            p { "This task will collapse each group of identical media into a single copy." }
+                            ul { style: "margin-top: var(--space-2); margin-left: var(--space-4); list-style-type: disc;",
+                                li { "The copy in the most collections is kept, and is added to the collections of the others." }
+                                li { "Tags from every copy are added to the one that is kept." }
+                                li { "The other copies are tagged DUPLICATE:<uuid> and moved to the trash." }
+                                li { "No originals will be deleted from the filesystem." }
+                            }
+                        },
+                        TaskType::ParseDates => rsx! {
+                            p { "This task will look for better dates for media whose date is missing or only the file modification time." }
+                            ul { style: "margin-top: var(--space-2); margin-left: var(--space-4); list-style-type: disc;",
+                                li { "The exif capture time is used if the image has one." }
+                                li { "Otherwise, dates in filenames like IMG_20210704_123456.jpg or 2021-07-04.jpg are used." }
+                                li { "Media that already have a capture date, or where no date can be found, are left alone." }
+                                li { "The number of dates corrected is shown once the task completes." }
+                            }
+                        },
+                        TaskType::VerifyHashes => rsx! {
+                            p { "This task will rehash every original in the library and compare it to the stored hash." }
+                            ul { style: "margin-top: var(--space-2); margin-left: var(--space-4); list-style-type: disc;",
+                                li { "Originals that changed (from disk errors or being replaced) or no longer exist are counted as warnings." }
+                                li { "The number of verified, changed, and missing originals is shown once the task completes." }
+                                li { "Nothing is changed.  Rescan the library to pick up files that were replaced on purpose." }
+                            }
+                        },
+                        TaskType::VerifyHashesTagMissing => rsx! {
+                            p { "This task will verify the originals like Verify Files, and also mark the missing ones." }
+                            ul { style: "margin-top: var(--space-2); margin-left: var(--space-4); list-style-type: disc;",
+                                li { "Media whose original no longer exists are tagged TBD_DELETEME." }
+                                li { "Originals that changed are only reported." }
+                                li { "No originals will be deleted from the filesystem." }
+                            }
+                        },
                         _ => rsx! {},
                     }
                     p { style: "margin-top: var(--space-3); font-style: italic; color: var(--text-tertiary);",
@@ -290,7 +391,7 @@ pub struct TaskHistoryModalProps {
 pub fn TaskHistoryModal(props: TaskHistoryModalProps) -> Element {
     let library_uuid = props.library_uuid;
 
-    let task_history_future = use_resource(move || async move {
+    let mut task_history_future = use_resource(move || async move {
         show_tasks(&ShowTasksReq {
             library: TaskLibrary::User { library_uuid },
         })
@@ -398,10 +499,9 @@ pub fn TaskHistoryModal(props: TaskHistoryModalProps) -> Element {
                         }
                     }
                     Some(Err(err)) => rsx! {
-                        div {
-                            class: "error-state",
-                            style: "padding: var(--space-4); color: var(--error); text-align: center;",
-                            "Failed to load task history: {err}"
+                        ModalError {
+                            message: format!("Failed to load task history: {err}"),
+                            on_retry: move |_| task_history_future.restart(),
                         }
                     },
                     None => rsx! {
@@ -416,3 +516,118 @@ pub fn TaskHistoryModal(props: TaskHistoryModalProps) -> Element {
         }
     }
 }
+
+#[derive(Clone, PartialEq, Props)]
+pub struct EditLibraryModalProps {
+    update_signal: Signal<()>,
+    library_uuid: LibraryUuid,
+}
+
+#[component]
+pub fn EditLibraryModal(props: EditLibraryModalProps) -> Element {
+    let mut update_signal = props.update_signal;
+    let library_uuid = props.library_uuid;
+
+    let mut library_future =
+        use_resource(move || async move { get_library(&GetLibraryReq { library_uuid }).await });
+
+    let mut status_signal = use_signal(String::new);
+    let mut library_name = use_signal(String::new);
+    let mut library_note = use_signal(String::new);
+
+    let handle_submit = move |_| async move {
+        status_signal.set("Updating library...".into());
+
+        match update_library(&UpdateLibraryReq {
+            library_uuid,
+            update: LibraryUpdate {
+                name: Some(library_name().trim().to_owned()),
+                note: Some(library_note()),
+                count: None,
+            },
+        })
+        .await
+        {
+            Ok(_) => {
+                status_signal.set("Library updated successfully".into());
+                update_signal.set(());
+
+                let task = Timeout::new(1500, move || {
+                    MODAL_STACK.with_mut(|v| v.pop());
+                });
+                task.forget();
+            }
+            Err(err) => {
+                status_signal.set(format!("Error: {}", err));
+            }
+        }
+    };
+
+    use_effect(move || {
+        if let Some(Ok(result)) = &*library_future.read() {
+            library_name.set(result.library.name.clone());
+            library_note.set(result.library.note.clone());
+        }
+    });
+
+    let footer = rsx! {
+        span { class: "status-message", style: "color: var(--primary);", "{status_signal}" }
+        div {
+            class: "modal-buttons",
+            style: "display: flex; gap: var(--space-4); justify-content: flex-end;",
+            button {
+                class: "btn btn-secondary",
+                onclick: move |_| {
+                    MODAL_STACK.with_mut(|v| v.pop());
+                },
+                "Cancel"
+            }
+            button { class: "btn btn-primary", onclick: handle_submit, "Save Changes" }
+        }
+    };
+
+    rsx! {
+        ModalInner { title: "Edit Library", size: ModalSize::Medium, footer,
+            div { class: "edit-library-form",
+                match &*library_future.read() {
+                    Some(Ok(resp)) => {
+                        rsx! {
+                            div { class: "form-group",
+                                label { class: "form-label", "Library Name (optional)" }
+                                input {
+                                    class: "form-input",
+                                    r#type: "text",
+                                    value: "{library_name}",
+                                    oninput: move |evt| library_name.set(evt.value().clone()),
+                                    placeholder: "{resp.library.path}",
+                                }
+                            }
+                            div { class: "form-group",
+                                label { class: "form-label", "Description (optional)" }
+                                textarea {
+                                    class: "form-textarea",
+                                    rows: 3,
+                                    value: "{library_note}",
+                                    oninput: move |evt| library_note.set(evt.value().clone()),
+                                    placeholder: "Add a description for this library...",
+                                }
+                            }
+                        }
+                    }
+                    Some(Err(err)) => rsx! {
+                        ModalError {
+                            message: format!("Error loading library: {err}"),
+                            on_retry: move |_| library_future.restart(),
+                        }
+                    },
+                    None => rsx! {
+                        div { class: "loading-state",
+                            div { class: "skeleton", style: "height: 40px; margin-bottom: 16px;" }
+                            div { class: "skeleton", style: "height: 80px; margin-bottom: 16px;" }
+                        }
+                    },
+                }
+            }
+        }
+    }
+}
@@ -1,10 +1,44 @@
 use std::collections::HashSet;
 
-use dioxus::prelude::*;
+use dioxus::{
+    html::geometry::{ClientPoint, WheelDelta},
+    prelude::*,
+};
 use tracing::error;
 
-use crate::components::modal::{MODAL_STACK, ModalInner, ModalSize, ProgressBar};
-use api::{FOLDING_SEPARATOR, full_link, media::*, unfold_set};
+use crate::components::modal::{MODAL_STACK, ModalError, ModalInner, ModalSize, ProgressBar};
+use api::{FOLDING_SEPARATOR, full_link, media::*, unfold_set, validate_tags};
+
+// zoom limits for the image viewer, as multiples of the fitted size
+const MIN_ZOOM: f64 = 0.5;
+const MAX_ZOOM: f64 = 8.0;
+const ZOOM_STEP: f64 = 0.25;
+
+// how quickly scrolling zooms, per pixel of scroll
+const WHEEL_ZOOM_RATE: f64 = 0.002;
+
+// scroll deltas reported in lines or pages are converted to (rough) pixels
+const WHEEL_LINE_HEIGHT: f64 = 16.0;
+const WHEEL_PAGE_HEIGHT: f64 = 800.0;
+
+const ZOOM_CONTAINER_ID: &str = "zoom-container";
+
+// the offset of a point from the center of the image container, which is also where the
+// image is centered before any translation
+fn container_offset(point: ClientPoint) -> (f64, f64) {
+    let rect = web_sys::window()
+        .and_then(|v| v.document())
+        .and_then(|v| v.get_element_by_id(ZOOM_CONTAINER_ID))
+        .map(|v| v.get_bounding_client_rect());
+
+    match rect {
+        Some(rect) => (
+            point.x - rect.left() - rect.width() / 2.0,
+            point.y - rect.top() - rect.height() / 2.0,
+        ),
+        None => (0.0, 0.0),
+    }
+}
 
 #[derive(Clone, PartialEq, Props)]
 pub struct EnhancedMediaModalProps {
@@ -16,45 +50,60 @@ pub fn EnhancedMediaModal(props: EnhancedMediaModalProps) -> Element {
     let media_uuid = props.media_uuid;
 
     // State for image pan and zoom
+    //
+    // the offset is in screen pixels and is applied before the scale, both relative to the
+    // center of the container (where the image sits when unzoomed)
     let mut zoom_level = use_signal(|| 1.0);
     let mut is_panning = use_signal(|| false);
     let mut translate_x = use_signal(|| 0.0);
     let mut translate_y = use_signal(|| 0.0);
     let mut start_pos_x = use_signal(|| 0.0);
     let mut start_pos_y = use_signal(|| 0.0);
+    let mut pinch_distance = use_signal(|| None::<f64>);
 
     // Fetch media data
-    let media_future =
+    let mut media_future =
         use_resource(move || async move { get_media(&GetMediaReq { media_uuid }).await });
 
     let get_transform_style = move || {
         format!(
-            "transform: scale({}) translate({}px, {}px);",
-            zoom_level(),
+            "transform: translate({}px, {}px) scale({});",
             translate_x(),
-            translate_y()
+            translate_y(),
+            zoom_level()
         )
     };
 
-    // Helper functions for zoom controls
-    let mut zoom_in = move |_| {
-        if zoom_level() < 3.0 {
-            zoom_level.set(zoom_level() + 0.25);
+    // zoom to the target level while keeping the point at (focus_x, focus_y), measured from
+    // the container center, fixed on screen
+    let mut zoom_to = move |target: f64, focus_x: f64, focus_y: f64| {
+        let target = target.clamp(MIN_ZOOM, MAX_ZOOM);
+
+        // Reset translation if we're back to normal size
+        if target <= 1.0 {
+            translate_x.set(0.0);
+            translate_y.set(0.0);
+        } else {
+            let ratio = target / zoom_level();
+            translate_x.set(focus_x - (focus_x - translate_x()) * ratio);
+            translate_y.set(focus_y - (focus_y - translate_y()) * ratio);
         }
-    };
 
-    let mut zoom_out = move |_| {
-        if zoom_level() > 0.5 {
-            zoom_level.set(zoom_level() - 0.25);
+        zoom_level.set(target);
+    };
 
-            // Reset translation if we're back to normal size
-            if zoom_level() <= 1.0 {
-                translate_x.set(0.0);
-                translate_y.set(0.0);
-            }
-        }
+    let mut pan_to = move |x: f64, y: f64| {
+        translate_x.set(translate_x() + x - start_pos_x());
+        translate_y.set(translate_y() + y - start_pos_y());
+        start_pos_x.set(x);
+        start_pos_y.set(y);
     };
 
+    // Helper functions for zoom controls
+    let mut zoom_in = move |_| zoom_to(zoom_level() + ZOOM_STEP, 0.0, 0.0);
+
+    let mut zoom_out = move |_| zoom_to(zoom_level() - ZOOM_STEP, 0.0, 0.0);
+
     let mut reset_zoom = move |_| {
         zoom_level.set(1.0);
         translate_x.set(0.0);
@@ -82,48 +131,108 @@ pub fn EnhancedMediaModal(props: EnhancedMediaModalProps) -> Element {
                                 }
                             },
 
-                            div { class: "fullsize-image-container",
-                                img {
-                                    src: full_link(media_uuid),
-                                    alt: media.note.clone(),
-                                    class: if is_panning() { "fullsize-image panning".to_string() } else if zoom_level() > 1.0 { "fullsize-image zoomed".to_string() } else { "fullsize-image".to_string() },
-                                    style: get_transform_style(),
-
-                                    // Mouse event handlers for panning
-                                    onmousedown: move |event| {
+                            div {
+                                class: "fullsize-image-container",
+                                id: ZOOM_CONTAINER_ID,
+
+                                // Scrolling (or pinching on a trackpad, which the browser
+                                // reports as a scroll with ctrl held) zooms around the cursor
+                                onwheel: move |event| {
+                                    event.prevent_default();
+                                    let delta = match event.delta() {
+                                        WheelDelta::Pixels(v) => v.y,
+                                        WheelDelta::Lines(v) => v.y * WHEEL_LINE_HEIGHT,
+                                        WheelDelta::Pages(v) => v.y * WHEEL_PAGE_HEIGHT,
+                                    };
+                                    let (focus_x, focus_y) = container_offset(event.client_coordinates());
+                                    zoom_to(
+                                        zoom_level() * (-delta * WHEEL_ZOOM_RATE).exp(),
+                                        focus_x,
+                                        focus_y,
+                                    );
+                                },
+
+                                // Mouse event handlers for panning, which are on the container
+                                // so that a fast drag doesn't slip off of the image
+                                onmousedown: move |event| {
+                                    if zoom_level() > 1.0 {
+                                        is_panning.set(true);
+                                        start_pos_x.set(event.client_coordinates().x);
+                                        start_pos_y.set(event.client_coordinates().y);
+                                    }
+                                },
+                                onmousemove: move |event| {
+                                    if is_panning() {
+                                        pan_to(event.client_coordinates().x, event.client_coordinates().y);
+                                    }
+                                },
+                                onmouseup: move |_| {
+                                    is_panning.set(false);
+                                },
+                                onmouseleave: move |_| {
+                                    is_panning.set(false);
+                                },
+
+                                // Touch event handlers, where two fingers zoom around their
+                                // midpoint and one finger pans
+                                ontouchstart: move |event| {
+                                    let touches = event.touches();
+                                    if let [first, second] = touches.as_slice() {
+                                        let distance = first
+                                            .client_coordinates()
+                                            .distance_to(second.client_coordinates());
+                                        is_panning.set(false);
+                                        pinch_distance.set(Some(distance));
+                                    } else if let [touch] = touches.as_slice() {
                                         if zoom_level() > 1.0 {
                                             is_panning.set(true);
-                                            start_pos_x.set(event.client_coordinates().x);
-                                            start_pos_y.set(event.client_coordinates().y);
+                                            start_pos_x.set(touch.client_coordinates().x);
+                                            start_pos_y.set(touch.client_coordinates().y);
                                         }
-                                    },
-                                    onmousemove: move |event| {
+                                    }
+                                },
+                                ontouchmove: move |event| {
+                                    let touches = event.touches();
+                                    if let ([first, second], Some(previous)) = (touches.as_slice(), pinch_distance()) {
+                                        let first = first.client_coordinates();
+                                        let second = second.client_coordinates();
+                                        let distance = first.distance_to(second);
+                                        let (focus_x, focus_y) = container_offset(first.lerp(second, 0.5));
+                                        if previous > 0.0 {
+                                            zoom_to(zoom_level() * distance / previous, focus_x, focus_y);
+                                        }
+                                        pinch_distance.set(Some(distance));
+                                    } else if let [touch] = touches.as_slice() {
                                         if is_panning() {
-                                            let current_x = event.client_coordinates().x;
-                                            let current_y = event.client_coordinates().y;
-                                            let delta_x = current_x - start_pos_x();
-                                            let delta_y = current_y - start_pos_y();
-                                            translate_x.set(translate_x() + delta_x / zoom_level());
-                                            translate_y.set(translate_y() + delta_y / zoom_level());
-                                            start_pos_x.set(current_x);
-                                            start_pos_y.set(current_y);
+                                            pan_to(touch.client_coordinates().x, touch.client_coordinates().y);
                                         }
-                                    },
-                                    onmouseup: move |_| {
-                                        is_panning.set(false);
-                                    },
-                                    onmouseleave: move |_| {
-                                        is_panning.set(false);
-                                    },
+                                    }
+                                },
+                                ontouchend: move |_| {
+                                    is_panning.set(false);
+                                    pinch_distance.set(None);
+                                },
 
-                                    // Double-click to reset zoom
-                                    ondoubleclick: move |_| {
-                                        reset_zoom(());
-                                    },
+                                // Double-click to reset zoom
+                                ondoubleclick: move |_| {
+                                    reset_zoom(());
+                                },
+
+                                img {
+                                    src: full_link(media_uuid),
+                                    alt: media.note.clone(),
+                                    class: if is_panning() { "fullsize-image panning" } else { "fullsize-image" },
+                                    style: get_transform_style(),
+                                    draggable: "false",
                                 }
 
-                                // Zoom controls
-                                div { class: "zoom-controls",
+                                // Zoom controls, which shouldn't start a pan or reset the
+                                // zoom when clicked quickly
+                                div {
+                                    class: "zoom-controls",
+                                    onmousedown: move |event| event.stop_propagation(),
+                                    ondoubleclick: move |event| event.stop_propagation(),
+
                                     button {
                                         class: "zoom-button",
                                         onclick: move |_| zoom_out(()),
@@ -214,8 +323,9 @@ pub fn EnhancedMediaModal(props: EnhancedMediaModalProps) -> Element {
                         }
                     },
 
-                    div { class: "error-state",
-                        p { "Failed to load media: {err}" }
+                    ModalError {
+                        message: format!("Failed to load media: {err}"),
+                        on_retry: move |_| media_future.restart(),
                     }
                 }
             }
@@ -271,66 +381,51 @@ pub fn BulkEditTagsModal(props: BulkAddTagsModalProps) -> Element {
     let handle_submit = move |_| {
         let media_uuids = media_uuids.clone();
         async move {
+            let edit_tags = unfold_set(&edit_tags());
+
+            if let Err(err) = validate_tags(&edit_tags) {
+                status_signal.set(err.to_string());
+                return;
+            }
+
             status_signal.set(format!("Modifying tags on {} media items...", media_count));
 
             processing_count.set(0);
             success_count.set(0);
             error_count.set(0);
 
-            let edit_tags = unfold_set(&edit_tags());
-
-            // Process each media item
-            for media_uuid in media_uuids.clone() {
-                processing_count.set(processing_count() + 1);
-
-                let media = match get_media(&GetMediaReq { media_uuid }).await {
-                    Ok(v) => v.media,
-                    Err(err) => {
-                        error!("failed to get media while bulk editing tags: {err}");
-                        error_count.set(error_count() + 1);
-                        continue;
-                    }
-                };
-
-                let tags = media.tags.clone();
-
-                let new_tags: HashSet<String> = match edit_mode_signal() {
-                    TagEditMode::Add => {
-                        if edit_tags.difference(&tags).collect::<Vec<_>>().is_empty() {
-                            success_count.set(success_count() + 1);
-                            continue;
-                        }
-
-                        tags.union(&edit_tags).cloned().collect()
+            // the server applies the edit to each item's stored tags, so the whole selection
+            // goes in one request
+            let update = match edit_mode_signal() {
+                TagEditMode::Add => MediaUpdate {
+                    tags_add: edit_tags,
+                    ..Default::default()
+                },
+                TagEditMode::Remove => MediaUpdate {
+                    tags_remove: edit_tags,
+                    ..Default::default()
+                },
+            };
+
+            processing_count.set(media_count);
+
+            match batch_update_media(&BatchUpdateMediaReq {
+                media_uuids: media_uuids.into_iter().collect(),
+                update,
+            })
+            .await
+            {
+                Ok(resp) => {
+                    for media_uuid in resp.failed.iter() {
+                        error!("failed to update {media_uuid} while bulk editing tags");
                     }
-                    TagEditMode::Remove => {
-                        if edit_tags.intersection(&tags).collect::<Vec<_>>().is_empty() {
-                            success_count.set(success_count() + 1);
-                            continue;
-                        }
 
-                        tags.difference(&edit_tags).cloned().collect()
-                    }
-                };
-
-                match update_media(&UpdateMediaReq {
-                    media_uuid,
-                    update: MediaUpdate {
-                        hidden: None,
-                        date: None,
-                        note: None,
-                        tags: Some(new_tags),
-                    },
-                })
-                .await
-                {
-                    Ok(_) => {
-                        success_count.set(success_count() + 1);
-                    }
-                    Err(err) => {
-                        error!("failed to update media while bulk editing tags: {err}");
-                        error_count.set(error_count() + 1);
-                    }
+                    success_count.set(resp.updated.len() as i64);
+                    error_count.set(resp.failed.len() as i64);
+                }
+                Err(err) => {
+                    error!("failed to update media while bulk editing tags: {err}");
+                    error_count.set(media_count);
                 }
             }
 
@@ -480,3 +575,188 @@ fn TagEditOption(props: TagEditOptionProps) -> Element {
         }
     }
 }
+
+#[derive(Clone, PartialEq, Props)]
+pub struct BulkHideModalProps {
+    update_signal: Signal<()>,
+    media_uuids: Option<HashSet<MediaUuid>>,
+}
+
+#[component]
+pub fn BulkHideModal(props: BulkHideModalProps) -> Element {
+    let media_uuids = match props.media_uuids {
+        None => {
+            MODAL_STACK.with_mut(|v| v.pop());
+            return rsx! {};
+        }
+        Some(v) => v,
+    };
+
+    let mut update_signal = props.update_signal;
+
+    let hide_signal = use_signal(|| true);
+
+    let mut status_signal = use_signal(String::new);
+
+    let mut processing_count = use_signal(|| 0);
+    let mut success_count = use_signal(|| 0);
+    let mut error_count = use_signal(|| 0);
+    let media_count = media_uuids.len() as i64;
+
+    let handle_submit = move |_| {
+        let media_uuids = media_uuids.clone();
+        async move {
+            let hidden = hide_signal();
+            let action = if hidden { "hiding" } else { "unhiding" };
+
+            status_signal.set(format!("Updating {} media items...", media_count));
+
+            processing_count.set(0);
+            success_count.set(0);
+            error_count.set(0);
+
+            processing_count.set(media_count);
+
+            match batch_update_media(&BatchUpdateMediaReq {
+                media_uuids: media_uuids.into_iter().collect(),
+                update: MediaUpdate {
+                    hidden: Some(hidden),
+                    ..Default::default()
+                },
+            })
+            .await
+            {
+                Ok(resp) => {
+                    for media_uuid in resp.failed.iter() {
+                        error!("failed to update {media_uuid} while bulk {action}");
+                    }
+
+                    success_count.set(resp.updated.len() as i64);
+                    error_count.set(resp.failed.len() as i64);
+                }
+                Err(err) => {
+                    error!("failed to update media while bulk {action}: {err}");
+                    error_count.set(media_count);
+                }
+            }
+
+            if error_count() == 0 {
+                status_signal.set(format!("Successfully updated {} items", success_count()));
+            } else {
+                status_signal.set(format!(
+                    "Updated {} items, {} failed; see browser console",
+                    success_count(),
+                    error_count()
+                ));
+            }
+
+            update_signal.set(());
+
+            if error_count() == 0 {
+                let task = gloo_timers::callback::Timeout::new(1500, move || {
+                    MODAL_STACK.with_mut(|v| v.pop());
+                });
+                task.forget();
+            }
+        }
+    };
+
+    let footer = rsx! {
+        span { class: "status-message", style: "color: var(--primary);", "{status_signal}" }
+        div {
+            class: "modal-buttons",
+            style: "display: flex; gap: var(--space-4); justify-content: flex-end;",
+            button {
+                class: "btn btn-secondary",
+                onclick: move |_| {
+                    MODAL_STACK.with_mut(|v| v.pop());
+                },
+                "Cancel"
+            }
+            button {
+                class: "btn btn-primary",
+                onclick: handle_submit,
+                if hide_signal() {
+                    "Hide"
+                } else {
+                    "Unhide"
+                }
+            }
+        }
+    };
+
+    rsx! {
+        ModalInner {
+            title: format!("Hide or Unhide {} Items", media_count),
+            size: ModalSize::Medium,
+            footer,
+            div {
+                ProgressBar {
+                    processing_count,
+                    success_count,
+                    error_count,
+                    media_count,
+                }
+
+                p { "Hidden media only appear in their libraries, and only if hidden files are shown" }
+                div { class: "task-options",
+                    HideOption {
+                        hidden: true,
+                        hide_signal,
+                        title: "Hide media",
+                        description: "Remove media from searches and collections",
+                        icon: "🙈",
+                    }
+                    HideOption {
+                        hidden: false,
+                        hide_signal,
+                        title: "Unhide media",
+                        description: "Return media to searches and collections",
+                        icon: "👁️",
+                    }
+                }
+
+                // Media count summary
+                div { style: "margin-top: var(--space-4); padding: var(--space-3); background-color: var(--neutral-50); border-radius: var(--radius-md);",
+                    p { style: "margin: 0; color: var(--text-secondary); font-weight: 500;",
+                        "{media_count} items selected for bulk operation"
+                    }
+                }
+            }
+        }
+    }
+}
+
+#[derive(Clone, PartialEq, Props)]
+struct HideOptionProps {
+    hidden: bool,
+    hide_signal: Signal<bool>,
+    title: String,
+    description: String,
+    icon: String,
+}
+
+#[component]
+fn HideOption(props: HideOptionProps) -> Element {
+    let hidden = props.hidden;
+    let mut hide_signal = props.hide_signal;
+
+    rsx! {
+        div {
+            class: if hidden == hide_signal() { "task-option selected" } else { "task-option" },
+            onclick: move |_| hide_signal.set(hidden),
+            div { class: "task-radio",
+                div { class: "task-radio-outer",
+                    if hidden == hide_signal() {
+                        div { class: "task-radio-inner" }
+                    }
+                }
+            }
+            div { class: "task-icon", "{props.icon}" }
+            div { class: "task-info",
+                div { class: "task-name", "{props.title}" }
+                div { class: "task-description", "{props.description}" }
+            }
+        }
+    }
+}
@@ -16,10 +16,10 @@ use collections::{
 };
 
 mod library;
-use library::{StartTaskModal, StopTaskModal, TaskHistoryModal};
+use library::{EditLibraryModal, StartTaskModal, StopTaskModal, TaskHistoryModal};
 
 mod media;
-use media::{BulkEditTagsModal, EnhancedMediaModal};
+use media::{BulkEditTagsModal, BulkHideModal, EnhancedMediaModal};
 
 // global modal signal
 //
@@ -43,6 +43,8 @@ pub enum Modal {
     RmMediaFromCollection(MediaUuid, CollectionUuid),
     BulkAddToCollection(Option<HashSet<MediaUuid>>),
     BulkEditTags(Option<HashSet<MediaUuid>>),
+    BulkHide(Option<HashSet<MediaUuid>>),
+    EditLibrary(LibraryUuid),
     StartTask(LibraryUuid),
     StopTask(LibraryUuid),
     TaskHistory(LibraryUuid),
@@ -112,6 +114,16 @@ pub fn ModalBox(props: ModalBoxProps) -> Element {
                     BulkEditTagsModal { update_signal, media_uuids: media_uuids.clone() }
                 }
             }
+            Modal::BulkHide(ref media_uuids) => {
+                rsx! {
+                    BulkHideModal { update_signal, media_uuids: media_uuids.clone() }
+                }
+            }
+            Modal::EditLibrary(library_uuid) => {
+                rsx! {
+                    EditLibraryModal { update_signal, library_uuid }
+                }
+            }
             Modal::StartTask(library_uuid) => {
                 rsx! {
                     StartTaskModal { update_signal, library_uuid }
@@ -163,13 +175,19 @@ pub fn ModalInner(props: ModalInnerProps) -> Element {
         ModalSize::Full => "max-width: 95%;",
     };
 
+    // a click that starts inside the content and ends on the overlay (like dragging the
+    // zoomed image past the edge) is dispatched to the overlay, so it only counts if the
+    // press started there too
+    let mut overlay_pressed = use_signal(|| false);
+
     rsx! {
         div {
             class: "modal-overlay",
+            onmousedown: move |_| overlay_pressed.set(true),
             // Clicking overlay closes modal unless disabled
             onclick: move |evt| {
                 evt.stop_propagation();
-                if !props.disable_close {
+                if overlay_pressed() && !props.disable_close {
                     MODAL_STACK
                         .with_mut(|v| {
                             v.pop();
@@ -181,6 +199,10 @@ pub fn ModalInner(props: ModalInnerProps) -> Element {
                 style: "{width}",
                 // Stop click propagation to prevent closing when clicking content
                 onclick: move |evt| evt.stop_propagation(),
+                onmousedown: move |evt| {
+                    evt.stop_propagation();
+                    overlay_pressed.set(false);
+                },
 
                 div { class: "modal-header",
                     h2 { class: "modal-title", "{props.title}" }
@@ -212,6 +234,36 @@ pub fn ModalInner(props: ModalInnerProps) -> Element {
     }
 }
 
+// ModalError
+//
+// the banner that the modals show when one of their fetches fails, instead of quietly
+// falling back to an empty state.  the retry button is left out when there is nothing to
+// restart.
+#[derive(Clone, PartialEq, Props)]
+pub struct ModalErrorProps {
+    message: String,
+    #[props(default)]
+    on_retry: Option<EventHandler<MouseEvent>>,
+}
+
+#[component]
+pub fn ModalError(props: ModalErrorProps) -> Element {
+    rsx! {
+        div {
+            class: "error-state",
+            style: "display: flex; align-items: center; justify-content: space-between; gap: var(--space-3); padding: var(--space-3); margin-bottom: var(--space-3); background-color: rgba(239, 68, 68, 0.1); border-left: 3px solid var(--error); border-radius: var(--radius-md); color: var(--error);",
+            span { "{props.message}" }
+            if let Some(on_retry) = props.on_retry {
+                button {
+                    class: "btn btn-sm btn-secondary",
+                    onclick: move |evt| on_retry.call(evt),
+                    "Retry"
+                }
+            }
+        }
+    }
+}
+
 #[derive(Clone, PartialEq, Props)]
 pub struct ProgressBarProps {
     processing_count: Signal<i64>,
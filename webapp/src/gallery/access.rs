@@ -0,0 +1,84 @@
+use dioxus::prelude::*;
+use dioxus_router::prelude::*;
+
+use crate::Route;
+use api::media::*;
+
+// media access
+//
+// the groups that can see the media, and where each one comes from, so that an owner can
+// work out why something is (or isn't) shared.  the endpoint only answers the owner, so
+// anyone else just doesn't get the section.
+#[derive(Clone, PartialEq, Props)]
+pub struct MediaAccessTableProps {
+    media_uuid: Memo<MediaUuid>,
+    update_signal: Signal<()>,
+}
+
+#[component]
+pub fn MediaAccessTable(props: MediaAccessTableProps) -> Element {
+    let media_uuid = props.media_uuid;
+    let update_signal = props.update_signal;
+
+    let access_future = use_resource(move || async move {
+        update_signal();
+        let media_uuid = media_uuid();
+
+        get_media_access(&GetMediaAccessReq { media_uuid }).await
+    });
+
+    let access = match &*access_future.read() {
+        Some(Ok(v)) => v.access.clone(),
+        _ => return rsx! {},
+    };
+
+    rsx! {
+        div {
+            class: "detail-section",
+            style: "background-color: var(--surface); padding: var(--space-4); border-radius: var(--radius-lg); box-shadow: var(--shadow-sm);",
+            h2 { style: "margin-bottom: var(--space-3);", "Access" }
+
+            div {
+                class: "table-container",
+                style: "max-height: 250px; overflow-y: auto; border: 1px solid var(--border); border-radius: var(--radius-md);",
+                table { style: "border-collapse: separate; border-spacing: 0;",
+                    thead { style: "position: sticky; top: 0; z-index: 1; background-color: var(--primary);",
+                        tr {
+                            th { "Group" }
+                            th { "Through" }
+                        }
+                    }
+                    tbody {
+                        for entry in access {
+                            tr {
+                                td { style: "padding: var(--space-2) var(--space-3);", "{entry.gid}" }
+                                td { style: "padding: var(--space-2) var(--space-3);",
+                                    match entry.source {
+                                        AccessSource::Library { library_uuid } => rsx! {
+                                            Link {
+                                                to: Route::LibraryDetail {
+                                                    library_uuid: library_uuid.to_string(),
+                                                },
+                                                style: "color: var(--primary);",
+                                                "Library"
+                                            }
+                                        },
+                                        AccessSource::Collection { collection_uuid } => rsx! {
+                                            Link {
+                                                to: Route::CollectionDetail {
+                                                    collection_uuid: collection_uuid.to_string(),
+                                                },
+                                                style: "color: var(--primary);",
+                                                "Collection"
+                                            }
+                                        },
+                                    }
+                                }
+                            }
+                        }
+                    }
+                }
+            }
+        }
+    }
+}
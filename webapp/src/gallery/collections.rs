@@ -108,7 +108,7 @@ fn CollectionTableInner(props: CollectionTableInnerProps) -> Element {
                                             th { "Name" }
                                             th { "Group" }
                                             th { "Note" }
-                                            th { style: "width: 100px;", "Actions" }
+                                            th { style: "width: 200px;", "Actions" }
                                         }
                                     }
                                     tbody {
@@ -133,7 +133,25 @@ fn CollectionTableInner(props: CollectionTableInnerProps) -> Element {
                                                         "{collection.note}"
                                                     }
                                                 }
-                                                td { style: "text-align: right; padding: var(--space-2) var(--space-3);",
+                                                td { style: "text-align: right; padding: var(--space-2) var(--space-3); white-space: nowrap;",
+                                                    button {
+                                                        class: "btn btn-sm btn-secondary",
+                                                        style: "margin-right: var(--space-2);",
+                                                        title: "Show this media on the collection card",
+                                                        onclick: move |_| async move {
+                                                            if let Err(err) = set_collection_cover(
+                                                                    &SetCollectionCoverReq {
+                                                                        collection_uuid: collection_id,
+                                                                        media_uuid: Some(media_uuid),
+                                                                    },
+                                                                )
+                                                                .await
+                                                            {
+                                                                error!("Failed to set cover for {collection_id}: {err}");
+                                                            }
+                                                        },
+                                                        "Set as Cover"
+                                                    }
                                                     button {
                                                         class: "btn btn-sm btn-danger",
                                                         onclick: move |_| {
@@ -2,7 +2,7 @@ use dioxus::prelude::*;
 use tracing::error;
 
 use crate::common::local_time;
-use api::{comment::*, media::MediaUuid};
+use api::{comment::*, markdown::render_markdown, media::MediaUuid};
 
 #[derive(Clone, PartialEq, Props)]
 pub struct CommentListProps {
@@ -90,6 +90,7 @@ fn CommentListInner(props: CommentListInnerProps) -> Element {
                                     uid: String::from(""),
                                     date: 0,
                                     text: comment_text,
+                                    edited: None,
                                 },
                             },
                         )
@@ -155,9 +156,15 @@ fn CommentListInner(props: CommentListInnerProps) -> Element {
                                                 class: "comment-time",
                                                 style: "font-size: 0.875rem; color: var(--text-tertiary);",
                                                 "{local_time(comment.date)}"
+                                                if let Some(edited) = comment.edited {
+                                                    span { title: "{local_time(edited)}", " (edited)" }
+                                                }
                                             }
                                         }
-                                        div { class: "comment-text", style: "white-space: pre-wrap;", "{comment.text}" }
+                                        div {
+                                            class: "comment-text",
+                                            dangerous_inner_html: render_markdown(&comment.text),
+                                        }
                                         div {
                                             class: "comment-actions",
                                             style: "display: flex; justify-content: flex-end; margin-top: var(--space-2);",
@@ -1,12 +1,20 @@
+use std::rc::Rc;
+
 use dioxus::prelude::*;
 use dioxus_router::prelude::*;
+use futures_util::StreamExt;
+use wasm_bindgen::{JsCast, closure::Closure};
+use web_sys::{Element, KeyboardEvent};
 
 use crate::{
     Route,
     components::modal::{MODAL_STACK, Modal, ModalBox},
-    gallery::{collections::CollectionTable, comments::CommentList, similar::SimilarMedia},
+    gallery::{
+        GALLERY_RESULTS, access::MediaAccessTable, collections::CollectionTable,
+        comments::CommentList, similar::SimilarMedia,
+    },
 };
-use api::{UuidSource, fold_set, full_link, media::*, unfold_set};
+use api::{UuidSource, fold_set, full_link, media::*, thumbnail_link, unfold_set};
 
 #[derive(Clone, PartialEq, Props)]
 pub struct GalleryDetailProps {
@@ -98,9 +106,18 @@ fn GalleryInner(props: GalleryInnerProps) -> Element {
     // render the GalleryError) and then match/return early for the Option
     //
     // TODO -- find a way to implement this without a clone()
+    //
+    // the server answers 401 both for media that the user can't see and media that doesn't
+    // exist, so neither gets the raw status
     let media_data = match media_data.clone().transpose().show(|error| {
+        let message = if error.is_unauthorized() {
+            String::from("you do not have access to this media")
+        } else {
+            error.to_string()
+        };
+
         rsx! {
-            GalleryError { message: error }
+            GalleryError { message }
         }
     })? {
         None => {
@@ -138,6 +155,7 @@ fn GalleryInner(props: GalleryInnerProps) -> Element {
     });
 
     rsx! {
+        GalleryKeyboardNav { media_uuid }
         div { class: "container",
             // side-by-side layout with independent scrolling
             div { class: "media-detail-page",
@@ -151,7 +169,7 @@ fn GalleryInner(props: GalleryInnerProps) -> Element {
                             MediaMetadata::Image => rsx! {
                                 img {
                                     class: "media-detail-image",
-                                    src: full_link(media_uuid()),
+                                    src: thumbnail_link(media_uuid(), ThumbnailSize::Preview),
                                     onclick: move |_| {
                                         MODAL_STACK.with_mut(|v| v.push(Modal::EnhancedImageView(media_uuid())));
                                     },
@@ -164,6 +182,17 @@ fn GalleryInner(props: GalleryInnerProps) -> Element {
                                     controls: true,
                                 }
                             },
+                            MediaMetadata::Audio => rsx! {
+                                img {
+                                    class: "media-detail-image",
+                                    src: thumbnail_link(media_uuid(), ThumbnailSize::Preview),
+                                }
+                                audio {
+                                    class: "media-detail-audio",
+                                    src: full_link(media_uuid()),
+                                    controls: true,
+                                }
+                            },
                             _ => rsx! {
                                 div { class: "unsupported-media", "This media type is not supported for preview" }
                             },
@@ -196,10 +225,10 @@ fn GalleryInner(props: GalleryInnerProps) -> Element {
                                         &UpdateMediaReq {
                                             media_uuid: media_uuid(),
                                             update: MediaUpdate {
-                                                hidden: None,
                                                 date,
                                                 note,
                                                 tags,
+                                                ..Default::default()
                                             },
                                         },
                                     )
@@ -268,9 +297,7 @@ fn GalleryInner(props: GalleryInnerProps) -> Element {
                                                         media_uuid: media_uuid(),
                                                         update: MediaUpdate {
                                                             hidden: Some(!media.hidden),
-                                                            date: None,
-                                                            note: None,
-                                                            tags: None,
+                                                            ..Default::default()
                                                         },
                                                     },
                                                 )
@@ -352,6 +379,9 @@ fn GalleryInner(props: GalleryInnerProps) -> Element {
                     // which have the update_signal already
                     CollectionTable { collection_uuids, media_uuid }
 
+                    // access section, only shown to the owner
+                    MediaAccessTable { media_uuid, update_signal }
+
                     // comments section
                     //
                     // while some comment actions are in modals, creating comments
@@ -370,6 +400,113 @@ fn GalleryInner(props: GalleryInnerProps) -> Element {
     }
 }
 
+// keyboard navigation
+//
+// left and right step through GALLERY_RESULTS, stopping at either end rather than wrapping
+// around, and escape goes back to the grid.  paging replaces the history entry so that
+// going back always lands on the grid.
+//
+// the listener is attached to the document so that it works without focusing anything,
+// which means that it has to be removed by hand when the detail view unmounts.  it can
+// only hand the keys off, so the navigation itself happens in a coroutine.
+#[derive(Clone, PartialEq, Props)]
+struct GalleryKeyboardNavProps {
+    media_uuid: Memo<MediaUuid>,
+}
+
+#[component]
+fn GalleryKeyboardNav(props: GalleryKeyboardNavProps) -> Element {
+    let media_uuid = props.media_uuid;
+    let navigator = navigator();
+
+    let keys = use_coroutine(move |mut rx: UnboundedReceiver<String>| async move {
+        while let Some(key) = rx.next().await {
+            // the keys belong to the modal while one is open
+            if !MODAL_STACK.peek().is_empty() {
+                continue;
+            }
+
+            match key.as_str() {
+                "ArrowLeft" | "ArrowRight" => {
+                    let neighbor = {
+                        let results = GALLERY_RESULTS.peek();
+
+                        results
+                            .iter()
+                            .position(|v| *v == *media_uuid.peek())
+                            .and_then(|index| match key.as_str() {
+                                "ArrowLeft" => index.checked_sub(1),
+                                _ => Some(index + 1),
+                            })
+                            .and_then(|index| results.get(index).copied())
+                    };
+
+                    if let Some(neighbor) = neighbor {
+                        navigator.replace(Route::GalleryDetail {
+                            media_uuid: neighbor.to_string(),
+                        });
+                    }
+                }
+                "Escape" => {
+                    if navigator.can_go_back() {
+                        navigator.go_back();
+                    } else {
+                        navigator.push(Route::GallerySearch {});
+                    }
+                }
+                _ => {}
+            }
+        }
+    });
+
+    let listener = use_hook(move || {
+        let tx = keys.tx();
+
+        let listener = Closure::<dyn Fn(KeyboardEvent)>::new(move |event: KeyboardEvent| {
+            if event.alt_key() || event.ctrl_key() || event.meta_key() || in_form(&event) {
+                return;
+            }
+
+            let key = event.key();
+
+            if matches!(key.as_str(), "ArrowLeft" | "ArrowRight" | "Escape") {
+                let _ = tx.unbounded_send(key);
+            }
+        });
+
+        if let Some(document) = web_sys::window().and_then(|v| v.document()) {
+            let _ = document
+                .add_event_listener_with_callback("keydown", listener.as_ref().unchecked_ref());
+        }
+
+        Rc::new(listener)
+    });
+
+    use_drop(move || {
+        if let Some(document) = web_sys::window().and_then(|v| v.document()) {
+            let _ = document.remove_event_listener_with_callback(
+                "keydown",
+                (*listener).as_ref().unchecked_ref(),
+            );
+        }
+    });
+
+    rsx! {}
+}
+
+// the arrow keys already mean something in text fields and media controls
+fn in_form(event: &KeyboardEvent) -> bool {
+    event
+        .target()
+        .and_then(|v| v.dyn_into::<Element>().ok())
+        .is_some_and(|v| {
+            matches!(
+                v.tag_name().as_str(),
+                "INPUT" | "TEXTAREA" | "SELECT" | "VIDEO" | "AUDIO"
+            )
+        })
+}
+
 #[component]
 fn GalleryDetailSkeleton() -> Element {
     rsx! {
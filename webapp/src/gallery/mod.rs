@@ -2,6 +2,7 @@ use dioxus::prelude::*;
 use dioxus_router::prelude::*;
 
 use crate::Route;
+use api::media::MediaUuid;
 
 mod search;
 pub use search::GallerySearch;
@@ -9,9 +10,12 @@ pub use search::GallerySearch;
 mod detail;
 pub use detail::GalleryDetail;
 
+mod access;
 mod collections;
 mod comments;
 mod similar;
+mod timeline;
+mod upload;
 
 const MEDIA_SEARCH_KEY: &str = "media_search";
 
@@ -19,6 +23,11 @@ const MEDIA_SEARCH_KEY: &str = "media_search";
 // to keep track of which collection we were browsing upon navigating here
 pub const GALLERY_COLLECTION_KEY: &str = "gallery_collection";
 
+// the media in the grid that GalleryDetail was last opened from, in display order, so that
+// the detail view can page through its neighbors.  every grid that links there should keep
+// this in sync with its results.
+pub static GALLERY_RESULTS: GlobalSignal<Vec<MediaUuid>> = Signal::global(Vec::new);
+
 #[component]
 pub fn Gallery() -> Element {
     rsx! {
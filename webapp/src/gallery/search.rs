@@ -1,9 +1,22 @@
-use std::collections::{HashMap, HashSet};
+use std::{
+    cell::Cell,
+    collections::{HashMap, HashSet},
+    rc::Rc,
+};
 
 use dioxus::prelude::*;
+use dioxus_router::prelude::*;
+use futures_util::StreamExt;
+use js_sys::Array;
+use wasm_bindgen::{JsCast, closure::Closure};
+use web_sys::{Element, IntersectionObserver, IntersectionObserverEntry, IntersectionObserverInit};
 
 use crate::{
-    common::storage::try_local_storage,
+    Route,
+    common::{
+        debounce::{DEBOUNCE_MS, use_debounced},
+        storage::{ScrollState, get_scroll_state, set_scroll_state, try_local_storage},
+    },
     components::{
         advanced::{AdvancedSearchTab, BulkEditMode, BulkEditTab, CollectionColorTab},
         media_card::MediaCard,
@@ -11,48 +24,334 @@ use crate::{
         search::SearchBar,
         sidebar::AdvancedSidebar,
     },
-    gallery::MEDIA_SEARCH_KEY,
+    gallery::{GALLERY_RESULTS, MEDIA_SEARCH_KEY, timeline::TimelineTab, upload::UploadDropZone},
 };
 use api::{
     media::*,
-    search::{BatchSearchAndSortReq, SearchFilter, SearchRequest, batch_search_and_sort},
-    sort::SortMethod,
+    search::{
+        BatchSearchAndSortReq, SearchFilter, SearchRequest, SearchResponse, batch_search_and_sort,
+    },
+    sort::{SortMethod, SortOrder},
+    stats::DateBucket,
 };
 
+// paged results
+//
+// rather than fetching every match up front, the gallery loads PAGE_SIZE media at a time
+// and fetches the next page when the sentinel below the grid scrolls within PAGE_MARGIN of
+// the bottom.  the loaded pages are kept in a global signal (along with the scroll
+// position) so that returning from the detail view doesn't start over from the top.
+//
+// the scroll position and page count are also saved to local storage, which only matters
+// after a reload.  the first search then asks for as many pages as were loaded before in
+// one go, and the saved position is clamped to whatever the results fill now.
+const PAGE_SIZE: u64 = 100;
+const PAGE_MARGIN: &str = "600px";
+
+// local storage can hold anything, so the saved page count is capped before it becomes the
+// size of a single request.  scrolling further than this after a reload fetches the rest a
+// page at a time as usual.
+const MAX_RESTORED_PAGES: u64 = 10;
+
+const GALLERY_SCROLL_ID: &str = "gallery-scroll";
+
+#[derive(Clone, Debug, Default)]
+struct GalleryPages {
+    filter: String,
+    // the timeline bucket that the search is narrowed to, if any
+    bucket: Option<DateBucket>,
+    media: Vec<SearchResponse>,
+    // None until the first page arrives
+    total: Option<u64>,
+}
+
+impl GalleryPages {
+    fn exhausted(&self) -> bool {
+        self.total
+            .is_some_and(|total| self.media.len() as u64 >= total)
+    }
+}
+
+static GALLERY_PAGES: GlobalSignal<GalleryPages> = Signal::global(GalleryPages::default);
+
+// only ever peeked, so that scrolling doesn't re-render anything
+static GALLERY_SCROLL: GlobalSignal<i32> = Signal::global(|| 0);
+
+// the observer stops when this is dropped, i.e. when the sentinel is replaced or the
+// gallery unmounts, and the callback has to live exactly as long
+struct PageObserver {
+    observer: IntersectionObserver,
+    _callback: Closure<dyn Fn(Array)>,
+}
+
+impl Drop for PageObserver {
+    fn drop(&mut self) {
+        self.observer.disconnect();
+    }
+}
+
+// the search terms, along with the dates of the timeline bucket
+fn gallery_filter(filter: &str, bucket: &Option<DateBucket>) -> SearchFilter {
+    let terms = SearchFilter::SubstringAny {
+        filter: filter.split_whitespace().map(|s| s.to_owned()).collect(),
+    };
+
+    match bucket {
+        Some(bucket) => SearchFilter::All(Vec::from([
+            terms,
+            SearchFilter::DateRange {
+                start: Some(bucket.start),
+                end: Some(bucket.end),
+            },
+        ])),
+        None => terms,
+    }
+}
+
+// the size of the first search after a reload, which covers the pages that were loaded before
+fn restored_limit(pages: u64) -> u64 {
+    PAGE_SIZE * pages.clamp(1, MAX_RESTORED_PAGES)
+}
+
+fn scroll_container() -> Option<Element> {
+    web_sys::window()?
+        .document()?
+        .get_element_by_id(GALLERY_SCROLL_ID)
+}
+
+// ask for the next page whenever the sentinel comes within PAGE_MARGIN of the bottom of
+// the scroll container
+fn observe_sentinel(sentinel: &Element, loader: UnboundedSender<()>) -> Option<PageObserver> {
+    let callback = Closure::<dyn Fn(Array)>::new(move |entries: Array| {
+        if entries.iter().any(|v| {
+            v.unchecked_into::<IntersectionObserverEntry>()
+                .is_intersecting()
+        }) {
+            let _ = loader.unbounded_send(());
+        }
+    });
+
+    let init = IntersectionObserverInit::new();
+    init.set_root_margin(PAGE_MARGIN);
+
+    if let Some(container) = scroll_container() {
+        init.set_root(Some(container.unchecked_ref()));
+    }
+
+    let observer =
+        IntersectionObserver::new_with_options(callback.as_ref().unchecked_ref(), &init).ok()?;
+
+    observer.observe(sentinel);
+
+    Some(PageObserver {
+        observer,
+        _callback: callback,
+    })
+}
+
 #[component]
 pub fn GallerySearch() -> Element {
     let update_signal = use_signal(|| ());
 
     let media_search_signal = use_signal::<String>(|| try_local_storage(MEDIA_SEARCH_KEY));
+    let date_bucket_signal = use_signal(|| GALLERY_PAGES.peek().bucket.clone());
     let mut advanced_expanded = use_signal(|| false);
     let mut bulk_edit_signal = use_signal(|| None);
     let mut collection_color_signal = use_signal(HashMap::new);
 
-    let media_future = use_resource(move || async move {
+    let mut loading = use_signal(|| false);
+    let mut error = use_signal(|| None::<String>);
+    let mut observer = use_signal(|| None::<PageObserver>);
+
+    let route = use_route::<Route>().to_string();
+    let mut restore_signal = use_signal(|| None::<ScrollState>);
+    let mut scroll_signal = use_signal(|| *GALLERY_SCROLL.peek());
+    let saved_scroll = use_debounced(scroll_signal, DEBOUNCE_MS);
+
+    // pages are loaded one at a time, so a burst of requests (from the observer and a new
+    // search at once) can't fetch the same page twice
+    let loader = use_coroutine(move |mut rx: UnboundedReceiver<()>| async move {
+        while rx.next().await.is_some() {
+            while let Ok(Some(())) = rx.try_next() {}
+
+            let (filter, bucket, offset) = {
+                let pages = GALLERY_PAGES.peek();
+
+                if pages.exhausted() {
+                    continue;
+                }
+
+                (
+                    pages.filter.clone(),
+                    pages.bucket.clone(),
+                    pages.media.len() as u64,
+                )
+            };
+
+            let limit = match *restore_signal.peek() {
+                Some(saved) if offset == 0 => restored_limit(saved.pages),
+                _ => PAGE_SIZE,
+            };
+
+            loading.set(true);
+
+            let result = batch_search_and_sort(&BatchSearchAndSortReq {
+                req: SearchRequest::Media(SearchMediaReq {
+                    filter: gallery_filter(&filter, &bucket),
+                    sort: SortOrder::DateDesc,
+                    limit: Some(limit),
+                    offset: Some(offset),
+                }),
+                sort: SortMethod::Date,
+            })
+            .await;
+
+            loading.set(false);
+
+            match result {
+                Ok(resp) => {
+                    let mut pages = GALLERY_PAGES.write();
+
+                    // the search changed while this page was in flight
+                    if pages.filter != filter
+                        || pages.bucket != bucket
+                        || pages.media.len() as u64 != offset
+                    {
+                        continue;
+                    }
+
+                    pages.media.extend(resp.media);
+                    pages.total = Some(resp.total);
+                }
+                Err(err) => {
+                    restore_signal.set(None);
+                    error.set(Some(err.to_string()));
+                }
+            }
+        }
+    });
+
+    // a new search starts over, except that the first run keeps whatever was already
+    // loaded for the same search
+    let first_run = use_hook(|| Rc::new(Cell::new(true)));
+
+    let saved_route = route.clone();
+
+    use_effect(move || {
         update_signal();
+        let filter = media_search_signal();
+        let bucket = date_bucket_signal();
 
-        let filter = media_search_signal()
-            .split_whitespace()
-            .map(|s| s.to_owned())
+        let first = first_run.replace(false);
+
+        let restore = first && {
+            let pages = GALLERY_PAGES.peek();
+            pages.filter == filter && pages.bucket == bucket && pages.total.is_some()
+        };
+
+        if !restore {
+            // nothing in memory on the first run means that the app was reloaded
+            if first {
+                let saved = get_scroll_state(&saved_route);
+
+                if saved != ScrollState::default() {
+                    restore_signal.set(Some(saved));
+                }
+            } else {
+                restore_signal.set(None);
+            }
+
+            *GALLERY_PAGES.write() = GalleryPages {
+                filter,
+                bucket,
+                ..Default::default()
+            };
+            *GALLERY_SCROLL.write() = 0;
+            error.set(None);
+            loader.send(());
+        }
+    });
+
+    // effects run after the render, so the restored pages are already in the grid
+    use_effect(move || {
+        if GALLERY_PAGES.read().total.is_none() {
+            return;
+        }
+
+        let Some(saved) = *restore_signal.peek() else {
+            return;
+        };
+
+        restore_signal.set(None);
+
+        if let Some(container) = scroll_container() {
+            let scroll_top = saved
+                .scroll_top
+                .min(container.scroll_height() - container.client_height())
+                .max(0);
+
+            container.set_scroll_top(scroll_top);
+            *GALLERY_SCROLL.write() = scroll_top;
+        }
+    });
+
+    // nothing is saved until a pending restore has been applied, so that the empty grid
+    // it starts from can't overwrite it
+    use_effect(move || {
+        let scroll_top = saved_scroll();
+
+        if restore_signal.peek().is_some() {
+            return;
+        }
+
+        set_scroll_state(
+            &route,
+            ScrollState {
+                scroll_top,
+                pages: (GALLERY_PAGES.peek().media.len() as u64).div_ceil(PAGE_SIZE),
+            },
+        );
+    });
+
+    // see GALLERY_RESULTS
+    use_effect(move || {
+        *GALLERY_RESULTS.write() = GALLERY_PAGES
+            .read()
+            .media
+            .iter()
+            .map(|m| m.media_uuid)
             .collect();
+    });
+
+    // a new search clears the selection, while loading more pages of the same one leaves
+    // it alone
+    use_effect(move || {
+        media_search_signal();
+        date_bucket_signal();
+
+        if bulk_edit_signal.peek().is_some() {
+            bulk_edit_signal.set(Some(HashSet::new()));
+        }
+    });
+
+    // bulk edits (i.e. select all) apply to every match, not just the pages loaded so far,
+    // so this fetches the full set of uuids separately
+    let all_media_future = use_resource(move || async move {
+        update_signal();
+        let filter = media_search_signal();
+        let bucket = date_bucket_signal();
 
-        batch_search_and_sort(&BatchSearchAndSortReq {
-            req: SearchRequest::Media(SearchMediaReq {
-                filter: SearchFilter::SubstringAny { filter },
-            }),
-            sort: SortMethod::Date,
+        search_media(&SearchMediaReq {
+            filter: gallery_filter(&filter, &bucket),
+            sort: SortOrder::DateDesc,
+            limit: None,
+            offset: None,
         })
         .await
     });
 
-    // clunky, but it avoids cloning the reponse
-    let media_uuids = use_memo(move || match &*media_future.read() {
-        Some(Ok(v)) => Some(
-            v.media
-                .iter()
-                .map(|m| m.media_uuid)
-                .collect::<HashSet<MediaUuid>>(),
-        ),
+    let media_uuids = use_memo(move || match &*all_media_future.read() {
+        Some(Ok(resp)) => Some(resp.media.iter().copied().collect::<HashSet<MediaUuid>>()),
         _ => None,
     });
 
@@ -75,6 +374,8 @@ pub fn GallerySearch() -> Element {
         }
     };
 
+    let pages = GALLERY_PAGES.read();
+
     rsx! {
         div { class: "container with-sticky",
             ModalBox { update_signal }
@@ -90,14 +391,19 @@ pub fn GallerySearch() -> Element {
                     search_signal: media_search_signal,
                     storage_key: MEDIA_SEARCH_KEY,
                     placeholder: "Search by date or description...",
-                    status: match &*media_future.read() {
-                        Some(Ok(resp)) => format!("Found {} results", resp.media.len()),
-                        Some(Err(_)) => String::from("Error searching media"),
-                        None => String::from("Loading..."),
+                    status: match (pages.total, error(), bulk_edit_signal()) {
+                        (_, Some(_), _) => String::from("Error searching media"),
+                        (Some(total), None, Some(selected)) => {
+                            format!("Found {total} results, {} selected", selected.len())
+                        }
+                        (Some(total), None, None) => format!("Found {total} results"),
+                        (None, None, _) => String::from("Loading..."),
                     },
                     action_button,
                 }
 
+                UploadDropZone { update_signal }
+
                 AdvancedSidebar {
                     show_signal: advanced_expanded,
                     tabs: HashMap::from([
@@ -114,60 +420,111 @@ pub fn GallerySearch() -> Element {
                         ("Collection Labels".to_owned(), rsx! {
                             CollectionColorTab { collection_color_signal }
                         }),
+                        ("Timeline".to_owned(), rsx! {
+                            TimelineTab { date_bucket_signal }
+                        }),
                     ]),
                 }
             }
 
-            div { class: "scrollable-content",
-                match &*media_future.read() {
-                    Some(Ok(resp)) => {
-                        rsx! {
-                            if resp.media.is_empty() {
-                                div { class: "empty-state",
-                                    p { "No media found matching your search criteria." }
+            div {
+                class: "scrollable-content",
+                id: GALLERY_SCROLL_ID,
+                onmounted: move |_| {
+                    let scroll_top = *GALLERY_SCROLL.peek();
+                    if let Some(container) = scroll_container() {
+                        container.set_scroll_top(scroll_top);
+                    }
+                },
+                onscroll: move |_| {
+                    if let Some(container) = scroll_container() {
+                        *GALLERY_SCROLL.write() = container.scroll_top();
+                        scroll_signal.set(container.scroll_top());
+                    }
+                },
+
+                if let Some(err) = error() {
+                    div { class: "error-state",
+                        p { "Error: {err}" }
+                    }
+                } else if pages.total.is_none() {
+                    div { class: "loading-state media-grid",
+                        for _ in 0..8 {
+                            div { class: "skeleton-card",
+                                div { class: "skeleton", style: "height: 200px;" }
+                                div {
+                                    class: "skeleton",
+                                    style: "height: 24px; width: 40%; margin-top: 12px;",
                                 }
-                            } else {
-                                // TODO -- convert this to a MediaGrid element (see equivalent in collections and libraries)
-                                //         and convert them all to reactive memos instead of cloning
-                                div { class: "media-grid",
-                                    for search_resp in resp.media.iter() {
-                                        MediaCard {
-                                            key: "{search_resp.media_uuid}",
-                                            media_uuid: search_resp.media_uuid,
-                                            media: search_resp.media.clone(),
-                                            collections: search_resp.collections.clone(),
-                                            bulk_edit_signal,
-                                            collection_color_signal,
-                                        }
-                                    }
+                                div {
+                                    class: "skeleton",
+                                    style: "height: 18px; width: 80%; margin-top: 8px;",
                                 }
                             }
                         }
                     }
-                    Some(Err(err)) => rsx! {
-                        div { class: "error-state",
-                            p { "Error: {err}" }
-                        }
-                    },
-                    None => rsx! {
-                        div { class: "loading-state media-grid",
-                            for _ in 0..8 {
-                                div { class: "skeleton-card",
-                                    div { class: "skeleton", style: "height: 200px;" }
-                                    div {
-                                        class: "skeleton",
-                                        style: "height: 24px; width: 40%; margin-top: 12px;",
-                                    }
-                                    div {
-                                        class: "skeleton",
-                                        style: "height: 18px; width: 80%; margin-top: 8px;",
-                                    }
-                                }
+                } else if pages.media.is_empty() {
+                    div { class: "empty-state",
+                        p { "No media found matching your search criteria." }
+                    }
+                } else {
+                    // TODO -- convert this to a MediaGrid element (see equivalent in collections and libraries)
+                    //         and convert them all to reactive memos instead of cloning
+                    div { class: "media-grid",
+                        for search_resp in pages.media.iter() {
+                            MediaCard {
+                                key: "{search_resp.media_uuid}",
+                                media_uuid: search_resp.media_uuid,
+                                media: search_resp.media.clone(),
+                                collections: search_resp.collections.clone(),
+                                bulk_edit_signal,
+                                collection_color_signal,
                             }
                         }
-                    },
+                    }
+                }
+
+                if loading() && pages.total.is_some() {
+                    div { class: "loading-spinner" }
+                }
+
+                // a new sentinel is mounted for each page, and observing it fires right away
+                // if it is already in range, so a short page can't stall the loading
+                if pages.total.is_some() && !pages.exhausted() && error().is_none() {
+                    div {
+                        key: "{pages.media.len()}",
+                        style: "height: 1px;",
+                        onmounted: move |event| {
+                            if let Some(sentinel) = event.data().downcast::<Element>() {
+                                observer.set(observe_sentinel(sentinel, loader.tx()));
+                            }
+                        },
+                    }
                 }
             }
         }
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn restored_limit_covers_the_saved_pages() {
+        assert_eq!(restored_limit(1), PAGE_SIZE);
+        assert_eq!(restored_limit(3), 3 * PAGE_SIZE);
+    }
+
+    #[test]
+    fn restored_limit_is_clamped() {
+        let max = MAX_RESTORED_PAGES * PAGE_SIZE;
+
+        assert_eq!(restored_limit(0), PAGE_SIZE);
+        assert_eq!(restored_limit(MAX_RESTORED_PAGES), max);
+        assert_eq!(restored_limit(MAX_RESTORED_PAGES + 1), max);
+
+        // would overflow without the clamp
+        assert_eq!(restored_limit(u64::MAX), max);
+    }
+}
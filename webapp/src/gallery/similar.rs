@@ -78,7 +78,7 @@ pub fn SimilarMediaInner(props: SimilarMediaInnerProps) -> Element {
     let filtered_items = similar_media
         .media
         .iter()
-        .filter(|uuid| **uuid != media_uuid())
+        .filter(|(uuid, _)| *uuid != media_uuid())
         .collect::<Vec<_>>();
 
     rsx! {
@@ -143,7 +143,7 @@ pub fn SimilarMediaInner(props: SimilarMediaInnerProps) -> Element {
                         class: "similar-media-grid",
                         style: "display: grid; grid-template-columns: repeat(3, 1fr); gap: var(--space-2); width: 100%;",
 
-                        for & media_uuid in filtered_items {
+                        for &(media_uuid, distance) in filtered_items {
                             Link {
                                 key: "{media_uuid}",
                                 to: Route::GalleryDetail {
@@ -164,8 +164,9 @@ pub fn SimilarMediaInner(props: SimilarMediaInnerProps) -> Element {
                                             z-index: 1;
                                         }}
                                     ",
+                                    title: "{distance} bits apart",
                                     img {
-                                        src: thumbnail_link(media_uuid),
+                                        src: thumbnail_link(media_uuid, ThumbnailSize::Grid),
                                         alt: "Similar media",
                                         style: "width: 100%; aspect-ratio: 1; object-fit: cover;",
                                         loading: "lazy",
@@ -0,0 +1,153 @@
+use dioxus::prelude::*;
+
+use api::stats::*;
+
+// timeline
+//
+// one bar per day, month, or year, scaled against the busiest bucket so that the density
+// of the library stands out.  clicking a bar narrows the gallery search to its dates, and
+// clicking it again (or clearing it) goes back to the whole library.
+#[derive(Clone, PartialEq, Props)]
+pub struct TimelineTabProps {
+    date_bucket_signal: Signal<Option<DateBucket>>,
+}
+
+#[component]
+pub fn TimelineTab(props: TimelineTabProps) -> Element {
+    let mut date_bucket_signal = props.date_bucket_signal;
+    let mut granularity_signal = use_signal(|| DateGranularity::Month);
+
+    let histogram_future = use_resource(move || async move {
+        get_date_histogram(&GetDateHistogramReq {
+            granularity: granularity_signal(),
+        })
+        .await
+    });
+
+    let histogram = &*histogram_future.read();
+
+    let buckets = match histogram.clone().transpose().show(|error| {
+        rsx! {
+            div { style: "padding: var(--space-4); text-align: center; color: var(--error);",
+                "Error loading timeline: {error}"
+            }
+        }
+    })? {
+        None => {
+            return rsx! {
+                div { class: "skeleton", style: "height: 160px;" }
+            };
+        }
+        Some(v) => v.buckets,
+    };
+
+    let busiest = buckets.iter().map(|v| v.count).max().unwrap_or(1).max(1);
+
+    rsx! {
+        div { class: "timeline-options",
+            div { style: "display: flex; align-items: center; gap: var(--space-2); margin-bottom: var(--space-3);",
+                label { class: "form-label", "Group by" }
+                select {
+                    class: "form-select",
+                    style: "width: auto;",
+                    onchange: move |evt| {
+                        granularity_signal
+                            .set(
+                                match evt.value().as_str() {
+                                    "day" => DateGranularity::Day,
+                                    "year" => DateGranularity::Year,
+                                    _ => DateGranularity::Month,
+                                },
+                            );
+                    },
+                    option {
+                        value: "year",
+                        selected: granularity_signal() == DateGranularity::Year,
+                        "Year"
+                    }
+                    option {
+                        value: "month",
+                        selected: granularity_signal() == DateGranularity::Month,
+                        "Month"
+                    }
+                    option {
+                        value: "day",
+                        selected: granularity_signal() == DateGranularity::Day,
+                        "Day"
+                    }
+                }
+
+                if let Some(bucket) = date_bucket_signal() {
+                    span { style: "margin-left: auto; color: var(--text-secondary);",
+                        "Showing {bucket.date}"
+                    }
+                    button {
+                        class: "btn btn-secondary",
+                        onclick: move |_| date_bucket_signal.set(None),
+                        "Clear"
+                    }
+                }
+            }
+
+            if buckets.is_empty() {
+                div { class: "empty-state",
+                    p { "No dated media found." }
+                }
+            } else {
+                div { style: "display: flex; align-items: flex-end; gap: 2px; height: 160px; overflow-x: auto; padding-bottom: var(--space-1);",
+                    for bucket in buckets {
+                        TimelineBar {
+                            key: "{bucket.date}",
+                            bucket,
+                            busiest,
+                            date_bucket_signal,
+                        }
+                    }
+                }
+            }
+        }
+    }
+}
+
+#[derive(Clone, PartialEq, Props)]
+struct TimelineBarProps {
+    bucket: DateBucket,
+    busiest: u64,
+    date_bucket_signal: Signal<Option<DateBucket>>,
+}
+
+#[component]
+fn TimelineBar(props: TimelineBarProps) -> Element {
+    let bucket = props.bucket;
+    let mut date_bucket_signal = props.date_bucket_signal;
+
+    let selected = date_bucket_signal
+        .read()
+        .as_ref()
+        .is_some_and(|v| v.start == bucket.start && v.end == bucket.end);
+
+    // a sliver for the quiet buckets, so that they can still be clicked
+    let height = (bucket.count * 100 / props.busiest).max(2);
+
+    let color = if selected {
+        "var(--primary)"
+    } else {
+        "var(--neutral-300)"
+    };
+
+    let title = format!("{}: {} media", bucket.date, bucket.count);
+
+    rsx! {
+        div {
+            title,
+            style: "flex: 0 0 8px; height: {height}%; background-color: {color}; border-radius: 2px 2px 0 0; cursor: pointer;",
+            onclick: move |_| {
+                if selected {
+                    date_bucket_signal.set(None);
+                } else {
+                    date_bucket_signal.set(Some(bucket.clone()));
+                }
+            },
+        }
+    }
+}
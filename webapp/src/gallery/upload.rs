@@ -0,0 +1,255 @@
+use dioxus::prelude::*;
+use gloo_net::http::{Request, Response};
+use web_sys::{File, FormData};
+
+use crate::components::modal::ProgressBar;
+use api::{
+    HTTP_URL_ROOT, WebError,
+    library::*,
+    media::{UPLOAD_MEDIA_FILE_FIELD, UPLOAD_MEDIA_LIBRARY_FIELD, is_upload_filename},
+};
+
+// drag-and-drop uploads
+//
+// files dropped onto the zone are sent to UploadMedia one at a time, in the order that the
+// browser lists them.  each file gets its own status line, since a duplicate or an
+// unsupported file shouldn't read like the whole upload failed.
+#[derive(Clone, Debug, PartialEq)]
+enum UploadStatus {
+    Queued,
+    Uploading,
+    Done,
+    Exists,
+    Rejected(String),
+    Failed(String),
+}
+
+#[derive(Clone, Debug, PartialEq)]
+struct UploadItem {
+    filename: String,
+    status: UploadStatus,
+}
+
+#[derive(Clone, PartialEq, Props)]
+pub struct UploadDropZoneProps {
+    // set once the uploads finish, so that the gallery picks up the new media
+    update_signal: Signal<()>,
+}
+
+#[component]
+pub fn UploadDropZone(props: UploadDropZoneProps) -> Element {
+    let mut update_signal = props.update_signal;
+
+    let mut dragging = use_signal(|| false);
+    let mut library_signal = use_signal(|| None::<LibraryUuid>);
+    let mut uploads = use_signal(Vec::<UploadItem>::new);
+
+    let mut processing_count = use_signal(|| 0);
+    let mut success_count = use_signal(|| 0);
+    let mut error_count = use_signal(|| 0);
+
+    // uploads are only allowed into libraries owned by one of the user's groups, which is
+    // the same set that SearchLibraries returns
+    let library_future = use_resource(move || async move {
+        let libraries = search_libraries(&SearchLibrariesReq {
+            filter: String::new(),
+        })
+        .await?
+        .libraries;
+
+        let mut out = Vec::new();
+
+        for library_uuid in libraries {
+            let library = get_library(&GetLibraryReq { library_uuid }).await?.library;
+            out.push((library_uuid, library.path));
+        }
+
+        Ok::<_, WebError>(out)
+    });
+
+    let libraries = match &*library_future.read() {
+        Some(Ok(v)) => v.clone(),
+        _ => Vec::new(),
+    };
+
+    // nothing to upload into
+    if libraries.is_empty() {
+        return rsx! {};
+    }
+
+    let options = libraries.clone();
+
+    let handle_drop = move |event: DragEvent| async move {
+        event.prevent_default();
+        dragging.set(false);
+
+        let Some(library_uuid) = library_signal() else {
+            return;
+        };
+
+        let Some(files) = event.files() else {
+            return;
+        };
+
+        let filenames = files.files();
+
+        uploads.set(
+            filenames
+                .iter()
+                .map(|filename| UploadItem {
+                    filename: filename.clone(),
+                    status: match is_upload_filename(filename) {
+                        true => UploadStatus::Queued,
+                        false => UploadStatus::Rejected("not a supported media file".to_owned()),
+                    },
+                })
+                .collect(),
+        );
+
+        processing_count.set(0);
+        success_count.set(0);
+        error_count.set(0);
+
+        for (index, filename) in filenames.iter().enumerate() {
+            if uploads.read()[index].status == UploadStatus::Queued {
+                uploads.write()[index].status = UploadStatus::Uploading;
+
+                let status = match files
+                    .get_native_file(filename)
+                    .await
+                    .and_then(|file| file.downcast::<File>().ok())
+                {
+                    Some(file) => upload_file(library_uuid, filename, &file).await,
+                    None => UploadStatus::Failed("could not read file".to_owned()),
+                };
+
+                uploads.write()[index].status = status;
+            }
+
+            match uploads.read()[index].status {
+                UploadStatus::Done => success_count += 1,
+                _ => error_count += 1,
+            }
+
+            processing_count += 1;
+        }
+
+        if success_count() > 0 {
+            update_signal.set(());
+        }
+    };
+
+    rsx! {
+        div {
+            class: if dragging() { "drop-zone active" } else { "drop-zone" },
+            ondragover: move |event: DragEvent| {
+                event.prevent_default();
+                dragging.set(true);
+            },
+            ondragleave: move |_| dragging.set(false),
+            ondrop: handle_drop,
+
+            div { style: "display: flex; align-items: center; gap: var(--space-4);",
+                span {
+                    if library_signal().is_some() {
+                        "Drop files here to upload them to"
+                    } else {
+                        "Choose a library, then drop files here to upload them to it"
+                    }
+                }
+                select {
+                    class: "form-select",
+                    onchange: move |event| {
+                        let value = event.value();
+                        library_signal.set(
+                            libraries
+                                .iter()
+                                .find(|(library_uuid, _)| library_uuid.to_string() == value)
+                                .map(|(library_uuid, _)| *library_uuid),
+                        );
+                    },
+                    option { value: "", "Select a library" }
+                    for (library_uuid , path) in options.iter() {
+                        option { value: "{library_uuid}", "{path}" }
+                    }
+                }
+            }
+
+            if !uploads().is_empty() {
+                div { style: "margin-top: var(--space-4);",
+                    ProgressBar {
+                        processing_count,
+                        success_count,
+                        error_count,
+                        media_count: uploads().len() as i64,
+                    }
+                    ul { class: "upload-list",
+                        for item in uploads().iter() {
+                            li {
+                                span { "{item.filename}" }
+                                match &item.status {
+                                    UploadStatus::Queued => rsx! {
+                                        span { style: "color: var(--text-tertiary);", "Queued" }
+                                    },
+                                    UploadStatus::Uploading => rsx! {
+                                        span { style: "color: var(--primary);", "Uploading..." }
+                                    },
+                                    UploadStatus::Done => rsx! {
+                                        span { style: "color: var(--success);", "Uploaded" }
+                                    },
+                                    UploadStatus::Exists => rsx! {
+                                        span { style: "color: var(--warning);", "Already exists in the library" }
+                                    },
+                                    UploadStatus::Rejected(reason) => rsx! {
+                                        span { style: "color: var(--error);", "Rejected: {reason}" }
+                                    },
+                                    UploadStatus::Failed(reason) => rsx! {
+                                        span { style: "color: var(--error);", "Failed: {reason}" }
+                                    },
+                                }
+                            }
+                        }
+                    }
+                }
+            }
+        }
+    }
+}
+
+// UploadMedia is multipart rather than json, so it has no generated endpoint function
+//
+// the file is handed to the browser as-is, so that large videos are streamed from disk
+// instead of being read into memory first
+async fn upload_file(library_uuid: LibraryUuid, filename: &str, file: &File) -> UploadStatus {
+    let result = async {
+        let form =
+            FormData::new().map_err(|_| WebError::msg("failed to create form".to_owned()))?;
+
+        // the server expects the library first, see http/upload.rs
+        form.append_with_str(UPLOAD_MEDIA_LIBRARY_FIELD, &library_uuid.to_string())
+            .and_then(|_| {
+                form.append_with_blob_and_filename(UPLOAD_MEDIA_FILE_FIELD, file, filename)
+            })
+            .map_err(|_| WebError::msg("failed to create form".to_owned()))?;
+
+        let resp = Request::post(&format!("/{HTTP_URL_ROOT}/api/UploadMedia"))
+            .body(form)?
+            .send()
+            .await?;
+
+        Ok::<Response, WebError>(resp)
+    }
+    .await;
+
+    let resp = match result {
+        Ok(resp) => resp,
+        Err(err) => return UploadStatus::Failed(err.to_string()),
+    };
+
+    match resp.status() {
+        200 => UploadStatus::Done,
+        409 => UploadStatus::Exists,
+        400 => UploadStatus::Rejected(resp.text().await.unwrap_or_default()),
+        _ => UploadStatus::Failed(resp.text().await.unwrap_or_default()),
+    }
+}
@@ -1,12 +1,13 @@
 use dioxus::prelude::*;
 use dioxus_router::prelude::*;
+use tracing::error;
 
 use crate::Route;
+use api::stats::*;
 
 #[component]
 pub fn ModernHome() -> Element {
-    // Stats for the dashboard - in a real implementation,
-    // these would be fetched from your API
+    // counts are limited to what the current user can see
     let media_count = use_signal(|| 0);
     let collections_count = use_signal(|| 0);
     let libraries_count = use_signal(|| 0);
@@ -21,12 +22,16 @@ pub fn ModernHome() -> Element {
             stats_loaded
         ];
         async move {
-            // Simulate an API call
-            // In a real implementation, you would fetch real data
-            media_count.set(3752);
-            collections_count.set(48);
-            libraries_count.set(5);
-            stats_loaded.set(true);
+            // on failure, the placeholders stay up rather than showing misleading zeroes
+            match get_stats(&GetStatsReq {}).await {
+                Ok(resp) => {
+                    media_count.set(resp.stats.media);
+                    collections_count.set(resp.stats.collections);
+                    libraries_count.set(resp.stats.libraries);
+                    stats_loaded.set(true);
+                }
+                Err(err) => error!("Failed to fetch stats: {err}"),
+            }
         }
     });
 
@@ -65,7 +70,7 @@ pub fn ModernHome() -> Element {
                             div { class: "stat-icon media-icon" }
                             div { class: "stat-content",
                                 h3 { class: "stat-value",
-                                    if media_count() > 0 {
+                                    if stats_loaded() {
                                         "{media_count()}"
                                     } else {
                                         div {
@@ -88,7 +93,7 @@ pub fn ModernHome() -> Element {
                             div { class: "stat-icon collection-icon" }
                             div { class: "stat-content",
                                 h3 { class: "stat-value",
-                                    if collections_count() > 0 {
+                                    if stats_loaded() {
                                         "{collections_count()}"
                                     } else {
                                         div {
@@ -111,7 +116,7 @@ pub fn ModernHome() -> Element {
                             div { class: "stat-icon library-icon" }
                             div { class: "stat-content",
                                 h3 { class: "stat-value",
-                                    if libraries_count() > 0 {
+                                    if stats_loaded() {
                                         "{libraries_count()}"
                                     } else {
                                         div {
@@ -13,6 +13,7 @@ use crate::{
         search::SearchBar,
         sidebar::AdvancedSidebar,
     },
+    gallery::GALLERY_RESULTS,
     library::{MEDIA_SEARCH_KEY, taskbar::TaskBar},
 };
 use api::{
@@ -92,6 +93,14 @@ fn LibraryInner(props: LibraryInnerProps) -> Element {
 
     // the library media search is the only place where we can specify hidden = true
     let mut show_hidden = use_signal(|| false);
+
+    // only the owners get a count, so anyone else just sees the plain toggle
+    let hidden_future = use_resource(move || async move {
+        update_signal();
+
+        let library_uuid = library_uuid();
+        count_hidden_in_library(&CountHiddenInLibraryReq { library_uuid }).await
+    });
     let media_search_signal = use_signal::<String>(|| try_local_storage(MEDIA_SEARCH_KEY));
     let mut advanced_expanded = use_signal(|| false);
     let mut bulk_edit_signal = use_signal(|| None);
@@ -117,13 +126,26 @@ fn LibraryInner(props: LibraryInnerProps) -> Element {
         .await
     });
 
+    // see GALLERY_RESULTS
+    use_effect(move || {
+        if let Some(Ok(resp)) = &*media_future.read() {
+            *GALLERY_RESULTS.write() = resp.media.iter().map(|m| m.media_uuid).collect();
+        }
+    });
+
     // see GalleryInner for details
     //
     // the two futures both early return the same loading skeleton, but they could differ in principle
     let library_data = &*library_future.read();
     let library_data = match library_data.clone().transpose().show(|error| {
+        let message = if error.is_unauthorized() {
+            String::from("You do not have access to this library")
+        } else {
+            format!("There was an error fetching the library metadata: {error}")
+        };
+
         rsx! {
-            LibraryError { message: format!("There was an error fetching the library metadata: {error}") }
+            LibraryError { message }
         }
     })? {
         None => {
@@ -161,6 +183,11 @@ fn LibraryInner(props: LibraryInnerProps) -> Element {
     let library = library_data.library;
     let media = media_data.media;
 
+    let hidden_label = match &*hidden_future.read() {
+        Some(Ok(resp)) => format!("Show hidden files ({} hidden)", resp.count),
+        _ => String::from("Show hidden files"),
+    };
+
     // search bar action button
     let action_button = rsx! {
         div { style: "display: flex; align-items: center; margin-left: auto;",
@@ -190,7 +217,7 @@ fn LibraryInner(props: LibraryInnerProps) -> Element {
                 },
                 style: "margin: 0 8px 0 8px;",
             }
-            label { r#for: "show-hidden-checkbox", style: "margin: 0 16px 0 0;", "Show hidden files" }
+            label { r#for: "show-hidden-checkbox", style: "margin: 0 16px 0 0;", "{hidden_label}" }
         }
     };
 
@@ -206,7 +233,19 @@ fn LibraryInner(props: LibraryInnerProps) -> Element {
                     div { style: "display: flex; justify-content: space-between; align-items: flex-start;",
                         // Library info
                         div {
-                            h1 { style: "margin: 0 0 var(--space-2) 0;", "Library: {library.path}" }
+                            if library.name.is_empty() {
+                                h1 { style: "margin: 0 0 var(--space-2) 0;", "Library: {library.path}" }
+                            } else {
+                                h1 { style: "margin: 0 0 var(--space-2) 0;", "Library: {library.name}" }
+                                p { style: "margin: 0 0 var(--space-2) 0; color: var(--text-tertiary);",
+                                    "{library.path}"
+                                }
+                            }
+                            if !library.note.is_empty() {
+                                p { style: "margin: 0 0 var(--space-3) 0; color: var(--text-secondary);",
+                                    "{library.note}"
+                                }
+                            }
                             div { style: "display: flex; gap: var(--space-4); margin-bottom: var(--space-3); color: var(--text-secondary) font-size: 0.875rem;",
                                 span { "Owner: {library.uid}" }
                                 span { "Group: {library.gid}" }
@@ -217,6 +256,13 @@ fn LibraryInner(props: LibraryInnerProps) -> Element {
                         }
                         // Action buttons
                         div { style: "display: flex; gap: var(--space-2);",
+                            button {
+                                class: "btn btn-secondary",
+                                onclick: move |_| {
+                                    MODAL_STACK.with_mut(|v| v.push(Modal::EditLibrary(library_uuid())));
+                                },
+                                "Edit"
+                            }
                             button {
                                 class: "btn btn-secondary",
                                 onclick: move |_| {
@@ -253,7 +299,11 @@ fn LibraryInner(props: LibraryInnerProps) -> Element {
                             BulkEditTab {
                                 bulk_edit_signal,
                                 media_uuids,
-                                modes: Vec::from([BulkEditMode::EditTags, BulkEditMode::AddToCollection]),
+                                modes: Vec::from([
+                                    BulkEditMode::EditTags,
+                                    BulkEditMode::AddToCollection,
+                                    BulkEditMode::Hide,
+                                ]),
                             }
                         }),
                         ("Collection Labels".to_owned(), rsx! {
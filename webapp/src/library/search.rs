@@ -17,7 +17,7 @@ pub fn LibrarySearch() -> Element {
         update_signal();
 
         let filter = library_search_signal();
-        search_libraries(&SearchLibrariesReq { filter }).await
+        search_libraries_detailed(&SearchLibrariesDetailedReq { filter }).await
     });
 
     let status = match &*library_future.read() {
@@ -40,7 +40,7 @@ pub fn LibrarySearch() -> Element {
                 SearchBar {
                     search_signal: library_search_signal,
                     storage_key: LIBRARY_SEARCH_KEY,
-                    placeholder: "Search by library path...",
+                    placeholder: "Search by library path, name, or note...",
                     status,
                 }
             }
@@ -66,7 +66,7 @@ pub fn LibrarySearch() -> Element {
                             table { style: "width: 100%; border-collapse: collapse;",
                                 thead {
                                     tr {
-                                        for _ in 0..4 {
+                                        for _ in 0..5 {
                                             th {
                                                 div {
                                                     class: "skeleton",
@@ -79,7 +79,7 @@ pub fn LibrarySearch() -> Element {
                                 tbody {
                                     for _ in 0..5 {
                                         tr {
-                                            for _ in 0..4 {
+                                            for _ in 0..5 {
                                                 td {
                                                     div {
                                                         class: "skeleton",
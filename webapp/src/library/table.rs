@@ -9,12 +9,12 @@ use api::library::*;
 
 #[derive(Clone, PartialEq, Props)]
 pub struct LibraryTableProps {
-    libraries: Vec<LibraryUuid>,
+    libraries: Vec<LibrarySummary>,
 }
 
 #[component]
 pub fn LibraryTable(props: LibraryTableProps) -> Element {
-    let libraries = props.libraries.clone();
+    let mut libraries = props.libraries.clone();
 
     if libraries.is_empty() {
         return rsx! {
@@ -34,104 +34,88 @@ pub fn LibraryTable(props: LibraryTableProps) -> Element {
         };
     }
 
-    // Fetch details for each library
-    let libraries_future = use_resource(move || {
-        let libraries = libraries.clone();
+    // Sort libraries by name (or path, if unnamed) for better display
+    libraries.sort_by(|a, b| display_name(&a.library).cmp(display_name(&b.library)));
 
-        async move {
-            let mut library_details = Vec::new();
-
-            for library_uuid in libraries.iter() {
-                match get_library(&GetLibraryReq {
-                    library_uuid: *library_uuid,
-                })
-                .await
-                {
-                    Ok(resp) => library_details.push((*library_uuid, resp.library)),
-                    Err(err) => {
-                        tracing::error!("Failed to fetch library {library_uuid}: {err}");
+    rsx! {
+        div {
+            class: "table-container",
+            style: "margin-top: var(--space-4); background-color: var(--surface); border-radius: var(--radius-lg); overflow: hidden; box-shadow: var(--shadow-sm);",
+            table { style: "width: 100%; border-collapse: collapse;",
+                thead {
+                    tr { style: "background-color: var(--primary); color: white;",
+                        th { style: "padding: var(--space-3); text-align: left;",
+                            "Library"
+                        }
+                        th { style: "padding: var(--space-3); text-align: left;",
+                            "Group"
+                        }
+                        th { style: "padding: var(--space-3); text-align: left;",
+                            "Media"
+                        }
+                        th { style: "padding: var(--space-3); text-align: left;",
+                            "File Count"
+                        }
+                        th { style: "padding: var(--space-3); text-align: right;",
+                            "Actions"
+                        }
                     }
                 }
-            }
+                tbody {
+                    for LibrarySummary { library_uuid , library , media } in libraries {
+                        tr {
+                            key: "{library_uuid}",
+                            style: "border-bottom: 1px solid var(--border); transition: background-color var(--transition-fast) var(--easing-standard);",
+                            onmouseenter: move |_| {},
 
-            // Sort libraries by path for better display
-            library_details.sort_by(|a, b| a.1.path.cmp(&b.1.path));
-            library_details
-        }
-    });
-
-    let libraries = &*libraries_future.read();
-
-    let libraries = libraries.clone();
-
-    match libraries {
-        Some(library_details) => {
-            rsx! {
-                div {
-                    class: "table-container",
-                    style: "margin-top: var(--space-4); background-color: var(--surface); border-radius: var(--radius-lg); overflow: hidden; box-shadow: var(--shadow-sm);",
-                    table { style: "width: 100%; border-collapse: collapse;",
-                        thead {
-                            tr { style: "background-color: var(--primary); color: white;",
-                                th { style: "padding: var(--space-3); text-align: left;",
-                                    "Path"
+                            td { style: "padding: var(--space-3);",
+                                Link {
+                                    to: Route::LibraryDetail {
+                                        library_uuid: library_uuid.to_string(),
+                                    },
+                                    style: "color: var(--primary); font-weight: 500; text-decoration: none;",
+                                    "{display_name(&library)}"
                                 }
-                                th { style: "padding: var(--space-3); text-align: left;",
-                                    "Group"
-                                }
-                                th { style: "padding: var(--space-3); text-align: left;",
-                                    "File Count"
+                                if !library.name.is_empty() {
+                                    div { style: "font-size: 0.875rem; color: var(--text-tertiary);",
+                                        "{library.path}"
+                                    }
                                 }
-                                th { style: "padding: var(--space-3); text-align: right;",
-                                    "Actions"
+                                if !library.note.is_empty() {
+                                    div { style: "font-size: 0.875rem; color: var(--text-secondary);",
+                                        "{library.note}"
+                                    }
                                 }
                             }
-                        }
-                        tbody {
-                            for (library_uuid , library) in library_details {
-                                tr {
-                                    key: "{library_uuid}",
-                                    style: "border-bottom: 1px solid var(--border); transition: background-color var(--transition-fast) var(--easing-standard);",
-                                    onmouseenter: move |_| {},
 
-                                    td { style: "padding: var(--space-3);",
-                                        Link {
-                                            to: Route::LibraryDetail {
-                                                library_uuid: library_uuid.to_string(),
-                                            },
-                                            style: "color: var(--primary); font-weight: 500; text-decoration: none;",
-                                            "{library.path}"
-                                        }
-                                    }
+                            td { style: "padding: var(--space-3);",
+                                span {
+                                    class: "group-badge",
+                                    style: "display: inline-block; padding: var(--space-1) var(--space-2); background-color: var(--neutral-100); border-radius: var(--radius-full); font-size: 0.875rem;",
+                                    "{library.gid}"
+                                }
+                            }
 
-                                    td { style: "padding: var(--space-3);",
-                                        span {
-                                            class: "group-badge",
-                                            style: "display: inline-block; padding: var(--space-1) var(--space-2); background-color: var(--neutral-100); border-radius: var(--radius-full); font-size: 0.875rem;",
-                                            "{library.gid}"
-                                        }
-                                    }
+                            td { style: "padding: var(--space-3);", "{media}" }
 
-                                    td { style: "padding: var(--space-3);", "{library.count}" }
+                            td { style: "padding: var(--space-3);", "{library.count}" }
 
-                                    td { style: "padding: var(--space-3); display: flex; justify-content: right;",
-                                        button {
-                                            class: "btn btn-secondary",
-                                            style: "margin-right: var(--space-2);",
-                                            onclick: move |_| {
-                                                MODAL_STACK.with_mut(|v| v.push(Modal::StartTask(library_uuid)));
-                                            },
-                                            "Start Task"
-                                        }
-                                        button {
-                                            class: "btn btn-secondary",
-                                            style: "margin-right: var(--space-2);",
-                                            onclick: move |_| {
-                                                MODAL_STACK.with_mut(|v| v.push(Modal::TaskHistory(library_uuid)));
-                                            },
-                                            "Task History"
-                                        }
-                                    }
+                            td { style: "padding: var(--space-3); display: flex; justify-content: right;",
+                                button {
+                                    class: "btn btn-secondary",
+                                    style: "margin-right: var(--space-2);",
+                                    onclick: move |_| {
+                                        MODAL_STACK.with_mut(|v| v.push(Modal::StartTask(library_uuid)));
+                                    },
+                                    "Start Task"
+                                }
+                                button {
+                                    class: "btn btn-secondary",
+                                    style: "margin-right: var(--space-2);",
+                                    onclick: move |_| {
+                                        MODAL_STACK.with_mut(|v| v.push(Modal::TaskHistory(library_uuid)));
+                                    },
+                                    "Task History"
                                 }
                             }
                         }
@@ -139,14 +123,14 @@ pub fn LibraryTable(props: LibraryTableProps) -> Element {
                 }
             }
         }
-        None => {
-            rsx! {
-                div {
-                    class: "loading-state",
-                    style: "margin-top: var(--space-4); padding: var(--space-6);",
-                    "Loading libraries..."
-                }
-            }
-        }
+    }
+}
+
+// libraries are unnamed until someone names them
+fn display_name(library: &Library) -> &str {
+    if library.name.is_empty() {
+        &library.path
+    } else {
+        &library.name
     }
 }
@@ -1,4 +1,5 @@
 use dioxus::prelude::*;
+use gloo_timers::future::TimeoutFuture;
 
 use crate::{
     common::local_time,
@@ -6,6 +7,9 @@ use crate::{
 };
 use api::{library::*, task::*};
 
+// how often to refresh a running task's progress, in milliseconds
+const TASK_POLL_MS: u32 = 3000;
+
 #[derive(Clone, PartialEq, Props)]
 pub struct TaskBarProps {
     update_signal: Signal<()>,
@@ -49,7 +53,7 @@ fn TaskBarInner(props: TaskBarInnerProps) -> Element {
     // get information about any running tasks
     //
     // failures here shouldn't prevent the rest of the page from rendering
-    let task_future = use_resource(move || async move {
+    let mut task_future = use_resource(move || async move {
         let library_uuid = library_uuid();
         update_signal();
 
@@ -59,6 +63,22 @@ fn TaskBarInner(props: TaskBarInnerProps) -> Element {
         .await
     });
 
+    // poll while a task is running so that its progress stays current
+    use_future(move || async move {
+        loop {
+            TimeoutFuture::new(TASK_POLL_MS).await;
+
+            let running = matches!(
+                &*task_future.peek(),
+                Some(Ok(resp)) if resp.tasks.first().is_some_and(|t| t.status == TaskStatus::Running)
+            );
+
+            if running {
+                task_future.restart();
+            }
+        }
+    });
+
     let task_data = &*task_future.read();
     let task_data = match task_data.clone().transpose().show(|error| {
         rsx! {
@@ -90,6 +110,9 @@ fn TaskBarInner(props: TaskBarInnerProps) -> Element {
                     rsx! {
                         span { "Current task:" }
                         span { "{v.task_type} running, started by {v.uid} at {start}" }
+                        if let Some(progress) = v.progress {
+                            TaskProgressBar { progress, start: v.start }
+                        }
                         div { style: "display: flex; gap: var(--space-2);",
                             button {
                                 class: "btn btn-danger",
@@ -107,9 +130,41 @@ fn TaskBarInner(props: TaskBarInnerProps) -> Element {
                         span {
                             "{v.task_type} returned {v.status}, started by {v.uid} at {start} ended at {end} with {warnings} warnings"
                         }
+                        if let Some(summary) = v.summary {
+                            span { "{summary}" }
+                        }
                     }
                 }
             }
         }
     }
 }
+
+#[derive(Clone, PartialEq, Props)]
+struct TaskProgressBarProps {
+    progress: TaskProgress,
+    start: u64,
+}
+
+#[component]
+fn TaskProgressBar(props: TaskProgressBarProps) -> Element {
+    let progress = props.progress;
+    let percent = progress.percent().unwrap_or_default();
+
+    let eta = match progress.eta(props.start) {
+        Some(secs) if secs >= 60 => format!(", about {} min remaining", secs.div_ceil(60)),
+        Some(_) => ", less than a minute remaining".to_owned(),
+        None => "".to_owned(),
+    };
+
+    rsx! {
+        div { style: "display: flex; align-items: center; gap: var(--space-2); min-width: 240px;",
+            div {
+                class: "progress-bar",
+                style: "flex-grow: 1; height: 8px; background-color: var(--neutral-200); border-radius: var(--radius-full); overflow: hidden;",
+                div { style: format!("height: 100%; background-color: var(--primary); width: {}%;", percent as i64) }
+            }
+            span { "{progress.processed} of {progress.total} files{eta}" }
+        }
+    }
+}